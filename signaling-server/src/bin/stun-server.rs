@@ -0,0 +1,11 @@
+//! Placeholder for a STUN server to run beside the signaling server.
+
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    eprintln!(
+        "stun-server is not implemented yet; point clients at a public STUN \
+         server, or run one such as coturn"
+    );
+    ExitCode::FAILURE
+}
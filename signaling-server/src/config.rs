@@ -0,0 +1,129 @@
+//! Command-line configuration.
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: signaling-server [OPTIONS]
+
+Options:
+  --listen ADDRESS          Address to listen on [default: 127.0.0.1:8080]
+  --max-participants N      Members a room takes unless created with a limit
+  --no-auto-create          Only admit members to rooms created through POST /rooms
+  --token TOKEN             Accept this bearer token; may be repeated
+  --tokens-file PATH        Accept the bearer tokens in PATH, one per line
+  --chat-history N          Relay chat and keep each room's last N messages for
+                            members who join later [default: 0, chat is refused]
+  --invite-key-file PATH    Sign invite tokens with the key in PATH, so links
+                            outlive a restart [default: a fresh key each run]
+  --tls-cert PATH           PEM certificate chain, serving wss:// with --tls-key
+  --tls-key PATH            PEM private key for --tls-cert
+  --help                    Print this help";
+
+/// A certificate chain and its key, both PEM.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    pub listen: SocketAddr,
+    /// Limit for rooms created without their own; `None` for no limit.
+    pub max_participants: Option<usize>,
+    /// Whether joining a room nobody created makes it.
+    pub auto_create: bool,
+    /// Bearer tokens clients have to present; empty lets everyone in.
+    pub tokens: HashSet<String>,
+    /// Chat messages kept per room; 0 turns chat relaying off.
+    pub chat_history: usize,
+    /// Signs invite tokens; `None` makes up a key for each run.
+    pub invite_key: Option<Vec<u8>>,
+    pub tls: Option<TlsFiles>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            max_participants: None,
+            auto_create: true,
+            tokens: HashSet::new(),
+            chat_history: 0,
+            invite_key: None,
+            tls: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Parses the arguments after the program name. `Ok(None)` asks for
+    /// the usage text.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut config = Self::default();
+        let (mut cert, mut key) = (None, None);
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--listen" => {
+                    let address = value()?;
+                    config.listen = address
+                        .parse()
+                        .map_err(|_| format!("not an address: {}", address))?;
+                }
+                "--max-participants" => {
+                    let limit = value()?;
+                    match limit.parse::<usize>() {
+                        Ok(limit) if limit >= 2 => config.max_participants = Some(limit),
+                        _ => {
+                            return Err(format!("not a participant limit of 2 or more: {}", limit))
+                        }
+                    }
+                }
+                "--no-auto-create" => config.auto_create = false,
+                "--token" => {
+                    config.tokens.insert(value()?);
+                }
+                "--tokens-file" => {
+                    let path = value()?;
+                    let text = std::fs::read_to_string(&path)
+                        .map_err(|err| format!("cannot read {}: {}", path, err))?;
+                    config.tokens.extend(
+                        text.lines()
+                            .map(str::trim)
+                            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                            .map(str::to_owned),
+                    );
+                }
+                "--chat-history" => {
+                    let count = value()?;
+                    config.chat_history = count
+                        .parse()
+                        .map_err(|_| format!("not a message count: {}", count))?;
+                }
+                "--invite-key-file" => {
+                    let path = value()?;
+                    let key = std::fs::read(&path)
+                        .map_err(|err| format!("cannot read {}: {}", path, err))?;
+                    if key.is_empty() {
+                        return Err(format!("no invite key in {}", path));
+                    }
+                    config.invite_key = Some(key);
+                }
+                "--tls-cert" => cert = Some(PathBuf::from(value()?)),
+                "--tls-key" => key = Some(PathBuf::from(value()?)),
+                "--help" | "-h" => return Ok(None),
+                other => return Err(format!("unknown option {}", other)),
+            }
+        }
+        config.tls = match (cert, key) {
+            (Some(cert), Some(key)) => Some(TlsFiles { cert, key }),
+            (None, None) => None,
+            _ => return Err("--tls-cert and --tls-key go together".to_owned()),
+        };
+        Ok(Some(config))
+    }
+}
@@ -0,0 +1,274 @@
+//! Reference signaling server for the GUI client: members join a room by
+//! name, learn who else is in it, and relay offers and answers to one
+//! another by peer ID. Messages are `webrtc_core::signaling`'s JSON.
+//!
+//! Besides the WebSocket at `/`, `GET /rooms` lists rooms and `POST /rooms`
+//! makes one, taking `{"name": ...}` for a server channel or `{"code": ...}`
+//! for a GUI room code, or neither for a fresh code, and an optional
+//! `"max_participants"`. With tokens configured, every request needs one as
+//! `Authorization: Bearer`.
+//!
+//! With `--chat-history`, members can also chat through the server while
+//! their data channel is down, and whoever joins later is sent what was said.
+//!
+//! Members can ask for invite tokens for their room, which the server signs.
+//! From the first one on, joining that room takes a valid token: one that
+//! is missing, forged, for another room or expired is turned away alike.
+
+pub mod config;
+pub mod rooms;
+
+use std::collections::HashSet;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use axum_server::tls_rustls::RustlsConfig;
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use rand::distributions::{Alphanumeric, DistString};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use webrtc_core::signaling::invite::InviteKey;
+use webrtc_core::signaling::room::room_channel;
+use webrtc_core::signaling::{ClientMessage, ServerMessage};
+
+pub use config::{ServerConfig, TlsFiles};
+pub use rooms::{RoomError, RoomPolicy, RoomSummary, Rooms};
+
+/// Length of the room codes `POST /rooms` makes up.
+const CODE_LENGTH: usize = 8;
+
+/// The longest an invite token is issued for.
+pub const MAX_INVITE_VALIDITY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Clone)]
+struct AppState {
+    rooms: Rooms,
+    tokens: Arc<HashSet<String>>,
+    invites: Arc<InviteKey>,
+}
+
+impl AppState {
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        if self.tokens.is_empty() {
+            return true;
+        }
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| self.tokens.contains(token.trim()))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CreateRoom {
+    name: Option<String>,
+    code: Option<String>,
+    max_participants: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct CreatedRoom {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
+}
+
+/// Serves `config` on `listener`, which the caller has bound (usually to
+/// `config.listen`), until the listener fails.
+pub async fn serve(listener: TcpListener, config: ServerConfig) -> std::io::Result<()> {
+    listener.set_nonblocking(true)?;
+    let rooms = Rooms::new(RoomPolicy {
+        max_participants: config.max_participants,
+        auto_create: config.auto_create,
+        chat_history: config.chat_history,
+    });
+    let invites = match config.invite_key {
+        Some(key) => InviteKey::new(&key),
+        None => InviteKey::new(&rand::random::<[u8; 32]>()),
+    };
+    let app = router(rooms, config.tokens, invites).into_make_service();
+    match config.tls {
+        Some(tls) => {
+            let tls = RustlsConfig::from_pem_file(&tls.cert, &tls.key).await?;
+            axum_server::from_tcp_rustls(listener, tls).serve(app).await
+        }
+        None => axum_server::from_tcp(listener).serve(app).await,
+    }
+}
+
+pub fn router(rooms: Rooms, tokens: HashSet<String>, invites: InviteKey) -> Router {
+    Router::new()
+        .route("/", get(upgrade))
+        .route("/rooms", get(list_rooms).post(create_room))
+        .with_state(AppState {
+            rooms,
+            tokens: Arc::new(tokens),
+            invites: Arc::new(invites),
+        })
+}
+
+async fn upgrade(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response {
+    if !state.authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    ws.on_upgrade(move |socket| connection(socket, state.rooms, state.invites))
+}
+
+async fn list_rooms(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if !state.authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    Json(state.rooms.summaries()).into_response()
+}
+
+async fn create_room(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    request: Option<Json<CreateRoom>>,
+) -> Response {
+    if !state.authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let request = request.map(|Json(request)| request).unwrap_or_default();
+    if request.max_participants.is_some_and(|max| max < 2) {
+        return (
+            StatusCode::BAD_REQUEST,
+            "max_participants must be 2 or more",
+        )
+            .into_response();
+    }
+    let (name, code) = match (request.name, request.code) {
+        (Some(_), Some(_)) => {
+            return (StatusCode::BAD_REQUEST, "give a name or a code, not both").into_response()
+        }
+        (Some(name), None) => (name, None),
+        (None, code) => {
+            let code = code.unwrap_or_else(|| {
+                Alphanumeric
+                    .sample_string(&mut rand::thread_rng(), CODE_LENGTH)
+                    .to_lowercase()
+            });
+            (room_channel(&code), Some(code))
+        }
+    };
+    match state.rooms.create(&name, request.max_participants) {
+        Ok(()) => (StatusCode::CREATED, Json(CreatedRoom { name, code })).into_response(),
+        Err(err) => (StatusCode::CONFLICT, err.to_string()).into_response(),
+    }
+}
+
+/// One client, for as long as its socket stays open.
+async fn connection(socket: WebSocket, rooms: Rooms, invites: Arc<InviteKey>) {
+    let (mut sink, mut stream) = socket.split();
+    let (outbox, mut inbox) = mpsc::unbounded_channel::<ServerMessage>();
+    let writer = tokio::spawn(async move {
+        while let Some(message) = inbox.recv().await {
+            let text = serde_json::to_string(&message).expect("server messages serialize");
+            if sink.send(Message::Text(text)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // The room and peer ID this socket is in.
+    let mut joined: Option<(String, String)> = None;
+    let reply_error = |message: String| {
+        let _ = outbox.send(ServerMessage::Error { message });
+    };
+    while let Some(Ok(frame)) = stream.next().await {
+        let text = match frame {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let message = match serde_json::from_str::<ClientMessage>(&text) {
+            Ok(message) => message,
+            Err(err) => {
+                warn!("Ignoring malformed client message: {}", err);
+                reply_error(format!("malformed message: {}", err));
+                continue;
+            }
+        };
+        match message {
+            ClientMessage::Join {
+                room,
+                name,
+                resume,
+                resume_secret,
+                invite,
+            } => {
+                let invited = match invite.map(|token| invites.verify(&room, &token)) {
+                    Some(Ok(())) => true,
+                    Some(Err(err)) => {
+                        info!("Turned away a join to room {}: {}", room, err);
+                        reply_error(RoomError::Uninvited.to_string());
+                        continue;
+                    }
+                    None => false,
+                };
+                if let Some((room, peer_id)) = joined.take() {
+                    rooms.leave(&room, &peer_id);
+                }
+                let resume = resume.as_deref().zip(resume_secret.as_deref());
+                match rooms.join(&room, &name, resume, invited, outbox.clone()) {
+                    Ok(peer_id) => joined = Some((room, peer_id)),
+                    Err(err) => reply_error(err.to_string()),
+                }
+            }
+            ClientMessage::Leave => {
+                if let Some((room, peer_id)) = joined.take() {
+                    rooms.leave(&room, &peer_id);
+                }
+            }
+            ClientMessage::Signal { to, payload } => match &joined {
+                Some((room, from)) => {
+                    if let Err(err) = rooms.signal(room, from, &to, payload) {
+                        reply_error(err.to_string());
+                    }
+                }
+                None => reply_error("join a room first".to_owned()),
+            },
+            ClientMessage::Chat { message } => match &joined {
+                Some((room, from)) => {
+                    if let Err(err) = rooms.chat(room, from, message) {
+                        reply_error(err.to_string());
+                    }
+                }
+                None => reply_error("join a room first".to_owned()),
+            },
+            ClientMessage::Invite { valid_for } => match &joined {
+                Some((room, from)) => {
+                    let valid_for = Duration::from_secs(valid_for).min(MAX_INVITE_VALIDITY);
+                    match rooms.invite(room, from, valid_for) {
+                        Ok(()) => {
+                            let token = invites.sign(room, valid_for);
+                            let _ = outbox.send(ServerMessage::Invite { token });
+                        }
+                        Err(err) => reply_error(err.to_string()),
+                    }
+                }
+                None => reply_error("join a room first".to_owned()),
+            },
+        }
+    }
+
+    if let Some((room, peer_id)) = joined {
+        info!("{} disconnected from room {}", peer_id, room);
+        rooms.detach(&room, &peer_id, &outbox);
+    }
+    writer.abort();
+}
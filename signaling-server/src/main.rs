@@ -0,0 +1,29 @@
+//! The reference signaling server; see `config::USAGE` for its options.
+
+use std::net::TcpListener;
+
+use log::info;
+use signaling_server::config::{ServerConfig, USAGE};
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let config = match ServerConfig::from_args(std::env::args().skip(1)) {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let listener = TcpListener::bind(config.listen)?;
+    let scheme = if config.tls.is_some() { "wss" } else { "ws" };
+    info!("Signaling on {}://{}", scheme, listener.local_addr()?);
+    if config.tokens.is_empty() {
+        info!("No tokens configured, so anyone can join");
+    }
+    signaling_server::serve(listener, config).await
+}
@@ -0,0 +1,488 @@
+//! Room membership and relaying between members.
+//!
+//! Rooms are made by their first member, or ahead of time through the
+//! HTTP API when the server is set not to make them on demand; either
+//! way they can cap how many members they take.
+//!
+//! A member whose socket drops is kept for `RESUME_GRACE`, with signals for
+//! them held back, so a client that reconnects with its old peer ID picks
+//! up where it was and the rest of the room never sees it leave. Every
+//! member sees every peer ID, so resuming also takes the secret only that
+//! member's welcome carried.
+//!
+//! Once a member asks for an invite, the room only takes joins carrying a
+//! valid one, besides members resuming their seat. It is kept while empty
+//! until the last invite issued for it has expired, so nobody can make it
+//! afresh without one in the meantime.
+//!
+//! With chat history on, rooms relay chat and keep the last few messages
+//! for whoever joins next. A room emptied with history in it is kept for
+//! `CHAT_RETENTION`, so both ends of a dropped call can come back to it.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::info;
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use webrtc_core::chat::ChatMessage;
+use webrtc_core::signaling::{PeerInfo, ServerMessage, SignalPayload};
+
+/// How long a dropped member keeps its place.
+pub const RESUME_GRACE: Duration = Duration::from_secs(15);
+/// How long an empty room keeps its chat history.
+pub const CHAT_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+/// Messages held for a dropped member; older ones go first.
+const MAX_HELD: usize = 32;
+
+pub type Outbox = mpsc::UnboundedSender<ServerMessage>;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RoomError {
+    #[error("no room {0} on this server")]
+    NoSuchRoom(String),
+    #[error("room {0} already exists")]
+    Exists(String),
+    #[error("the room is full ({0} participants)")]
+    Full(usize),
+    #[error("no peer {0} in this room")]
+    NoSuchPeer(String),
+    #[error("this server does not relay chat")]
+    ChatOff,
+    #[error("this room takes a valid invite from one of its members")]
+    Uninvited,
+}
+
+/// How rooms come about and how many members they take.
+#[derive(Debug, Clone, Copy)]
+pub struct RoomPolicy {
+    /// Limit for rooms without their own; `None` for no limit.
+    pub max_participants: Option<usize>,
+    /// Whether joining a room nobody created makes it.
+    pub auto_create: bool,
+    /// Chat messages kept per room; 0 refuses chat.
+    pub chat_history: usize,
+}
+
+impl Default for RoomPolicy {
+    fn default() -> Self {
+        Self {
+            max_participants: None,
+            auto_create: true,
+            chat_history: 0,
+        }
+    }
+}
+
+/// A room as the HTTP API lists it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RoomSummary {
+    pub name: String,
+    pub participants: usize,
+    pub max_participants: Option<usize>,
+}
+
+struct Member {
+    info: PeerInfo,
+    /// Resumes this member after a reconnect.
+    resume_secret: String,
+    /// `None` while the member's socket is gone.
+    outbox: Option<Outbox>,
+    detached_at: Option<Instant>,
+    held: Vec<ServerMessage>,
+}
+
+impl Member {
+    fn deliver(&mut self, message: ServerMessage) {
+        match &self.outbox {
+            Some(outbox) => {
+                let _ = outbox.send(message);
+            }
+            None => {
+                if self.held.len() == MAX_HELD {
+                    self.held.remove(0);
+                }
+                self.held.push(message);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Room {
+    members: Vec<Member>,
+    max_participants: Option<usize>,
+    /// Made through the API, so kept while empty.
+    created: bool,
+    /// Recent chat, oldest first, with the sender's peer ID.
+    chat: VecDeque<(String, ChatMessage)>,
+    /// When the last member left a room kept for its chat.
+    emptied_at: Option<Instant>,
+    /// When the last invite issued for the room expires. A room that has
+    /// issued one takes only invited joins from then on.
+    invited_until: Option<Instant>,
+}
+
+impl Room {
+    /// Whether an empty room has nothing left worth keeping.
+    fn disposable(&self) -> bool {
+        self.members.is_empty()
+            && !self.created
+            && self
+                .invited_until
+                .is_none_or(|until| until <= Instant::now())
+            && (self.chat.is_empty()
+                || self
+                    .emptied_at
+                    .is_some_and(|at| at.elapsed() >= CHAT_RETENTION))
+    }
+}
+
+/// Every room on the server, shared by the connections.
+#[derive(Clone, Default)]
+pub struct Rooms {
+    policy: RoomPolicy,
+    rooms: Arc<Mutex<HashMap<String, Room>>>,
+}
+
+impl Rooms {
+    pub fn new(policy: RoomPolicy) -> Self {
+        Self {
+            policy,
+            rooms: Arc::default(),
+        }
+    }
+
+    /// Makes an empty room that stays until the server stops, with its own
+    /// participant limit or the policy's.
+    pub fn create(&self, room: &str, max_participants: Option<usize>) -> Result<(), RoomError> {
+        let mut rooms = self.rooms.lock().unwrap();
+        rooms.retain(|_, room| !room.disposable());
+        if rooms.contains_key(room) {
+            return Err(RoomError::Exists(room.to_owned()));
+        }
+        rooms.insert(
+            room.to_owned(),
+            Room {
+                max_participants: max_participants.or(self.policy.max_participants),
+                created: true,
+                ..Default::default()
+            },
+        );
+        info!("Created room {}", room);
+        Ok(())
+    }
+
+    pub fn summaries(&self) -> Vec<RoomSummary> {
+        let rooms = self.rooms.lock().unwrap();
+        let mut summaries: Vec<_> = rooms
+            .iter()
+            .map(|(name, room)| RoomSummary {
+                name: name.clone(),
+                participants: room.members.len(),
+                max_participants: room.max_participants,
+            })
+            .collect();
+        summaries.sort_by(|a, b| a.name.cmp(&b.name));
+        summaries
+    }
+
+    /// Adds a member to `room` and sends them a welcome through `outbox`,
+    /// then the room's chat history. A `resume` peer ID and secret still
+    /// held for a dropped member reattach it instead, with whatever was
+    /// held for it. A room that has issued invites turns away anyone else
+    /// not `invited`. Returns the member's peer ID.
+    pub fn join(
+        &self,
+        room: &str,
+        name: &str,
+        resume: Option<(&str, &str)>,
+        invited: bool,
+        outbox: Outbox,
+    ) -> Result<String, RoomError> {
+        let mut rooms = self.rooms.lock().unwrap();
+        rooms.retain(|_, room| !room.disposable());
+        if !self.policy.auto_create && !rooms.contains_key(room) {
+            return Err(RoomError::NoSuchRoom(room.to_owned()));
+        }
+        let entry = rooms.entry(room.to_owned()).or_insert_with(|| Room {
+            max_participants: self.policy.max_participants,
+            ..Default::default()
+        });
+        let max_participants = entry.max_participants;
+        let history = &entry.chat;
+        let members = &mut entry.members;
+        let resumed = resume.and_then(|(id, secret)| {
+            members.iter().position(|m| {
+                m.info.peer_id == id && m.resume_secret == secret && m.outbox.is_none()
+            })
+        });
+        if resumed.is_none() && !invited && entry.invited_until.is_some() {
+            return Err(RoomError::Uninvited);
+        }
+        if resumed.is_none() && max_participants.is_some_and(|max| members.len() >= max) {
+            let full = RoomError::Full(members.len());
+            if entry.disposable() {
+                rooms.remove(room);
+            }
+            return Err(full);
+        }
+        entry.emptied_at = None;
+        let index = match resumed {
+            Some(index) => {
+                let member = &mut members[index];
+                member.info.name = name.to_owned();
+                member.outbox = Some(outbox.clone());
+                member.detached_at = None;
+                index
+            }
+            None => {
+                let info = PeerInfo {
+                    peer_id: format!("{:016x}", rand::random::<u64>()),
+                    name: name.to_owned(),
+                };
+                for member in members.iter_mut() {
+                    member.deliver(ServerMessage::PeerJoined { peer: info.clone() });
+                }
+                members.push(Member {
+                    info,
+                    resume_secret: format!("{:032x}", rand::random::<u128>()),
+                    outbox: Some(outbox.clone()),
+                    detached_at: None,
+                    held: Vec::new(),
+                });
+                members.len() - 1
+            }
+        };
+        let peer_id = members[index].info.peer_id.clone();
+        let peers = members
+            .iter()
+            .filter(|m| m.info.peer_id != peer_id)
+            .map(|m| m.info.clone())
+            .collect();
+        let _ = outbox.send(ServerMessage::Welcome {
+            peer_id: peer_id.clone(),
+            peers,
+            resume_secret: Some(members[index].resume_secret.clone()),
+        });
+        if resumed.is_none() {
+            for (from, message) in history {
+                let _ = outbox.send(ServerMessage::Chat {
+                    from: from.clone(),
+                    message: message.clone(),
+                });
+            }
+        }
+        for message in members[index].held.drain(..) {
+            let _ = outbox.send(message);
+        }
+        let action = if resumed.is_some() {
+            "resumed"
+        } else {
+            "joined"
+        };
+        info!("{} {} room {}", peer_id, action, room);
+        Ok(peer_id)
+    }
+
+    /// Removes a member for good and tells the rest of the room.
+    pub fn leave(&self, room: &str, peer_id: &str) {
+        let mut rooms = self.rooms.lock().unwrap();
+        let Some(entry) = rooms.get_mut(room) else {
+            return;
+        };
+        let before = entry.members.len();
+        entry.members.retain(|m| m.info.peer_id != peer_id);
+        if entry.members.len() == before {
+            return;
+        }
+        for member in entry.members.iter_mut() {
+            member.deliver(ServerMessage::PeerLeft {
+                peer_id: peer_id.to_owned(),
+            });
+        }
+        if entry.members.is_empty() {
+            entry.emptied_at = Some(Instant::now());
+        }
+        if entry.disposable() {
+            rooms.remove(room);
+        }
+        info!("{} left room {}", peer_id, room);
+    }
+
+    /// Marks a member's socket as gone, unless it has already been resumed
+    /// on a newer one, and lets it go after `RESUME_GRACE`.
+    pub fn detach(&self, room: &str, peer_id: &str, outbox: &Outbox) {
+        {
+            let mut rooms = self.rooms.lock().unwrap();
+            let member = rooms.get_mut(room).and_then(|entry| {
+                entry.members.iter_mut().find(|m| {
+                    m.info.peer_id == peer_id
+                        && m.outbox.as_ref().is_some_and(|o| o.same_channel(outbox))
+                })
+            });
+            let Some(member) = member else {
+                return;
+            };
+            member.outbox = None;
+            member.detached_at = Some(Instant::now());
+        }
+        let rooms = self.clone();
+        let (room, peer_id) = (room.to_owned(), peer_id.to_owned());
+        tokio::spawn(async move {
+            tokio::time::sleep(RESUME_GRACE).await;
+            let expired = rooms
+                .rooms
+                .lock()
+                .unwrap()
+                .get(&room)
+                .and_then(|entry| entry.members.iter().find(|m| m.info.peer_id == peer_id))
+                .and_then(|m| m.detached_at)
+                .is_some_and(|at| at.elapsed() >= RESUME_GRACE);
+            if expired {
+                rooms.leave(&room, &peer_id);
+            }
+        });
+    }
+
+    /// Makes `room` take only invited joins, now that `from`, one of its
+    /// members, has an invite for it lasting `valid_for`.
+    pub fn invite(&self, room: &str, from: &str, valid_for: Duration) -> Result<(), RoomError> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let entry = rooms
+            .get_mut(room)
+            .filter(|entry| entry.members.iter().any(|m| m.info.peer_id == from))
+            .ok_or_else(|| RoomError::NoSuchPeer(from.to_owned()))?;
+        let until = Instant::now() + valid_for;
+        entry.invited_until = Some(entry.invited_until.map_or(until, |at| at.max(until)));
+        Ok(())
+    }
+
+    /// Relays `payload` from one member of `room` to another.
+    pub fn signal(
+        &self,
+        room: &str,
+        from: &str,
+        to: &str,
+        payload: SignalPayload,
+    ) -> Result<(), RoomError> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let member = rooms
+            .get_mut(room)
+            .and_then(|entry| entry.members.iter_mut().find(|m| m.info.peer_id == to))
+            .ok_or_else(|| RoomError::NoSuchPeer(to.to_owned()))?;
+        member.deliver(ServerMessage::Signal {
+            from: from.to_owned(),
+            payload,
+        });
+        Ok(())
+    }
+
+    /// Passes chat from a member of `room` to the others, holding it for
+    /// any that are dropped, and adds it to the room's history.
+    pub fn chat(&self, room: &str, from: &str, message: ChatMessage) -> Result<(), RoomError> {
+        let limit = self.policy.chat_history;
+        if limit == 0 {
+            return Err(RoomError::ChatOff);
+        }
+        let mut rooms = self.rooms.lock().unwrap();
+        let entry = rooms
+            .get_mut(room)
+            .filter(|entry| entry.members.iter().any(|m| m.info.peer_id == from))
+            .ok_or_else(|| RoomError::NoSuchPeer(from.to_owned()))?;
+        for member in entry.members.iter_mut() {
+            if member.info.peer_id != from {
+                member.deliver(ServerMessage::Chat {
+                    from: from.to_owned(),
+                    message: message.clone(),
+                });
+            }
+        }
+        if entry.chat.len() == limit {
+            entry.chat.pop_front();
+        }
+        entry.chat.push_back((from.to_owned(), message));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The peers a welcome lists, and the secret it carries.
+    fn welcome(inbox: &mut mpsc::UnboundedReceiver<ServerMessage>) -> (Vec<PeerInfo>, String) {
+        match inbox.try_recv() {
+            Ok(ServerMessage::Welcome {
+                peers,
+                resume_secret: Some(secret),
+                ..
+            }) => (peers, secret),
+            other => panic!("expected a welcome, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn resumed_members_keep_their_place() {
+        let rooms = Rooms::default();
+        let (alice_tx, mut alice) = mpsc::unbounded_channel();
+        let (bob_tx, mut bob) = mpsc::unbounded_channel();
+        let alice_id = rooms.join("room", "alice", None, false, alice_tx).unwrap();
+        assert!(welcome(&mut alice).0.is_empty());
+        let bob_id = rooms
+            .join("room", "bob", None, false, bob_tx.clone())
+            .unwrap();
+        let (peers, bob_secret) = welcome(&mut bob);
+        assert_eq!(peers[0].peer_id, alice_id);
+        assert!(matches!(
+            alice.try_recv(),
+            Ok(ServerMessage::PeerJoined { .. })
+        ));
+
+        // Bob drops; alice's offer waits for him.
+        rooms.detach("room", &bob_id, &bob_tx);
+        let offer = SignalPayload::Offer { sdp: "v=0".into() };
+        rooms.signal("room", &alice_id, &bob_id, offer).unwrap();
+
+        // Anyone can see bob's peer ID, but only bob has the secret.
+        let (mallory_tx, mut mallory) = mpsc::unbounded_channel();
+        let guessed = rooms
+            .join("room", "bob", Some((&bob_id, "guess")), false, mallory_tx)
+            .unwrap();
+        assert_ne!(guessed, bob_id);
+        assert_eq!(welcome(&mut mallory).0.len(), 2);
+        assert!(mallory.try_recv().is_err());
+        assert!(matches!(
+            alice.try_recv(),
+            Ok(ServerMessage::PeerJoined { .. })
+        ));
+        rooms.leave("room", &guessed);
+        assert!(matches!(
+            alice.try_recv(),
+            Ok(ServerMessage::PeerLeft { .. })
+        ));
+
+        let (bob_tx, mut bob) = mpsc::unbounded_channel();
+        let resumed = rooms
+            .join("room", "bob", Some((&bob_id, &bob_secret)), false, bob_tx)
+            .unwrap();
+        assert_eq!(resumed, bob_id);
+        assert_eq!(welcome(&mut bob), (peers, bob_secret));
+        assert!(matches!(bob.try_recv(), Ok(ServerMessage::Signal { .. })));
+        assert!(alice.try_recv().is_err());
+
+        rooms.leave("room", &bob_id);
+        assert!(matches!(
+            alice.try_recv(),
+            Ok(ServerMessage::PeerLeft { .. })
+        ));
+        let answer = SignalPayload::Answer { sdp: "v=0".into() };
+        assert_eq!(
+            rooms.signal("room", &alice_id, &bob_id, answer),
+            Err(RoomError::NoSuchPeer(bob_id))
+        );
+    }
+}
@@ -0,0 +1,407 @@
+//! The server against the GUI's own signaling client, over real sockets.
+
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::time::Duration;
+
+use signaling_server::ServerConfig;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use webrtc::api::media_engine::MediaEngine;
+use webrtc::api::APIBuilder;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc_core::chat::ChatMessage;
+use webrtc_core::signaling::room::Room;
+use webrtc_core::signaling::{
+    ClientMessage, ServerMessage, SignalPayload, SignalingClient, SignalingError,
+};
+
+const WAIT: Duration = Duration::from_secs(10);
+
+type Inbox = mpsc::UnboundedReceiver<ServerMessage>;
+
+fn start(config: ServerConfig) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(signaling_server::serve(listener, config));
+    address
+}
+
+async fn next(inbox: &mut Inbox) -> ServerMessage {
+    timeout(WAIT, inbox.recv())
+        .await
+        .expect("no message from the server")
+        .expect("signaling closed")
+}
+
+/// Connects and joins `room`, returning the client, its inbox and the
+/// server's first reply.
+async fn join(
+    url: &str,
+    token: Option<&str>,
+    room: &str,
+    name: &str,
+) -> (SignalingClient, Inbox, ServerMessage) {
+    let (client, mut inbox) = SignalingClient::connect(url, token).await.unwrap();
+    client
+        .send(ClientMessage::Join {
+            room: room.to_owned(),
+            name: name.to_owned(),
+            resume: None,
+            resume_secret: None,
+            invite: None,
+        })
+        .unwrap();
+    let reply = next(&mut inbox).await;
+    (client, inbox, reply)
+}
+
+async fn peer_connection() -> Arc<RTCPeerConnection> {
+    let mut media_engine = MediaEngine::default();
+    media_engine.register_default_codecs().unwrap();
+    let api = APIBuilder::new().with_media_engine(media_engine).build();
+    Arc::new(
+        api.new_peer_connection(RTCConfiguration::default())
+            .await
+            .unwrap(),
+    )
+}
+
+/// Sets `description` and waits for gathering, as the GUI does before
+/// sending, so the SDP carries every candidate.
+async fn complete_sdp(pc: &RTCPeerConnection, description: RTCSessionDescription) -> String {
+    let mut gathered = pc.gathering_complete_promise().await;
+    pc.set_local_description(description).await.unwrap();
+    let _ = gathered.recv().await;
+    pc.local_description().await.unwrap().sdp
+}
+
+#[tokio::test]
+async fn room_members_negotiate_a_peer_connection() {
+    let url = format!("ws://{}", start(ServerConfig::default()));
+    let room = Room::new("demo-42");
+
+    let (alice, mut alice_inbox, welcome) = join(&url, None, &room.channel, "alice").await;
+    let mut alice_room = room.clone();
+    alice_room.apply(&welcome, "alice");
+    let (bob, mut bob_inbox, welcome) = join(&url, None, &room.channel, "bob").await;
+    let mut bob_room = room.clone();
+    bob_room.apply(&welcome, "bob");
+    alice_room.apply(&next(&mut alice_inbox).await, "alice");
+
+    // Whoever joined second offers, so both sides agree without asking.
+    assert!(bob_room.we_offer() && !alice_room.we_offer());
+    assert_eq!(alice_room.offerer(), bob_room.offerer());
+
+    let offerer = peer_connection().await;
+    let answerer = peer_connection().await;
+    let (opened_tx, mut opened) = mpsc::unbounded_channel();
+    answerer.on_data_channel(Box::new(move |channel| {
+        let opened_tx = opened_tx.clone();
+        Box::pin(async move {
+            channel.on_open(Box::new(move || {
+                let _ = opened_tx.send(());
+                Box::pin(async {})
+            }));
+        })
+    }));
+    let _channel = offerer.create_data_channel("chat", None).await.unwrap();
+
+    let offer = offerer.create_offer(None).await.unwrap();
+    let sdp = complete_sdp(&offerer, offer).await;
+    let alice_id = bob_room.partner().unwrap().peer_id.clone();
+    bob.send(ClientMessage::Signal {
+        to: alice_id,
+        payload: SignalPayload::Offer { sdp },
+    })
+    .unwrap();
+
+    let ServerMessage::Signal {
+        from,
+        payload: SignalPayload::Offer { sdp },
+    } = next(&mut alice_inbox).await
+    else {
+        panic!("expected bob's offer");
+    };
+    assert_eq!(Some(from.as_str()), alice_room.offerer());
+    answerer
+        .set_remote_description(RTCSessionDescription::offer(sdp).unwrap())
+        .await
+        .unwrap();
+    let answer = answerer.create_answer(None).await.unwrap();
+    let sdp = complete_sdp(&answerer, answer).await;
+    alice
+        .send(ClientMessage::Signal {
+            to: from,
+            payload: SignalPayload::Answer { sdp },
+        })
+        .unwrap();
+
+    let ServerMessage::Signal {
+        payload: SignalPayload::Answer { sdp },
+        ..
+    } = next(&mut bob_inbox).await
+    else {
+        panic!("expected alice's answer");
+    };
+    offerer
+        .set_remote_description(RTCSessionDescription::answer(sdp).unwrap())
+        .await
+        .unwrap();
+    timeout(WAIT, opened.recv())
+        .await
+        .expect("the data channel never opened");
+
+    alice.send(ClientMessage::Leave).unwrap();
+    assert!(matches!(
+        next(&mut bob_inbox).await,
+        ServerMessage::PeerLeft { .. }
+    ));
+    let _ = tokio::join!(offerer.close(), answerer.close());
+}
+
+#[tokio::test]
+async fn full_rooms_turn_members_away() {
+    let url = format!(
+        "ws://{}",
+        start(ServerConfig {
+            max_participants: Some(2),
+            ..Default::default()
+        })
+    );
+    let (_alice, _, _) = join(&url, None, "small", "alice").await;
+    let (_bob, _, _) = join(&url, None, "small", "bob").await;
+    let (_carol, _, reply) = join(&url, None, "small", "carol").await;
+    assert!(
+        matches!(&reply, ServerMessage::Error { message } if message.contains("full")),
+        "{:?}",
+        reply
+    );
+    let (_dave, _, reply) = join(&url, None, "elsewhere", "dave").await;
+    assert!(matches!(reply, ServerMessage::Welcome { .. }));
+}
+
+#[tokio::test]
+async fn tokens_are_required_once_configured() {
+    let address = start(ServerConfig {
+        tokens: ["s3cret".to_owned()].into(),
+        ..Default::default()
+    });
+    let url = format!("ws://{}", address);
+    let refused = SignalingClient::connect(&url, None).await;
+    assert!(matches!(refused, Err(SignalingError::WebSocket(_))));
+    let refused = SignalingClient::connect(&url, Some("guess")).await;
+    assert!(refused.is_err());
+    let (_client, _, reply) = join(&url, Some("s3cret"), "room", "alice").await;
+    assert!(matches!(reply, ServerMessage::Welcome { .. }));
+
+    let rooms = format!("http://{}/rooms", address);
+    let http = reqwest::Client::new();
+    let status = http.get(&rooms).send().await.unwrap().status();
+    assert_eq!(status, reqwest::StatusCode::UNAUTHORIZED);
+    let listed: serde_json::Value = http
+        .get(&rooms)
+        .bearer_auth("s3cret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(listed[0]["participants"], 1);
+}
+
+#[tokio::test]
+async fn rooms_can_be_required_to_exist() {
+    let address = start(ServerConfig {
+        auto_create: false,
+        ..Default::default()
+    });
+    let url = format!("ws://{}", address);
+    let room = Room::new("AB-12");
+    let (_early, _, reply) = join(&url, None, &room.channel, "early").await;
+    assert!(matches!(reply, ServerMessage::Error { .. }), "{:?}", reply);
+
+    let http = reqwest::Client::new();
+    let created = http
+        .post(format!("http://{}/rooms", address))
+        .json(&serde_json::json!({ "code": "ab12", "max_participants": 3 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(created.status(), reqwest::StatusCode::CREATED);
+    let created: serde_json::Value = created.json().await.unwrap();
+    assert_eq!(created["name"], room.channel.as_str());
+
+    let (_member, _, reply) = join(&url, None, &room.channel, "member").await;
+    assert!(
+        matches!(reply, ServerMessage::Welcome { .. }),
+        "{:?}",
+        reply
+    );
+
+    let again = http
+        .post(format!("http://{}/rooms", address))
+        .json(&serde_json::json!({ "name": room.channel }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(again.status(), reqwest::StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn chat_is_relayed_and_kept_for_later_members() {
+    let url = format!(
+        "ws://{}",
+        start(ServerConfig {
+            chat_history: 10,
+            ..Default::default()
+        })
+    );
+    let (alice, mut alice_inbox, _) = join(&url, None, "chatty", "alice").await;
+    let (bob, mut bob_inbox, _) = join(&url, None, "chatty", "bob").await;
+    assert!(matches!(
+        next(&mut alice_inbox).await,
+        ServerMessage::PeerJoined { .. }
+    ));
+
+    let hello = ChatMessage::new("alice", "are you still there?");
+    alice
+        .send(ClientMessage::Chat {
+            message: hello.clone(),
+        })
+        .unwrap();
+    let ServerMessage::Chat { message, .. } = next(&mut bob_inbox).await else {
+        panic!("expected alice's chat");
+    };
+    assert_eq!(message, hello);
+
+    // Both leave; the room keeps what was said for whoever comes back.
+    bob.send(ClientMessage::Leave).unwrap();
+    assert!(matches!(
+        next(&mut alice_inbox).await,
+        ServerMessage::PeerLeft { .. }
+    ));
+    alice.send(ClientMessage::Leave).unwrap();
+    // Once alice is out the server refuses her chat, so the room is empty.
+    alice
+        .send(ClientMessage::Chat {
+            message: ChatMessage::new("alice", "bye"),
+        })
+        .unwrap();
+    assert!(matches!(
+        next(&mut alice_inbox).await,
+        ServerMessage::Error { .. }
+    ));
+    let (_bob, mut bob_inbox, welcome) = join(&url, None, "chatty", "bob").await;
+    assert!(
+        matches!(&welcome, ServerMessage::Welcome { peers, .. } if peers.is_empty()),
+        "{:?}",
+        welcome
+    );
+    let ServerMessage::Chat { message, .. } = next(&mut bob_inbox).await else {
+        panic!("expected the room's history");
+    };
+    assert_eq!(message, hello);
+}
+
+#[tokio::test]
+async fn chat_is_refused_without_history() {
+    let url = format!("ws://{}", start(ServerConfig::default()));
+    let (alice, mut inbox, _) = join(&url, None, "quiet", "alice").await;
+    alice
+        .send(ClientMessage::Chat {
+            message: ChatMessage::new("alice", "hello?"),
+        })
+        .unwrap();
+    assert!(
+        matches!(next(&mut inbox).await, ServerMessage::Error { message } if message.contains("chat"))
+    );
+}
+
+#[tokio::test]
+async fn joins_check_the_invite_they_carry() {
+    let url = format!("ws://{}", start(ServerConfig::default()));
+    let room = Room::new("party");
+    let (alice, mut alice_inbox, _) = join(&url, None, &room.channel, "alice").await;
+    alice.send(ClientMessage::Invite { valid_for: 60 }).unwrap();
+    let ServerMessage::Invite { token } = next(&mut alice_inbox).await else {
+        panic!("expected an invite token");
+    };
+
+    alice.send(ClientMessage::Invite { valid_for: 0 }).unwrap();
+    let ServerMessage::Invite { token: expired } = next(&mut alice_inbox).await else {
+        panic!("expected an invite token");
+    };
+
+    let follow = |channel: &str, token: Option<&str>| ClientMessage::Join {
+        room: channel.to_owned(),
+        name: "guest".to_owned(),
+        resume: None,
+        resume_secret: None,
+        invite: token.map(str::to_owned),
+    };
+    let (guest, mut inbox) = SignalingClient::connect(&url, None).await.unwrap();
+    let (expires, signature) = token.split_once('.').unwrap();
+    let stretched = format!("{}0.{}", expires, signature);
+    // Leaving the token out fares no better than any bad one.
+    let mut refusals = Vec::new();
+    for (channel, token) in [
+        (room.channel.as_str(), None),
+        (room.channel.as_str(), Some(expired.as_str())),
+        ("elsewhere", Some(token.as_str())),
+        (room.channel.as_str(), Some(stretched.as_str())),
+    ] {
+        guest.send(follow(channel, token)).unwrap();
+        match next(&mut inbox).await {
+            ServerMessage::Error { message } => refusals.push(message),
+            other => panic!("joined {} with {:?}: {:?}", channel, token, other),
+        }
+    }
+    assert!(refusals.iter().all(|message| *message == refusals[0]));
+    guest.send(follow(&room.channel, Some(&token))).unwrap();
+    let reply = next(&mut inbox).await;
+    assert!(
+        matches!(&reply, ServerMessage::Welcome { peers, .. } if peers.len() == 1),
+        "{:?}",
+        reply
+    );
+}
+
+#[tokio::test]
+async fn serves_wss_with_a_self_signed_certificate() {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+    let dir = std::env::temp_dir().join(format!("signaling-server-tls-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (cert, key) = (dir.join("cert.pem"), dir.join("key.pem"));
+    let cert_pem = certified.cert.pem();
+    std::fs::write(&cert, &cert_pem).unwrap();
+    std::fs::write(&key, certified.key_pair.serialize_pem()).unwrap();
+
+    let address = start(ServerConfig {
+        tls: Some(signaling_server::TlsFiles { cert, key }),
+        ..Default::default()
+    });
+    let url = format!("wss://localhost:{}", address.port());
+    // Nobody vouches for the certificate until the client is told to.
+    assert!(SignalingClient::connect(&url, None).await.is_err());
+    let (client, mut inbox) = SignalingClient::connect_trusting(&url, None, cert_pem.as_bytes())
+        .await
+        .unwrap();
+    client
+        .send(ClientMessage::Join {
+            room: "secure".to_owned(),
+            name: "alice".to_owned(),
+            resume: None,
+            resume_secret: None,
+            invite: None,
+        })
+        .unwrap();
+    assert!(matches!(
+        next(&mut inbox).await,
+        ServerMessage::Welcome { .. }
+    ));
+    let _ = std::fs::remove_dir_all(dir);
+}
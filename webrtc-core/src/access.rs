@@ -0,0 +1,147 @@
+//! Allow and block lists for incoming calls. Entries are signaling
+//! identities (a room name, a Nostr public key, a SIP URI) or DTLS
+//! certificate fingerprints pinned from a previous call.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessList {
+    /// Turn away everyone who is not on `allowed`.
+    pub allowlist_only: bool,
+    pub allowed: Vec<String>,
+    pub blocked: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Denied {
+    #[error("{0} is blocked")]
+    Blocked(String),
+    #[error("{0} is not on the allowlist")]
+    NotAllowed(String),
+}
+
+impl AccessList {
+    /// Checks a caller by identity and by the fingerprints in their offer.
+    /// Blocking wins over allowing.
+    pub fn check(&self, identity: &str, offer_sdp: &str) -> Result<(), Denied> {
+        let fingerprints = sdp_fingerprints(offer_sdp);
+        let matches = |entry: &String| {
+            let entry = entry.trim();
+            !entry.is_empty()
+                && (entry.eq_ignore_ascii_case(identity.trim())
+                    || fingerprints
+                        .iter()
+                        .any(|fingerprint| same_fingerprint(entry, fingerprint)))
+        };
+        if let Some(entry) = self.blocked.iter().find(|entry| matches(entry)) {
+            return Err(Denied::Blocked(entry.trim().to_owned()));
+        }
+        if self.allowlist_only && !self.allowed.iter().any(matches) {
+            return Err(Denied::NotAllowed(identity.to_owned()));
+        }
+        Ok(())
+    }
+}
+
+/// The `a=fingerprint` values of an SDP, such as `sha-256 AB:CD:...`.
+pub fn sdp_fingerprints(sdp: &str) -> Vec<String> {
+    sdp.lines()
+        .filter_map(|line| line.trim().strip_prefix("a=fingerprint:"))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Compares fingerprints ignoring case and colons. An entry without a hash
+/// algorithm matches a fingerprint made with any of them.
+fn same_fingerprint(entry: &str, fingerprint: &str) -> bool {
+    let digits = |value: &str| -> String {
+        value
+            .chars()
+            .filter(|c| *c != ':')
+            .map(|c| c.to_ascii_uppercase())
+            .collect()
+    };
+    let (algorithm, value) = fingerprint.split_once(' ').unwrap_or(("", fingerprint));
+    let (entry_algorithm, entry_value) = match entry.split_once(' ') {
+        Some((algorithm, value)) => (Some(algorithm), value),
+        None => (None, entry),
+    };
+    // Identities are compared separately; only hex strings can be fingerprints.
+    let entry_digits = digits(entry_value.trim());
+    entry_digits.len() >= 32
+        && entry_digits.chars().all(|c| c.is_ascii_hexdigit())
+        && entry_algorithm.is_none_or(|a| a.eq_ignore_ascii_case(algorithm))
+        && entry_digits == digits(value.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FINGERPRINT: &str = "sha-256 4A:AD:B9:B1:3F:82:18:3B:54:02:12:DF:3E:5D:49:6B:\
+                               19:E5:7C:AB:3E:4B:65:0B:AB:38:A3:E2:4F:C2:2B:B7";
+
+    fn offer() -> String {
+        format!(
+            "v=0\r\nm=audio 9 UDP/TLS/RTP/SAVPF 111\r\na=fingerprint:{}\r\n",
+            FINGERPRINT
+        )
+    }
+
+    #[test]
+    fn normalizes_case_and_colons() {
+        let value = FINGERPRINT.split_once(' ').unwrap().1;
+        assert!(same_fingerprint(FINGERPRINT, FINGERPRINT));
+        assert!(same_fingerprint(&value.to_lowercase(), FINGERPRINT));
+        assert!(same_fingerprint(&value.replace(':', ""), FINGERPRINT));
+        assert!(same_fingerprint(
+            &format!("SHA-256 {}", value.replace(':', "").to_lowercase()),
+            FINGERPRINT
+        ));
+        assert_eq!(sdp_fingerprints(&offer()), [FINGERPRINT]);
+    }
+
+    #[test]
+    fn keeps_algorithms_apart() {
+        let value = FINGERPRINT.split_once(' ').unwrap().1;
+        assert!(!same_fingerprint(&format!("sha-1 {}", value), FINGERPRINT));
+        assert!(!same_fingerprint(
+            &format!("sha-256 {}", value.replace("4A", "4B")),
+            FINGERPRINT
+        ));
+        // Too short, or not hex, to be taken for a fingerprint.
+        assert!(!same_fingerprint("4A:AD", "sha-256 4A:AD"));
+        let named = "alice-the-caller-with-a-long-name-here";
+        assert!(!same_fingerprint(named, &format!("sha-256 {}", named)));
+    }
+
+    #[test]
+    fn blocking_wins_over_allowing() {
+        let mut access = AccessList {
+            allowlist_only: true,
+            allowed: vec![" Alice ".to_owned()],
+            blocked: vec![],
+        };
+        assert_eq!(access.check("alice", ""), Ok(()));
+        assert_eq!(
+            access.check("bob", &offer()),
+            Err(Denied::NotAllowed("bob".to_owned()))
+        );
+        access.allowed.push(FINGERPRINT.to_lowercase());
+        assert_eq!(access.check("bob", &offer()), Ok(()));
+
+        access.blocked = vec![String::new(), FINGERPRINT.to_owned()];
+        assert_eq!(
+            access.check("alice", &offer()),
+            Err(Denied::Blocked(FINGERPRINT.to_owned()))
+        );
+        access.blocked = vec!["ALICE".to_owned()];
+        assert_eq!(
+            access.check("alice", ""),
+            Err(Denied::Blocked("ALICE".to_owned()))
+        );
+        access.allowlist_only = false;
+        assert_eq!(access.check("carol", ""), Ok(()));
+    }
+}
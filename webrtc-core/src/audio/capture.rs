@@ -0,0 +1,295 @@
+//! Microphone capture through cpal, or a test signal, encoded to Opus.
+
+use std::sync::mpsc as std_mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::SizedSample;
+use log::info;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use super::processing::CaptureChain;
+use super::{
+    device_name, downmix, mix_channels, synthetic, AudioError, AudioLevel, CaptureOptions,
+    ChannelMap, EchoReference, LevelMeter, MicrophoneTrack, OpusApplication, OpusSettings,
+    Protection, Resampler, TestSignal, OPUS_FRAME, OPUS_SAMPLE_RATE,
+};
+use crate::recording::CallMix;
+use crate::snapshot::Snapshot;
+
+/// Callbacks' worth of samples waiting for the encoder; more than this and
+/// the newest are dropped.
+const QUEUE: usize = 64;
+
+/// Opus never needs more than this for one 20 ms frame, stereo or not.
+const MAX_PACKET: usize = 4000;
+
+/// How often the capture thread checks whether it is still wanted.
+const POLL: Duration = Duration::from_millis(200);
+
+fn input_device(device_id: Option<&str>) -> Result<cpal::Device, AudioError> {
+    let host = cpal::default_host();
+    let Some(device_id) = device_id else {
+        return host.default_input_device().ok_or(AudioError::NoDevice);
+    };
+    let name = device_name(&host, device_id);
+    host.input_devices()
+        .map_err(|err| AudioError::Backend(err.to_string()))?
+        .find(|device| device.name().is_ok_and(|n| n == name))
+        .ok_or_else(|| AudioError::DeviceNotFound(name.to_owned()))
+}
+
+/// 48 kHz when the device offers it, which Opus takes as is; otherwise
+/// the device default, resampled.
+fn input_config(device: &cpal::Device) -> Result<cpal::SupportedStreamConfig, AudioError> {
+    let wanted = cpal::SampleRate(OPUS_SAMPLE_RATE);
+    let native = device
+        .supported_input_configs()
+        .ok()
+        .and_then(|mut configs| {
+            configs.find(|range| {
+                range.min_sample_rate() <= wanted && wanted <= range.max_sample_rate()
+            })
+        });
+    match native {
+        Some(range) => Ok(range.with_sample_rate(wanted)),
+        None => device
+            .default_input_config()
+            .map_err(|err| AudioError::Backend(err.to_string())),
+    }
+}
+
+/// Input channels capture would open `device` with.
+pub(crate) fn input_channels(device: &cpal::Device) -> Option<u16> {
+    input_config(device).ok().map(|config| config.channels())
+}
+
+fn encoder(settings: &OpusSettings) -> Result<opus::Encoder, AudioError> {
+    let application = match settings.application {
+        OpusApplication::Voip => opus::Application::Voip,
+        OpusApplication::Audio => opus::Application::Audio,
+    };
+    let encoder_error = |err: opus::Error| AudioError::Encoder(err.to_string());
+    let channels = if settings.stereo {
+        opus::Channels::Stereo
+    } else {
+        opus::Channels::Mono
+    };
+    let mut encoder =
+        opus::Encoder::new(OPUS_SAMPLE_RATE, channels, application).map_err(encoder_error)?;
+    let bitrate = settings
+        .bitrate_bps()
+        .map_or(opus::Bitrate::Auto, opus::Bitrate::Bits);
+    encoder.set_bitrate(bitrate).map_err(encoder_error)?;
+    encoder.set_dtx(settings.dtx).map_err(encoder_error)?;
+    let protection = match settings.loss_threshold_percent {
+        _ if !settings.fec && !settings.red => "off".to_owned(),
+        Some(percent) => format!("past {}% loss", percent),
+        None => "on".to_owned(),
+    };
+    info!(
+        "Encoding the microphone as {} Opus for {:?} at {}, FEC {}, RED {}, DTX {}, \
+         protection {}",
+        if settings.stereo { "stereo" } else { "mono" },
+        settings.application,
+        settings
+            .bitrate_kbps
+            .map_or("automatic bitrate".to_owned(), |kbps| format!(
+                "{} kbps",
+                kbps
+            )),
+        if settings.fec { "on" } else { "off" },
+        if settings.red { "on" } else { "off" },
+        if settings.dtx { "on" } else { "off" },
+        protection,
+    );
+    Ok(encoder)
+}
+
+/// Aims the encoder's in-band FEC at what `protection` says.
+fn protect(encoder: &mut opus::Encoder, protection: &Protection) -> Result<(), AudioError> {
+    let encoder_error = |err: opus::Error| AudioError::Encoder(err.to_string());
+    encoder
+        .set_inband_fec(protection.fec())
+        .map_err(encoder_error)?;
+    encoder
+        .set_packet_loss_perc(i32::from(protection.loss_percent()))
+        .map_err(encoder_error)
+}
+
+pub fn spawn(
+    device_id: Option<&str>,
+    options: CaptureOptions,
+    track: Arc<MicrophoneTrack>,
+    level: Snapshot<AudioLevel>,
+    muted: Snapshot<bool>,
+    echo: &EchoReference,
+    mix: &CallMix,
+) -> Result<JoinHandle<()>, AudioError> {
+    let mut encoder = encoder(&options.opus)?;
+    let mut protection = Protection::new(&options.opus);
+    protect(&mut encoder, &protection)?;
+    let loss = track.loss();
+    let channels = options.opus.channels();
+    let mut chain = CaptureChain::new(&options.processing, echo, channels)?;
+    let mix = mix.clone();
+    let (chunks_tx, mut chunks) = mpsc::channel::<Vec<f32>>(QUEUE);
+    let sample_rate = match device_id.and_then(TestSignal::from_device_id) {
+        Some(signal) => {
+            synthetic::spawn(signal, options.test_tone_hz, channels, chunks_tx);
+            info!("Sending {} instead of a microphone", signal.name());
+            OPUS_SAMPLE_RATE
+        }
+        None => open(device_id, options.channels, channels, chunks_tx)?,
+    };
+
+    Ok(tokio::spawn(async move {
+        let mut resampler = Resampler::with_channels(sample_rate, OPUS_SAMPLE_RATE, channels);
+        let mut meter = LevelMeter::new(OPUS_SAMPLE_RATE, level);
+        let mut pcm = Vec::new();
+        // The meter and the call recording take mono.
+        let mut mono = Vec::with_capacity(OPUS_FRAME);
+        let frame_len = OPUS_FRAME * channels;
+        let silence = vec![0.0; frame_len];
+        let mut packet = vec![0; MAX_PACKET];
+        while let Some(chunk) = chunks.recv().await {
+            resampler.push(&chunk, &mut pcm);
+            let mut start = 0;
+            while pcm.len() - start >= frame_len {
+                let frame = &mut pcm[start..start + frame_len];
+                start += frame_len;
+                chain.process(frame);
+                mono.clear();
+                downmix(channels, frame, &mut mono);
+                meter.push(&mono);
+                let frame = if *muted.get() {
+                    mono.fill(0.0);
+                    &silence[..]
+                } else {
+                    frame
+                };
+                mix.local(&mono);
+                let engaged = protection.fec() || protection.redundant();
+                if protection.update(*loss.get()) {
+                    if engaged != (protection.fec() || protection.redundant()) {
+                        info!(
+                            "Peer reports {}% loss; {} audio protection",
+                            protection.loss_percent(),
+                            if engaged { "dropping" } else { "adding" }
+                        );
+                    }
+                    if let Err(err) = protect(&mut encoder, &protection) {
+                        info!("{}", err);
+                    }
+                }
+                let len = match encoder.encode_float(frame, &mut packet) {
+                    Ok(len) => len,
+                    Err(err) => {
+                        info!("Opus encode error: {}", err);
+                        continue;
+                    }
+                };
+                let written = track
+                    .write_frame(&packet[..len], OPUS_FRAME as u32, protection.redundant())
+                    .await;
+                if let Err(err) = written {
+                    info!("Failed to write microphone sample: {:?}", err);
+                }
+            }
+            pcm.drain(..start);
+        }
+    }))
+}
+
+/// Opens the microphone on a thread of its own, queueing what it hears,
+/// mixed to `outputs` channels, into `chunks_tx` until the receiving side
+/// goes away, and returns its sample rate.
+fn open(
+    device_id: Option<&str>,
+    channels: ChannelMap,
+    outputs: usize,
+    chunks_tx: mpsc::Sender<Vec<f32>>,
+) -> Result<u32, AudioError> {
+    let (ready_tx, ready) = std_mpsc::channel();
+    let device_id = device_id.map(str::to_owned);
+
+    // cpal streams cannot move between threads, so one thread owns it
+    // until the encoder goes away.
+    std::thread::Builder::new()
+        .name("microphone".to_owned())
+        .spawn(move || {
+            let opened = input_device(device_id.as_deref()).and_then(|device| {
+                let config = input_config(&device)?;
+                let format = config.sample_format();
+                let config = config.config();
+                let sample_rate = config.sample_rate.0;
+                let weights = channels.weights(usize::from(config.channels), outputs);
+                let chunks = chunks_tx.clone();
+                let stream = match format {
+                    cpal::SampleFormat::F32 => build::<f32>(&device, &config, weights, chunks),
+                    cpal::SampleFormat::I16 => build::<i16>(&device, &config, weights, chunks),
+                    cpal::SampleFormat::U16 => build::<u16>(&device, &config, weights, chunks),
+                    other => Err(AudioError::Backend(format!(
+                        "unsupported sample format {}",
+                        other
+                    ))),
+                }?;
+                stream
+                    .play()
+                    .map_err(|err| AudioError::Backend(err.to_string()))?;
+                Ok((stream, sample_rate))
+            });
+            let stream = match opened {
+                Ok((stream, sample_rate)) => {
+                    let _ = ready_tx.send(Ok(sample_rate));
+                    stream
+                }
+                Err(err) => {
+                    let _ = ready_tx.send(Err(err));
+                    return;
+                }
+            };
+            while !chunks_tx.is_closed() {
+                std::thread::sleep(POLL);
+            }
+            drop(stream);
+        })
+        .map_err(|err| AudioError::Backend(err.to_string()))?;
+
+    let sample_rate = ready
+        .recv()
+        .map_err(|_| AudioError::Backend("microphone thread ended".to_owned()))??;
+    info!("Capturing microphone at {} Hz", sample_rate);
+    Ok(sample_rate)
+}
+
+/// An input stream that mixes each callback down to the call's channels
+/// by `weights` (see `ChannelMap::weights`) and queues it.
+fn build<T: SizedSample>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    weights: Vec<Vec<f32>>,
+    chunks: mpsc::Sender<Vec<f32>>,
+) -> Result<cpal::Stream, AudioError>
+where
+    f32: cpal::FromSample<T>,
+{
+    let mut samples = Vec::new();
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _| {
+                samples.clear();
+                samples.extend(data.iter().map(|s| s.to_sample::<f32>()));
+                let mut mixed = Vec::new();
+                mix_channels(&weights, &samples, &mut mixed);
+                // Falling behind drops audio rather than delaying it.
+                let _ = chunks.try_send(mixed);
+            },
+            |err| info!("Microphone stream error: {}", err),
+            None,
+        )
+        .map_err(|err| AudioError::Backend(err.to_string()))
+}
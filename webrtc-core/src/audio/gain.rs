@@ -0,0 +1,113 @@
+//! Gain on the microphone: automatic, bringing a voice to a steady
+//! loudness however far it is from the microphone, or fixed.
+
+use super::level::dbfs;
+
+/// Where automatic gain puts a voice, as the average of its frames.
+const TARGET_DBFS: f32 = -18.0;
+/// Frames quieter than this are pauses, which leave the gain alone so
+/// the room is not brought up to speaking level.
+const GATE_DBFS: f32 = -50.0;
+/// The most automatic gain adds or takes away.
+const MAX_GAIN_DB: f32 = 30.0;
+const MIN_GAIN_DB: f32 = -10.0;
+/// Slow to rise, so a pause does not end in a burst; fast to fall.
+const RISE_DB_PER_SECOND: f32 = 6.0;
+const FALL_DB_PER_SECOND: f32 = 30.0;
+/// Peaks are held under this, just short of clipping.
+const CEILING: f32 = 0.9;
+
+fn linear(gain_db: f32) -> f32 {
+    10f32.powf(gain_db / 20.0)
+}
+
+/// Multiplies `frame` by `gain_db`, clipping at full scale.
+pub fn apply_gain(frame: &mut [f32], gain_db: f32) {
+    let gain = linear(gain_db);
+    for sample in frame {
+        *sample = (*sample * gain).clamp(-1.0, 1.0);
+    }
+}
+
+/// Follows the loudness of speech and moves the gain to bring it to
+/// `TARGET_DBFS`, never so far that a peak would clip.
+pub struct AutomaticGain {
+    sample_rate: u32,
+    gain_db: f32,
+}
+
+impl AutomaticGain {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            gain_db: 0.0,
+        }
+    }
+
+    /// The gain applied to the last frame.
+    pub fn gain_db(&self) -> f32 {
+        self.gain_db
+    }
+
+    pub fn process(&mut self, frame: &mut [f32]) {
+        if frame.is_empty() {
+            return;
+        }
+        let square_sum: f32 = frame.iter().map(|s| s * s).sum();
+        let level = dbfs((square_sum / frame.len() as f32).sqrt());
+        if level > GATE_DBFS {
+            let wanted = (TARGET_DBFS - level).clamp(MIN_GAIN_DB, MAX_GAIN_DB);
+            let seconds = frame.len() as f32 / self.sample_rate as f32;
+            let step = if wanted > self.gain_db {
+                RISE_DB_PER_SECOND
+            } else {
+                FALL_DB_PER_SECOND
+            } * seconds;
+            self.gain_db += (wanted - self.gain_db).clamp(-step, step);
+        }
+        let peak = frame.iter().fold(0.0, |peak: f32, s| peak.max(s.abs()));
+        if peak > 0.0 && peak * linear(self.gain_db) > CEILING {
+            self.gain_db = 20.0 * (CEILING / peak).log10();
+        }
+        apply_gain(frame, self.gain_db);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(amplitude: f32) -> Vec<f32> {
+        (0..960)
+            .map(|i| amplitude * (i as f32 * 0.1).sin())
+            .collect()
+    }
+
+    #[test]
+    fn brings_a_quiet_voice_up_without_clipping() {
+        let mut gain = AutomaticGain::new(48000);
+        // -43 dBFS on average; ten seconds at 6 dB a second.
+        for _ in 0..500 {
+            gain.process(&mut tone(0.01));
+        }
+        assert!((gain.gain_db() - 25.0).abs() < 0.5, "{}", gain.gain_db());
+
+        // A shout right after is held under the ceiling at once.
+        let mut loud = tone(0.5);
+        gain.process(&mut loud);
+        assert!(loud.iter().all(|s| s.abs() <= CEILING + 1e-4));
+
+        // Silence leaves the gain where it was.
+        let before = gain.gain_db();
+        gain.process(&mut [0.0; 960]);
+        assert_eq!(gain.gain_db(), before);
+    }
+
+    #[test]
+    fn fixed_gain_clips() {
+        let mut frame = [0.25, -0.75];
+        apply_gain(&mut frame, 6.0);
+        assert!((frame[0] - 0.499).abs() < 0.01);
+        assert_eq!(frame[1], -1.0);
+    }
+}
@@ -0,0 +1,115 @@
+//! How loud a stream is right now, for a meter that shows at a glance
+//! whether audio is flowing.
+
+use crate::snapshot::Snapshot;
+
+/// The bottom of the meter; anything quieter reads as silence.
+pub const SILENCE_DBFS: f32 = -60.0;
+
+/// How fast the meter falls back once the sound stops, in dB a second.
+const FALL_DB_PER_SECOND: f32 = 30.0;
+
+/// The loudness of the last stretch of a stream, in dB below full scale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioLevel {
+    /// Average loudness, falling back gradually rather than at once.
+    pub rms_dbfs: f32,
+    /// The loudest sample, falling back the same way.
+    pub peak_dbfs: f32,
+}
+
+impl Default for AudioLevel {
+    fn default() -> Self {
+        Self {
+            rms_dbfs: SILENCE_DBFS,
+            peak_dbfs: SILENCE_DBFS,
+        }
+    }
+}
+
+impl AudioLevel {
+    /// Where `dbfs` sits on a meter running from silence to full scale.
+    pub fn fraction(dbfs: f32) -> f32 {
+        ((dbfs - SILENCE_DBFS) / -SILENCE_DBFS).clamp(0.0, 1.0)
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.peak_dbfs <= SILENCE_DBFS
+    }
+}
+
+pub(super) fn dbfs(amplitude: f32) -> f32 {
+    if amplitude > 0.0 {
+        (20.0 * amplitude.log10()).max(SILENCE_DBFS)
+    } else {
+        SILENCE_DBFS
+    }
+}
+
+/// Measures the samples pushed through it and publishes their level.
+pub struct LevelMeter {
+    sample_rate: u32,
+    level: AudioLevel,
+    published: Snapshot<AudioLevel>,
+}
+
+impl LevelMeter {
+    pub fn new(sample_rate: u32, published: Snapshot<AudioLevel>) -> Self {
+        published.set(AudioLevel::default());
+        Self {
+            sample_rate,
+            level: AudioLevel::default(),
+            published,
+        }
+    }
+
+    /// Takes the next stretch of mono `samples` and publishes the level.
+    pub fn push(&mut self, samples: &[f32]) {
+        if samples.is_empty() {
+            return;
+        }
+        let square_sum: f32 = samples.iter().map(|s| s * s).sum();
+        let rms = dbfs((square_sum / samples.len() as f32).sqrt());
+        let peak = dbfs(samples.iter().fold(0.0, |peak: f32, s| peak.max(s.abs())));
+        let fall = FALL_DB_PER_SECOND * samples.len() as f32 / self.sample_rate as f32;
+        let falling = |previous: f32, now: f32| now.max(previous - fall).max(SILENCE_DBFS);
+        self.level = AudioLevel {
+            rms_dbfs: falling(self.level.rms_dbfs, rms),
+            peak_dbfs: falling(self.level.peak_dbfs, peak),
+        };
+        self.published.set(self.level);
+    }
+}
+
+impl Drop for LevelMeter {
+    /// A stream that ended reads as silence rather than stuck where it was.
+    fn drop(&mut self) {
+        self.published.set(AudioLevel::default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_and_falls_back() {
+        let published = Snapshot::default();
+        let mut meter = LevelMeter::new(48000, published.clone());
+        meter.push(&[0.5, -0.5, 0.5, -0.5]);
+        let loud = *published.get();
+        assert!((loud.rms_dbfs - -6.02).abs() < 0.01);
+        assert!((loud.peak_dbfs - -6.02).abs() < 0.01);
+
+        // A tenth of a second of silence takes 3 dB off.
+        meter.push(&[0.0; 4800]);
+        let falling = *published.get();
+        assert!((falling.peak_dbfs - -9.02).abs() < 0.01);
+        assert!(!falling.is_silent());
+
+        drop(meter);
+        assert!(published.get().is_silent());
+        assert_eq!(AudioLevel::fraction(SILENCE_DBFS), 0.0);
+        assert_eq!(AudioLevel::fraction(-30.0), 0.5);
+    }
+}
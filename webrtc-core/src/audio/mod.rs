@@ -0,0 +1,565 @@
+//! Audio sources, playback, and sending the microphone.
+
+#[cfg(feature = "audio")]
+mod capture;
+mod gain;
+mod level;
+#[cfg(feature = "audio")]
+mod output;
+#[cfg(feature = "audio")]
+mod playback;
+mod processing;
+mod protection;
+mod route;
+mod synthetic;
+mod track;
+
+#[cfg(feature = "audio")]
+pub(crate) use capture::input_channels;
+pub use gain::{apply_gain, AutomaticGain};
+pub use level::{AudioLevel, LevelMeter, SILENCE_DBFS};
+pub use processing::{can_cancel_echo, EchoReference, ProcessingSettings};
+pub use protection::{watch_loss, Protection};
+pub use route::{OutputRoute, OutputStatus};
+pub use synthetic::TestSignal;
+pub use track::MicrophoneTrack;
+
+use std::f32::consts::TAU;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use webrtc::track::track_remote::TrackRemote;
+
+use crate::lipsync::LipSync;
+use crate::recording::{CallMix, RecordingTap};
+use crate::snapshot::Snapshot;
+
+#[derive(Debug, Error)]
+pub enum AudioError {
+    #[error("audio support is not compiled into this build")]
+    Unsupported,
+    #[error("no output device named {0:?}")]
+    DeviceNotFound(String),
+    #[error("no audio output device available")]
+    NoDevice,
+    #[error("audio backend error: {0}")]
+    Backend(String),
+    #[error("Opus encoder error: {0}")]
+    Encoder(String),
+    #[error("Opus decoder error: {0}")]
+    Decoder(String),
+    #[error("audio processing error: {0}")]
+    Processing(String),
+}
+
+/// Opus always runs at 48 kHz on the wire.
+pub const OPUS_SAMPLE_RATE: u32 = 48000;
+/// Samples in one 20 ms Opus frame at `OPUS_SAMPLE_RATE`.
+pub const OPUS_FRAME: usize = 960;
+
+/// Which input channels of the microphone feed which channels of the call,
+/// and how loud, for interfaces with more inputs than voices (a USB mixer
+/// whose microphone is on channels 3 and 4, say).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelMap {
+    /// `routes[output][input]`. Left empty, inputs take turns across the
+    /// outputs: a stereo microphone keeps its sides in a stereo call, a
+    /// mono one feeds both, and everything mixes into a mono one.
+    pub routes: Vec<Vec<bool>>,
+    /// Per input channel; channels without one are left at 0 dB.
+    pub gains_db: Vec<f32>,
+}
+
+impl ChannelMap {
+    pub fn is_default(&self) -> bool {
+        self.routes.is_empty() && self.gains_db.iter().all(|&gain| gain == 0.0)
+    }
+
+    /// Whether `input` feeds `output`, of `inputs` and `outputs`.
+    pub fn feeds(&self, output: usize, input: usize, inputs: usize, outputs: usize) -> bool {
+        if self.routes.is_empty() {
+            return input % outputs.max(1) == output % inputs.max(1);
+        }
+        self.routes
+            .get(output)
+            .and_then(|inputs| inputs.get(input))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Routes `input` to `output` or not, first writing out the default
+    /// routing of `inputs` to `outputs` if nothing was chosen yet.
+    pub fn set_feeds(
+        &mut self,
+        output: usize,
+        input: usize,
+        (inputs, outputs): (usize, usize),
+        feeds: bool,
+    ) {
+        if self.routes.is_empty() {
+            self.routes = (0..outputs)
+                .map(|o| {
+                    (0..inputs)
+                        .map(|i| self.feeds(o, i, inputs, outputs))
+                        .collect()
+                })
+                .collect();
+        }
+        if self.routes.len() <= output {
+            self.routes.resize(output + 1, vec![]);
+        }
+        let row = &mut self.routes[output];
+        if row.len() <= input {
+            row.resize(input + 1, false);
+        }
+        row[input] = feeds;
+    }
+
+    pub fn gain_db(&self, input: usize) -> f32 {
+        self.gains_db.get(input).copied().unwrap_or(0.0)
+    }
+
+    pub fn set_gain_db(&mut self, input: usize, gain_db: f32) {
+        if self.gains_db.len() <= input {
+            self.gains_db.resize(input + 1, 0.0);
+        }
+        self.gains_db[input] = gain_db;
+    }
+
+    /// The weight of each input in each output, `[output][input]`. An
+    /// output averages the inputs routed to it, so a stereo microphone
+    /// mixed to mono is no louder than either side; gains apply on top.
+    pub fn weights(&self, inputs: usize, outputs: usize) -> Vec<Vec<f32>> {
+        (0..outputs)
+            .map(|output| {
+                let feeds = |input| self.feeds(output, input, inputs, outputs);
+                let routed = (0..inputs).filter(|&i| feeds(i)).count();
+                (0..inputs)
+                    .map(|input| {
+                        if !feeds(input) {
+                            return 0.0;
+                        }
+                        10f32.powf(self.gain_db(input) / 20.0) / routed as f32
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Mixes interleaved frames of `weights[0].len()` channels into frames of
+/// `weights.len()` channels, appended to `output`.
+pub fn mix_channels(weights: &[Vec<f32>], input: &[f32], output: &mut Vec<f32>) {
+    let channels = weights.first().map_or(0, Vec::len);
+    if channels == 0 {
+        return;
+    }
+    for frame in input.chunks_exact(channels) {
+        for row in weights {
+            output.push(row.iter().zip(frame).map(|(w, s)| w * s).sum());
+        }
+    }
+}
+
+/// Averages interleaved frames of `channels` into mono, appended to
+/// `output`.
+pub fn downmix(channels: usize, input: &[f32], output: &mut Vec<f32>) {
+    let weights = ChannelMap::default().weights(channels, 1);
+    mix_channels(&weights, input, output);
+}
+
+/// Whether this build can capture the microphone.
+pub fn can_capture() -> bool {
+    cfg!(feature = "audio")
+}
+
+/// The cpal device name in an id from `devices::enumerate`.
+#[cfg(feature = "audio")]
+fn device_name<'a>(host: &cpal::Host, device_id: &'a str) -> &'a str {
+    let prefix = format!("{}:", host.id().name());
+    device_id.strip_prefix(&prefix).unwrap_or(device_id)
+}
+
+/// Linear-interpolation resampling of interleaved frames, fed in chunks.
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    /// Input frames per output frame.
+    step: f64,
+    /// Where the next output frame falls, counted from the current chunk;
+    /// -1 is the last frame of the previous one.
+    position: f64,
+    last: Vec<f32>,
+}
+
+impl Resampler {
+    /// For a mono stream.
+    pub fn new(from: u32, to: u32) -> Self {
+        Self::with_channels(from, to, 1)
+    }
+
+    /// For frames of `channels` samples.
+    pub fn with_channels(from: u32, to: u32, channels: usize) -> Self {
+        Self {
+            step: f64::from(from) / f64::from(to),
+            position: 0.0,
+            last: vec![0.0; channels.max(1)],
+        }
+    }
+
+    pub fn push(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        if self.step == 1.0 {
+            output.extend_from_slice(input);
+            return;
+        }
+        let channels = self.last.len();
+        let frames = input.len() / channels;
+        if frames == 0 {
+            return;
+        }
+        let sample = |frame: isize, channel: usize| {
+            if frame < 0 {
+                self.last[channel]
+            } else {
+                input[frame as usize * channels + channel]
+            }
+        };
+        while self.position < (frames - 1) as f64 {
+            let index = self.position.floor();
+            let fraction = (self.position - index) as f32;
+            for channel in 0..channels {
+                let a = sample(index as isize, channel);
+                let b = sample(index as isize + 1, channel);
+                output.push(a + (b - a) * fraction);
+            }
+            self.position += self.step;
+        }
+        self.position -= frames as f64;
+        self.last
+            .copy_from_slice(&input[(frames - 1) * channels..frames * channels]);
+    }
+}
+
+/// What libopus tunes its encoding for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OpusApplication {
+    /// Speech: clearer voices, and the lowest bitrate they stay clear at.
+    #[default]
+    Voip,
+    /// Music and everything else, as played.
+    Audio,
+}
+
+impl OpusApplication {
+    pub const ALL: [OpusApplication; 2] = [OpusApplication::Voip, OpusApplication::Audio];
+}
+
+impl fmt::Display for OpusApplication {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OpusApplication::Voip => "Voice",
+            OpusApplication::Audio => "Music and general audio",
+        })
+    }
+}
+
+/// How the microphone is encoded to Opus.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpusSettings {
+    pub application: OpusApplication,
+    /// `None` leaves the bitrate to libopus, about 32 kbps for mono voice.
+    pub bitrate_kbps: Option<u32>,
+    /// In-band forward error correction: each packet also carries a rougher
+    /// copy of the one before, so a single lost packet can be rebuilt.
+    pub fec: bool,
+    /// The packet loss to spend the error correction on, in percent,
+    /// until the peer reports how much it is losing.
+    pub expected_loss_percent: u8,
+    /// Also send each packet again inside the next (RFC 2198 RED), where
+    /// the peer takes it.
+    pub red: bool,
+    /// Hold FEC and RED back until the peer reports more loss than this,
+    /// in percent; `None` has them on throughout.
+    pub loss_threshold_percent: Option<u8>,
+    /// Two channels, left and right, for music; the SDP asks the peer to
+    /// send two back.
+    pub stereo: bool,
+    /// Discontinuous transmission: frames of a byte or two while silent.
+    /// They still go out every 20 ms, as webrtc-rs can only leave a gap
+    /// in the timestamps by also leaving one in the sequence numbers,
+    /// which would read as loss.
+    pub dtx: bool,
+}
+
+impl Default for OpusSettings {
+    fn default() -> Self {
+        Self {
+            application: OpusApplication::Voip,
+            bitrate_kbps: None,
+            fec: true,
+            expected_loss_percent: 10,
+            red: false,
+            loss_threshold_percent: Some(2),
+            stereo: false,
+            dtx: false,
+        }
+    }
+}
+
+impl OpusSettings {
+    /// The bitrates Opus can encode at.
+    pub const BITRATE_KBPS: std::ops::RangeInclusive<u32> = 6..=510;
+
+    /// Channels sent: two in stereo, one otherwise.
+    pub fn channels(&self) -> usize {
+        if self.stereo {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// The chosen bitrate in bits per second, held inside what Opus
+    /// supports.
+    pub fn bitrate_bps(&self) -> Option<i32> {
+        self.bitrate_kbps.map(|kbps| {
+            let kbps = kbps.clamp(*Self::BITRATE_KBPS.start(), *Self::BITRATE_KBPS.end());
+            kbps as i32 * 1000
+        })
+    }
+}
+
+/// How `spawn_microphone` treats what it captures.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureOptions {
+    pub channels: ChannelMap,
+    /// The frequency of `TestSignal::Tone`, in Hz.
+    pub test_tone_hz: f32,
+    pub opus: OpusSettings,
+    pub processing: ProcessingSettings,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        Self {
+            channels: ChannelMap::default(),
+            test_tone_hz: 440.0,
+            opus: OpusSettings::default(),
+            processing: ProcessingSettings::default(),
+        }
+    }
+}
+
+/// Captures a microphone (cpal id as listed by `devices::enumerate`, or
+/// the default one), mixes its channels down by `options.channels` to
+/// those `options.opus` sends, and
+/// sends it as Opus encoded by `options.opus` into `track` until the
+/// returned task is aborted, publishing how loud it is into `level`. The
+/// ids of a `TestSignal` send that instead. While `muted` is set it sends
+/// silence, which DTX, when on, cuts down to a packet now and then; the
+/// level still follows the microphone. Echo of what is played into `echo`
+/// is cancelled when `options.processing` asks for it. What is sent goes
+/// to `mix` too. FEC and RED, as `options.opus` allows, follow the loss
+/// reported into `track.loss()`.
+pub fn spawn_microphone(
+    device_id: Option<&str>,
+    options: CaptureOptions,
+    track: Arc<MicrophoneTrack>,
+    level: Snapshot<AudioLevel>,
+    muted: Snapshot<bool>,
+    echo: &EchoReference,
+    mix: &CallMix,
+) -> Result<JoinHandle<()>, AudioError> {
+    #[cfg(feature = "audio")]
+    {
+        capture::spawn(device_id, options, track, level, muted, echo, mix)
+    }
+    #[cfg(not(feature = "audio"))]
+    {
+        let _ = (device_id, options, track, level, muted, echo, mix);
+        Err(AudioError::Unsupported)
+    }
+}
+
+/// Plays a remote Opus track on the speaker `route` asks for until the
+/// track ends, moving when it changes and falling back to the default
+/// speaker while it is gone. It is held back as long as `sync` says the
+/// audio is ahead of the video, and publishes how loud it is into `level`.
+/// What it plays goes to `echo` to be cancelled off the microphone and to
+/// `mix`, and each packet, as received, to `recording`.
+pub async fn play_remote_track(
+    track: Arc<TrackRemote>,
+    route: OutputRoute,
+    sync: Option<Arc<Mutex<LipSync>>>,
+    level: Snapshot<AudioLevel>,
+    echo: EchoReference,
+    recording: RecordingTap,
+    mix: CallMix,
+) -> Result<(), AudioError> {
+    #[cfg(feature = "audio")]
+    {
+        playback::play_track(track, route, sync, level, echo, recording, mix).await
+    }
+    #[cfg(not(feature = "audio"))]
+    {
+        let _ = (track, route, sync, level, echo, recording, mix);
+        Err(AudioError::Unsupported)
+    }
+}
+
+/// Decodes a remote Opus track that is not played, only to publish how
+/// loud it is into `level` and hand its packets to `recording`, until the
+/// track ends.
+pub async fn meter_remote_track(
+    track: Arc<TrackRemote>,
+    level: Snapshot<AudioLevel>,
+    recording: RecordingTap,
+) -> Result<(), AudioError> {
+    #[cfg(feature = "audio")]
+    {
+        playback::meter_track(track, level, recording).await
+    }
+    #[cfg(not(feature = "audio"))]
+    {
+        let _ = (track, level, recording);
+        Err(AudioError::Unsupported)
+    }
+}
+
+/// A sine tone, optionally gated into beeps.
+#[derive(Debug, Clone)]
+pub struct ToneGenerator {
+    pub frequency: f32,
+    pub amplitude: f32,
+    sample_rate: u32,
+    position: u64,
+}
+
+impl ToneGenerator {
+    pub fn new(frequency: f32, amplitude: f32, sample_rate: u32) -> Self {
+        Self {
+            frequency,
+            amplitude,
+            sample_rate,
+            position: 0,
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let t = self.position as f32 / self.sample_rate as f32;
+        self.position += 1;
+        (t * self.frequency * TAU).sin() * self.amplitude
+    }
+}
+
+/// The speaker check sound: three short beeps with soft edges so they do
+/// not click.
+pub fn speaker_test_envelope(t: f32) -> f32 {
+    const BEEP: f32 = 0.3;
+    const PERIOD: f32 = 0.5;
+    const FADE: f32 = 0.02;
+    if t >= PERIOD * 3.0 {
+        return 0.0;
+    }
+    let within = t % PERIOD;
+    if within >= BEEP {
+        return 0.0;
+    }
+    (within / FADE).min((BEEP - within) / FADE).min(1.0)
+}
+
+/// How long the speaker check sound lasts.
+pub const SPEAKER_TEST_SECONDS: f32 = 1.5;
+
+/// Plays the speaker check sound on the given output device (cpal id as
+/// listed by `devices::enumerate`), or the default one. Blocks until done.
+pub fn play_speaker_test(device_id: Option<&str>) -> Result<(), AudioError> {
+    #[cfg(feature = "audio")]
+    {
+        output::play(device_id, 880.0, SPEAKER_TEST_SECONDS)
+    }
+    #[cfg(not(feature = "audio"))]
+    {
+        let _ = device_id;
+        Err(AudioError::Unsupported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_map_picks_and_scales_inputs() {
+        let stereo = [0.2, 0.4, 0.2, 0.4];
+        let mut mono = vec![];
+        mix_channels(&ChannelMap::default().weights(2, 1), &stereo, &mut mono);
+        assert_eq!(mono, [0.3, 0.3]);
+
+        // Channels 3 and 4 of a four-input mixer, the fourth 6 dB down.
+        let mut map = ChannelMap::default();
+        map.set_feeds(0, 0, (4, 1), false);
+        map.set_feeds(0, 1, (4, 1), false);
+        map.set_gain_db(3, -20.0 * 2f32.log10());
+        let mut mono = vec![];
+        mix_channels(&map.weights(4, 1), &[9.0, 9.0, 0.5, 1.0], &mut mono);
+        assert!((mono[0] - 0.5).abs() < 1e-6, "{:?}", mono);
+        assert!(!map.is_default());
+    }
+
+    #[test]
+    fn channel_map_keeps_sides_in_stereo() {
+        let stereo = [0.2, 0.4, 0.2, 0.4];
+        let mut sent = vec![];
+        mix_channels(&ChannelMap::default().weights(2, 2), &stereo, &mut sent);
+        assert_eq!(sent, stereo);
+        let mut sent = vec![];
+        mix_channels(&ChannelMap::default().weights(1, 2), &[0.5, 0.1], &mut sent);
+        assert_eq!(sent, [0.5, 0.5, 0.1, 0.1]);
+
+        // The default written out, then the second input taken off the left.
+        let mut map = ChannelMap::default();
+        map.set_feeds(0, 1, (2, 2), false);
+        assert_eq!(map.routes, [vec![true, false], vec![false, true]]);
+        let mut mono = vec![];
+        downmix(2, &stereo, &mut mono);
+        assert!((mono[0] - 0.3).abs() < 1e-6, "{:?}", mono);
+    }
+
+    #[test]
+    fn resamples_each_channel_alike() {
+        let left: Vec<f32> = (0..480).map(|i| (i as f32 * 0.05).sin()).collect();
+        let right: Vec<f32> = left.iter().map(|s| -s).collect();
+        let stereo: Vec<f32> = left
+            .iter()
+            .zip(&right)
+            .flat_map(|(l, r)| [*l, *r])
+            .collect();
+        let mut mono = Resampler::new(48000, 44100);
+        let mut both = Resampler::with_channels(48000, 44100, 2);
+        let (mut expected, mut resampled) = (vec![], vec![]);
+        for (chunk, frames) in left.chunks(100).zip(stereo.chunks(200)) {
+            mono.push(chunk, &mut expected);
+            both.push(frames, &mut resampled);
+        }
+        assert_eq!(resampled.len(), expected.len() * 2);
+        for (frame, sample) in resampled.chunks(2).zip(&expected) {
+            assert_eq!(frame, [*sample, -sample]);
+        }
+    }
+
+    #[test]
+    fn opus_bitrate_stays_in_range() {
+        let mut opus = OpusSettings::default();
+        assert_eq!(opus.bitrate_bps(), None);
+        opus.bitrate_kbps = Some(2);
+        assert_eq!(opus.bitrate_bps(), Some(6000));
+        opus.bitrate_kbps = Some(64);
+        assert_eq!(opus.bitrate_bps(), Some(64_000));
+    }
+}
@@ -0,0 +1,77 @@
+//! Playback through cpal.
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample};
+
+use super::{device_name, speaker_test_envelope, AudioError, ToneGenerator};
+
+pub(super) fn output_device(device_id: Option<&str>) -> Result<cpal::Device, AudioError> {
+    let host = cpal::default_host();
+    let Some(device_id) = device_id else {
+        return host.default_output_device().ok_or(AudioError::NoDevice);
+    };
+    let name = device_name(&host, device_id);
+    host.output_devices()
+        .map_err(|err| AudioError::Backend(err.to_string()))?
+        .find(|device| device.name().is_ok_and(|n| n == name))
+        .ok_or_else(|| AudioError::DeviceNotFound(name.to_owned()))
+}
+
+pub fn play(device_id: Option<&str>, frequency: f32, seconds: f32) -> Result<(), AudioError> {
+    let device = output_device(device_id)?;
+    let config = device
+        .default_output_config()
+        .map_err(|err| AudioError::Backend(err.to_string()))?;
+    let (errors_tx, errors) = mpsc::channel();
+    let stream = match config.sample_format() {
+        cpal::SampleFormat::F32 => build::<f32>(&device, &config.into(), frequency, errors_tx),
+        cpal::SampleFormat::I16 => build::<i16>(&device, &config.into(), frequency, errors_tx),
+        cpal::SampleFormat::U16 => build::<u16>(&device, &config.into(), frequency, errors_tx),
+        other => {
+            return Err(AudioError::Backend(format!(
+                "unsupported sample format {}",
+                other
+            )))
+        }
+    }?;
+    stream
+        .play()
+        .map_err(|err| AudioError::Backend(err.to_string()))?;
+    std::thread::sleep(Duration::from_secs_f32(seconds + 0.1));
+    match errors.try_recv() {
+        Ok(err) => Err(AudioError::Backend(err)),
+        Err(_) => Ok(()),
+    }
+}
+
+fn build<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    frequency: f32,
+    errors: mpsc::Sender<String>,
+) -> Result<cpal::Stream, AudioError> {
+    let channels = usize::from(config.channels);
+    let sample_rate = config.sample_rate.0;
+    let mut tone = ToneGenerator::new(frequency, 0.3, sample_rate);
+    let mut frame = 0u64;
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                for out in data.chunks_mut(channels) {
+                    let t = frame as f32 / sample_rate as f32;
+                    frame += 1;
+                    let value = T::from_sample(tone.next_sample() * speaker_test_envelope(t));
+                    out.fill(value);
+                }
+            },
+            move |err| {
+                let _ = errors.send(err.to_string());
+            },
+            None,
+        )
+        .map_err(|err| AudioError::Backend(err.to_string()))
+}
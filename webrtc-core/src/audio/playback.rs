@@ -0,0 +1,514 @@
+//! Playing a received Opus track through cpal, in stereo: a mono stream
+//! decodes to both sides alike. Lost packets are made up from the RED
+//! copies in those after them, then the next one's in-band FEC, and
+//! otherwise Opus's concealment.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{FromSample, SizedSample};
+use log::info;
+use tokio::sync::{mpsc, oneshot};
+use webrtc::rtp::packet::Packet;
+use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+use webrtc::track::track_remote::TrackRemote;
+
+use super::output::output_device;
+use super::{
+    device_name, downmix, AudioError, AudioLevel, EchoReference, LevelMeter, OutputRoute,
+    OutputStatus, Resampler, OPUS_FRAME, OPUS_SAMPLE_RATE,
+};
+use crate::lipsync::LipSync;
+use crate::recording::{CallMix, RecordingTap};
+use crate::red::{self, MIME_TYPE_RED};
+use crate::snapshot::Snapshot;
+
+/// Audio queued ahead of the speaker to ride out jitter.
+const PLAYOUT_BUFFER: Duration = Duration::from_millis(60);
+
+/// How far past its target the queue may run before audio is skipped.
+const SLACK: Duration = Duration::from_millis(40);
+
+/// The longest Opus frame, 120 ms at 48 kHz.
+const MAX_FRAME: usize = 5760;
+
+/// Opus is always decoded to left and right.
+const CHANNELS: usize = 2;
+
+/// Packets lost in a row past which nothing is made up for them, and
+/// packets behind the last one past which the stream is taken to have
+/// started over.
+const MAX_GAP: u16 = 10;
+
+/// How often the playback thread checks whether it is still wanted, and
+/// on which speaker.
+const POLL: Duration = Duration::from_millis(200);
+
+/// How often a speaker that failed or went away is tried again.
+const RETRY: Duration = Duration::from_secs(2);
+
+/// Left and right samples waiting for the speaker, at the rate of
+/// whichever one is playing them.
+struct Output {
+    samples: VecDeque<[f32; CHANNELS]>,
+    sample_rate: u32,
+}
+
+type Queue = Arc<Mutex<Output>>;
+
+/// An output stream playing the queue, and whether it failed since.
+struct Speaker {
+    stream: cpal::Stream,
+    name: String,
+    failed: Arc<AtomicBool>,
+}
+
+/// Opens `device_id` on the queue, moving what is queued over to its rate
+/// so a switch plays on without a gap.
+fn open_speaker(device_id: Option<&str>, queue: &Queue) -> Result<Speaker, AudioError> {
+    let device = output_device(device_id)?;
+    let name = device
+        .name()
+        .map_err(|err| AudioError::Backend(err.to_string()))?;
+    let config = device
+        .default_output_config()
+        .map_err(|err| AudioError::Backend(err.to_string()))?;
+    let format = config.sample_format();
+    let config = config.config();
+    let failed = Arc::new(AtomicBool::new(false));
+    let output = (Arc::clone(queue), Arc::clone(&failed));
+    let stream = match format {
+        cpal::SampleFormat::F32 => build::<f32>(&device, &config, output),
+        cpal::SampleFormat::I16 => build::<i16>(&device, &config, output),
+        cpal::SampleFormat::U16 => build::<u16>(&device, &config, output),
+        other => Err(AudioError::Backend(format!(
+            "unsupported sample format {}",
+            other
+        ))),
+    }?;
+    {
+        let mut output = queue.lock().unwrap();
+        let sample_rate = config.sample_rate.0;
+        if output.sample_rate != sample_rate {
+            let queued: Vec<f32> = output.samples.drain(..).flatten().collect();
+            let mut resampled = Vec::new();
+            Resampler::with_channels(output.sample_rate, sample_rate, CHANNELS)
+                .push(&queued, &mut resampled);
+            output.samples.extend(frames(&resampled));
+            output.sample_rate = sample_rate;
+        }
+    }
+    stream
+        .play()
+        .map_err(|err| AudioError::Backend(err.to_string()))?;
+    info!(
+        "Playing remote audio on {} at {} Hz",
+        name, config.sample_rate.0
+    );
+    Ok(Speaker {
+        stream,
+        name,
+        failed,
+    })
+}
+
+/// Opens `device_id`, or the default speaker when it cannot be, naming it
+/// as missing then.
+fn open_routed(
+    device_id: Option<&str>,
+    queue: &Queue,
+) -> Result<(Speaker, Option<String>), AudioError> {
+    match (open_speaker(device_id, queue), device_id) {
+        (Ok(speaker), _) => Ok((speaker, None)),
+        (Err(err), Some(device_id)) => {
+            info!("Speaker {} not usable, falling back: {}", device_id, err);
+            let name = device_name(&cpal::default_host(), device_id).to_owned();
+            Ok((open_speaker(None, queue)?, Some(name)))
+        }
+        (Err(err), None) => Err(err),
+    }
+}
+
+/// Keeps the queue playing on the speaker `route` asks for until
+/// `alive_tx` closes: moving when it changes, falling back to the default
+/// one when it fails, and going back to it once it opens again.
+fn follow_route(
+    route: OutputRoute,
+    queue: Queue,
+    alive_tx: mpsc::Sender<()>,
+    ready_tx: oneshot::Sender<Result<(), AudioError>>,
+) {
+    let mut asked = route.wanted();
+    let (mut speaker, mut missing) = match open_routed(asked.as_deref(), &queue) {
+        Ok(opened) => {
+            let _ = ready_tx.send(Ok(()));
+            opened
+        }
+        Err(err) => {
+            let _ = ready_tx.send(Err(err));
+            return;
+        }
+    };
+    let publish = |speaker: &Speaker, missing: &Option<String>| {
+        route.set_status(Some(OutputStatus {
+            device: speaker.name.clone(),
+            missing: missing.clone(),
+        }));
+    };
+    publish(&speaker, &missing);
+    let mut tried = Instant::now();
+    while !alive_tx.is_closed() {
+        std::thread::sleep(POLL);
+        let wanted = route.wanted();
+        let moved = wanted != asked;
+        let failed = speaker.failed.load(Ordering::Relaxed);
+        let due = tried.elapsed() >= RETRY && (failed || missing.is_some());
+        if !moved && !due {
+            continue;
+        }
+        tried = Instant::now();
+        if !moved && !failed {
+            // On the default speaker in place of the one asked for, which
+            // may be back.
+            if let Ok(found) = open_speaker(wanted.as_deref(), &queue) {
+                speaker = found;
+                missing = None;
+                publish(&speaker, &missing);
+            }
+            continue;
+        }
+        asked = wanted;
+        // The new stream starts before the old one is dropped.
+        match open_routed(asked.as_deref(), &queue) {
+            Ok((found, lost)) => {
+                speaker = found;
+                missing = lost;
+                publish(&speaker, &missing);
+            }
+            Err(err) => info!("No speaker to play remote audio on: {}", err),
+        }
+    }
+    drop(speaker.stream);
+    route.set_status(None);
+}
+
+pub async fn play_track(
+    track: Arc<TrackRemote>,
+    route: OutputRoute,
+    sync: Option<Arc<Mutex<LipSync>>>,
+    level: Snapshot<AudioLevel>,
+    echo: EchoReference,
+    recording: RecordingTap,
+    mix: CallMix,
+) -> Result<(), AudioError> {
+    let mut decoder = decoder()?;
+    let mut recovery = Recovery::new(&track);
+    let queue = Arc::new(Mutex::new(Output {
+        samples: VecDeque::new(),
+        sample_rate: OPUS_SAMPLE_RATE,
+    }));
+    let (alive_tx, _alive) = mpsc::channel::<()>(1);
+    let (ready_tx, ready) = oneshot::channel();
+
+    // cpal streams cannot move between threads, so one thread owns them
+    // until this function returns.
+    let output = Arc::clone(&queue);
+    std::thread::Builder::new()
+        .name("speaker".to_owned())
+        .spawn(move || follow_route(route, output, alive_tx, ready_tx))
+        .map_err(|err| AudioError::Backend(err.to_string()))?;
+    ready
+        .await
+        .map_err(|_| AudioError::Backend("speaker thread ended".to_owned()))??;
+
+    let mut sample_rate = OPUS_SAMPLE_RATE;
+    let mut resampler = Resampler::with_channels(OPUS_SAMPLE_RATE, sample_rate, CHANNELS);
+    let mut meter = LevelMeter::new(OPUS_SAMPLE_RATE, level);
+    let mut pcm = Vec::with_capacity(MAX_FRAME * CHANNELS);
+    // What is played, mixed down for all that takes mono.
+    let mut mono = Vec::with_capacity(MAX_FRAME);
+    let mut resampled = Vec::new();
+    while let Ok((packet, _)) = track.read_rtp().await {
+        let Some(opus) = recovery.receive(&mut decoder, &packet, &mut pcm) else {
+            continue;
+        };
+        recording.audio(opus, packet.header.timestamp);
+        if pcm.is_empty() {
+            continue;
+        }
+        mono.clear();
+        downmix(CHANNELS, &pcm, &mut mono);
+        meter.push(&mono);
+        echo.played(&mono);
+        mix.remote(&mono);
+
+        let delay = sync.as_ref().map_or(Duration::ZERO, |sync| {
+            let mut sync = sync.lock().unwrap();
+            let heard = Instant::now() + PLAYOUT_BUFFER;
+            sync.presented(RTPCodecType::Audio, packet.header.timestamp, heard);
+            sync.corrections().audio
+        });
+        let mut output = queue.lock().unwrap();
+        // Another speaker may have taken over at another rate.
+        if output.sample_rate != sample_rate {
+            sample_rate = output.sample_rate;
+            resampler = Resampler::with_channels(OPUS_SAMPLE_RATE, sample_rate, CHANNELS);
+        }
+        resampled.clear();
+        resampler.push(&pcm, &mut resampled);
+        let samples =
+            |duration: Duration| (duration.as_secs_f64() * f64::from(sample_rate)) as usize;
+        let target = samples(PLAYOUT_BUFFER + delay);
+        let queue = &mut output.samples;
+        // Silence makes up a queue that ran short, whether from a late
+        // packet or a correction that grew; a long one skips ahead.
+        let short = target.saturating_sub(queue.len() + resampled.len() / CHANNELS);
+        queue.extend(std::iter::repeat_n([0.0; CHANNELS], short));
+        queue.extend(frames(&resampled));
+        let excess = queue.len().saturating_sub(target + samples(SLACK));
+        queue.drain(..excess);
+    }
+    Ok(())
+}
+
+/// Decodes `track` without playing it, only for its level.
+pub async fn meter_track(
+    track: Arc<TrackRemote>,
+    level: Snapshot<AudioLevel>,
+    recording: RecordingTap,
+) -> Result<(), AudioError> {
+    let mut decoder = decoder()?;
+    let mut recovery = Recovery::new(&track);
+    let mut meter = LevelMeter::new(OPUS_SAMPLE_RATE, level);
+    let mut pcm = Vec::with_capacity(MAX_FRAME * CHANNELS);
+    let mut mono = Vec::with_capacity(MAX_FRAME);
+    while let Ok((packet, _)) = track.read_rtp().await {
+        let Some(opus) = recovery.receive(&mut decoder, &packet, &mut pcm) else {
+            continue;
+        };
+        recording.audio(opus, packet.header.timestamp);
+        mono.clear();
+        downmix(CHANNELS, &pcm, &mut mono);
+        meter.push(&mono);
+    }
+    Ok(())
+}
+
+/// Puts a track's packets back in order as Opus frames, making up those
+/// lost on the way.
+struct Recovery {
+    /// The payload type of RED packets, when the track is RED.
+    red: Option<u8>,
+    last: Option<u16>,
+    /// Samples per channel in the last frame decoded, the size lost ones
+    /// are taken to have been.
+    frame: usize,
+}
+
+impl Recovery {
+    fn new(track: &TrackRemote) -> Self {
+        let codec = track.codec();
+        let red = codec
+            .capability
+            .mime_type
+            .eq_ignore_ascii_case(MIME_TYPE_RED)
+            .then_some(codec.payload_type);
+        Self {
+            red,
+            last: None,
+            frame: OPUS_FRAME,
+        }
+    }
+
+    /// Decodes into `pcm` what was lost just before `packet` and then
+    /// `packet` itself, and returns its Opus frame; `None` for one that
+    /// came too late or broken.
+    fn receive<'a>(
+        &mut self,
+        decoder: &mut opus::Decoder,
+        packet: &'a Packet,
+        pcm: &mut Vec<f32>,
+    ) -> Option<&'a [u8]> {
+        let blocks = match self.red {
+            Some(red) if packet.header.payload_type == red => match red::parse(&packet.payload) {
+                Ok(blocks) => blocks,
+                Err(err) => {
+                    info!("Dropping a RED packet: {}", err);
+                    return None;
+                }
+            },
+            _ => vec![red::Block {
+                payload_type: packet.header.payload_type,
+                timestamp_offset: 0,
+                data: &packet.payload,
+            }],
+        };
+        let primary = blocks.last()?.data;
+        let sequence_number = packet.header.sequence_number;
+        let lost = match self.last {
+            Some(last) => {
+                let ahead = sequence_number.wrapping_sub(last);
+                if ahead == 0 || ahead > u16::MAX - MAX_GAP {
+                    // Repeated or reordered; the frame was made up already.
+                    return None;
+                }
+                ahead - 1
+            }
+            None => 0,
+        };
+        self.last = Some(sequence_number);
+        pcm.clear();
+        if lost <= MAX_GAP {
+            // Oldest first: `back` frames before this packet.
+            for back in (1..=usize::from(lost)).rev() {
+                let copy = blocks.iter().find(|block| {
+                    usize::from(block.timestamp_offset) == back * self.frame
+                        && !block.data.is_empty()
+                });
+                match copy {
+                    Some(block) => Self::decode(decoder, block.data, false, MAX_FRAME, pcm),
+                    None if back == 1 && !primary.is_empty() => {
+                        Self::decode(decoder, primary, true, self.frame, pcm)
+                    }
+                    None => Self::decode(decoder, &[], false, self.frame, pcm),
+                }
+            }
+        }
+        if !primary.is_empty() {
+            let start = pcm.len();
+            Self::decode(decoder, primary, false, MAX_FRAME, pcm);
+            if pcm.len() > start {
+                self.frame = (pcm.len() - start) / CHANNELS;
+            }
+        }
+        Some(primary)
+    }
+
+    /// Appends `input` decoded, at most `frame` samples per channel, or
+    /// exactly that many when concealing or taken from FEC.
+    fn decode(
+        decoder: &mut opus::Decoder,
+        input: &[u8],
+        fec: bool,
+        frame: usize,
+        pcm: &mut Vec<f32>,
+    ) {
+        let start = pcm.len();
+        pcm.resize(start + frame * CHANNELS, 0.0);
+        match decoder.decode_float(input, &mut pcm[start..], fec) {
+            Ok(frames) => pcm.truncate(start + frames * CHANNELS),
+            Err(err) => {
+                info!("Opus decode error: {}", err);
+                pcm.truncate(start);
+            }
+        }
+    }
+}
+
+fn decoder() -> Result<opus::Decoder, AudioError> {
+    opus::Decoder::new(OPUS_SAMPLE_RATE, opus::Channels::Stereo)
+        .map_err(|err| AudioError::Decoder(err.to_string()))
+}
+
+fn frames(interleaved: &[f32]) -> impl Iterator<Item = [f32; CHANNELS]> + '_ {
+    interleaved
+        .chunks_exact(CHANNELS)
+        .map(|frame| [frame[0], frame[1]])
+}
+
+/// An output stream playing `queue`, left and right on the first two
+/// channels and both mixed on any others or on a mono speaker, and silence
+/// when it runs dry, setting `failed` on an error, as when the device is
+/// unplugged.
+fn build<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    (queue, failed): (Queue, Arc<AtomicBool>),
+) -> Result<cpal::Stream, AudioError> {
+    let channels = usize::from(config.channels);
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                let mut output = queue.lock().unwrap();
+                for out in data.chunks_mut(channels) {
+                    let [left, right] = output.samples.pop_front().unwrap_or_default();
+                    out.fill(T::from_sample((left + right) / 2.0));
+                    if let [first, second, ..] = out {
+                        *first = T::from_sample(left);
+                        *second = T::from_sample(right);
+                    }
+                }
+            },
+            move |err| {
+                info!("Speaker stream error: {}", err);
+                failed.store(true, Ordering::Relaxed);
+            },
+            None,
+        )
+        .map_err(|err| AudioError::Backend(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use webrtc::rtp::header::Header;
+
+    use super::*;
+
+    fn packet(sequence_number: u16, payload: Vec<u8>) -> Packet {
+        Packet {
+            header: Header {
+                payload_type: 63,
+                sequence_number,
+                timestamp: u32::from(sequence_number) * OPUS_FRAME as u32,
+                ..Default::default()
+            },
+            payload: Bytes::from(payload),
+        }
+    }
+
+    #[test]
+    fn makes_up_lost_packets_from_red_and_concealment() {
+        let mut encoder = opus::Encoder::new(
+            OPUS_SAMPLE_RATE,
+            opus::Channels::Mono,
+            opus::Application::Voip,
+        )
+        .unwrap();
+        let frames: Vec<Vec<u8>> = (0..6)
+            .map(|_| encoder.encode_vec_float(&[0.1; OPUS_FRAME], 4000).unwrap())
+            .collect();
+        let red = |index: usize| {
+            let previous = (index > 0).then(|| (OPUS_FRAME as u16, &frames[index - 1][..]));
+            packet(index as u16, red::encode(111, &frames[index], previous))
+        };
+        let mut decoder = decoder().unwrap();
+        let mut recovery = Recovery {
+            red: Some(63),
+            last: None,
+            frame: OPUS_FRAME,
+        };
+        let mut pcm = Vec::new();
+        let frame = OPUS_FRAME * CHANNELS;
+
+        let first = red(0);
+        assert_eq!(
+            recovery.receive(&mut decoder, &first, &mut pcm),
+            Some(&frames[0][..])
+        );
+        assert_eq!(pcm.len(), frame);
+        // 1 is lost and comes back out of 2.
+        recovery.receive(&mut decoder, &red(2), &mut pcm).unwrap();
+        assert_eq!(pcm.len(), 2 * frame);
+        // 1 again is too late.
+        assert_eq!(recovery.receive(&mut decoder, &red(1), &mut pcm), None);
+        // Behind a gap of two, one is concealed and one is from RED.
+        recovery.receive(&mut decoder, &red(5), &mut pcm).unwrap();
+        assert_eq!(pcm.len(), 3 * frame);
+        assert_eq!(recovery.last, Some(5));
+    }
+}
@@ -0,0 +1,270 @@
+//! Processing the microphone between capture and the encoder. Echo
+//! cancellation takes what the speaker plays back out of what the
+//! microphone hears, so a call on speakers does not hear itself; noise
+//! suppression then takes out what is not a voice, with RNNoise; and
+//! gain, automatic or fixed, brings what is left to a good loudness.
+
+#[cfg(feature = "echo-cancellation")]
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "audio")]
+use nnnoiseless::DenoiseState;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "echo-cancellation")]
+use webrtc_audio_processing::config::{EchoCanceller, HighPassFilter};
+#[cfg(feature = "echo-cancellation")]
+use webrtc_audio_processing::{Config, Processor};
+
+#[cfg(feature = "audio")]
+use super::{apply_gain, AudioError, AutomaticGain, OPUS_SAMPLE_RATE};
+
+/// What runs on the microphone before it is encoded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessingSettings {
+    /// Cancel the echo of the remote audio off the speaker; only in builds
+    /// with echo cancellation.
+    pub echo_cancellation: bool,
+    /// Take background noise out: fans, traffic, typing.
+    pub noise_suppression: bool,
+    /// Follow the voice's loudness rather than apply `manual_gain_db`.
+    pub automatic_gain: bool,
+    pub manual_gain_db: f32,
+}
+
+impl ProcessingSettings {
+    /// What the manual gain can be set to.
+    pub const MANUAL_GAIN_DB: std::ops::RangeInclusive<f32> = -20.0..=30.0;
+}
+
+impl Default for ProcessingSettings {
+    fn default() -> Self {
+        Self {
+            echo_cancellation: true,
+            noise_suppression: false,
+            automatic_gain: true,
+            manual_gain_db: 0.0,
+        }
+    }
+}
+
+/// RNNoise works on 16-bit samples held in floats.
+#[cfg(feature = "audio")]
+const PCM_SCALE: f32 = i16::MAX as f32;
+
+/// Whether this build can cancel echo.
+pub fn can_cancel_echo() -> bool {
+    cfg!(feature = "echo-cancellation")
+}
+
+/// WebRTC's audio processing at 48 kHz mono, shared by the capture that
+/// cleans the microphone with it and the playback that feeds it what the
+/// speaker plays.
+#[cfg(feature = "echo-cancellation")]
+struct Canceller {
+    processor: Processor,
+    /// Played samples short of a whole 10 ms frame.
+    render: Mutex<Vec<f32>>,
+}
+
+#[cfg(feature = "echo-cancellation")]
+impl Canceller {
+    fn new() -> Result<Self, AudioError> {
+        let processor = Processor::new(OPUS_SAMPLE_RATE)
+            .map_err(|err| AudioError::Processing(err.to_string()))?;
+        processor.set_config(Config {
+            // AEC3, finding the delay from the speaker back to the
+            // microphone on its own.
+            echo_canceller: Some(EchoCanceller::Full {
+                stream_delay_ms: None,
+            }),
+            high_pass_filter: Some(HighPassFilter::default()),
+            ..Default::default()
+        });
+        Ok(Self {
+            processor,
+            render: Mutex::new(Vec::new()),
+        })
+    }
+}
+
+/// Where the audio being played meets the microphone's echo canceller.
+/// One is shared by the capture and the remote playback of a call; while
+/// no capture cancels echo, what is played is dropped.
+#[derive(Clone, Default)]
+pub struct EchoReference {
+    #[cfg(feature = "echo-cancellation")]
+    canceller: Arc<Mutex<Option<Arc<Canceller>>>>,
+}
+
+impl EchoReference {
+    /// Feeds 48 kHz mono audio about to be played, in any length.
+    #[cfg(feature = "audio")]
+    pub(crate) fn played(&self, samples: &[f32]) {
+        #[cfg(feature = "echo-cancellation")]
+        {
+            let Some(canceller) = self.canceller.lock().unwrap().clone() else {
+                return;
+            };
+            let frame = canceller.processor.num_samples_per_frame();
+            let mut render = canceller.render.lock().unwrap();
+            render.extend_from_slice(samples);
+            let whole = render.len() - render.len() % frame;
+            for chunk in render[..whole].chunks_exact(frame) {
+                if let Err(err) = canceller.processor.analyze_render_frame([chunk]) {
+                    log::info!("Echo reference dropped: {}", err);
+                }
+            }
+            render.drain(..whole);
+        }
+        #[cfg(not(feature = "echo-cancellation"))]
+        let _ = samples;
+    }
+}
+
+/// The steps of `ProcessingSettings` for one capture, applied to each
+/// 48 kHz frame on its way to the encoder. All but the manual gain work
+/// in mono, so stereo goes out as captured otherwise.
+#[cfg(feature = "audio")]
+pub(super) struct CaptureChain {
+    #[cfg(feature = "echo-cancellation")]
+    echo: Option<(Arc<Canceller>, EchoReference)>,
+    /// With the frame it was last given, in its scale.
+    denoise: Option<(Box<DenoiseState<'static>>, Vec<f32>)>,
+    gain: Gain,
+}
+
+#[cfg(feature = "audio")]
+enum Gain {
+    Automatic(AutomaticGain),
+    /// In dB; nothing is done at 0.
+    Manual(f32),
+}
+
+#[cfg(feature = "audio")]
+impl CaptureChain {
+    pub fn new(
+        settings: &ProcessingSettings,
+        reference: &EchoReference,
+        channels: usize,
+    ) -> Result<Self, AudioError> {
+        let mono = channels == 1;
+        if !mono
+            && (settings.echo_cancellation || settings.noise_suppression || settings.automatic_gain)
+        {
+            log::info!("Sending stereo without echo cancellation, noise suppression or AGC");
+        }
+        let denoise = (settings.noise_suppression && mono).then(|| {
+            log::info!("Suppressing noise on the microphone");
+            (DenoiseState::new(), vec![0.0; DenoiseState::FRAME_SIZE])
+        });
+        let gain = if settings.automatic_gain && mono {
+            Gain::Automatic(AutomaticGain::new(OPUS_SAMPLE_RATE))
+        } else {
+            let (low, high) = ProcessingSettings::MANUAL_GAIN_DB.into_inner();
+            Gain::Manual(settings.manual_gain_db.clamp(low, high))
+        };
+        #[cfg(feature = "echo-cancellation")]
+        {
+            let echo = if settings.echo_cancellation && mono {
+                let canceller = Arc::new(Canceller::new()?);
+                *reference.canceller.lock().unwrap() = Some(Arc::clone(&canceller));
+                log::info!("Cancelling echo on the microphone");
+                Some((canceller, reference.clone()))
+            } else {
+                None
+            };
+            Ok(Self {
+                echo,
+                denoise,
+                gain,
+            })
+        }
+        #[cfg(not(feature = "echo-cancellation"))]
+        {
+            let _ = reference;
+            Ok(Self { denoise, gain })
+        }
+    }
+
+    /// Processes `frame` in place; it holds whole 10 ms frames, as every
+    /// Opus frame does.
+    pub fn process(&mut self, frame: &mut [f32]) {
+        #[cfg(feature = "echo-cancellation")]
+        if let Some((canceller, _)) = &self.echo {
+            let size = canceller.processor.num_samples_per_frame();
+            for chunk in frame.chunks_exact_mut(size) {
+                if let Err(err) = canceller.processor.process_capture_frame([chunk]) {
+                    log::info!("Echo cancellation skipped a frame: {}", err);
+                }
+            }
+        }
+        // Both work on 10 ms, which RNNoise's frame is too.
+        if let Some((state, input)) = &mut self.denoise {
+            for chunk in frame.chunks_exact_mut(DenoiseState::FRAME_SIZE) {
+                for (scaled, sample) in input.iter_mut().zip(chunk.iter()) {
+                    *scaled = sample * PCM_SCALE;
+                }
+                state.process_frame(chunk, input);
+                for sample in chunk.iter_mut() {
+                    *sample /= PCM_SCALE;
+                }
+            }
+        }
+        match &mut self.gain {
+            Gain::Automatic(gain) => gain.process(frame),
+            Gain::Manual(gain_db) if *gain_db != 0.0 => apply_gain(frame, *gain_db),
+            Gain::Manual(_) => {}
+        }
+    }
+}
+
+#[cfg(feature = "echo-cancellation")]
+impl Drop for CaptureChain {
+    /// Stops feeding the reference into a canceller nobody uses any more,
+    /// unless a newer capture has already taken its place.
+    fn drop(&mut self) {
+        if let Some((canceller, reference)) = self.echo.take() {
+            let mut current = reference.canceller.lock().unwrap();
+            if current
+                .as_ref()
+                .is_some_and(|current| Arc::ptr_eq(current, &canceller))
+            {
+                *current = None;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "audio"))]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn noise_suppression_quietens_noise() {
+        let settings = ProcessingSettings {
+            echo_cancellation: false,
+            noise_suppression: true,
+            automatic_gain: false,
+            manual_gain_db: 0.0,
+        };
+        let mut chain = CaptureChain::new(&settings, &EchoReference::default(), 1).unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        let energy = |frame: &[f32]| frame.iter().map(|s| s * s).sum::<f32>();
+        let (mut before, mut after) = (0.0, 0.0);
+        for round in 0..200 {
+            let mut frame: Vec<f32> = (0..960).map(|_| rng.gen_range(-0.05..0.05)).collect();
+            let heard = energy(&frame);
+            chain.process(&mut frame);
+            // Once it has had a second to learn the noise.
+            if round >= 50 {
+                before += heard;
+                after += energy(&frame);
+            }
+        }
+        assert!(after < before / 2.0, "{} of {} left", after, before);
+    }
+}
@@ -0,0 +1,144 @@
+//! Spending bitrate on redundancy only while the link loses audio: Opus's
+//! in-band FEC and RED copies of the packet before go on once the peer
+//! reports more loss than the threshold, and off again once it is down to
+//! half of that.
+
+use std::sync::Arc;
+
+use tokio::task::JoinHandle;
+use webrtc::rtcp::receiver_report::ReceiverReport;
+use webrtc::rtcp::reception_report::ReceptionReport;
+use webrtc::rtcp::sender_report::SenderReport;
+use webrtc::rtp_transceiver::rtp_sender::RTCRtpSender;
+
+use super::OpusSettings;
+use crate::snapshot::Snapshot;
+
+/// Which of the settings' protection is on, given the loss reported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Protection {
+    fec: bool,
+    red: bool,
+    /// As a fraction of packets.
+    threshold: Option<f32>,
+    expected_percent: u8,
+    engaged: bool,
+    /// The last loss reported, in percent.
+    loss_percent: Option<u8>,
+}
+
+impl Protection {
+    pub fn new(settings: &OpusSettings) -> Self {
+        let threshold = settings
+            .loss_threshold_percent
+            .map(|percent| f32::from(percent.min(100)) / 100.0);
+        Self {
+            fec: settings.fec,
+            red: settings.red,
+            threshold,
+            expected_percent: settings.expected_loss_percent.min(100),
+            engaged: threshold.is_none(),
+            loss_percent: None,
+        }
+    }
+
+    /// Takes in the fraction of packets last reported lost, and returns
+    /// whether that changes how the encoder should be set.
+    pub fn update(&mut self, loss: Option<f32>) -> bool {
+        let Some(loss) = loss else {
+            return false;
+        };
+        let before = (self.engaged, self.loss_percent);
+        if let Some(threshold) = self.threshold {
+            if loss > threshold {
+                self.engaged = true;
+            } else if loss <= threshold / 2.0 {
+                self.engaged = false;
+            }
+        }
+        self.loss_percent = Some((loss * 100.0).round().clamp(0.0, 100.0) as u8);
+        (self.engaged, self.loss_percent) != before
+    }
+
+    /// Whether the encoder adds in-band FEC.
+    pub fn fec(&self) -> bool {
+        self.fec && self.engaged
+    }
+
+    /// Whether packets also carry the one before, where they go as RED.
+    pub fn redundant(&self) -> bool {
+        self.red && self.engaged
+    }
+
+    /// The loss for the encoder to aim its FEC at: what the peer reported,
+    /// or what the settings expect until it has.
+    pub fn loss_percent(&self) -> u8 {
+        self.loss_percent.unwrap_or(self.expected_percent).max(1)
+    }
+}
+
+/// Publishes into `loss` the fraction of packets the peer reports losing
+/// from `sender`, until the sender stops.
+pub fn watch_loss(sender: Arc<RTCRtpSender>, loss: Snapshot<Option<f32>>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let ssrc = sender
+            .get_parameters()
+            .await
+            .encodings
+            .first()
+            .map(|encoding| encoding.ssrc);
+        while let Ok((packets, _)) = sender.read_rtcp().await {
+            for packet in &packets {
+                let any = packet.as_any();
+                let reports: &[ReceptionReport] =
+                    if let Some(report) = any.downcast_ref::<ReceiverReport>() {
+                        &report.reports
+                    } else if let Some(report) = any.downcast_ref::<SenderReport>() {
+                        &report.reports
+                    } else {
+                        continue;
+                    };
+                for report in reports
+                    .iter()
+                    .filter(|report| ssrc.is_none_or(|ssrc| report.ssrc == ssrc))
+                {
+                    loss.set(Some(f32::from(report.fraction_lost) / 256.0));
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn engages_past_the_threshold_and_lets_go_at_half() {
+        let settings = OpusSettings {
+            red: true,
+            loss_threshold_percent: Some(4),
+            ..Default::default()
+        };
+        let mut protection = Protection::new(&settings);
+        assert!(!protection.fec() && !protection.redundant());
+        assert_eq!(protection.loss_percent(), 10);
+        assert!(!protection.update(None));
+
+        assert!(protection.update(Some(0.05)));
+        assert!(protection.fec() && protection.redundant());
+        assert_eq!(protection.loss_percent(), 5);
+        // Between half and the threshold it holds.
+        protection.update(Some(0.03));
+        assert!(protection.fec());
+        protection.update(Some(0.02));
+        assert!(!protection.fec() && !protection.redundant());
+        assert!(!protection.update(Some(0.02)));
+
+        let always = Protection::new(&OpusSettings {
+            loss_threshold_percent: None,
+            ..Default::default()
+        });
+        assert!(always.fec() && !always.redundant());
+    }
+}
@@ -0,0 +1,53 @@
+//! Which speaker the remote audio of a call plays on. The playback reads
+//! it as it goes, so the call moves to another speaker without stopping,
+//! and falls back to the default one when its own goes away.
+
+use crate::snapshot::Snapshot;
+
+/// The speaker remote audio is playing on, and the one it was asked for
+/// when that could not be used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputStatus {
+    pub device: String,
+    /// Unplugged or failing; playback moves back once it opens again.
+    pub missing: Option<String>,
+}
+
+/// Shared by whoever picks the speaker and the playback that follows it.
+#[derive(Clone, Default)]
+pub struct OutputRoute {
+    /// A cpal id as listed by `devices::enumerate`; `None` for the default.
+    wanted: Snapshot<Option<String>>,
+    status: Snapshot<Option<OutputStatus>>,
+}
+
+impl OutputRoute {
+    pub fn new(device_id: Option<String>) -> Self {
+        Self {
+            wanted: Snapshot::new(device_id),
+            status: Snapshot::default(),
+        }
+    }
+
+    /// Moves playback to `device_id`; cheap when it is already there.
+    pub fn route_to(&self, device_id: Option<&str>) {
+        if self.wanted.get().as_deref() != device_id {
+            self.wanted.set(device_id.map(str::to_owned));
+        }
+    }
+
+    /// What is playing now, `None` while nothing is.
+    pub fn status(&self) -> Option<OutputStatus> {
+        (*self.status.get()).clone()
+    }
+
+    #[cfg(feature = "audio")]
+    pub(crate) fn wanted(&self) -> Option<String> {
+        (*self.wanted.get()).clone()
+    }
+
+    #[cfg(feature = "audio")]
+    pub(crate) fn set_status(&self, status: Option<OutputStatus>) {
+        self.status.set(status);
+    }
+}
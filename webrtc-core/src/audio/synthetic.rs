@@ -0,0 +1,104 @@
+//! Test signals offered next to the real microphones, for trying the send
+//! path and the far end's playback on machines without one.
+
+#[cfg(feature = "audio")]
+use std::time::Duration;
+
+#[cfg(feature = "audio")]
+use rand::Rng;
+#[cfg(feature = "audio")]
+use tokio::sync::mpsc;
+#[cfg(feature = "audio")]
+use tokio::task::JoinHandle;
+
+#[cfg(feature = "audio")]
+use super::{mix_channels, ChannelMap, ToneGenerator, OPUS_FRAME, OPUS_SAMPLE_RATE};
+
+#[cfg(feature = "audio")]
+const TONE_AMPLITUDE: f32 = 0.3;
+/// Noise is louder than a tone at the same peak, so it peaks lower.
+#[cfg(feature = "audio")]
+const NOISE_AMPLITUDE: f32 = 0.15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestSignal {
+    /// A sine at the frequency in the media settings.
+    Tone,
+    WhiteNoise,
+}
+
+impl TestSignal {
+    pub const ALL: [TestSignal; 2] = [TestSignal::Tone, TestSignal::WhiteNoise];
+
+    /// The id it is listed under among the microphones.
+    pub fn device_id(self) -> &'static str {
+        match self {
+            TestSignal::Tone => "test-signal:tone",
+            TestSignal::WhiteNoise => "test-signal:noise",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TestSignal::Tone => "Test tone",
+            TestSignal::WhiteNoise => "White noise",
+        }
+    }
+
+    pub fn from_device_id(device_id: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|signal| signal.device_id() == device_id)
+    }
+}
+
+/// Queues 20 ms of `signal` at 48 kHz into `chunks` every 20 ms, the way a
+/// microphone callback would, until the receiving side goes away. It is
+/// the same on each of `channels`.
+#[cfg(feature = "audio")]
+pub(super) fn spawn(
+    signal: TestSignal,
+    tone_hz: f32,
+    channels: usize,
+    chunks: mpsc::Sender<Vec<f32>>,
+) -> JoinHandle<()> {
+    let weights = ChannelMap::default().weights(1, channels);
+    let period = Duration::from_secs_f64(OPUS_FRAME as f64 / OPUS_SAMPLE_RATE as f64);
+    let mut tone = ToneGenerator::new(tone_hz, TONE_AMPLITUDE, OPUS_SAMPLE_RATE);
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(period);
+        loop {
+            ticks.tick().await;
+            let mono: Vec<f32> = match signal {
+                TestSignal::Tone => (0..OPUS_FRAME).map(|_| tone.next_sample()).collect(),
+                TestSignal::WhiteNoise => noise(OPUS_FRAME),
+            };
+            let mut chunk = Vec::with_capacity(OPUS_FRAME * channels);
+            mix_channels(&weights, &mono, &mut chunk);
+            if chunks.send(chunk).await.is_err() {
+                return;
+            }
+        }
+    })
+}
+
+#[cfg(feature = "audio")]
+fn noise(len: usize) -> Vec<f32> {
+    let mut rng = rand::thread_rng();
+    (0..len)
+        .map(|_| rng.gen_range(-NOISE_AMPLITUDE..=NOISE_AMPLITUDE))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_ids_name_one_signal_each() {
+        for signal in TestSignal::ALL {
+            assert_eq!(TestSignal::from_device_id(signal.device_id()), Some(signal));
+        }
+        assert_eq!(TestSignal::from_device_id("ALSA:default"), None);
+    }
+}
@@ -0,0 +1,190 @@
+//! The microphone's track: Opus packets written as they are encoded, as
+//! RED (RFC 2198) carrying the frame before when the peer takes it.
+//! webrtc-rs has no payloader for RED and overwrites the payload type of
+//! what goes through its RTP tracks, so this one binds itself.
+
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use webrtc::api::media_engine::MIME_TYPE_OPUS;
+use webrtc::rtp::header::Header;
+use webrtc::rtp::packet::Packet;
+use webrtc::rtp_transceiver::rtp_codec::{RTCRtpCodecParameters, RTPCodecType};
+use webrtc::track::track_local::{TrackLocal, TrackLocalContext, TrackLocalWriter};
+
+use crate::red::{self, MIME_TYPE_RED};
+use crate::snapshot::Snapshot;
+
+/// One sender the track is bound to.
+struct Binding {
+    id: String,
+    ssrc: u32,
+    /// The payload type RED packets go out with, when it was negotiated.
+    red: Option<u8>,
+    opus: u8,
+    mime_type: String,
+    writer: Arc<dyn TrackLocalWriter + Send + Sync>,
+    sequence_number: u16,
+    timestamp: u32,
+    started: bool,
+}
+
+#[derive(Default)]
+struct Bindings {
+    bindings: Vec<Binding>,
+    /// The last frame written and how many samples it held, which the
+    /// next packet repeats when redundant.
+    previous: Option<(Bytes, u32)>,
+}
+
+/// An audio track taking Opus frames from `spawn_microphone`.
+pub struct MicrophoneTrack {
+    id: String,
+    stream_id: String,
+    bindings: Mutex<Bindings>,
+    loss: Snapshot<Option<f32>>,
+}
+
+impl MicrophoneTrack {
+    pub fn new(id: String, stream_id: String) -> Self {
+        Self {
+            id,
+            stream_id,
+            bindings: Mutex::default(),
+            loss: Snapshot::default(),
+        }
+    }
+
+    /// The fraction of packets the peer last reported losing, for
+    /// `watch_loss` to fill in and the encoder to follow.
+    pub fn loss(&self) -> Snapshot<Option<f32>> {
+        self.loss.clone()
+    }
+
+    /// The codec the peer is sent, once bound: Opus, or RED around it.
+    pub fn mime_type(&self) -> Option<String> {
+        let bindings = self.bindings.lock().unwrap();
+        bindings
+            .bindings
+            .first()
+            .map(|binding| binding.mime_type.clone())
+    }
+
+    /// Sends `opus`, a frame of `samples` per channel, to every sender
+    /// bound, with the frame before it too when `redundant` and the
+    /// sender takes RED.
+    pub async fn write_frame(
+        &self,
+        opus: &[u8],
+        samples: u32,
+        redundant: bool,
+    ) -> Result<(), webrtc::Error> {
+        let packets: Vec<_> = {
+            let mut bindings = self.bindings.lock().unwrap();
+            let previous = bindings
+                .previous
+                .replace((Bytes::copy_from_slice(opus), samples));
+            let previous = previous
+                .as_ref()
+                .filter(|_| redundant)
+                .and_then(|(data, samples)| Some((u16::try_from(*samples).ok()?, &data[..])));
+            bindings
+                .bindings
+                .iter_mut()
+                .map(|binding| {
+                    let (payload_type, payload) = match binding.red {
+                        Some(red) => (red, red::encode(binding.opus, opus, previous).into()),
+                        None => (binding.opus, Bytes::copy_from_slice(opus)),
+                    };
+                    let packet = Packet {
+                        header: Header {
+                            version: 2,
+                            marker: !binding.started,
+                            payload_type,
+                            sequence_number: binding.sequence_number,
+                            timestamp: binding.timestamp,
+                            ssrc: binding.ssrc,
+                            ..Default::default()
+                        },
+                        payload,
+                    };
+                    binding.started = true;
+                    binding.sequence_number = binding.sequence_number.wrapping_add(1);
+                    binding.timestamp = binding.timestamp.wrapping_add(samples);
+                    (Arc::clone(&binding.writer), packet)
+                })
+                .collect()
+        };
+        for (writer, packet) in packets {
+            writer.write_rtp(&packet).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TrackLocal for MicrophoneTrack {
+    async fn bind(&self, t: &TrackLocalContext) -> Result<RTCRtpCodecParameters, webrtc::Error> {
+        let codecs = t.codec_parameters();
+        let is = |codec: &&RTCRtpCodecParameters, mime_type: &str| {
+            codec.capability.mime_type.eq_ignore_ascii_case(mime_type)
+        };
+        let opus = codecs
+            .iter()
+            .find(|codec| is(codec, MIME_TYPE_OPUS))
+            .ok_or(webrtc::Error::ErrUnsupportedCodec)?;
+        // RED that repeats this Opus and nothing else, "111/111".
+        let red = codecs.iter().find(|codec| {
+            is(codec, MIME_TYPE_RED)
+                && codec
+                    .capability
+                    .sdp_fmtp_line
+                    .split('/')
+                    .all(|part| part.trim().parse() == Ok(opus.payload_type))
+        });
+        let writer = t
+            .write_stream()
+            .ok_or_else(|| webrtc::Error::new("the sender has no stream to write to".to_owned()))?;
+        let codec = red.unwrap_or(opus).clone();
+        self.bindings.lock().unwrap().bindings.push(Binding {
+            id: t.id(),
+            ssrc: t.ssrc(),
+            red: red.map(|red| red.payload_type),
+            opus: opus.payload_type,
+            mime_type: codec.capability.mime_type.clone(),
+            writer,
+            sequence_number: rand::random(),
+            timestamp: rand::random(),
+            started: false,
+        });
+        Ok(codec)
+    }
+
+    async fn unbind(&self, t: &TrackLocalContext) -> Result<(), webrtc::Error> {
+        let mut bindings = self.bindings.lock().unwrap();
+        let before = bindings.bindings.len();
+        bindings.bindings.retain(|binding| binding.id != t.id());
+        if bindings.bindings.len() == before {
+            return Err(webrtc::Error::ErrUnbindFailed);
+        }
+        Ok(())
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn stream_id(&self) -> &str {
+        &self.stream_id
+    }
+
+    fn kind(&self) -> RTPCodecType {
+        RTPCodecType::Audio
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
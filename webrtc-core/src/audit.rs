@@ -0,0 +1,115 @@
+//! Security audit log: an append-only JSON Lines file of access decisions,
+//! with the most recent entries kept in memory for display.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::bounded::{BoundedBuffer, DropPolicy, Footprint};
+
+const MAX_RECENT: usize = 200;
+const MAX_RECENT_BYTES: usize = 256 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// An incoming call was turned away by the access list.
+    CallRejected,
+    /// The user agreed to stream their log to the peer.
+    LogsShared,
+    /// The user answered a mid-call offer they were asked about.
+    RenegotiationAccepted,
+    RenegotiationDeclined,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// RFC 3339, UTC.
+    pub at: String,
+    pub event: AuditEvent,
+    /// Where the call came from: room, nostr, sip.
+    pub source: String,
+    pub peer: String,
+    pub detail: String,
+}
+
+impl Footprint for AuditEntry {
+    fn footprint(&self) -> usize {
+        self.at.len() + self.source.len() + self.peer.len() + self.detail.len()
+    }
+}
+
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    recent: BoundedBuffer<AuditEntry>,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self::open(None)
+    }
+}
+
+impl AuditLog {
+    /// Opens the log at `path`, loading its latest entries. Without a path
+    /// entries are only kept in memory.
+    pub fn open(path: Option<PathBuf>) -> Self {
+        let mut recent = BoundedBuffer::new(MAX_RECENT, MAX_RECENT_BYTES, DropPolicy::DropOldest);
+        if let Some(file) = path.as_ref().and_then(|path| File::open(path).ok()) {
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                if let Ok(entry) = serde_json::from_str(&line) {
+                    recent.push(entry);
+                }
+            }
+        }
+        Self { path, recent }
+    }
+
+    pub fn record(
+        &mut self,
+        event: AuditEvent,
+        source: &str,
+        peer: &str,
+        detail: impl Into<String>,
+    ) {
+        let entry = AuditEntry {
+            at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            event,
+            source: source.to_owned(),
+            peer: peer.to_owned(),
+            detail: detail.into(),
+        };
+        if let Some(path) = &self.path {
+            let written = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| {
+                    let line = serde_json::to_string(&entry).expect("audit entries serialize");
+                    writeln!(file, "{}", line)
+                });
+            if let Err(err) = written {
+                warn!("Failed to write audit log {}: {}", path.display(), err);
+            }
+        }
+        self.recent.push(entry);
+    }
+
+    /// Oldest first.
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &AuditEntry> {
+        self.recent.iter()
+    }
+
+    /// Older entries no longer held in memory.
+    pub fn hidden(&self) -> usize {
+        self.recent.dropped()
+    }
+
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+}
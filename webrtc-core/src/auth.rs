@@ -0,0 +1,254 @@
+//! Logging in to secured signaling servers: OAuth 2 password and refresh
+//! grants against a token endpoint, yielding a bearer token (usually a JWT)
+//! to send with signaling requests.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+
+/// Refresh this long before the token expires, or halfway through its
+/// lifetime if that is shorter.
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("login refused ({status}): {message}")]
+    Refused {
+        status: reqwest::StatusCode,
+        message: String,
+    },
+    #[error("the session cannot be renewed, log in again")]
+    NoRefreshToken,
+}
+
+impl AuthError {
+    /// Whether trying again later may work: the server was unreachable or
+    /// failed, rather than turning us down.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            AuthError::Http(_) => true,
+            AuthError::Refused { status, .. } => status.is_server_error(),
+            AuthError::NoRefreshToken => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthSettings {
+    /// OAuth 2 token endpoint; login is off while it is empty.
+    pub token_url: String,
+    pub client_id: String,
+    /// Remembered for the login dialog; the password never is.
+    pub username: String,
+}
+
+impl AuthSettings {
+    pub fn enabled(&self) -> bool {
+        !self.token_url.trim().is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub issued_at: SystemTime,
+    /// From `expires_in`, or the JWT's `exp` claim; `None` if neither says.
+    pub expires_at: Option<SystemTime>,
+}
+
+impl Token {
+    /// When to refresh, if the token expires at all.
+    pub fn refresh_at(&self) -> Option<SystemTime> {
+        let expires_at = self.expires_at?;
+        let lifetime = expires_at
+            .duration_since(self.issued_at)
+            .unwrap_or_default();
+        Some(expires_at - REFRESH_MARGIN.min(lifetime / 2))
+    }
+
+    pub fn needs_refresh(&self, now: SystemTime) -> bool {
+        self.refresh_at().is_some_and(|at| now >= at)
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    exp: Option<u64>,
+}
+
+/// The `exp` claim of a JWT. The signature is not checked; that is the
+/// server's business, we only need to know when to refresh.
+pub fn jwt_expiry(token: &str) -> Option<SystemTime> {
+    let payload = token.split('.').nth(1)?;
+    let claims: Claims = serde_json::from_slice(&BASE64_URL.decode(payload).ok()?).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(claims.exp?))
+}
+
+async fn request_token(
+    settings: &AuthSettings,
+    form: &[(&str, &str)],
+) -> Result<TokenResponse, AuthError> {
+    let mut form = form.to_vec();
+    if !settings.client_id.trim().is_empty() {
+        form.push(("client_id", settings.client_id.trim()));
+    }
+    let response = reqwest::Client::new()
+        .post(settings.token_url.trim())
+        .form(&form)
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        let message = match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(error) => error.error_description.unwrap_or(error.error),
+            Err(_) => body,
+        };
+        return Err(AuthError::Refused { status, message });
+    }
+    Ok(response.json().await?)
+}
+
+fn token_from(response: TokenResponse, previous_refresh: Option<String>) -> Token {
+    let now = SystemTime::now();
+    let expires_at = response
+        .expires_in
+        .map(|secs| now + Duration::from_secs(secs))
+        .or_else(|| jwt_expiry(&response.access_token));
+    Token {
+        access_token: response.access_token,
+        // Servers that do not rotate refresh tokens leave it out.
+        refresh_token: response.refresh_token.or(previous_refresh),
+        issued_at: now,
+        expires_at,
+    }
+}
+
+pub async fn login(
+    settings: &AuthSettings,
+    username: &str,
+    password: &str,
+) -> Result<Token, AuthError> {
+    let response = request_token(
+        settings,
+        &[
+            ("grant_type", "password"),
+            ("username", username),
+            ("password", password),
+        ],
+    )
+    .await?;
+    Ok(token_from(response, None))
+}
+
+pub async fn refresh(settings: &AuthSettings, token: &Token) -> Result<Token, AuthError> {
+    let refresh_token = token
+        .refresh_token
+        .as_deref()
+        .ok_or(AuthError::NoRefreshToken)?;
+    let response = request_token(
+        settings,
+        &[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ],
+    )
+    .await?;
+    Ok(token_from(response, token.refresh_token.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jwt(payload: &str) -> String {
+        format!("eyJhbGciOiJIUzI1NiJ9.{}.c2lnbmF0dXJl", payload)
+    }
+
+    fn token(lifetime: Duration) -> Token {
+        let issued_at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        Token {
+            access_token: String::new(),
+            refresh_token: None,
+            issued_at,
+            expires_at: Some(issued_at + lifetime),
+        }
+    }
+
+    #[test]
+    fn reads_the_expiry_of_unpadded_payloads() {
+        // 29 bytes, which standard base64 would pad with one `=`.
+        let payload = BASE64_URL.encode(r#"{"sub":"ab","exp":1700000000}"#);
+        assert_eq!(payload.len() % 4, 3);
+        assert_eq!(
+            jwt_expiry(&jwt(&payload)),
+            Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_tokens() {
+        let no_exp = BASE64_URL.encode(r#"{"sub":"ab"}"#);
+        let not_json = BASE64_URL.encode("not json");
+        let padded = format!(
+            "{}==",
+            BASE64_URL.encode(r#"{"sub":"ab","exp":1700000000}"#)
+        );
+        for token in [
+            "".to_owned(),
+            "opaque-access-token".to_owned(),
+            jwt("not*base64"),
+            jwt(&not_json),
+            jwt(&no_exp),
+            jwt(&padded),
+            jwt(&BASE64_URL.encode(r#"{"exp":"soon"}"#)),
+        ] {
+            assert_eq!(jwt_expiry(&token), None, "{}", token);
+        }
+    }
+
+    #[test]
+    fn refreshes_a_minute_early_or_halfway() {
+        let long = token(Duration::from_secs(3600));
+        assert_eq!(
+            long.refresh_at(),
+            Some(long.issued_at + Duration::from_secs(3540))
+        );
+        let short = token(Duration::from_secs(30));
+        assert_eq!(
+            short.refresh_at(),
+            Some(short.issued_at + Duration::from_secs(15))
+        );
+        assert!(!short.needs_refresh(short.issued_at + Duration::from_secs(14)));
+        assert!(short.needs_refresh(short.issued_at + Duration::from_secs(15)));
+
+        let unknown = Token {
+            expires_at: None,
+            ..short
+        };
+        assert_eq!(unknown.refresh_at(), None);
+        assert!(!unknown.needs_refresh(SystemTime::now()));
+    }
+}
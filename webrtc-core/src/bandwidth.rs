@@ -0,0 +1,378 @@
+//! Adapting outgoing video to the link. The feedback the remote sends for
+//! a sender (transport-wide congestion control, receiver reports and REMB)
+//! drives a bitrate estimate in the manner of GCC's loss-based controller:
+//! up while almost nothing is lost, down in proportion to heavy loss or a
+//! round trip swelling with queued packets, and never above what the
+//! remote says it can take. Past a point a lower bitrate alone looks worse
+//! than a smaller picture, so the target also halves the size and then
+//! the frame rate, with some hysteresis so it does not flap.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tokio::task::JoinHandle;
+use webrtc::rtcp::packet::Packet;
+use webrtc::rtcp::payload_feedbacks::receiver_estimated_maximum_bitrate::ReceiverEstimatedMaximumBitrate;
+use webrtc::rtcp::receiver_report::ReceiverReport;
+use webrtc::rtcp::reception_report::ReceptionReport;
+use webrtc::rtcp::sender_report::SenderReport;
+use webrtc::rtcp::transport_feedbacks::transport_layer_cc::{
+    PacketStatusChunk, SymbolTypeTcc, TransportLayerCc,
+};
+use webrtc::rtp_transceiver::rtp_sender::RTCRtpSender;
+
+use crate::snapshot::Snapshot;
+use crate::video::{self, VideoEncoder, VideoFrame};
+
+/// Below this the picture is not worth sending smaller still.
+pub const MIN_BITRATE_BPS: u32 = 50_000;
+
+/// How often the estimate moves, given feedback to move it on.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+/// Up to this much loss the link has room to spare.
+const LOW_LOSS: f64 = 0.02;
+/// Past this much loss the bitrate comes down.
+const HIGH_LOSS: f64 = 0.10;
+/// The climb each update without loss, about 10% a second.
+const INCREASE: f64 = 1.05;
+/// A round trip this much over the shortest seen means packets queue.
+const QUEUING_DELAY: Duration = Duration::from_millis(150);
+const QUEUING_DECREASE: f64 = 0.85;
+/// Seconds from 1900, where NTP time starts, to 1970.
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// The bitrate for the link, from the feedback on one sender.
+#[derive(Debug, Clone)]
+pub struct BandwidthEstimator {
+    estimate_bps: f64,
+    max_bps: u32,
+    /// The most the remote says it can take.
+    remb_bps: Option<u32>,
+    /// Packets reported received and lost since the last update.
+    received: u32,
+    lost: u32,
+    /// Transport-wide feedback counts every packet, so once any arrives
+    /// receiver reports only give round trips.
+    transport_cc: bool,
+    /// The highest sequence number the last receiver report had.
+    highest_sequence: Option<u32>,
+    rtt: Option<Duration>,
+    min_rtt: Option<Duration>,
+    last_update: Instant,
+}
+
+impl BandwidthEstimator {
+    pub fn new(now: Instant, start_bps: u32, max_bps: u32) -> Self {
+        let max_bps = max_bps.max(MIN_BITRATE_BPS);
+        Self {
+            estimate_bps: f64::from(start_bps.clamp(MIN_BITRATE_BPS, max_bps)),
+            max_bps,
+            remb_bps: None,
+            received: 0,
+            lost: 0,
+            transport_cc: false,
+            highest_sequence: None,
+            rtt: None,
+            min_rtt: None,
+            last_update: now,
+        }
+    }
+
+    pub fn estimate_bps(&self) -> u32 {
+        let estimate = self.estimate_bps.round() as u32;
+        self.remb_bps.map_or(estimate, |remb| estimate.min(remb))
+    }
+
+    /// Takes in whatever `packet` says about the stream from `ssrc`.
+    pub fn on_rtcp(&mut self, packet: &(dyn Packet + Send + Sync), ssrc: Option<u32>) {
+        let any = packet.as_any();
+        if let Some(feedback) = any.downcast_ref::<TransportLayerCc>() {
+            self.transport_cc = true;
+            let (received, lost) = transport_cc_counts(feedback);
+            self.on_packets(received, lost);
+        } else if let Some(report) = any.downcast_ref::<ReceiverReport>() {
+            self.on_reception_reports(&report.reports, ssrc);
+        } else if let Some(report) = any.downcast_ref::<SenderReport>() {
+            self.on_reception_reports(&report.reports, ssrc);
+        } else if let Some(remb) = any.downcast_ref::<ReceiverEstimatedMaximumBitrate>() {
+            self.remb_bps = Some((remb.bitrate as u32).max(MIN_BITRATE_BPS));
+        }
+    }
+
+    fn on_packets(&mut self, received: u32, lost: u32) {
+        self.received += received;
+        self.lost += lost;
+    }
+
+    fn on_reception_reports(&mut self, reports: &[ReceptionReport], ssrc: Option<u32>) {
+        for report in reports
+            .iter()
+            .filter(|report| ssrc.is_none_or(|ssrc| report.ssrc == ssrc))
+        {
+            if let Some(rtt) = round_trip(report) {
+                self.rtt = Some(rtt);
+                self.min_rtt = Some(self.min_rtt.map_or(rtt, |min| min.min(rtt)));
+            }
+            let highest = report.last_sequence_number;
+            let expected = self
+                .highest_sequence
+                .map_or(0, |previous| highest.saturating_sub(previous));
+            self.highest_sequence = Some(highest);
+            if !self.transport_cc && expected > 0 {
+                let lost = expected * u32::from(report.fraction_lost) / 256;
+                self.on_packets(expected - lost, lost);
+            }
+        }
+    }
+
+    /// Moves the estimate on the feedback since the last update, once an
+    /// update is due, and returns it.
+    pub fn update(&mut self, now: Instant) -> Option<u32> {
+        if now.saturating_duration_since(self.last_update) < UPDATE_INTERVAL {
+            return None;
+        }
+        self.last_update = now;
+        let total = self.received + self.lost;
+        let loss = if total == 0 {
+            None
+        } else {
+            Some(f64::from(self.lost) / f64::from(total))
+        };
+        (self.received, self.lost) = (0, 0);
+        let queuing = match (self.rtt, self.min_rtt) {
+            (Some(rtt), Some(min)) => rtt > min + QUEUING_DELAY,
+            _ => false,
+        };
+        match loss {
+            _ if queuing => self.estimate_bps *= QUEUING_DECREASE,
+            Some(loss) if loss > HIGH_LOSS => self.estimate_bps *= 1.0 - 0.5 * loss,
+            Some(loss) if loss < LOW_LOSS => self.estimate_bps *= INCREASE,
+            // Some loss, or no word at all: hold.
+            _ => {}
+        }
+        // Climbing past what the remote takes only makes the way back
+        // down longer.
+        let ceiling = self
+            .remb_bps
+            .map_or(self.max_bps, |remb| remb.min(self.max_bps));
+        self.estimate_bps = self.estimate_bps.clamp(
+            f64::from(MIN_BITRATE_BPS),
+            f64::from(ceiling.max(MIN_BITRATE_BPS)),
+        );
+        Some(self.estimate_bps())
+    }
+}
+
+/// How many of the packets a transport-wide feedback covers arrived, and
+/// how many did not.
+fn transport_cc_counts(feedback: &TransportLayerCc) -> (u32, u32) {
+    let (mut received, mut lost) = (0, 0);
+    let mut remaining = u32::from(feedback.packet_status_count);
+    let mut count = |symbol: SymbolTypeTcc, run: u32| {
+        let run = run.min(remaining);
+        remaining -= run;
+        if symbol == SymbolTypeTcc::PacketNotReceived {
+            lost += run;
+        } else {
+            received += run;
+        }
+    };
+    for chunk in &feedback.packet_chunks {
+        match chunk {
+            PacketStatusChunk::RunLengthChunk(chunk) => {
+                count(chunk.packet_status_symbol, u32::from(chunk.run_length))
+            }
+            PacketStatusChunk::StatusVectorChunk(chunk) => {
+                for &symbol in &chunk.symbol_list {
+                    count(symbol, 1);
+                }
+            }
+        }
+    }
+    (received, lost)
+}
+
+/// The round trip a reception report gives: now, less when the sender
+/// report it answers went out, less how long the remote held it.
+fn round_trip(report: &ReceptionReport) -> Option<Duration> {
+    if report.last_sender_report == 0 {
+        return None;
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+    // The middle 32 bits of NTP time, in 1/65536 s as the report has it.
+    let seconds = (now.as_secs() + NTP_UNIX_OFFSET) as u32;
+    let fraction = ((u64::from(now.subsec_nanos()) << 16) / 1_000_000_000) as u32;
+    let now = (seconds << 16) | fraction;
+    let rtt = now
+        .wrapping_sub(report.last_sender_report)
+        .wrapping_sub(report.delay);
+    // A clock step or a stale report comes out as a huge round trip.
+    (rtt < 10 << 16).then(|| Duration::from_secs_f64(f64::from(rtt) / 65536.0))
+}
+
+/// What a video sender is held to for the link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendTarget {
+    pub bitrate_bps: u32,
+    /// The picture's width and height are divided by this.
+    pub scale_down: usize,
+    /// Only every this many frames is sent.
+    pub frame_rate_divisor: u32,
+}
+
+impl SendTarget {
+    /// The target for `bitrate_bps` out of the `max_bps` a full-size,
+    /// full-rate picture would get, changing `previous`'s size and frame
+    /// rate only once the bitrate is well past where it changed them.
+    pub fn follow(previous: Option<SendTarget>, bitrate_bps: u32, max_bps: u32) -> Self {
+        let ratio = f64::from(bitrate_bps) / f64::from(max_bps.max(1));
+        // Each halving of the size takes a quarter of the bits; go down
+        // once it gets under a quarter of its own, back up at 1.6 times.
+        let halving_below = |scale: usize| 0.25 / (scale * scale) as f64;
+        let mut scale_down = previous.map_or(1, |target| target.scale_down);
+        while scale_down < 4 && ratio < halving_below(scale_down) {
+            scale_down *= 2;
+        }
+        while scale_down > 1 && ratio > 1.6 * halving_below(scale_down / 2) {
+            scale_down /= 2;
+        }
+        let mut frame_rate_divisor = previous.map_or(1, |target| target.frame_rate_divisor);
+        if ratio < 0.03 {
+            frame_rate_divisor = 2;
+        } else if ratio > 0.05 {
+            frame_rate_divisor = 1;
+        }
+        Self {
+            bitrate_bps,
+            scale_down,
+            frame_rate_divisor,
+        }
+    }
+}
+
+impl fmt::Display for SendTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Sending at {} kbps", self.bitrate_bps / 1000)?;
+        match self.scale_down {
+            1 => {}
+            2 => write!(f, ", half size")?,
+            scale => write!(f, ", 1/{} size", scale)?,
+        }
+        if self.frame_rate_divisor > 1 {
+            write!(f, ", 1/{} of the frame rate", self.frame_rate_divisor)?;
+        }
+        write!(f, " for the link")
+    }
+}
+
+/// Reads the feedback on `sender` into an estimate, published in `target`
+/// for a video sending up to `max_bps`, until the sender stops. A target
+/// already there, as from the camera before a switch, is where it starts.
+pub fn spawn_rate_control(
+    sender: Arc<RTCRtpSender>,
+    max_bps: u32,
+    target: Snapshot<Option<SendTarget>>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let ssrc = sender
+            .get_parameters()
+            .await
+            .encodings
+            .first()
+            .map(|encoding| encoding.ssrc);
+        let start_bps = target.get().map_or(max_bps, |target| target.bitrate_bps);
+        let mut estimator = BandwidthEstimator::new(Instant::now(), start_bps, max_bps);
+        while let Ok((packets, _)) = sender.read_rtcp().await {
+            for packet in &packets {
+                estimator.on_rtcp(packet.as_ref(), ssrc);
+            }
+            if let Some(bitrate_bps) = estimator.update(Instant::now()) {
+                let previous = *target.get();
+                let next = SendTarget::follow(previous, bitrate_bps, max_bps);
+                if previous != Some(next) {
+                    target.set(Some(next));
+                }
+            }
+        }
+    })
+}
+
+/// Holds a capture loop to the target published in a snapshot: hands its
+/// bitrate to the encoder, drops frames beyond its frame rate and scales
+/// down the rest.
+pub struct TargetFollower {
+    target: Snapshot<Option<SendTarget>>,
+    frames: u32,
+}
+
+impl TargetFollower {
+    pub fn new(target: Snapshot<Option<SendTarget>>) -> Self {
+        Self { target, frames: 0 }
+    }
+
+    /// Whether to encode the next frame into `encoder`, at the bitrate
+    /// the target has.
+    pub fn admit(&mut self, encoder: &mut dyn VideoEncoder) -> bool {
+        let Some(target) = *self.target.get() else {
+            return true;
+        };
+        encoder.set_bitrate(target.bitrate_bps);
+        self.frames = self.frames.wrapping_add(1);
+        self.frames.is_multiple_of(target.frame_rate_divisor.max(1))
+    }
+
+    /// `frame`, or a copy scaled down to the target's size.
+    pub fn scale<'a>(&self, frame: &'a VideoFrame) -> Cow<'a, VideoFrame> {
+        match *self.target.get() {
+            Some(target) if target.scale_down > 1 => {
+                Cow::Owned(video::downscale(frame, target.scale_down))
+            }
+            _ => Cow::Borrowed(frame),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimate_climbs_without_loss_and_drops_with_it() {
+        let start = Instant::now();
+        let mut estimator = BandwidthEstimator::new(start, 1_000_000, 1_100_000);
+        let at = |ms| start + Duration::from_millis(ms);
+        estimator.on_packets(100, 0);
+        assert_eq!(estimator.update(at(100)), None);
+        assert_eq!(estimator.update(at(500)), Some(1_050_000));
+        // Capped at the maximum, then at what the remote takes.
+        estimator.on_packets(100, 0);
+        assert_eq!(estimator.update(at(1000)), Some(1_100_000));
+        estimator.remb_bps = Some(900_000);
+        estimator.on_packets(100, 0);
+        assert_eq!(estimator.update(at(1500)), Some(900_000));
+        // A fifth lost takes a tenth off.
+        estimator.on_packets(80, 20);
+        assert_eq!(estimator.update(at(2000)), Some(810_000));
+        // Some loss holds.
+        estimator.on_packets(95, 5);
+        assert_eq!(estimator.update(at(2500)), Some(810_000));
+    }
+
+    #[test]
+    fn target_shrinks_the_picture_with_hysteresis() {
+        let max = 2_000_000;
+        let target = SendTarget::follow(None, 1_500_000, max);
+        assert_eq!((target.scale_down, target.frame_rate_divisor), (1, 1));
+        let target = SendTarget::follow(Some(target), 400_000, max);
+        assert_eq!(target.scale_down, 2);
+        // Back over a quarter is not far enough over to grow again.
+        let target = SendTarget::follow(Some(target), 600_000, max);
+        assert_eq!(target.scale_down, 2);
+        let target = SendTarget::follow(Some(target), 900_000, max);
+        assert_eq!(target.scale_down, 1);
+        let target = SendTarget::follow(Some(target), 50_000, max);
+        assert_eq!((target.scale_down, target.frame_rate_divisor), (4, 2));
+    }
+}
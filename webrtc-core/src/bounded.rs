@@ -0,0 +1,177 @@
+//! Buffers with a cap on both entry count and accounted bytes, for state
+//! that would otherwise keep growing over a long-lived session.
+
+use std::collections::VecDeque;
+
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+
+/// Approximate heap size of a buffered value, used against a byte budget.
+pub trait Footprint {
+    fn footprint(&self) -> usize;
+}
+
+impl Footprint for String {
+    fn footprint(&self) -> usize {
+        self.len()
+    }
+}
+
+impl Footprint for RTCIceCandidateInit {
+    fn footprint(&self) -> usize {
+        self.candidate.len()
+            + self.sdp_mid.as_ref().map_or(0, String::len)
+            + self.username_fragment.as_ref().map_or(0, String::len)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Make room by evicting the oldest entries; for history.
+    DropOldest,
+    /// Refuse new entries once full; for queues where the first entries
+    /// matter most.
+    DropNewest,
+}
+
+#[derive(Debug, Clone)]
+pub struct BoundedBuffer<T> {
+    entries: VecDeque<(T, usize)>,
+    max_entries: usize,
+    max_bytes: usize,
+    policy: DropPolicy,
+    bytes: usize,
+    dropped: usize,
+}
+
+impl<T: Footprint> BoundedBuffer<T> {
+    pub fn new(max_entries: usize, max_bytes: usize, policy: DropPolicy) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            max_entries,
+            max_bytes,
+            policy,
+            bytes: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Adds `value`, dropping entries as the policy says when over budget.
+    /// Returns whether anything was dropped. A value bigger than the whole
+    /// byte budget is never kept.
+    pub fn push(&mut self, value: T) -> bool {
+        let size = value.footprint();
+        if size > self.max_bytes || self.max_entries == 0 {
+            self.dropped += 1;
+            return true;
+        }
+        let fits = |buffer: &Self| {
+            buffer.entries.len() < buffer.max_entries && buffer.bytes + size <= buffer.max_bytes
+        };
+        let mut dropped = false;
+        match self.policy {
+            DropPolicy::DropOldest => {
+                while !fits(self) {
+                    let Some((_, evicted)) = self.entries.pop_front() else {
+                        break;
+                    };
+                    self.bytes -= evicted;
+                    self.dropped += 1;
+                    dropped = true;
+                }
+            }
+            DropPolicy::DropNewest => {
+                if !fits(self) {
+                    self.dropped += 1;
+                    return true;
+                }
+            }
+        }
+        self.bytes += size;
+        self.entries.push_back((value, size));
+        dropped
+    }
+
+    /// Oldest first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+        self.entries.iter().map(|(value, _)| value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Accounted size of what is buffered now.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Entries dropped since the buffer was created or last cleared.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        self.bytes = 0;
+        self.entries.drain(..).map(|(value, _)| value)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.bytes = 0;
+        self.dropped = 0;
+    }
+
+    /// A user-facing note when entries have been dropped.
+    pub fn warning(&self, what: &str) -> Option<String> {
+        (self.dropped > 0).then(|| {
+            let reason = match self.policy {
+                DropPolicy::DropOldest => "oldest discarded",
+                DropPolicy::DropNewest => "not kept",
+            };
+            format!(
+                "{} {} over the limit of {} / {} KiB, {}",
+                self.dropped,
+                what,
+                self.max_entries,
+                self.max_bytes / 1024,
+                reason
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drop_oldest_evicts_until_the_byte_budget_fits() {
+        let mut history = BoundedBuffer::new(10, 8, DropPolicy::DropOldest);
+        assert!(!history.push("abc".to_owned()));
+        assert!(!history.push("def".to_owned()));
+        assert!(history.push("ghijk".to_owned()));
+        let kept: Vec<_> = history.iter().map(String::as_str).collect();
+        assert_eq!(kept, ["def", "ghijk"]);
+        assert_eq!(history.bytes(), 8);
+        assert_eq!(history.dropped(), 1);
+        assert!(history.warning("lines").is_some());
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_first_entries() {
+        let mut queue = BoundedBuffer::new(2, 1024, DropPolicy::DropNewest);
+        assert!(!queue.push("first".to_owned()));
+        assert!(!queue.push("second".to_owned()));
+        assert!(queue.push("third".to_owned()));
+        assert!(queue.push("x".repeat(2048)));
+        assert_eq!(queue.drain().collect::<Vec<_>>(), ["first", "second"]);
+        assert_eq!(queue.bytes(), 0);
+        assert_eq!(queue.dropped(), 2);
+        queue.clear();
+        assert!(queue.warning("signals").is_none());
+    }
+}
@@ -0,0 +1,577 @@
+//! Blurring or replacing what is behind the person on camera, before the
+//! frame is previewed or encoded. A segmentation model run by ONNX Runtime
+//! tells the person from the background: one that takes a picture in
+//! either channel order, scaled to 0..1, and gives back how likely each
+//! pixel is to be the person, as MediaPipe's selfie segmentation does.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::snapshot::Snapshot;
+use crate::video::{self, VideoFrame};
+
+/// Used when the model does not say what size it takes.
+#[cfg(any(test, feature = "background"))]
+const DEFAULT_MODEL_SIZE: usize = 256;
+
+/// How much of the last mask each new one keeps, against flicker at the
+/// edges.
+#[cfg(feature = "background")]
+const MASK_SMOOTHING: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackgroundEffect {
+    #[default]
+    None,
+    Blur,
+    /// With `BackgroundSettings::image`.
+    Replace,
+}
+
+impl BackgroundEffect {
+    pub const ALL: [BackgroundEffect; 3] = [
+        BackgroundEffect::None,
+        BackgroundEffect::Blur,
+        BackgroundEffect::Replace,
+    ];
+}
+
+impl fmt::Display for BackgroundEffect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BackgroundEffect::None => "No background effect",
+            BackgroundEffect::Blur => "Blur the background",
+            BackgroundEffect::Replace => "Replace the background",
+        })
+    }
+}
+
+/// What is done behind the person on camera; read for every frame, so a
+/// change applies at once.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackgroundSettings {
+    pub effect: BackgroundEffect,
+    /// The segmentation model, in ONNX.
+    pub model: Option<PathBuf>,
+    /// The picture that replaces the background, cropped to fill.
+    pub image: Option<PathBuf>,
+    /// How far the blur spreads, in pixels of the frame.
+    pub blur_radius: u32,
+}
+
+impl BackgroundSettings {
+    /// What the blur radius can be set to.
+    pub const BLUR_RADIUS: RangeInclusive<u32> = 4..=64;
+}
+
+impl Default for BackgroundSettings {
+    fn default() -> Self {
+        Self {
+            effect: BackgroundEffect::None,
+            model: None,
+            image: None,
+            blur_radius: 24,
+        }
+    }
+}
+
+/// Whether this build can tell the person from the background.
+pub fn can_segment() -> bool {
+    cfg!(feature = "background")
+}
+
+#[derive(Debug, Error)]
+pub enum BackgroundError {
+    #[error("this build has no background effects")]
+    Unsupported,
+    #[error("no segmentation model is set")]
+    NoModel,
+    #[error("no background image is set")]
+    NoImage,
+    #[error("segmentation model: {0}")]
+    Model(String),
+    #[error("background image: {0}")]
+    Image(String),
+}
+
+/// The four samples around a point given as fractions of the width and
+/// height, with their weights.
+fn taps(width: usize, height: usize, x: f32, y: f32) -> [(usize, f32); 4] {
+    let x = (x * width as f32 - 0.5).clamp(0.0, (width - 1) as f32);
+    let y = (y * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+    let (left, top) = (x as usize, y as usize);
+    let (right, bottom) = ((left + 1).min(width - 1), (top + 1).min(height - 1));
+    let (dx, dy) = (x - left as f32, y - top as f32);
+    [
+        (top * width + left, (1.0 - dx) * (1.0 - dy)),
+        (top * width + right, dx * (1.0 - dy)),
+        (bottom * width + left, (1.0 - dx) * dy),
+        (bottom * width + right, dx * dy),
+    ]
+}
+
+/// The color of `frame` at a point given as fractions of its size.
+fn sample(frame: &VideoFrame, x: f32, y: f32) -> [f32; 3] {
+    let mut color = [0.0; 3];
+    for (index, weight) in taps(frame.width, frame.height, x, y) {
+        for (channel, value) in color.iter_mut().zip(&frame.rgba[index * 4..]) {
+            *channel += f32::from(*value) * weight;
+        }
+    }
+    color
+}
+
+/// How much of each pixel is the person, from 0 for the background to 1.
+#[derive(Debug, Clone, Default)]
+pub struct Mask {
+    pub width: usize,
+    pub height: usize,
+    pub alpha: Vec<f32>,
+}
+
+impl Mask {
+    /// The mask at a point given as fractions of its size, between its
+    /// samples.
+    pub fn at(&self, x: f32, y: f32) -> f32 {
+        taps(self.width, self.height, x, y)
+            .iter()
+            .map(|(index, weight)| self.alpha[*index] * weight)
+            .sum()
+    }
+
+    /// Reads a mask out of what a model gave back in `shape`: one channel,
+    /// or two with the person second, either side of the size.
+    pub fn from_output(shape: &[i64], data: &[f32]) -> Option<Self> {
+        let dims: Vec<usize> = shape
+            .iter()
+            .map(|&dim| usize::try_from(dim).unwrap_or(1))
+            .collect();
+        let (height, width, channels, channels_first) = match dims.as_slice() {
+            [1, height, width, channels] if *channels <= 2 => (*height, *width, *channels, false),
+            [1, channels, height, width] if *channels <= 2 => (*height, *width, *channels, true),
+            [1, height, width] | [height, width] => (*height, *width, 1, false),
+            _ => return None,
+        };
+        let area = width * height;
+        if area == 0 || data.len() != area * channels {
+            return None;
+        }
+        let person = channels - 1;
+        let alpha = (0..area)
+            .map(|pixel| {
+                let value = if channels_first {
+                    data[person * area + pixel]
+                } else {
+                    data[pixel * channels + person]
+                };
+                value.clamp(0.0, 1.0)
+            })
+            .collect();
+        Some(Self {
+            width,
+            height,
+            alpha,
+        })
+    }
+
+    /// Moves part of the way to `next`, or all of it when the size changed.
+    #[cfg(feature = "background")]
+    fn follow(&mut self, next: Mask) {
+        if (self.width, self.height) != (next.width, next.height) {
+            *self = next;
+            return;
+        }
+        for (alpha, next) in self.alpha.iter_mut().zip(next.alpha) {
+            *alpha = *alpha * MASK_SMOOTHING + next * (1.0 - MASK_SMOOTHING);
+        }
+    }
+}
+
+/// Box-blurs one row or column of `rgba`, `len` pixels `stride` bytes
+/// apart, repeating the pixels at its ends.
+fn blur_line(
+    rgba: &mut [u8],
+    start: usize,
+    stride: usize,
+    len: usize,
+    radius: usize,
+    line: &mut Vec<[u8; 4]>,
+) {
+    line.clear();
+    line.extend((0..len).map(|i| {
+        let at = start + i * stride;
+        [rgba[at], rgba[at + 1], rgba[at + 2], rgba[at + 3]]
+    }));
+    let pixel = |i: isize| line[i.clamp(0, len as isize - 1) as usize];
+    let radius = radius as isize;
+    let window = (2 * radius + 1) as u32;
+    let mut sum = [0u32; 4];
+    for i in -radius..=radius {
+        for (sum, value) in sum.iter_mut().zip(pixel(i)) {
+            *sum += u32::from(value);
+        }
+    }
+    for i in 0..len {
+        let at = start + i * stride;
+        for (channel, sum) in sum.iter().enumerate() {
+            rgba[at + channel] = (sum / window) as u8;
+        }
+        let (leaving, entering) = (pixel(i as isize - radius), pixel(i as isize + radius + 1));
+        for ((sum, leaving), entering) in sum.iter_mut().zip(leaving).zip(entering) {
+            *sum = *sum + u32::from(entering) - u32::from(leaving);
+        }
+    }
+}
+
+/// A blurred copy of `frame`, spread about `radius` pixels. It comes back
+/// smaller, which loses nothing a blur would keep, and `composite` scales
+/// it up again.
+pub fn blur(frame: &VideoFrame, radius: u32) -> VideoFrame {
+    let factor = (radius / 4).clamp(1, 8) as usize;
+    let mut small = video::downscale(frame, factor);
+    let radius = (radius as usize / factor / 2).max(1);
+    let (width, height) = (small.width, small.height);
+    let mut line = Vec::new();
+    // Twice over comes close to a Gaussian.
+    for _ in 0..2 {
+        for row in 0..height {
+            blur_line(
+                &mut small.rgba,
+                row * width * 4,
+                4,
+                width,
+                radius,
+                &mut line,
+            );
+        }
+        for column in 0..width {
+            blur_line(
+                &mut small.rgba,
+                column * 4,
+                width * 4,
+                height,
+                radius,
+                &mut line,
+            );
+        }
+    }
+    small
+}
+
+/// Keeps the person in `frame` as `mask` says and puts `background`,
+/// scaled to the frame, everywhere else.
+pub fn composite(frame: &mut VideoFrame, mask: &Mask, background: &VideoFrame) {
+    if mask.alpha.is_empty() || background.width == 0 || background.height == 0 {
+        return;
+    }
+    for row in 0..frame.height {
+        let y = (row as f32 + 0.5) / frame.height as f32;
+        for column in 0..frame.width {
+            let x = (column as f32 + 0.5) / frame.width as f32;
+            let person = mask.at(x, y);
+            if person >= 1.0 {
+                continue;
+            }
+            let behind = sample(background, x, y);
+            let at = (row * frame.width + column) * 4;
+            for (value, behind) in frame.rgba[at..at + 3].iter_mut().zip(behind) {
+                *value = (f32::from(*value) * person + behind * (1.0 - person)).round() as u8;
+            }
+        }
+    }
+}
+
+/// The size a model takes, and whether its channels come before the
+/// size, from the shape of its input.
+#[cfg(any(test, feature = "background"))]
+fn input_layout(shape: &[i64]) -> Option<(usize, usize, bool)> {
+    let size = |dim: i64| usize::try_from(dim).unwrap_or(DEFAULT_MODEL_SIZE);
+    match shape {
+        [_, 3, height, width] => Some((size(*width), size(*height), true)),
+        [_, height, width, 3] => Some((size(*width), size(*height), false)),
+        _ => None,
+    }
+}
+
+/// A segmentation model, open in ONNX Runtime.
+#[cfg(feature = "background")]
+pub struct Segmenter {
+    session: ort::session::Session,
+    width: usize,
+    height: usize,
+    channels_first: bool,
+}
+
+#[cfg(feature = "background")]
+impl Segmenter {
+    pub fn open(model: &std::path::Path) -> Result<Self, BackgroundError> {
+        let model_error = |err: ort::Error| BackgroundError::Model(err.to_string());
+        let session = ort::session::Session::builder()
+            .and_then(|mut builder| builder.commit_from_file(model))
+            .map_err(model_error)?;
+        let shape = session
+            .inputs()
+            .first()
+            .and_then(|input| input.dtype().tensor_shape())
+            .ok_or_else(|| BackgroundError::Model("it takes no tensor".to_owned()))?;
+        let (width, height, channels_first) = input_layout(shape).ok_or_else(|| {
+            BackgroundError::Model(format!("it takes {:?}, not a picture", &shape[..]))
+        })?;
+        info!(
+            "Segmenting the camera with {} at {}x{}",
+            model.display(),
+            width,
+            height
+        );
+        Ok(Self {
+            session,
+            width,
+            height,
+            channels_first,
+        })
+    }
+
+    /// Where the person is in `frame`.
+    pub fn segment(&mut self, frame: &VideoFrame) -> Result<Mask, BackgroundError> {
+        let model_error = |err: ort::Error| BackgroundError::Model(err.to_string());
+        let (width, height) = (self.width, self.height);
+        let area = width * height;
+        let mut input = vec![0.0f32; area * 3];
+        for row in 0..height {
+            let y = (row as f32 + 0.5) / height as f32;
+            for column in 0..width {
+                let x = (column as f32 + 0.5) / width as f32;
+                let pixel = row * width + column;
+                for (channel, value) in sample(frame, x, y).into_iter().enumerate() {
+                    let index = if self.channels_first {
+                        channel * area + pixel
+                    } else {
+                        pixel * 3 + channel
+                    };
+                    input[index] = value / 255.0;
+                }
+            }
+        }
+        let shape = if self.channels_first {
+            [1, 3, height, width]
+        } else {
+            [1, height, width, 3]
+        };
+        let input = ort::value::Tensor::from_array((shape, input)).map_err(model_error)?;
+        let outputs = self.session.run(ort::inputs![input]).map_err(model_error)?;
+        let (shape, data) = outputs[0]
+            .try_extract_tensor::<f32>()
+            .map_err(model_error)?;
+        Mask::from_output(shape, data).ok_or_else(|| {
+            BackgroundError::Model(format!("it gives back {:?}, not a mask", &shape[..]))
+        })
+    }
+}
+
+/// Reads the picture at `path`, scaled and cropped to fill `width` by
+/// `height`.
+#[cfg(feature = "background")]
+fn load_image(
+    path: &std::path::Path,
+    width: usize,
+    height: usize,
+) -> Result<VideoFrame, BackgroundError> {
+    let image = image::open(path).map_err(|err| BackgroundError::Image(err.to_string()))?;
+    let image = image
+        .resize_to_fill(
+            width as u32,
+            height as u32,
+            image::imageops::FilterType::Triangle,
+        )
+        .into_rgba8();
+    Ok(VideoFrame {
+        width: image.width() as usize,
+        height: image.height() as usize,
+        rgba: image.into_raw(),
+    })
+}
+
+/// Applies the settings in `settings` to each frame from a camera. The
+/// model and image are opened when an effect first needs them and again
+/// when they change; until they open, and whenever segmenting fails, the
+/// frame goes through as it is and `problem` says why.
+pub struct BackgroundStage {
+    settings: Snapshot<BackgroundSettings>,
+    problem: Snapshot<Option<String>>,
+    /// The model and image last opened, opened or not.
+    opened: Option<(Option<PathBuf>, Option<PathBuf>)>,
+    #[cfg(feature = "background")]
+    segmenter: Option<Segmenter>,
+    #[cfg(feature = "background")]
+    replacement: Option<VideoFrame>,
+    #[cfg(feature = "background")]
+    mask: Mask,
+}
+
+impl BackgroundStage {
+    pub fn new(settings: Snapshot<BackgroundSettings>, problem: Snapshot<Option<String>>) -> Self {
+        Self {
+            settings,
+            problem,
+            opened: None,
+            #[cfg(feature = "background")]
+            segmenter: None,
+            #[cfg(feature = "background")]
+            replacement: None,
+            #[cfg(feature = "background")]
+            mask: Mask::default(),
+        }
+    }
+
+    pub fn process(&mut self, frame: &mut VideoFrame) {
+        let settings = self.settings.get();
+        if settings.effect == BackgroundEffect::None {
+            if self.opened.take().is_some() {
+                self.close();
+                self.problem.set(None);
+            }
+            return;
+        }
+        let wanted = (
+            settings.model.clone(),
+            (settings.effect == BackgroundEffect::Replace)
+                .then(|| settings.image.clone())
+                .flatten(),
+        );
+        if self.opened.as_ref() != Some(&wanted) {
+            self.close();
+            let problem = self.open(&settings, frame).err().map(|err| {
+                info!("No background effect: {}", err);
+                err.to_string()
+            });
+            self.problem.set(problem);
+            self.opened = Some(wanted);
+        }
+        #[cfg(feature = "background")]
+        {
+            let Some(segmenter) = &mut self.segmenter else {
+                return;
+            };
+            match segmenter.segment(frame) {
+                Ok(mask) => self.mask.follow(mask),
+                Err(err) => {
+                    info!("Segmenting stopped: {}", err);
+                    self.problem.set(Some(err.to_string()));
+                    self.close();
+                    return;
+                }
+            }
+            let blurred;
+            let background = match (settings.effect, &self.replacement) {
+                (BackgroundEffect::Blur, _) => {
+                    blurred = blur(frame, settings.blur_radius);
+                    &blurred
+                }
+                (BackgroundEffect::Replace, Some(image)) => image,
+                _ => return,
+            };
+            composite(frame, &self.mask, background);
+        }
+    }
+
+    fn open(
+        &mut self,
+        settings: &BackgroundSettings,
+        frame: &VideoFrame,
+    ) -> Result<(), BackgroundError> {
+        #[cfg(feature = "background")]
+        {
+            let model = settings.model.as_ref().ok_or(BackgroundError::NoModel)?;
+            if settings.effect == BackgroundEffect::Replace {
+                let image = settings.image.as_ref().ok_or(BackgroundError::NoImage)?;
+                self.replacement = Some(load_image(image, frame.width, frame.height)?);
+            }
+            self.segmenter = Some(Segmenter::open(model)?);
+            Ok(())
+        }
+        #[cfg(not(feature = "background"))]
+        {
+            let _ = (settings, frame);
+            Err(BackgroundError::Unsupported)
+        }
+    }
+
+    fn close(&mut self) {
+        #[cfg(feature = "background")]
+        {
+            self.segmenter = None;
+            self.replacement = None;
+            self.mask = Mask::default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: usize, height: usize, color: [u8; 4]) -> VideoFrame {
+        VideoFrame {
+            width,
+            height,
+            rgba: color.repeat(width * height),
+        }
+    }
+
+    #[test]
+    fn reads_masks_and_model_inputs() {
+        let nhwc = Mask::from_output(&[1, 2, 2, 1], &[0.0, 1.0, 0.5, 2.0]).unwrap();
+        assert_eq!((nhwc.width, nhwc.height), (2, 2));
+        assert_eq!(nhwc.alpha, [0.0, 1.0, 0.5, 1.0]);
+        // Two classes, the person second, channels first.
+        let nchw = Mask::from_output(&[1, 2, 1, 2], &[1.0, 0.0, 0.0, 1.0]).unwrap();
+        assert_eq!(nchw.alpha, [0.0, 1.0]);
+        assert!(Mask::from_output(&[1, 2, 2, 1], &[0.0; 3]).is_none());
+        assert!(Mask::from_output(&[1, 8, 8, 8], &[0.0; 512]).is_none());
+
+        assert_eq!(input_layout(&[1, 256, 256, 3]), Some((256, 256, false)));
+        assert_eq!(input_layout(&[1, 3, 144, 256]), Some((256, 144, true)));
+        assert_eq!(input_layout(&[1, 3, -1, -1]), Some((256, 256, true)));
+        assert_eq!(input_layout(&[1, 16]), None);
+    }
+
+    #[test]
+    fn keeps_the_person_and_replaces_the_rest() {
+        let mut frame = solid(4, 2, [200, 0, 0, 255]);
+        // The person on the left half.
+        let mask = Mask {
+            width: 2,
+            height: 1,
+            alpha: vec![1.0, 0.0],
+        };
+        composite(&mut frame, &mask, &solid(2, 2, [0, 0, 100, 255]));
+        assert_eq!(&frame.rgba[..4], &[200, 0, 0, 255]);
+        assert_eq!(&frame.rgba[12..16], &[0, 0, 100, 255]);
+        // Between the two, a blend.
+        assert!(frame.rgba[4] > 0 && frame.rgba[4] < 200);
+    }
+
+    #[test]
+    fn blurs_an_edge_and_keeps_flat_color() {
+        let mut frame = solid(32, 8, [0, 0, 0, 255]);
+        for row in 0..8 {
+            for column in 16..32 {
+                frame.rgba[(row * 32 + column) * 4] = 255;
+            }
+        }
+        let blurred = blur(&frame, 8);
+        assert_eq!((blurred.width, blurred.height), (16, 4));
+        let red: Vec<u8> = (0..16).map(|column| blurred.rgba[column * 4]).collect();
+        assert_eq!(red[0], 0);
+        assert_eq!(red[15], 255);
+        assert!(red[7] > 0 && red[8] < 255);
+        assert!(blurred.rgba.chunks_exact(4).all(|pixel| pixel[3] == 255));
+    }
+}
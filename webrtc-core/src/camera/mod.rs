@@ -0,0 +1,331 @@
+//! Webcam capture, and sending it as a video track.
+//!
+//! Cameras are read in YUYV, which every UVC webcam offers; compressed
+//! formats such as MJPG would need a decoder first. Frames go out in
+//! whichever codec the track was made for, and to a preview whether or
+//! not they are being sent. That is VP8 where the `vp8` feature is built
+//! in; without libvpx, as in the default build, it is H.264. A scalable track's layers are capped by a
+//! `LayerCap` that can change while it sends, and the bitrate, size and
+//! frame rate follow a `SendTarget` for the link once one is published.
+//! The background behind the person can be blurred or replaced first.
+
+mod background;
+#[cfg(target_os = "linux")]
+mod v4l2;
+
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use bytes::Bytes;
+use log::info;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use webrtc::media::Sample;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+
+use crate::bandwidth::{SendTarget, TargetFollower};
+use crate::codec::{LayerCap, ScalabilityMode};
+use crate::devices::{CaptureMode, DeviceInfo};
+use crate::power::{EncodeLimits, EncodeThrottle};
+use crate::snapshot::Snapshot;
+use crate::video::{self, VideoEncoder, VideoFrame};
+
+#[cfg(feature = "background")]
+pub use background::Segmenter;
+pub use background::{
+    blur, can_segment, composite, BackgroundEffect, BackgroundError, BackgroundSettings,
+    BackgroundStage, Mask,
+};
+
+/// The pixel format capture asks cameras for.
+pub const CAPTURE_FORMAT: &str = "YUYV";
+
+/// Used when the camera's modes are unknown.
+const DEFAULT_WIDTH: u32 = 640;
+const DEFAULT_HEIGHT: u32 = 480;
+const DEFAULT_FRAME_RATE: f64 = 30.0;
+
+#[derive(Debug, Error)]
+pub enum CameraError {
+    #[error("camera capture is not supported on this platform")]
+    Unsupported,
+    #[error("this build has no video encoder")]
+    NoEncoder,
+    #[error("the camera delivers {0} instead of YUYV")]
+    UnsupportedFormat(String),
+    #[error("{0}: {1}")]
+    Io(&'static str, #[source] std::io::Error),
+}
+
+/// Whether capture can use a mode as the camera reports it.
+pub fn can_capture(mode: &CaptureMode) -> bool {
+    mode.format == CAPTURE_FORMAT
+}
+
+/// An open camera, streaming until dropped.
+pub struct Camera {
+    width: usize,
+    height: usize,
+    frame_rate: f64,
+    #[cfg(target_os = "linux")]
+    stream: v4l2::Stream,
+}
+
+impl Camera {
+    /// Starts streaming from `device` in `mode`, or in 640x480 at 30 fps
+    /// when its modes are unknown. The camera may settle on a nearby size.
+    pub fn open(device: &DeviceInfo, mode: Option<&CaptureMode>) -> Result<Self, CameraError> {
+        let (width, height, frame_rate) = mode.map_or(
+            (DEFAULT_WIDTH, DEFAULT_HEIGHT, DEFAULT_FRAME_RATE),
+            |mode| (mode.width, mode.height, mode.frame_rate),
+        );
+        #[cfg(target_os = "linux")]
+        {
+            let stream =
+                v4l2::Stream::open(std::path::Path::new(&device.id), width, height, frame_rate)?;
+            Ok(Self {
+                width: stream.width as usize,
+                height: stream.height as usize,
+                frame_rate: stream.frame_rate,
+                stream,
+            })
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (device, width, height, frame_rate);
+            Err(CameraError::Unsupported)
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        self.frame_rate
+    }
+
+    /// Blocks until the camera delivers its next frame.
+    pub fn next_frame(&mut self) -> Result<VideoFrame, CameraError> {
+        #[cfg(target_os = "linux")]
+        {
+            let (width, height) = (self.width, self.height);
+            self.stream.next_frame(|data, bytes_per_line| VideoFrame {
+                width,
+                height,
+                rgba: yuyv_to_rgba(data, width, height, bytes_per_line),
+            })
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            Err(CameraError::Unsupported)
+        }
+    }
+}
+
+/// Converts packed YUYV (two pixels sharing one U and V sample) to RGBA,
+/// with the BT.601 limited-range coefficients webcams use.
+pub fn yuyv_to_rgba(data: &[u8], width: usize, height: usize, bytes_per_line: usize) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for row in data.chunks(bytes_per_line).take(height) {
+        for pair in row[..(width * 2).min(row.len())].chunks_exact(4) {
+            let u = i32::from(pair[1]) - 128;
+            let v = i32::from(pair[3]) - 128;
+            for y in [pair[0], pair[2]] {
+                let c = 298 * (i32::from(y) - 16);
+                let channel = |value: i32| ((value + 128) >> 8).clamp(0, 255) as u8;
+                rgba.extend_from_slice(&[
+                    channel(c + 409 * v),
+                    channel(c - 100 * u - 208 * v),
+                    channel(c + 516 * u),
+                    255,
+                ]);
+            }
+        }
+    }
+    // A short final frame still has to fill the picture.
+    rgba.resize(width * height * 4, 0);
+    rgba
+}
+
+/// What the capture thread encodes into while the feed is being sent.
+type Sending = Option<(Arc<TrackLocalStaticSample>, Box<dyn VideoEncoder>)>;
+
+/// A camera streaming on its own thread into a preview, and into a track
+/// while one is attached. Dropping the feed closes the camera at its next
+/// frame.
+pub struct CameraFeed {
+    width: usize,
+    height: usize,
+    frame_rate: f64,
+    sending: Arc<Mutex<Sending>>,
+    writer: JoinHandle<()>,
+    capture: Option<std::thread::JoinHandle<()>>,
+}
+
+impl CameraFeed {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        self.frame_rate
+    }
+
+    pub fn sending(&self) -> bool {
+        self.sending.lock().unwrap().is_some()
+    }
+
+    /// Starts encoding into `track` in `scalability`'s layers, where its
+    /// codec has them, beginning with a keyframe, or stops sending with
+    /// `None`; the preview carries on either way.
+    pub fn send_to(
+        &self,
+        track: Option<Arc<TrackLocalStaticSample>>,
+        scalability: ScalabilityMode,
+    ) -> Result<(), CameraError> {
+        let sending = match track {
+            Some(track) => {
+                let encoder = video::encoder_for_track(
+                    &track,
+                    self.width,
+                    self.height,
+                    self.frame_rate as f32,
+                    scalability,
+                )
+                .ok_or(CameraError::NoEncoder)?;
+                Some((track, encoder))
+            }
+            None => None,
+        };
+        *self.sending.lock().unwrap() = sending;
+        Ok(())
+    }
+
+    /// Closes the camera and blocks until it has, at its next frame, so
+    /// the same device can be opened again in another mode.
+    pub fn close(mut self) {
+        self.writer.abort();
+        if let Some(capture) = self.capture.take() {
+            let _ = capture.join();
+        }
+    }
+}
+
+impl Drop for CameraFeed {
+    fn drop(&mut self) {
+        self.writer.abort();
+    }
+}
+
+/// Streams `camera` into `preview` until the returned feed is dropped, and
+/// into a track once one is given to `CameraFeed::send_to`, held to
+/// `limits` while they are set, and to `layer_cap`'s layers and `target`
+/// on top. The preview always gets every frame, both after `background`.
+pub fn spawn_camera(
+    mut camera: Camera,
+    preview: Snapshot<Option<VideoFrame>>,
+    limits: Snapshot<Option<EncodeLimits>>,
+    layer_cap: Snapshot<LayerCap>,
+    target: Snapshot<Option<SendTarget>>,
+    mut background: BackgroundStage,
+) -> Result<CameraFeed, CameraError> {
+    let sending = Arc::new(Mutex::new(Sending::None));
+    let (samples_tx, mut samples) = mpsc::channel::<(Arc<TrackLocalStaticSample>, Sample)>(2);
+    let (width, height, frame_rate) = (camera.width, camera.height, camera.frame_rate);
+    let encoding = Arc::clone(&sending);
+    let capture = std::thread::Builder::new()
+        .name("camera".to_owned())
+        .spawn(move || {
+            let mut last = Instant::now();
+            let mut throttle = EncodeThrottle::new(limits);
+            let mut follower = TargetFollower::new(target);
+            while !samples_tx.is_closed() {
+                let mut frame = match camera.next_frame() {
+                    Ok(frame) => frame,
+                    Err(err) => {
+                        info!("Camera capture stopped: {}", err);
+                        return;
+                    }
+                };
+                background.process(&mut frame);
+                let now = Instant::now();
+                // A dropped frame's time goes to the next one sent.
+                if !throttle.admit(now) {
+                    preview.set(Some(frame));
+                    continue;
+                }
+                let encoded = encoding.lock().unwrap().as_mut().map(|(track, encoder)| {
+                    let track = Arc::clone(track);
+                    encoder.set_layer_cap(*layer_cap.get());
+                    if !follower.admit(encoder.as_mut()) {
+                        return (track, None);
+                    }
+                    let frame = throttle.scale(&frame);
+                    (track, encoder.encode(&follower.scale(&frame)))
+                });
+                preview.set(Some(frame));
+                // So does the time of a frame held back for the link, or
+                // in a layer above the cap.
+                let (track, data) = match encoded {
+                    Some((track, Some(data))) => (track, data),
+                    Some((_, None)) => continue,
+                    None => {
+                        last = now;
+                        continue;
+                    }
+                };
+                let duration = now - last;
+                last = now;
+                let sample = Sample {
+                    data: Bytes::from(data),
+                    duration,
+                    ..Default::default()
+                };
+                // Waiting here leaves frames to the camera's own queue,
+                // which drops them before they cost an encode.
+                if samples_tx.blocking_send((track, sample)).is_err() {
+                    return;
+                }
+            }
+        })
+        .map_err(|err| CameraError::Io("spawn capture thread", err))?;
+    let writer = tokio::spawn(async move {
+        while let Some((track, sample)) = samples.recv().await {
+            if let Err(err) = track.write_sample(&sample).await {
+                info!("Failed to write camera sample: {:?}", err);
+            }
+        }
+    });
+    Ok(CameraFeed {
+        width,
+        height,
+        frame_rate,
+        sending,
+        writer,
+        capture: Some(capture),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_yuyv_and_skips_row_padding() {
+        // One row of white then black, padded to 12 bytes per line.
+        let row = [235, 128, 16, 128, 0, 0, 0, 0, 0, 0, 0, 0];
+        let rgba = yuyv_to_rgba(&row, 2, 1, 12);
+        assert_eq!(rgba, [255, 255, 255, 255, 0, 0, 0, 255]);
+        assert_eq!(yuyv_to_rgba(&[], 2, 2, 4).len(), 16);
+    }
+}
@@ -0,0 +1,293 @@
+//! Streaming capture through V4L2 memory-mapped buffers.
+
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+use crate::devices::v4l2::{fourcc, ioctl, iow, iowr, V4L2_BUF_TYPE_VIDEO_CAPTURE};
+
+use super::CameraError;
+
+const V4L2_PIX_FMT_YUYV: u32 = u32::from_le_bytes(*b"YUYV");
+const V4L2_FIELD_NONE: u32 = 1;
+const V4L2_MEMORY_MMAP: u32 = 1;
+
+/// Buffers the kernel fills while we encode the previous frame.
+const BUFFERS: u32 = 4;
+
+/// `v4l2_pix_format`.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct PixFormat {
+    width: u32,
+    height: u32,
+    pixel_format: u32,
+    field: u32,
+    bytes_per_line: u32,
+    size_image: u32,
+    colorspace: u32,
+    private: u32,
+    flags: u32,
+    ycbcr_enc: u32,
+    quantization: u32,
+    xfer_func: u32,
+}
+
+/// The 200-byte union of `v4l2_format`, pointer aligned; we only use its
+/// `pix` member.
+#[repr(C)]
+#[derive(Default)]
+struct FormatUnion {
+    pix: PixFormat,
+    reserved: [[u8; 8]; 19],
+    _align: [usize; 0],
+}
+
+/// `v4l2_format`.
+#[repr(C)]
+#[derive(Default)]
+struct Format {
+    typ: u32,
+    fmt: FormatUnion,
+}
+
+/// `v4l2_streamparm` with its `capture` member.
+#[repr(C)]
+#[derive(Default)]
+struct StreamParm {
+    typ: u32,
+    capability: u32,
+    capture_mode: u32,
+    /// Seconds per frame as numerator/denominator.
+    time_per_frame: [u32; 2],
+    extended_mode: u32,
+    read_buffers: u32,
+    reserved: [u32; 4],
+    rest: [[u8; 8]; 20],
+}
+
+/// `v4l2_requestbuffers`.
+#[repr(C)]
+#[derive(Default)]
+struct RequestBuffers {
+    count: u32,
+    typ: u32,
+    memory: u32,
+    capabilities: u32,
+    flags: u8,
+    reserved: [u8; 3],
+}
+
+/// The `m` union of `v4l2_buffer`; MMAP buffers use `offset`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+union Location {
+    offset: u32,
+    userptr: libc::c_ulong,
+}
+
+impl Default for Location {
+    fn default() -> Self {
+        Self { userptr: 0 }
+    }
+}
+
+/// `v4l2_buffer`.
+#[repr(C)]
+#[derive(Default)]
+struct Buffer {
+    index: u32,
+    typ: u32,
+    bytes_used: u32,
+    flags: u32,
+    field: u32,
+    timestamp: [libc::c_long; 2],
+    timecode: [u32; 4],
+    sequence: u32,
+    memory: u32,
+    m: Location,
+    length: u32,
+    reserved2: u32,
+    request_fd: u32,
+}
+
+impl Buffer {
+    fn new(index: u32) -> Self {
+        Self {
+            index,
+            typ: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            memory: V4L2_MEMORY_MMAP,
+            ..Default::default()
+        }
+    }
+}
+
+const VIDIOC_S_FMT: u64 = iowr::<Format>(5);
+const VIDIOC_REQBUFS: u64 = iowr::<RequestBuffers>(8);
+const VIDIOC_QUERYBUF: u64 = iowr::<Buffer>(9);
+const VIDIOC_QBUF: u64 = iowr::<Buffer>(15);
+const VIDIOC_DQBUF: u64 = iowr::<Buffer>(17);
+const VIDIOC_STREAMON: u64 = iow::<i32>(18);
+const VIDIOC_STREAMOFF: u64 = iow::<i32>(19);
+const VIDIOC_S_PARM: u64 = iowr::<StreamParm>(22);
+
+/// `ioctl`, with the OS error when it fails.
+fn call<T>(file: &File, name: &'static str, request: u64, arg: &mut T) -> Result<(), CameraError> {
+    loop {
+        if ioctl(file, request, arg) {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(CameraError::Io(name, err));
+        }
+    }
+}
+
+/// A buffer shared with the driver.
+struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+pub struct Stream {
+    file: File,
+    mappings: Vec<Mapping>,
+    pub width: u32,
+    pub height: u32,
+    bytes_per_line: u32,
+    pub frame_rate: f64,
+}
+
+// SAFETY: the mappings belong to this stream alone and are only touched
+// through `&mut self`.
+unsafe impl Send for Stream {}
+
+impl Stream {
+    pub fn open(
+        node: &Path,
+        width: u32,
+        height: u32,
+        frame_rate: f64,
+    ) -> Result<Self, CameraError> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .open(node)
+            .map_err(|err| CameraError::Io("open camera", err))?;
+
+        let mut format = Format {
+            typ: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            fmt: FormatUnion {
+                pix: PixFormat {
+                    width,
+                    height,
+                    pixel_format: V4L2_PIX_FMT_YUYV,
+                    field: V4L2_FIELD_NONE,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        };
+        call(&file, "set format", VIDIOC_S_FMT, &mut format)?;
+        let pix = format.fmt.pix;
+        if pix.pixel_format != V4L2_PIX_FMT_YUYV {
+            return Err(CameraError::UnsupportedFormat(fourcc(pix.pixel_format)));
+        }
+
+        // Not every driver lets the rate be chosen; keep what it has then.
+        let mut parm = StreamParm {
+            typ: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            time_per_frame: [1000, (frame_rate * 1000.0).round() as u32],
+            ..Default::default()
+        };
+        let frame_rate = match call(&file, "set frame rate", VIDIOC_S_PARM, &mut parm) {
+            Ok(()) if parm.time_per_frame.iter().all(|&n| n > 0) => {
+                let [numerator, denominator] = parm.time_per_frame;
+                f64::from(denominator) / f64::from(numerator)
+            }
+            _ => frame_rate,
+        };
+
+        let mut request = RequestBuffers {
+            count: BUFFERS,
+            typ: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            memory: V4L2_MEMORY_MMAP,
+            ..Default::default()
+        };
+        call(&file, "request buffers", VIDIOC_REQBUFS, &mut request)?;
+
+        let mut stream = Self {
+            file,
+            mappings: vec![],
+            width: pix.width,
+            height: pix.height,
+            bytes_per_line: pix.bytes_per_line.max(pix.width * 2),
+            frame_rate,
+        };
+        for index in 0..request.count {
+            let mut buffer = Buffer::new(index);
+            call(&stream.file, "query buffer", VIDIOC_QUERYBUF, &mut buffer)?;
+            // SAFETY: maps the buffer the driver just described, at the
+            // offset and length it gave; unmapped on drop.
+            let ptr = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    buffer.length as usize,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_SHARED,
+                    stream.file.as_raw_fd(),
+                    buffer.m.offset as libc::off_t,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(CameraError::Io("map buffer", io::Error::last_os_error()));
+            }
+            stream.mappings.push(Mapping {
+                ptr,
+                len: buffer.length as usize,
+            });
+            call(&stream.file, "queue buffer", VIDIOC_QBUF, &mut buffer)?;
+        }
+
+        let mut typ = V4L2_BUF_TYPE_VIDEO_CAPTURE as i32;
+        call(&stream.file, "start streaming", VIDIOC_STREAMON, &mut typ)?;
+        Ok(stream)
+    }
+
+    /// Waits for the next filled buffer and hands its bytes and row stride
+    /// to `convert` before giving the buffer back to the driver.
+    pub fn next_frame<T>(
+        &mut self,
+        convert: impl FnOnce(&[u8], usize) -> T,
+    ) -> Result<T, CameraError> {
+        let mut buffer = Buffer::new(0);
+        call(&self.file, "dequeue buffer", VIDIOC_DQBUF, &mut buffer)?;
+        let mapping = &self.mappings[buffer.index as usize];
+        // SAFETY: the driver has finished writing this buffer and will not
+        // touch it again until we queue it below.
+        let data = unsafe {
+            std::slice::from_raw_parts(
+                mapping.ptr as *const u8,
+                (buffer.bytes_used as usize).min(mapping.len),
+            )
+        };
+        let frame = convert(data, self.bytes_per_line as usize);
+        call(&self.file, "queue buffer", VIDIOC_QBUF, &mut buffer)?;
+        Ok(frame)
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let mut typ = V4L2_BUF_TYPE_VIDEO_CAPTURE as i32;
+        let _ = call(&self.file, "stop streaming", VIDIOC_STREAMOFF, &mut typ);
+        for mapping in &self.mappings {
+            // SAFETY: each mapping came from mmap above and is unmapped once.
+            unsafe {
+                libc::munmap(mapping.ptr, mapping.len);
+            }
+        }
+    }
+}
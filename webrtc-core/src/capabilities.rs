@@ -0,0 +1,177 @@
+//! "Export capabilities": what this build will offer (codecs, RTCP
+//! feedback, header extensions) and which devices it can see, as one JSON
+//! document to attach to interop bug reports.
+
+use std::time::SystemTime;
+
+use serde::Serialize;
+use webrtc::api::media_engine::MediaEngine;
+use webrtc::api::API;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+use webrtc::rtp_transceiver::rtp_transceiver_direction::RTCRtpTransceiverDirection;
+use webrtc::rtp_transceiver::RTCRtpTransceiverInit;
+
+use crate::devices::{self, DeviceInfo, DeviceKind};
+use crate::settings::Settings;
+use crate::video;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CapabilityReport {
+    /// RFC 3339, UTC.
+    pub generated_at: String,
+    pub app_version: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    /// Cargo features this build was compiled with.
+    pub features: Vec<&'static str>,
+    pub engines: Vec<EngineCapabilities>,
+    pub devices: Vec<DeviceInfo>,
+}
+
+/// One media engine configuration, as advertised in an offer from it.
+#[derive(Debug, Clone, Serialize)]
+pub struct EngineCapabilities {
+    /// Where the engine is used, e.g. `peer connection` or `playback`.
+    pub name: &'static str,
+    pub codecs: Vec<CodecCapability>,
+    pub header_extensions: Vec<HeaderExtension>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CodecCapability {
+    pub kind: String,
+    pub payload_type: u8,
+    pub mime_type: String,
+    pub clock_rate: u32,
+    pub channels: u16,
+    pub fmtp: String,
+    pub rtcp_feedback: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HeaderExtension {
+    pub kind: String,
+    pub id: u16,
+    pub uri: String,
+}
+
+fn features() -> Vec<&'static str> {
+    let mut features = vec![];
+    if cfg!(feature = "audio") {
+        features.push("audio");
+    }
+    if cfg!(feature = "h264") {
+        features.push("h264");
+    }
+    features
+}
+
+/// Collects the report for the engines the app builds from `settings`.
+pub async fn report(settings: &Settings) -> Result<CapabilityReport, webrtc::Error> {
+    let mut playback = MediaEngine::default();
+    video::register_playback_codecs(&mut playback)?;
+    let engines = vec![
+        probe("peer connection", &settings.api()?).await?,
+        probe("playback", &settings.api_with(playback)).await?,
+    ];
+    Ok(CapabilityReport {
+        generated_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        app_version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        features: features(),
+        engines,
+        devices: DeviceKind::ALL
+            .into_iter()
+            .flat_map(devices::enumerate)
+            .collect(),
+    })
+}
+
+/// The media engine keeps its registrations private, so read them back from
+/// an offer with one audio and one video m-line.
+pub async fn probe(name: &'static str, api: &API) -> Result<EngineCapabilities, webrtc::Error> {
+    let pc = api.new_peer_connection(RTCConfiguration::default()).await?;
+    for kind in [RTPCodecType::Audio, RTPCodecType::Video] {
+        let init = RTCRtpTransceiverInit {
+            direction: RTCRtpTransceiverDirection::Recvonly,
+            send_encodings: vec![],
+        };
+        pc.add_transceiver_from_kind(kind, Some(init)).await?;
+    }
+    let offer = pc.create_offer(None).await;
+    pc.close().await?;
+    let (codecs, header_extensions) = parse_offer(&offer?.sdp);
+    Ok(EngineCapabilities {
+        name,
+        codecs,
+        header_extensions,
+    })
+}
+
+/// Reads codecs and header extensions out of an SDP's media sections.
+pub fn parse_offer(sdp: &str) -> (Vec<CodecCapability>, Vec<HeaderExtension>) {
+    let mut codecs: Vec<CodecCapability> = vec![];
+    let mut extensions: Vec<HeaderExtension> = vec![];
+    let mut kind = String::new();
+    // Payload types are only unique within a media section.
+    let mut section_start = 0;
+    for line in sdp.lines().map(str::trim) {
+        if let Some(media) = line.strip_prefix("m=") {
+            kind = media.split(' ').next().unwrap_or_default().to_owned();
+            section_start = codecs.len();
+            continue;
+        }
+        let Some(attribute) = line.strip_prefix("a=") else {
+            continue;
+        };
+        let (name, value) = attribute.split_once(':').unwrap_or((attribute, ""));
+        let (first, rest) = value.split_once(' ').unwrap_or((value, ""));
+        let codec = |codecs: &[CodecCapability]| {
+            let payload_type = first.parse::<u8>().ok()?;
+            let index = codecs[section_start..]
+                .iter()
+                .position(|c| c.payload_type == payload_type)?;
+            Some(section_start + index)
+        };
+        match name {
+            "rtpmap" => {
+                let Ok(payload_type) = first.parse() else {
+                    continue;
+                };
+                let mut encoding = rest.split('/');
+                codecs.push(CodecCapability {
+                    kind: kind.clone(),
+                    payload_type,
+                    mime_type: format!("{}/{}", kind, encoding.next().unwrap_or_default()),
+                    clock_rate: encoding.next().and_then(|r| r.parse().ok()).unwrap_or(0),
+                    channels: encoding.next().and_then(|c| c.parse().ok()).unwrap_or(0),
+                    ..Default::default()
+                });
+            }
+            "fmtp" => {
+                if let Some(index) = codec(&codecs) {
+                    codecs[index].fmtp = rest.to_owned();
+                }
+            }
+            "rtcp-fb" => {
+                if let Some(index) = codec(&codecs) {
+                    codecs[index].rtcp_feedback.push(rest.to_owned());
+                }
+            }
+            "extmap" => {
+                let id = first.split('/').next().and_then(|id| id.parse().ok());
+                if let Some(id) = id {
+                    extensions.push(HeaderExtension {
+                        kind: kind.clone(),
+                        id,
+                        uri: rest.split(' ').next().unwrap_or_default().to_owned(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    (codecs, extensions)
+}
@@ -0,0 +1,316 @@
+//! Live captions and their transcripts. Whoever captions a call types what
+//! is said, and each line goes to the peer over a pre-negotiated
+//! "captions" data channel. The cues either way, with their times from the
+//! start of the call, are exported as SubRip (`.srt`) or WebVTT (`.vtt`)
+//! for review after the call, next to its recording where there is one.
+
+use std::fmt::{self, Write};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+pub const CAPTIONS_LABEL: &str = "captions";
+pub const CAPTIONS_STREAM_ID: u16 = 7;
+
+/// How long a caption stays up at the least, and at the most however long
+/// it is.
+const MIN_SHOWN: Duration = Duration::from_secs(2);
+const MAX_SHOWN: Duration = Duration::from_secs(7);
+/// Reading time added for each character.
+const PER_CHAR: Duration = Duration::from_millis(60);
+
+/// One line of captions, as it goes over the captions channel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Caption {
+    /// The captioner's display name.
+    pub speaker: String,
+    pub text: String,
+}
+
+impl Caption {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("captions always serialize")
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptionFormat {
+    Srt,
+    Vtt,
+}
+
+impl CaptionFormat {
+    pub const ALL: [CaptionFormat; 2] = [CaptionFormat::Srt, CaptionFormat::Vtt];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            CaptionFormat::Srt => "srt",
+            CaptionFormat::Vtt => "vtt",
+        }
+    }
+}
+
+impl fmt::Display for CaptionFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CaptionFormat::Srt => "SubRip (.srt)",
+            CaptionFormat::Vtt => "WebVTT (.vtt)",
+        })
+    }
+}
+
+/// One caption, shown from `start` to `end` of the call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cue {
+    pub start: Duration,
+    pub end: Duration,
+    /// Who said it, where the captions tell speakers apart.
+    pub speaker: Option<String>,
+    pub text: String,
+}
+
+impl Cue {
+    /// `caption` as it arrived `start` into the call, up for as long as it
+    /// takes to read.
+    pub fn arrived(start: Duration, caption: Caption) -> Self {
+        let chars = caption.text.chars().count() as u32;
+        let shown = (MIN_SHOWN + PER_CHAR * chars).min(MAX_SHOWN);
+        Self {
+            start,
+            end: start + shown,
+            speaker: Some(caption.speaker).filter(|speaker| !speaker.is_empty()),
+            text: caption.text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transcript {
+    cues: Vec<Cue>,
+}
+
+impl Transcript {
+    /// Adds a cue, in order of its start however late it arrives. Blank
+    /// ones are dropped.
+    pub fn push(&mut self, cue: Cue) {
+        if cue.text.trim().is_empty() {
+            return;
+        }
+        let at = self.cues.partition_point(|other| other.start <= cue.start);
+        self.cues.insert(at, cue);
+    }
+
+    pub fn cues(&self) -> &[Cue] {
+        &self.cues
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cues.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.cues.clear();
+    }
+
+    pub fn export(&self, format: CaptionFormat) -> String {
+        let mut out = String::new();
+        if format == CaptionFormat::Vtt {
+            out.push_str("WEBVTT\n\n");
+        }
+        for (index, cue) in self.cues.iter().enumerate() {
+            let end = cue.end.max(cue.start);
+            // Writing to a String cannot fail.
+            let _ = match format {
+                CaptionFormat::Srt => writeln!(
+                    out,
+                    "{}\n{} --> {}\n{}\n",
+                    index + 1,
+                    timestamp(cue.start, ','),
+                    timestamp(end, ','),
+                    match &cue.speaker {
+                        Some(speaker) => format!("{}: {}", speaker, cue.text.trim()),
+                        None => cue.text.trim().to_owned(),
+                    }
+                ),
+                CaptionFormat::Vtt => writeln!(
+                    out,
+                    "{} --> {}\n{}\n",
+                    timestamp(cue.start, '.'),
+                    timestamp(end, '.'),
+                    match &cue.speaker {
+                        Some(speaker) => format!("<v {}>{}", speaker, vtt_escape(cue.text.trim())),
+                        None => vtt_escape(cue.text.trim()),
+                    }
+                ),
+            };
+        }
+        out
+    }
+
+    /// The cues from `offset` into the call on, timed from there, for a
+    /// recording that started then. A cue still up at `offset` starts at
+    /// zero.
+    pub fn since(&self, offset: Duration) -> Transcript {
+        let cues = self
+            .cues
+            .iter()
+            .filter(|cue| cue.end > offset)
+            .map(|cue| Cue {
+                start: cue.start.saturating_sub(offset),
+                end: cue.end - offset,
+                ..cue.clone()
+            })
+            .collect();
+        Transcript { cues }
+    }
+
+    /// Writes the transcript beside `recording`, with the same name and the
+    /// format's extension, and returns where.
+    pub fn save_beside(&self, recording: &Path, format: CaptionFormat) -> io::Result<PathBuf> {
+        let path = recording.with_extension(format.extension());
+        std::fs::write(&path, self.export(format))?;
+        Ok(path)
+    }
+}
+
+/// A new file name for a transcript with no recording to go beside.
+pub fn file_name(format: CaptionFormat) -> String {
+    let now = humantime::format_rfc3339_seconds(std::time::SystemTime::now());
+    format!(
+        "captions-{}.{}",
+        now.to_string().replace(':', "-"),
+        format.extension()
+    )
+}
+
+/// `HH:MM:SS,mmm`, with `.` before the milliseconds for WebVTT.
+fn timestamp(at: Duration, separator: char) -> String {
+    let millis = at.as_millis();
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+/// Cue text may not hold markup characters; escaping them also keeps a
+/// `-->` in the text from reading as a timing line.
+fn vtt_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_srt_and_vtt() {
+        let mut transcript = Transcript::default();
+        transcript.push(Cue {
+            start: Duration::from_millis(3_723_004),
+            end: Duration::from_millis(3_725_500),
+            speaker: Some("Alex".to_owned()),
+            text: "a <b> & c".to_owned(),
+        });
+        transcript.push(Cue {
+            start: Duration::from_millis(1500),
+            end: Duration::from_millis(2000),
+            speaker: None,
+            text: " hello ".to_owned(),
+        });
+        transcript.push(Cue {
+            start: Duration::ZERO,
+            end: Duration::ZERO,
+            speaker: None,
+            text: "  ".to_owned(),
+        });
+        assert_eq!(
+            transcript.export(CaptionFormat::Srt),
+            "1\n00:00:01,500 --> 00:00:02,000\nhello\n\n\
+             2\n01:02:03,004 --> 01:02:05,500\nAlex: a <b> & c\n\n"
+        );
+        assert_eq!(
+            transcript.export(CaptionFormat::Vtt),
+            "WEBVTT\n\n\
+             00:00:01.500 --> 00:00:02.000\nhello\n\n\
+             01:02:03.004 --> 01:02:05.500\n<v Alex>a &lt;b&gt; &amp; c\n\n"
+        );
+    }
+
+    fn caption(speaker: &str, text: &str) -> Caption {
+        Caption {
+            speaker: speaker.to_owned(),
+            text: text.to_owned(),
+        }
+    }
+
+    #[test]
+    fn times_cues_by_their_length() {
+        let start = Duration::from_secs(10);
+        let short = Cue::arrived(start, caption("Alex", "hi"));
+        assert_eq!(short.end, start + MIN_SHOWN + PER_CHAR * 2);
+        assert_eq!(short.speaker.as_deref(), Some("Alex"));
+        let long = Cue::arrived(start, caption("", &"word ".repeat(100)));
+        assert_eq!(long.end, start + MAX_SHOWN);
+        assert_eq!(long.speaker, None);
+
+        let sent = caption("Alex", "a \"quoted\" line");
+        assert_eq!(Caption::from_json(&sent.to_json()).unwrap(), sent);
+        assert!(Caption::from_json("plain text").is_err());
+    }
+
+    #[test]
+    fn retimes_from_a_recording_started_later() {
+        let mut transcript = Transcript::default();
+        for (start, text) in [(1, "before"), (9, "during"), (20, "after")] {
+            transcript.push(Cue {
+                start: Duration::from_secs(start),
+                end: Duration::from_secs(start + 3),
+                speaker: None,
+                text: text.to_owned(),
+            });
+        }
+        let since = transcript.since(Duration::from_secs(10));
+        let times: Vec<_> = since
+            .cues()
+            .iter()
+            .map(|cue| (cue.start.as_secs(), cue.end.as_secs(), cue.text.as_str()))
+            .collect();
+        assert_eq!(times, [(0, 2, "during"), (10, 13, "after")]);
+        assert_eq!(transcript.since(Duration::ZERO), transcript);
+    }
+
+    #[test]
+    fn saves_beside_the_recording() {
+        let dir = std::env::temp_dir().join(format!("captions-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let recording = dir.join("call-2026-10-14T16-20-56Z.webm");
+        let mut transcript = Transcript::default();
+        transcript.push(Cue::arrived(Duration::ZERO, caption("Alex", "hello")));
+
+        for format in CaptionFormat::ALL {
+            let saved = transcript.save_beside(&recording, format).unwrap();
+            assert_eq!(saved.parent(), Some(dir.as_path()));
+            assert_eq!(saved.file_stem(), recording.file_stem());
+            assert_eq!(saved.extension().unwrap(), format.extension());
+            assert_eq!(
+                std::fs::read_to_string(&saved).unwrap(),
+                transcript.export(format)
+            );
+        }
+        assert!(file_name(CaptionFormat::Vtt).starts_with("captions-"));
+        assert!(!file_name(CaptionFormat::Srt).contains(':'));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
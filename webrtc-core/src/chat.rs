@@ -0,0 +1,143 @@
+//! Text chat between the ends of a call. Messages go over their own
+//! pre-negotiated "chat" data channel while it is open and through the
+//! signaling server otherwise, so the same message can turn up twice; the
+//! log shows it once.
+
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::bounded::Footprint;
+
+pub const CHAT_LABEL: &str = "chat";
+pub const CHAT_STREAM_ID: u16 = 2;
+
+/// Messages the log holds before dropping the oldest.
+const MAX_MESSAGES: usize = 500;
+
+/// Who plain text on the chat channel is from, having no name with it.
+const UNNAMED: &str = "peer";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    /// Random, and how repeats are recognised.
+    pub id: String,
+    /// The sender's display name.
+    pub name: String,
+    pub text: String,
+    /// Milliseconds since the Unix epoch, by the sender's clock.
+    pub sent_at: u64,
+}
+
+impl ChatMessage {
+    pub fn new(name: &str, text: &str) -> Self {
+        let sent_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        Self {
+            id: format!("{:016x}", rand::random::<u64>()),
+            name: name.to_owned(),
+            text: text.to_owned(),
+            sent_at,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("chat messages always serialize")
+    }
+
+    /// A message as it came over the chat channel: ours as JSON, or plain
+    /// text from an app that sends only that, such as a browser page.
+    pub fn from_text(text: &str) -> Self {
+        serde_json::from_str(text).unwrap_or_else(|_| Self::new(UNNAMED, text))
+    }
+}
+
+impl Footprint for ChatMessage {
+    fn footprint(&self) -> usize {
+        self.id.len() + self.name.len() + self.text.len()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatEntry {
+    pub message: ChatMessage,
+    /// Sent from here rather than received.
+    pub local: bool,
+}
+
+/// The conversation so far, in the order the messages were sent.
+#[derive(Debug, Default)]
+pub struct ChatLog {
+    entries: Vec<ChatEntry>,
+    /// Every id ever logged, so a repeat of a message that has since been
+    /// dropped stays out too.
+    seen: HashSet<String>,
+}
+
+impl ChatLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `message` unless it is already in the log. Returns whether it
+    /// was new.
+    pub fn push(&mut self, message: ChatMessage, local: bool) -> bool {
+        if !self.seen.insert(message.id.clone()) {
+            return false;
+        }
+        // History replayed by the server arrives after newer messages.
+        let at = self
+            .entries
+            .partition_point(|entry| entry.message.sent_at <= message.sent_at);
+        self.entries.insert(at, ChatEntry { message, local });
+        if self.entries.len() > MAX_MESSAGES {
+            self.entries.remove(0);
+        }
+        true
+    }
+
+    pub fn entries(&self) -> &[ChatEntry] {
+        &self.entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: &str, sent_at: u64) -> ChatMessage {
+        ChatMessage {
+            id: id.to_owned(),
+            name: "alice".to_owned(),
+            text: id.to_owned(),
+            sent_at,
+        }
+    }
+
+    #[test]
+    fn keeps_one_copy_in_send_order() {
+        let mut log = ChatLog::new();
+        assert!(log.push(message("b", 20), false));
+        assert!(log.push(message("a", 10), true));
+        assert!(!log.push(message("b", 20), false));
+        let ids: Vec<_> = log
+            .entries()
+            .iter()
+            .map(|e| e.message.id.as_str())
+            .collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert!(log.entries()[0].local);
+    }
+
+    #[test]
+    fn reads_its_own_json_and_plain_text() {
+        let sent = message("a", 10);
+        assert_eq!(ChatMessage::from_text(&sent.to_json()), sent);
+        let plain = ChatMessage::from_text("hello from a browser");
+        assert_eq!(plain.name, "peer");
+        assert_eq!(plain.text, "hello from a browser");
+        assert_ne!(plain.id, ChatMessage::from_text("hello from a browser").id);
+    }
+}
@@ -0,0 +1,119 @@
+//! The last few seconds of remote video, kept small enough to hold in
+//! memory and exported as an animated GIF for bug reports.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use thiserror::Error;
+use tokio::time::{Duration, Instant};
+
+use crate::thumbnails::downscale;
+use crate::video::VideoFrame;
+
+/// How much video a clip covers.
+pub const CLIP_LENGTH: Duration = Duration::from_secs(30);
+/// Frames kept per second; about 32 MB of RGBA at `CLIP_WIDTH` for a full
+/// clip of 16:9 video.
+pub const CLIP_FPS: u32 = 8;
+/// Width the kept frames are scaled down to.
+pub const CLIP_WIDTH: usize = 240;
+
+/// Quantization effort, 1 (best) to 30 (fastest).
+const QUANTIZE_SPEED: i32 = 10;
+
+#[derive(Debug, Error)]
+pub enum ClipError {
+    #[error("no video to clip yet")]
+    Empty,
+    #[error("cannot write clip: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("cannot encode GIF: {0}")]
+    Encoding(#[from] gif::EncodingError),
+}
+
+pub struct ClipBuffer {
+    frames: VecDeque<(Instant, VideoFrame)>,
+    last: Option<Instant>,
+}
+
+impl Default for ClipBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClipBuffer {
+    pub fn new() -> Self {
+        Self {
+            frames: VecDeque::new(),
+            last: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Seconds of video currently held.
+    pub fn covered(&self) -> Duration {
+        match (self.frames.front(), self.frames.back()) {
+            (Some((first, _)), Some((last, _))) => last.duration_since(*first),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Keeps a scaled-down copy of `frame` if it is time for the next clip
+    /// frame, dropping those older than `CLIP_LENGTH`.
+    pub fn offer(&mut self, frame: &VideoFrame) {
+        let now = Instant::now();
+        let step = Duration::from_secs(1) / CLIP_FPS;
+        if self
+            .last
+            .is_some_and(|last| now.duration_since(last) < step)
+        {
+            return;
+        }
+        self.last = Some(now);
+        while self
+            .frames
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > CLIP_LENGTH)
+        {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((now, downscale(frame, CLIP_WIDTH)));
+    }
+
+    /// The frames to export, oldest first.
+    pub fn frames(&self) -> Vec<VideoFrame> {
+        self.frames.iter().map(|(_, frame)| frame.clone()).collect()
+    }
+}
+
+/// Writes `frames`, taken `CLIP_FPS` apart, to `path` as a looping GIF.
+/// Slow enough to belong on a blocking thread.
+pub fn export_gif(frames: Vec<VideoFrame>, path: &Path) -> Result<(), ClipError> {
+    let width = frames
+        .iter()
+        .map(|f| f.width)
+        .max()
+        .ok_or(ClipError::Empty)?;
+    let height = frames.iter().map(|f| f.height).max().unwrap_or_default();
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = gif::Encoder::new(file, width as u16, height as u16, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    let delay = (100 / CLIP_FPS) as u16;
+    for mut frame in frames {
+        let mut gif_frame = gif::Frame::from_rgba_speed(
+            frame.width as u16,
+            frame.height as u16,
+            &mut frame.rgba,
+            QUANTIZE_SPEED,
+        );
+        gif_frame.delay = delay;
+        encoder.write_frame(&gif_frame)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+//! Keeping the clipboards at both ends of a call the same: text copied on
+//! one goes over a pre-negotiated "clipboard" data channel and is set on
+//! the other. Setting it there is itself a change, which must not be sent
+//! back, so this remembers the last text either way.
+
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+use crate::framing::MAX_REASSEMBLED_BYTES;
+
+pub const CLIPBOARD_LABEL: &str = "clipboard";
+pub const CLIPBOARD_STREAM_ID: u16 = 4;
+
+/// Copies the log holds before dropping the oldest.
+const MAX_ENTRIES: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClipboardEntry {
+    pub at: SystemTime,
+    /// Copied here and sent, rather than received.
+    pub outgoing: bool,
+    pub text: String,
+}
+
+/// What came of the clipboard holding new text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Copied {
+    /// It is what was last sent or received.
+    Unchanged,
+    Send(String),
+    /// More bytes than the peer puts back together, so it stays here.
+    TooLarge(usize),
+}
+
+#[derive(Debug, Default)]
+pub struct ClipboardSync {
+    last: Option<String>,
+    log: VecDeque<ClipboardEntry>,
+}
+
+impl ClipboardSync {
+    /// Takes what the clipboard held when syncing started as known, so
+    /// turning it on does not send that.
+    pub fn start(&mut self, text: Option<String>) {
+        self.last = text;
+    }
+
+    /// The clipboard here holds `text`.
+    pub fn copied(&mut self, text: String) -> Copied {
+        if text.is_empty() || self.last.as_ref() == Some(&text) {
+            return Copied::Unchanged;
+        }
+        self.last = Some(text.clone());
+        if text.len() > MAX_REASSEMBLED_BYTES {
+            return Copied::TooLarge(text.len());
+        }
+        Copied::Send(text)
+    }
+
+    /// Logs `text` as having gone to the peer.
+    pub fn sent(&mut self, text: String) {
+        self.push(true, text);
+    }
+
+    /// The peer copied `text`. Returns whether the clipboard here needs
+    /// setting to it.
+    pub fn received(&mut self, text: String) -> bool {
+        if text.is_empty() || self.last.as_ref() == Some(&text) {
+            return false;
+        }
+        self.last = Some(text.clone());
+        self.push(false, text);
+        true
+    }
+
+    pub fn log(&self) -> impl DoubleEndedIterator<Item = &ClipboardEntry> {
+        self.log.iter()
+    }
+
+    fn push(&mut self, outgoing: bool, text: String) {
+        if self.log.len() == MAX_ENTRIES {
+            self.log.pop_front();
+        }
+        self.log.push_back(ClipboardEntry {
+            at: SystemTime::now(),
+            outgoing,
+            text,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn does_not_send_back_what_it_received() {
+        let mut sync = ClipboardSync::default();
+        sync.start(Some("before".to_owned()));
+        assert_eq!(sync.copied("before".to_owned()), Copied::Unchanged);
+        assert_eq!(
+            sync.copied("mine".to_owned()),
+            Copied::Send("mine".to_owned())
+        );
+        sync.sent("mine".to_owned());
+
+        assert!(sync.received("theirs".to_owned()));
+        // Setting the clipboard to it shows up as a copy here.
+        assert_eq!(sync.copied("theirs".to_owned()), Copied::Unchanged);
+        assert!(!sync.received("theirs".to_owned()));
+
+        let huge = "x".repeat(MAX_REASSEMBLED_BYTES + 1);
+        assert_eq!(
+            sync.copied(huge.clone()),
+            Copied::TooLarge(MAX_REASSEMBLED_BYTES + 1)
+        );
+        assert_eq!(sync.copied(huge), Copied::Unchanged);
+
+        let log: Vec<_> = sync.log().map(|e| (e.outgoing, e.text.as_str())).collect();
+        assert_eq!(log, [(true, "mine"), (false, "theirs")]);
+    }
+}
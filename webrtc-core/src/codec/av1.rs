@@ -0,0 +1,180 @@
+//! AV1 encoding through rav1e at its fastest preset with no lookahead,
+//! and decoding through libdav1d without frame threading, so neither
+//! holds frames back.
+
+use dav1d::{PixelLayout, PlanarImageComponent};
+use log::info;
+use rav1e::prelude::{
+    ChromaSampling, Config, Context, EncoderStatus, FrameParameters, FrameTypeOverride, Rational,
+    SceneDetectionSpeed,
+};
+
+use super::{i420_strided_to_rgba, rgba_to_i420, EncoderConfig};
+use crate::video::{VideoDecoder, VideoEncoder, VideoFrame};
+
+/// rav1e's speed presets go from 0, slowest, to 10.
+const SPEED: u8 = 10;
+
+fn threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |threads| threads.get().min(4))
+}
+
+pub struct Av1Encoder {
+    context: Context<u8>,
+    config: rav1e::EncoderConfig,
+    /// The next frame must be a keyframe, as after a size change.
+    keyframe: bool,
+}
+
+impl Av1Encoder {
+    pub fn new(config: &EncoderConfig) -> Option<Self> {
+        let mut av1_config = rav1e::EncoderConfig::with_speed_preset(SPEED);
+        av1_config.width = config.width;
+        av1_config.height = config.height;
+        av1_config.bit_depth = 8;
+        av1_config.chroma_sampling = ChromaSampling::Cs420;
+        av1_config.time_base = Rational::new(1, config.frame_rate.round().max(1.0) as u64);
+        av1_config.low_latency = true;
+        av1_config.error_resilient = true;
+        av1_config.bitrate = config.bitrate_bps.min(i32::MAX as u32) as i32;
+        av1_config.set_key_frame_interval(0, u64::from(config.keyframe_frames()));
+        av1_config.speed_settings.rdo_lookahead_frames = 1;
+        av1_config.speed_settings.scene_detection_mode = SceneDetectionSpeed::None;
+        let context = Self::open(&av1_config)?;
+        Some(Self {
+            context,
+            config: av1_config,
+            keyframe: true,
+        })
+    }
+
+    fn open(config: &rav1e::EncoderConfig) -> Option<Context<u8>> {
+        let context = Config::new()
+            .with_encoder_config(config.clone())
+            .with_threads(threads())
+            .new_context();
+        match context {
+            Ok(context) => Some(context),
+            Err(err) => {
+                info!("Failed to create AV1 encoder: {}", err);
+                None
+            }
+        }
+    }
+}
+
+impl VideoEncoder for Av1Encoder {
+    fn encode(&mut self, frame: &VideoFrame) -> Option<Vec<u8>> {
+        if (frame.width, frame.height) != (self.config.width, self.config.height) {
+            self.config.width = frame.width;
+            self.config.height = frame.height;
+            self.context = Self::open(&self.config)?;
+            self.keyframe = true;
+        }
+        let i420 = rgba_to_i420(&frame.rgba, frame.width, frame.height);
+        let chroma_width = frame.width.div_ceil(2);
+        let (y, chroma) = i420.split_at(frame.width * frame.height);
+        let (u, v) = chroma.split_at(chroma.len() / 2);
+        let mut picture = self.context.new_frame();
+        for (plane, (data, stride)) in
+            picture
+                .planes
+                .iter_mut()
+                .zip([(y, frame.width), (u, chroma_width), (v, chroma_width)])
+        {
+            plane.copy_from_raw_u8(data, stride, 1);
+        }
+        let sent = if self.keyframe {
+            let parameters = FrameParameters {
+                frame_type_override: FrameTypeOverride::Key,
+                ..Default::default()
+            };
+            self.context.send_frame((picture, parameters))
+        } else {
+            self.context.send_frame(picture)
+        };
+        if let Err(err) = sent {
+            info!("AV1 encode error: {}", err);
+            return None;
+        }
+        self.keyframe = false;
+
+        let mut data = vec![];
+        loop {
+            match self.context.receive_packet() {
+                Ok(packet) => data.extend_from_slice(&packet.data),
+                Err(EncoderStatus::Encoded) => continue,
+                Err(EncoderStatus::NeedMoreData) => break,
+                Err(err) => {
+                    info!("AV1 encode error: {}", err);
+                    break;
+                }
+            }
+        }
+        Some(data).filter(|data| !data.is_empty())
+    }
+}
+
+pub struct Av1Decoder(dav1d::Decoder);
+
+impl Av1Decoder {
+    pub fn new() -> Option<Self> {
+        let mut settings = dav1d::Settings::new();
+        settings.set_n_threads(threads() as u32);
+        settings.set_max_frame_delay(1);
+        match dav1d::Decoder::with_settings(&settings) {
+            Ok(decoder) => Some(Self(decoder)),
+            Err(err) => {
+                info!("Failed to create AV1 decoder: {}", err);
+                None
+            }
+        }
+    }
+}
+
+impl VideoDecoder for Av1Decoder {
+    fn decode(&mut self, access_unit: &[u8]) -> Option<VideoFrame> {
+        match self.0.send_data(access_unit.to_vec(), None, None, None) {
+            // Again: the rest waits for the pictures below to be taken.
+            Ok(()) | Err(dav1d::Error::Again) => {}
+            Err(err) => {
+                info!("AV1 decode error: {}", err);
+                return None;
+            }
+        }
+        let mut decoded = None;
+        loop {
+            let picture = match self.0.get_picture() {
+                Ok(picture) => picture,
+                Err(dav1d::Error::Again) => break,
+                Err(err) => {
+                    info!("AV1 decode error: {}", err);
+                    break;
+                }
+            };
+            // 8-bit 4:2:0 only, what WebRTC senders use.
+            if picture.pixel_layout() == PixelLayout::I420 && picture.bit_depth() == 8 {
+                let (width, height) = (picture.width() as usize, picture.height() as usize);
+                let [y, u, v] = [
+                    PlanarImageComponent::Y,
+                    PlanarImageComponent::U,
+                    PlanarImageComponent::V,
+                ]
+                .map(|component| picture.plane(component));
+                let strides = [
+                    picture.stride(PlanarImageComponent::Y) as usize,
+                    picture.stride(PlanarImageComponent::U) as usize,
+                ];
+                decoded = Some(VideoFrame {
+                    width,
+                    height,
+                    rgba: i420_strided_to_rgba([&y, &u, &v], strides, width, height),
+                });
+            }
+            // Again here too is data that still waits; anything else has
+            // been logged by the send above once already.
+            let _ = self.0.send_pending_data();
+        }
+        decoded
+    }
+}
@@ -0,0 +1,167 @@
+//! Taking AV1 back out of RTP, which webrtc-rs can only put in. Payloads
+//! (AV1 RTP spec, section 4) carry OBUs without their size fields, and a
+//! large OBU is split over several packets; this puts each one back
+//! together and writes it out with a size field, the low-overhead format
+//! decoders read.
+
+use bytes::{Bytes, BytesMut};
+use webrtc::rtp::packetizer::Depacketizer;
+
+/// Set on the first OBU element when it continues one from the last
+/// packet.
+const Z: u8 = 0x80;
+/// Set on the last OBU element when the next packet continues it.
+const Y: u8 = 0x40;
+const OBU_HAS_SIZE: u8 = 0x02;
+const OBU_HAS_EXTENSION: u8 = 0x04;
+/// Dropped by senders anyway; decoders find their own boundaries.
+const OBU_TEMPORAL_DELIMITER: u8 = 2;
+const OBU_TILE_LIST: u8 = 8;
+
+#[derive(Debug, Default, Clone)]
+pub struct Av1Packet {
+    /// The part of an OBU that a later packet finishes.
+    partial: Option<BytesMut>,
+}
+
+impl Av1Packet {
+    /// Appends a whole OBU, as its header, a size and its payload.
+    fn finish(obu: &[u8], out: &mut BytesMut) {
+        let Some(&header) = obu.first() else {
+            return;
+        };
+        let obu_type = (header >> 3) & 0x0f;
+        if obu_type == OBU_TEMPORAL_DELIMITER || obu_type == OBU_TILE_LIST {
+            return;
+        }
+        if header & OBU_HAS_SIZE != 0 {
+            out.extend_from_slice(obu);
+            return;
+        }
+        let header_len = if header & OBU_HAS_EXTENSION != 0 {
+            2
+        } else {
+            1
+        };
+        let Some(payload) = obu.get(header_len..) else {
+            return;
+        };
+        out.extend_from_slice(&[header | OBU_HAS_SIZE]);
+        out.extend_from_slice(&obu[1..header_len]);
+        write_leb128(payload.len(), out);
+        out.extend_from_slice(payload);
+    }
+}
+
+impl Depacketizer for Av1Packet {
+    fn depacketize(&mut self, packet: &Bytes) -> Result<Bytes, webrtc::rtp::Error> {
+        let (&aggregation, mut rest) = packet
+            .split_first()
+            .ok_or(webrtc::rtp::Error::ErrShortPacket)?;
+        // W: how many elements there are when all but the last carry a
+        // length, or 0 when every one does.
+        let count = usize::from((aggregation >> 4) & 0x03);
+        let mut out = BytesMut::new();
+        let mut index = 0;
+        while !rest.is_empty() {
+            index += 1;
+            let last = index == count;
+            let element = if last {
+                std::mem::take(&mut rest)
+            } else {
+                let (len, read) = read_leb128(rest).ok_or(webrtc::rtp::Error::ErrShortPacket)?;
+                let element = rest
+                    .get(read..read + len)
+                    .ok_or(webrtc::rtp::Error::ErrShortPacket)?;
+                rest = &rest[read + len..];
+                element
+            };
+            let obu = if index == 1 && aggregation & Z != 0 {
+                // A continuation whose start was lost is no use.
+                let Some(mut partial) = self.partial.take() else {
+                    continue;
+                };
+                partial.extend_from_slice(element);
+                partial
+            } else {
+                // So is a start whose end was.
+                self.partial = None;
+                BytesMut::from(element)
+            };
+            if rest.is_empty() && aggregation & Y != 0 {
+                self.partial = Some(obu);
+            } else {
+                Self::finish(&obu, &mut out);
+            }
+            if last {
+                break;
+            }
+        }
+        Ok(out.freeze())
+    }
+
+    fn is_partition_head(&self, payload: &Bytes) -> bool {
+        payload
+            .first()
+            .is_some_and(|aggregation| aggregation & Z == 0)
+    }
+
+    fn is_partition_tail(&self, marker: bool, _payload: &Bytes) -> bool {
+        marker
+    }
+}
+
+/// A LEB128 value and how many bytes it took.
+fn read_leb128(bytes: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0usize;
+    for (index, byte) in bytes.iter().enumerate().take(8) {
+        value |= usize::from(byte & 0x7f) << (index * 7);
+        if byte & 0x80 == 0 {
+            return Some((value, index + 1));
+        }
+    }
+    None
+}
+
+fn write_leb128(mut value: usize, out: &mut BytesMut) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.extend_from_slice(&[byte]);
+            return;
+        }
+        out.extend_from_slice(&[byte | 0x80]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reassembles_fragmented_obus_with_sizes() {
+        // A sequence header (type 1) and the first 200 bytes of a frame
+        // (type 6), then the frame's last 100 bytes.
+        let sequence_header = [0x08, 0xaa, 0xbb];
+        let frame: Vec<u8> = std::iter::once(0x30).chain([0x55; 299]).collect();
+        let mut first = vec![Y | (2 << 4), sequence_header.len() as u8];
+        first.extend_from_slice(&sequence_header);
+        first.extend_from_slice(&frame[..200]);
+        let mut second = vec![Z | (1 << 4)];
+        second.extend_from_slice(&frame[200..]);
+
+        let mut depacketizer = Av1Packet::default();
+        assert!(depacketizer.is_partition_head(&Bytes::from(first.clone())));
+        assert!(!depacketizer.is_partition_head(&Bytes::from(second.clone())));
+        let start = depacketizer.depacketize(&Bytes::from(first)).unwrap();
+        assert_eq!(&start[..], &[0x0a, 0x02, 0xaa, 0xbb]);
+        let end = depacketizer.depacketize(&Bytes::from(second)).unwrap();
+        assert_eq!(&end[..3], &[0x32, 0xab, 0x02]);
+        assert_eq!(end.len(), 3 + 299);
+
+        // A continuation of nothing is dropped.
+        let orphan = Bytes::from_static(&[Z | (1 << 4), 0x55, 0x55]);
+        assert!(depacketizer.depacketize(&orphan).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,169 @@
+//! H.264 through OpenH264, built from source. It sends constrained
+//! baseline, which every H.264 decoder takes, at the level the remote
+//! asked for.
+
+use std::borrow::Cow;
+use std::ffi::c_int;
+
+use log::info;
+use openh264::decoder::Decoder;
+use openh264::encoder::{BitRate, Encoder, FrameRate, IntraFramePeriod, Level, Profile};
+use openh264::formats::{RgbaSliceU8, YUVBuffer, YUVSource};
+use openh264::OpenH264API;
+use openh264_sys2::{SBitrateInfo, ENCODER_OPTION_BITRATE, SPATIAL_LAYER_ALL};
+
+use super::{EncoderConfig, ProfileLevelId};
+use crate::video::{self, VideoDecoder, VideoEncoder, VideoFrame};
+
+pub struct H264Encoder {
+    encoder: Encoder,
+    /// The level's largest picture, in macroblocks.
+    max_macroblocks: Option<usize>,
+    bitrate_bps: u32,
+    /// The picture size last encoded; OpenH264 starts over at its first
+    /// bitrate when it changes.
+    size: (usize, usize),
+}
+
+impl H264Encoder {
+    pub fn new(config: &EncoderConfig) -> Option<Self> {
+        let profile = config.h264_profile.unwrap_or(ProfileLevelId::DEFAULT);
+        let encoder_config = openh264::encoder::EncoderConfig::new()
+            .profile(Profile::Baseline)
+            .level(level(profile.level_idc))
+            .bitrate(BitRate::from_bps(config.bitrate_bps))
+            .max_frame_rate(FrameRate::from_hz(config.frame_rate))
+            .intra_frame_period(IntraFramePeriod::from_num_frames(config.keyframe_frames()));
+        match Encoder::with_api_config(OpenH264API::from_source(), encoder_config) {
+            Ok(encoder) => Some(Self {
+                encoder,
+                max_macroblocks: profile.max_macroblocks(),
+                bitrate_bps: config.bitrate_bps,
+                size: (0, 0),
+            }),
+            Err(err) => {
+                info!("Failed to create H.264 encoder: {:?}", err);
+                None
+            }
+        }
+    }
+
+    fn apply_bitrate(&mut self) {
+        let mut info = SBitrateInfo {
+            iLayer: SPATIAL_LAYER_ALL,
+            iBitrate: self.bitrate_bps.min(c_int::MAX as u32) as c_int,
+        };
+        // SAFETY: the bitrate option takes a pointer to an SBitrateInfo,
+        // read during the call.
+        let err = unsafe {
+            self.encoder.raw_api().set_option(
+                ENCODER_OPTION_BITRATE,
+                (&mut info as *mut SBitrateInfo).cast(),
+            )
+        };
+        if err != 0 {
+            info!("Failed to change the H.264 bitrate: {}", err);
+        }
+    }
+}
+
+/// `frame`, scaled down by a whole factor until it fits a level's largest
+/// picture of `max_macroblocks`.
+pub(super) fn fit(frame: &VideoFrame, max_macroblocks: Option<usize>) -> Cow<'_, VideoFrame> {
+    let Some(max) = max_macroblocks else {
+        return Cow::Borrowed(frame);
+    };
+    let macroblocks = |factor: usize| {
+        ((frame.width / factor) & !1).div_ceil(16) * ((frame.height / factor) & !1).div_ceil(16)
+    };
+    match (1..=16).find(|factor| macroblocks(*factor) <= max) {
+        Some(1) => Cow::Borrowed(frame),
+        Some(factor) => Cow::Owned(video::downscale(frame, factor)),
+        None => Cow::Owned(video::downscale(frame, 16)),
+    }
+}
+
+/// OpenH264's level for a `level_idc`, the highest for unknown ones.
+fn level(level_idc: u8) -> Level {
+    match level_idc {
+        0x09 => Level::Level_1_B,
+        0x0a => Level::Level_1_0,
+        0x0b => Level::Level_1_1,
+        0x0c => Level::Level_1_2,
+        0x0d => Level::Level_1_3,
+        0x14 => Level::Level_2_0,
+        0x15 => Level::Level_2_1,
+        0x16 => Level::Level_2_2,
+        0x1e => Level::Level_3_0,
+        0x1f => Level::Level_3_1,
+        0x20 => Level::Level_3_2,
+        0x28 => Level::Level_4_0,
+        0x29 => Level::Level_4_1,
+        0x2a => Level::Level_4_2,
+        0x32 => Level::Level_5_0,
+        0x33 => Level::Level_5_1,
+        _ => Level::Level_5_2,
+    }
+}
+
+impl VideoEncoder for H264Encoder {
+    fn encode(&mut self, frame: &VideoFrame) -> Option<Vec<u8>> {
+        let frame = fit(frame, self.max_macroblocks);
+        let rgba = RgbaSliceU8::new(&frame.rgba, (frame.width, frame.height));
+        let yuv = YUVBuffer::from_rgba8_source(rgba);
+        let resized = (frame.width, frame.height) != self.size;
+        self.size = (frame.width, frame.height);
+        let data = match self.encoder.encode(&yuv) {
+            Ok(bitstream) => Some(bitstream.to_vec()).filter(|data| !data.is_empty()),
+            Err(err) => {
+                info!("H.264 encode error: {:?}", err);
+                None
+            }
+        };
+        if resized {
+            self.apply_bitrate();
+        }
+        data
+    }
+
+    fn set_bitrate(&mut self, bitrate_bps: u32) {
+        if bitrate_bps != self.bitrate_bps {
+            self.bitrate_bps = bitrate_bps;
+            self.apply_bitrate();
+        }
+    }
+}
+
+pub struct H264Decoder(Decoder);
+
+impl H264Decoder {
+    pub fn new() -> Option<Self> {
+        match Decoder::new() {
+            Ok(decoder) => Some(Self(decoder)),
+            Err(err) => {
+                info!("Failed to create H.264 decoder: {:?}", err);
+                None
+            }
+        }
+    }
+}
+
+impl VideoDecoder for H264Decoder {
+    fn decode(&mut self, access_unit: &[u8]) -> Option<VideoFrame> {
+        let yuv = match self.0.decode(access_unit) {
+            Ok(yuv) => yuv?,
+            Err(err) => {
+                info!("H.264 decode error: {:?}", err);
+                return None;
+            }
+        };
+        let (width, height) = yuv.dimensions();
+        let mut rgba = vec![0; width * height * 4];
+        yuv.write_rgba8(&mut rgba);
+        Some(VideoFrame {
+            width,
+            height,
+            rgba,
+        })
+    }
+}
@@ -0,0 +1,353 @@
+//! H.264 on the GPU through FFmpeg's hardware encoders, tried in turn for
+//! the platform, so a 4K screen share does not take whole CPU cores. Each
+//! goes out in the same constrained baseline as OpenH264 at the remote's
+//! level, and `FallbackEncoder` moves over to OpenH264 when one fails
+//! partway through a call.
+
+use std::ffi::c_int;
+use std::fmt;
+use std::ptr;
+
+use ffmpeg_next as ffmpeg;
+use ffmpeg_next::codec::profile::{Profile, H264};
+use ffmpeg_next::ffi;
+use ffmpeg_next::format::Pixel;
+use log::info;
+
+use super::h264::{self, H264Encoder};
+use super::{rgba_to_i420, EncoderConfig, ProfileLevelId};
+use crate::video::{VideoEncoder, VideoFrame};
+
+/// How far a hardware encoder's bitrate drifts before it opens again.
+const REOPEN_BITRATE_CHANGE: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HardwareBackend {
+    /// Intel and AMD GPUs on Linux.
+    Vaapi,
+    /// NVIDIA GPUs on Linux and Windows.
+    Nvenc,
+    VideoToolbox,
+    /// Whatever the GPU vendor's MediaFoundation transform is on Windows.
+    MediaFoundation,
+}
+
+impl HardwareBackend {
+    /// The backends to try on this platform, in order.
+    pub fn for_platform() -> &'static [HardwareBackend] {
+        if cfg!(target_os = "macos") {
+            &[HardwareBackend::VideoToolbox]
+        } else if cfg!(target_os = "windows") {
+            &[HardwareBackend::Nvenc, HardwareBackend::MediaFoundation]
+        } else {
+            &[HardwareBackend::Nvenc, HardwareBackend::Vaapi]
+        }
+    }
+
+    fn encoder_name(self) -> &'static str {
+        match self {
+            HardwareBackend::Vaapi => "h264_vaapi",
+            HardwareBackend::Nvenc => "h264_nvenc",
+            HardwareBackend::VideoToolbox => "h264_videotoolbox",
+            HardwareBackend::MediaFoundation => "h264_mf",
+        }
+    }
+
+    /// Private options for the lowest latency each has; ones an older
+    /// FFmpeg does not know are left unused rather than failing.
+    fn options(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            HardwareBackend::Vaapi => &[("rc_mode", "CBR"), ("async_depth", "1")],
+            HardwareBackend::Nvenc => &[
+                ("preset", "p1"),
+                ("tune", "ull"),
+                ("rc", "cbr"),
+                ("zerolatency", "1"),
+            ],
+            // Without allow_sw it fails where there is no hardware encoder,
+            // leaving the fallback to OpenH264.
+            HardwareBackend::VideoToolbox => &[("realtime", "1"), ("allow_sw", "0")],
+            HardwareBackend::MediaFoundation => &[
+                ("hw_encoding", "1"),
+                ("rate_control", "cbr"),
+                ("scenario", "live_streaming"),
+            ],
+        }
+    }
+}
+
+impl fmt::Display for HardwareBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HardwareBackend::Vaapi => "VAAPI",
+            HardwareBackend::Nvenc => "NVENC",
+            HardwareBackend::VideoToolbox => "VideoToolbox",
+            HardwareBackend::MediaFoundation => "MediaFoundation",
+        })
+    }
+}
+
+fn check(code: c_int) -> Result<(), ffmpeg::Error> {
+    if code < 0 {
+        Err(ffmpeg::Error::from(code))
+    } else {
+        Ok(())
+    }
+}
+
+/// A pool of frames on the GPU, which VAAPI encodes from.
+struct GpuFrames(*mut ffi::AVBufferRef);
+
+// Only ever used by the encoder that owns it.
+unsafe impl Send for GpuFrames {}
+
+impl GpuFrames {
+    fn vaapi(width: u32, height: u32) -> Result<Self, ffmpeg::Error> {
+        unsafe {
+            let mut device = ptr::null_mut();
+            check(ffi::av_hwdevice_ctx_create(
+                &mut device,
+                ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_VAAPI,
+                ptr::null(),
+                ptr::null_mut(),
+                0,
+            ))?;
+            let frames = ffi::av_hwframe_ctx_alloc(device);
+            ffi::av_buffer_unref(&mut device);
+            if frames.is_null() {
+                return Err(ffmpeg::Error::Other {
+                    errno: ffmpeg::error::ENOMEM,
+                });
+            }
+            let frames = GpuFrames(frames);
+            let context = (*frames.0).data.cast::<ffi::AVHWFramesContext>();
+            (*context).format = ffi::AVPixelFormat::AV_PIX_FMT_VAAPI;
+            (*context).sw_format = ffi::AVPixelFormat::AV_PIX_FMT_NV12;
+            (*context).width = width as c_int;
+            (*context).height = height as c_int;
+            (*context).initial_pool_size = 4;
+            check(ffi::av_hwframe_ctx_init(frames.0))?;
+            Ok(frames)
+        }
+    }
+
+    /// A frame from the pool holding what `frame` does.
+    fn upload(&self, frame: &ffmpeg::frame::Video) -> Result<ffmpeg::frame::Video, ffmpeg::Error> {
+        let mut gpu = ffmpeg::frame::Video::empty();
+        unsafe {
+            check(ffi::av_hwframe_get_buffer(self.0, gpu.as_mut_ptr(), 0))?;
+            check(ffi::av_hwframe_transfer_data(
+                gpu.as_mut_ptr(),
+                frame.as_ptr(),
+                0,
+            ))?;
+        }
+        gpu.set_pts(frame.pts());
+        Ok(gpu)
+    }
+}
+
+impl Drop for GpuFrames {
+    fn drop(&mut self) {
+        unsafe { ffi::av_buffer_unref(&mut self.0) };
+    }
+}
+
+/// One FFmpeg hardware encoder, opened for one picture size.
+struct FfmpegEncoder {
+    backend: HardwareBackend,
+    encoder: ffmpeg::encoder::Video,
+    gpu_frames: Option<GpuFrames>,
+    width: u32,
+    height: u32,
+    pts: i64,
+}
+
+impl FfmpegEncoder {
+    fn open(
+        backend: HardwareBackend,
+        config: &EncoderConfig,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, ffmpeg::Error> {
+        ffmpeg::init()?;
+        let codec = ffmpeg::encoder::find_by_name(backend.encoder_name())
+            .ok_or(ffmpeg::Error::EncoderNotFound)?;
+        let mut video = ffmpeg::codec::Context::new_with_codec(codec)
+            .encoder()
+            .video()?;
+        let frame_rate = config.frame_rate.round().max(1.0) as i32;
+        video.set_width(width);
+        video.set_height(height);
+        video.set_time_base((1, frame_rate));
+        video.set_frame_rate(Some((frame_rate, 1)));
+        video.set_bit_rate(config.bitrate_bps as usize);
+        video.set_gop(config.keyframe_frames());
+        video.set_max_b_frames(0);
+        let level = config
+            .h264_profile
+            .unwrap_or(ProfileLevelId::DEFAULT)
+            .level_idc;
+        unsafe {
+            let context = video.as_mut_ptr();
+            (*context).profile = Profile::H264(H264::ConstrainedBaseline).into();
+            (*context).level = c_int::from(level);
+        }
+        let gpu_frames = if backend == HardwareBackend::Vaapi {
+            let frames = GpuFrames::vaapi(width, height)?;
+            video.set_format(Pixel::VAAPI);
+            // The codec context keeps a reference of its own.
+            unsafe { (*video.as_mut_ptr()).hw_frames_ctx = ffi::av_buffer_ref(frames.0) };
+            Some(frames)
+        } else {
+            video.set_format(Pixel::NV12);
+            None
+        };
+        let mut options = ffmpeg::Dictionary::new();
+        for (key, value) in backend.options() {
+            options.set(key, value);
+        }
+        Ok(Self {
+            backend,
+            encoder: video.open_with(options)?,
+            gpu_frames,
+            width,
+            height,
+            pts: 0,
+        })
+    }
+
+    fn encode(&mut self, frame: &VideoFrame) -> Result<Vec<u8>, ffmpeg::Error> {
+        let mut nv12 = ffmpeg::frame::Video::new(Pixel::NV12, self.width, self.height);
+        write_nv12(frame, &mut nv12);
+        nv12.set_pts(Some(self.pts));
+        self.pts += 1;
+        match &self.gpu_frames {
+            Some(gpu_frames) => self.encoder.send_frame(&gpu_frames.upload(&nv12)?)?,
+            None => self.encoder.send_frame(&nv12)?,
+        }
+        let mut data = vec![];
+        let mut packet = ffmpeg::Packet::empty();
+        loop {
+            match self.encoder.receive_packet(&mut packet) {
+                Ok(()) => data.extend_from_slice(packet.data().unwrap_or_default()),
+                Err(ffmpeg::Error::Other { errno }) if errno == ffmpeg::error::EAGAIN => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(data)
+    }
+}
+
+/// Converts `frame` into `nv12`, whose even size may leave out the last
+/// column or row of an odd-sized frame.
+fn write_nv12(frame: &VideoFrame, nv12: &mut ffmpeg::frame::Video) {
+    let i420 = rgba_to_i420(&frame.rgba, frame.width, frame.height);
+    let chroma_width = frame.width.div_ceil(2);
+    let chroma_size = chroma_width * frame.height.div_ceil(2);
+    let (y, chroma) = i420.split_at(frame.width * frame.height);
+    let (u, v) = chroma.split_at(chroma_size);
+    let (width, height) = (nv12.width() as usize, nv12.height() as usize);
+
+    let stride = nv12.stride(0);
+    let luma = nv12.data_mut(0);
+    for row in 0..height {
+        luma[row * stride..][..width].copy_from_slice(&y[row * frame.width..][..width]);
+    }
+    let stride = nv12.stride(1);
+    let interleaved = nv12.data_mut(1);
+    for row in 0..height / 2 {
+        let out = &mut interleaved[row * stride..][..width];
+        for (column, pair) in out.chunks_exact_mut(2).enumerate() {
+            pair[0] = u[row * chroma_width + column];
+            pair[1] = v[row * chroma_width + column];
+        }
+    }
+}
+
+/// H.264 from the first hardware encoder that opens, and from OpenH264
+/// once that one fails. Hardware encoders take a new bitrate by opening
+/// again, with a keyframe, so only a change of a quarter or more does.
+pub struct FallbackEncoder {
+    config: EncoderConfig,
+    hardware: Option<FfmpegEncoder>,
+    software: Option<H264Encoder>,
+    max_macroblocks: Option<usize>,
+}
+
+impl FallbackEncoder {
+    /// `None` when no hardware encoder opens here.
+    pub fn new(config: &EncoderConfig) -> Option<Self> {
+        let (width, height) = ((config.width & !1) as u32, (config.height & !1) as u32);
+        let hardware =
+            HardwareBackend::for_platform().iter().find_map(
+                |&backend| match FfmpegEncoder::open(backend, config, width, height) {
+                    Ok(encoder) => {
+                        info!("Encoding H.264 with {}", backend);
+                        Some(encoder)
+                    }
+                    Err(err) => {
+                        info!("No {} H.264 encoder: {}", backend, err);
+                        None
+                    }
+                },
+            )?;
+        Some(Self {
+            config: *config,
+            hardware: Some(hardware),
+            software: None,
+            max_macroblocks: config
+                .h264_profile
+                .unwrap_or(ProfileLevelId::DEFAULT)
+                .max_macroblocks(),
+        })
+    }
+
+    fn encode_hardware(&mut self, frame: &VideoFrame) -> Result<Vec<u8>, ffmpeg::Error> {
+        let Some(hardware) = &mut self.hardware else {
+            return Err(ffmpeg::Error::EncoderNotFound);
+        };
+        let frame = h264::fit(frame, self.max_macroblocks);
+        let size = ((frame.width & !1) as u32, (frame.height & !1) as u32);
+        if size != (hardware.width, hardware.height) {
+            *hardware = FfmpegEncoder::open(hardware.backend, &self.config, size.0, size.1)?;
+        }
+        hardware.encode(&frame)
+    }
+}
+
+impl VideoEncoder for FallbackEncoder {
+    fn encode(&mut self, frame: &VideoFrame) -> Option<Vec<u8>> {
+        if let Some(backend) = self.hardware.as_ref().map(|hardware| hardware.backend) {
+            match self.encode_hardware(frame) {
+                Ok(data) => return Some(data).filter(|data| !data.is_empty()),
+                Err(err) => {
+                    info!("{} failed, encoding with OpenH264: {}", backend, err);
+                    self.hardware = None;
+                    self.software = H264Encoder::new(&self.config);
+                }
+            }
+        }
+        self.software.as_mut()?.encode(frame)
+    }
+
+    fn set_bitrate(&mut self, bitrate_bps: u32) {
+        if let Some(software) = &mut self.software {
+            self.config.bitrate_bps = bitrate_bps;
+            software.set_bitrate(bitrate_bps);
+            return;
+        }
+        let change = (bitrate_bps as f32 / self.config.bitrate_bps.max(1) as f32 - 1.0).abs();
+        if change < REOPEN_BITRATE_CHANGE {
+            return;
+        }
+        self.config.bitrate_bps = bitrate_bps;
+        if let Some(hardware) = &mut self.hardware {
+            let (backend, width, height) = (hardware.backend, hardware.width, hardware.height);
+            match FfmpegEncoder::open(backend, &self.config, width, height) {
+                Ok(reopened) => *hardware = reopened,
+                Err(err) => info!("{} cannot change its bitrate: {}", backend, err),
+            }
+        }
+    }
+}
@@ -0,0 +1,415 @@
+//! The video codecs this build can encode and decode, behind the
+//! `VideoEncoder` and `VideoDecoder` traits so capture and playback do not
+//! care which one a call ends up with.
+//!
+//! VP8 and VP9 come from libvpx with the `vp8` and `vp9` features; VP8
+//! is preferred for sending where it is built in, being the codec every
+//! WebRTC endpoint has to support. It is not a default feature only
+//! because libvpx is a native library, so the default build sends H.264.
+//! H.264 comes from OpenH264 with the `h264` feature, for Safari and
+//! hardware endpoints that only do H.264; it goes out in packetization
+//! mode 1 at the remote's profile and level (see `sending_capability`);
+//! with the `hardware` feature it is encoded on the GPU where FFmpeg can,
+//! and by OpenH264 where not. AV1 comes from rav1e and libdav1d with the
+//! `av1` feature, for better quality at low bitrates; it is offered last,
+//! being slower to encode. Answering an offer, the remote's order
+//! wins instead (see `remote_preference`). VP9 can also go out in
+//! scalable layers (see `ScalabilityMode`); the others send one.
+
+#[cfg(feature = "av1")]
+mod av1;
+mod av1_rtp;
+#[cfg(feature = "h264")]
+mod h264;
+#[cfg(feature = "hardware")]
+mod hardware;
+mod sdp;
+mod svc;
+#[cfg(any(feature = "vp8", feature = "vp9"))]
+mod vpx;
+
+pub use av1_rtp::Av1Packet;
+pub use sdp::{fmtp_parameter, video_formats, ProfileLevelId, VideoFormat};
+pub use svc::{LayerCap, ScalabilityMode};
+
+use std::time::Duration;
+
+use webrtc::api::media_engine::{
+    MIME_TYPE_AV1, MIME_TYPE_G722, MIME_TYPE_H264, MIME_TYPE_OPUS, MIME_TYPE_VP8, MIME_TYPE_VP9,
+};
+use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+
+use crate::video::{VideoDecoder, VideoEncoder};
+
+/// The video codecs this build can encode, best first.
+const ENCODERS: &[(&str, bool)] = &[
+    (MIME_TYPE_VP8, cfg!(feature = "vp8")),
+    (MIME_TYPE_VP9, cfg!(feature = "vp9")),
+    (MIME_TYPE_H264, cfg!(feature = "h264")),
+    (MIME_TYPE_AV1, cfg!(feature = "av1")),
+];
+
+/// How an encoder is set up. `new` picks a bitrate for the size and a
+/// keyframe every two seconds; both can be overridden.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncoderConfig {
+    pub width: usize,
+    pub height: usize,
+    pub frame_rate: f32,
+    pub bitrate_bps: u32,
+    /// Time between forced keyframes, so late joiners and lossy links
+    /// recover without asking.
+    pub keyframe_interval: Duration,
+    /// The remote's H.264 `profile-level-id`, whose level caps the picture
+    /// size; other codecs ignore it.
+    pub h264_profile: Option<ProfileLevelId>,
+    /// Layers to encode in, for codecs that can; the rest send one.
+    pub scalability: ScalabilityMode,
+}
+
+impl EncoderConfig {
+    pub fn new(width: usize, height: usize, frame_rate: f32) -> Self {
+        Self {
+            width,
+            height,
+            frame_rate,
+            // Roughly 0.1 bits per pixel per frame.
+            bitrate_bps: ((width * height) as f32 * frame_rate * 0.1) as u32,
+            keyframe_interval: Duration::from_secs(2),
+            h264_profile: None,
+            scalability: ScalabilityMode::L1T1,
+        }
+    }
+
+    pub fn bitrate(mut self, bitrate_bps: u32) -> Self {
+        self.bitrate_bps = bitrate_bps;
+        self
+    }
+
+    pub fn keyframe_interval(mut self, interval: Duration) -> Self {
+        self.keyframe_interval = interval;
+        self
+    }
+
+    pub fn h264_profile(mut self, profile: ProfileLevelId) -> Self {
+        self.h264_profile = Some(profile);
+        self
+    }
+
+    pub fn scalability(mut self, mode: ScalabilityMode) -> Self {
+        self.scalability = mode;
+        self
+    }
+
+    /// The keyframe interval in frames, at least one.
+    pub fn keyframe_frames(&self) -> u32 {
+        ((self.keyframe_interval.as_secs_f32() * self.frame_rate).round() as u32).max(1)
+    }
+}
+
+/// The codec this build sends video with, if any.
+pub fn encoder_mime_type() -> Option<&'static str> {
+    ENCODERS
+        .iter()
+        .find(|(_, built)| *built)
+        .map(|(mime_type, _)| *mime_type)
+}
+
+pub fn can_encode(mime_type: &str) -> bool {
+    ENCODERS
+        .iter()
+        .any(|(codec, built)| *built && mime_type.eq_ignore_ascii_case(codec))
+}
+
+/// The first video codec in `sdp`'s first video section that this build
+/// can send: the one to answer it with.
+pub fn remote_preference(sdp: &str) -> Option<&'static str> {
+    remote_preference_among(sdp, |_| true)
+}
+
+/// As `remote_preference`, skipping codecs `allowed` says no to, as ones
+/// our own offer or answer left out.
+pub fn remote_preference_among(sdp: &str, allowed: impl Fn(&str) -> bool) -> Option<&'static str> {
+    sdp::video_formats(sdp).iter().find_map(|format| {
+        let (mime_type, _) = ENCODERS.iter().find(|(mime_type, built)| {
+            *built
+                && allowed(mime_type)
+                && mime_type
+                    .strip_prefix("video/")
+                    .is_some_and(|codec| codec.eq_ignore_ascii_case(format.name))
+        })?;
+        (*mime_type != MIME_TYPE_H264 || ProfileLevelId::sendable(format.fmtp))
+            .then_some(*mime_type)
+    })
+}
+
+/// What to make a track sending `mime_type` with. For H.264 that takes
+/// the profile from `remote_sdp`'s first format we can send into, as a
+/// track only binds to a payload type with the same packetization mode
+/// and profile; its level then holds the encoder to the remote's size.
+pub fn sending_capability(mime_type: &str, remote_sdp: Option<&str>) -> RTCRtpCodecCapability {
+    let sdp_fmtp_line = if mime_type.eq_ignore_ascii_case(MIME_TYPE_H264) {
+        let remote = remote_sdp.and_then(|sdp| {
+            sdp::video_formats(sdp)
+                .iter()
+                .filter(|format| format.name.eq_ignore_ascii_case("H264"))
+                .find(|format| ProfileLevelId::sendable(format.fmtp))
+                .and_then(|format| ProfileLevelId::from_fmtp(format.fmtp))
+        });
+        remote.unwrap_or(ProfileLevelId::DEFAULT).fmtp()
+    } else {
+        String::new()
+    };
+    RTCRtpCodecCapability {
+        mime_type: mime_type.to_owned(),
+        sdp_fmtp_line,
+        ..Default::default()
+    }
+}
+
+/// How a codec is written for people, `H.264` for `video/H264`.
+pub fn display_name(mime_type: &str) -> &str {
+    [
+        (MIME_TYPE_VP8, "VP8"),
+        (MIME_TYPE_VP9, "VP9"),
+        (MIME_TYPE_H264, "H.264"),
+        (MIME_TYPE_AV1, "AV1"),
+        (MIME_TYPE_OPUS, "Opus"),
+        (MIME_TYPE_G722, "G.722"),
+    ]
+    .into_iter()
+    .find(|(codec, _)| mime_type.eq_ignore_ascii_case(codec))
+    .map_or_else(
+        || {
+            mime_type
+                .split_once('/')
+                .map_or(mime_type, |(_, name)| name)
+        },
+        |(_, name)| name,
+    )
+}
+
+/// Whether this build can decode the given video codec; the same codecs
+/// as it can encode.
+pub fn can_decode(mime_type: &str) -> bool {
+    can_encode(mime_type)
+}
+
+/// The layers video in `mime_type` goes out in when `mode` is asked for;
+/// only VP9 has more than one.
+pub fn scalability_for(mime_type: &str, mode: ScalabilityMode) -> ScalabilityMode {
+    if cfg!(feature = "vp9") && mime_type.eq_ignore_ascii_case(MIME_TYPE_VP9) {
+        mode
+    } else {
+        ScalabilityMode::L1T1
+    }
+}
+
+pub fn encoder_for(mime_type: &str, config: &EncoderConfig) -> Option<Box<dyn VideoEncoder>> {
+    #[cfg(feature = "vp8")]
+    if mime_type.eq_ignore_ascii_case(MIME_TYPE_VP8) {
+        return vpx::VpxEncoder::new(vpx::Vpx::Vp8, config)
+            .map(|e| Box::new(e) as Box<dyn VideoEncoder>);
+    }
+    #[cfg(feature = "vp9")]
+    if mime_type.eq_ignore_ascii_case(MIME_TYPE_VP9) {
+        return vpx::VpxEncoder::new(vpx::Vpx::Vp9, config)
+            .map(|e| Box::new(e) as Box<dyn VideoEncoder>);
+    }
+    #[cfg(feature = "hardware")]
+    if mime_type.eq_ignore_ascii_case(MIME_TYPE_H264) {
+        if let Some(encoder) = hardware::FallbackEncoder::new(config) {
+            return Some(Box::new(encoder));
+        }
+    }
+    #[cfg(feature = "h264")]
+    if mime_type.eq_ignore_ascii_case(MIME_TYPE_H264) {
+        return h264::H264Encoder::new(config).map(|e| Box::new(e) as Box<dyn VideoEncoder>);
+    }
+    #[cfg(feature = "av1")]
+    if mime_type.eq_ignore_ascii_case(MIME_TYPE_AV1) {
+        return av1::Av1Encoder::new(config).map(|e| Box::new(e) as Box<dyn VideoEncoder>);
+    }
+    let _ = (mime_type, config);
+    None
+}
+
+pub fn decoder_for(mime_type: &str) -> Option<Box<dyn VideoDecoder>> {
+    #[cfg(feature = "vp8")]
+    if mime_type.eq_ignore_ascii_case(MIME_TYPE_VP8) {
+        return vpx::VpxDecoder::new(vpx::Vpx::Vp8).map(|d| Box::new(d) as Box<dyn VideoDecoder>);
+    }
+    #[cfg(feature = "vp9")]
+    if mime_type.eq_ignore_ascii_case(MIME_TYPE_VP9) {
+        return vpx::VpxDecoder::new(vpx::Vpx::Vp9).map(|d| Box::new(d) as Box<dyn VideoDecoder>);
+    }
+    #[cfg(feature = "h264")]
+    if mime_type.eq_ignore_ascii_case(MIME_TYPE_H264) {
+        return h264::H264Decoder::new().map(|d| Box::new(d) as Box<dyn VideoDecoder>);
+    }
+    #[cfg(feature = "av1")]
+    if mime_type.eq_ignore_ascii_case(MIME_TYPE_AV1) {
+        return av1::Av1Decoder::new().map(|d| Box::new(d) as Box<dyn VideoDecoder>);
+    }
+    let _ = mime_type;
+    None
+}
+
+/// Converts RGBA to planar 4:2:0, with the BT.601 limited-range
+/// coefficients of `i420_to_rgba`. Odd sizes get a chroma sample for the
+/// last lone row or column.
+#[cfg_attr(
+    not(any(
+        feature = "vp8",
+        feature = "vp9",
+        feature = "av1",
+        feature = "hardware"
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn rgba_to_i420(rgba: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    let mut i420 = vec![0; width * height + 2 * chroma_width * chroma_height];
+    let (y_plane, chroma) = i420.split_at_mut(width * height);
+    let (u_plane, v_plane) = chroma.split_at_mut(chroma_width * chroma_height);
+    for row in 0..height {
+        for column in 0..width {
+            let pixel = &rgba[(row * width + column) * 4..][..3];
+            let [r, g, b] = [0, 1, 2].map(|channel| i32::from(pixel[channel]));
+            y_plane[row * width + column] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+        }
+    }
+    for row in 0..chroma_height {
+        for column in 0..chroma_width {
+            // Average the block of up to four pixels the sample covers.
+            let (mut sum, mut count) = ([0; 3], 0);
+            for y in row * 2..(row * 2 + 2).min(height) {
+                for x in column * 2..(column * 2 + 2).min(width) {
+                    let pixel = &rgba[(y * width + x) * 4..][..3];
+                    for (sum, value) in sum.iter_mut().zip(pixel) {
+                        *sum += i32::from(*value);
+                    }
+                    count += 1;
+                }
+            }
+            let [r, g, b] = sum.map(|sum| sum / count);
+            let at = row * chroma_width + column;
+            u_plane[at] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+            v_plane[at] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+        }
+    }
+    i420
+}
+
+/// Converts planar 4:2:0 to RGBA, with the same BT.601 limited-range
+/// coefficients as the camera's YUYV.
+pub(crate) fn i420_to_rgba(y: &[u8], u: &[u8], v: &[u8], width: usize, height: usize) -> Vec<u8> {
+    i420_strided_to_rgba([y, u, v], [width, width.div_ceil(2)], width, height)
+}
+
+/// As `i420_to_rgba`, for planes with padding at the end of their rows,
+/// as decoders hand them out: luma stride first, then chroma.
+pub(crate) fn i420_strided_to_rgba(
+    [y, u, v]: [&[u8]; 3],
+    [luma_stride, chroma_stride]: [usize; 2],
+    width: usize,
+    height: usize,
+) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for row in 0..height {
+        for column in 0..width {
+            let chroma = (row / 2) * chroma_stride + column / 2;
+            let c = 298 * (i32::from(y[row * luma_stride + column]) - 16);
+            let u = i32::from(u[chroma]) - 128;
+            let v = i32::from(v[chroma]) - 128;
+            let channel = |value: i32| ((value + 128) >> 8).clamp(0, 255) as u8;
+            rgba.extend_from_slice(&[
+                channel(c + 409 * v),
+                channel(c - 100 * u - 208 * v),
+                channel(c + 516 * u),
+                255,
+            ]);
+        }
+    }
+    rgba
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codecs::{CodecOverride, CodecPreferences};
+
+    #[test]
+    fn i420_round_trips_within_rounding() {
+        // 3x2 pixels: odd width, so the last chroma column covers one.
+        let rgba = [
+            [255, 0, 0],
+            [255, 0, 0],
+            [16, 200, 40],
+            [255, 0, 0],
+            [255, 0, 0],
+            [16, 200, 40],
+        ]
+        .iter()
+        .flat_map(|[r, g, b]| [*r, *g, *b, 255])
+        .collect::<Vec<u8>>();
+        let i420 = rgba_to_i420(&rgba, 3, 2);
+        assert_eq!(i420.len(), 6 + 2 * 2);
+        let (y, chroma) = i420.split_at(6);
+        let (u, v) = chroma.split_at(2);
+        let back = i420_to_rgba(y, u, v, 3, 2);
+        for (original, converted) in rgba.iter().zip(&back) {
+            assert!(
+                original.abs_diff(*converted) <= 4,
+                "{:?} -> {:?}",
+                rgba,
+                back
+            );
+        }
+    }
+
+    #[test]
+    fn answers_with_the_remotes_first_encodable_codec() {
+        let sdp = "v=0\r\n\
+                   m=audio 9 UDP/TLS/RTP/SAVPF 111\r\n\
+                   a=rtpmap:111 opus/48000/2\r\n\
+                   m=video 9 UDP/TLS/RTP/SAVPF 45 98 96 102\r\n\
+                   a=rtpmap:96 VP8/90000\r\n\
+                   a=rtpmap:98 VP9/90000\r\n\
+                   a=rtpmap:102 H264/90000\r\n\
+                   a=fmtp:102 packetization-mode=1;profile-level-id=42e01f\r\n\
+                   a=rtpmap:45 AV1/90000\r\n";
+        let expected = [MIME_TYPE_AV1, MIME_TYPE_VP9, MIME_TYPE_VP8, MIME_TYPE_H264]
+            .into_iter()
+            .find(|mime_type| can_encode(mime_type));
+        assert_eq!(remote_preference(sdp), expected);
+        assert_eq!(remote_preference("v=0\r\nm=audio 9 RTP/AVP 0\r\n"), None);
+        let h264 = sending_capability(MIME_TYPE_H264, Some(sdp));
+        assert_eq!(
+            ProfileLevelId::from_fmtp(&h264.sdp_fmtp_line),
+            Some(ProfileLevelId::DEFAULT)
+        );
+        assert_eq!(display_name("video/h264"), "H.264");
+        assert_eq!(display_name("video/rtx"), "rtx");
+    }
+
+    #[test]
+    fn sends_vp8_where_it_is_built_in() {
+        let preferred = CodecPreferences::default().preferred_encoder(&CodecOverride::default());
+        if cfg!(feature = "vp8") {
+            assert_eq!(encoder_mime_type(), Some(MIME_TYPE_VP8));
+            assert_eq!(preferred, Some(MIME_TYPE_VP8));
+        } else {
+            assert_eq!(preferred, encoder_mime_type());
+        }
+    }
+
+    #[test]
+    fn keyframe_interval_is_counted_in_frames() {
+        let config = EncoderConfig::new(640, 480, 30.0);
+        assert_eq!(config.keyframe_frames(), 60);
+        assert_eq!(config.bitrate_bps, 921_600);
+        let config = config.keyframe_interval(Duration::ZERO).bitrate(500_000);
+        assert_eq!((config.keyframe_frames(), config.bitrate_bps), (1, 500_000));
+    }
+}
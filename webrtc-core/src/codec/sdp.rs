@@ -0,0 +1,162 @@
+//! Reading the video formats out of a remote description, and H.264's
+//! `profile-level-id` (RFC 6184) among their parameters.
+
+/// One payload type of a video section: `a=rtpmap` and `a=fmtp` together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VideoFormat<'a> {
+    pub payload_type: &'a str,
+    /// The encoding name, such as `VP8` or `H264`.
+    pub name: &'a str,
+    pub fmtp: &'a str,
+}
+
+/// The formats of `sdp`'s first video section, in the remote's order of
+/// preference.
+pub fn video_formats(sdp: &str) -> Vec<VideoFormat<'_>> {
+    let mut lines = sdp.lines().map(str::trim_end);
+    let Some(media) = lines.find_map(|line| line.strip_prefix("m=video ")) else {
+        return vec![];
+    };
+    let mut formats: Vec<VideoFormat> = media
+        .split_whitespace()
+        .skip(2)
+        .map(|payload_type| VideoFormat {
+            payload_type,
+            name: "",
+            fmtp: "",
+        })
+        .collect();
+    for line in lines.take_while(|line| !line.starts_with("m=")) {
+        let (attribute, payload_type, value) = match line.split_once(' ') {
+            Some((key, value)) => match key.split_once(':') {
+                Some((attribute, payload_type)) => (attribute, payload_type, value),
+                None => continue,
+            },
+            None => continue,
+        };
+        let Some(format) = formats.iter_mut().find(|f| f.payload_type == payload_type) else {
+            continue;
+        };
+        match attribute {
+            "a=rtpmap" => format.name = value.split('/').next().unwrap_or_default(),
+            "a=fmtp" => format.fmtp = value,
+            _ => {}
+        }
+    }
+    formats.retain(|format| !format.name.is_empty());
+    formats
+}
+
+/// The value of `key` in an `a=fmtp` parameter list.
+pub fn fmtp_parameter<'a>(fmtp: &'a str, key: &str) -> Option<&'a str> {
+    fmtp.split(';').find_map(|parameter| {
+        let (name, value) = parameter.trim().split_once('=')?;
+        name.eq_ignore_ascii_case(key).then_some(value)
+    })
+}
+
+/// Profile, constraint flags and level of an H.264 stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProfileLevelId {
+    pub profile_idc: u8,
+    pub profile_iop: u8,
+    pub level_idc: u8,
+}
+
+impl ProfileLevelId {
+    /// Constrained baseline at level 3.1, for when the remote has not
+    /// said.
+    pub const DEFAULT: ProfileLevelId = ProfileLevelId {
+        profile_idc: 0x42,
+        profile_iop: 0xe0,
+        level_idc: 0x1f,
+    };
+
+    /// Profiles that take the constrained baseline stream OpenH264 makes
+    /// and that webrtc-rs registers with packetization mode 1: constrained
+    /// baseline, baseline and high. Its payloader only does mode 1.
+    const SENDABLE: [(u8, u8); 3] = [(0x42, 0xe0), (0x42, 0x00), (0x64, 0x00)];
+
+    pub fn parse(hex: &str) -> Option<Self> {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let byte = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).ok();
+        Some(Self {
+            profile_idc: byte(0)?,
+            profile_iop: byte(2)?,
+            level_idc: byte(4)?,
+        })
+    }
+
+    pub fn from_fmtp(fmtp: &str) -> Option<Self> {
+        Self::parse(fmtp_parameter(fmtp, "profile-level-id")?)
+    }
+
+    /// Whether an H.264 format with these `a=fmtp` parameters is one this
+    /// build can send into.
+    pub fn sendable(fmtp: &str) -> bool {
+        fmtp_parameter(fmtp, "packetization-mode") == Some("1")
+            && Self::from_fmtp(fmtp).is_some_and(|profile| {
+                Self::SENDABLE.contains(&(profile.profile_idc, profile.profile_iop))
+            })
+    }
+
+    /// The largest picture the level allows, in 16x16 macroblocks; `None`
+    /// for levels past 5.2 or unknown ones.
+    pub fn max_macroblocks(self) -> Option<usize> {
+        Some(match self.level_idc {
+            0x09 | 0x0a => 99,
+            0x0b..=0x0d | 0x14 => 396,
+            0x15 => 792,
+            0x16 | 0x1e => 1620,
+            0x1f => 3600,
+            0x20 => 5120,
+            0x28 | 0x29 => 8192,
+            0x2a => 8704,
+            0x32 => 22080,
+            0x33 | 0x34 => 36864,
+            _ => return None,
+        })
+    }
+
+    /// The `a=fmtp` line a sending track asks for this profile with.
+    pub fn fmtp(self) -> String {
+        format!(
+            "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id={:02x}{:02x}{:02x}",
+            self.profile_idc, self.profile_iop, self.level_idc
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_out_sendable_h264_formats() {
+        let sdp = "v=0\r\n\
+                   m=video 9 UDP/TLS/RTP/SAVPF 127 125 96\r\n\
+                   a=rtpmap:96 VP8/90000\r\n\
+                   a=rtpmap:127 H264/90000\r\n\
+                   a=fmtp:127 packetization-mode=0;profile-level-id=42e01f\r\n\
+                   a=rtpmap:125 H264/90000\r\n\
+                   a=fmtp:125 level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e016\r\n\
+                   m=audio 9 UDP/TLS/RTP/SAVPF 111\r\n\
+                   a=rtpmap:111 opus/48000/2\r\n";
+        let formats = video_formats(sdp);
+        let names: Vec<_> = formats.iter().map(|f| (f.payload_type, f.name)).collect();
+        assert_eq!(names, [("127", "H264"), ("125", "H264"), ("96", "VP8")]);
+        assert!(!ProfileLevelId::sendable(formats[0].fmtp));
+        assert!(ProfileLevelId::sendable(formats[1].fmtp));
+        let profile = ProfileLevelId::from_fmtp(formats[1].fmtp).unwrap();
+        assert_eq!(profile.max_macroblocks(), Some(1620));
+        assert_eq!(
+            profile.fmtp(),
+            "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e016"
+        );
+        assert!(!ProfileLevelId::sendable(
+            "packetization-mode=1;profile-level-id=4d001f"
+        ));
+    }
+}
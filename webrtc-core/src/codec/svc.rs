@@ -0,0 +1,128 @@
+//! Scalable video coding: a stream in layers that build on each other, so
+//! an SFU, or the sender itself, can leave out the top ones and the rest
+//! still decodes, at a smaller size or a lower frame rate. Modes are named
+//! as in the W3C's WebRTC-SVC: L for spatial layers, T for temporal ones.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ScalabilityMode {
+    /// A single layer, as without SVC.
+    #[default]
+    L1T1,
+    L1T2,
+    L1T3,
+    L2T3,
+    L3T3,
+}
+
+impl ScalabilityMode {
+    pub const ALL: [ScalabilityMode; 5] = [
+        ScalabilityMode::L1T1,
+        ScalabilityMode::L1T2,
+        ScalabilityMode::L1T3,
+        ScalabilityMode::L2T3,
+        ScalabilityMode::L3T3,
+    ];
+
+    pub fn spatial_layers(self) -> usize {
+        match self {
+            ScalabilityMode::L1T1 | ScalabilityMode::L1T2 | ScalabilityMode::L1T3 => 1,
+            ScalabilityMode::L2T3 => 2,
+            ScalabilityMode::L3T3 => 3,
+        }
+    }
+
+    pub fn temporal_layers(self) -> usize {
+        match self {
+            ScalabilityMode::L1T1 => 1,
+            ScalabilityMode::L1T2 => 2,
+            ScalabilityMode::L1T3 | ScalabilityMode::L2T3 | ScalabilityMode::L3T3 => 3,
+        }
+    }
+
+    pub fn is_scalable(self) -> bool {
+        self != ScalabilityMode::L1T1
+    }
+
+    /// The share of the bitrate each layer gets, indexed by spatial then
+    /// temporal layer from the bottom, each temporal share counting the
+    /// ones under it as libvpx wants. Spatial layers above `cap` get none.
+    pub fn layer_bitrates_kbps(self, total_kbps: u32, cap: LayerCap) -> Vec<u32> {
+        // Each spatial layer twice the one under it; a quarter of the
+        // area for half the bits is about right at real-time speeds.
+        let spatial: Vec<u32> = (0..self.spatial_layers()).map(|layer| 1 << layer).collect();
+        let spatial_sum: u32 = spatial.iter().sum();
+        // Up to each temporal layer, of its spatial layer's bitrate.
+        let temporal: &[f32] = match self.temporal_layers() {
+            1 => &[1.0],
+            2 => &[0.6, 1.0],
+            _ => &[0.4, 0.6, 1.0],
+        };
+        let mut rates = vec![];
+        for (index, weight) in spatial.iter().enumerate() {
+            let layer_kbps = if index < cap.spatial_layers {
+                total_kbps * weight / spatial_sum
+            } else {
+                0
+            };
+            rates.extend(
+                temporal
+                    .iter()
+                    .map(|share| (layer_kbps as f32 * share) as u32),
+            );
+        }
+        rates
+    }
+}
+
+impl fmt::Display for ScalabilityMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// How many layers, from the bottom, a scalable stream sends; the mode's
+/// own counts bound it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerCap {
+    pub spatial_layers: usize,
+    pub temporal_layers: usize,
+}
+
+impl LayerCap {
+    /// Every layer of every mode.
+    pub const NONE: LayerCap = LayerCap {
+        spatial_layers: 3,
+        temporal_layers: 3,
+    };
+}
+
+impl Default for LayerCap {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layer_bitrates_split_and_cap() {
+        let rates = ScalabilityMode::L2T3.layer_bitrates_kbps(900, LayerCap::NONE);
+        assert_eq!(rates, [120, 180, 300, 240, 360, 600]);
+        let capped = LayerCap {
+            spatial_layers: 1,
+            temporal_layers: 3,
+        };
+        let rates = ScalabilityMode::L2T3.layer_bitrates_kbps(900, capped);
+        assert_eq!(rates, [120, 180, 300, 0, 0, 0]);
+        assert_eq!(
+            ScalabilityMode::L1T1.layer_bitrates_kbps(500, LayerCap::NONE),
+            [500]
+        );
+    }
+}
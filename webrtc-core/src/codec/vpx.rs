@@ -0,0 +1,525 @@
+//! VP8 and VP9 through libvpx, tuned for real time: one pass, no
+//! lookahead, and error resilient so a lost packet costs a frame rather
+//! than the stream. VP9 can also encode in spatial and temporal layers
+//! (see `ScalabilityMode`), and leave the top ones out while capped.
+
+use std::ffi::CStr;
+use std::os::raw::{c_int, c_long, c_uint, c_ulong};
+use std::ptr;
+
+use log::info;
+use vpx_sys::{
+    vpx_codec_ctx_t, vpx_codec_cx_pkt_kind, vpx_codec_dec_cfg_t, vpx_codec_enc_cfg_t,
+    vpx_codec_err_t, vpx_codec_iface_t, vpx_codec_iter_t, vpx_image_t, vpx_img_fmt, vpx_kf_mode,
+    vpx_rational, vpx_rc_mode,
+};
+
+use super::{i420_strided_to_rgba, rgba_to_i420, EncoderConfig, LayerCap, ScalabilityMode};
+use crate::video::{VideoDecoder, VideoEncoder, VideoFrame};
+
+/// libvpx's speed for real time; higher is faster and blurrier.
+const CPU_USED: c_int = 8;
+/// Timestamps are in milliseconds.
+const TIMEBASE: i64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vpx {
+    #[cfg(feature = "vp8")]
+    Vp8,
+    #[cfg(feature = "vp9")]
+    Vp9,
+}
+
+impl Vpx {
+    fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "vp8")]
+            Vpx::Vp8 => "VP8",
+            #[cfg(feature = "vp9")]
+            Vpx::Vp9 => "VP9",
+        }
+    }
+
+    fn encoder(self) -> *const vpx_codec_iface_t {
+        // SAFETY: these only return pointers to libvpx's static tables.
+        match self {
+            #[cfg(feature = "vp8")]
+            Vpx::Vp8 => unsafe { vpx_sys::vpx_codec_vp8_cx() },
+            #[cfg(feature = "vp9")]
+            Vpx::Vp9 => unsafe { vpx_sys::vpx_codec_vp9_cx() },
+        }
+    }
+
+    fn decoder(self) -> *const vpx_codec_iface_t {
+        // SAFETY: as for `encoder`.
+        match self {
+            #[cfg(feature = "vp8")]
+            Vpx::Vp8 => unsafe { vpx_sys::vpx_codec_vp8_dx() },
+            #[cfg(feature = "vp9")]
+            Vpx::Vp9 => unsafe { vpx_sys::vpx_codec_vp9_dx() },
+        }
+    }
+}
+
+/// The message for the last error on `ctx`.
+fn last_error(ctx: &mut vpx_codec_ctx_t) -> String {
+    // SAFETY: libvpx returns a static, NUL-terminated string.
+    unsafe { CStr::from_ptr(vpx_sys::vpx_codec_error(ctx)) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// An initialised codec context, destroyed on drop. Boxed so libvpx's
+/// pointers into it stay put.
+struct Context(Box<vpx_codec_ctx_t>);
+
+impl Context {
+    fn zeroed() -> Box<vpx_codec_ctx_t> {
+        // SAFETY: an all-zero context is the uninitialised state libvpx's
+        // init functions expect.
+        Box::new(unsafe { std::mem::zeroed() })
+    }
+
+    /// Sets an encoder control that takes an int.
+    fn control(&mut self, id: c_int, value: c_int) {
+        // SAFETY: an initialised encoder and a control it takes an int for.
+        unsafe { vpx_sys::vpx_codec_control_(&mut *self.0, id, value) };
+    }
+
+    /// Sets or gets an encoder control that takes a pointer to `T`.
+    #[cfg(feature = "vp9")]
+    fn control_ptr<T>(&mut self, id: c_int, value: &mut T) {
+        // SAFETY: an initialised encoder and a control that takes a
+        // pointer to `T`, used only for the call.
+        unsafe { vpx_sys::vpx_codec_control_(&mut *self.0, id, value as *mut T) };
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        // SAFETY: the context was initialised, and is destroyed only here.
+        unsafe { vpx_sys::vpx_codec_destroy(&mut *self.0) };
+    }
+}
+
+// SAFETY: a context is only ever used through `&mut self`, so never from
+// two threads at once; libvpx keeps no thread-local state for it.
+unsafe impl Send for Context {}
+
+pub struct VpxEncoder {
+    codec: Vpx,
+    context: Context,
+    config: vpx_codec_enc_cfg_t,
+    frame_duration: i64,
+    pts: i64,
+    /// The next frame must be a keyframe, as after a size change.
+    keyframe: bool,
+    /// The layers `config` encodes in, L1T1 but for VP9.
+    scalability: ScalabilityMode,
+    cap: LayerCap,
+    /// The whole stream's, split over the layers sent.
+    bitrate_kbps: u32,
+}
+
+// SAFETY: the pointers in the configuration are for two-pass statistics,
+// null in one-pass mode; the context is `Send` itself.
+unsafe impl Send for VpxEncoder {}
+
+impl VpxEncoder {
+    pub fn new(codec: Vpx, config: &EncoderConfig) -> Option<Self> {
+        // SAFETY: the default is written into a zeroed plain C struct.
+        let mut vpx_config: vpx_codec_enc_cfg_t = unsafe { std::mem::zeroed() };
+        let err =
+            unsafe { vpx_sys::vpx_codec_enc_config_default(codec.encoder(), &mut vpx_config, 0) };
+        if err != vpx_codec_err_t::VPX_CODEC_OK {
+            info!(
+                "Failed to get the {} encoder defaults: {:?}",
+                codec.name(),
+                err
+            );
+            return None;
+        }
+        vpx_config.g_w = config.width as c_uint;
+        vpx_config.g_h = config.height as c_uint;
+        vpx_config.g_timebase = vpx_rational {
+            num: 1,
+            den: TIMEBASE as c_int,
+        };
+        vpx_config.g_threads = std::thread::available_parallelism()
+            .map_or(1, |threads| threads.get().min(4)) as c_uint;
+        vpx_config.g_lag_in_frames = 0;
+        vpx_config.g_error_resilient = vpx_sys::VPX_ERROR_RESILIENT_DEFAULT;
+        vpx_config.rc_end_usage = vpx_rc_mode::VPX_CBR;
+        let bitrate_kbps = config.bitrate_bps.div_ceil(1000);
+        vpx_config.rc_target_bitrate = bitrate_kbps;
+        vpx_config.kf_mode = vpx_kf_mode::VPX_KF_AUTO;
+        vpx_config.kf_max_dist = config.keyframe_frames();
+        let scalability = match codec {
+            #[cfg(feature = "vp9")]
+            Vpx::Vp9 => config.scalability,
+            #[allow(unreachable_patterns)]
+            _ => {
+                if config.scalability.is_scalable() {
+                    info!(
+                        "{} sends in one layer, not {}",
+                        codec.name(),
+                        config.scalability
+                    );
+                }
+                ScalabilityMode::L1T1
+            }
+        };
+        if scalability.is_scalable() {
+            set_layers(&mut vpx_config, scalability);
+            set_layer_bitrates(&mut vpx_config, scalability, bitrate_kbps, LayerCap::NONE);
+        }
+        let context = Self::open(codec, &vpx_config)?;
+        Some(Self {
+            codec,
+            context,
+            config: vpx_config,
+            frame_duration: (TIMEBASE as f32 / config.frame_rate.max(1.0)).round() as i64,
+            pts: 0,
+            keyframe: true,
+            scalability,
+            cap: LayerCap::NONE,
+            bitrate_kbps,
+        })
+    }
+
+    fn open(codec: Vpx, config: &vpx_codec_enc_cfg_t) -> Option<Context> {
+        let mut ctx = Context::zeroed();
+        // SAFETY: `ctx` is zeroed and `config` a complete configuration.
+        let err = unsafe {
+            vpx_sys::vpx_codec_enc_init_ver(
+                &mut *ctx,
+                codec.encoder(),
+                config,
+                0,
+                vpx_sys::VPX_ENCODER_ABI_VERSION as c_int,
+            )
+        };
+        if err != vpx_codec_err_t::VPX_CODEC_OK {
+            info!(
+                "Failed to create {} encoder: {}",
+                codec.name(),
+                last_error(&mut ctx)
+            );
+            return None;
+        }
+        let mut context = Context(ctx);
+        context.control(
+            vpx_sys::vp8e_enc_control_id::VP8E_SET_CPUUSED as c_int,
+            CPU_USED,
+        );
+        #[cfg(feature = "vp9")]
+        if codec == Vpx::Vp9 {
+            // Rows of a frame encode on threads of their own, and cyclic
+            // refresh spends bits where the picture changes.
+            context.control(vpx_sys::vp8e_enc_control_id::VP9E_SET_ROW_MT as c_int, 1);
+            context.control(vpx_sys::vp8e_enc_control_id::VP9E_SET_AQ_MODE as c_int, 3);
+            if config.ss_number_layers > 1 || config.ts_number_layers > 1 {
+                context.control(vpx_sys::vp8e_enc_control_id::VP9E_SET_SVC as c_int, 1);
+                context.control_ptr(
+                    vpx_sys::vp8e_enc_control_id::VP9E_SET_SVC_PARAMETERS as c_int,
+                    &mut svc_parameters(config),
+                );
+            }
+        }
+        Some(context)
+    }
+
+    /// Which temporal layer the frame just encoded is in.
+    #[cfg(feature = "vp9")]
+    fn temporal_layer(&mut self) -> usize {
+        // SAFETY: a plain C struct, which the control fills in.
+        let mut layer_id: vpx_sys::vpx_svc_layer_id_t = unsafe { std::mem::zeroed() };
+        self.context.control_ptr(
+            vpx_sys::vp8e_enc_control_id::VP9E_GET_SVC_LAYER_ID as c_int,
+            &mut layer_id,
+        );
+        layer_id.temporal_layer_id.max(0) as usize
+    }
+
+    /// Hands libvpx the bitrate and its split over the layers sent.
+    fn reconfigure(&mut self) {
+        self.config.rc_target_bitrate = self.bitrate_kbps;
+        if self.scalability.is_scalable() {
+            set_layer_bitrates(
+                &mut self.config,
+                self.scalability,
+                self.bitrate_kbps,
+                self.cap,
+            );
+        }
+        // SAFETY: an initialised encoder and a configuration changed only
+        // in its bitrates.
+        let err = unsafe { vpx_sys::vpx_codec_enc_config_set(&mut *self.context.0, &self.config) };
+        if err != vpx_codec_err_t::VPX_CODEC_OK {
+            info!(
+                "Failed to change the {} bitrate: {}",
+                self.codec.name(),
+                last_error(&mut self.context.0)
+            );
+        }
+    }
+}
+
+/// Sets up `config`'s layer counts and its temporal pattern: with three
+/// layers 0, 2, 1, 2, so each layer doubles the frame rate of those under
+/// it, and with two 0, 1.
+fn set_layers(config: &mut vpx_codec_enc_cfg_t, mode: ScalabilityMode) {
+    config.ss_number_layers = mode.spatial_layers() as c_uint;
+    config.ts_number_layers = mode.temporal_layers() as c_uint;
+    let (decimators, ids, layering): (&[c_uint], &[c_uint], _) = match mode.temporal_layers() {
+        1 => (
+            &[1],
+            &[0],
+            vpx_sys::VP9E_TEMPORAL_LAYERING_MODE::VP9E_TEMPORAL_LAYERING_MODE_NOLAYERING,
+        ),
+        2 => (
+            &[2, 1],
+            &[0, 1],
+            vpx_sys::VP9E_TEMPORAL_LAYERING_MODE::VP9E_TEMPORAL_LAYERING_MODE_0101,
+        ),
+        _ => (
+            &[4, 2, 1],
+            &[0, 2, 1, 2],
+            vpx_sys::VP9E_TEMPORAL_LAYERING_MODE::VP9E_TEMPORAL_LAYERING_MODE_0212,
+        ),
+    };
+    config.ts_rate_decimator[..decimators.len()].copy_from_slice(decimators);
+    config.ts_periodicity = ids.len() as c_uint;
+    config.ts_layer_id[..ids.len()].copy_from_slice(ids);
+    config.temporal_layering_mode = layering as c_int;
+}
+
+/// Splits `total_kbps` over `config`'s layers, leaving nothing for
+/// spatial layers above `cap`, which libvpx then skips.
+fn set_layer_bitrates(
+    config: &mut vpx_codec_enc_cfg_t,
+    mode: ScalabilityMode,
+    total_kbps: u32,
+    cap: LayerCap,
+) {
+    let temporal = mode.temporal_layers();
+    let rates = mode.layer_bitrates_kbps(total_kbps, cap);
+    config.layer_target_bitrate[..rates.len()].copy_from_slice(&rates);
+    for (spatial, layer_rates) in rates.chunks(temporal).enumerate() {
+        config.ss_target_bitrate[spatial] = layer_rates[temporal - 1];
+    }
+    if mode.spatial_layers() == 1 {
+        config.ts_target_bitrate[..temporal].copy_from_slice(&rates);
+    }
+    config.rc_target_bitrate = total_kbps;
+}
+
+/// Each spatial layer half the size of the one above it, all at the same
+/// quantizer range and speed.
+#[cfg(feature = "vp9")]
+fn svc_parameters(config: &vpx_codec_enc_cfg_t) -> vpx_sys::vpx_svc_extra_cfg_t {
+    // SAFETY: a plain C struct, all of which is filled in below.
+    let mut parameters: vpx_sys::vpx_svc_extra_cfg_t = unsafe { std::mem::zeroed() };
+    let spatial = config.ss_number_layers as usize;
+    for layer in 0..spatial * config.ts_number_layers as usize {
+        parameters.max_quantizers[layer] = config.rc_max_quantizer as c_int;
+        parameters.min_quantizers[layer] = config.rc_min_quantizer as c_int;
+        parameters.speed_per_layer[layer] = CPU_USED;
+    }
+    for layer in 0..spatial {
+        parameters.scaling_factor_num[layer] = 1;
+        parameters.scaling_factor_den[layer] = 1 << (spatial - 1 - layer);
+    }
+    parameters.temporal_layering_mode = config.temporal_layering_mode;
+    parameters
+}
+
+impl VideoEncoder for VpxEncoder {
+    fn encode(&mut self, frame: &VideoFrame) -> Option<Vec<u8>> {
+        let (width, height) = (frame.width as c_uint, frame.height as c_uint);
+        if (width, height) != (self.config.g_w, self.config.g_h) {
+            // VP8 cannot grow past its first size in place; start over.
+            self.config.g_w = width;
+            self.config.g_h = height;
+            self.context = Self::open(self.codec, &self.config)?;
+            self.keyframe = true;
+        }
+        let mut i420 = rgba_to_i420(&frame.rgba, frame.width, frame.height);
+        // SAFETY: the image only borrows `i420`, which outlives the encode
+        // call, and is big enough for a 4:2:0 picture of this size.
+        let mut image: vpx_image_t = unsafe { std::mem::zeroed() };
+        let wrapped = unsafe {
+            vpx_sys::vpx_img_wrap(
+                &mut image,
+                vpx_img_fmt::VPX_IMG_FMT_I420,
+                width,
+                height,
+                1,
+                i420.as_mut_ptr(),
+            )
+        };
+        if wrapped.is_null() {
+            return None;
+        }
+        let flags = if self.keyframe {
+            vpx_sys::VPX_EFLAG_FORCE_KF as c_long
+        } else {
+            0
+        };
+        let ctx = &mut *self.context.0;
+        // SAFETY: an initialised encoder and a wrapped image.
+        let err = unsafe {
+            vpx_sys::vpx_codec_encode(
+                ctx,
+                &image,
+                self.pts,
+                self.frame_duration as c_ulong,
+                flags,
+                vpx_sys::VPX_DL_REALTIME as c_ulong,
+            )
+        };
+        self.pts += self.frame_duration;
+        if err != vpx_codec_err_t::VPX_CODEC_OK {
+            info!("{} encode error: {}", self.codec.name(), last_error(ctx));
+            return None;
+        }
+        self.keyframe = false;
+        #[cfg(feature = "vp9")]
+        if self.scalability.temporal_layers() > 1
+            && self.temporal_layer() >= self.cap.temporal_layers
+        {
+            // Nothing refers to a frame in a layer above the ones sent.
+            return None;
+        }
+
+        let ctx = &mut *self.context.0;
+        let mut data = vec![];
+        let mut iter: vpx_codec_iter_t = ptr::null();
+        loop {
+            // SAFETY: packets stay valid until the next call on `ctx`, and
+            // are copied out before it.
+            let packet = unsafe { vpx_sys::vpx_codec_get_cx_data(ctx, &mut iter).as_ref() };
+            let Some(packet) = packet else {
+                break;
+            };
+            if packet.kind == vpx_codec_cx_pkt_kind::VPX_CODEC_CX_FRAME_PKT {
+                // SAFETY: frame packets carry the `frame` variant, pointing
+                // at `sz` bytes of bitstream.
+                let bytes = unsafe {
+                    let frame = packet.data.frame;
+                    std::slice::from_raw_parts(frame.buf as *const u8, frame.sz)
+                };
+                data.extend_from_slice(bytes);
+            }
+        }
+        Some(data).filter(|data| !data.is_empty())
+    }
+
+    fn set_layer_cap(&mut self, cap: LayerCap) {
+        let cap = LayerCap {
+            spatial_layers: cap.spatial_layers.max(1),
+            temporal_layers: cap.temporal_layers.max(1),
+        };
+        if !self.scalability.is_scalable() || cap == self.cap {
+            return;
+        }
+        let spatial = self.scalability.spatial_layers();
+        let resplit = cap.spatial_layers.min(spatial) != self.cap.spatial_layers.min(spatial);
+        self.cap = cap;
+        if resplit {
+            self.reconfigure();
+        }
+    }
+
+    fn set_bitrate(&mut self, bitrate_bps: u32) {
+        let bitrate_kbps = bitrate_bps.div_ceil(1000);
+        if bitrate_kbps != self.bitrate_kbps {
+            self.bitrate_kbps = bitrate_kbps;
+            self.reconfigure();
+        }
+    }
+}
+
+pub struct VpxDecoder {
+    codec: Vpx,
+    context: Context,
+}
+
+impl VpxDecoder {
+    pub fn new(codec: Vpx) -> Option<Self> {
+        let mut ctx = Context::zeroed();
+        let config = vpx_codec_dec_cfg_t {
+            threads: 1,
+            w: 0,
+            h: 0,
+        };
+        // SAFETY: `ctx` is zeroed and `config` complete.
+        let err = unsafe {
+            vpx_sys::vpx_codec_dec_init_ver(
+                &mut *ctx,
+                codec.decoder(),
+                &config,
+                0,
+                vpx_sys::VPX_DECODER_ABI_VERSION as c_int,
+            )
+        };
+        if err != vpx_codec_err_t::VPX_CODEC_OK {
+            info!(
+                "Failed to create {} decoder: {}",
+                codec.name(),
+                last_error(&mut ctx)
+            );
+            return None;
+        }
+        Some(Self {
+            codec,
+            context: Context(ctx),
+        })
+    }
+}
+
+impl VideoDecoder for VpxDecoder {
+    fn decode(&mut self, access_unit: &[u8]) -> Option<VideoFrame> {
+        let ctx = &mut *self.context.0;
+        // SAFETY: an initialised decoder reading a borrowed buffer.
+        let err = unsafe {
+            vpx_sys::vpx_codec_decode(
+                ctx,
+                access_unit.as_ptr(),
+                access_unit.len() as c_uint,
+                ptr::null_mut(),
+                0,
+            )
+        };
+        if err != vpx_codec_err_t::VPX_CODEC_OK {
+            info!("{} decode error: {}", self.codec.name(), last_error(ctx));
+            return None;
+        }
+        let mut iter: vpx_codec_iter_t = ptr::null();
+        let mut decoded = None;
+        // SAFETY: images stay valid until the next decode, and are
+        // converted before it.
+        while let Some(image) = unsafe { vpx_sys::vpx_codec_get_frame(ctx, &mut iter).as_ref() } {
+            // Profile 0 only; the others are 4:4:4 or high bit depth.
+            if image.fmt != vpx_img_fmt::VPX_IMG_FMT_I420 {
+                continue;
+            }
+            let (width, height) = (image.d_w as usize, image.d_h as usize);
+            let strides = [image.stride[0] as usize, image.stride[1] as usize];
+            let plane = |index: usize, stride: usize, rows: usize| unsafe {
+                std::slice::from_raw_parts(image.planes[index] as *const u8, stride * rows)
+            };
+            let planes = [
+                plane(0, strides[0], height),
+                plane(1, strides[1], height.div_ceil(2)),
+                plane(2, strides[1], height.div_ceil(2)),
+            ];
+            decoded = Some(VideoFrame {
+                width,
+                height,
+                rgba: i420_strided_to_rgba(planes, strides, width, height),
+            });
+        }
+        decoded
+    }
+}
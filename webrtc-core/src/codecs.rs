@@ -0,0 +1,576 @@
+//! Choosing and narrowing the codecs a peer connection offers.
+//!
+//! `CodecPreferences` is saved with the settings: which codecs calls
+//! offer, in what order. A `CodecOverride` narrows that further for one
+//! peer connection at a time, for interop experiments such as forcing
+//! H.264 baseline against a picky endpoint. webrtc-rs keeps its default
+//! registrations private, so `DEFAULT_CODECS` repeats them to filter from.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use webrtc::api::media_engine::{
+    MediaEngine, MIME_TYPE_AV1, MIME_TYPE_G722, MIME_TYPE_H264, MIME_TYPE_OPUS, MIME_TYPE_PCMA,
+    MIME_TYPE_PCMU, MIME_TYPE_VP8, MIME_TYPE_VP9,
+};
+use webrtc::rtp_transceiver::rtp_codec::{
+    RTCRtpCodecCapability, RTCRtpCodecParameters, RTPCodecType,
+};
+use webrtc::rtp_transceiver::RTCPFeedback;
+
+use crate::audio::OpusSettings;
+use crate::red::MIME_TYPE_RED;
+
+const MIME_TYPE_ULPFEC: &str = "video/ulpfec";
+
+/// The default Opus fmtp, saying both that we send two channels and that
+/// we would hear two back (RFC 7587).
+const OPUS_STEREO_FMTP: &str = "minptime=10;useinbandfec=1;stereo=1;sprop-stereo=1";
+
+/// The payload type browsers put RED on.
+const RED_PAYLOAD_TYPE: u8 = 63;
+
+/// `MediaEngine::register_default_codecs`, as (kind, mime type, clock rate,
+/// channels, fmtp, payload type).
+const DEFAULT_CODECS: [(RTPCodecType, &str, u32, u16, &str, u8); 14] = [
+    (
+        RTPCodecType::Audio,
+        MIME_TYPE_OPUS,
+        48000,
+        2,
+        "minptime=10;useinbandfec=1",
+        111,
+    ),
+    (RTPCodecType::Audio, MIME_TYPE_G722, 8000, 0, "", 9),
+    (RTPCodecType::Audio, MIME_TYPE_PCMU, 8000, 0, "", 0),
+    (RTPCodecType::Audio, MIME_TYPE_PCMA, 8000, 0, "", 8),
+    (RTPCodecType::Video, MIME_TYPE_VP8, 90000, 0, "", 96),
+    (
+        RTPCodecType::Video,
+        MIME_TYPE_VP9,
+        90000,
+        0,
+        "profile-id=0",
+        98,
+    ),
+    (
+        RTPCodecType::Video,
+        MIME_TYPE_VP9,
+        90000,
+        0,
+        "profile-id=1",
+        100,
+    ),
+    (
+        RTPCodecType::Video,
+        MIME_TYPE_H264,
+        90000,
+        0,
+        "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42001f",
+        102,
+    ),
+    (
+        RTPCodecType::Video,
+        MIME_TYPE_H264,
+        90000,
+        0,
+        "level-asymmetry-allowed=1;packetization-mode=0;profile-level-id=42001f",
+        127,
+    ),
+    (
+        RTPCodecType::Video,
+        MIME_TYPE_H264,
+        90000,
+        0,
+        "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f",
+        125,
+    ),
+    (
+        RTPCodecType::Video,
+        MIME_TYPE_H264,
+        90000,
+        0,
+        "level-asymmetry-allowed=1;packetization-mode=0;profile-level-id=42e01f",
+        108,
+    ),
+    (
+        RTPCodecType::Video,
+        MIME_TYPE_H264,
+        90000,
+        0,
+        "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=640032",
+        123,
+    ),
+    (
+        RTPCodecType::Video,
+        MIME_TYPE_AV1,
+        90000,
+        0,
+        "profile-id=0",
+        41,
+    ),
+    (RTPCodecType::Video, MIME_TYPE_ULPFEC, 90000, 0, "", 116),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AudioCodecs {
+    #[default]
+    Any,
+    Opus,
+    G722,
+    /// PCMU and PCMA.
+    G711,
+}
+
+impl AudioCodecs {
+    pub const ALL: [AudioCodecs; 4] = [
+        AudioCodecs::Any,
+        AudioCodecs::Opus,
+        AudioCodecs::G722,
+        AudioCodecs::G711,
+    ];
+
+    fn allows(self, mime_type: &str) -> bool {
+        match self {
+            AudioCodecs::Any => true,
+            AudioCodecs::Opus => mime_type == MIME_TYPE_OPUS,
+            AudioCodecs::G722 => mime_type == MIME_TYPE_G722,
+            AudioCodecs::G711 => mime_type == MIME_TYPE_PCMU || mime_type == MIME_TYPE_PCMA,
+        }
+    }
+
+    /// Whether Opus, which the microphone sends, is still offered.
+    pub fn allows_opus(self) -> bool {
+        self.allows(MIME_TYPE_OPUS)
+    }
+}
+
+impl fmt::Display for AudioCodecs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AudioCodecs::Any => "Any audio codec",
+            AudioCodecs::Opus => "Opus only",
+            AudioCodecs::G722 => "G.722 only",
+            AudioCodecs::G711 => "G.711 only",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VideoCodecs {
+    #[default]
+    Any,
+    Vp8,
+    Vp9,
+    /// Every H.264 profile.
+    H264,
+    /// Baseline and constrained baseline (`42001f`, `42e01f`).
+    H264Baseline,
+    Av1,
+}
+
+impl VideoCodecs {
+    pub const ALL: [VideoCodecs; 6] = [
+        VideoCodecs::Any,
+        VideoCodecs::Vp8,
+        VideoCodecs::Vp9,
+        VideoCodecs::H264,
+        VideoCodecs::H264Baseline,
+        VideoCodecs::Av1,
+    ];
+
+    fn allows(self, mime_type: &str, fmtp: &str) -> bool {
+        // FEC protects whichever codec is left.
+        if mime_type == MIME_TYPE_ULPFEC {
+            return true;
+        }
+        match self {
+            VideoCodecs::Any => true,
+            VideoCodecs::Vp8 => mime_type == MIME_TYPE_VP8,
+            VideoCodecs::Vp9 => mime_type == MIME_TYPE_VP9,
+            VideoCodecs::H264 => mime_type == MIME_TYPE_H264,
+            VideoCodecs::H264Baseline => {
+                mime_type == MIME_TYPE_H264 && fmtp.contains("profile-level-id=42")
+            }
+            VideoCodecs::Av1 => mime_type == MIME_TYPE_AV1,
+        }
+    }
+}
+
+impl fmt::Display for VideoCodecs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VideoCodecs::Any => "Any video codec",
+            VideoCodecs::Vp8 => "VP8 only",
+            VideoCodecs::Vp9 => "VP9 only",
+            VideoCodecs::H264 => "H.264 only",
+            VideoCodecs::H264Baseline => "H.264 baseline only",
+            VideoCodecs::Av1 => "AV1 only",
+        })
+    }
+}
+
+/// The codecs to offer on the next peer connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CodecOverride {
+    pub audio: AudioCodecs,
+    pub video: VideoCodecs,
+}
+
+impl CodecOverride {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn allows(&self, kind: RTPCodecType, mime_type: &str, fmtp: &str) -> bool {
+        match kind {
+            RTPCodecType::Audio => self.audio.allows(mime_type),
+            RTPCodecType::Video => self.video.allows(mime_type, fmtp),
+            RTPCodecType::Unspecified => false,
+        }
+    }
+
+    /// Whether any registration of `mime_type` gets through.
+    pub fn allows_codec(&self, mime_type: &str) -> bool {
+        DEFAULT_CODECS.iter().any(|(kind, codec, _, _, fmtp, _)| {
+            codec.eq_ignore_ascii_case(mime_type) && self.allows(*kind, codec, fmtp)
+        })
+    }
+}
+
+impl fmt::Display for CodecOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}", self.audio, self.video)
+    }
+}
+
+/// One codec in a `CodecPreferences` list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodecPreference {
+    pub mime_type: String,
+    pub enabled: bool,
+}
+
+/// Which codecs calls offer, best first. H.264 counts as one codec here,
+/// with all its profiles; a `CodecOverride` can still pick among those.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CodecPreferences {
+    pub audio: Vec<CodecPreference>,
+    pub video: Vec<CodecPreference>,
+}
+
+impl Default for CodecPreferences {
+    fn default() -> Self {
+        let all = |kind| {
+            known_codecs(kind)
+                .map(|mime_type| CodecPreference {
+                    mime_type: mime_type.to_owned(),
+                    enabled: true,
+                })
+                .collect()
+        };
+        Self {
+            audio: all(RTPCodecType::Audio),
+            video: all(RTPCodecType::Video),
+        }
+    }
+}
+
+/// The codecs of `kind` that `DEFAULT_CODECS` registers, once each and in
+/// its order, leaving out FEC.
+fn known_codecs(kind: RTPCodecType) -> impl Iterator<Item = &'static str> {
+    DEFAULT_CODECS
+        .iter()
+        .enumerate()
+        .filter(move |(index, (codec_kind, mime_type, ..))| {
+            *codec_kind == kind
+                && *mime_type != MIME_TYPE_ULPFEC
+                && !DEFAULT_CODECS[..*index]
+                    .iter()
+                    .any(|(_, earlier, ..)| earlier == mime_type)
+        })
+        .map(|(_, (_, mime_type, ..))| *mime_type)
+}
+
+impl CodecPreferences {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The list for `kind`, as saved, less codecs this version does not
+    /// know and with new ones enabled at the end so upgrades keep them.
+    pub fn list_mut(&mut self, kind: RTPCodecType) -> &mut Vec<CodecPreference> {
+        let list = match kind {
+            RTPCodecType::Audio => &mut self.audio,
+            _ => &mut self.video,
+        };
+        list.retain(|entry| known_codecs(kind).any(|known| known == entry.mime_type));
+        for known in known_codecs(kind) {
+            if !list.iter().any(|entry| entry.mime_type == known) {
+                list.push(CodecPreference {
+                    mime_type: known.to_owned(),
+                    enabled: true,
+                });
+            }
+        }
+        list
+    }
+
+    /// The enabled codecs of `kind`, best first, as `list_mut` would
+    /// leave them.
+    pub fn enabled(&self, kind: RTPCodecType) -> Vec<&'static str> {
+        let list = match kind {
+            RTPCodecType::Audio => &self.audio,
+            _ => &self.video,
+        };
+        let saved = list
+            .iter()
+            .filter(|entry| entry.enabled)
+            .filter_map(|entry| known_codecs(kind).find(|known| *known == entry.mime_type));
+        let new =
+            known_codecs(kind).filter(|known| !list.iter().any(|entry| entry.mime_type == *known));
+        saved.chain(new).collect()
+    }
+
+    pub fn allows(&self, mime_type: &str) -> bool {
+        [RTPCodecType::Audio, RTPCodecType::Video]
+            .into_iter()
+            .flat_map(|kind| self.enabled(kind))
+            .any(|codec| codec.eq_ignore_ascii_case(mime_type))
+    }
+
+    /// The first enabled video codec this build can send that `codecs`
+    /// also lets through: what to send before the remote has said.
+    pub fn preferred_encoder(&self, codecs: &CodecOverride) -> Option<&'static str> {
+        self.enabled(RTPCodecType::Video)
+            .into_iter()
+            .find(|mime_type| crate::codec::can_encode(mime_type) && codecs.allows_codec(mime_type))
+    }
+}
+
+/// The registrations of `kind` to make, in order: those of each codec
+/// `preferences` enables, less what `codecs` leaves out.
+fn registrations(
+    kind: RTPCodecType,
+    codecs: &CodecOverride,
+    preferences: &CodecPreferences,
+) -> Vec<(RTPCodecType, &'static str, u32, u16, &'static str, u8)> {
+    let mut registrations: Vec<_> = preferences
+        .enabled(kind)
+        .into_iter()
+        .flat_map(|mime_type| {
+            DEFAULT_CODECS
+                .into_iter()
+                .filter(move |(codec_kind, codec, ..)| *codec_kind == kind && *codec == mime_type)
+        })
+        .filter(|(kind, mime_type, _, _, fmtp, _)| codecs.allows(*kind, mime_type, fmtp))
+        .collect();
+    // FEC protects whichever codec is left.
+    if !registrations.is_empty() {
+        registrations.extend(
+            DEFAULT_CODECS
+                .into_iter()
+                .filter(|(codec_kind, mime_type, ..)| {
+                    *codec_kind == kind && *mime_type == MIME_TYPE_ULPFEC
+                }),
+        );
+    }
+    registrations
+}
+
+/// The fmtp to register `mime_type` with, given its default one.
+fn fmtp_for(mime_type: &str, fmtp: &'static str, stereo: bool) -> &'static str {
+    if stereo && mime_type == MIME_TYPE_OPUS {
+        OPUS_STEREO_FMTP
+    } else {
+        fmtp
+    }
+}
+
+/// Registers the default codecs `preferences` enables, in its order, and
+/// that `codecs` lets through. Where the override leaves none of a kind,
+/// it wins over the preferences, being the later and narrower choice.
+/// Opus is negotiated in two channels when `opus` sends stereo, and
+/// offered inside RED as well, just after it, when `opus` sends that.
+pub fn register(
+    media_engine: &mut MediaEngine,
+    codecs: &CodecOverride,
+    preferences: &CodecPreferences,
+    opus: &OpusSettings,
+) -> Result<(), webrtc::Error> {
+    if codecs.is_default() && preferences.is_default() && !opus.stereo && !opus.red {
+        return media_engine.register_default_codecs();
+    }
+    let feedback = vec![
+        RTCPFeedback {
+            typ: "goog-remb".to_owned(),
+            parameter: String::new(),
+        },
+        RTCPFeedback {
+            typ: "ccm".to_owned(),
+            parameter: "fir".to_owned(),
+        },
+        RTCPFeedback {
+            typ: "nack".to_owned(),
+            parameter: String::new(),
+        },
+        RTCPFeedback {
+            typ: "nack".to_owned(),
+            parameter: "pli".to_owned(),
+        },
+    ];
+    let chosen = [RTPCodecType::Audio, RTPCodecType::Video]
+        .into_iter()
+        .flat_map(|kind| {
+            let chosen = registrations(kind, codecs, preferences);
+            if chosen.is_empty() {
+                registrations(kind, codecs, &CodecPreferences::default())
+            } else {
+                chosen
+            }
+        });
+    for (kind, mime_type, clock_rate, channels, fmtp, payload_type) in chosen {
+        let rtcp_feedback = if kind == RTPCodecType::Video && mime_type != MIME_TYPE_ULPFEC {
+            feedback.clone()
+        } else {
+            vec![]
+        };
+        media_engine.register_codec(
+            RTCRtpCodecParameters {
+                capability: RTCRtpCodecCapability {
+                    mime_type: mime_type.to_owned(),
+                    clock_rate,
+                    channels,
+                    sdp_fmtp_line: fmtp_for(mime_type, fmtp, opus.stereo).to_owned(),
+                    rtcp_feedback,
+                },
+                payload_type,
+                ..Default::default()
+            },
+            kind,
+        )?;
+        if opus.red && mime_type == MIME_TYPE_OPUS {
+            // One redundant copy of this Opus per packet.
+            media_engine.register_codec(
+                RTCRtpCodecParameters {
+                    capability: RTCRtpCodecCapability {
+                        mime_type: MIME_TYPE_RED.to_owned(),
+                        clock_rate,
+                        channels,
+                        sdp_fmtp_line: format!("{}/{}", payload_type, payload_type),
+                        rtcp_feedback: vec![],
+                    },
+                    payload_type: RED_PAYLOAD_TYPE,
+                    ..Default::default()
+                },
+                kind,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registered(
+        kind: RTPCodecType,
+        codecs: &CodecOverride,
+        preferences: &CodecPreferences,
+    ) -> Vec<u8> {
+        registrations(kind, codecs, preferences)
+            .iter()
+            .map(|(.., payload_type)| *payload_type)
+            .collect()
+    }
+
+    #[test]
+    fn preferences_order_and_override_narrows() {
+        let mut preferences = CodecPreferences::default();
+        let video = preferences.list_mut(RTPCodecType::Video);
+        // H.264 first, VP9 off.
+        let h264 = video
+            .iter()
+            .position(|entry| entry.mime_type == MIME_TYPE_H264)
+            .unwrap();
+        let entry = video.remove(h264);
+        video.insert(0, entry);
+        video
+            .iter_mut()
+            .find(|entry| entry.mime_type == MIME_TYPE_VP9)
+            .unwrap()
+            .enabled = false;
+        // A codec from an older version is dropped.
+        preferences
+            .audio
+            .retain(|entry| entry.mime_type != MIME_TYPE_G722);
+        preferences.audio.push(CodecPreference {
+            mime_type: "audio/iLBC".to_owned(),
+            enabled: true,
+        });
+
+        let any = CodecOverride::default();
+        assert_eq!(
+            registered(RTPCodecType::Video, &any, &preferences),
+            [102, 127, 125, 108, 123, 96, 41, 116]
+        );
+        assert_eq!(
+            preferences.enabled(RTPCodecType::Audio),
+            [
+                MIME_TYPE_OPUS,
+                MIME_TYPE_PCMU,
+                MIME_TYPE_PCMA,
+                MIME_TYPE_G722
+            ]
+        );
+        assert!(!preferences.allows("video/vp9"));
+
+        // An override for a disabled codec still gets it.
+        let vp9 = CodecOverride {
+            video: VideoCodecs::Vp9,
+            ..Default::default()
+        };
+        assert!(registered(RTPCodecType::Video, &vp9, &preferences).is_empty());
+        assert_eq!(
+            registered(RTPCodecType::Video, &vp9, &CodecPreferences::default()),
+            [98, 100, 116]
+        );
+    }
+
+    #[tokio::test]
+    async fn stereo_and_red_go_into_the_offer() {
+        let mut media_engine = MediaEngine::default();
+        let (any, preferences) = (CodecOverride::default(), CodecPreferences::default());
+        let opus = OpusSettings {
+            stereo: true,
+            red: true,
+            ..Default::default()
+        };
+        register(&mut media_engine, &any, &preferences, &opus).unwrap();
+        let api = webrtc::api::APIBuilder::new()
+            .with_media_engine(media_engine)
+            .build();
+        let pc = api.new_peer_connection(Default::default()).await.unwrap();
+        pc.add_transceiver_from_kind(RTPCodecType::Audio, None)
+            .await
+            .unwrap();
+        let offer = pc.create_offer(None).await.unwrap();
+        assert!(
+            offer
+                .sdp
+                .contains(&format!("a=fmtp:111 {}", OPUS_STEREO_FMTP)),
+            "{}",
+            offer.sdp
+        );
+        assert!(
+            offer.sdp.contains("a=rtpmap:63 red/48000/2"),
+            "{}",
+            offer.sdp
+        );
+        assert!(offer.sdp.contains("a=fmtp:63 111/111"), "{}", offer.sdp);
+        pc.close().await.unwrap();
+    }
+}
@@ -0,0 +1,442 @@
+//! `getUserMedia`-style video constraints and the selection algorithm from
+//! Media Capture and Streams §4.3.8: required constraints (`exact`, `min`,
+//! `max`) filter candidates, and the remaining ones are ranked by fitness
+//! distance to the `ideal` values.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::devices::{CaptureMode, DeviceInfo, FacingMode};
+
+/// A numeric constraint such as `{ ideal: 1280, min: 640 }`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConstrainRange<T> {
+    pub exact: Option<T>,
+    pub ideal: Option<T>,
+    pub min: Option<T>,
+    pub max: Option<T>,
+}
+
+impl<T: Copy + Into<f64>> ConstrainRange<T> {
+    pub fn ideal(value: T) -> Self {
+        Self {
+            exact: None,
+            ideal: Some(value),
+            min: None,
+            max: None,
+        }
+    }
+
+    pub fn exact(value: T) -> Self {
+        Self {
+            exact: Some(value),
+            ideal: None,
+            min: None,
+            max: None,
+        }
+    }
+
+    fn satisfied_by(&self, value: f64) -> bool {
+        self.exact.is_none_or(|exact| value == exact.into())
+            && self.min.is_none_or(|min| value >= min.into())
+            && self.max.is_none_or(|max| value <= max.into())
+    }
+
+    fn is_required(&self) -> bool {
+        self.exact.is_some() || self.min.is_some() || self.max.is_some()
+    }
+
+    fn distance(&self, value: f64) -> f64 {
+        match self.ideal {
+            Some(ideal) => {
+                let ideal = ideal.into();
+                if value == ideal {
+                    0.0
+                } else {
+                    (value - ideal).abs() / value.abs().max(ideal.abs())
+                }
+            }
+            None => 0.0,
+        }
+    }
+}
+
+/// A discrete constraint such as `facingMode` or `deviceId`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConstrainValue<T> {
+    pub exact: Option<T>,
+    pub ideal: Option<T>,
+}
+
+impl<T> Default for ConstrainValue<T> {
+    fn default() -> Self {
+        Self {
+            exact: None,
+            ideal: None,
+        }
+    }
+}
+
+impl<T: PartialEq> ConstrainValue<T> {
+    pub fn ideal(value: T) -> Self {
+        Self {
+            exact: None,
+            ideal: Some(value),
+        }
+    }
+
+    pub fn exact(value: T) -> Self {
+        Self {
+            exact: Some(value),
+            ideal: None,
+        }
+    }
+
+    fn satisfied_by(&self, value: Option<&T>) -> bool {
+        self.exact.as_ref().is_none_or(|exact| value == Some(exact))
+    }
+
+    fn distance(&self, value: Option<&T>) -> f64 {
+        match &self.ideal {
+            Some(ideal) if value != Some(ideal) => 1.0,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Common capture sizes by name, smallest first.
+pub const RESOLUTIONS: [(&str, u32, u32); 3] = [
+    ("360p", 640, 360),
+    ("720p", 1280, 720),
+    ("1080p", 1920, 1080),
+];
+
+/// Common capture frame rates, slowest first.
+pub const FRAME_RATES: [f64; 4] = [15.0, 24.0, 30.0, 60.0];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoConstraints {
+    pub device_id: ConstrainValue<String>,
+    pub width: ConstrainRange<u32>,
+    pub height: ConstrainRange<u32>,
+    pub frame_rate: ConstrainRange<f64>,
+    pub facing_mode: ConstrainValue<FacingMode>,
+}
+
+/// No camera mode satisfies the required constraints; names the one that
+/// ruled out the most candidates, like `OverconstrainedError.constraint`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverconstrainedError {
+    pub constraint: &'static str,
+}
+
+impl fmt::Display for OverconstrainedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no camera satisfies the {} constraint", self.constraint)
+    }
+}
+
+impl std::error::Error for OverconstrainedError {}
+
+/// The camera and mode picked for a set of constraints.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoSelection {
+    pub device: DeviceInfo,
+    /// `None` when the camera's modes could not be queried; capture then
+    /// uses whatever the device defaults to.
+    pub mode: Option<CaptureMode>,
+}
+
+impl VideoConstraints {
+    /// Picks the camera and capture mode with the lowest fitness distance
+    /// among those meeting every required constraint.
+    pub fn select(&self, cameras: &[DeviceInfo]) -> Result<VideoSelection, OverconstrainedError> {
+        let mut best: Option<(f64, VideoSelection)> = None;
+        let mut failures = [
+            ("deviceId", 0),
+            ("facingMode", 0),
+            ("width", 0),
+            ("height", 0),
+            ("frameRate", 0),
+        ];
+
+        for camera in cameras {
+            if !self.device_id.satisfied_by(Some(&camera.id)) {
+                failures[0].1 += 1;
+                continue;
+            }
+            if !self.facing_mode.satisfied_by(camera.facing.as_ref()) {
+                failures[1].1 += 1;
+                continue;
+            }
+            let device_distance = self.device_id.distance(Some(&camera.id))
+                + self.facing_mode.distance(camera.facing.as_ref());
+
+            let modes: Vec<Option<&CaptureMode>> = if camera.modes.is_empty() {
+                vec![None]
+            } else {
+                camera.modes.iter().map(Some).collect()
+            };
+            for mode in modes {
+                let distance = match mode {
+                    Some(mode) => {
+                        let checks = [
+                            self.width.satisfied_by(mode.width.into()),
+                            self.height.satisfied_by(mode.height.into()),
+                            self.frame_rate.satisfied_by(mode.frame_rate),
+                        ];
+                        if let Some(failed) = checks.iter().position(|ok| !ok) {
+                            failures[2 + failed].1 += 1;
+                            continue;
+                        }
+                        self.width.distance(mode.width.into())
+                            + self.height.distance(mode.height.into())
+                            + self.frame_rate.distance(mode.frame_rate)
+                    }
+                    // Unknown modes cannot prove they meet a requirement.
+                    None => {
+                        let required = [
+                            self.width.is_required(),
+                            self.height.is_required(),
+                            self.frame_rate.is_required(),
+                        ];
+                        if let Some(failed) = required.iter().position(|r| *r) {
+                            failures[2 + failed].1 += 1;
+                            continue;
+                        }
+                        0.0
+                    }
+                };
+                let total = device_distance + distance;
+                if best.as_ref().is_none_or(|(b, _)| total < *b) {
+                    best = Some((
+                        total,
+                        VideoSelection {
+                            device: camera.clone(),
+                            mode: mode.cloned(),
+                        },
+                    ));
+                }
+            }
+        }
+
+        best.map(|(_, selection)| selection).ok_or_else(|| {
+            let (constraint, _) = failures
+                .iter()
+                .max_by_key(|(_, count)| *count)
+                .copied()
+                .unwrap_or(("deviceId", 0));
+            OverconstrainedError { constraint }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::DeviceKind;
+
+    fn mode(width: u32, height: u32, frame_rate: f64) -> CaptureMode {
+        CaptureMode {
+            width,
+            height,
+            frame_rate,
+            format: "YUYV".to_owned(),
+        }
+    }
+
+    fn camera(id: &str, facing: Option<FacingMode>, modes: Vec<CaptureMode>) -> DeviceInfo {
+        DeviceInfo {
+            kind: DeviceKind::Camera,
+            id: id.to_owned(),
+            name: id.to_owned(),
+            modes,
+            facing,
+            channels: None,
+        }
+    }
+
+    fn webcam() -> DeviceInfo {
+        camera(
+            "webcam",
+            None,
+            vec![
+                mode(640, 360, 30.0),
+                mode(1280, 720, 30.0),
+                mode(1920, 1080, 15.0),
+            ],
+        )
+    }
+
+    fn selected_mode(constraints: &VideoConstraints, cameras: &[DeviceInfo]) -> CaptureMode {
+        constraints.select(cameras).unwrap().mode.unwrap()
+    }
+
+    #[test]
+    fn picks_the_mode_nearest_the_ideal() {
+        let constraints = VideoConstraints {
+            width: ConstrainRange::ideal(1200),
+            height: ConstrainRange::ideal(700),
+            ..Default::default()
+        };
+        assert_eq!(
+            selected_mode(&constraints, &[webcam()]),
+            mode(1280, 720, 30.0)
+        );
+    }
+
+    #[test]
+    fn exact_rules_out_every_other_value() {
+        let constraints = VideoConstraints {
+            width: ConstrainRange::exact(1920),
+            frame_rate: ConstrainRange::ideal(30.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            selected_mode(&constraints, &[webcam()]),
+            mode(1920, 1080, 15.0)
+        );
+
+        let constraints = VideoConstraints {
+            width: ConstrainRange::exact(800),
+            ..Default::default()
+        };
+        assert_eq!(
+            constraints.select(&[webcam()]),
+            Err(OverconstrainedError {
+                constraint: "width"
+            })
+        );
+    }
+
+    #[test]
+    fn min_and_max_bound_the_ideal() {
+        // The ideal alone would pick 360p; the minimum rules it out.
+        let constraints = VideoConstraints {
+            height: ConstrainRange {
+                ideal: Some(360),
+                min: Some(480),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            selected_mode(&constraints, &[webcam()]),
+            mode(1280, 720, 30.0)
+        );
+
+        // And here the maximum rules out 1080p.
+        let constraints = VideoConstraints {
+            height: ConstrainRange {
+                ideal: Some(1080),
+                max: Some(720),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            selected_mode(&constraints, &[webcam()]),
+            mode(1280, 720, 30.0)
+        );
+
+        // Bounds are inclusive.
+        let constraints = VideoConstraints {
+            frame_rate: ConstrainRange {
+                min: Some(15.0),
+                max: Some(15.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            selected_mode(&constraints, &[webcam()]),
+            mode(1920, 1080, 15.0)
+        );
+    }
+
+    #[test]
+    fn names_the_constraint_that_ruled_out_the_most() {
+        let constraints = VideoConstraints {
+            width: ConstrainRange {
+                min: Some(1280),
+                ..Default::default()
+            },
+            frame_rate: ConstrainRange {
+                min: Some(60.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // 360p fails on width; 720p and 1080p on frame rate.
+        assert_eq!(
+            constraints.select(&[webcam()]),
+            Err(OverconstrainedError {
+                constraint: "frameRate"
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_modes_only_meet_ideals() {
+        let unqueried = camera("unqueried", None, vec![]);
+        let selection = VideoConstraints {
+            width: ConstrainRange::ideal(1280),
+            ..Default::default()
+        }
+        .select(std::slice::from_ref(&unqueried))
+        .unwrap();
+        assert_eq!(selection.device, unqueried);
+        assert_eq!(selection.mode, None);
+
+        let constraints = VideoConstraints {
+            width: ConstrainRange {
+                max: Some(1280),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            constraints.select(&[unqueried]),
+            Err(OverconstrainedError {
+                constraint: "width"
+            })
+        );
+    }
+
+    #[test]
+    fn device_constraints_pick_the_camera() {
+        let front = camera("front", Some(FacingMode::User), vec![mode(640, 360, 30.0)]);
+        let back = camera(
+            "back",
+            Some(FacingMode::Environment),
+            vec![mode(1920, 1080, 30.0)],
+        );
+        let cameras = [front, back, webcam()];
+
+        let constraints = VideoConstraints {
+            facing_mode: ConstrainValue::ideal(FacingMode::Environment),
+            width: ConstrainRange::ideal(640),
+            ..Default::default()
+        };
+        assert_eq!(constraints.select(&cameras).unwrap().device.id, "back");
+
+        let constraints = VideoConstraints {
+            device_id: ConstrainValue::exact("front".to_owned()),
+            width: ConstrainRange {
+                min: Some(1280),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            constraints.select(&cameras),
+            Err(OverconstrainedError {
+                constraint: "deviceId"
+            })
+        );
+    }
+}
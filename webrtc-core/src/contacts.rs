@@ -0,0 +1,458 @@
+//! The address book: people and the identities we can reach them at on the
+//! different signaling backends, with vCard import and export.
+//!
+//! vCards carry handles as `IMPP` URIs (`sip:`, `nostr:`, `libp2p:`) and
+//! addresses as `EMAIL`; an email address doubles as a SIP address, which is
+//! what most SIP providers hand out.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// vCard lines longer than this many octets are folded on export.
+const FOLD_AT: usize = 75;
+
+#[derive(Debug, thiserror::Error)]
+pub enum VCardError {
+    #[error("line {0}: property outside BEGIN:VCARD/END:VCARD")]
+    OutsideCard(usize),
+    #[error("line {0}: malformed property")]
+    Malformed(usize),
+    #[error("card ending on line {0} has no name")]
+    Unnamed(usize),
+    #[error("missing END:VCARD")]
+    Unterminated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentityKind {
+    Email,
+    Sip,
+    Nostr,
+    Libp2p,
+}
+
+impl fmt::Display for IdentityKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IdentityKind::Email => "Email",
+            IdentityKind::Sip => "SIP",
+            IdentityKind::Nostr => "Nostr",
+            IdentityKind::Libp2p => "libp2p",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identity {
+    pub kind: IdentityKind,
+    /// An email address, a `sip:` URI, a hex Nostr public key or a `PeerId`.
+    pub value: String,
+}
+
+impl Identity {
+    /// The SIP URI to dial for this identity, if it has one.
+    pub fn sip_uri(&self) -> Option<String> {
+        match self.kind {
+            IdentityKind::Sip => Some(self.value.clone()),
+            IdentityKind::Email => Some(format!("sip:{}", self.value)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contact {
+    pub name: String,
+    pub identities: Vec<Identity>,
+}
+
+impl Contact {
+    fn add_identity(&mut self, identity: Identity) -> bool {
+        if self.identities.contains(&identity) {
+            return false;
+        }
+        self.identities.push(identity);
+        true
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Contacts {
+    pub contacts: Vec<Contact>,
+}
+
+impl Contacts {
+    /// The contact a peer calling as `identity` is, by name or by one of
+    /// their identities. A SIP URI matches the email address it dials.
+    pub fn find(&self, identity: &str) -> Option<&Contact> {
+        let identity = identity.trim();
+        self.contacts.iter().find(|contact| {
+            contact.name.eq_ignore_ascii_case(identity)
+                || contact.identities.iter().any(|known| {
+                    known.value.eq_ignore_ascii_case(identity)
+                        || known
+                            .sip_uri()
+                            .is_some_and(|uri| uri.eq_ignore_ascii_case(identity))
+                })
+        })
+    }
+
+    /// Adds `imported`, merging identities into existing contacts of the
+    /// same name. Returns how many contacts were added or changed.
+    pub fn merge(&mut self, imported: Vec<Contact>) -> usize {
+        let mut changed = 0;
+        for contact in imported {
+            match self.contacts.iter_mut().find(|c| c.name == contact.name) {
+                Some(existing) => {
+                    let mut added = false;
+                    for identity in contact.identities {
+                        added |= existing.add_identity(identity);
+                    }
+                    changed += usize::from(added);
+                }
+                None => {
+                    self.contacts.push(contact);
+                    changed += 1;
+                }
+            }
+        }
+        self.contacts
+            .sort_by_key(|contact| contact.name.to_lowercase());
+        changed
+    }
+}
+
+/// Parses every card in a `.vcf` file. Properties we have no use for are
+/// skipped; cards without any identity are kept so the names come along.
+pub fn parse_vcards(text: &str) -> Result<Vec<Contact>, VCardError> {
+    let mut contacts = vec![];
+    let mut card: Option<(Contact, Option<String>)> = None;
+    for (number, line) in unfold(text) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (name, params, value) = split_property(&line).ok_or(VCardError::Malformed(number))?;
+        if name == "BEGIN" && value.eq_ignore_ascii_case("VCARD") {
+            card = Some((Contact::default(), None));
+            continue;
+        }
+        let Some((contact, structured_name)) = card.as_mut() else {
+            return Err(VCardError::OutsideCard(number));
+        };
+        match name.as_str() {
+            "END" => {
+                let (mut contact, structured_name) = card.take().expect("inside a card");
+                if contact.name.is_empty() {
+                    contact.name = structured_name.ok_or(VCardError::Unnamed(number))?;
+                }
+                contacts.push(contact);
+            }
+            "FN" => contact.name = unescape(&value),
+            "N" => {
+                // Family;Given;Additional;Prefix;Suffix
+                let parts: Vec<String> = split_unescaped(&value, ';');
+                let given = parts.get(1).map_or("", String::as_str);
+                let family = parts.first().map_or("", String::as_str);
+                let name = format!("{} {}", given, family).trim().to_owned();
+                if !name.is_empty() {
+                    *structured_name = Some(name);
+                }
+            }
+            "EMAIL" => {
+                contact.add_identity(Identity {
+                    kind: IdentityKind::Email,
+                    value: unescape(&value).trim_start_matches("mailto:").to_owned(),
+                });
+            }
+            "IMPP" | "X-SIP" | "X-NOSTR" | "X-LIBP2P" => {
+                let value = unescape(&value);
+                if let Some(identity) = parse_handle(&name, &params, &value) {
+                    contact.add_identity(identity);
+                }
+            }
+            _ => {}
+        }
+    }
+    if card.is_some() {
+        return Err(VCardError::Unterminated);
+    }
+    Ok(contacts)
+}
+
+/// Writes contacts as vCard 4.0, one card each.
+pub fn to_vcards(contacts: &[Contact]) -> String {
+    let mut out = String::new();
+    for contact in contacts {
+        push_line(&mut out, "BEGIN:VCARD");
+        push_line(&mut out, "VERSION:4.0");
+        push_line(&mut out, &format!("FN:{}", escape(&contact.name)));
+        for identity in &contact.identities {
+            let line = match identity.kind {
+                IdentityKind::Email => format!("EMAIL:{}", escape(&identity.value)),
+                IdentityKind::Sip => format!("IMPP:{}", escape(&identity.value)),
+                IdentityKind::Nostr => format!("IMPP:nostr:{}", escape(&identity.value)),
+                IdentityKind::Libp2p => format!("IMPP:libp2p:{}", escape(&identity.value)),
+            };
+            push_line(&mut out, &line);
+        }
+        push_line(&mut out, "END:VCARD");
+    }
+    out
+}
+
+/// Joins folded lines (continuations start with a space or tab), keeping
+/// the number of the line each property starts on.
+fn unfold(text: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = vec![];
+    for (index, line) in text.lines().enumerate() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some((_, last))) => last.push_str(rest),
+            _ => lines.push((index + 1, line.to_owned())),
+        }
+    }
+    lines
+}
+
+/// `group.NAME;PARAM=x;TYPE=y:value` into the upper-cased name, the
+/// upper-cased parameters and the raw value.
+fn split_property(line: &str) -> Option<(String, Vec<String>, String)> {
+    let (head, value) = line.split_once(':')?;
+    let mut parts = head.split(';');
+    let name = parts.next()?;
+    let name = name.rsplit('.').next()?.trim().to_ascii_uppercase();
+    if name.is_empty() {
+        return None;
+    }
+    let params = parts.map(|param| param.to_ascii_uppercase()).collect();
+    Some((name, params, value.to_owned()))
+}
+
+fn parse_handle(name: &str, params: &[String], value: &str) -> Option<Identity> {
+    let (scheme, handle) = match name {
+        "X-SIP" => ("sip", value.trim_start_matches("sip:")),
+        "X-NOSTR" => ("nostr", value.trim_start_matches("nostr:")),
+        "X-LIBP2P" => ("libp2p", value.trim_start_matches("libp2p:")),
+        // Older cards put the service in a parameter: IMPP;X-SERVICE-TYPE=sip:...
+        _ => match value.split_once(':') {
+            Some((scheme, handle)) => (scheme, handle),
+            None => (
+                params
+                    .iter()
+                    .find_map(|param| param.strip_prefix("X-SERVICE-TYPE="))
+                    .unwrap_or(""),
+                value,
+            ),
+        },
+    };
+    let handle = handle.trim();
+    if handle.is_empty() {
+        return None;
+    }
+    let (kind, value) = match scheme.to_ascii_lowercase().as_str() {
+        "sip" | "sips" => (
+            IdentityKind::Sip,
+            format!("{}:{}", scheme.to_ascii_lowercase(), handle),
+        ),
+        "nostr" => (IdentityKind::Nostr, handle.to_ascii_lowercase()),
+        "libp2p" => (IdentityKind::Libp2p, handle.to_owned()),
+        _ => return None,
+    };
+    Some(Identity { kind, value })
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Splits a structured value on unescaped `separator`s and unescapes each
+/// component.
+fn split_unescaped(value: &str, separator: char) -> Vec<String> {
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut escaped = false;
+    for c in value.chars() {
+        if escaped {
+            current.push('\\');
+            current.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == separator {
+            parts.push(unescape(&current));
+            current.clear();
+        } else {
+            current.push(c);
+        }
+    }
+    parts.push(unescape(&current));
+    parts
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace('\n', "\\n")
+}
+
+/// Appends a CRLF-terminated line, folded so no physical line exceeds
+/// [`FOLD_AT`] octets, without splitting a UTF-8 sequence.
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > FOLD_AT {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(kind: IdentityKind, value: &str) -> Identity {
+        Identity {
+            kind,
+            value: value.to_owned(),
+        }
+    }
+
+    #[test]
+    fn unfolds_continuation_lines() {
+        let text =
+            "BEGIN:VCARD\r\nFN:Ada Love\r\n lace\r\nEMAIL:ada@\r\n\texample.com\r\nEND:VCARD\r\n";
+        let numbers: Vec<_> = unfold(text).into_iter().map(|(number, _)| number).collect();
+        assert_eq!(numbers, [1, 2, 4, 6]);
+        let contacts = parse_vcards(text).unwrap();
+        assert_eq!(contacts[0].name, "Ada Lovelace");
+        assert_eq!(
+            contacts[0].identities,
+            [identity(IdentityKind::Email, "ada@example.com")]
+        );
+        // Errors count physical lines, folds included.
+        assert!(matches!(
+            parse_vcards("BEGIN:VCARD\nFN:A\n b\nno colon\n"),
+            Err(VCardError::Malformed(4))
+        ));
+    }
+
+    #[test]
+    fn unescapes_values() {
+        let text = "BEGIN:VCARD\nFN:Smith\\, John\\; Jr.\\nSecond\\\\line\nEND:VCARD\n";
+        assert_eq!(
+            parse_vcards(text).unwrap()[0].name,
+            "Smith, John; Jr.\nSecond\\line"
+        );
+        assert_eq!(
+            split_unescaped("O\\;Brien;Pat\\,Jo;;", ';'),
+            ["O;Brien", "Pat,Jo", "", ""]
+        );
+        assert_eq!(
+            split_property("item1.impp;type=home;X-SERVICE-TYPE=sip:alice@example.com"),
+            Some((
+                "IMPP".to_owned(),
+                vec!["TYPE=HOME".to_owned(), "X-SERVICE-TYPE=SIP".to_owned()],
+                "alice@example.com".to_owned()
+            ))
+        );
+        assert_eq!(split_property(".:value"), None);
+    }
+
+    #[test]
+    fn parses_several_cards() {
+        let text = "\
+BEGIN:VCARD
+VERSION:3.0
+FN:Alice
+item1.EMAIL;TYPE=work:mailto:alice@example.com
+IMPP:sips:alice@sip.example.com
+IMPP;X-SERVICE-TYPE=nostr:ABCDEF
+PHOTO:ignored
+END:VCARD
+
+BEGIN:VCARD
+FN:Bob
+X-LIBP2P:12D3KooWBob
+IMPP:xmpp:bob@example.com
+END:VCARD
+";
+        let contacts = parse_vcards(text).unwrap();
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(
+            contacts[0].identities,
+            [
+                identity(IdentityKind::Email, "alice@example.com"),
+                identity(IdentityKind::Sip, "sips:alice@sip.example.com"),
+                identity(IdentityKind::Nostr, "abcdef"),
+            ]
+        );
+        assert_eq!(contacts[1].name, "Bob");
+        assert_eq!(
+            contacts[1].identities,
+            [identity(IdentityKind::Libp2p, "12D3KooWBob")]
+        );
+
+        assert!(matches!(
+            parse_vcards("FN:Stray\n"),
+            Err(VCardError::OutsideCard(1))
+        ));
+        assert!(matches!(
+            parse_vcards("BEGIN:VCARD\nFN:Open\n"),
+            Err(VCardError::Unterminated)
+        ));
+    }
+
+    #[test]
+    fn names_cards_without_fn() {
+        let text = "BEGIN:VCARD\nN:Hopper;Grace;Brewster;Rear Admiral;\nEND:VCARD\n";
+        assert_eq!(parse_vcards(text).unwrap()[0].name, "Grace Hopper");
+        assert!(matches!(
+            parse_vcards("BEGIN:VCARD\nN:;;;;\nEMAIL:x@example.com\nEND:VCARD\n"),
+            Err(VCardError::Unnamed(4))
+        ));
+    }
+
+    #[test]
+    fn round_trips_through_vcards() {
+        let contacts = vec![
+            Contact {
+                name: "Smith, John; \"Jr.\"\nÉtienne".to_owned(),
+                identities: vec![
+                    identity(IdentityKind::Email, "john@example.com"),
+                    identity(IdentityKind::Sip, "sip:john@example.com"),
+                    identity(IdentityKind::Nostr, &"ab".repeat(32)),
+                    identity(IdentityKind::Libp2p, "12D3KooWJohn"),
+                ],
+            },
+            Contact {
+                name: "Zoë ".repeat(30).trim().to_owned(),
+                identities: vec![],
+            },
+        ];
+        let text = to_vcards(&contacts);
+        assert!(text.split("\r\n").all(|line| line.len() <= FOLD_AT));
+        assert!(text.contains("\r\n "));
+        assert_eq!(parse_vcards(&text).unwrap(), contacts);
+    }
+}
@@ -0,0 +1,67 @@
+//! Messages on the reserved "control" data channel.
+//!
+//! Both peers create the channel as a pre-negotiated SCTP stream with a fixed
+//! id, so it opens as soon as the first negotiation completes. Subsequent
+//! offers and answers travel over it instead of the original signaling path.
+//! Both sides also send heartbeats on it, so a remote app that hangs is
+//! noticed even while ICE keeps the connection up, and pings, to time the
+//! data path. Whether the microphone is muted or the camera stopped goes
+//! over it too while it is open.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+pub const CONTROL_LABEL: &str = "control";
+pub const CONTROL_STREAM_ID: u16 = 0;
+
+/// How often each side sends a heartbeat.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+/// Heartbeats missed in a row before the peer is taken for dead.
+pub const MAX_MISSED_HEARTBEATS: u32 = 5;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ControlMessage {
+    Offer {
+        sdp: String,
+    },
+    Answer {
+        sdp: String,
+    },
+    /// Turns down the last offer; the offerer rolls it back.
+    Declined,
+    /// Proves the remote app is still running, even while ICE alone would
+    /// look healthy.
+    Heartbeat {
+        seq: u64,
+    },
+    /// Asks for a `Pong` with the same `seq` straight back, to time the
+    /// data path.
+    Ping {
+        seq: u64,
+    },
+    Pong {
+        seq: u64,
+    },
+    /// The sender muted or unmuted its microphone; the track stays up and
+    /// carries silence meanwhile.
+    MicrophoneMuted {
+        muted: bool,
+    },
+    /// The sender stopped or resumed its video; while stopped the video
+    /// m-line is receive-only and the receiver shows a placeholder.
+    CameraPaused {
+        paused: bool,
+    },
+}
+
+impl ControlMessage {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("control messages always serialize")
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(text)
+    }
+}
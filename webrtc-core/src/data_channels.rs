@@ -0,0 +1,394 @@
+//! Data channels opened by hand, by either side, to try a protocol out:
+//! how each one delivers and what went over it, both ways. The app's own
+//! channels are pre-negotiated on reserved labels and kept out of the way.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::SystemTime;
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use webrtc::data_channel::data_channel_init::RTCDataChannelInit;
+use webrtc::data_channel::RTCDataChannel;
+
+use crate::captions::CAPTIONS_LABEL;
+use crate::chat::CHAT_LABEL;
+use crate::clipboard::CLIPBOARD_LABEL;
+use crate::control::CONTROL_LABEL;
+use crate::file_transfer::TRANSFER_LABEL;
+use crate::log_stream::LOG_LABEL;
+use crate::messaging::TypedMessage;
+use crate::remote_control::INPUT_LABEL;
+use crate::rpc::RPC_LABEL;
+
+/// Messages each channel's log holds before dropping the oldest.
+const MAX_MESSAGES: usize = 200;
+
+/// SCTP carries labels of at most this many bytes (RFC 8832).
+const MAX_LABEL_BYTES: usize = u16::MAX as usize;
+
+/// Most bytes a file snippet takes, as one message every SCTP stack
+/// accepts.
+pub const MAX_SNIPPET_BYTES: usize = 64 * 1024;
+
+/// Labels the app opens channels on itself.
+pub const RESERVED_LABELS: [&str; 8] = [
+    CONTROL_LABEL,
+    LOG_LABEL,
+    CHAT_LABEL,
+    TRANSFER_LABEL,
+    CLIPBOARD_LABEL,
+    INPUT_LABEL,
+    RPC_LABEL,
+    CAPTIONS_LABEL,
+];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LabelError {
+    #[error("a channel needs a label")]
+    Empty,
+    #[error("\"{0}\" is one of the app's own channels")]
+    Reserved(String),
+    #[error("labels are at most {MAX_LABEL_BYTES} bytes")]
+    TooLong,
+}
+
+/// Whether the user can open a channel on `label`.
+pub fn check_label(label: &str) -> Result<(), LabelError> {
+    if label.is_empty() {
+        Err(LabelError::Empty)
+    } else if RESERVED_LABELS.contains(&label) {
+        Err(LabelError::Reserved(label.to_owned()))
+    } else if label.len() > MAX_LABEL_BYTES {
+        Err(LabelError::TooLong)
+    } else {
+        Ok(())
+    }
+}
+
+/// When a channel gives up resending a message. SCTP lets a channel set
+/// one limit or the other, never both (RFC 8831).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Reliability {
+    #[default]
+    Reliable,
+    /// Resent at most this many times. webrtc-rs takes 0 to mean no limit,
+    /// so a channel cannot be opened that never resends.
+    MaxRetransmits(u16),
+    /// Resent for at most this many milliseconds.
+    MaxPacketLifetime(u16),
+}
+
+impl Reliability {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Reliable => "Reliable",
+            Self::MaxRetransmits(_) => "Limited retransmits",
+            Self::MaxPacketLifetime(_) => "Limited lifetime",
+        }
+    }
+}
+
+/// How a channel delivers what is sent on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelOptions {
+    /// Messages arrive in the order they were sent.
+    pub ordered: bool,
+    pub reliability: Reliability,
+}
+
+impl Default for ChannelOptions {
+    fn default() -> Self {
+        Self {
+            ordered: true,
+            reliability: Reliability::Reliable,
+        }
+    }
+}
+
+impl ChannelOptions {
+    /// What to open a channel with to get these options.
+    pub fn init(&self) -> RTCDataChannelInit {
+        let (max_retransmits, max_packet_life_time) = match self.reliability {
+            Reliability::Reliable => (None, None),
+            Reliability::MaxRetransmits(retransmits) => (Some(retransmits), None),
+            Reliability::MaxPacketLifetime(lifetime) => (None, Some(lifetime)),
+        };
+        RTCDataChannelInit {
+            ordered: Some(self.ordered),
+            max_retransmits,
+            max_packet_life_time,
+            ..Default::default()
+        }
+    }
+
+    /// What `channel` was opened with, by whichever side opened it.
+    pub fn of(channel: &RTCDataChannel) -> Self {
+        Self::from_parameters(
+            channel.ordered(),
+            channel.max_retransmits(),
+            channel.max_packet_lifetime(),
+        )
+    }
+
+    fn from_parameters(ordered: bool, max_retransmits: u16, max_packet_lifetime: u16) -> Self {
+        let reliability = if max_retransmits != 0 {
+            Reliability::MaxRetransmits(max_retransmits)
+        } else if max_packet_lifetime != 0 {
+            Reliability::MaxPacketLifetime(max_packet_lifetime)
+        } else {
+            Reliability::Reliable
+        };
+        Self {
+            ordered,
+            reliability,
+        }
+    }
+}
+
+impl fmt::Display for ChannelOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.ordered { "ordered" } else { "unordered" })?;
+        match self.reliability {
+            Reliability::Reliable => write!(f, ", reliable"),
+            Reliability::MaxRetransmits(retransmits) => {
+                write!(f, ", at most {} retransmits", retransmits)
+            }
+            Reliability::MaxPacketLifetime(lifetime) => write!(f, ", within {} ms", lifetime),
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum HexError {
+    #[error("{0:?} is not a hex digit")]
+    NotHex(char),
+    #[error("\"{0}\" has an odd number of digits")]
+    OddLength(String),
+}
+
+/// The bytes written out in `hex`: pairs of digits, in groups split by
+/// spaces, commas, colons or dashes, each group with or without "0x".
+pub fn parse_hex(hex: &str) -> Result<Vec<u8>, HexError> {
+    let mut bytes = Vec::new();
+    let groups = hex
+        .split(|c: char| c.is_whitespace() || matches!(c, ',' | ':' | '-'))
+        .filter(|group| !group.is_empty());
+    for group in groups {
+        let digits = group
+            .strip_prefix("0x")
+            .or_else(|| group.strip_prefix("0X"))
+            .unwrap_or(group);
+        if let Some(c) = digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(HexError::NotHex(c));
+        }
+        if digits.len() % 2 != 0 {
+            return Err(HexError::OddLength(group.to_owned()));
+        }
+        for pair in digits.as_bytes().chunks(2) {
+            let pair = std::str::from_utf8(pair).expect("hex digits are ASCII");
+            bytes.push(u8::from_str_radix(pair, 16).expect("checked to be hex"));
+        }
+    }
+    Ok(bytes)
+}
+
+/// `data` laid out as `hexdump -C` does: sixteen bytes a line, after
+/// their offset and before them as ASCII.
+pub fn hexdump(data: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, bytes) in data.chunks(16).enumerate() {
+        let _ = write!(dump, "{:08x} ", line * 16);
+        for column in 0..16 {
+            if column == 8 {
+                dump.push(' ');
+            }
+            match bytes.get(column) {
+                Some(byte) => {
+                    let _ = write!(dump, " {:02x}", byte);
+                }
+                None => dump.push_str("   "),
+            }
+        }
+        dump.push_str("  |");
+        dump.extend(bytes.iter().map(|&byte| match byte {
+            0x20..=0x7e => byte as char,
+            _ => '.',
+        }));
+        dump.push_str("|\n");
+    }
+    dump.pop();
+    dump
+}
+
+/// Up to `length` bytes of the file at `path` from `offset` on, and no
+/// more than `MAX_SNIPPET_BYTES`.
+pub fn read_snippet(path: &Path, offset: u64, length: usize) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut snippet = Vec::new();
+    file.take(length.min(MAX_SNIPPET_BYTES) as u64)
+        .read_to_end(&mut snippet)?;
+    Ok(snippet)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelMessage {
+    pub at: SystemTime,
+    /// Sent from here rather than received.
+    pub outgoing: bool,
+    /// Sent as text rather than binary.
+    pub is_string: bool,
+    pub data: Vec<u8>,
+}
+
+impl ChannelMessage {
+    /// The message as text, with anything that is not UTF-8 replaced.
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.data)
+    }
+}
+
+/// Sends `message` on `channel` as text or binary, whichever it is.
+pub async fn send(channel: &RTCDataChannel, message: &ChannelMessage) -> Result<(), webrtc::Error> {
+    if message.is_string {
+        channel.send_text(message.text().into_owned()).await?;
+    } else {
+        channel.send(&Bytes::copy_from_slice(&message.data)).await?;
+    }
+    Ok(())
+}
+
+/// Asks the app at the other end to answer with a [`Pong`], to time a
+/// round trip on a channel opened by hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ping {
+    /// When it was sent, in microseconds since the Unix epoch.
+    pub sent_us: u64,
+}
+
+impl TypedMessage for Ping {
+    const KIND: &'static str = "data_channels.ping";
+}
+
+/// The answer to a [`Ping`], carrying its send time back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pong {
+    pub sent_us: u64,
+}
+
+impl TypedMessage for Pong {
+    const KIND: &'static str = "data_channels.pong";
+}
+
+/// What one channel carried: the latest messages, and counts of them all.
+#[derive(Debug, Default)]
+pub struct ChannelLog {
+    messages: VecDeque<ChannelMessage>,
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+impl ChannelLog {
+    pub fn push(&mut self, message: ChannelMessage) {
+        let bytes = message.data.len() as u64;
+        if message.outgoing {
+            self.messages_sent += 1;
+            self.bytes_sent += bytes;
+        } else {
+            self.messages_received += 1;
+            self.bytes_received += bytes;
+        }
+        if self.messages.len() == MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message);
+    }
+
+    pub fn messages(&self) -> impl Iterator<Item = &ChannelMessage> {
+        self.messages.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_everything_and_keeps_the_latest() {
+        let mut log = ChannelLog::default();
+        for n in 0..MAX_MESSAGES + 5 {
+            log.push(ChannelMessage {
+                at: SystemTime::UNIX_EPOCH,
+                outgoing: n % 2 == 0,
+                is_string: true,
+                data: n.to_string().into_bytes(),
+            });
+        }
+        assert_eq!(log.messages().count(), MAX_MESSAGES);
+        assert_eq!(log.messages().next().unwrap().text(), "5");
+        assert_eq!((log.messages_sent, log.messages_received), (103, 102));
+        // Odd numbers up to 204: five of one digit, 45 of two, 52 of three.
+        assert_eq!(log.bytes_received, 5 + 45 * 2 + 52 * 3);
+
+        assert_eq!(check_label("telemetry"), Ok(()));
+        assert_eq!(check_label(""), Err(LabelError::Empty));
+        assert_eq!(
+            check_label("chat"),
+            Err(LabelError::Reserved("chat".to_owned()))
+        );
+    }
+
+    #[test]
+    fn parses_hex_and_dumps_it() {
+        assert_eq!(
+            parse_hex("de ad:BE-ef,0x01 0X0203\n"),
+            Ok(vec![0xde, 0xad, 0xbe, 0xef, 1, 2, 3])
+        );
+        assert_eq!(parse_hex(""), Ok(vec![]));
+        assert_eq!(parse_hex("abc"), Err(HexError::OddLength("abc".to_owned())));
+        assert_eq!(parse_hex("0xzz"), Err(HexError::NotHex('z')));
+
+        let dump = hexdump(b"Hello, data channel\x00\xff");
+        assert_eq!(
+            dump,
+            "00000000  48 65 6c 6c 6f 2c 20 64  61 74 61 20 63 68 61 6e  |Hello, data chan|\n\
+             00000010  6e 65 6c 00 ff                                    |nel..|"
+        );
+        assert_eq!(hexdump(&[]), "");
+    }
+
+    #[test]
+    fn reads_back_the_options_a_channel_was_opened_with() {
+        let lossy = ChannelOptions {
+            ordered: false,
+            reliability: Reliability::MaxRetransmits(2),
+        };
+        let init = lossy.init();
+        assert_eq!(
+            (
+                init.ordered,
+                init.max_retransmits,
+                init.max_packet_life_time
+            ),
+            (Some(false), Some(2), None)
+        );
+        assert_eq!(ChannelOptions::from_parameters(false, 2, 0), lossy);
+        assert_eq!(lossy.to_string(), "unordered, at most 2 retransmits");
+
+        let timed = ChannelOptions::from_parameters(true, 0, 150);
+        assert_eq!(timed.reliability, Reliability::MaxPacketLifetime(150));
+        assert_eq!(timed.to_string(), "ordered, within 150 ms");
+        assert_eq!(
+            ChannelOptions::from_parameters(true, 0, 0),
+            ChannelOptions::default()
+        );
+    }
+}
@@ -0,0 +1,316 @@
+//! Capture and playback device discovery, and matching remembered device
+//! preferences against what is plugged in right now.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(target_os = "linux")]
+pub(crate) mod v4l2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceKind {
+    Camera,
+    Microphone,
+    Speaker,
+}
+
+impl DeviceKind {
+    pub const ALL: [DeviceKind; 3] = [
+        DeviceKind::Camera,
+        DeviceKind::Microphone,
+        DeviceKind::Speaker,
+    ];
+}
+
+impl fmt::Display for DeviceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DeviceKind::Camera => "camera",
+            DeviceKind::Microphone => "microphone",
+            DeviceKind::Speaker => "speaker",
+        })
+    }
+}
+
+/// Which way a camera points, as in `MediaTrackSettings.facingMode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FacingMode {
+    User,
+    Environment,
+    Left,
+    Right,
+}
+
+/// A resolution and frame rate a camera can deliver.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CaptureMode {
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: f64,
+    /// Pixel format fourcc, e.g. `YUYV` or `MJPG`.
+    pub format: String,
+}
+
+impl fmt::Display for CaptureMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} @ {:.0} fps ({})",
+            self.width, self.height, self.frame_rate, self.format
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceInfo {
+    pub kind: DeviceKind,
+    /// Survives reboots and re-plugging where the platform allows it, e.g.
+    /// the `/dev/v4l/by-id` name of a USB camera.
+    pub id: String,
+    pub name: String,
+    /// Capture modes, for cameras whose formats could be queried.
+    pub modes: Vec<CaptureMode>,
+    /// Only known for cameras that report it; desktop webcams do not.
+    pub facing: Option<FacingMode>,
+    /// Input channels, for microphones.
+    pub channels: Option<u16>,
+}
+
+/// A remembered device. The name is kept as well so a device whose id
+/// changed (plugged into another port, say) is still recognised.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DevicePreference {
+    pub id: String,
+    pub name: String,
+}
+
+impl From<&DeviceInfo> for DevicePreference {
+    fn from(device: &DeviceInfo) -> Self {
+        Self {
+            id: device.id.clone(),
+            name: device.name.clone(),
+        }
+    }
+}
+
+/// The outcome of matching a preference against the available devices.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceChoice {
+    pub device: Option<DeviceInfo>,
+    /// Set when the preferred device could not be used.
+    pub notice: Option<String>,
+}
+
+/// Picks the preferred device by id, then by name, and otherwise falls
+/// back to the first available device with a notice saying so.
+pub fn resolve(
+    kind: DeviceKind,
+    preferred: Option<&DevicePreference>,
+    available: &[DeviceInfo],
+) -> DeviceChoice {
+    let Some(preferred) = preferred else {
+        return DeviceChoice {
+            device: available.first().cloned(),
+            notice: None,
+        };
+    };
+    let found = available
+        .iter()
+        .find(|d| d.id == preferred.id)
+        .or_else(|| available.iter().find(|d| d.name == preferred.name));
+    if let Some(device) = found {
+        return DeviceChoice {
+            device: Some(device.clone()),
+            notice: None,
+        };
+    }
+    let fallback = available.first().cloned();
+    let notice = match &fallback {
+        Some(device) => format!(
+            "Preferred {} \"{}\" is not connected; using \"{}\" instead",
+            kind, preferred.name, device.name
+        ),
+        None => format!(
+            "Preferred {} \"{}\" is not connected and no other {} was found",
+            kind, preferred.name, kind
+        ),
+    };
+    DeviceChoice {
+        device: fallback,
+        notice: Some(notice),
+    }
+}
+
+/// Lists the devices of one kind that are currently available.
+pub fn enumerate(kind: DeviceKind) -> Vec<DeviceInfo> {
+    match kind {
+        DeviceKind::Camera => cameras(),
+        DeviceKind::Microphone | DeviceKind::Speaker => audio_devices(kind),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn cameras() -> Vec<DeviceInfo> {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+
+    // Stable names live in /dev/v4l/by-id as symlinks to /dev/videoN.
+    let mut stable_ids: HashMap<PathBuf, String> = HashMap::new();
+    if let Ok(entries) = fs::read_dir("/dev/v4l/by-id") {
+        for entry in entries.flatten() {
+            if let Ok(target) = fs::canonicalize(entry.path()) {
+                stable_ids.insert(target, entry.path().to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    let Ok(entries) = fs::read_dir("/sys/class/video4linux") else {
+        return vec![];
+    };
+    let mut cameras: Vec<DeviceInfo> = entries
+        .flatten()
+        .filter(|entry| {
+            // Only the first node of a device captures; the others carry
+            // metadata.
+            fs::read_to_string(entry.path().join("index"))
+                .map(|index| index.trim() == "0")
+                .unwrap_or(true)
+        })
+        .map(|entry| {
+            let node = PathBuf::from("/dev").join(entry.file_name());
+            let name = fs::read_to_string(entry.path().join("name"))
+                .map(|name| name.trim().to_owned())
+                .unwrap_or_else(|_| node.to_string_lossy().into_owned());
+            let id = stable_ids
+                .get(&node)
+                .cloned()
+                .unwrap_or_else(|| node.to_string_lossy().into_owned());
+            DeviceInfo {
+                kind: DeviceKind::Camera,
+                id,
+                name,
+                modes: v4l2::capture_modes(&node),
+                facing: None,
+                channels: None,
+            }
+        })
+        .collect();
+    cameras.sort_by(|a, b| a.name.cmp(&b.name));
+    cameras
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cameras() -> Vec<DeviceInfo> {
+    vec![]
+}
+
+#[cfg(feature = "audio")]
+fn audio_devices(kind: DeviceKind) -> Vec<DeviceInfo> {
+    use cpal::traits::{DeviceTrait, HostTrait};
+
+    let host = cpal::default_host();
+    let devices = match kind {
+        DeviceKind::Microphone => host.input_devices(),
+        _ => host.output_devices(),
+    };
+    let Ok(devices) = devices else {
+        return vec![];
+    };
+    let cards = std::fs::read_to_string("/proc/asound/cards").unwrap_or_default();
+    // cpal has no persistent device ids; the host plus the device name is
+    // as stable as it gets.
+    devices
+        .filter_map(|device| Some((device.name().ok()?, device)))
+        .map(|(name, device)| DeviceInfo {
+            kind,
+            id: format!("{}:{}", host.id().name(), name),
+            name: alsa_friendly_name(&name, &cards).unwrap_or(name),
+            modes: vec![],
+            facing: None,
+            channels: match kind {
+                DeviceKind::Microphone => crate::audio::input_channels(&device),
+                _ => None,
+            },
+        })
+        .chain(
+            crate::audio::TestSignal::ALL
+                .into_iter()
+                .filter(|_| kind == DeviceKind::Microphone)
+                .map(|signal| DeviceInfo {
+                    kind,
+                    id: signal.device_id().to_owned(),
+                    name: signal.name().to_owned(),
+                    modes: vec![],
+                    facing: None,
+                    channels: Some(1),
+                }),
+        )
+        .collect()
+}
+
+/// ALSA lists devices as `front:CARD=PCH,DEV=0`; this puts the card's own
+/// name from `/proc/asound/cards` in front, as `HDA Intel PCH (front)`.
+/// `None` for names without a card, like `default` or `pipewire`.
+#[cfg(any(feature = "audio", test))]
+fn alsa_friendly_name(name: &str, cards: &str) -> Option<String> {
+    let (plugin, params) = name.split_once(':')?;
+    let mut card = None;
+    let mut device = None;
+    for param in params.split(',') {
+        match param.split_once('=') {
+            Some(("CARD", value)) => card = Some(value),
+            Some(("DEV", value)) => device = Some(value),
+            _ => {}
+        }
+    }
+    let card = card?;
+    // Lines look like ` 0 [PCH            ]: HDA-Intel - HDA Intel PCH`.
+    let card_name = cards.lines().find_map(|line| {
+        let (id, rest) = line.split_once('[')?.1.split_once(']')?;
+        let (_, card_name) = rest.split_once(" - ")?;
+        (id.trim() == card).then(|| card_name.trim())
+    })?;
+    Some(match device {
+        Some(device) if device != "0" => format!("{} ({} {})", card_name, plugin, device),
+        _ => format!("{} ({})", card_name, plugin),
+    })
+}
+
+#[cfg(not(feature = "audio"))]
+fn audio_devices(_kind: DeviceKind) -> Vec<DeviceInfo> {
+    vec![]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARDS: &str = " 0 [PCH            ]: HDA-Intel - HDA Intel PCH
+                      HDA Intel PCH at 0xf7f10000 irq 32
+ 1 [C920           ]: USB-Audio - HD Pro Webcam C920
+                      HD Pro Webcam C920 at usb-0000:00:14.0-2, high speed
+";
+
+    #[test]
+    fn alsa_names_use_the_card_name() {
+        let name = |raw| alsa_friendly_name(raw, CARDS);
+        assert_eq!(
+            name("front:CARD=PCH,DEV=0").as_deref(),
+            Some("HDA Intel PCH (front)")
+        );
+        assert_eq!(
+            name("hdmi:CARD=PCH,DEV=2").as_deref(),
+            Some("HDA Intel PCH (hdmi 2)")
+        );
+        assert_eq!(
+            name("sysdefault:CARD=C920").as_deref(),
+            Some("HD Pro Webcam C920 (sysdefault)")
+        );
+        assert_eq!(name("default"), None);
+        assert_eq!(name("front:CARD=Gone,DEV=0"), None);
+    }
+}
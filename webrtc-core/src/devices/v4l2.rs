@@ -0,0 +1,175 @@
+//! Capture mode enumeration through the V4L2 `VIDIOC_ENUM_*` ioctls. The
+//! ioctl plumbing is shared with capture in `camera`.
+
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+use super::CaptureMode;
+
+pub(crate) const V4L2_BUF_TYPE_VIDEO_CAPTURE: u32 = 1;
+const V4L2_FRMSIZE_TYPE_DISCRETE: u32 = 1;
+const V4L2_FRMIVAL_TYPE_DISCRETE: u32 = 1;
+
+#[repr(C)]
+#[derive(Default)]
+struct FmtDesc {
+    index: u32,
+    typ: u32,
+    flags: u32,
+    description: [u8; 32],
+    pixel_format: u32,
+    mbus_code: u32,
+    reserved: [u32; 3],
+}
+
+/// `v4l2_frmsizeenum`; the union holds either a discrete size or a
+/// stepwise range (min_w, max_w, step_w, min_h, max_h, step_h).
+#[repr(C)]
+#[derive(Default)]
+struct FrameSizeEnum {
+    index: u32,
+    pixel_format: u32,
+    typ: u32,
+    union: [u32; 6],
+    reserved: [u32; 2],
+}
+
+/// `v4l2_frmivalenum`; the union holds a discrete interval or a stepwise
+/// (min, max, step) range of fractions.
+#[repr(C)]
+#[derive(Default)]
+struct FrameIntervalEnum {
+    index: u32,
+    pixel_format: u32,
+    width: u32,
+    height: u32,
+    typ: u32,
+    union: [u32; 6],
+    reserved: [u32; 2],
+}
+
+pub(crate) const fn iowr<T>(nr: u64) -> u64 {
+    (3 << 30) | ((std::mem::size_of::<T>() as u64) << 16) | ((b'V' as u64) << 8) | nr
+}
+
+pub(crate) const fn iow<T>(nr: u64) -> u64 {
+    (1 << 30) | ((std::mem::size_of::<T>() as u64) << 16) | ((b'V' as u64) << 8) | nr
+}
+
+const VIDIOC_ENUM_FMT: u64 = iowr::<FmtDesc>(2);
+const VIDIOC_ENUM_FRAMESIZES: u64 = iowr::<FrameSizeEnum>(74);
+const VIDIOC_ENUM_FRAMEINTERVALS: u64 = iowr::<FrameIntervalEnum>(75);
+
+/// Sizes offered for cameras that report a continuous or stepwise range
+/// instead of a list.
+const COMMON_SIZES: [(u32, u32); 6] = [
+    (320, 240),
+    (640, 360),
+    (640, 480),
+    (1280, 720),
+    (1920, 1080),
+    (3840, 2160),
+];
+
+pub(crate) fn ioctl<T>(file: &File, request: u64, arg: &mut T) -> bool {
+    // SAFETY: every request passed in takes a pointer to the matching repr(C)
+    // struct, which the kernel only reads and writes within its size.
+    unsafe { libc::ioctl(file.as_raw_fd(), request as _, arg as *mut T) == 0 }
+}
+
+pub(crate) fn fourcc(code: u32) -> String {
+    code.to_le_bytes()
+        .iter()
+        .map(|&b| b as char)
+        .collect::<String>()
+        .trim_end()
+        .to_owned()
+}
+
+pub fn capture_modes(node: &Path) -> Vec<CaptureMode> {
+    let Ok(file) = File::open(node) else {
+        return vec![];
+    };
+    let mut modes = vec![];
+    for format_index in 0.. {
+        let mut format = FmtDesc {
+            index: format_index,
+            typ: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            ..Default::default()
+        };
+        if !ioctl(&file, VIDIOC_ENUM_FMT, &mut format) {
+            break;
+        }
+        for (width, height) in frame_sizes(&file, format.pixel_format) {
+            for frame_rate in frame_rates(&file, format.pixel_format, width, height) {
+                modes.push(CaptureMode {
+                    width,
+                    height,
+                    frame_rate,
+                    format: fourcc(format.pixel_format),
+                });
+            }
+        }
+    }
+    modes
+}
+
+fn frame_sizes(file: &File, pixel_format: u32) -> Vec<(u32, u32)> {
+    let mut sizes = vec![];
+    for index in 0.. {
+        let mut size = FrameSizeEnum {
+            index,
+            pixel_format,
+            ..Default::default()
+        };
+        if !ioctl(file, VIDIOC_ENUM_FRAMESIZES, &mut size) {
+            break;
+        }
+        if size.typ == V4L2_FRMSIZE_TYPE_DISCRETE {
+            sizes.push((size.union[0], size.union[1]));
+            continue;
+        }
+        let [min_w, max_w, _, min_h, max_h, _] = size.union;
+        sizes.extend(
+            COMMON_SIZES
+                .iter()
+                .copied()
+                .filter(|&(w, h)| (min_w..=max_w).contains(&w) && (min_h..=max_h).contains(&h)),
+        );
+        break;
+    }
+    sizes
+}
+
+fn frame_rates(file: &File, pixel_format: u32, width: u32, height: u32) -> Vec<f64> {
+    let mut rates = vec![];
+    for index in 0.. {
+        let mut interval = FrameIntervalEnum {
+            index,
+            pixel_format,
+            width,
+            height,
+            ..Default::default()
+        };
+        if !ioctl(file, VIDIOC_ENUM_FRAMEINTERVALS, &mut interval) {
+            break;
+        }
+        // Intervals are seconds per frame as numerator/denominator.
+        let rate = |numerator: u32, denominator: u32| {
+            (numerator > 0).then(|| f64::from(denominator) / f64::from(numerator))
+        };
+        if interval.typ == V4L2_FRMIVAL_TYPE_DISCRETE {
+            rates.extend(rate(interval.union[0], interval.union[1]));
+            continue;
+        }
+        // A range: offer its fastest and slowest ends.
+        rates.extend(rate(interval.union[0], interval.union[1]));
+        rates.extend(rate(interval.union[2], interval.union[3]));
+        break;
+    }
+    if rates.is_empty() {
+        rates.push(30.0);
+    }
+    rates
+}
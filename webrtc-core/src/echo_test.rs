@@ -0,0 +1,345 @@
+//! "Test my setup": a short call against an echo endpoint that reflects
+//! media and data back, producing a pass/fail report.
+//!
+//! The endpoint is reached with a WHIP-style POST of our offer. We send
+//! 20 ms Opus silence frames on an audio track and ping over a data channel;
+//! the echoed packets give loss and jitter for the media path, the pings
+//! give application round-trip time.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use bytes::Bytes;
+use log::info;
+use tokio::sync::mpsc;
+use tokio::time::{timeout, Duration, Instant};
+use webrtc::api::media_engine::MIME_TYPE_OPUS;
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::media::Sample;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+
+use crate::settings::Settings;
+use crate::whip;
+
+/// An Opus packet encoding 20 ms of silence (RFC 6716 §3, code 0 CELT frame).
+const OPUS_SILENCE: [u8; 3] = [0xf8, 0xff, 0xfe];
+const FRAME: Duration = Duration::from_millis(20);
+const MEDIA_DURATION: Duration = Duration::from_secs(5);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const PINGS: u32 = 10;
+
+const MAX_LOSS_PERCENT: f64 = 5.0;
+const MAX_JITTER_MS: f64 = 30.0;
+const MAX_RTT_MS: f64 = 300.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    Skipped,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Fail => "FAIL",
+            CheckStatus::Skipped => "SKIPPED",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EchoTestReport {
+    pub checks: Vec<CheckResult>,
+}
+
+impl EchoTestReport {
+    fn push(&mut self, name: &'static str, status: CheckStatus, detail: impl Into<String>) {
+        self.checks.push(CheckResult {
+            name,
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.status != CheckStatus::Fail)
+    }
+}
+
+/// Arrival statistics for echoed RTP packets.
+#[derive(Default)]
+struct Arrivals {
+    count: u64,
+    last: Option<Instant>,
+    /// RFC 3550 interarrival jitter estimate, in milliseconds.
+    jitter_ms: f64,
+}
+
+pub async fn run(settings: &Settings, endpoint: &str) -> EchoTestReport {
+    let mut report = EchoTestReport::default();
+    // Capture devices are not part of this build yet, so only the network and
+    // media loopback can be exercised.
+    report.push(
+        "Microphone",
+        CheckStatus::Skipped,
+        "no microphone capture available",
+    );
+    report.push(
+        "Camera",
+        CheckStatus::Skipped,
+        "no camera capture available",
+    );
+
+    if endpoint.trim().is_empty() {
+        report.push(
+            "Network",
+            CheckStatus::Fail,
+            "no echo endpoint configured in Settings",
+        );
+        return report;
+    }
+
+    let pc = match settings.api() {
+        Ok(api) => match api
+            .new_peer_connection(settings.rtc_configuration(false))
+            .await
+        {
+            Ok(pc) => Arc::new(pc),
+            Err(err) => {
+                report.push("Network", CheckStatus::Fail, err.to_string());
+                return report;
+            }
+        },
+        Err(err) => {
+            report.push("Network", CheckStatus::Fail, err.to_string());
+            return report;
+        }
+    };
+
+    if let Err(detail) = exercise(&pc, endpoint.trim(), &mut report).await {
+        report.push("Network", CheckStatus::Fail, detail);
+    }
+    if let Err(err) = pc.close().await {
+        info!("Failed to close echo test peer connection: {:?}", err);
+    }
+    report
+}
+
+async fn exercise(
+    pc: &Arc<RTCPeerConnection>,
+    endpoint: &str,
+    report: &mut EchoTestReport,
+) -> Result<(), String> {
+    let track = Arc::new(TrackLocalStaticSample::new(
+        RTCRtpCodecCapability {
+            mime_type: MIME_TYPE_OPUS.to_owned(),
+            clock_rate: 48000,
+            channels: 2,
+            ..Default::default()
+        },
+        "audio".to_owned(),
+        "echo-test".to_owned(),
+    ));
+    pc.add_track(track.clone())
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let arrivals = Arc::new(Mutex::new(Arrivals::default()));
+    let track_arrivals = Arc::clone(&arrivals);
+    pc.on_track(Box::new(move |remote, _, _| {
+        let arrivals = Arc::clone(&track_arrivals);
+        Box::pin(async move {
+            while remote.read_rtp().await.is_ok() {
+                let now = Instant::now();
+                let mut arrivals = arrivals.lock().unwrap();
+                if let Some(last) = arrivals.last {
+                    let deviation = (now.duration_since(last).as_secs_f64() * 1000.0 - 20.0).abs();
+                    arrivals.jitter_ms += (deviation - arrivals.jitter_ms) / 16.0;
+                }
+                arrivals.last = Some(now);
+                arrivals.count += 1;
+            }
+        })
+    }));
+
+    let channel = pc
+        .create_data_channel("echo-test", None)
+        .await
+        .map_err(|err| err.to_string())?;
+    let (pong_tx, mut pong_rx) = mpsc::unbounded_channel::<u32>();
+    channel.on_message(Box::new(move |message: DataChannelMessage| {
+        if let Some(n) = std::str::from_utf8(&message.data)
+            .ok()
+            .and_then(|text| text.strip_prefix("ping:"))
+            .and_then(|n| n.parse().ok())
+        {
+            let _ = pong_tx.send(n);
+        }
+        Box::pin(async {})
+    }));
+
+    let (connected_tx, mut connected_rx) = mpsc::unbounded_channel();
+    pc.on_peer_connection_state_change(Box::new(move |state| {
+        let _ = connected_tx.send(state);
+        Box::pin(async {})
+    }));
+
+    let started = Instant::now();
+    let offer = pc.create_offer(None).await.map_err(|err| err.to_string())?;
+    let mut gathered = pc.gathering_complete_promise().await;
+    pc.set_local_description(offer)
+        .await
+        .map_err(|err| err.to_string())?;
+    let _ = gathered.recv().await;
+    let offer = pc.local_description().await.ok_or("no local description")?;
+
+    let client = reqwest::Client::new();
+    let session = whip::post_offer(&client, endpoint, &offer.sdp, None)
+        .await
+        .map_err(|err| err.to_string())?;
+    let answer =
+        RTCSessionDescription::answer(session.answer.clone()).map_err(|err| err.to_string())?;
+    pc.set_remote_description(answer)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let connected = timeout(CONNECT_TIMEOUT, async {
+        while let Some(state) = connected_rx.recv().await {
+            match state {
+                RTCPeerConnectionState::Connected => return true,
+                RTCPeerConnectionState::Failed | RTCPeerConnectionState::Closed => return false,
+                _ => {}
+            }
+        }
+        false
+    })
+    .await
+    .unwrap_or(false);
+    if !connected {
+        return Err(format!(
+            "could not connect within {}s",
+            CONNECT_TIMEOUT.as_secs()
+        ));
+    }
+    report.push(
+        "Network",
+        CheckStatus::Pass,
+        format!("connected in {} ms", started.elapsed().as_millis()),
+    );
+
+    // Media loopback and data channel pings run concurrently.
+    let sent = Arc::new(AtomicU64::new(0));
+    let media = {
+        let sent = Arc::clone(&sent);
+        async move {
+            let mut ticker = tokio::time::interval(FRAME);
+            let end = Instant::now() + MEDIA_DURATION;
+            while Instant::now() < end {
+                ticker.tick().await;
+                let sample = Sample {
+                    data: Bytes::from_static(&OPUS_SILENCE),
+                    timestamp: SystemTime::now(),
+                    duration: FRAME,
+                    ..Default::default()
+                };
+                if track.write_sample(&sample).await.is_ok() {
+                    sent.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            // Give the last echoed packets time to arrive.
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    };
+    let pings = async {
+        let mut rtts = vec![];
+        for n in 0..PINGS {
+            let sent_at = Instant::now();
+            if channel.send_text(format!("ping:{}", n)).await.is_err() {
+                break;
+            }
+            let reply = timeout(Duration::from_secs(2), async {
+                while let Some(pong) = pong_rx.recv().await {
+                    if pong == n {
+                        return true;
+                    }
+                }
+                false
+            })
+            .await;
+            if reply == Ok(true) {
+                rtts.push(sent_at.elapsed().as_secs_f64() * 1000.0);
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+        rtts
+    };
+    let ((), rtts) = tokio::join!(media, pings);
+
+    let sent = sent.load(Ordering::Relaxed);
+    let (received, jitter_ms) = {
+        let arrivals = arrivals.lock().unwrap();
+        (arrivals.count, arrivals.jitter_ms)
+    };
+    if sent == 0 || received == 0 {
+        report.push(
+            "Media loopback",
+            CheckStatus::Fail,
+            format!("sent {} packets, none echoed back", sent),
+        );
+    } else {
+        let loss = 100.0 * sent.saturating_sub(received) as f64 / sent as f64;
+        let status = if loss <= MAX_LOSS_PERCENT && jitter_ms <= MAX_JITTER_MS {
+            CheckStatus::Pass
+        } else {
+            CheckStatus::Fail
+        };
+        report.push(
+            "Media loopback",
+            status,
+            format!(
+                "{}/{} packets echoed, {:.1}% loss, {:.1} ms jitter",
+                received, sent, loss, jitter_ms
+            ),
+        );
+    }
+
+    if rtts.is_empty() {
+        report.push(
+            "Data round trip",
+            CheckStatus::Fail,
+            "no data channel echo received",
+        );
+    } else {
+        let mean = rtts.iter().sum::<f64>() / rtts.len() as f64;
+        let status = if mean <= MAX_RTT_MS {
+            CheckStatus::Pass
+        } else {
+            CheckStatus::Fail
+        };
+        report.push(
+            "Data round trip",
+            status,
+            format!("{}/{} pings, mean RTT {:.0} ms", rtts.len(), PINGS, mean),
+        );
+    }
+
+    if let Err(err) = whip::delete_session(&client, &session, None).await {
+        info!("Failed to delete echo test session: {:?}", err);
+    }
+    Ok(())
+}
@@ -0,0 +1,165 @@
+//! TURN failover: spots a relay that has gone bad from stats reports, and
+//! keeps its candidates out of the ICE restart offer that replaces it.
+//!
+//! webrtc-rs gathers from the same TURN servers on every restart, so the
+//! degraded relay cannot be dropped from the local agent. Leaving its
+//! candidates out of the offer means the peer never checks them, which in
+//! practice moves the session to one of the other configured servers.
+
+use tokio::net::lookup_host;
+use webrtc::ice::candidate::{CandidatePairState, CandidateType};
+use webrtc::stats::{StatsReport, StatsReportType};
+
+use crate::settings::TurnServer;
+
+/// Reports in a row a relay must miss the thresholds before we give up on it,
+/// so a single slow sample does not trigger a restart.
+const BAD_SAMPLES: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FailoverThresholds {
+    pub max_rtt_ms: f64,
+    /// Fraction of packets the peer reports lost, from 0 to 1.
+    pub max_loss: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RelayEvent {
+    /// The relay we send through has been over a threshold for a while.
+    Degraded {
+        address: String,
+        rtt_ms: Option<f64>,
+        loss: f64,
+    },
+    /// The session moved off a relay we gave up on.
+    Replaced { from: String, to: String },
+}
+
+pub struct RelayMonitor {
+    thresholds: FailoverThresholds,
+    /// Address of the local relay candidate on the selected pair.
+    relay: Option<String>,
+    bad_samples: u32,
+    /// The relay we reported as degraded, until the session leaves it.
+    abandoned: Option<String>,
+}
+
+impl RelayMonitor {
+    pub fn new(thresholds: FailoverThresholds) -> Self {
+        Self {
+            thresholds,
+            relay: None,
+            bad_samples: 0,
+            abandoned: None,
+        }
+    }
+
+    /// Feeds one stats report. A relay is reported as degraded once; the
+    /// next event is the session moving to another candidate.
+    pub fn check(&mut self, report: &StatsReport) -> Option<RelayEvent> {
+        let pair = report.reports.values().find_map(|stats| match stats {
+            StatsReportType::CandidatePair(pair)
+                if pair.nominated && pair.state == CandidatePairState::Succeeded =>
+            {
+                Some(pair)
+            }
+            _ => None,
+        })?;
+        let local = report.reports.values().find_map(|stats| match stats {
+            StatsReportType::LocalCandidate(candidate)
+                if candidate.id == pair.local_candidate_id =>
+            {
+                Some(candidate)
+            }
+            _ => None,
+        })?;
+
+        if let Some(from) = &self.abandoned {
+            if local.candidate_type != CandidateType::Relay || &local.ip != from {
+                let to = format!("{} {}", local.candidate_type, local.ip);
+                let from = self.abandoned.take().unwrap_or_default();
+                self.relay = None;
+                self.bad_samples = 0;
+                return Some(RelayEvent::Replaced { from, to });
+            }
+            return None;
+        }
+        if local.candidate_type != CandidateType::Relay {
+            self.relay = None;
+            self.bad_samples = 0;
+            return None;
+        }
+        if self.relay.as_ref() != Some(&local.ip) {
+            self.relay = Some(local.ip.clone());
+            self.bad_samples = 0;
+        }
+
+        let rtt_ms = Some(pair.current_round_trip_time * 1000.0).filter(|rtt| *rtt > 0.0);
+        let loss = report
+            .reports
+            .values()
+            .filter_map(|stats| match stats {
+                StatsReportType::RemoteInboundRTP(rtp) => Some(rtp.fraction_lost),
+                _ => None,
+            })
+            .fold(0.0, f64::max);
+        let degraded = rtt_ms.is_some_and(|rtt| rtt > self.thresholds.max_rtt_ms)
+            || loss > self.thresholds.max_loss;
+        if !degraded {
+            self.bad_samples = 0;
+            return None;
+        }
+        self.bad_samples += 1;
+        if self.bad_samples < BAD_SAMPLES {
+            return None;
+        }
+        self.abandoned = Some(local.ip.clone());
+        Some(RelayEvent::Degraded {
+            address: local.ip.clone(),
+            rtt_ms,
+            loss,
+        })
+    }
+}
+
+/// Drops the relay candidates allocated at `address` from an SDP.
+pub fn strip_relay_candidates(sdp: &str, address: &str) -> String {
+    sdp.split_inclusive('\n')
+        .filter(|line| {
+            let Some(candidate) = line.trim_end().strip_prefix("a=candidate:") else {
+                return true;
+            };
+            // foundation component transport priority address port typ type
+            let fields: Vec<&str> = candidate.split_whitespace().collect();
+            !(fields.get(4) == Some(&address) && fields.get(7) == Some(&"relay"))
+        })
+        .collect()
+}
+
+/// The host of a `turn:` or `turns:` URL.
+fn turn_host(url: &str) -> Option<&str> {
+    let rest = url.trim().split_once(':')?.1;
+    let rest = rest.split('?').next()?;
+    if let Some(bracketed) = rest.strip_prefix('[') {
+        return bracketed.split(']').next();
+    }
+    rest.split(':').next().filter(|host| !host.is_empty())
+}
+
+/// Which configured TURN server allocated a relay address, by resolving the
+/// server hosts. Servers that hand out addresses on another interface are
+/// not recognised.
+pub async fn turn_server_for(servers: &[TurnServer], address: &str) -> Option<String> {
+    for server in servers {
+        let Some(host) = turn_host(&server.url) else {
+            continue;
+        };
+        let Ok(mut resolved) = lookup_host((host, 0)).await else {
+            continue;
+        };
+        if resolved.any(|resolved| resolved.ip().to_string() == address) {
+            return Some(server.url.trim().to_owned());
+        }
+    }
+    None
+}
@@ -0,0 +1,682 @@
+//! Sending files to the peer over their own pre-negotiated "files" data
+//! channel. The sender offers a file by name, size and SHA-256; once the
+//! receiver has picked where to save it, the file follows as binary
+//! messages, and the receiver checks the hash before keeping it.
+//!
+//! A folder goes the same way, offered as a manifest of every file in it
+//! with its path, size and hash. Once accepted, the files follow one after
+//! another in manifest order, so the receiver splits them by size alone,
+//! and rebuilds the tree under the folder the user picks.
+
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+use webrtc::data_channel::RTCDataChannel;
+
+use crate::send_queue::SendQueue;
+
+pub const TRANSFER_LABEL: &str = "files";
+pub const TRANSFER_STREAM_ID: u16 = 3;
+
+/// Bytes in each binary message, well under what every SCTP stack takes
+/// in one.
+pub const CHUNK_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Error)]
+pub enum TransferError {
+    #[error("file error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("data channel error: {0}")]
+    Channel(#[from] webrtc::Error),
+    #[error("the peer sent more than the {0} bytes it offered")]
+    TooLong(u64),
+    #[error("the file does not match the hash it was offered with")]
+    HashMismatch,
+    #[error("{0} changed since it was offered")]
+    Changed(String),
+    #[error("the folder offered has a path outside it: {0}")]
+    UnsafePath(String),
+}
+
+/// What the receiver is asked to take.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileOffer {
+    /// As the sender has it; see `file_name` before saving under it.
+    pub name: String,
+    pub size: u64,
+    /// Lowercase hex.
+    pub sha256: String,
+}
+
+impl FileOffer {
+    /// Reads the file at `path` through to hash it, so it blocks for a
+    /// while on a large one.
+    pub fn for_file(path: &Path) -> Result<Self, TransferError> {
+        let mut file = File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 64 * 1024];
+        let mut size = 0;
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            size += read as u64;
+        }
+        let name = path.file_name().map_or("file".to_owned(), |name| {
+            name.to_string_lossy().into_owned()
+        });
+        Ok(Self {
+            name,
+            size,
+            sha256: hex(&hasher.finalize()),
+        })
+    }
+
+    /// The name to suggest saving under: the peer's, without any folders
+    /// in it, so it cannot point anywhere but where the user picks.
+    pub fn file_name(&self) -> String {
+        bare_name(&self.name, "file")
+    }
+}
+
+/// The last part of `name`, or `fallback` when that is empty or a step
+/// up or nowhere.
+fn bare_name(name: &str, fallback: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+    match name {
+        "" | "." | ".." => fallback.to_owned(),
+        name => name.to_owned(),
+    }
+}
+
+/// One file of a folder on offer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Inside the folder, with `/` between its parts.
+    pub path: String,
+    pub size: u64,
+    /// Lowercase hex.
+    pub sha256: String,
+}
+
+/// A folder on offer: every file in it, in the order they are sent, and
+/// the folders with nothing in them, which would otherwise go missing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FolderManifest {
+    pub name: String,
+    pub files: Vec<ManifestEntry>,
+    pub empty_folders: Vec<String>,
+}
+
+impl FolderManifest {
+    /// Walks the folder at `root`, hashing every file, so it blocks for a
+    /// while on a large one. Links are left out rather than followed.
+    pub fn for_folder(root: &Path) -> Result<Self, TransferError> {
+        let mut manifest = Self {
+            name: root.file_name().map_or("folder".to_owned(), |name| {
+                name.to_string_lossy().into_owned()
+            }),
+            files: Vec::new(),
+            empty_folders: Vec::new(),
+        };
+        manifest.walk(root, "")?;
+        Ok(manifest)
+    }
+
+    fn walk(&mut self, dir: &Path, prefix: &str) -> Result<(), TransferError> {
+        let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        if entries.is_empty() && !prefix.is_empty() {
+            self.empty_folders
+                .push(prefix.trim_end_matches('/').to_owned());
+        }
+        for entry in entries {
+            let path = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            let kind = entry.file_type()?;
+            if kind.is_dir() {
+                self.walk(&entry.path(), &format!("{}/", path))?;
+            } else if kind.is_file() {
+                let offer = FileOffer::for_file(&entry.path())?;
+                self.files.push(ManifestEntry {
+                    path,
+                    size: offer.size,
+                    sha256: offer.sha256,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// All the files together.
+    pub fn size(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+
+    /// The name to save under, like [`FileOffer::file_name`].
+    pub fn folder_name(&self) -> String {
+        bare_name(&self.name, "folder")
+    }
+}
+
+/// `path` from a manifest as a relative path, as long as it stays inside
+/// the folder.
+fn relative_path(path: &str) -> Result<PathBuf, TransferError> {
+    let unsafe_path = || TransferError::UnsafePath(path.to_owned());
+    let mut relative = PathBuf::new();
+    for part in path.split('/') {
+        // A backslash or drive would be a separator or root on Windows.
+        if part.contains(['\\', ':']) {
+            return Err(unsafe_path());
+        }
+        match Path::new(part).components().collect::<Vec<_>>()[..] {
+            [Component::Normal(part)] => relative.push(part),
+            _ => return Err(unsafe_path()),
+        }
+    }
+    Ok(relative)
+}
+
+/// What to do when the folder being received is already there.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Conflict {
+    /// Save it next to that one, numbered.
+    #[default]
+    Rename,
+    /// Save into that one, replacing files of the same name.
+    Overwrite,
+    /// Save into that one, keeping files already there.
+    Skip,
+}
+
+impl Conflict {
+    pub const ALL: [Conflict; 3] = [Conflict::Rename, Conflict::Overwrite, Conflict::Skip];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Conflict::Rename => "Save as a new folder",
+            Conflict::Overwrite => "Replace files",
+            Conflict::Skip => "Keep files",
+        }
+    }
+}
+
+/// `path`, or the first of `name (2)`, `name (3)`... not taken.
+fn unused_path(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    (2..)
+        .map(|n| path.with_file_name(format!("{} ({})", name, n)))
+        .find(|candidate| !candidate.exists())
+        .expect("some number is not taken")
+}
+
+/// The file of a folder now arriving.
+enum Current {
+    /// Written beside where it goes, and moved there once it checks out,
+    /// so a damaged one does not replace what was there.
+    File {
+        incoming: Box<IncomingFile>,
+        target: PathBuf,
+    },
+    /// Already there, and kept; this many bytes are read past.
+    Skip(u64),
+}
+
+/// How receiving a folder went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FolderResult {
+    pub root: PathBuf,
+    pub saved: usize,
+    /// Kept as they were, rather than replaced.
+    pub skipped: usize,
+    /// Paths in the manifest of the files that did not match their hash,
+    /// which were not kept.
+    pub damaged: Vec<String>,
+}
+
+/// A folder being rebuilt as the files in it arrive.
+pub struct IncomingFolder {
+    manifest: FolderManifest,
+    /// Where each file of the manifest goes.
+    targets: Vec<PathBuf>,
+    conflict: Conflict,
+    /// The manifest entry arriving now.
+    next: usize,
+    current: Option<Current>,
+    received: u64,
+    result: FolderResult,
+}
+
+impl IncomingFolder {
+    /// Creates the folder, and the ones inside it, under `parent`.
+    pub fn create(
+        parent: &Path,
+        manifest: FolderManifest,
+        conflict: Conflict,
+    ) -> Result<Self, TransferError> {
+        let mut root = parent.join(manifest.folder_name());
+        if conflict == Conflict::Rename {
+            root = unused_path(&root);
+        }
+        let targets = manifest
+            .files
+            .iter()
+            .map(|file| relative_path(&file.path).map(|path| root.join(path)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let empty_folders = manifest
+            .empty_folders
+            .iter()
+            .map(|folder| relative_path(folder).map(|path| root.join(path)))
+            .collect::<Result<Vec<_>, _>>()?;
+        std::fs::create_dir_all(&root)?;
+        for folder in targets
+            .iter()
+            .filter_map(|target| target.parent())
+            .chain(empty_folders.iter().map(PathBuf::as_path))
+        {
+            std::fs::create_dir_all(folder)?;
+        }
+        let mut folder = Self {
+            manifest,
+            targets,
+            conflict,
+            next: 0,
+            current: None,
+            received: 0,
+            result: FolderResult {
+                root,
+                saved: 0,
+                skipped: 0,
+                damaged: Vec::new(),
+            },
+        };
+        folder.advance()?;
+        Ok(folder)
+    }
+
+    pub fn manifest(&self) -> &FolderManifest {
+        &self.manifest
+    }
+
+    /// Bytes of all the files together.
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// Whether every file has arrived.
+    pub fn is_done(&self) -> bool {
+        self.current.is_none()
+    }
+
+    /// Starts on the next file that has anything to arrive, finishing any
+    /// empty ones on the way.
+    fn advance(&mut self) -> Result<(), TransferError> {
+        while self.current.is_none() && self.next < self.manifest.files.len() {
+            let entry = self.manifest.files[self.next].clone();
+            let target = self.targets[self.next].clone();
+            self.next += 1;
+            if self.conflict == Conflict::Skip && target.exists() {
+                self.result.skipped += 1;
+                if entry.size > 0 {
+                    self.current = Some(Current::Skip(entry.size));
+                }
+                continue;
+            }
+            let mut part = target.clone().into_os_string();
+            part.push(".part");
+            let offer = FileOffer {
+                name: entry.path,
+                size: entry.size,
+                sha256: entry.sha256,
+            };
+            let incoming = Box::new(IncomingFile::create(Path::new(&part), offer)?);
+            self.current = Some(Current::File { incoming, target });
+            if entry.size == 0 {
+                self.finish_current()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Done with the file arriving now, keeping it if it checks out.
+    fn finish_current(&mut self) -> Result<(), TransferError> {
+        let Some(Current::File { incoming, target }) = self.current.take() else {
+            return Ok(());
+        };
+        let name = incoming.offer().name.clone();
+        match incoming.finish() {
+            Ok(part) => {
+                std::fs::rename(part, target)?;
+                self.result.saved += 1;
+            }
+            Err(TransferError::HashMismatch) => self.result.damaged.push(name),
+            Err(err) => return Err(err),
+        }
+        Ok(())
+    }
+
+    /// Writes the next `chunk`, which may run from one file into the next,
+    /// and returns whether the whole folder has arrived.
+    pub fn write(&mut self, mut chunk: &[u8]) -> Result<bool, TransferError> {
+        while !chunk.is_empty() {
+            let left = match &self.current {
+                Some(Current::File { incoming, .. }) => incoming.offer().size - incoming.received(),
+                Some(Current::Skip(left)) => *left,
+                None => return Err(TransferError::TooLong(self.manifest.size())),
+            };
+            let (this, rest) = chunk.split_at(chunk.len().min(left as usize));
+            let finished = match &mut self.current {
+                Some(Current::File { incoming, .. }) => incoming.write(this)?,
+                Some(Current::Skip(left)) => {
+                    *left -= this.len() as u64;
+                    *left == 0
+                }
+                None => false,
+            };
+            if finished {
+                self.finish_current()?;
+            }
+            self.received += this.len() as u64;
+            chunk = rest;
+            self.advance()?;
+        }
+        Ok(self.is_done())
+    }
+
+    /// How it went, once every file has arrived.
+    pub fn finish(self) -> FolderResult {
+        self.result
+    }
+
+    /// Deletes what arrived of the file that was arriving when the
+    /// transfer stopped; the files before it stay.
+    pub fn discard(self) {
+        if let Some(Current::File { incoming, .. }) = self.current {
+            incoming.discard();
+        }
+    }
+}
+
+/// `size` bytes for people: "512 B", "1.5 MB".
+pub fn size_label(size: u64) -> String {
+    const UNITS: [&str; 4] = ["kB", "MB", "GB", "TB"];
+    if size < 1000 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1000.0;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Text messages on the channel; the file itself goes as binary ones.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransferMessage {
+    Offer(FileOffer),
+    OfferFolder(FolderManifest),
+    /// The receiver has somewhere to put it; the sender starts.
+    Accept,
+    Decline,
+    /// Either side stopped the transfer halfway.
+    Cancel,
+    /// Everything offered arrived, and whether it matched the hash.
+    Received {
+        verified: bool,
+    },
+    /// Every file of the folder arrived, and which did not match.
+    FolderReceived {
+        damaged: Vec<String>,
+    },
+}
+
+impl TransferMessage {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("transfer messages always serialize")
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(text)
+    }
+}
+
+/// A file being written as it arrives, hashed on the way.
+pub struct IncomingFile {
+    offer: FileOffer,
+    path: PathBuf,
+    file: BufWriter<File>,
+    hasher: Sha256,
+    received: u64,
+}
+
+impl IncomingFile {
+    pub fn create(path: &Path, offer: FileOffer) -> Result<Self, TransferError> {
+        Ok(Self {
+            offer,
+            path: path.to_path_buf(),
+            file: BufWriter::new(File::create(path)?),
+            hasher: Sha256::new(),
+            received: 0,
+        })
+    }
+
+    pub fn offer(&self) -> &FileOffer {
+        &self.offer
+    }
+
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// Writes the next `chunk`, and returns whether the file is whole.
+    pub fn write(&mut self, chunk: &[u8]) -> Result<bool, TransferError> {
+        if self.received + chunk.len() as u64 > self.offer.size {
+            return Err(TransferError::TooLong(self.offer.size));
+        }
+        self.file.write_all(chunk)?;
+        self.hasher.update(chunk);
+        self.received += chunk.len() as u64;
+        Ok(self.received == self.offer.size)
+    }
+
+    /// Checks what arrived against the offer's hash, and returns where it
+    /// was saved. A file that does not match is deleted.
+    pub fn finish(mut self) -> Result<PathBuf, TransferError> {
+        self.file.flush()?;
+        drop(self.file);
+        if hex(&self.hasher.finalize()) != self.offer.sha256 {
+            let _ = std::fs::remove_file(&self.path);
+            return Err(TransferError::HashMismatch);
+        }
+        Ok(self.path)
+    }
+
+    /// Deletes what arrived of a transfer that stopped halfway.
+    pub fn discard(self) {
+        drop(self.file);
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Sends the file at `path` in `CHUNK_SIZE` binary messages on `channel`,
+/// adding each one's length to `sent`. It keeps to the pace the channel
+/// drains at, so only so much of the file is held in memory.
+pub async fn send_file(
+    channel: &RTCDataChannel,
+    path: &Path,
+    sent: &AtomicU64,
+) -> Result<(), TransferError> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut queue = SendQueue::new(channel).await;
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        queue.send(&Bytes::copy_from_slice(&buffer[..read])).await?;
+        sent.fetch_add(read as u64, Ordering::Relaxed);
+    }
+}
+
+/// Sends every file of `manifest`, from the folder at `root`, one after
+/// another like [`send_file`]. Each must still have the size it was
+/// offered with, or the receiver would split them in the wrong places.
+pub async fn send_folder(
+    channel: &RTCDataChannel,
+    root: &Path,
+    manifest: &FolderManifest,
+    sent: &AtomicU64,
+) -> Result<(), TransferError> {
+    let mut queue = SendQueue::new(channel).await;
+    let mut buffer = vec![0; CHUNK_SIZE];
+    for entry in &manifest.files {
+        let path = root.join(relative_path(&entry.path)?);
+        let changed = || TransferError::Changed(entry.path.clone());
+        let file = tokio::fs::File::open(&path).await?;
+        if file.metadata().await?.len() != entry.size {
+            return Err(changed());
+        }
+        let mut file = file.take(entry.size);
+        let mut left = entry.size;
+        while left > 0 {
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                return Err(changed());
+            }
+            queue.send(&Bytes::copy_from_slice(&buffer[..read])).await?;
+            left -= read as u64;
+            sent.fetch_add(read as u64, Ordering::Relaxed);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_a_file_that_matches_its_offer() {
+        let dir = std::env::temp_dir();
+        let source = dir.join(format!("transfer-source-{}", std::process::id()));
+        let contents: Vec<u8> = (0..40_000u32).map(|n| n as u8).collect();
+        std::fs::write(&source, &contents).unwrap();
+        let offer = FileOffer::for_file(&source).unwrap();
+        assert_eq!(offer.size, 40_000);
+        assert_eq!(offer.sha256.len(), 64);
+
+        let target = dir.join(format!("transfer-target-{}", std::process::id()));
+        let mut incoming = IncomingFile::create(&target, offer.clone()).unwrap();
+        let chunks: Vec<_> = contents.chunks(CHUNK_SIZE).collect();
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(!incoming.write(chunk).unwrap());
+        }
+        assert!(incoming.write(chunks[chunks.len() - 1]).unwrap());
+        assert!(matches!(
+            incoming.write(&[0]),
+            Err(TransferError::TooLong(40_000))
+        ));
+        assert_eq!(incoming.finish().unwrap(), target);
+        assert_eq!(std::fs::read(&target).unwrap(), contents);
+
+        let mut corrupt = IncomingFile::create(&target, offer).unwrap();
+        corrupt.write(&vec![0; 40_000]).unwrap();
+        assert!(matches!(corrupt.finish(), Err(TransferError::HashMismatch)));
+        assert!(!target.exists());
+        std::fs::remove_file(&source).unwrap();
+    }
+
+    #[test]
+    fn rebuilds_a_folder_from_its_manifest() {
+        let dir = std::env::temp_dir().join(format!("transfer-folder-{}", std::process::id()));
+        let source = dir.join("photos");
+        std::fs::create_dir_all(source.join("2024/empty")).unwrap();
+        std::fs::write(source.join("a.txt"), "alpha").unwrap();
+        std::fs::write(source.join("2024/b.bin"), vec![7; 20_000]).unwrap();
+        std::fs::write(source.join("2024/none"), "").unwrap();
+        let manifest = FolderManifest::for_folder(&source).unwrap();
+        let paths: Vec<_> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["2024/b.bin", "2024/none", "a.txt"]);
+        assert_eq!(manifest.empty_folders, ["2024/empty"]);
+        assert_eq!(manifest.size(), 20_005);
+
+        let stream: Vec<u8> = [vec![7; 20_000], b"alpha".to_vec()].concat();
+        let target = dir.join("received");
+        let receive = |conflict, stream: &[u8]| {
+            let mut incoming = IncomingFolder::create(&target, manifest.clone(), conflict).unwrap();
+            // Chunks that straddle the files.
+            for chunk in stream.chunks(CHUNK_SIZE) {
+                incoming.write(chunk).unwrap();
+            }
+            assert!(incoming.is_done());
+            incoming.finish()
+        };
+        let first = receive(Conflict::Rename, &stream);
+        assert_eq!(first.root, target.join("photos"));
+        assert_eq!((first.saved, first.skipped), (3, 0));
+        assert_eq!(std::fs::read(first.root.join("a.txt")).unwrap(), b"alpha");
+        assert!(first.root.join("2024/empty").is_dir());
+        assert!(!first.root.join("a.txt.part").exists());
+
+        assert_eq!(
+            receive(Conflict::Rename, &stream).root,
+            target.join("photos (2)")
+        );
+        let kept = receive(Conflict::Skip, &stream);
+        assert_eq!(
+            (kept.root, kept.saved, kept.skipped),
+            (first.root.clone(), 0, 3)
+        );
+        let damaged: Vec<u8> = [vec![7; 20_000], b"alpho".to_vec()].concat();
+        let replaced = receive(Conflict::Overwrite, &damaged);
+        assert_eq!(replaced.damaged, ["a.txt"]);
+        // A damaged file does not replace the one already there.
+        assert_eq!(std::fs::read(first.root.join("a.txt")).unwrap(), b"alpha");
+
+        let mut sneaky = manifest.clone();
+        sneaky.files[0].path = "../outside".to_owned();
+        assert!(matches!(
+            IncomingFolder::create(&target, sneaky, Conflict::Rename),
+            Err(TransferError::UnsafePath(_))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn saves_under_the_name_alone() {
+        let offer = |name: &str| FileOffer {
+            name: name.to_owned(),
+            size: 0,
+            sha256: String::new(),
+        };
+        assert_eq!(offer("notes.txt").file_name(), "notes.txt");
+        assert_eq!(offer("../../.bashrc").file_name(), ".bashrc");
+        assert_eq!(offer("C:\\Users\\x\\a.png").file_name(), "a.png");
+        assert_eq!(offer("dir/..").file_name(), "file");
+        assert_eq!(size_label(512), "512 B");
+        assert_eq!(size_label(1_500_000), "1.5 MB");
+        assert_eq!(
+            TransferMessage::from_json(&TransferMessage::Offer(offer("a")).to_json()).unwrap(),
+            TransferMessage::Offer(offer("a"))
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! Text messages too large to trust to a single SCTP message, since stacks
+//! differ in what they accept, go over in pieces and are put back together
+//! on arrival. Each piece starts with a control character no JSON message
+//! starts with, then the message's id, the piece's index and how many
+//! there are. Smaller messages go over as they are.
+
+use std::collections::HashMap;
+use std::str::Utf8Error;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use thiserror::Error;
+use webrtc::data_channel::RTCDataChannel;
+
+/// Largest text sent as one message; anything longer goes in pieces.
+pub const CHUNK_THRESHOLD: usize = 16 * 1024;
+
+/// Largest message put back together, so a peer cannot make this end
+/// hold any amount.
+pub const MAX_REASSEMBLED_BYTES: usize = 16 * 1024 * 1024;
+
+/// Starts every piece.
+const MARK: char = '\u{1}';
+
+/// Text in each piece, leaving room for the header.
+const PIECE_BYTES: usize = CHUNK_THRESHOLD - 64;
+
+/// Messages being put back together at once, on one channel. Starting
+/// another gives up on the one started longest ago, whose pieces may
+/// never come.
+const MAX_ASSEMBLING: usize = 8;
+
+/// Tells apart the pieces of messages sent at the same time.
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FramingError {
+    #[error("not text: {0}")]
+    NotText(#[from] Utf8Error),
+    #[error("malformed piece header")]
+    BadHeader,
+    #[error("a message of more than {MAX_REASSEMBLED_BYTES} bytes")]
+    TooLarge,
+}
+
+/// `text` as the messages to send, in order.
+pub fn split(text: String) -> Vec<String> {
+    // A short text that looks like a piece still goes as one.
+    if text.len() <= CHUNK_THRESHOLD && !text.starts_with(MARK) {
+        return vec![text];
+    }
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let mut pieces = Vec::new();
+    let mut rest = text.as_str();
+    while !rest.is_empty() {
+        let mut end = rest.len().min(PIECE_BYTES);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (piece, after) = rest.split_at(end);
+        pieces.push(piece);
+        rest = after;
+    }
+    let count = pieces.len();
+    pieces
+        .into_iter()
+        .enumerate()
+        .map(|(index, piece)| format!("{MARK}{id} {index} {count}\n{piece}"))
+        .collect()
+}
+
+/// Sends `text` on `channel`, in pieces if it is long.
+pub async fn send_text(channel: &RTCDataChannel, text: String) -> Result<(), webrtc::Error> {
+    for piece in split(text) {
+        channel.send_text(piece).await?;
+    }
+    Ok(())
+}
+
+struct Partial {
+    pieces: Vec<Option<String>>,
+    arrived: usize,
+    bytes: usize,
+    /// When its first piece came, counted in messages started.
+    started: u64,
+}
+
+/// Puts the pieces arriving on one channel back together.
+#[derive(Default)]
+pub struct Reassembler {
+    partial: HashMap<u32, Partial>,
+    started: u64,
+}
+
+impl Reassembler {
+    /// The text in `data`, once all of it is here: a whole message right
+    /// away, one in pieces when its last piece arrives.
+    pub fn receive(&mut self, data: &[u8]) -> Result<Option<String>, FramingError> {
+        let text = std::str::from_utf8(data)?;
+        let Some(piece) = text.strip_prefix(MARK) else {
+            return Ok(Some(text.to_owned()));
+        };
+        let (header, piece) = piece.split_once('\n').ok_or(FramingError::BadHeader)?;
+        let mut fields = header.split(' ').map(str::parse::<usize>);
+        let (Some(Ok(id)), Some(Ok(index)), Some(Ok(count)), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(FramingError::BadHeader);
+        };
+        let id = u32::try_from(id).map_err(|_| FramingError::BadHeader)?;
+        if index >= count {
+            return Err(FramingError::BadHeader);
+        }
+        if count > MAX_REASSEMBLED_BYTES / PIECE_BYTES + 1 {
+            return Err(FramingError::TooLarge);
+        }
+        if !self.partial.contains_key(&id) {
+            if self.partial.len() == MAX_ASSEMBLING {
+                let oldest = self
+                    .partial
+                    .iter()
+                    .min_by_key(|(_, partial)| partial.started)
+                    .map(|(&oldest, _)| oldest)
+                    .expect("full");
+                self.partial.remove(&oldest);
+            }
+            self.started += 1;
+        }
+        let started = self.started;
+        let partial = self.partial.entry(id).or_insert_with(|| Partial {
+            pieces: vec![None; count],
+            arrived: 0,
+            bytes: 0,
+            started,
+        });
+        if partial.pieces.len() != count {
+            return Err(FramingError::BadHeader);
+        }
+        if partial.pieces[index].is_none() {
+            partial.arrived += 1;
+            partial.bytes += piece.len();
+            partial.pieces[index] = Some(piece.to_owned());
+        }
+        if partial.bytes > MAX_REASSEMBLED_BYTES {
+            self.partial.remove(&id);
+            return Err(FramingError::TooLarge);
+        }
+        if partial.arrived < count {
+            return Ok(None);
+        }
+        let partial = self.partial.remove(&id).expect("just looked up");
+        Ok(Some(partial.pieces.into_iter().flatten().collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_long_text_and_puts_it_back_in_any_order() {
+        let mut reassembler = Reassembler::default();
+        let short = "short".to_owned();
+        assert_eq!(split(short), ["short"]);
+        assert_eq!(reassembler.receive(b"short"), Ok(Some("short".to_owned())));
+
+        // Multi-byte characters, so some piece boundary falls inside one.
+        let long = "héllo wörld ".repeat(5000);
+        let mut pieces = split(long.clone());
+        assert_eq!(pieces.len(), long.len().div_ceil(PIECE_BYTES));
+        assert!(pieces.iter().all(|piece| piece.len() <= CHUNK_THRESHOLD));
+        pieces.swap(0, 2);
+        let last = pieces.pop().unwrap();
+        for piece in &pieces {
+            assert_eq!(reassembler.receive(piece.as_bytes()), Ok(None));
+        }
+        // A repeat changes nothing.
+        assert_eq!(reassembler.receive(pieces[0].as_bytes()), Ok(None));
+        assert_eq!(reassembler.receive(last.as_bytes()), Ok(Some(long)));
+
+        let odd = format!("{MARK}looks like a piece");
+        let pieces = split(odd.clone());
+        assert_eq!(pieces.len(), 1);
+        assert_eq!(reassembler.receive(pieces[0].as_bytes()), Ok(Some(odd)));
+
+        assert_eq!(
+            reassembler.receive(format!("{MARK}1 2 2\nx").as_bytes()),
+            Err(FramingError::BadHeader)
+        );
+        assert_eq!(
+            reassembler.receive(format!("{MARK}1 0 99999999\nx").as_bytes()),
+            Err(FramingError::TooLarge)
+        );
+        assert!(matches!(
+            reassembler.receive(&[0xff]),
+            Err(FramingError::NotText(_))
+        ));
+    }
+
+    #[test]
+    fn gives_up_on_the_oldest_message_when_too_many_are_unfinished() {
+        let mut reassembler = Reassembler::default();
+        let piece = |id: usize, index: usize| format!("{MARK}{id} {index} 2\n{id}.{index} ");
+        for id in 0..=MAX_ASSEMBLING {
+            assert_eq!(reassembler.receive(piece(id, 0).as_bytes()), Ok(None));
+        }
+        // The first was dropped to make room for the last, and starts over.
+        assert_eq!(reassembler.receive(piece(0, 1).as_bytes()), Ok(None));
+        assert_eq!(
+            reassembler.receive(piece(MAX_ASSEMBLING, 1).as_bytes()),
+            Ok(Some(format!("{0}.0 {0}.1 ", MAX_ASSEMBLING)))
+        );
+        assert_eq!(
+            reassembler.receive(piece(0, 0).as_bytes()),
+            Ok(Some("0.0 0.1 ".to_owned()))
+        );
+    }
+}
@@ -0,0 +1,297 @@
+//! Chat and file transfers kept between calls, in a SQLite database, under
+//! the identity of the peer they were with. Calling the same peer again
+//! brings back what was said before.
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection};
+use thiserror::Error;
+
+use crate::chat::{ChatEntry, ChatMessage};
+
+/// Chat messages brought back when a peer calls again.
+pub const RECALLED_MESSAGES: usize = 200;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS chat (
+        peer TEXT NOT NULL,
+        id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        text TEXT NOT NULL,
+        sent_at INTEGER NOT NULL,
+        local INTEGER NOT NULL,
+        PRIMARY KEY (peer, id)
+    );
+    CREATE INDEX IF NOT EXISTS chat_by_time ON chat (peer, sent_at);
+    CREATE TABLE IF NOT EXISTS transfers (
+        peer TEXT NOT NULL,
+        at INTEGER NOT NULL,
+        outgoing INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        outcome TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS transfers_by_time ON transfers (peer, at);
+";
+
+#[derive(Debug, Error)]
+pub enum HistoryError {
+    #[error("history database: {0}")]
+    Database(#[from] rusqlite::Error),
+}
+
+/// A file or folder that went either way, and how that ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferRecord {
+    /// Milliseconds since the Unix epoch.
+    pub at: u64,
+    pub outgoing: bool,
+    pub name: String,
+    pub size: u64,
+    /// Such as "received" or "declined by the peer".
+    pub outcome: String,
+}
+
+impl TransferRecord {
+    pub fn now(outgoing: bool, name: &str, size: u64, outcome: &str) -> Self {
+        Self {
+            at: now_ms(),
+            outgoing,
+            name: name.to_owned(),
+            size,
+            outcome: outcome.to_owned(),
+        }
+    }
+}
+
+/// Everything kept for one peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerHistory {
+    pub peer: String,
+    pub messages: u64,
+    pub transfers: u64,
+    /// When anything was last kept for them, in milliseconds since the
+    /// Unix epoch.
+    pub last_at: u64,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+pub struct History {
+    db: Connection,
+}
+
+impl History {
+    /// Opens the database at `path`, creating it if need be.
+    pub fn open(path: &Path) -> Result<Self, HistoryError> {
+        let db = Connection::open(path)?;
+        // One write per message; the journal keeps them cheap.
+        db.pragma_update(None, "journal_mode", "WAL")?;
+        db.pragma_update(None, "synchronous", "NORMAL")?;
+        Self::with(db)
+    }
+
+    /// A database that goes away with this.
+    pub fn in_memory() -> Result<Self, HistoryError> {
+        Self::with(Connection::open_in_memory()?)
+    }
+
+    fn with(db: Connection) -> Result<Self, HistoryError> {
+        db.execute_batch(SCHEMA)?;
+        Ok(Self { db })
+    }
+
+    /// Keeps `entry` for `peer`, unless it already is.
+    pub fn add_chat(&self, peer: &str, entry: &ChatEntry) -> Result<(), HistoryError> {
+        let message = &entry.message;
+        self.db.execute(
+            "INSERT OR IGNORE INTO chat (peer, id, name, text, sent_at, local)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                peer,
+                message.id,
+                message.name,
+                message.text,
+                message.sent_at as i64,
+                entry.local
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The last `limit` messages with `peer`, oldest first.
+    pub fn chat(&self, peer: &str, limit: usize) -> Result<Vec<ChatEntry>, HistoryError> {
+        let mut query = self.db.prepare(
+            "SELECT id, name, text, sent_at, local FROM chat WHERE peer = ?1
+             ORDER BY sent_at DESC LIMIT ?2",
+        )?;
+        let mut entries = query
+            .query_map(params![peer, limit as i64], |row| {
+                Ok(ChatEntry {
+                    message: ChatMessage {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        text: row.get(2)?,
+                        sent_at: row.get::<_, i64>(3)? as u64,
+                    },
+                    local: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.reverse();
+        Ok(entries)
+    }
+
+    pub fn add_transfer(&self, peer: &str, record: &TransferRecord) -> Result<(), HistoryError> {
+        self.db.execute(
+            "INSERT INTO transfers (peer, at, outgoing, name, size, outcome)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                peer,
+                record.at as i64,
+                record.outgoing,
+                record.name,
+                record.size as i64,
+                record.outcome
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The last `limit` transfers with `peer`, newest first.
+    pub fn transfers(&self, peer: &str, limit: usize) -> Result<Vec<TransferRecord>, HistoryError> {
+        let mut query = self.db.prepare(
+            "SELECT at, outgoing, name, size, outcome FROM transfers WHERE peer = ?1
+             ORDER BY at DESC LIMIT ?2",
+        )?;
+        let records = query
+            .query_map(params![peer, limit as i64], |row| {
+                Ok(TransferRecord {
+                    at: row.get::<_, i64>(0)? as u64,
+                    outgoing: row.get(1)?,
+                    name: row.get(2)?,
+                    size: row.get::<_, i64>(3)? as u64,
+                    outcome: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(records)
+    }
+
+    /// Every peer anything is kept for, the latest first.
+    pub fn peers(&self) -> Result<Vec<PeerHistory>, HistoryError> {
+        let mut query = self.db.prepare(
+            "SELECT peer, SUM(messages), SUM(transfers), MAX(last_at) FROM (
+                 SELECT peer, COUNT(*) AS messages, 0 AS transfers, MAX(sent_at) AS last_at
+                 FROM chat GROUP BY peer
+                 UNION ALL
+                 SELECT peer, 0, COUNT(*), MAX(at) FROM transfers GROUP BY peer
+             ) GROUP BY peer ORDER BY MAX(last_at) DESC",
+        )?;
+        let peers = query
+            .query_map([], |row| {
+                Ok(PeerHistory {
+                    peer: row.get(0)?,
+                    messages: row.get::<_, i64>(1)? as u64,
+                    transfers: row.get::<_, i64>(2)? as u64,
+                    last_at: row.get::<_, i64>(3)? as u64,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(peers)
+    }
+
+    /// Forgets everything kept for `peer`, or for everyone.
+    pub fn purge(&self, peer: Option<&str>) -> Result<(), HistoryError> {
+        match peer {
+            Some(peer) => {
+                self.db
+                    .execute("DELETE FROM chat WHERE peer = ?1", params![peer])?;
+                self.db
+                    .execute("DELETE FROM transfers WHERE peer = ?1", params![peer])?;
+            }
+            None => self
+                .db
+                .execute_batch("DELETE FROM chat; DELETE FROM transfers;")?,
+        }
+        // Deleted rows would otherwise linger in the file.
+        self.db.execute_batch("VACUUM")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, sent_at: u64, local: bool) -> ChatEntry {
+        ChatEntry {
+            message: ChatMessage {
+                id: id.to_owned(),
+                name: "alice".to_owned(),
+                text: format!("message {}", id),
+                sent_at,
+            },
+            local,
+        }
+    }
+
+    #[test]
+    fn keeps_each_peer_apart_until_purged() {
+        let history = History::in_memory().unwrap();
+        history.add_chat("alice", &entry("b", 20, false)).unwrap();
+        history.add_chat("alice", &entry("a", 10, true)).unwrap();
+        history.add_chat("alice", &entry("a", 10, true)).unwrap();
+        history.add_chat("alice", &entry("c", 30, false)).unwrap();
+        history.add_chat("bob", &entry("z", 5, false)).unwrap();
+
+        let recent: Vec<_> = history
+            .chat("alice", 2)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.message.id)
+            .collect();
+        assert_eq!(recent, ["b", "c"]);
+        assert!(history.chat("alice", 10).unwrap()[0].local);
+
+        let record = TransferRecord {
+            at: 40,
+            outgoing: true,
+            name: "notes.txt".to_owned(),
+            size: 512,
+            outcome: "received".to_owned(),
+        };
+        history.add_transfer("bob", &record).unwrap();
+        assert_eq!(history.transfers("bob", 10).unwrap(), [record]);
+
+        let peers = history.peers().unwrap();
+        assert_eq!(
+            peers,
+            [
+                PeerHistory {
+                    peer: "bob".to_owned(),
+                    messages: 1,
+                    transfers: 1,
+                    last_at: 40,
+                },
+                PeerHistory {
+                    peer: "alice".to_owned(),
+                    messages: 3,
+                    transfers: 0,
+                    last_at: 30,
+                },
+            ]
+        );
+
+        history.purge(Some("alice")).unwrap();
+        assert!(history.chat("alice", 10).unwrap().is_empty());
+        assert_eq!(history.peers().unwrap()[0].peer, "bob");
+        history.purge(None).unwrap();
+        assert!(history.peers().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,312 @@
+//! Client for the Janus WebSocket API (`janus-protocol`): sessions, plugin
+//! handles, plugin messages with JSEP and trickle ICE.
+
+pub mod videoroom;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use serde_json::{json, Value};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{interval, Duration};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+
+/// Janus drops sessions that are silent for 60 seconds by default.
+const KEEPALIVE: Duration = Duration::from_secs(25);
+
+#[derive(Debug, thiserror::Error)]
+pub enum JanusError {
+    #[error("WebSocket error: {0}")]
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    #[error("Janus error {code}: {reason}")]
+    Janus { code: i64, reason: String },
+    #[error("unexpected reply from Janus: {0}")]
+    Protocol(String),
+    #[error("Janus connection is closed")]
+    Closed,
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for JanusError {
+    fn from(err: tokio_tungstenite::tungstenite::Error) -> Self {
+        JanusError::WebSocket(Box::new(err))
+    }
+}
+
+/// An asynchronous event for one plugin handle.
+#[derive(Debug, Clone)]
+pub struct HandleEvent {
+    /// `event`, `webrtcup`, `media`, `hangup`, `slowlink`, `detached`, ...
+    pub janus: String,
+    /// `plugindata.data` of plugin events.
+    pub data: Value,
+    pub jsep: Option<Value>,
+}
+
+/// A plugin reply: its data and, for negotiation requests, the JSEP.
+#[derive(Debug, Clone)]
+pub struct PluginReply {
+    pub data: Value,
+    pub jsep: Option<Value>,
+}
+
+type Pending = Arc<Mutex<HashMap<String, oneshot::Sender<Value>>>>;
+type Handles = Arc<Mutex<HashMap<u64, mpsc::UnboundedSender<HandleEvent>>>>;
+
+/// A Janus session over one WebSocket. Dropping every clone ends it.
+#[derive(Clone)]
+pub struct JanusSession {
+    session_id: u64,
+    outgoing: mpsc::UnboundedSender<Value>,
+    pending: Pending,
+    handles: Handles,
+}
+
+impl JanusSession {
+    pub async fn connect(url: &str) -> Result<Self, JanusError> {
+        let mut request = url.into_client_request()?;
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static("janus-protocol"),
+        );
+        let (stream, _) = tokio_tungstenite::connect_async(request).await?;
+        info!("Connected to Janus at {}", url);
+        let (mut sink, mut source) = stream.split();
+
+        let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel::<Value>();
+        let pending: Pending = Arc::default();
+        let handles: Handles = Arc::default();
+
+        tokio::spawn(async move {
+            while let Some(message) = outgoing_rx.recv().await {
+                if let Err(err) = sink.send(Message::Text(message.to_string())).await {
+                    warn!("Failed to send Janus message: {:?}", err);
+                    break;
+                }
+            }
+            let _ = sink.close().await;
+        });
+
+        {
+            let pending = Arc::clone(&pending);
+            let handles = Arc::clone(&handles);
+            tokio::spawn(async move {
+                while let Some(frame) = source.next().await {
+                    let text = match frame {
+                        Ok(Message::Text(text)) => text,
+                        Ok(Message::Close(_)) | Err(_) => break,
+                        Ok(_) => continue,
+                    };
+                    match serde_json::from_str::<Value>(&text) {
+                        Ok(message) => route(message, &pending, &handles),
+                        Err(err) => warn!("Ignoring malformed Janus message: {}", err),
+                    }
+                }
+                info!("Janus connection closed");
+                // Fail outstanding requests and end handle event streams.
+                pending.lock().unwrap().clear();
+                handles.lock().unwrap().clear();
+            });
+        }
+
+        let mut session = Self {
+            session_id: 0,
+            outgoing,
+            pending,
+            handles,
+        };
+        let reply = session.request(json!({ "janus": "create" })).await?;
+        session.session_id = reply["data"]["id"]
+            .as_u64()
+            .ok_or_else(|| JanusError::Protocol(reply.to_string()))?;
+
+        let keepalive = session.clone();
+        tokio::spawn(async move {
+            let mut ticker = interval(KEEPALIVE);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if keepalive
+                    .send(json!({ "janus": "keepalive" }), &new_transaction())
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+        Ok(session)
+    }
+
+    /// Stamps a message with its transaction and our session, and queues it
+    /// for sending.
+    fn send(&self, mut message: Value, transaction: &str) -> Result<(), JanusError> {
+        message["transaction"] = json!(transaction);
+        if self.session_id != 0 {
+            message["session_id"] = json!(self.session_id);
+        }
+        self.outgoing.send(message).map_err(|_| JanusError::Closed)
+    }
+
+    /// Sends a request and waits for its reply, skipping the `ack` Janus
+    /// sends first for asynchronous requests.
+    async fn request(&self, message: Value) -> Result<Value, JanusError> {
+        let transaction = new_transaction();
+        let (reply_tx, reply_rx) = oneshot::channel();
+        // Register before sending so a fast reply cannot be missed.
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(transaction.clone(), reply_tx);
+        if let Err(err) = self.send(message, &transaction) {
+            self.pending.lock().unwrap().remove(&transaction);
+            return Err(err);
+        }
+        let reply = reply_rx.await.map_err(|_| JanusError::Closed)?;
+        if reply["janus"] == "error" {
+            return Err(JanusError::Janus {
+                code: reply["error"]["code"].as_i64().unwrap_or(0),
+                reason: reply["error"]["reason"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_owned(),
+            });
+        }
+        if let Some(error) = reply["plugindata"]["data"]["error"].as_str() {
+            return Err(JanusError::Janus {
+                code: reply["plugindata"]["data"]["error_code"]
+                    .as_i64()
+                    .unwrap_or(0),
+                reason: error.to_owned(),
+            });
+        }
+        Ok(reply)
+    }
+
+    /// Attaches to a plugin, returning the handle and its event stream.
+    pub async fn attach(
+        &self,
+        plugin: &str,
+    ) -> Result<(PluginHandle, mpsc::UnboundedReceiver<HandleEvent>), JanusError> {
+        let reply = self
+            .request(json!({ "janus": "attach", "plugin": plugin }))
+            .await?;
+        let handle_id = reply["data"]["id"]
+            .as_u64()
+            .ok_or_else(|| JanusError::Protocol(reply.to_string()))?;
+        let (events_tx, events) = mpsc::unbounded_channel();
+        self.handles.lock().unwrap().insert(handle_id, events_tx);
+        Ok((
+            PluginHandle {
+                session: self.clone(),
+                handle_id,
+            },
+            events,
+        ))
+    }
+
+    pub async fn destroy(&self) -> Result<(), JanusError> {
+        self.request(json!({ "janus": "destroy" }))
+            .await
+            .map(|_| ())
+    }
+}
+
+fn new_transaction() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+fn route(message: Value, pending: &Pending, handles: &Handles) {
+    let kind = message["janus"].as_str().unwrap_or_default().to_owned();
+    if kind == "ack" {
+        return;
+    }
+    if let Some(transaction) = message["transaction"].as_str() {
+        if let Some(reply) = pending.lock().unwrap().remove(transaction) {
+            let _ = reply.send(message);
+            return;
+        }
+    }
+    let Some(handle_id) = message["sender"].as_u64() else {
+        return;
+    };
+    let handles = handles.lock().unwrap();
+    if let Some(events) = handles.get(&handle_id) {
+        let _ = events.send(HandleEvent {
+            janus: kind,
+            data: message["plugindata"]["data"].clone(),
+            jsep: message.get("jsep").cloned(),
+        });
+    }
+}
+
+/// A handle attached to one plugin in a session.
+#[derive(Clone)]
+pub struct PluginHandle {
+    session: JanusSession,
+    handle_id: u64,
+}
+
+impl PluginHandle {
+    pub fn id(&self) -> u64 {
+        self.handle_id
+    }
+
+    /// Sends a plugin message, with an optional JSEP, and waits for the
+    /// plugin's reply.
+    pub async fn message(
+        &self,
+        body: Value,
+        jsep: Option<Value>,
+    ) -> Result<PluginReply, JanusError> {
+        let mut message = json!({
+            "janus": "message",
+            "handle_id": self.handle_id,
+            "body": body,
+        });
+        if let Some(jsep) = jsep {
+            message["jsep"] = jsep;
+        }
+        let reply = self.session.request(message).await?;
+        Ok(PluginReply {
+            data: reply["plugindata"]["data"].clone(),
+            jsep: reply.get("jsep").cloned(),
+        })
+    }
+
+    /// Trickles a local candidate, or signals the end of gathering.
+    pub fn trickle(&self, candidate: Option<RTCIceCandidateInit>) -> Result<(), JanusError> {
+        let candidate = match candidate {
+            Some(candidate) => json!({
+                "candidate": candidate.candidate,
+                "sdpMid": candidate.sdp_mid,
+                "sdpMLineIndex": candidate.sdp_mline_index,
+            }),
+            None => json!({ "completed": true }),
+        };
+        self.session.send(
+            json!({
+            "janus": "trickle",
+            "handle_id": self.handle_id,
+            "candidate": candidate,
+            }),
+            &new_transaction(),
+        )
+    }
+
+    pub async fn detach(&self) -> Result<(), JanusError> {
+        self.session.handles.lock().unwrap().remove(&self.handle_id);
+        self.session
+            .request(json!({ "janus": "detach", "handle_id": self.handle_id }))
+            .await
+            .map(|_| ())
+    }
+}
+
+/// Builds a JSEP object for a local description.
+pub fn jsep(sdp_type: &str, sdp: &str) -> Value {
+    json!({ "type": sdp_type, "sdp": sdp })
+}
@@ -0,0 +1,405 @@
+//! Janus VideoRoom: publish into a room and subscribe to every other
+//! publisher, one peer connection per feed.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use log::{info, warn};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use webrtc::api::media_engine::MediaEngine;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+
+use super::{jsep, JanusError, JanusSession, PluginHandle};
+use crate::codec;
+use crate::codecs::{self, CodecOverride};
+use crate::settings::Settings;
+use crate::simulcast::{self, SimulcastTrack};
+use crate::snapshot::Snapshot;
+use crate::video::{self, VideoFrame};
+
+const PLUGIN: &str = "janus.plugin.videoroom";
+const PATTERN_WIDTH: usize = 640;
+const PATTERN_HEIGHT: usize = 480;
+const PATTERN_FPS: f32 = 15.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum VideoRoomError {
+    #[error(transparent)]
+    Janus(#[from] JanusError),
+    #[error("WebRTC error: {0}")]
+    WebRtc(#[from] webrtc::Error),
+    #[error("Janus sent no {0}")]
+    MissingJsep(&'static str),
+}
+
+#[derive(Clone)]
+pub enum RoomEvent {
+    /// Our publisher connection changed state.
+    StateChanged(RTCPeerConnectionState),
+    /// Someone else is publishing; a subscription follows.
+    PublisherJoined {
+        id: u64,
+        display: String,
+    },
+    PublisherLeft {
+        id: u64,
+    },
+    /// Decoded video of publisher `id` is published to `frames`.
+    Subscribed {
+        id: u64,
+        display: String,
+        frames: Snapshot<Option<VideoFrame>>,
+    },
+    Error(String),
+}
+
+struct Subscriber {
+    handle: PluginHandle,
+    pc: Arc<RTCPeerConnection>,
+}
+
+struct Shared {
+    session: JanusSession,
+    settings: Settings,
+    room: u64,
+    private_id: Option<u64>,
+    subscribers: Mutex<HashMap<u64, Subscriber>>,
+    events: mpsc::UnboundedSender<RoomEvent>,
+}
+
+pub struct JanusRoom {
+    shared: Arc<Shared>,
+    publisher: PluginHandle,
+    pc: Arc<RTCPeerConnection>,
+    /// What we publish, when it goes out as simulcast.
+    simulcast: Option<Arc<SimulcastTrack>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl JanusRoom {
+    /// Joins `room` as a publisher. We publish a test pattern when this
+    /// build can encode video, as simulcast if the settings say so, and
+    /// subscribe to everyone already there.
+    pub async fn join(
+        settings: &Settings,
+        url: &str,
+        room: u64,
+        display: &str,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<RoomEvent>), VideoRoomError> {
+        let session = JanusSession::connect(url).await?;
+        let (publisher, mut publisher_events) = session.attach(PLUGIN).await?;
+        let joined = publisher
+            .message(
+                json!({
+                    "request": "join",
+                    "ptype": "publisher",
+                    "room": room,
+                    "display": display,
+                }),
+                None,
+            )
+            .await?;
+        info!("Joined Janus room {} as {}", room, joined.data["id"]);
+
+        let (events_tx, events) = mpsc::unbounded_channel();
+        let shared = Arc::new(Shared {
+            session,
+            settings: settings.clone(),
+            room,
+            private_id: joined.data["private_id"].as_u64(),
+            subscribers: Mutex::default(),
+            events: events_tx,
+        });
+
+        let layers = simulcast::layers_for(PATTERN_WIDTH, PATTERN_HEIGHT);
+        let use_simulcast = settings.media.simulcast && layers.len() > 1;
+        let api = if use_simulcast {
+            let mut media_engine = MediaEngine::default();
+            codecs::register(
+                &mut media_engine,
+                &CodecOverride::default(),
+                &settings.media.codecs,
+                &settings.media.opus,
+            )?;
+            simulcast::register_extensions(&mut media_engine)?;
+            settings.api_with(media_engine)
+        } else {
+            settings.api()?
+        };
+        let pc = Arc::new(
+            api.new_peer_connection(settings.rtc_configuration(false))
+                .await?,
+        );
+        {
+            let events = shared.events.clone();
+            pc.on_peer_connection_state_change(Box::new(move |state| {
+                let _ = events.send(RoomEvent::StateChanged(state));
+                Box::pin(async {})
+            }));
+        }
+        trickle_candidates(&pc, &publisher);
+
+        let mut tasks = vec![];
+        let mut simulcast = None;
+        if let Some(mime_type) = video::encoder_mime_type() {
+            let capability = codec::sending_capability(mime_type, None);
+            if use_simulcast {
+                let track = Arc::new(SimulcastTrack::new(
+                    capability,
+                    "video".to_owned(),
+                    "janus-test-pattern".to_owned(),
+                    layers,
+                ));
+                pc.add_track(track.clone()).await?;
+                tasks.extend(simulcast::spawn_test_pattern(
+                    Arc::clone(&track),
+                    PATTERN_WIDTH,
+                    PATTERN_HEIGHT,
+                    PATTERN_FPS,
+                ));
+                simulcast = Some(track);
+            } else {
+                let track = Arc::new(TrackLocalStaticSample::new(
+                    capability,
+                    "video".to_owned(),
+                    "janus-test-pattern".to_owned(),
+                ));
+                pc.add_track(track.clone()).await?;
+                tasks.extend(video::spawn_test_pattern(
+                    track,
+                    PATTERN_WIDTH,
+                    PATTERN_HEIGHT,
+                    PATTERN_FPS,
+                ));
+            }
+
+            let offer = match &simulcast {
+                Some(track) => simulcast::set_local_offer(&pc, track).await?,
+                None => {
+                    let offer = pc.create_offer(None).await?;
+                    pc.set_local_description(offer.clone()).await?;
+                    offer
+                }
+            };
+            let configured = publisher
+                .message(
+                    json!({ "request": "configure", "audio": false, "video": true }),
+                    Some(jsep("offer", &offer.sdp)),
+                )
+                .await?;
+            let answer = configured
+                .jsep
+                .and_then(|jsep| jsep["sdp"].as_str().map(str::to_owned))
+                .ok_or(VideoRoomError::MissingJsep("answer"))?;
+            pc.set_remote_description(RTCSessionDescription::answer(answer)?)
+                .await?;
+        } else {
+            info!("No video encoder in this build; joining Janus without publishing");
+        }
+
+        for publisher in publishers(&joined.data) {
+            spawn_subscribe(&shared, publisher);
+        }
+
+        {
+            let shared = Arc::clone(&shared);
+            tasks.push(tokio::spawn(async move {
+                while let Some(event) = publisher_events.recv().await {
+                    if event.janus == "hangup" {
+                        let _ = shared
+                            .events
+                            .send(RoomEvent::Error("Janus hung up our publisher".to_owned()));
+                        continue;
+                    }
+                    for publisher in publishers(&event.data) {
+                        spawn_subscribe(&shared, publisher);
+                    }
+                    for key in ["unpublished", "leaving"] {
+                        if let Some(id) = event.data[key].as_u64() {
+                            shared.unsubscribe(id).await;
+                        }
+                    }
+                }
+            }));
+        }
+
+        Ok((
+            Self {
+                shared,
+                publisher,
+                pc,
+                simulcast,
+                tasks,
+            },
+            events,
+        ))
+    }
+
+    /// The layers we publish, when simulcast is on.
+    pub fn simulcast(&self) -> Option<&Arc<SimulcastTrack>> {
+        self.simulcast.as_ref()
+    }
+
+    pub async fn leave(self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        if let Err(err) = self
+            .publisher
+            .message(json!({ "request": "leave" }), None)
+            .await
+        {
+            info!("Failed to leave Janus room: {:?}", err);
+        }
+        let ids: Vec<u64> = self
+            .shared
+            .subscribers
+            .lock()
+            .unwrap()
+            .keys()
+            .copied()
+            .collect();
+        for id in ids {
+            self.shared.unsubscribe(id).await;
+        }
+        if let Err(err) = self.pc.close().await {
+            info!("Failed to close Janus publisher connection: {:?}", err);
+        }
+        if let Err(err) = self.shared.session.destroy().await {
+            info!("Failed to destroy Janus session: {:?}", err);
+        }
+    }
+}
+
+impl Shared {
+    async fn subscribe(&self, id: u64, display: String) -> Result<(), VideoRoomError> {
+        let (handle, _events) = self.session.attach(PLUGIN).await?;
+        let mut body = json!({
+            "request": "join",
+            "ptype": "subscriber",
+            "room": self.room,
+            "feed": id,
+        });
+        if let Some(private_id) = self.private_id {
+            body["private_id"] = json!(private_id);
+        }
+        let joined = handle.message(body, None).await?;
+        let offer = joined
+            .jsep
+            .and_then(|jsep| jsep["sdp"].as_str().map(str::to_owned))
+            .ok_or(VideoRoomError::MissingJsep("offer"))?;
+
+        let mut media_engine = MediaEngine::default();
+        video::register_playback_codecs(&mut media_engine)?;
+        let api = self.settings.api_with(media_engine);
+        let pc = Arc::new(
+            api.new_peer_connection(self.settings.rtc_configuration(false))
+                .await?,
+        );
+        trickle_candidates(&pc, &handle);
+        let frames = Snapshot::default();
+        {
+            let frames = frames.clone();
+            pc.on_track(Box::new(move |track, _, _| {
+                let frames = frames.clone();
+                Box::pin(async move {
+                    if track.kind() == RTPCodecType::Video {
+                        video::play_track(track, frames).await;
+                    } else {
+                        while track.read_rtp().await.is_ok() {}
+                    }
+                })
+            }));
+        }
+
+        pc.set_remote_description(RTCSessionDescription::offer(offer)?)
+            .await?;
+        let answer = pc.create_answer(None).await?;
+        pc.set_local_description(answer.clone()).await?;
+        handle
+            .message(
+                json!({ "request": "start", "room": self.room }),
+                Some(jsep("answer", &answer.sdp)),
+            )
+            .await?;
+
+        self.subscribers
+            .lock()
+            .unwrap()
+            .insert(id, Subscriber { handle, pc });
+        let _ = self.events.send(RoomEvent::Subscribed {
+            id,
+            display,
+            frames,
+        });
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, id: u64) {
+        let Some(subscriber) = self.subscribers.lock().unwrap().remove(&id) else {
+            return;
+        };
+        let _ = self.events.send(RoomEvent::PublisherLeft { id });
+        if let Err(err) = subscriber.pc.close().await {
+            info!("Failed to close subscriber connection: {:?}", err);
+        }
+        if let Err(err) = subscriber.handle.detach().await {
+            info!("Failed to detach subscriber handle: {:?}", err);
+        }
+    }
+}
+
+fn spawn_subscribe(shared: &Arc<Shared>, (id, display): (u64, String)) {
+    let _ = shared.events.send(RoomEvent::PublisherJoined {
+        id,
+        display: display.clone(),
+    });
+    let shared = Arc::clone(shared);
+    tokio::spawn(async move {
+        if let Err(err) = shared.subscribe(id, display).await {
+            warn!("Failed to subscribe to Janus feed {}: {:?}", id, err);
+            let _ = shared.events.send(RoomEvent::Error(format!(
+                "Could not subscribe to feed {}: {}",
+                id, err
+            )));
+        }
+    });
+}
+
+/// The `publishers` list of a join reply or room event.
+fn publishers(data: &Value) -> Vec<(u64, String)> {
+    data["publishers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|publisher| {
+            let id = publisher["id"].as_u64()?;
+            let display = publisher["display"].as_str().unwrap_or_default();
+            Some((id, display.to_owned()))
+        })
+        .collect()
+}
+
+/// Trickles local candidates to Janus as they are gathered.
+fn trickle_candidates(pc: &RTCPeerConnection, handle: &PluginHandle) {
+    let handle = handle.clone();
+    pc.on_ice_candidate(Box::new(move |candidate| {
+        let candidate = match candidate.map(|c| c.to_json()).transpose() {
+            Ok(candidate) => candidate,
+            Err(err) => {
+                warn!("Failed to serialize ICE candidate: {:?}", err);
+                return Box::pin(async {});
+            }
+        };
+        if let Err(err) = handle.trickle(candidate) {
+            warn!("Failed to trickle ICE candidate to Janus: {:?}", err);
+        }
+        Box::pin(async {})
+    }));
+}
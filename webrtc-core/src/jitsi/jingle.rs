@@ -0,0 +1,769 @@
+//! Conversion between Jingle (XEP-0166/0167/0176/0320) and SDP, in the
+//! dialect Jitsi's focus speaks.
+
+use std::fmt::Write as _;
+
+use minidom::Element;
+
+pub const NS_JINGLE: &str = "urn:xmpp:jingle:1";
+pub const NS_RTP: &str = "urn:xmpp:jingle:apps:rtp:1";
+const NS_RTCP_FB: &str = "urn:xmpp:jingle:apps:rtp:rtcp-fb:0";
+const NS_HDREXT: &str = "urn:xmpp:jingle:apps:rtp:rtp-hdrext:0";
+const NS_SSMA: &str = "urn:xmpp:jingle:apps:rtp:ssma:0";
+const NS_GROUPING: &str = "urn:xmpp:jingle:apps:grouping:0";
+const NS_ICE_UDP: &str = "urn:xmpp:jingle:transports:ice-udp:1";
+const NS_DTLS: &str = "urn:xmpp:jingle:apps:dtls:0";
+const NS_SCTP: &str = "urn:xmpp:jingle:transports:dtls-sctp:1";
+
+/// The features we advertise in disco#info so the focus offers us a
+/// session it can describe.
+pub const FEATURES: &[&str] = &[
+    NS_JINGLE,
+    NS_RTP,
+    NS_ICE_UDP,
+    NS_DTLS,
+    "urn:xmpp:jingle:apps:rtp:audio",
+    "urn:xmpp:jingle:apps:rtp:video",
+    "urn:ietf:rfc:4588",
+    "urn:ietf:rfc:5761",
+    "urn:ietf:rfc:5888",
+    "http://jitsi.org/source-name",
+    "http://jitsi.org/receive-multiple-video-streams",
+];
+
+fn children<'a>(element: &'a Element, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+    element.children().filter(move |child| child.name() == name)
+}
+
+fn attr<'a>(element: &'a Element, name: &str) -> &'a str {
+    element.attr(name).unwrap_or_default()
+}
+
+/// The SDP direction, from the initiator's side, of a content's `senders`.
+fn direction(senders: &str) -> &'static str {
+    match senders {
+        "initiator" => "sendonly",
+        "responder" => "recvonly",
+        "none" => "inactive",
+        _ => "sendrecv",
+    }
+}
+
+/// Renders the offer of a `session-initiate` (with any sources added since)
+/// as SDP.
+pub fn to_sdp(jingle: &Element) -> String {
+    let mut sdp = String::from("v=0\r\no=- 1923518516 2 IN IP4 0.0.0.0\r\ns=-\r\nt=0 0\r\n");
+    for group in children(jingle, "group") {
+        let mids: Vec<&str> = children(group, "content")
+            .map(|content| attr(content, "name"))
+            .collect();
+        let _ = write!(
+            sdp,
+            "a=group:{} {}\r\n",
+            attr(group, "semantics"),
+            mids.join(" ")
+        );
+    }
+    sdp.push_str("a=msid-semantic: WMS *\r\n");
+    for content in children(jingle, "content") {
+        media_section(&mut sdp, content);
+    }
+    sdp
+}
+
+fn media_section(sdp: &mut String, content: &Element) {
+    let description = content.get_child("description", NS_RTP);
+    let transport = content.get_child("transport", NS_ICE_UDP);
+    match description {
+        Some(description) => {
+            let ids: Vec<&str> = children(description, "payload-type")
+                .map(|payload| attr(payload, "id"))
+                .collect();
+            let _ = write!(
+                sdp,
+                "m={} 9 UDP/TLS/RTP/SAVPF {}\r\n",
+                attr(description, "media"),
+                ids.join(" ")
+            );
+        }
+        None => sdp.push_str("m=application 9 UDP/DTLS/SCTP webrtc-datachannel\r\n"),
+    }
+    sdp.push_str("c=IN IP4 0.0.0.0\r\na=rtcp:9 IN IP4 0.0.0.0\r\n");
+
+    if let Some(transport) = transport {
+        let _ = write!(
+            sdp,
+            "a=ice-ufrag:{}\r\na=ice-pwd:{}\r\n",
+            attr(transport, "ufrag"),
+            attr(transport, "pwd")
+        );
+        for fingerprint in children(transport, "fingerprint") {
+            let _ = write!(
+                sdp,
+                "a=fingerprint:{} {}\r\na=setup:{}\r\n",
+                attr(fingerprint, "hash"),
+                fingerprint.text().trim(),
+                fingerprint.attr("setup").unwrap_or("actpass")
+            );
+        }
+        for candidate in children(transport, "candidate") {
+            let _ = write!(sdp, "a={}\r\n", candidate_line(candidate));
+        }
+    }
+    let _ = write!(sdp, "a=mid:{}\r\n", attr(content, "name"));
+
+    let Some(description) = description else {
+        let port = transport
+            .and_then(|transport| transport.get_child("sctpmap", NS_SCTP))
+            .and_then(|sctpmap| sctpmap.attr("number"))
+            .unwrap_or("5000");
+        let _ = write!(sdp, "a=sctp-port:{}\r\n", port);
+        return;
+    };
+    let _ = write!(
+        sdp,
+        "a={}\r\n",
+        direction(content.attr("senders").unwrap_or("both"))
+    );
+    if description.has_child("rtcp-mux", NS_RTP) {
+        sdp.push_str("a=rtcp-mux\r\n");
+    }
+    for payload in children(description, "payload-type") {
+        let id = attr(payload, "id");
+        let _ = write!(
+            sdp,
+            "a=rtpmap:{} {}/{}",
+            id,
+            attr(payload, "name"),
+            attr(payload, "clockrate")
+        );
+        match payload.attr("channels") {
+            Some(channels) if channels != "1" => {
+                let _ = write!(sdp, "/{}\r\n", channels);
+            }
+            _ => sdp.push_str("\r\n"),
+        }
+        let parameters: Vec<String> = children(payload, "parameter")
+            .map(|parameter| format!("{}={}", attr(parameter, "name"), attr(parameter, "value")))
+            .collect();
+        if !parameters.is_empty() {
+            let _ = write!(sdp, "a=fmtp:{} {}\r\n", id, parameters.join(";"));
+        }
+        for feedback in payload.children().filter(|c| c.is("rtcp-fb", NS_RTCP_FB)) {
+            let _ = write!(sdp, "a=rtcp-fb:{} {}", id, attr(feedback, "type"));
+            match feedback.attr("subtype") {
+                Some(subtype) => {
+                    let _ = write!(sdp, " {}\r\n", subtype);
+                }
+                None => sdp.push_str("\r\n"),
+            }
+        }
+    }
+    for extension in description
+        .children()
+        .filter(|c| c.is("rtp-hdrext", NS_HDREXT))
+    {
+        let _ = write!(
+            sdp,
+            "a=extmap:{} {}\r\n",
+            attr(extension, "id"),
+            attr(extension, "uri")
+        );
+    }
+    for group in description
+        .children()
+        .filter(|c| c.is("ssrc-group", NS_SSMA))
+    {
+        let ssrcs: Vec<&str> = children(group, "source")
+            .map(|source| attr(source, "ssrc"))
+            .collect();
+        let _ = write!(
+            sdp,
+            "a=ssrc-group:{} {}\r\n",
+            attr(group, "semantics"),
+            ssrcs.join(" ")
+        );
+    }
+    for source in description.children().filter(|c| c.is("source", NS_SSMA)) {
+        let ssrc = attr(source, "ssrc");
+        let mut has_cname = false;
+        for parameter in children(source, "parameter") {
+            let name = attr(parameter, "name");
+            has_cname |= name == "cname";
+            match parameter.attr("value") {
+                Some(value) => {
+                    let _ = write!(sdp, "a=ssrc:{} {}:{}\r\n", ssrc, name, value);
+                }
+                None => {
+                    let _ = write!(sdp, "a=ssrc:{} {}\r\n", ssrc, name);
+                }
+            }
+        }
+        if !has_cname {
+            let _ = write!(sdp, "a=ssrc:{} cname:{}\r\n", ssrc, ssrc);
+        }
+    }
+}
+
+fn candidate_line(candidate: &Element) -> String {
+    let mut line = format!(
+        "candidate:{} {} {} {} {} {} typ {}",
+        attr(candidate, "foundation"),
+        attr(candidate, "component"),
+        attr(candidate, "protocol"),
+        attr(candidate, "priority"),
+        attr(candidate, "ip"),
+        attr(candidate, "port"),
+        attr(candidate, "type")
+    );
+    if let (Some(address), Some(port)) = (candidate.attr("rel-addr"), candidate.attr("rel-port")) {
+        let _ = write!(line, " raddr {} rport {}", address, port);
+    }
+    if let Some(tcp_type) = candidate.attr("tcptype") {
+        let _ = write!(line, " tcptype {}", tcp_type);
+    }
+    let _ = write!(
+        line,
+        " generation {}",
+        candidate.attr("generation").unwrap_or("0")
+    );
+    line
+}
+
+/// Parses an SDP `candidate:` line into a Jingle candidate.
+pub fn candidate_element(line: &str) -> Option<Element> {
+    let line = line.trim().trim_start_matches("a=");
+    let fields: Vec<&str> = line
+        .strip_prefix("candidate:")?
+        .split_whitespace()
+        .collect();
+    if fields.len() < 8 || fields[6] != "typ" {
+        return None;
+    }
+    let mut builder = Element::builder("candidate", NS_ICE_UDP)
+        .attr("foundation", fields[0])
+        .attr("component", fields[1])
+        .attr("protocol", fields[2].to_lowercase())
+        .attr("priority", fields[3])
+        .attr("ip", fields[4])
+        .attr("port", fields[5])
+        .attr("type", fields[7])
+        .attr("network", "0")
+        .attr("id", format!("{:08x}", rand::random::<u32>()));
+    let mut generation = "0";
+    for pair in fields[8..].chunks(2) {
+        if let [key, value] = pair {
+            builder = match *key {
+                "raddr" => builder.attr("rel-addr", *value),
+                "rport" => builder.attr("rel-port", *value),
+                "tcptype" => builder.attr("tcptype", *value),
+                "generation" => {
+                    generation = value;
+                    builder
+                }
+                _ => builder,
+            };
+        }
+    }
+    Some(builder.attr("generation", generation).build())
+}
+
+/// Our side of the session as Jingle contents, for `session-accept`.
+/// `source_name` names our source of each media, as newer focuses require.
+pub fn from_sdp(sdp: &str, source_name: impl Fn(&str) -> String) -> Vec<Element> {
+    let mut sections = sdp.split("\r\nm=").map(|section| section.lines());
+    let session: Vec<&str> = sections.next().map(Iterator::collect).unwrap_or_default();
+    sections
+        .map(|lines| {
+            let lines: Vec<&str> = lines.collect();
+            content(&session, &lines, &source_name)
+        })
+        .collect()
+}
+
+fn content(session: &[&str], lines: &[&str], source_name: &impl Fn(&str) -> String) -> Element {
+    let media = lines[0].split_whitespace().next().unwrap_or_default();
+    let value = |key: &str| {
+        lines.iter().chain(session).find_map(|line| {
+            line.strip_prefix("a=")?
+                .strip_prefix(key)?
+                .strip_prefix(':')
+        })
+    };
+    let values = |key: &'static str| {
+        lines.iter().filter_map(move |line| {
+            line.strip_prefix("a=")?
+                .strip_prefix(key)?
+                .strip_prefix(':')
+        })
+    };
+    let has = |flag: &str| {
+        lines
+            .iter()
+            .any(|line| line.strip_prefix("a=") == Some(flag))
+    };
+    let mid = value("mid").unwrap_or(media);
+
+    let mut transport = Element::builder("transport", NS_ICE_UDP)
+        .attr("ufrag", value("ice-ufrag").unwrap_or_default())
+        .attr("pwd", value("ice-pwd").unwrap_or_default());
+    if let Some((hash, fingerprint)) = value("fingerprint").and_then(|f| f.split_once(' ')) {
+        transport = transport.append(
+            Element::builder("fingerprint", NS_DTLS)
+                .attr("hash", hash)
+                .attr("setup", value("setup").unwrap_or("active"))
+                .append(fingerprint)
+                .build(),
+        );
+    }
+    let candidates = lines
+        .iter()
+        .filter(|line| line.starts_with("a=candidate:"))
+        .filter_map(|line| candidate_element(line));
+    transport = transport.append_all(candidates);
+
+    let mut content = Element::builder("content", NS_JINGLE)
+        .attr("creator", "initiator")
+        .attr("name", mid);
+    if media == "application" {
+        let port = value("sctp-port").unwrap_or("5000");
+        transport = transport.append(
+            Element::builder("sctpmap", NS_SCTP)
+                .attr("number", port)
+                .attr("protocol", "webrtc-datachannel")
+                .attr("streams", "1024")
+                .build(),
+        );
+        return content.append(transport.build()).build();
+    }
+
+    // Our answer's direction, as the responder.
+    let senders = if has("sendrecv") {
+        "both"
+    } else if has("recvonly") {
+        "initiator"
+    } else if has("sendonly") {
+        "responder"
+    } else {
+        "none"
+    };
+    content = content.attr("senders", senders);
+
+    let mut description = Element::builder("description", NS_RTP).attr("media", media);
+    for rtpmap in values("rtpmap") {
+        let Some((id, codec)) = rtpmap.split_once(' ') else {
+            continue;
+        };
+        let mut parts = codec.split('/');
+        let mut payload = Element::builder("payload-type", NS_RTP)
+            .attr("id", id)
+            .attr("name", parts.next().unwrap_or_default())
+            .attr("clockrate", parts.next().unwrap_or_default());
+        if let Some(channels) = parts.next() {
+            payload = payload.attr("channels", channels);
+        }
+        let fmtp = values("fmtp").find_map(|fmtp| fmtp.strip_prefix(id)?.strip_prefix(' '));
+        for parameter in fmtp.into_iter().flat_map(|fmtp| fmtp.split(';')) {
+            if let Some((name, value)) = parameter.trim().split_once('=') {
+                payload = payload.append(
+                    Element::builder("parameter", NS_RTP)
+                        .attr("name", name)
+                        .attr("value", value)
+                        .build(),
+                );
+            }
+        }
+        for feedback in values("rtcp-fb").filter_map(|fb| fb.strip_prefix(id)?.strip_prefix(' ')) {
+            let mut parts = feedback.splitn(2, ' ');
+            let mut element = Element::builder("rtcp-fb", NS_RTCP_FB)
+                .attr("type", parts.next().unwrap_or_default());
+            if let Some(subtype) = parts.next() {
+                element = element.attr("subtype", subtype);
+            }
+            payload = payload.append(element.build());
+        }
+        description = description.append(payload.build());
+    }
+    for extmap in values("extmap") {
+        if let Some((id, uri)) = extmap.split_once(' ') {
+            description = description.append(
+                Element::builder("rtp-hdrext", NS_HDREXT)
+                    .attr("id", id)
+                    .attr("uri", uri.split_whitespace().next().unwrap_or_default())
+                    .build(),
+            );
+        }
+    }
+
+    // Only advertise sources we send.
+    if senders == "both" || senders == "responder" {
+        let mut ssrcs: Vec<&str> = vec![];
+        for ssrc in values("ssrc").filter_map(|ssrc| ssrc.split(' ').next()) {
+            if !ssrcs.contains(&ssrc) {
+                ssrcs.push(ssrc);
+            }
+        }
+        for ssrc in &ssrcs {
+            let mut source = Element::builder("source", NS_SSMA)
+                .attr("ssrc", *ssrc)
+                .attr("name", source_name(media));
+            for attribute in
+                values("ssrc").filter_map(|line| line.strip_prefix(ssrc)?.strip_prefix(' '))
+            {
+                let (name, value) = attribute.split_once(':').unwrap_or((attribute, ""));
+                source = source.append(
+                    Element::builder("parameter", NS_SSMA)
+                        .attr("name", name)
+                        .attr("value", value)
+                        .build(),
+                );
+            }
+            description = description.append(source.build());
+        }
+        for group in values("ssrc-group") {
+            let mut parts = group.split_whitespace();
+            let mut element = Element::builder("ssrc-group", NS_SSMA)
+                .attr("semantics", parts.next().unwrap_or_default());
+            for ssrc in parts {
+                element = element.append(
+                    Element::builder("source", NS_SSMA)
+                        .attr("ssrc", ssrc)
+                        .build(),
+                );
+            }
+            description = description.append(element.build());
+        }
+    }
+    if has("rtcp-mux") {
+        description = description.append(Element::builder("rtcp-mux", NS_RTP).build());
+    }
+    content
+        .append(description.build())
+        .append(transport.build())
+        .build()
+}
+
+/// Merges the sources of a `source-add` into the session offer.
+pub fn add_sources(session: &mut Element, update: &Element) {
+    for added in children(update, "content") {
+        let Some(added_description) = added.get_child("description", NS_RTP) else {
+            continue;
+        };
+        let name = attr(added, "name").to_owned();
+        let media = attr(added_description, "media").to_owned();
+        let Some(description) = session
+            .children_mut()
+            .filter(|content| content.name() == "content")
+            .find(|content| {
+                content.attr("name") == Some(name.as_str())
+                    || content
+                        .get_child("description", NS_RTP)
+                        .is_some_and(|d| d.attr("media") == Some(media.as_str()))
+            })
+            .and_then(|content| content.get_child_mut("description", NS_RTP))
+        else {
+            continue;
+        };
+        for child in added_description.children() {
+            if child.is("source", NS_SSMA) || child.is("ssrc-group", NS_SSMA) {
+                description.append_child(child.clone());
+            }
+        }
+    }
+}
+
+/// Drops the sources of a `source-remove` from the session offer.
+pub fn remove_sources(session: &mut Element, update: &Element) {
+    let removed: Vec<String> = children(update, "content")
+        .filter_map(|content| content.get_child("description", NS_RTP))
+        .flat_map(|description| description.children().filter(|c| c.is("source", NS_SSMA)))
+        .map(|source| attr(source, "ssrc").to_owned())
+        .collect();
+    for content in session.children_mut().filter(|c| c.name() == "content") {
+        let Some(description) = content.get_child_mut("description", NS_RTP) else {
+            continue;
+        };
+        let kept: Vec<Element> = description
+            .children()
+            .filter(|child| {
+                if child.is("source", NS_SSMA) {
+                    !removed.iter().any(|ssrc| child.attr("ssrc") == Some(ssrc))
+                } else if child.is("ssrc-group", NS_SSMA) {
+                    !children(child, "source")
+                        .any(|s| removed.iter().any(|r| s.attr("ssrc") == Some(r)))
+                } else {
+                    true
+                }
+            })
+            .cloned()
+            .collect();
+        let mut rebuilt = Element::builder("description", NS_RTP);
+        for (name, value) in description.attrs() {
+            rebuilt = rebuilt.attr(name, value);
+        }
+        *description = rebuilt.append_all(kept).build();
+    }
+}
+
+/// The endpoint owning each remote source, from Jitsi's `ssrc-info`.
+pub fn source_owners(session: &Element) -> Vec<(u32, String)> {
+    children(session, "content")
+        .filter_map(|content| content.get_child("description", NS_RTP))
+        .flat_map(|description| description.children().filter(|c| c.is("source", NS_SSMA)))
+        .filter_map(|source| {
+            let ssrc = source.attr("ssrc")?.parse().ok()?;
+            let owner = source
+                .children()
+                .find(|child| child.name() == "ssrc-info")
+                .and_then(|info| info.attr("owner"))?;
+            // Owners are full MUC JIDs; the resource is the endpoint id.
+            let endpoint = owner.rsplit('/').next().unwrap_or(owner);
+            Some((ssrc, endpoint.to_owned()))
+        })
+        .collect()
+}
+
+/// Group element listing our contents for BUNDLE.
+pub fn bundle_group(contents: &[Element]) -> Element {
+    Element::builder("group", NS_GROUPING)
+        .attr("semantics", "BUNDLE")
+        .append_all(contents.iter().map(|content| {
+            Element::builder("content", NS_GROUPING)
+                .attr("name", attr(content, "name"))
+                .build()
+        }))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `session-initiate` as Jicofo sends it, trimmed to one source of
+    /// each kind.
+    const SESSION_INITIATE: &str = r#"<jingle xmlns="urn:xmpp:jingle:1" action="session-initiate" initiator="focus@auth.meet.example.com/focus" sid="7a8bf9n0e4rq0">
+  <content creator="initiator" name="audio" senders="both">
+    <description xmlns="urn:xmpp:jingle:apps:rtp:1" media="audio" maxptime="60">
+      <payload-type id="111" name="opus" clockrate="48000" channels="2">
+        <parameter name="minptime" value="10"/>
+        <parameter name="useinbandfec" value="1"/>
+        <rtcp-fb xmlns="urn:xmpp:jingle:apps:rtp:rtcp-fb:0" type="transport-cc"/>
+      </payload-type>
+      <payload-type id="126" name="telephone-event" clockrate="8000"/>
+      <rtp-hdrext xmlns="urn:xmpp:jingle:apps:rtp:rtp-hdrext:0" id="1" uri="urn:ietf:params:rtp-hdrext:ssrc-audio-level"/>
+      <rtcp-mux/>
+      <source xmlns="urn:xmpp:jingle:apps:rtp:ssma:0" ssrc="1856470707" name="jvb-a0">
+        <ssrc-info xmlns="http://jitsi.org/jitmeet" owner="room@conference.meet.example.com/1a2b3c4d"/>
+        <parameter name="cname" value="mixed"/>
+        <parameter name="msid" value="mixedmslabel mixedlabelaudio0"/>
+      </source>
+    </description>
+    <transport xmlns="urn:xmpp:jingle:transports:ice-udp:1" ufrag="4b0q61h5v6nkvl" pwd="26mbgag0daf6b6ss2sqsn8g8b2">
+      <rtcp-mux/>
+      <fingerprint xmlns="urn:xmpp:jingle:apps:dtls:0" hash="sha-256" setup="actpass" required="false">4A:AD:B9:B1:3F:82:18:3B:54:02:12:DF:3E:5D:49:6B:19:E5:7C:AB:3E:4B:65:0B:AB:38:A3:E2:4F:C2:2B:B7</fingerprint>
+      <candidate component="1" foundation="1" generation="0" id="6f1a2b3c" ip="10.0.0.5" network="0" port="10000" priority="2130706431" protocol="udp" type="host"/>
+      <candidate component="1" foundation="2" generation="0" id="6f1a2b3d" ip="203.0.113.7" network="0" port="10000" priority="1694498815" protocol="udp" rel-addr="10.0.0.5" rel-port="10000" type="srflx"/>
+      <candidate component="1" foundation="3" generation="0" id="6f1a2b3e" ip="10.0.0.5" network="0" port="4443" priority="1518280447" protocol="tcp" tcptype="passive" type="host"/>
+    </transport>
+  </content>
+  <content creator="initiator" name="video" senders="both">
+    <description xmlns="urn:xmpp:jingle:apps:rtp:1" media="video">
+      <payload-type id="100" name="VP8" clockrate="90000">
+        <rtcp-fb xmlns="urn:xmpp:jingle:apps:rtp:rtcp-fb:0" type="ccm" subtype="fir"/>
+        <rtcp-fb xmlns="urn:xmpp:jingle:apps:rtp:rtcp-fb:0" type="nack"/>
+        <rtcp-fb xmlns="urn:xmpp:jingle:apps:rtp:rtcp-fb:0" type="nack" subtype="pli"/>
+      </payload-type>
+      <payload-type id="96" name="rtx" clockrate="90000">
+        <parameter name="apt" value="100"/>
+      </payload-type>
+      <rtp-hdrext xmlns="urn:xmpp:jingle:apps:rtp:rtp-hdrext:0" id="3" uri="http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time"/>
+      <rtcp-mux/>
+      <source xmlns="urn:xmpp:jingle:apps:rtp:ssma:0" ssrc="1111" name="1a2b3c4d-v0">
+        <ssrc-info xmlns="http://jitsi.org/jitmeet" owner="room@conference.meet.example.com/1a2b3c4d"/>
+        <parameter name="cname" value="peer"/>
+        <parameter name="msid" value="1a2b3c4d-video-0 track0"/>
+      </source>
+      <source xmlns="urn:xmpp:jingle:apps:rtp:ssma:0" ssrc="2222" name="1a2b3c4d-v0">
+        <parameter name="cname" value="peer"/>
+        <parameter name="msid" value="1a2b3c4d-video-0 track0"/>
+      </source>
+      <ssrc-group xmlns="urn:xmpp:jingle:apps:rtp:ssma:0" semantics="FID">
+        <source ssrc="1111"/>
+        <source ssrc="2222"/>
+      </ssrc-group>
+    </description>
+    <transport xmlns="urn:xmpp:jingle:transports:ice-udp:1" ufrag="4b0q61h5v6nkvl" pwd="26mbgag0daf6b6ss2sqsn8g8b2">
+      <fingerprint xmlns="urn:xmpp:jingle:apps:dtls:0" hash="sha-256" setup="actpass">4A:AD:B9:B1:3F:82:18:3B:54:02:12:DF:3E:5D:49:6B:19:E5:7C:AB:3E:4B:65:0B:AB:38:A3:E2:4F:C2:2B:B7</fingerprint>
+      <candidate component="1" foundation="1" generation="0" id="6f1a2b3f" ip="10.0.0.5" network="0" port="10000" priority="2130706431" protocol="udp" type="host"/>
+    </transport>
+  </content>
+  <content creator="initiator" name="data">
+    <transport xmlns="urn:xmpp:jingle:transports:ice-udp:1" ufrag="4b0q61h5v6nkvl" pwd="26mbgag0daf6b6ss2sqsn8g8b2">
+      <sctpmap xmlns="urn:xmpp:jingle:transports:dtls-sctp:1" number="5000" protocol="webrtc-datachannel" streams="1024"/>
+      <fingerprint xmlns="urn:xmpp:jingle:apps:dtls:0" hash="sha-256" setup="actpass">4A:AD:B9:B1:3F:82:18:3B:54:02:12:DF:3E:5D:49:6B:19:E5:7C:AB:3E:4B:65:0B:AB:38:A3:E2:4F:C2:2B:B7</fingerprint>
+      <candidate component="1" foundation="1" generation="0" id="6f1a2b40" ip="10.0.0.5" network="0" port="10000" priority="2130706431" protocol="udp" type="host"/>
+    </transport>
+  </content>
+  <group xmlns="urn:xmpp:jingle:apps:grouping:0" semantics="BUNDLE">
+    <content name="audio"/>
+    <content name="video"/>
+    <content name="data"/>
+  </group>
+</jingle>"#;
+
+    fn session() -> Element {
+        SESSION_INITIATE.parse().unwrap()
+    }
+
+    fn attrs(element: &Element, names: &[&str]) -> String {
+        let values: Vec<String> = names
+            .iter()
+            .map(|name| format!("{}={}", name, attr(element, name)))
+            .collect();
+        format!("{} {}", element.name(), values.join(" "))
+    }
+
+    /// What a content says, leaving out what only the focus adds (source
+    /// names and owners, candidate ids) and the order of unlike elements.
+    fn summary(content: &Element) -> Vec<String> {
+        let mut lines = vec![attrs(content, &["name", "senders"])];
+        if let Some(description) = content.get_child("description", NS_RTP) {
+            lines.push(attrs(description, &["media"]));
+            for payload in children(description, "payload-type") {
+                lines.push(attrs(payload, &["id", "name", "clockrate", "channels"]));
+                for child in payload.children() {
+                    lines.push(attrs(child, &["name", "value", "type", "subtype"]));
+                }
+            }
+            for extension in children(description, "rtp-hdrext") {
+                lines.push(attrs(extension, &["id", "uri"]));
+            }
+            for source in children(description, "source") {
+                lines.push(attrs(source, &["ssrc"]));
+                for parameter in children(source, "parameter") {
+                    lines.push(attrs(parameter, &["name", "value"]));
+                }
+            }
+            for group in children(description, "ssrc-group") {
+                lines.push(attrs(group, &["semantics"]));
+                for source in children(group, "source") {
+                    lines.push(attrs(source, &["ssrc"]));
+                }
+            }
+            lines.push(format!(
+                "rtcp-mux {}",
+                description.has_child("rtcp-mux", NS_RTP)
+            ));
+        }
+        let transport = content.get_child("transport", NS_ICE_UDP).unwrap();
+        lines.push(attrs(transport, &["ufrag", "pwd"]));
+        for fingerprint in children(transport, "fingerprint") {
+            lines.push(format!(
+                "{} {}",
+                attrs(fingerprint, &["hash", "setup"]),
+                fingerprint.text()
+            ));
+        }
+        for candidate in children(transport, "candidate") {
+            lines.push(attrs(
+                candidate,
+                &[
+                    "foundation",
+                    "component",
+                    "protocol",
+                    "priority",
+                    "ip",
+                    "port",
+                    "type",
+                    "rel-addr",
+                    "rel-port",
+                    "tcptype",
+                    "generation",
+                ],
+            ));
+        }
+        for sctpmap in children(transport, "sctpmap") {
+            lines.push(attrs(sctpmap, &["number", "protocol"]));
+        }
+        lines
+    }
+
+    #[test]
+    fn renders_a_session_initiate_as_sdp() {
+        let sdp = to_sdp(&session());
+        let lines: Vec<&str> = sdp.lines().collect();
+        for expected in [
+            "a=group:BUNDLE audio video data",
+            "m=audio 9 UDP/TLS/RTP/SAVPF 111 126",
+            "a=fingerprint:sha-256 4A:AD:B9:B1:3F:82:18:3B:54:02:12:DF:3E:5D:49:6B:19:E5:7C:AB:3E:4B:65:0B:AB:38:A3:E2:4F:C2:2B:B7",
+            "a=setup:actpass",
+            "a=candidate:1 1 udp 2130706431 10.0.0.5 10000 typ host generation 0",
+            "a=candidate:2 1 udp 1694498815 203.0.113.7 10000 typ srflx raddr 10.0.0.5 rport 10000 generation 0",
+            "a=candidate:3 1 tcp 1518280447 10.0.0.5 4443 typ host tcptype passive generation 0",
+            "a=sendrecv",
+            "a=rtcp-mux",
+            "a=rtpmap:111 opus/48000/2",
+            "a=fmtp:111 minptime=10;useinbandfec=1",
+            "a=rtcp-fb:111 transport-cc",
+            "a=rtpmap:126 telephone-event/8000",
+            "a=extmap:1 urn:ietf:params:rtp-hdrext:ssrc-audio-level",
+            "a=ssrc:1856470707 msid:mixedmslabel mixedlabelaudio0",
+            "m=video 9 UDP/TLS/RTP/SAVPF 100 96",
+            "a=rtcp-fb:100 ccm fir",
+            "a=rtcp-fb:100 nack",
+            "a=rtcp-fb:100 nack pli",
+            "a=fmtp:96 apt=100",
+            "a=ssrc-group:FID 1111 2222",
+            "a=ssrc:2222 cname:peer",
+            "m=application 9 UDP/DTLS/SCTP webrtc-datachannel",
+            "a=mid:data",
+            "a=sctp-port:5000",
+        ] {
+            assert!(lines.contains(&expected), "missing {}", expected);
+        }
+        // Each RTP section keeps its own rtcp-mux.
+        assert_eq!(lines.iter().filter(|l| **l == "a=rtcp-mux").count(), 2);
+        assert_eq!(
+            source_owners(&session()),
+            [
+                (1856470707, "1a2b3c4d".to_owned()),
+                (1111, "1a2b3c4d".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips_a_session_initiate() {
+        let session = session();
+        let contents = from_sdp(&to_sdp(&session), |media| format!("me-{}", media));
+        let original: Vec<&Element> = children(&session, "content").collect();
+        assert_eq!(contents.len(), original.len());
+        for (back, original) in contents.iter().zip(original) {
+            assert_eq!(summary(back), summary(original));
+        }
+        let video = contents[1].get_child("description", NS_RTP).unwrap();
+        assert!(children(video, "source").all(|source| attr(source, "name") == "me-video"));
+
+        let group = bundle_group(&contents);
+        let names: Vec<&str> = children(&group, "content")
+            .map(|content| attr(content, "name"))
+            .collect();
+        assert_eq!(names, ["audio", "video", "data"]);
+    }
+
+    #[test]
+    fn round_trips_candidate_lines() {
+        let session = session();
+        let transport = session
+            .get_child("content", NS_JINGLE)
+            .and_then(|content| content.get_child("transport", NS_ICE_UDP))
+            .unwrap();
+        for candidate in children(transport, "candidate") {
+            let line = candidate_line(candidate);
+            let parsed = candidate_element(&format!("a={}", line)).unwrap();
+            assert_eq!(candidate_line(&parsed), line);
+        }
+        assert!(candidate_element("a=candidate:1 1 udp 1 10.0.0.5 10000 host").is_none());
+        assert!(candidate_element("a=mid:0").is_none());
+    }
+}
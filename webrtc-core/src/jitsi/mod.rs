@@ -0,0 +1,710 @@
+//! Jitsi Meet client: anonymous XMPP over WebSocket (RFC 7395), a focus
+//! conference request, MUC presence, and a Jingle session with the focus.
+
+pub mod jingle;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use minidom::Element;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+
+use crate::codec;
+use crate::settings::Settings;
+use crate::snapshot::Snapshot;
+use crate::video::{self, VideoFrame};
+
+const NS_CLIENT: &str = "jabber:client";
+const NS_FRAMING: &str = "urn:ietf:params:xml:ns:xmpp-framing";
+const NS_SASL: &str = "urn:ietf:params:xml:ns:xmpp-sasl";
+const NS_BIND: &str = "urn:ietf:params:xml:ns:xmpp-bind";
+const NS_FOCUS: &str = "http://jitsi.org/protocol/focus";
+const NS_MUC: &str = "http://jabber.org/protocol/muc";
+const NS_NICK: &str = "http://jabber.org/protocol/nick";
+const NS_DISCO_INFO: &str = "http://jabber.org/protocol/disco#info";
+const NS_PING: &str = "urn:xmpp:ping";
+
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+const PATTERN_WIDTH: usize = 640;
+const PATTERN_HEIGHT: usize = 360;
+const PATTERN_FPS: f32 = 15.0;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum JitsiError {
+    #[error("WebSocket error: {0}")]
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    #[error("invalid meeting URL: {0}")]
+    Url(String),
+    #[error("malformed XMPP stanza: {0}")]
+    Xml(String),
+    #[error("XMPP login failed: {0}")]
+    Login(String),
+    #[error("the focus refused the conference: {0}")]
+    Refused(String),
+    #[error("XMPP connection is closed")]
+    Closed,
+    #[error("WebRTC error: {0}")]
+    WebRtc(#[from] webrtc::Error),
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for JitsiError {
+    fn from(err: tokio_tungstenite::tungstenite::Error) -> Self {
+        JitsiError::WebSocket(Box::new(err))
+    }
+}
+
+#[derive(Clone)]
+pub enum ConferenceEvent {
+    Joined {
+        room: String,
+        endpoint: String,
+    },
+    StateChanged(RTCPeerConnectionState),
+    ParticipantJoined {
+        endpoint: String,
+        name: String,
+    },
+    ParticipantLeft {
+        endpoint: String,
+    },
+    /// Decoded video of `endpoint`'s camera.
+    VideoStarted {
+        endpoint: String,
+        frames: Snapshot<Option<VideoFrame>>,
+    },
+    Disconnected(String),
+}
+
+/// A meeting URL split into its parts. Deployments whose XMPP domain
+/// differs from the web host (like the Docker default, `meet.jitsi`) need
+/// it given explicitly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Meeting {
+    pub websocket_url: String,
+    pub domain: String,
+    pub room: String,
+}
+
+impl Meeting {
+    pub fn parse(url: &str, xmpp_domain: Option<&str>) -> Result<Self, JitsiError> {
+        let parsed =
+            reqwest::Url::parse(url.trim()).map_err(|err| JitsiError::Url(err.to_string()))?;
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| JitsiError::Url(url.to_owned()))?;
+        let room = parsed
+            .path_segments()
+            .and_then(|mut segments| segments.rfind(|s| !s.is_empty()))
+            .ok_or_else(|| JitsiError::Url(format!("{} names no room", url)))?
+            .to_lowercase();
+        let scheme = if parsed.scheme() == "http" {
+            "ws"
+        } else {
+            "wss"
+        };
+        let authority = match parsed.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_owned(),
+        };
+        Ok(Self {
+            websocket_url: format!("{}://{}/xmpp-websocket?room={}", scheme, authority, room),
+            domain: xmpp_domain
+                .map(str::trim)
+                .filter(|domain| !domain.is_empty())
+                .unwrap_or(host)
+                .to_owned(),
+            room,
+        })
+    }
+
+    fn room_jid(&self) -> String {
+        format!("{}@conference.{}", self.room, self.domain)
+    }
+
+    fn focus_jid(&self) -> String {
+        format!("focus.{}", self.domain)
+    }
+}
+
+fn new_id() -> String {
+    format!("{:08x}", rand::random::<u32>())
+}
+
+fn iq(kind: &str, to: &str) -> minidom::element::ElementBuilder {
+    Element::builder("iq", NS_CLIENT)
+        .attr("type", kind)
+        .attr("to", to)
+        .attr("id", new_id())
+}
+
+/// An XMPP stream before login splits it into reader and writer tasks.
+struct Login {
+    sink: SplitSink<Socket, Message>,
+    source: SplitStream<Socket>,
+}
+
+impl Login {
+    async fn send(&mut self, stanza: &Element) -> Result<(), JitsiError> {
+        self.sink.send(Message::Text(String::from(stanza))).await?;
+        Ok(())
+    }
+
+    async fn next(&mut self) -> Result<Element, JitsiError> {
+        loop {
+            match self.source.next().await {
+                Some(Ok(Message::Text(text))) => {
+                    return text
+                        .parse()
+                        .map_err(|err: minidom::Error| JitsiError::Xml(err.to_string()))
+                }
+                Some(Ok(Message::Close(_))) | None => return Err(JitsiError::Closed),
+                Some(Err(err)) => return Err(err.into()),
+                Some(Ok(_)) => {}
+            }
+        }
+    }
+
+    /// Opens the stream and waits for its features.
+    async fn open(&mut self, domain: &str) -> Result<Element, JitsiError> {
+        let open = Element::builder("open", NS_FRAMING)
+            .attr("to", domain)
+            .attr("version", "1.0")
+            .build();
+        self.send(&open).await?;
+        loop {
+            let element = self.next().await?;
+            if element.name() == "features" {
+                return Ok(element);
+            }
+        }
+    }
+
+    /// Logs in anonymously and binds a resource, returning our full JID.
+    async fn anonymous(&mut self, domain: &str) -> Result<String, JitsiError> {
+        let features = self.open(domain).await?;
+        let anonymous = features
+            .get_child("mechanisms", NS_SASL)
+            .is_some_and(|mechanisms| {
+                mechanisms
+                    .children()
+                    .any(|mechanism| mechanism.text() == "ANONYMOUS")
+            });
+        if !anonymous {
+            return Err(JitsiError::Login(
+                "the server does not allow anonymous guests".to_owned(),
+            ));
+        }
+        let auth = Element::builder("auth", NS_SASL)
+            .attr("mechanism", "ANONYMOUS")
+            .build();
+        self.send(&auth).await?;
+        let reply = self.next().await?;
+        if reply.name() != "success" {
+            return Err(JitsiError::Login(String::from(&reply)));
+        }
+
+        self.open(domain).await?;
+        let bind = Element::builder("iq", NS_CLIENT)
+            .attr("type", "set")
+            .attr("id", "bind")
+            .append(Element::builder("bind", NS_BIND).build())
+            .build();
+        self.send(&bind).await?;
+        loop {
+            let reply = self.next().await?;
+            if reply.attr("id") != Some("bind") {
+                continue;
+            }
+            return reply
+                .get_child("bind", NS_BIND)
+                .and_then(|bind| bind.children().find(|c| c.name() == "jid"))
+                .map(|jid| jid.text())
+                .ok_or_else(|| JitsiError::Login(String::from(&reply)));
+        }
+    }
+}
+
+type Pending = Arc<Mutex<HashMap<String, oneshot::Sender<Element>>>>;
+
+/// A logged in XMPP stream. Replies to our IQs resolve their requests;
+/// every other stanza goes to the conference task.
+#[derive(Clone)]
+struct Xmpp {
+    outgoing: mpsc::UnboundedSender<Element>,
+    pending: Pending,
+}
+
+impl Xmpp {
+    /// Returns the stream of unsolicited stanzas and the reader task. The
+    /// writer closes the stream once every clone is dropped.
+    fn start(login: Login) -> (Self, mpsc::UnboundedReceiver<Element>, JoinHandle<()>) {
+        let Login {
+            mut sink,
+            mut source,
+        } = login;
+        let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel::<Element>();
+        let (stanzas_tx, stanzas) = mpsc::unbounded_channel();
+        let pending: Pending = Arc::default();
+
+        tokio::spawn(async move {
+            while let Some(stanza) = outgoing_rx.recv().await {
+                if let Err(err) = sink.send(Message::Text(String::from(&stanza))).await {
+                    warn!("Failed to send XMPP stanza: {:?}", err);
+                    break;
+                }
+            }
+            let close = Element::builder("close", NS_FRAMING).build();
+            let _ = sink.send(Message::Text(String::from(&close))).await;
+            let _ = sink.close().await;
+        });
+
+        let replies = Arc::clone(&pending);
+        let reader = tokio::spawn(async move {
+            while let Some(frame) = source.next().await {
+                let text = match frame {
+                    Ok(Message::Text(text)) => text,
+                    Ok(Message::Close(_)) | Err(_) => break,
+                    Ok(_) => continue,
+                };
+                let stanza: Element = match text.parse() {
+                    Ok(stanza) => stanza,
+                    Err(err) => {
+                        warn!("Ignoring malformed XMPP stanza: {}", err);
+                        continue;
+                    }
+                };
+                let is_reply = stanza.name() == "iq"
+                    && matches!(stanza.attr("type"), Some("result") | Some("error"));
+                if is_reply {
+                    let id = stanza.attr("id").unwrap_or_default().to_owned();
+                    if let Some(reply) = replies.lock().unwrap().remove(&id) {
+                        let _ = reply.send(stanza);
+                        continue;
+                    }
+                }
+                if stanzas_tx.send(stanza).is_err() {
+                    break;
+                }
+            }
+            replies.lock().unwrap().clear();
+        });
+
+        (Self { outgoing, pending }, stanzas, reader)
+    }
+
+    fn send(&self, stanza: Element) -> Result<(), JitsiError> {
+        self.outgoing.send(stanza).map_err(|_| JitsiError::Closed)
+    }
+
+    async fn request(&self, stanza: Element) -> Result<Element, JitsiError> {
+        let id = stanza.attr("id").unwrap_or_default().to_owned();
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id.clone(), reply_tx);
+        if let Err(err) = self.send(stanza) {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(err);
+        }
+        reply_rx.await.map_err(|_| JitsiError::Closed)
+    }
+
+    /// Acknowledges an IQ from someone else.
+    fn result(&self, request: &Element, payload: Option<Element>) {
+        let mut reply = Element::builder("iq", NS_CLIENT)
+            .attr("type", "result")
+            .attr("id", request.attr("id").unwrap_or_default())
+            .attr("to", request.attr("from").unwrap_or_default());
+        if let Some(payload) = payload {
+            reply = reply.append(payload);
+        }
+        let _ = self.send(reply.build());
+    }
+}
+
+/// State of the Jingle session with the focus.
+struct Session {
+    pc: Arc<RTCPeerConnection>,
+    sid: String,
+    focus: String,
+    /// The focus's offer, kept up to date with `source-add`/`source-remove`.
+    offer: Element,
+}
+
+pub struct JitsiConference {
+    xmpp: Xmpp,
+    presence_to: String,
+    session: Arc<tokio::sync::Mutex<Option<Session>>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl JitsiConference {
+    /// Joins the meeting as an anonymous guest named `display_name`,
+    /// sending a test pattern as our camera when this build can encode.
+    pub async fn join(
+        settings: &Settings,
+        meeting: &Meeting,
+        display_name: &str,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<ConferenceEvent>), JitsiError> {
+        let mut request = meeting.websocket_url.as_str().into_client_request()?;
+        request
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", HeaderValue::from_static("xmpp"));
+        let (stream, _) = tokio_tungstenite::connect_async(request).await?;
+        let (sink, source) = stream.split();
+        let mut login = Login { sink, source };
+        let jid = login.anonymous(&meeting.domain).await?;
+        info!("Logged in to {} as {}", meeting.domain, jid);
+        let (xmpp, mut stanzas, reader) = Xmpp::start(login);
+        let mut tasks = vec![reader];
+
+        // Ask the focus to set the conference up before entering the room.
+        let conference = iq("set", &meeting.focus_jid())
+            .append(
+                Element::builder("conference", NS_FOCUS)
+                    .attr("room", meeting.room_jid())
+                    .attr("machine-uid", new_id())
+                    .build(),
+            )
+            .build();
+        let reply = xmpp.request(conference).await?;
+        if reply.attr("type") == Some("error") {
+            return Err(JitsiError::Refused(String::from(&reply)));
+        }
+
+        let endpoint = new_id();
+        let presence_to = format!("{}/{}", meeting.room_jid(), endpoint);
+        let presence = Element::builder("presence", NS_CLIENT)
+            .attr("to", presence_to.as_str())
+            .append(Element::builder("x", NS_MUC).build())
+            .append(
+                Element::builder("nick", NS_NICK)
+                    .append(display_name)
+                    .build(),
+            )
+            .build();
+        xmpp.send(presence)?;
+
+        let (events_tx, events) = mpsc::unbounded_channel();
+        let _ = events_tx.send(ConferenceEvent::Joined {
+            room: meeting.room.clone(),
+            endpoint: endpoint.clone(),
+        });
+        let session: Arc<tokio::sync::Mutex<Option<Session>>> = Arc::default();
+
+        {
+            let xmpp = xmpp.clone();
+            let session = Arc::clone(&session);
+            let settings = settings.clone();
+            let room_jid = meeting.room_jid();
+            tasks.push(tokio::spawn(async move {
+                let mut handler = Handler {
+                    xmpp,
+                    settings,
+                    endpoint,
+                    room_jid,
+                    session,
+                    events: events_tx,
+                    owners: Arc::default(),
+                    pattern: None,
+                };
+                while let Some(stanza) = stanzas.recv().await {
+                    handler.handle(stanza).await;
+                }
+                if let Some(pattern) = handler.pattern.take() {
+                    pattern.abort();
+                }
+                let _ = handler.events.send(ConferenceEvent::Disconnected(
+                    "XMPP connection closed".to_owned(),
+                ));
+            }));
+        }
+
+        {
+            let xmpp = xmpp.clone();
+            let domain = meeting.domain.clone();
+            tasks.push(tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(PING_INTERVAL);
+                ticker.tick().await;
+                loop {
+                    ticker.tick().await;
+                    let ping = iq("get", &domain)
+                        .append(Element::builder("ping", NS_PING).build())
+                        .build();
+                    if xmpp.request(ping).await.is_err() {
+                        break;
+                    }
+                }
+            }));
+        }
+
+        Ok((
+            Self {
+                xmpp,
+                presence_to,
+                session,
+                tasks,
+            },
+            events,
+        ))
+    }
+
+    pub async fn leave(self) {
+        if let Some(session) = self.session.lock().await.take() {
+            let terminate = iq("set", &session.focus)
+                .append(
+                    Element::builder("jingle", jingle::NS_JINGLE)
+                        .attr("action", "session-terminate")
+                        .attr("sid", session.sid.as_str())
+                        .build(),
+                )
+                .build();
+            let _ = self.xmpp.send(terminate);
+            if let Err(err) = session.pc.close().await {
+                info!("Failed to close Jitsi peer connection: {:?}", err);
+            }
+        }
+        let unavailable = Element::builder("presence", NS_CLIENT)
+            .attr("to", self.presence_to.as_str())
+            .attr("type", "unavailable")
+            .build();
+        let _ = self.xmpp.send(unavailable);
+        // Let the writer flush before closing the stream.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+struct Handler {
+    xmpp: Xmpp,
+    settings: Settings,
+    endpoint: String,
+    room_jid: String,
+    session: Arc<tokio::sync::Mutex<Option<Session>>>,
+    events: mpsc::UnboundedSender<ConferenceEvent>,
+    /// Which endpoint sends each remote SSRC.
+    owners: Arc<Mutex<Vec<(u32, String)>>>,
+    pattern: Option<JoinHandle<()>>,
+}
+
+impl Handler {
+    async fn handle(&mut self, stanza: Element) {
+        match stanza.name() {
+            "presence" => self.presence(&stanza),
+            "iq" if stanza.attr("type") == Some("get") => {
+                let payload = stanza
+                    .get_child("query", NS_DISCO_INFO)
+                    .map(|_| disco_info());
+                self.xmpp.result(&stanza, payload);
+            }
+            "iq" if stanza.attr("type") == Some("set") => {
+                self.xmpp.result(&stanza, None);
+                if let Some(jingle) = stanza.get_child("jingle", jingle::NS_JINGLE) {
+                    let from = stanza.attr("from").unwrap_or_default().to_owned();
+                    if let Err(err) = self.jingle(&from, jingle).await {
+                        warn!(
+                            "Failed to handle Jingle {:?}: {:?}",
+                            jingle.attr("action"),
+                            err
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn presence(&self, presence: &Element) {
+        let Some(from) = presence.attr("from") else {
+            return;
+        };
+        let Some((room, endpoint)) = from.split_once('/') else {
+            return;
+        };
+        // The focus is a room member too, and so are we.
+        if room != self.room_jid || endpoint == self.endpoint || endpoint == "focus" {
+            return;
+        }
+        let event = if presence.attr("type") == Some("unavailable") {
+            ConferenceEvent::ParticipantLeft {
+                endpoint: endpoint.to_owned(),
+            }
+        } else {
+            let name = presence
+                .get_child("nick", NS_NICK)
+                .map(|nick| nick.text())
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| endpoint.to_owned());
+            ConferenceEvent::ParticipantJoined {
+                endpoint: endpoint.to_owned(),
+                name,
+            }
+        };
+        let _ = self.events.send(event);
+    }
+
+    async fn jingle(&mut self, from: &str, jingle: &Element) -> Result<(), JitsiError> {
+        match jingle.attr("action").unwrap_or_default() {
+            "session-initiate" => self.session_initiate(from, jingle).await,
+            "source-add" | "source-remove" => {
+                let mut session = self.session.lock().await;
+                let Some(session) = session.as_mut() else {
+                    return Ok(());
+                };
+                if jingle.attr("action") == Some("source-add") {
+                    jingle::add_sources(&mut session.offer, jingle);
+                } else {
+                    jingle::remove_sources(&mut session.offer, jingle);
+                }
+                *self.owners.lock().unwrap() = jingle::source_owners(&session.offer);
+                let offer = RTCSessionDescription::offer(jingle::to_sdp(&session.offer))?;
+                session.pc.set_remote_description(offer).await?;
+                let answer = session.pc.create_answer(None).await?;
+                session.pc.set_local_description(answer).await?;
+                Ok(())
+            }
+            "session-terminate" => {
+                if let Some(session) = self.session.lock().await.take() {
+                    let _ = session.pc.close().await;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn session_initiate(&mut self, from: &str, jingle: &Element) -> Result<(), JitsiError> {
+        let sid = jingle.attr("sid").unwrap_or_default().to_owned();
+        let mut media_engine = webrtc::api::media_engine::MediaEngine::default();
+        media_engine.register_default_codecs()?;
+        let api = self.settings.api_with(media_engine);
+        let pc = Arc::new(
+            api.new_peer_connection(self.settings.rtc_configuration(false))
+                .await?,
+        );
+
+        let state_events = self.events.clone();
+        pc.on_peer_connection_state_change(Box::new(move |state| {
+            let _ = state_events.send(ConferenceEvent::StateChanged(state));
+            Box::pin(async {})
+        }));
+        *self.owners.lock().unwrap() = jingle::source_owners(jingle);
+        let track_events = self.events.clone();
+        let owners = Arc::clone(&self.owners);
+        pc.on_track(Box::new(move |track, _, _| {
+            let events = track_events.clone();
+            let owners = Arc::clone(&owners);
+            Box::pin(async move {
+                if track.kind() != RTPCodecType::Video
+                    || !video::can_decode(&track.codec().capability.mime_type)
+                {
+                    while track.read_rtp().await.is_ok() {}
+                    return;
+                }
+                let owner = owners
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .find(|(ssrc, _)| *ssrc == track.ssrc())
+                    .map(|(_, endpoint)| endpoint.clone());
+                // Jitsi stream ids start with the endpoint id.
+                let endpoint = owner.unwrap_or_else(|| {
+                    let stream_id = track.stream_id();
+                    stream_id.split('-').next().unwrap_or_default().to_owned()
+                });
+                let frames = Snapshot::default();
+                let _ = events.send(ConferenceEvent::VideoStarted {
+                    endpoint,
+                    frames: frames.clone(),
+                });
+                video::play_track(track, frames).await;
+            })
+        }));
+
+        let offer = jingle::to_sdp(jingle);
+        let mime_type = codec::remote_preference(&offer).or_else(video::encoder_mime_type);
+        if let Some(mime_type) = mime_type {
+            let track = Arc::new(TrackLocalStaticSample::new(
+                codec::sending_capability(mime_type, Some(&offer)),
+                "video".to_owned(),
+                format!("{}-video", self.endpoint),
+            ));
+            pc.add_track(track.clone()).await?;
+            if let Some(pattern) = self.pattern.take() {
+                pattern.abort();
+            }
+            self.pattern =
+                video::spawn_test_pattern(track, PATTERN_WIDTH, PATTERN_HEIGHT, PATTERN_FPS);
+        }
+
+        pc.set_remote_description(RTCSessionDescription::offer(offer)?)
+            .await?;
+        let answer = pc.create_answer(None).await?;
+        let mut gathered = pc.gathering_complete_promise().await;
+        pc.set_local_description(answer).await?;
+        let _ = gathered.recv().await;
+        let answer = pc
+            .local_description()
+            .await
+            .ok_or(webrtc::Error::ErrConnectionClosed)?;
+
+        let endpoint = self.endpoint.clone();
+        let contents = jingle::from_sdp(&answer.sdp, |media| {
+            let kind = if media == "audio" { 'a' } else { 'v' };
+            format!("{}-{}0", endpoint, kind)
+        });
+        let accept = iq("set", from)
+            .append(
+                Element::builder("jingle", jingle::NS_JINGLE)
+                    .attr("action", "session-accept")
+                    .attr("sid", sid.as_str())
+                    .attr("responder", format!("{}/{}", self.room_jid, self.endpoint))
+                    .append(jingle::bundle_group(&contents))
+                    .append_all(contents)
+                    .build(),
+            )
+            .build();
+        let reply = self.xmpp.request(accept).await?;
+        if reply.attr("type") == Some("error") {
+            return Err(JitsiError::Refused(String::from(&reply)));
+        }
+
+        let previous = self.session.lock().await.replace(Session {
+            pc,
+            sid,
+            focus: from.to_owned(),
+            offer: jingle.clone(),
+        });
+        if let Some(previous) = previous {
+            let _ = previous.pc.close().await;
+        }
+        Ok(())
+    }
+}
+
+fn disco_info() -> Element {
+    Element::builder("query", NS_DISCO_INFO)
+        .append_all(jingle::FEATURES.iter().map(|feature| {
+            Element::builder("feature", NS_DISCO_INFO)
+                .attr("var", *feature)
+                .build()
+        }))
+        .build()
+}
@@ -0,0 +1,163 @@
+//! Timing the data path end to end: pings go over the control channel, the
+//! peer's app answers each straight away, and the round trip is measured
+//! here. Unlike the RTT ICE reports, this takes in SCTP, DTLS and both
+//! apps' event loops.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How often a ping is sent.
+pub const PING_INTERVAL: Duration = Duration::from_secs(1);
+/// Pings not answered within this are counted as lost.
+pub const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Samples kept for the plot: five minutes at one a second.
+const MAX_SAMPLES: usize = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySample {
+    /// Seconds since the first ping.
+    pub time: f64,
+    pub rtt_ms: f64,
+    /// Smoothed as RTP jitter is (RFC 3550), over successive round trips.
+    pub jitter_ms: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySummary {
+    pub last_ms: f64,
+    pub min_ms: f64,
+    pub mean_ms: f64,
+    pub max_ms: f64,
+    pub jitter_ms: f64,
+    pub sent: u64,
+    pub lost: u64,
+}
+
+#[derive(Debug)]
+pub struct LatencyProbe {
+    started: Instant,
+    next_seq: u64,
+    /// Pings awaiting their pong, oldest first.
+    pending: VecDeque<(u64, Instant)>,
+    samples: VecDeque<LatencySample>,
+    jitter_ms: f64,
+    sent: u64,
+    lost: u64,
+}
+
+impl Default for LatencyProbe {
+    fn default() -> Self {
+        Self::new(Instant::now())
+    }
+}
+
+impl LatencyProbe {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            started: now,
+            next_seq: 0,
+            pending: VecDeque::new(),
+            samples: VecDeque::new(),
+            jitter_ms: 0.0,
+            sent: 0,
+            lost: 0,
+        }
+    }
+
+    /// Starts a ping at `now` and returns its sequence number. Pings gone
+    /// unanswered for [`PING_TIMEOUT`] are given up on first.
+    pub fn ping(&mut self, now: Instant) -> u64 {
+        while self
+            .pending
+            .front()
+            .is_some_and(|(_, sent)| now.duration_since(*sent) >= PING_TIMEOUT)
+        {
+            self.pending.pop_front();
+            self.lost += 1;
+        }
+        self.next_seq += 1;
+        self.sent += 1;
+        self.pending.push_back((self.next_seq, now));
+        self.next_seq
+    }
+
+    /// The pong for `seq` arrived at `now`. Returns the round trip, unless
+    /// the ping is unknown or was already given up on.
+    pub fn pong(&mut self, seq: u64, now: Instant) -> Option<Duration> {
+        let index = self.pending.iter().position(|(s, _)| *s == seq)?;
+        let (_, sent) = self.pending.remove(index)?;
+        let rtt = now.duration_since(sent);
+        let rtt_ms = rtt.as_secs_f64() * 1000.0;
+        if let Some(last) = self.samples.back() {
+            self.jitter_ms += ((rtt_ms - last.rtt_ms).abs() - self.jitter_ms) / 16.0;
+        }
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(LatencySample {
+            time: now.duration_since(self.started).as_secs_f64(),
+            rtt_ms,
+            jitter_ms: self.jitter_ms,
+        });
+        Some(rtt)
+    }
+
+    pub fn samples(&self) -> impl DoubleEndedIterator<Item = &LatencySample> {
+        self.samples.iter()
+    }
+
+    /// Over the samples kept, once there is one.
+    pub fn summary(&self) -> Option<LatencySummary> {
+        let last = self.samples.back()?;
+        let rtts = self.samples.iter().map(|s| s.rtt_ms);
+        Some(LatencySummary {
+            last_ms: last.rtt_ms,
+            min_ms: rtts.clone().fold(f64::INFINITY, f64::min),
+            mean_ms: rtts.clone().sum::<f64>() / self.samples.len() as f64,
+            max_ms: rtts.fold(0.0, f64::max),
+            jitter_ms: self.jitter_ms,
+            sent: self.sent,
+            lost: self.lost,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_pongs_and_gives_up_on_missing_ones() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let mut probe = LatencyProbe::new(start);
+
+        let first = probe.ping(start);
+        assert_eq!(probe.pong(first, ms(40)), Some(Duration::from_millis(40)));
+        assert_eq!(probe.pong(first, ms(50)), None);
+
+        let second = probe.ping(ms(1000));
+        let third = probe.ping(ms(2000));
+        // Answered out of order, which still times each.
+        assert_eq!(probe.pong(third, ms(2060)), Some(Duration::from_millis(60)));
+        assert_eq!(
+            probe.pong(second, ms(2070)),
+            Some(Duration::from_millis(1070))
+        );
+
+        let fourth = probe.ping(ms(3000));
+        probe.ping(ms(3000 + PING_TIMEOUT.as_millis() as u64));
+        assert_eq!(probe.pong(fourth, ms(9000)), None);
+
+        let summary = probe.summary().unwrap();
+        assert_eq!(summary.last_ms, 1070.0);
+        assert_eq!(summary.min_ms, 40.0);
+        assert_eq!(summary.max_ms, 1070.0);
+        assert_eq!((summary.sent, summary.lost), (5, 1));
+        // 20 ms apart, then 1010.
+        let jitter = 20.0 / 16.0;
+        let jitter = jitter + (1010.0 - jitter) / 16.0;
+        assert!((summary.jitter_ms - jitter).abs() < 1e-9);
+    }
+}
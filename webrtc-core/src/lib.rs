@@ -0,0 +1,55 @@
+pub mod access;
+pub mod audio;
+pub mod audit;
+pub mod auth;
+pub mod backoff;
+pub mod bandwidth;
+pub mod bounded;
+pub mod camera;
+pub mod capabilities;
+pub mod captions;
+pub mod chat;
+pub mod clip;
+pub mod clipboard;
+pub mod codec;
+pub mod codecs;
+pub mod constraints;
+pub mod contacts;
+pub mod control;
+pub mod data_channels;
+pub mod devices;
+pub mod echo_test;
+pub mod failover;
+pub mod file_transfer;
+pub mod framing;
+pub mod history;
+pub mod janus;
+pub mod jitsi;
+pub mod latency;
+pub mod lipsync;
+pub mod livekit;
+pub mod log_stream;
+pub mod media_file;
+pub mod messaging;
+pub mod power;
+pub mod realtime;
+pub mod recording;
+pub mod red;
+pub mod remote_control;
+pub mod renegotiation;
+pub mod rpc;
+pub mod rtc;
+pub mod screen;
+pub mod send_queue;
+pub mod settings;
+pub mod signaling;
+pub mod simulcast;
+pub mod sip;
+pub mod snapshot;
+pub mod stats;
+pub mod still;
+pub mod thumbnails;
+pub mod turn_rest;
+pub mod video;
+pub mod whep;
+pub mod whip;
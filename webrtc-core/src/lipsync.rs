@@ -0,0 +1,199 @@
+//! Lip sync for received calls. Sender reports tie each stream's RTP
+//! timestamps to the sender's wall clock; comparing when audio and video
+//! captured at the same moment are presented here gives the skew, and the
+//! stream that is ahead is held back by it.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use webrtc::rtcp::sender_report::SenderReport;
+use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+use webrtc::rtp_transceiver::rtp_receiver::RTCRtpReceiver;
+
+/// The most either stream is held back, offset included.
+pub const MAX_CORRECTION: Duration = Duration::from_secs(1);
+
+/// How much of each new measurement goes into the running delay, so one
+/// late packet does not jerk the correction around.
+const SMOOTHING: f64 = 1.0 / 16.0;
+
+#[derive(Debug, Clone)]
+struct StreamClock {
+    clock_rate: f64,
+    /// The latest sender report: the sender's wall clock in seconds and
+    /// the RTP timestamp of the same instant.
+    report: Option<(f64, u32)>,
+    /// Presentation here minus capture there, in seconds, smoothed. The two
+    /// clocks differ, so this only means something next to the other
+    /// stream's.
+    delay: Option<f64>,
+}
+
+impl StreamClock {
+    fn new(clock_rate: u32) -> Self {
+        Self {
+            clock_rate: f64::from(clock_rate),
+            report: None,
+            delay: None,
+        }
+    }
+
+    fn sender_report(&mut self, ntp_time: u64, rtp_time: u32) {
+        let seconds = (ntp_time >> 32) as f64 + (ntp_time & 0xffff_ffff) as f64 / 2f64.powi(32);
+        self.report = Some((seconds, rtp_time));
+    }
+
+    fn presented(&mut self, rtp_timestamp: u32, at: f64) {
+        let Some((reported, reported_rtp)) = self.report else {
+            return;
+        };
+        // Wrapping, and signed so packets from before the report work too.
+        let since = rtp_timestamp.wrapping_sub(reported_rtp) as i32;
+        let captured = reported + f64::from(since) / self.clock_rate;
+        let delay = at - captured;
+        self.delay = Some(match self.delay {
+            Some(smoothed) => smoothed + (delay - smoothed) * SMOOTHING,
+            None => delay,
+        });
+    }
+}
+
+/// How long each stream is held back before it is presented.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Corrections {
+    pub audio: Duration,
+    pub video: Duration,
+}
+
+#[derive(Debug)]
+pub struct LipSync {
+    audio: StreamClock,
+    video: StreamClock,
+    /// Added to what is measured, for senders whose reports are off;
+    /// positive holds the audio back further.
+    pub manual_offset_ms: i32,
+    epoch: Instant,
+}
+
+impl LipSync {
+    pub fn new(manual_offset_ms: i32) -> Self {
+        Self {
+            // Opus and every video payload format use these RTP clocks.
+            audio: StreamClock::new(48000),
+            video: StreamClock::new(90000),
+            manual_offset_ms,
+            epoch: Instant::now(),
+        }
+    }
+
+    fn clock(&mut self, kind: RTPCodecType) -> Option<&mut StreamClock> {
+        match kind {
+            RTPCodecType::Audio => Some(&mut self.audio),
+            RTPCodecType::Video => Some(&mut self.video),
+            RTPCodecType::Unspecified => None,
+        }
+    }
+
+    pub fn sender_report(&mut self, kind: RTPCodecType, ntp_time: u64, rtp_time: u32) {
+        if let Some(clock) = self.clock(kind) {
+            clock.sender_report(ntp_time, rtp_time);
+        }
+    }
+
+    /// Records that media with `rtp_timestamp` is presented at `at`, before
+    /// any correction.
+    pub fn presented(&mut self, kind: RTPCodecType, rtp_timestamp: u32, at: Instant) {
+        let at = at.saturating_duration_since(self.epoch).as_secs_f64();
+        if let Some(clock) = self.clock(kind) {
+            clock.presented(rtp_timestamp, at);
+        }
+    }
+
+    /// How far audio trails the video it was captured with, in seconds;
+    /// negative when it leads. `None` until both streams have sender
+    /// reports and media.
+    pub fn skew(&self) -> Option<f64> {
+        Some(self.audio.delay? - self.video.delay?)
+    }
+
+    pub fn corrections(&self) -> Corrections {
+        let audio_later = f64::from(self.manual_offset_ms) / 1000.0 - self.skew().unwrap_or(0.0);
+        let max = MAX_CORRECTION.as_secs_f64();
+        let held = Duration::from_secs_f64(audio_later.abs().min(max));
+        if audio_later >= 0.0 {
+            Corrections {
+                audio: held,
+                video: Duration::ZERO,
+            }
+        } else {
+            Corrections {
+                audio: Duration::ZERO,
+                video: held,
+            }
+        }
+    }
+}
+
+/// Feeds the sender reports arriving for `receiver` into `sync` until the
+/// receiver stops.
+pub async fn read_sender_reports(
+    receiver: Arc<RTCRtpReceiver>,
+    kind: RTPCodecType,
+    sync: Arc<Mutex<LipSync>>,
+) {
+    while let Ok((packets, _)) = receiver.read_rtcp().await {
+        for packet in packets {
+            if let Some(report) = packet.as_any().downcast_ref::<SenderReport>() {
+                sync.lock()
+                    .unwrap()
+                    .sender_report(kind, report.ntp_time, report.rtp_time);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NTP_SECOND: u64 = 1 << 32;
+
+    #[test]
+    fn holds_back_whichever_stream_leads() {
+        let mut sync = LipSync::new(0);
+        let start = sync.epoch;
+        sync.sender_report(RTPCodecType::Audio, 1000 * NTP_SECOND, 0);
+        sync.sender_report(RTPCodecType::Video, 1000 * NTP_SECOND, 5000);
+        // Captured together a second after the reports; audio shows up
+        // 80 ms after the video.
+        sync.presented(
+            RTPCodecType::Audio,
+            48000,
+            start + Duration::from_millis(1180),
+        );
+        sync.presented(
+            RTPCodecType::Video,
+            95000,
+            start + Duration::from_millis(1100),
+        );
+        let skew = sync.skew().unwrap();
+        assert!((skew - 0.08).abs() < 1e-6, "{}", skew);
+        let corrections = sync.corrections();
+        assert_eq!(corrections.audio, Duration::ZERO);
+        assert!(corrections.video.abs_diff(Duration::from_millis(80)) < Duration::from_micros(1));
+
+        sync.manual_offset_ms = 200;
+        let corrections = sync.corrections();
+        assert!(corrections.audio.abs_diff(Duration::from_millis(120)) < Duration::from_micros(1));
+        assert_eq!(corrections.video, Duration::ZERO);
+    }
+
+    #[test]
+    fn waits_for_both_streams() {
+        let mut sync = LipSync::new(-50);
+        sync.sender_report(RTPCodecType::Audio, NTP_SECOND, 0);
+        sync.presented(RTPCodecType::Audio, 0, Instant::now());
+        assert_eq!(sync.skew(), None);
+        assert_eq!(sync.corrections().video, Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,408 @@
+//! LiveKit SFU client: joins a room through LiveKit's protobuf signaling and
+//! subscribes to every published track. Nothing is published.
+
+mod proto;
+
+use std::collections::HashSet;
+use std::sync::{Arc, Weak};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use prost::Message as _;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+use webrtc::api::media_engine::MediaEngine;
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+
+use crate::settings::Settings;
+use crate::snapshot::Snapshot;
+use crate::video::{self, VideoFrame};
+use proto::{
+    signal_request, signal_response, JoinResponse, ParticipantState, SessionDescription,
+    SignalRequest, SignalResponse, SignalTarget, TrickleRequest,
+};
+
+/// The LiveKit signaling protocol version we speak.
+const PROTOCOL_VERSION: u32 = 9;
+const DEFAULT_PING_INTERVAL: u64 = 5;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LiveKitError {
+    #[error("WebSocket error: {0}")]
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    #[error("invalid LiveKit URL: {0}")]
+    Url(String),
+    #[error("malformed signaling message: {0}")]
+    Decode(#[from] prost::DecodeError),
+    #[error("server closed the connection before joining")]
+    NotJoined,
+    #[error("WebRTC error: {0}")]
+    WebRtc(#[from] webrtc::Error),
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for LiveKitError {
+    fn from(err: tokio_tungstenite::tungstenite::Error) -> Self {
+        LiveKitError::WebSocket(Box::new(err))
+    }
+}
+
+#[derive(Clone)]
+pub enum RoomEvent {
+    Joined {
+        room: String,
+        identity: String,
+    },
+    /// The subscriber connection changed state.
+    StateChanged(RTCPeerConnectionState),
+    ParticipantJoined {
+        sid: String,
+        name: String,
+    },
+    ParticipantLeft {
+        sid: String,
+    },
+    /// `frames` carries decoded video, or is `None` for audio and video we
+    /// cannot decode.
+    TrackSubscribed {
+        participant_sid: String,
+        track_sid: String,
+        kind: RTPCodecType,
+        codec: String,
+        frames: Option<Snapshot<Option<VideoFrame>>>,
+    },
+    TrackUnpublished {
+        track_sid: String,
+    },
+    Disconnected(String),
+}
+
+pub struct LiveKitRoom {
+    subscriber: Arc<RTCPeerConnection>,
+    outgoing: mpsc::UnboundedSender<SignalRequest>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+/// Turns the server URL into its signaling endpoint.
+fn rtc_url(url: &str, token: &str) -> Result<String, LiveKitError> {
+    let url = url.trim().trim_end_matches('/');
+    let base = if let Some(rest) = url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else if url.starts_with("wss://") || url.starts_with("ws://") {
+        url.to_owned()
+    } else {
+        return Err(LiveKitError::Url(url.to_owned()));
+    };
+    Ok(format!(
+        "{}/rtc?access_token={}&auto_subscribe=1&protocol={}&sdk=rust",
+        base, token, PROTOCOL_VERSION
+    ))
+}
+
+fn request(message: signal_request::Message) -> SignalRequest {
+    SignalRequest {
+        message: Some(message),
+    }
+}
+
+impl LiveKitRoom {
+    /// Connects with an access token, whose grants name the room and our
+    /// identity, and auto-subscribes to everything in the room.
+    pub async fn join(
+        settings: &Settings,
+        url: &str,
+        token: &str,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<RoomEvent>), LiveKitError> {
+        let (stream, _) = tokio_tungstenite::connect_async(rtc_url(url, token)?).await?;
+        let (mut sink, mut source) = stream.split();
+
+        let join = loop {
+            match source.next().await {
+                Some(Ok(Message::Binary(data))) => {
+                    if let Some(signal_response::Message::Join(join)) =
+                        SignalResponse::decode(data.as_slice())?.message
+                    {
+                        break join;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                    return Err(LiveKitError::NotJoined)
+                }
+                Some(Ok(_)) => {}
+            }
+        };
+        info!(
+            "Joined LiveKit room {:?} (server {})",
+            join.room.as_ref().map(|room| &room.name),
+            join.server_version
+        );
+
+        let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel::<SignalRequest>();
+        let mut tasks = vec![tokio::spawn(async move {
+            while let Some(message) = outgoing_rx.recv().await {
+                if let Err(err) = sink.send(Message::Binary(message.encode_to_vec())).await {
+                    warn!("Failed to send LiveKit signal: {:?}", err);
+                    break;
+                }
+            }
+            let _ = sink.close().await;
+        })];
+
+        let (events_tx, events) = mpsc::unbounded_channel();
+        let subscriber =
+            Arc::new(subscriber_connection(settings, &join, &outgoing, &events_tx).await?);
+
+        let _ = events_tx.send(RoomEvent::Joined {
+            room: join.room.map(|room| room.name).unwrap_or_default(),
+            identity: join
+                .participant
+                .map(|participant| participant.identity)
+                .unwrap_or_default(),
+        });
+        let mut known = HashSet::new();
+        for participant in join.other_participants {
+            known.insert(participant.sid.clone());
+            let _ = events_tx.send(RoomEvent::ParticipantJoined {
+                sid: participant.sid,
+                name: display_name(participant.name, participant.identity),
+            });
+        }
+
+        {
+            let pc = Arc::clone(&subscriber);
+            let outgoing = outgoing.clone();
+            tasks.push(tokio::spawn(async move {
+                while let Some(frame) = source.next().await {
+                    let data = match frame {
+                        Ok(Message::Binary(data)) => data,
+                        Ok(Message::Close(_)) | Err(_) => break,
+                        Ok(_) => continue,
+                    };
+                    let message = match SignalResponse::decode(data.as_slice()) {
+                        Ok(response) => response.message,
+                        Err(err) => {
+                            warn!("Ignoring malformed LiveKit signal: {}", err);
+                            continue;
+                        }
+                    };
+                    match message {
+                        Some(signal_response::Message::Offer(offer)) => {
+                            if let Err(err) = answer(&pc, offer.sdp, &outgoing).await {
+                                warn!("Failed to answer LiveKit offer: {:?}", err);
+                            }
+                        }
+                        Some(signal_response::Message::Trickle(trickle))
+                            if trickle.target == SignalTarget::Subscriber as i32 =>
+                        {
+                            match serde_json::from_str::<RTCIceCandidateInit>(
+                                &trickle.candidate_init,
+                            ) {
+                                Ok(candidate) => {
+                                    if let Err(err) = pc.add_ice_candidate(candidate).await {
+                                        warn!("Failed to add LiveKit candidate: {:?}", err);
+                                    }
+                                }
+                                Err(err) => warn!("Ignoring malformed candidate: {}", err),
+                            }
+                        }
+                        Some(signal_response::Message::Update(update)) => {
+                            for participant in update.participants {
+                                if participant.state == ParticipantState::Disconnected as i32 {
+                                    known.remove(&participant.sid);
+                                    let _ = events_tx.send(RoomEvent::ParticipantLeft {
+                                        sid: participant.sid,
+                                    });
+                                } else if known.insert(participant.sid.clone()) {
+                                    let _ = events_tx.send(RoomEvent::ParticipantJoined {
+                                        sid: participant.sid,
+                                        name: display_name(participant.name, participant.identity),
+                                    });
+                                }
+                            }
+                        }
+                        Some(signal_response::Message::TrackUnpublished(unpublished)) => {
+                            let _ = events_tx.send(RoomEvent::TrackUnpublished {
+                                track_sid: unpublished.track_sid,
+                            });
+                        }
+                        Some(signal_response::Message::Leave(_)) => {
+                            let _ = events_tx.send(RoomEvent::Disconnected(
+                                "The server ended the session".to_owned(),
+                            ));
+                            return;
+                        }
+                        _ => {}
+                    }
+                }
+                let _ = events_tx.send(RoomEvent::Disconnected(
+                    "Signaling connection closed".to_owned(),
+                ));
+            }));
+        }
+
+        {
+            let outgoing = outgoing.clone();
+            let secs = u64::try_from(join.ping_interval)
+                .ok()
+                .filter(|&secs| secs > 0)
+                .unwrap_or(DEFAULT_PING_INTERVAL);
+            tasks.push(tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(Duration::from_secs(secs));
+                loop {
+                    ticker.tick().await;
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |since| since.as_millis() as i64);
+                    if outgoing
+                        .send(request(signal_request::Message::Ping(now)))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            }));
+        }
+
+        Ok((
+            Self {
+                subscriber,
+                outgoing,
+                tasks,
+            },
+            events,
+        ))
+    }
+
+    /// The connection carrying subscribed media, for stats sampling.
+    pub fn subscriber(&self) -> Weak<RTCPeerConnection> {
+        Arc::downgrade(&self.subscriber)
+    }
+
+    pub async fn leave(self) {
+        let _ = self
+            .outgoing
+            .send(request(signal_request::Message::Leave(Default::default())));
+        // Give the writer a moment to flush the leave before tearing down.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        for task in &self.tasks {
+            task.abort();
+        }
+        if let Err(err) = self.subscriber.close().await {
+            info!("Failed to close LiveKit subscriber connection: {:?}", err);
+        }
+    }
+}
+
+fn display_name(name: String, identity: String) -> String {
+    if name.is_empty() {
+        identity
+    } else {
+        name
+    }
+}
+
+async fn subscriber_connection(
+    settings: &Settings,
+    join: &JoinResponse,
+    outgoing: &mpsc::UnboundedSender<SignalRequest>,
+    events: &mpsc::UnboundedSender<RoomEvent>,
+) -> Result<RTCPeerConnection, LiveKitError> {
+    let mut media_engine = MediaEngine::default();
+    video::register_playback_codecs(&mut media_engine)?;
+    let api = settings.api_with(media_engine);
+    let mut config = settings.rtc_configuration(false);
+    // The server's own TURN/STUN servers come first.
+    let servers = join.ice_servers.iter().map(|server| RTCIceServer {
+        urls: server.urls.clone(),
+        username: server.username.clone(),
+        credential: server.credential.clone(),
+        ..Default::default()
+    });
+    config.ice_servers.splice(0..0, servers);
+    let pc = api.new_peer_connection(config).await?;
+
+    let trickle = outgoing.clone();
+    pc.on_ice_candidate(Box::new(move |candidate| {
+        let Some(candidate) = candidate else {
+            return Box::pin(async {});
+        };
+        let candidate_init = candidate
+            .to_json()
+            .ok()
+            .and_then(|init| serde_json::to_string(&init).ok());
+        match candidate_init {
+            Some(candidate_init) => {
+                let _ = trickle.send(request(signal_request::Message::Trickle(TrickleRequest {
+                    candidate_init,
+                    target: SignalTarget::Subscriber as i32,
+                })));
+            }
+            None => warn!("Failed to serialize ICE candidate {}", candidate),
+        }
+        Box::pin(async {})
+    }));
+
+    let state_events = events.clone();
+    pc.on_peer_connection_state_change(Box::new(move |state| {
+        let _ = state_events.send(RoomEvent::StateChanged(state));
+        Box::pin(async {})
+    }));
+
+    let track_events = events.clone();
+    pc.on_track(Box::new(move |track, _, _| {
+        // LiveKit sets the msid to "<participant sid>|<track sid>".
+        let stream_id = track.stream_id();
+        let (participant_sid, track_sid) = stream_id
+            .split_once('|')
+            .map_or((stream_id.clone(), track.id()), |(participant, track)| {
+                (participant.to_owned(), track.to_owned())
+            });
+        let kind = track.kind();
+        let codec = track.codec().capability.mime_type;
+        let frames =
+            (kind == RTPCodecType::Video && video::can_decode(&codec)).then(Snapshot::default);
+        let _ = track_events.send(RoomEvent::TrackSubscribed {
+            participant_sid,
+            track_sid,
+            kind,
+            codec,
+            frames: frames.clone(),
+        });
+        Box::pin(async move {
+            match frames {
+                Some(frames) => video::play_track(track, frames).await,
+                // Keep reading so the stream does not back up.
+                None => while track.read_rtp().await.is_ok() {},
+            }
+        })
+    }));
+    Ok(pc)
+}
+
+/// Answers a subscriber offer from the server.
+async fn answer(
+    pc: &RTCPeerConnection,
+    sdp: String,
+    outgoing: &mpsc::UnboundedSender<SignalRequest>,
+) -> Result<(), webrtc::Error> {
+    pc.set_remote_description(RTCSessionDescription::offer(sdp)?)
+        .await?;
+    let answer = pc.create_answer(None).await?;
+    pc.set_local_description(answer.clone()).await?;
+    let _ = outgoing.send(request(signal_request::Message::Answer(
+        SessionDescription {
+            r#type: "answer".to_owned(),
+            sdp: answer.sdp,
+        },
+    )));
+    Ok(())
+}
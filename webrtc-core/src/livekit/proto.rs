@@ -0,0 +1,184 @@
+//! The subset of LiveKit's signaling protobufs (`livekit_rtc.proto` and
+//! `livekit_models.proto`) a receive-only client needs. Field numbers match
+//! upstream; unknown fields are skipped by prost.
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SignalRequest {
+    #[prost(oneof = "signal_request::Message", tags = "1, 2, 3, 8, 14")]
+    pub message: Option<signal_request::Message>,
+}
+
+pub mod signal_request {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Message {
+        #[prost(message, tag = "1")]
+        Offer(super::SessionDescription),
+        #[prost(message, tag = "2")]
+        Answer(super::SessionDescription),
+        #[prost(message, tag = "3")]
+        Trickle(super::TrickleRequest),
+        #[prost(message, tag = "8")]
+        Leave(super::LeaveRequest),
+        /// Milliseconds since the Unix epoch.
+        #[prost(int64, tag = "14")]
+        Ping(i64),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SignalResponse {
+    #[prost(oneof = "signal_response::Message", tags = "1, 2, 3, 4, 5, 8, 17, 18")]
+    pub message: Option<signal_response::Message>,
+}
+
+pub mod signal_response {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Message {
+        #[prost(message, tag = "1")]
+        Join(super::JoinResponse),
+        #[prost(message, tag = "2")]
+        Answer(super::SessionDescription),
+        #[prost(message, tag = "3")]
+        Offer(super::SessionDescription),
+        #[prost(message, tag = "4")]
+        Trickle(super::TrickleRequest),
+        #[prost(message, tag = "5")]
+        Update(super::ParticipantUpdate),
+        #[prost(message, tag = "8")]
+        Leave(super::LeaveRequest),
+        #[prost(message, tag = "17")]
+        TrackUnpublished(super::TrackUnpublishedResponse),
+        #[prost(int64, tag = "18")]
+        Pong(i64),
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Copy, Debug, prost::Enumeration)]
+#[repr(i32)]
+pub enum SignalTarget {
+    Publisher = 0,
+    Subscriber = 1,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SessionDescription {
+    /// `offer` or `answer`.
+    #[prost(string, tag = "1")]
+    pub r#type: String,
+    #[prost(string, tag = "2")]
+    pub sdp: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TrickleRequest {
+    /// An `RTCIceCandidateInit` as JSON.
+    #[prost(string, tag = "1")]
+    pub candidate_init: String,
+    #[prost(enumeration = "SignalTarget", tag = "2")]
+    pub target: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct LeaveRequest {
+    #[prost(bool, tag = "1")]
+    pub can_reconnect: bool,
+    #[prost(int32, tag = "2")]
+    pub reason: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct JoinResponse {
+    #[prost(message, optional, tag = "1")]
+    pub room: Option<Room>,
+    #[prost(message, optional, tag = "2")]
+    pub participant: Option<ParticipantInfo>,
+    #[prost(message, repeated, tag = "3")]
+    pub other_participants: Vec<ParticipantInfo>,
+    #[prost(string, tag = "4")]
+    pub server_version: String,
+    #[prost(message, repeated, tag = "5")]
+    pub ice_servers: Vec<IceServer>,
+    #[prost(bool, tag = "6")]
+    pub subscriber_primary: bool,
+    /// Seconds without a pong before the connection counts as lost.
+    #[prost(int32, tag = "10")]
+    pub ping_timeout: i32,
+    /// Seconds between pings.
+    #[prost(int32, tag = "11")]
+    pub ping_interval: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct IceServer {
+    #[prost(string, repeated, tag = "1")]
+    pub urls: Vec<String>,
+    #[prost(string, tag = "2")]
+    pub username: String,
+    #[prost(string, tag = "3")]
+    pub credential: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Room {
+    #[prost(string, tag = "1")]
+    pub sid: String,
+    #[prost(string, tag = "2")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ParticipantUpdate {
+    #[prost(message, repeated, tag = "1")]
+    pub participants: Vec<ParticipantInfo>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ParticipantInfo {
+    #[prost(string, tag = "1")]
+    pub sid: String,
+    #[prost(string, tag = "2")]
+    pub identity: String,
+    #[prost(enumeration = "ParticipantState", tag = "3")]
+    pub state: i32,
+    #[prost(message, repeated, tag = "4")]
+    pub tracks: Vec<TrackInfo>,
+    #[prost(string, tag = "9")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Copy, Debug, prost::Enumeration)]
+#[repr(i32)]
+pub enum ParticipantState {
+    Joining = 0,
+    Joined = 1,
+    Active = 2,
+    Disconnected = 3,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TrackInfo {
+    #[prost(string, tag = "1")]
+    pub sid: String,
+    #[prost(enumeration = "TrackType", tag = "2")]
+    pub r#type: i32,
+    #[prost(string, tag = "3")]
+    pub name: String,
+    #[prost(bool, tag = "4")]
+    pub muted: bool,
+    #[prost(string, tag = "11")]
+    pub mime_type: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Copy, Debug, prost::Enumeration)]
+#[repr(i32)]
+pub enum TrackType {
+    Audio = 0,
+    Video = 1,
+    Data = 2,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TrackUnpublishedResponse {
+    #[prost(string, tag = "1")]
+    pub track_sid: String,
+}
@@ -0,0 +1,173 @@
+//! Streaming our log to the remote peer during a support session.
+//!
+//! The global logger is a tap around `env_logger`: records still go to
+//! stderr as configured by `RUST_LOG`, and while a stream is running those
+//! at `STREAM_LEVEL` and above are also copied to it, whatever `RUST_LOG`
+//! says. Nothing is copied until the user agrees to share.
+//!
+//! Records travel on their own pre-negotiated "logs" data channel next to
+//! the control channel, so a noisy log never delays renegotiation.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{Level, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::bounded::Footprint;
+
+pub const LOG_LABEL: &str = "logs";
+pub const LOG_STREAM_ID: u16 = 1;
+
+/// The most verbose level sent to the peer.
+pub const STREAM_LEVEL: Level = Level::Info;
+
+/// Records waiting to go out; more than this and they are dropped.
+const QUEUE: usize = 256;
+
+/// Sending a record logs from these; streaming them would feed itself.
+const QUIET_TARGETS: [&str; 2] = ["webrtc_sctp", "webrtc_data"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogRecord {
+    /// Milliseconds since the Unix epoch, on the sender's clock.
+    pub at: u64,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+impl LogRecord {
+    fn from_record(record: &Record) -> Self {
+        Self {
+            at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            level: record.level().to_string(),
+            target: record.target().to_owned(),
+            message: record.args().to_string(),
+        }
+    }
+
+    /// `None` for a level this version does not know.
+    pub fn level(&self) -> Option<Level> {
+        self.level.parse().ok()
+    }
+}
+
+impl Footprint for LogRecord {
+    fn footprint(&self) -> usize {
+        self.level.len() + self.target.len() + self.message.len()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LogMessage {
+    /// Asks the peer to share its log.
+    Request,
+    /// The peer agreed; records follow.
+    Started,
+    /// The peer declined, or stopped sharing.
+    Stopped,
+    Record(LogRecord),
+}
+
+impl LogMessage {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("log messages always serialize")
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(text)
+    }
+}
+
+pub struct LogTap {
+    inner: env_logger::Logger,
+    stream: Mutex<Option<mpsc::Sender<LogRecord>>>,
+    dropped: AtomicUsize,
+}
+
+static TAP: OnceLock<LogTap> = OnceLock::new();
+
+/// Installs the tap as the global logger, in place of `env_logger::init()`.
+pub fn init() {
+    let inner = env_logger::Builder::from_default_env().build();
+    let max_level = inner.filter().max(STREAM_LEVEL.to_level_filter());
+    let tap = TAP.get_or_init(|| LogTap {
+        inner,
+        stream: Mutex::new(None),
+        dropped: AtomicUsize::new(0),
+    });
+    if log::set_logger(tap).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// The installed tap; `None` before `init`.
+pub fn tap() -> Option<&'static LogTap> {
+    TAP.get()
+}
+
+impl LogTap {
+    /// Starts copying records to the returned receiver, ending any earlier
+    /// stream.
+    pub fn start(&self) -> mpsc::Receiver<LogRecord> {
+        let (sender, receiver) = mpsc::channel(QUEUE);
+        self.dropped.store(0, Ordering::Relaxed);
+        *self.stream.lock().unwrap() = Some(sender);
+        receiver
+    }
+
+    pub fn stop(&self) {
+        self.stream.lock().unwrap().take();
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        self.stream.lock().unwrap().is_some()
+    }
+
+    /// Records left out of the current stream because it fell behind.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn streams(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= STREAM_LEVEL
+            && !QUIET_TARGETS
+                .iter()
+                .any(|quiet| metadata.target().starts_with(quiet))
+    }
+}
+
+impl Log for LogTap {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata) || (self.streams(metadata) && self.is_streaming())
+    }
+
+    fn log(&self, record: &Record) {
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+        if !self.streams(record.metadata()) {
+            return;
+        }
+        let Ok(stream) = self.stream.try_lock() else {
+            // Logged while starting or stopping; not worth waiting for.
+            return;
+        };
+        if let Some(stream) = stream.as_ref() {
+            if stream.try_send(LogRecord::from_record(record)).is_err() {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
@@ -0,0 +1,87 @@
+//! IVF files: one VP8, VP9 or AV1 track of frames ready to send, as
+//! written by `ffmpeg -c:v libvpx -f ivf` and friends.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+
+use webrtc::api::media_engine::{MIME_TYPE_AV1, MIME_TYPE_VP8, MIME_TYPE_VP9};
+use webrtc::media::io::ivf_reader::IVFReader;
+
+use super::{Demuxer, FilePacket, FileTrack, MediaFileError};
+use crate::rtc::{MediaKind, TrackCodec};
+
+const VIDEO_CLOCK_RATE: u32 = 90_000;
+
+pub(super) struct IvfDemuxer {
+    reader: IVFReader<BufReader<File>>,
+    /// Seconds per timestamp tick.
+    timebase: f64,
+    tracks: Vec<FileTrack>,
+    skipped: Vec<String>,
+}
+
+impl IvfDemuxer {
+    pub(super) fn open(path: &Path) -> Result<Self, MediaFileError> {
+        let file = File::open(path).map_err(|err| MediaFileError::Io("open IVF file", err))?;
+        let (reader, header) = IVFReader::new(BufReader::new(file))
+            .map_err(|err| MediaFileError::Demux(err.to_string()))?;
+        let mime_type = match &header.four_cc {
+            b"VP80" => Some(MIME_TYPE_VP8),
+            b"VP90" => Some(MIME_TYPE_VP9),
+            b"AV01" => Some(MIME_TYPE_AV1),
+            _ => None,
+        };
+        let (tracks, skipped) = match mime_type {
+            Some(mime_type) => (
+                vec![FileTrack {
+                    kind: MediaKind::Video,
+                    codec: TrackCodec {
+                        mime_type: mime_type.to_owned(),
+                        clock_rate: VIDEO_CLOCK_RATE,
+                        channels: 0,
+                    },
+                }],
+                vec![],
+            ),
+            None => (
+                vec![],
+                vec![format!(
+                    "video in {}",
+                    String::from_utf8_lossy(&header.four_cc)
+                )],
+            ),
+        };
+        Ok(Self {
+            reader,
+            timebase: f64::from(header.timebase_numerator)
+                / f64::from(header.timebase_denominator.max(1)),
+            tracks,
+            skipped,
+        })
+    }
+}
+
+impl Demuxer for IvfDemuxer {
+    fn tracks(&self) -> &[FileTrack] {
+        &self.tracks
+    }
+
+    fn skipped(&self) -> &[String] {
+        &self.skipped
+    }
+
+    fn next_packet(&mut self) -> Result<Option<FilePacket>, MediaFileError> {
+        // The reader has no end of its own; a frame it cannot read ends the file.
+        let Ok((frame, header)) = self.reader.parse_next_frame() else {
+            return Ok(None);
+        };
+        Ok(Some(FilePacket {
+            track: 0,
+            timestamp: Duration::from_secs_f64(header.timestamp as f64 * self.timebase),
+            duration: None,
+            data: frame.freeze(),
+        }))
+    }
+}
@@ -0,0 +1,137 @@
+//! WebM and MKV files, whose blocks already come interleaved in time.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+
+use matroska_demuxer::{Frame, MatroskaFile, TrackType};
+use webrtc::api::media_engine::{
+    MIME_TYPE_AV1, MIME_TYPE_H264, MIME_TYPE_OPUS, MIME_TYPE_VP8, MIME_TYPE_VP9,
+};
+
+use super::{AvcConfig, Demuxer, FilePacket, FileTrack, MediaFileError};
+use crate::rtc::{MediaKind, TrackCodec};
+
+const VIDEO_CLOCK_RATE: u32 = 90_000;
+const OPUS_CLOCK_RATE: u32 = 48_000;
+
+struct Source {
+    index: usize,
+    default_duration: Option<Duration>,
+    avc: Option<AvcConfig>,
+}
+
+pub(super) struct MatroskaDemuxer {
+    file: MatroskaFile<BufReader<File>>,
+    /// Nanoseconds per block timestamp tick.
+    timestamp_scale: u64,
+    tracks: Vec<FileTrack>,
+    skipped: Vec<String>,
+    /// By Matroska track number.
+    sources: HashMap<u64, Source>,
+    frame: Frame,
+}
+
+fn demux_error(err: matroska_demuxer::DemuxError) -> MediaFileError {
+    MediaFileError::Demux(err.to_string())
+}
+
+impl MatroskaDemuxer {
+    pub(super) fn open(path: &Path) -> Result<Self, MediaFileError> {
+        let file = File::open(path).map_err(|err| MediaFileError::Io("open Matroska file", err))?;
+        let file = MatroskaFile::open(BufReader::new(file)).map_err(demux_error)?;
+        let (mut tracks, mut skipped, mut sources) = (vec![], vec![], HashMap::new());
+        for entry in file.tracks() {
+            let video = |mime_type: &str| {
+                let codec = TrackCodec {
+                    mime_type: mime_type.to_owned(),
+                    clock_rate: VIDEO_CLOCK_RATE,
+                    channels: 0,
+                };
+                Some((MediaKind::Video, codec))
+            };
+            let mut avc = None;
+            let sendable = match (entry.track_type(), entry.codec_id()) {
+                (TrackType::Video, "V_VP8") => video(MIME_TYPE_VP8),
+                (TrackType::Video, "V_VP9") => video(MIME_TYPE_VP9),
+                (TrackType::Video, "V_AV1") => video(MIME_TYPE_AV1),
+                (TrackType::Video, "V_MPEG4/ISO/AVC") => {
+                    avc = entry.codec_private().and_then(AvcConfig::parse);
+                    avc.is_some().then(|| video(MIME_TYPE_H264)).flatten()
+                }
+                // Opus always goes out as two channels in SDP terms.
+                (TrackType::Audio, "A_OPUS") => Some((
+                    MediaKind::Audio,
+                    TrackCodec {
+                        mime_type: MIME_TYPE_OPUS.to_owned(),
+                        clock_rate: OPUS_CLOCK_RATE,
+                        channels: 2,
+                    },
+                )),
+                _ => None,
+            };
+            let Some((kind, codec)) = sendable else {
+                skipped.push(format!(
+                    "{:?} track in {}",
+                    entry.track_type(),
+                    entry.codec_id()
+                ));
+                continue;
+            };
+            sources.insert(
+                entry.track_number().get(),
+                Source {
+                    index: tracks.len(),
+                    default_duration: entry
+                        .default_duration()
+                        .map(|nanos| Duration::from_nanos(nanos.get())),
+                    avc,
+                },
+            );
+            tracks.push(FileTrack { kind, codec });
+        }
+        Ok(Self {
+            timestamp_scale: file.info().timestamp_scale().get(),
+            file,
+            tracks,
+            skipped,
+            sources,
+            frame: Frame::default(),
+        })
+    }
+}
+
+impl Demuxer for MatroskaDemuxer {
+    fn tracks(&self) -> &[FileTrack] {
+        &self.tracks
+    }
+
+    fn skipped(&self) -> &[String] {
+        &self.skipped
+    }
+
+    fn next_packet(&mut self) -> Result<Option<FilePacket>, MediaFileError> {
+        loop {
+            if !self.file.next_frame(&mut self.frame).map_err(demux_error)? {
+                return Ok(None);
+            }
+            let Some(source) = self.sources.get(&self.frame.track) else {
+                continue;
+            };
+            let data = match &source.avc {
+                Some(avc) => avc.to_annex_b(&self.frame.data),
+                None => std::mem::take(&mut self.frame.data),
+            };
+            return Ok(Some(FilePacket {
+                track: source.index,
+                timestamp: Duration::from_nanos(
+                    self.frame.timestamp.saturating_mul(self.timestamp_scale),
+                ),
+                duration: source.default_duration,
+                data: data.into(),
+            }));
+        }
+    }
+}
@@ -0,0 +1,298 @@
+//! Sending a video file in place of the camera and microphone, for watching
+//! something together or for soak tests with the same content every run.
+//!
+//! The tracks of an MP4, WebM or MKV file go out as they are, without
+//! re-encoding, paced by their timestamps in decode order. Tracks in codecs
+//! WebRTC cannot carry, such as AAC audio, are left out. So do the
+//! elementary streams of IVF (VP8, VP9, AV1) and Ogg Opus files, which
+//! makes for codec-level interop tests with known bitstreams; raw Y4M
+//! pictures are the one thing encoded on the way.
+
+mod ivf;
+mod matroska;
+mod mp4;
+mod ogg;
+mod y4m;
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use log::info;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use webrtc::media::Sample;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+
+use crate::rtc::{MediaKind, TrackCodec};
+
+/// Where a sample's duration is not in the file and not known from the
+/// one before it, as for the first sample of a track.
+const FALLBACK_VIDEO_DURATION: Duration = Duration::from_millis(33);
+const FALLBACK_AUDIO_DURATION: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Error)]
+pub enum MediaFileError {
+    #[error("{0} is not an MP4, WebM, MKV, IVF, Ogg or Y4M file")]
+    UnknownContainer(String),
+    #[error("cannot read the file: {0}")]
+    Demux(String),
+    #[error("none of its tracks can be sent ({0})")]
+    NothingToSend(String),
+    #[error("this build has no video encoder for raw Y4M video")]
+    NoEncoder,
+    #[error("{0}: {1}")]
+    Io(&'static str, #[source] std::io::Error),
+}
+
+/// A track of the file that can be sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileTrack {
+    pub kind: MediaKind,
+    pub codec: TrackCodec,
+}
+
+/// One encoded frame of a track, with its time from the start of the file.
+#[derive(Debug, Clone)]
+struct FilePacket {
+    /// Index into the demuxer's tracks.
+    track: usize,
+    timestamp: Duration,
+    /// `None` where the container leaves it out.
+    duration: Option<Duration>,
+    data: Bytes,
+}
+
+trait Demuxer: Send {
+    fn tracks(&self) -> &[FileTrack];
+    /// Descriptions of the tracks that were left out.
+    fn skipped(&self) -> &[String];
+    /// The next packet of any track in decode order, `None` at the end.
+    fn next_packet(&mut self) -> Result<Option<FilePacket>, MediaFileError>;
+}
+
+pub struct MediaFile {
+    path: PathBuf,
+    demuxer: Box<dyn Demuxer>,
+}
+
+impl MediaFile {
+    /// Opens `path` by its contents, whatever its extension says.
+    pub fn open(path: &Path) -> Result<Self, MediaFileError> {
+        let mut magic = [0; 8];
+        File::open(path)
+            .and_then(|mut file| file.read_exact(&mut magic))
+            .map_err(|err| MediaFileError::Io("read media file", err))?;
+        let demuxer: Box<dyn Demuxer> = if &magic[4..8] == b"ftyp" {
+            Box::new(mp4::Mp4Demuxer::open(path)?)
+        } else if magic[..4] == [0x1a, 0x45, 0xdf, 0xa3] {
+            Box::new(matroska::MatroskaDemuxer::open(path)?)
+        } else if &magic[..4] == b"DKIF" {
+            Box::new(ivf::IvfDemuxer::open(path)?)
+        } else if &magic[..4] == b"OggS" {
+            Box::new(ogg::OggDemuxer::open(path)?)
+        } else if &magic == b"YUV4MPEG" {
+            Box::new(y4m::Y4mDemuxer::open(path)?)
+        } else {
+            return Err(MediaFileError::UnknownContainer(path.display().to_string()));
+        };
+        if demuxer.tracks().is_empty() {
+            return Err(MediaFileError::NothingToSend(demuxer.skipped().join(", ")));
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            demuxer,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn tracks(&self) -> &[FileTrack] {
+        self.demuxer.tracks()
+    }
+
+    pub fn skipped(&self) -> &[String] {
+        self.demuxer.skipped()
+    }
+}
+
+/// H.264 parameter sets and NAL length size from an `avcC` record, for
+/// turning length-prefixed samples into the Annex B the payloader takes.
+#[derive(Debug, Clone, PartialEq)]
+struct AvcConfig {
+    length_size: usize,
+    parameter_sets: Vec<Vec<u8>>,
+}
+
+impl AvcConfig {
+    fn parse(record: &[u8]) -> Option<Self> {
+        let length_size = usize::from(*record.get(4)? & 0x03) + 1;
+        let mut at = 5;
+        let mut parameter_sets = vec![];
+        // SPS count in the low five bits, then the PPS count as a byte.
+        for mask in [0x1f, 0xff] {
+            let count = *record.get(at)? & mask;
+            at += 1;
+            for _ in 0..count {
+                let len = record.get(at..at + 2)?;
+                let len = usize::from(u16::from_be_bytes([len[0], len[1]]));
+                parameter_sets.push(record.get(at + 2..at + 2 + len)?.to_vec());
+                at += 2 + len;
+            }
+        }
+        Some(Self {
+            length_size,
+            parameter_sets,
+        })
+    }
+
+    /// Rewrites `sample` with start codes, with the parameter sets in front
+    /// of IDR pictures so the far end can start decoding at any of them.
+    fn to_annex_b(&self, sample: &[u8]) -> Vec<u8> {
+        let mut nals = vec![];
+        let mut rest = sample;
+        while rest.len() > self.length_size {
+            let (len, tail) = rest.split_at(self.length_size);
+            let len = len
+                .iter()
+                .fold(0, |len, byte| len << 8 | usize::from(*byte))
+                .min(tail.len());
+            nals.push(&tail[..len]);
+            rest = &tail[len..];
+        }
+        let mut annex_b = Vec::with_capacity(sample.len() + 64);
+        let idr = nals
+            .iter()
+            .any(|nal| nal.first().map(|b| b & 0x1f) == Some(5));
+        if idr {
+            for set in &self.parameter_sets {
+                annex_b.extend_from_slice(&[0, 0, 0, 1]);
+                annex_b.extend_from_slice(set);
+            }
+        }
+        for nal in nals {
+            annex_b.extend_from_slice(&[0, 0, 0, 1]);
+            annex_b.extend_from_slice(nal);
+        }
+        annex_b
+    }
+}
+
+/// Writes the tracks of `file` into `tracks`, one per file track in the
+/// same order, in real time until the returned task is aborted or the
+/// file ends. With `looping` the file starts over instead of ending, with
+/// timestamps carrying on.
+pub fn spawn_playback(
+    mut file: MediaFile,
+    tracks: Vec<Arc<TrackLocalStaticSample>>,
+    looping: bool,
+) -> Result<JoinHandle<()>, MediaFileError> {
+    let kinds: Vec<MediaKind> = file.tracks().iter().map(|track| track.kind).collect();
+    let (samples_tx, mut samples) = mpsc::channel::<(usize, Sample)>(8);
+    std::thread::Builder::new()
+        .name("media file".to_owned())
+        .spawn(move || {
+            let start = Instant::now();
+            // Where the current pass over the file starts on the timeline,
+            // and where the last one ended.
+            let mut offset = Duration::ZERO;
+            let mut end = Duration::ZERO;
+            let mut previous: Vec<Option<Duration>> = vec![None; kinds.len()];
+            let mut durations: Vec<Duration> = kinds
+                .iter()
+                .map(|kind| match kind {
+                    MediaKind::Video => FALLBACK_VIDEO_DURATION,
+                    MediaKind::Audio => FALLBACK_AUDIO_DURATION,
+                })
+                .collect();
+            while !samples_tx.is_closed() {
+                let packet = match file.demuxer.next_packet() {
+                    Ok(Some(packet)) => packet,
+                    Ok(None) if looping => match MediaFile::open(&file.path) {
+                        Ok(reopened) => {
+                            file = reopened;
+                            offset = end;
+                            previous.fill(None);
+                            continue;
+                        }
+                        Err(err) => {
+                            info!("Cannot play {} again: {}", file.path.display(), err);
+                            return;
+                        }
+                    },
+                    Ok(None) => {
+                        info!("Finished playing {}", file.path.display());
+                        return;
+                    }
+                    Err(err) => {
+                        info!("Stopped playing {}: {}", file.path.display(), err);
+                        return;
+                    }
+                };
+                let track = packet.track;
+                if let Some(duration) = packet
+                    .duration
+                    .or_else(|| previous[track].and_then(|at| packet.timestamp.checked_sub(at)))
+                {
+                    durations[track] = duration;
+                }
+                previous[track] = Some(packet.timestamp);
+                let at = offset + packet.timestamp;
+                end = end.max(at + durations[track]);
+                std::thread::sleep((start + at).saturating_duration_since(Instant::now()));
+                let sample = Sample {
+                    data: packet.data,
+                    duration: durations[track],
+                    ..Default::default()
+                };
+                if samples_tx.blocking_send((track, sample)).is_err() {
+                    return;
+                }
+            }
+        })
+        .map_err(|err| MediaFileError::Io("spawn playback thread", err))?;
+    Ok(tokio::spawn(async move {
+        while let Some((index, sample)) = samples.recv().await {
+            let Some(track) = tracks.get(index) else {
+                continue;
+            };
+            if let Err(err) = track.write_sample(&sample).await {
+                info!("Failed to write media file sample: {:?}", err);
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn avc_samples_get_start_codes_and_parameter_sets_on_idr() {
+        let record = [
+            1, 0x42, 0xc0, 0x1e, 0xff, // 4-byte lengths
+            0xe1, 0, 2, 0x67, 0x42, // one SPS
+            1, 0, 2, 0x68, 0xce, // one PPS
+        ];
+        let config = AvcConfig::parse(&record).unwrap();
+        assert_eq!(config.length_size, 4);
+        assert_eq!(
+            config.parameter_sets,
+            vec![vec![0x67, 0x42], vec![0x68, 0xce]]
+        );
+
+        let idr = [0, 0, 0, 2, 0x65, 0xaa];
+        assert_eq!(
+            config.to_annex_b(&idr),
+            [0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x68, 0xce, 0, 0, 0, 1, 0x65, 0xaa]
+        );
+        let inter = [0, 0, 0, 1, 0x41];
+        assert_eq!(config.to_annex_b(&inter), [0, 0, 0, 1, 0x41]);
+    }
+}
@@ -0,0 +1,163 @@
+//! MP4 (and MOV) files, read a sample at a time from each track and merged
+//! by decode time.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+
+use mp4::{MediaType, Mp4Reader, Mp4Sample};
+use webrtc::api::media_engine::{MIME_TYPE_H264, MIME_TYPE_VP9};
+
+use super::{AvcConfig, Demuxer, FilePacket, FileTrack, MediaFileError};
+use crate::rtc::{MediaKind, TrackCodec};
+
+const VIDEO_CLOCK_RATE: u32 = 90_000;
+
+struct Source {
+    track_id: u32,
+    timescale: u32,
+    sample_count: u32,
+    /// 1-based, as sample ids are.
+    next_sample: u32,
+    avc: Option<AvcConfig>,
+    peeked: Option<Mp4Sample>,
+}
+
+pub(super) struct Mp4Demuxer {
+    reader: Mp4Reader<BufReader<File>>,
+    tracks: Vec<FileTrack>,
+    skipped: Vec<String>,
+    sources: Vec<Source>,
+}
+
+fn demux_error(err: mp4::Error) -> MediaFileError {
+    MediaFileError::Demux(err.to_string())
+}
+
+impl Mp4Demuxer {
+    pub(super) fn open(path: &Path) -> Result<Self, MediaFileError> {
+        let file = File::open(path).map_err(|err| MediaFileError::Io("open MP4 file", err))?;
+        let size = file
+            .metadata()
+            .map_err(|err| MediaFileError::Io("read MP4 file size", err))?
+            .len();
+        let reader = Mp4Reader::read_header(BufReader::new(file), size).map_err(demux_error)?;
+        let mut ids: Vec<u32> = reader.tracks().keys().copied().collect();
+        ids.sort_unstable();
+
+        let (mut tracks, mut skipped, mut sources) = (vec![], vec![], vec![]);
+        for id in ids {
+            let track = &reader.tracks()[&id];
+            let (codec, avc) = match track.media_type() {
+                Ok(MediaType::H264) => {
+                    let avc = track.trak.mdia.minf.stbl.stsd.avc1.as_ref().map(|avc1| {
+                        let avcc = &avc1.avcc;
+                        AvcConfig {
+                            length_size: usize::from(avcc.length_size_minus_one & 0x03) + 1,
+                            parameter_sets: avcc
+                                .sequence_parameter_sets
+                                .iter()
+                                .chain(&avcc.picture_parameter_sets)
+                                .map(|set| set.bytes.clone())
+                                .collect(),
+                        }
+                    });
+                    (MIME_TYPE_H264, avc)
+                }
+                Ok(MediaType::VP9) => (MIME_TYPE_VP9, None),
+                Ok(MediaType::AAC) => {
+                    skipped.push("AAC audio".to_owned());
+                    continue;
+                }
+                Ok(other) => {
+                    skipped.push(format!("{} track", other));
+                    continue;
+                }
+                Err(_) => {
+                    let kind = track
+                        .track_type()
+                        .map_or("unknown".to_owned(), |kind| kind.to_string());
+                    skipped.push(format!("{} track in an unsupported codec", kind));
+                    continue;
+                }
+            };
+            tracks.push(FileTrack {
+                kind: MediaKind::Video,
+                codec: TrackCodec {
+                    mime_type: codec.to_owned(),
+                    clock_rate: VIDEO_CLOCK_RATE,
+                    channels: 0,
+                },
+            });
+            sources.push(Source {
+                track_id: id,
+                timescale: track.timescale().max(1),
+                sample_count: track.sample_count(),
+                next_sample: 1,
+                avc,
+                peeked: None,
+            });
+        }
+        Ok(Self {
+            reader,
+            tracks,
+            skipped,
+            sources,
+        })
+    }
+}
+
+impl Demuxer for Mp4Demuxer {
+    fn tracks(&self) -> &[FileTrack] {
+        &self.tracks
+    }
+
+    fn skipped(&self) -> &[String] {
+        &self.skipped
+    }
+
+    fn next_packet(&mut self) -> Result<Option<FilePacket>, MediaFileError> {
+        for source in &mut self.sources {
+            while source.peeked.is_none() && source.next_sample <= source.sample_count {
+                source.peeked = self
+                    .reader
+                    .read_sample(source.track_id, source.next_sample)
+                    .map_err(demux_error)?;
+                source.next_sample += 1;
+            }
+        }
+        let next = self
+            .sources
+            .iter()
+            .enumerate()
+            .filter_map(|(index, source)| {
+                let sample = source.peeked.as_ref()?;
+                Some((
+                    index,
+                    sample.start_time as f64 / f64::from(source.timescale),
+                ))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        let Some((index, _)) = next else {
+            return Ok(None);
+        };
+        let source = &mut self.sources[index];
+        let Some(sample) = source.peeked.take() else {
+            return Ok(None);
+        };
+        let timescale = f64::from(source.timescale);
+        let data = match &source.avc {
+            Some(avc) => avc.to_annex_b(&sample.bytes).into(),
+            None => sample.bytes,
+        };
+        Ok(Some(FilePacket {
+            track: index,
+            timestamp: Duration::from_secs_f64(sample.start_time as f64 / timescale),
+            duration: Some(Duration::from_secs_f64(
+                f64::from(sample.duration) / timescale,
+            )),
+            data,
+        }))
+    }
+}
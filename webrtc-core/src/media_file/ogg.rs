@@ -0,0 +1,185 @@
+//! Ogg Opus files (`.ogg`, `.opus`), one Opus packet at a time. Pages are
+//! split along their segment tables, so files packing many packets into a
+//! page play as smoothly as those that do not.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+use std::path::Path;
+use std::time::Duration;
+
+use bytes::Bytes;
+use webrtc::api::media_engine::MIME_TYPE_OPUS;
+
+use super::{Demuxer, FilePacket, FileTrack, MediaFileError};
+use crate::rtc::{MediaKind, TrackCodec};
+
+const OPUS_CLOCK_RATE: u32 = 48_000;
+const PAGE_HEADER_LEN: usize = 27;
+
+pub(super) struct OggDemuxer {
+    reader: BufReader<File>,
+    /// The stream the Opus track is in; pages of others are passed over.
+    serial: Option<u32>,
+    /// Whole packets of the current page not yet handed out.
+    queued: VecDeque<Vec<u8>>,
+    /// A packet continuing onto the next page.
+    partial: Vec<u8>,
+    /// The `OpusHead` and `OpusTags` packets still to skip.
+    headers_left: usize,
+    timestamp: Duration,
+    tracks: Vec<FileTrack>,
+}
+
+impl OggDemuxer {
+    pub(super) fn open(path: &Path) -> Result<Self, MediaFileError> {
+        let file = File::open(path).map_err(|err| MediaFileError::Io("open Ogg file", err))?;
+        let mut demuxer = Self {
+            reader: BufReader::new(file),
+            serial: None,
+            queued: VecDeque::new(),
+            partial: vec![],
+            headers_left: 2,
+            timestamp: Duration::ZERO,
+            tracks: vec![],
+        };
+        let head = demuxer.next_raw_packet()?.unwrap_or_default();
+        if !head.starts_with(b"OpusHead") {
+            return Err(MediaFileError::NothingToSend(
+                "only Ogg files with Opus audio can be sent".to_owned(),
+            ));
+        }
+        demuxer.headers_left -= 1;
+        demuxer.tracks.push(FileTrack {
+            kind: MediaKind::Audio,
+            codec: TrackCodec {
+                mime_type: MIME_TYPE_OPUS.to_owned(),
+                clock_rate: OPUS_CLOCK_RATE,
+                channels: 2,
+            },
+        });
+        Ok(demuxer)
+    }
+
+    /// Reads a page into `queued`; false at the end of the file.
+    fn read_page(&mut self) -> Result<bool, MediaFileError> {
+        let mut header = [0; PAGE_HEADER_LEN];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+            Err(err) => return Err(MediaFileError::Io("read Ogg page", err)),
+        }
+        if &header[..4] != b"OggS" {
+            return Err(MediaFileError::Demux("lost the Ogg page sync".to_owned()));
+        }
+        let serial = u32::from_le_bytes([header[14], header[15], header[16], header[17]]);
+        let mut segments = vec![0; usize::from(header[26])];
+        self.reader
+            .read_exact(&mut segments)
+            .map_err(|err| MediaFileError::Io("read Ogg page", err))?;
+        let mut body = vec![0; segments.iter().map(|len| usize::from(*len)).sum()];
+        self.reader
+            .read_exact(&mut body)
+            .map_err(|err| MediaFileError::Io("read Ogg page", err))?;
+        if *self.serial.get_or_insert(serial) != serial {
+            return Ok(true);
+        }
+        // A packet ends at the first segment shorter than 255 bytes.
+        let mut at = 0;
+        for len in segments {
+            let len = usize::from(len);
+            self.partial.extend_from_slice(&body[at..at + len]);
+            at += len;
+            if len < 255 {
+                self.queued.push_back(std::mem::take(&mut self.partial));
+            }
+        }
+        Ok(true)
+    }
+
+    fn next_raw_packet(&mut self) -> Result<Option<Vec<u8>>, MediaFileError> {
+        while self.queued.is_empty() {
+            if !self.read_page()? {
+                return Ok(None);
+            }
+        }
+        Ok(self.queued.pop_front())
+    }
+}
+
+impl Demuxer for OggDemuxer {
+    fn tracks(&self) -> &[FileTrack] {
+        &self.tracks
+    }
+
+    fn skipped(&self) -> &[String] {
+        &[]
+    }
+
+    fn next_packet(&mut self) -> Result<Option<FilePacket>, MediaFileError> {
+        loop {
+            let Some(packet) = self.next_raw_packet()? else {
+                return Ok(None);
+            };
+            if self.headers_left > 0 {
+                self.headers_left -= 1;
+                continue;
+            }
+            let Some(duration) = opus_packet_duration(&packet) else {
+                continue;
+            };
+            let timestamp = self.timestamp;
+            self.timestamp += duration;
+            return Ok(Some(FilePacket {
+                track: 0,
+                timestamp,
+                duration: Some(duration),
+                data: Bytes::from(packet),
+            }));
+        }
+    }
+}
+
+/// How much audio an Opus packet holds, from its TOC byte (RFC 6716,
+/// section 3.1). `None` for packets too short to say.
+fn opus_packet_duration(packet: &[u8]) -> Option<Duration> {
+    let toc = *packet.first()?;
+    let config = toc >> 3;
+    // Frame sizes in tenths of a millisecond, by mode.
+    let frame = match config {
+        0..=11 => [100, 200, 400, 600][usize::from(config % 4)],
+        12..=15 => [100, 200][usize::from(config % 2)],
+        _ => [25, 50, 100, 200][usize::from(config % 4)],
+    };
+    let frames = match toc & 0x03 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => u64::from(*packet.get(1)? & 0x3f),
+    };
+    Some(Duration::from_micros(frame * frames * 100))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opus_durations_follow_the_toc_byte() {
+        // CELT fullband 20 ms, one frame; the usual WebRTC packet.
+        assert_eq!(
+            opus_packet_duration(&[0xf8, 0]),
+            Some(Duration::from_millis(20))
+        );
+        // SILK 60 ms, two frames.
+        assert_eq!(
+            opus_packet_duration(&[0x19, 0]),
+            Some(Duration::from_millis(120))
+        );
+        // CELT 2.5 ms, code 3 with five frames.
+        assert_eq!(
+            opus_packet_duration(&[0x83, 5]),
+            Some(Duration::from_micros(12_500))
+        );
+        assert_eq!(opus_packet_duration(&[]), None);
+    }
+}
@@ -0,0 +1,138 @@
+//! Y4M files of raw 4:2:0 pictures, encoded on the way out with the codec
+//! this build sends video with. Handy for feeding a codec the same frames
+//! another implementation was tested with.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::path::Path;
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use super::{Demuxer, FilePacket, FileTrack, MediaFileError};
+use crate::codec::i420_to_rgba;
+use crate::rtc::{MediaKind, TrackCodec};
+use crate::video::{self, VideoEncoder, VideoFrame};
+
+const VIDEO_CLOCK_RATE: u32 = 90_000;
+
+pub(super) struct Y4mDemuxer {
+    reader: BufReader<File>,
+    width: usize,
+    height: usize,
+    frame_duration: Duration,
+    encoder: Box<dyn VideoEncoder>,
+    frames: u32,
+    tracks: Vec<FileTrack>,
+}
+
+impl Y4mDemuxer {
+    pub(super) fn open(path: &Path) -> Result<Self, MediaFileError> {
+        let file = File::open(path).map_err(|err| MediaFileError::Io("open Y4M file", err))?;
+        let mut reader = BufReader::new(file);
+        let mut header = String::new();
+        reader
+            .read_line(&mut header)
+            .map_err(|err| MediaFileError::Io("read Y4M header", err))?;
+        let (mut width, mut height, mut rate) = (0, 0, (30, 1));
+        for param in header.split_whitespace().skip(1) {
+            let Some((tag, value)) = param.split_at_checked(1) else {
+                continue;
+            };
+            match tag {
+                "W" => width = value.parse().unwrap_or(0),
+                "H" => height = value.parse().unwrap_or(0),
+                "F" => {
+                    if let Some((num, den)) = value.split_once(':') {
+                        rate = (num.parse().unwrap_or(30), den.parse().unwrap_or(1));
+                    }
+                }
+                "C" if !value.starts_with("420") => {
+                    return Err(MediaFileError::NothingToSend(format!(
+                        "Y4M in {} colour, only 4:2:0 can be sent",
+                        value
+                    )));
+                }
+                _ => {}
+            }
+        }
+        if width == 0 || height == 0 || rate.0 == 0 || rate.1 == 0 {
+            return Err(MediaFileError::Demux("malformed Y4M header".to_owned()));
+        }
+        let frame_rate = rate.0 as f32 / rate.1 as f32;
+        let mime_type = video::encoder_mime_type().ok_or(MediaFileError::NoEncoder)?;
+        let encoder = video::encoder(width, height, frame_rate).ok_or(MediaFileError::NoEncoder)?;
+        Ok(Self {
+            reader,
+            width,
+            height,
+            frame_duration: Duration::from_secs_f64(f64::from(rate.1) / f64::from(rate.0)),
+            encoder,
+            frames: 0,
+            tracks: vec![FileTrack {
+                kind: MediaKind::Video,
+                codec: TrackCodec {
+                    mime_type: mime_type.to_owned(),
+                    clock_rate: VIDEO_CLOCK_RATE,
+                    channels: 0,
+                },
+            }],
+        })
+    }
+
+    /// The next picture, `None` at the end of the file.
+    fn read_frame(&mut self) -> Result<Option<VideoFrame>, MediaFileError> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => return Ok(None),
+            Ok(_) if line.starts_with("FRAME") => {}
+            Ok(_) => return Err(MediaFileError::Demux("expected a Y4M FRAME".to_owned())),
+            Err(err) => return Err(MediaFileError::Io("read Y4M frame", err)),
+        }
+        let (width, height) = (self.width, self.height);
+        let chroma = width.div_ceil(2) * height.div_ceil(2);
+        let mut planes = vec![0; width * height + 2 * chroma];
+        match self.reader.read_exact(&mut planes) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(MediaFileError::Io("read Y4M frame", err)),
+        }
+        let (y, chroma_planes) = planes.split_at(width * height);
+        let (u, v) = chroma_planes.split_at(chroma);
+        Ok(Some(VideoFrame {
+            width,
+            height,
+            rgba: i420_to_rgba(y, u, v, width, height),
+        }))
+    }
+}
+
+impl Demuxer for Y4mDemuxer {
+    fn tracks(&self) -> &[FileTrack] {
+        &self.tracks
+    }
+
+    fn skipped(&self) -> &[String] {
+        &[]
+    }
+
+    fn next_packet(&mut self) -> Result<Option<FilePacket>, MediaFileError> {
+        loop {
+            let Some(frame) = self.read_frame()? else {
+                return Ok(None);
+            };
+            let timestamp = self.frame_duration * self.frames;
+            self.frames += 1;
+            // Encoders may hold a frame back; its time still passes.
+            let Some(data) = self.encoder.encode(&frame) else {
+                continue;
+            };
+            return Ok(Some(FilePacket {
+                track: 0,
+                timestamp,
+                duration: Some(self.frame_duration),
+                data: Bytes::from(data),
+            }));
+        }
+    }
+}
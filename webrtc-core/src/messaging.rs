@@ -0,0 +1,154 @@
+//! Typed messages for protocols built on the app's data channels. Each
+//! message type is a serde struct with a kind; it goes over as JSON text
+//! in an envelope naming that kind, so the other end can tell types apart
+//! and the data channel log can show any of them laid out.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use webrtc::data_channel::RTCDataChannel;
+
+use crate::framing;
+
+/// A message type, and the kind it is sent as.
+pub trait TypedMessage: Serialize + DeserializeOwned {
+    /// Names the type on the wire, such as "telemetry.sample".
+    const KIND: &'static str;
+}
+
+/// How every typed message goes over: its kind, and itself as the body.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub kind: String,
+    pub body: Value,
+}
+
+impl Envelope {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("envelopes always serialize")
+    }
+
+    /// The envelope in `text`, if it is one.
+    pub fn from_json(text: &str) -> Result<Self, MessageError> {
+        serde_json::from_str(text).map_err(MessageError::NotEnvelope)
+    }
+
+    /// The body, as an `M`.
+    pub fn open<M: TypedMessage>(self) -> Result<M, MessageError> {
+        if self.kind != M::KIND {
+            return Err(MessageError::WrongKind {
+                expected: M::KIND,
+                found: self.kind,
+            });
+        }
+        serde_json::from_value(self.body).map_err(MessageError::Body)
+    }
+
+    /// The body laid out over several lines.
+    pub fn pretty_body(&self) -> String {
+        serde_json::to_string_pretty(&self.body).expect("JSON values always serialize")
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MessageError {
+    #[error("not a typed message: {0}")]
+    NotEnvelope(serde_json::Error),
+    #[error("expected a {expected} message, got {found}")]
+    WrongKind {
+        expected: &'static str,
+        found: String,
+    },
+    #[error("the body does not fit the message type: {0}")]
+    Body(serde_json::Error),
+    #[error("failed to send: {0}")]
+    Send(#[from] webrtc::Error),
+}
+
+/// An envelope to send, borrowing the message so its fields keep their
+/// order.
+#[derive(Serialize)]
+struct Sealed<'a, M> {
+    kind: &'a str,
+    body: &'a M,
+}
+
+/// `message` as the text to send.
+pub fn encode<M: TypedMessage>(message: &M) -> String {
+    let sealed = Sealed {
+        kind: M::KIND,
+        body: message,
+    };
+    serde_json::to_string(&sealed).expect("typed messages always serialize")
+}
+
+/// The `M` in `text`.
+pub fn decode<M: TypedMessage>(text: &str) -> Result<M, MessageError> {
+    Envelope::from_json(text)?.open()
+}
+
+/// Sends `message` on `channel` as text, in pieces if it is long, for a
+/// [`framing::Reassembler`] to put back together.
+pub async fn send<M: TypedMessage>(
+    channel: &RTCDataChannel,
+    message: &M,
+) -> Result<(), MessageError> {
+    framing::send_text(channel, encode(message)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Sample {
+        sensor: String,
+        celsius: f64,
+    }
+
+    impl TypedMessage for Sample {
+        const KIND: &'static str = "telemetry.sample";
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Other {}
+
+    impl TypedMessage for Other {
+        const KIND: &'static str = "other";
+    }
+
+    #[test]
+    fn round_trips_in_an_envelope_naming_the_kind() {
+        let sample = Sample {
+            sensor: "hall".to_owned(),
+            celsius: 21.5,
+        };
+        let text = encode(&sample);
+        assert_eq!(
+            text,
+            r#"{"kind":"telemetry.sample","body":{"sensor":"hall","celsius":21.5}}"#
+        );
+        assert_eq!(decode::<Sample>(&text).unwrap(), sample);
+
+        assert!(matches!(
+            decode::<Other>(&text),
+            Err(MessageError::WrongKind { expected: "other", found }) if found == Sample::KIND
+        ));
+        assert!(matches!(
+            decode::<Sample>(r#"{"kind":"telemetry.sample","body":{"sensor":1}}"#),
+            Err(MessageError::Body(_))
+        ));
+        assert!(matches!(
+            decode::<Sample>("hello"),
+            Err(MessageError::NotEnvelope(_))
+        ));
+
+        let envelope = Envelope::from_json(&text).unwrap();
+        assert_eq!(
+            envelope.pretty_body(),
+            "{\n  \"celsius\": 21.5,\n  \"sensor\": \"hall\"\n}"
+        );
+    }
+}
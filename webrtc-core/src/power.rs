@@ -0,0 +1,279 @@
+//! Battery and thermal state, and the encode limits that follow from it,
+//! so a laptop on battery or throttling its CPU sends smaller, slower
+//! video instead of draining or overheating further.
+//!
+//! The state is read from sysfs on Linux; elsewhere the machine counts as
+//! running on mains at a normal temperature, and nothing is limited.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::snapshot::Snapshot;
+use crate::video::{self, VideoFrame};
+
+/// Below this charge the limits tighten further.
+const LOW_BATTERY_PERCENT: u8 = 20;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PowerState {
+    pub on_battery: bool,
+    /// The charge left, where a battery reports it.
+    pub battery_percent: Option<u8>,
+    /// The kernel is cooling the machine by slowing it down.
+    pub thermal_throttled: bool,
+}
+
+impl PowerState {
+    /// Reads the current state. Missing or unreadable sysfs entries count
+    /// as mains power and a cool machine.
+    pub fn read() -> Self {
+        #[cfg(target_os = "linux")]
+        {
+            let root = std::path::Path::new("/sys/class");
+            let (on_battery, battery_percent) = linux::battery(&root.join("power_supply"));
+            Self {
+                on_battery,
+                battery_percent,
+                thermal_throttled: linux::thermal_throttled(&root.join("thermal")),
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            Self::default()
+        }
+    }
+
+    /// What video encoding should be held to, `None` when nothing calls
+    /// for saving power.
+    pub fn encode_limits(&self) -> Option<EncodeLimits> {
+        let mut limits: Option<EncodeLimits> = None;
+        let battery_low = self
+            .battery_percent
+            .is_some_and(|percent| percent <= LOW_BATTERY_PERCENT);
+        let mut tighten = |max_height, max_frame_rate| {
+            let limits = limits.get_or_insert(EncodeLimits {
+                max_height,
+                max_frame_rate,
+                state: *self,
+            });
+            limits.max_height = limits.max_height.min(max_height);
+            limits.max_frame_rate = limits.max_frame_rate.min(max_frame_rate);
+        };
+        if self.on_battery {
+            if battery_low {
+                tighten(480, 15.0);
+            } else {
+                tighten(720, 15.0);
+            }
+        }
+        if self.thermal_throttled {
+            tighten(360, 10.0);
+        }
+        limits
+    }
+}
+
+/// The largest video sent while saving power.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncodeLimits {
+    pub max_height: usize,
+    pub max_frame_rate: f64,
+    /// What the limits were worked out from.
+    pub state: PowerState,
+}
+
+impl EncodeLimits {
+    /// Why power is being saved, as in "On battery (40%)".
+    pub fn reason(&self) -> String {
+        let battery = match self.state.battery_percent {
+            Some(percent) => format!("On battery ({}%)", percent),
+            None => "On battery".to_owned(),
+        };
+        match (self.state.on_battery, self.state.thermal_throttled) {
+            (true, true) => format!("{} and running hot", battery),
+            (true, false) => battery,
+            (false, _) => "Running hot".to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for EncodeLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: sending video at up to {}p and {:.0} fps to save power",
+            self.reason(),
+            self.max_height,
+            self.max_frame_rate
+        )
+    }
+}
+
+/// Holds a capture loop to the limits published in a snapshot, dropping
+/// frames beyond the frame rate and scaling down those that are too tall.
+pub struct EncodeThrottle {
+    limits: Snapshot<Option<EncodeLimits>>,
+    last: Option<Instant>,
+}
+
+impl EncodeThrottle {
+    pub fn new(limits: Snapshot<Option<EncodeLimits>>) -> Self {
+        Self { limits, last: None }
+    }
+
+    /// Whether to encode a frame captured at `now`.
+    pub fn admit(&mut self, now: Instant) -> bool {
+        let Some(limits) = *self.limits.get() else {
+            self.last = Some(now);
+            return true;
+        };
+        // Some slack, or a camera running at twice the limit would have
+        // every other frame just miss and send at a third instead.
+        let interval = Duration::from_secs_f64(0.9 / limits.max_frame_rate.max(1.0));
+        if self
+            .last
+            .is_some_and(|last| now.saturating_duration_since(last) < interval)
+        {
+            return false;
+        }
+        self.last = Some(now);
+        true
+    }
+
+    /// `frame`, or a copy scaled down by a whole factor to fit the limits.
+    pub fn scale<'a>(&self, frame: &'a VideoFrame) -> Cow<'a, VideoFrame> {
+        match *self.limits.get() {
+            Some(limits) if frame.height > limits.max_height => {
+                let factor = frame.height.div_ceil(limits.max_height.max(1));
+                Cow::Owned(video::downscale(frame, factor))
+            }
+            _ => Cow::Borrowed(frame),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::fs;
+    use std::path::Path;
+
+    fn read(path: &Path) -> Option<String> {
+        Some(fs::read_to_string(path).ok()?.trim().to_owned())
+    }
+
+    /// Whether the machine runs off a discharging battery with no mains
+    /// supply online, and the charge of the first battery.
+    pub(super) fn battery(supplies: &Path) -> (bool, Option<u8>) {
+        let Ok(entries) = fs::read_dir(supplies) else {
+            return (false, None);
+        };
+        let (mut mains, mut discharging, mut percent) = (false, false, None);
+        for supply in entries.flatten().map(|entry| entry.path()) {
+            match read(&supply.join("type")).as_deref() {
+                Some("Mains") | Some("USB") => {
+                    mains |= read(&supply.join("online")).as_deref() == Some("1");
+                }
+                Some("Battery") => {
+                    // Peripherals such as mice report batteries too.
+                    if read(&supply.join("scope")).as_deref() == Some("Device") {
+                        continue;
+                    }
+                    discharging |= read(&supply.join("status")).as_deref() == Some("Discharging");
+                    if percent.is_none() {
+                        percent = read(&supply.join("capacity")).and_then(|c| c.parse().ok());
+                    }
+                }
+                _ => {}
+            }
+        }
+        (discharging && !mains, percent)
+    }
+
+    /// Whether any thermal zone has reached a passive trip point, where the
+    /// kernel starts slowing the CPU down to cool it.
+    pub(super) fn thermal_throttled(thermal: &Path) -> bool {
+        let Ok(entries) = fs::read_dir(thermal) else {
+            return false;
+        };
+        entries.flatten().map(|entry| entry.path()).any(|zone| {
+            let is_zone = zone
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("thermal_zone"));
+            let temp = read(&zone.join("temp")).and_then(|temp| temp.parse::<i64>().ok());
+            let (true, Some(temp)) = (is_zone, temp) else {
+                return false;
+            };
+            (0..)
+                .map_while(|trip| {
+                    let kind = read(&zone.join(format!("trip_point_{}_type", trip)))?;
+                    let at = read(&zone.join(format!("trip_point_{}_temp", trip)));
+                    Some((kind, at.and_then(|at| at.parse::<i64>().ok())))
+                })
+                .any(|(kind, at)| {
+                    matches!(kind.as_str(), "passive" | "hot")
+                        && at.is_some_and(|at| at > 0 && temp >= at)
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_tighten_with_each_reason() {
+        assert_eq!(PowerState::default().encode_limits(), None);
+        let battery = PowerState {
+            on_battery: true,
+            battery_percent: Some(80),
+            thermal_throttled: false,
+        };
+        let limits = battery.encode_limits().unwrap();
+        assert_eq!((limits.max_height, limits.max_frame_rate), (720, 15.0));
+        assert_eq!(
+            limits.to_string(),
+            "On battery (80%): sending video at up to 720p and 15 fps to save power"
+        );
+
+        let hot = PowerState {
+            battery_percent: Some(10),
+            thermal_throttled: true,
+            ..battery
+        };
+        let limits = hot.encode_limits().unwrap();
+        assert_eq!((limits.max_height, limits.max_frame_rate), (360, 10.0));
+        assert!(limits
+            .to_string()
+            .starts_with("On battery (10%) and running hot"));
+    }
+
+    #[test]
+    fn throttle_drops_frames_over_the_frame_rate() {
+        let limits = Snapshot::new(
+            PowerState {
+                thermal_throttled: true,
+                ..Default::default()
+            }
+            .encode_limits(),
+        );
+        let mut throttle = EncodeThrottle::new(limits.clone());
+        let start = Instant::now();
+        // A 30 fps camera against the 10 fps limit.
+        let sent = (0..30)
+            .filter(|frame| throttle.admit(start + Duration::from_millis(frame * 33)))
+            .count();
+        assert_eq!(sent, 10);
+
+        let frame = VideoFrame {
+            width: 1280,
+            height: 720,
+            rgba: vec![0; 1280 * 720 * 4],
+        };
+        let scaled = throttle.scale(&frame);
+        assert_eq!((scaled.width, scaled.height), (640, 360));
+        limits.set(None);
+        assert!(matches!(throttle.scale(&frame), Cow::Borrowed(_)));
+    }
+}
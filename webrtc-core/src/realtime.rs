@@ -0,0 +1,254 @@
+//! Channels for input that is stale once late, as when driving a robot or
+//! a game: unordered, hardly ever resent, and kept to small messages. Each
+//! message is stamped with when it was sent, so the receiver can see how
+//! late messages arrive, and which never do.
+//!
+//! The two clocks are not in step, so delays are measured from the
+//! quickest message seen lately rather than from zero.
+
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use thiserror::Error;
+use webrtc::data_channel::data_channel_init::RTCDataChannelInit;
+
+use crate::data_channels::{ChannelOptions, Reliability};
+
+/// Sub-protocol a realtime channel is opened with, so the other side knows
+/// to read the stamps.
+pub const REALTIME_PROTOCOL: &str = "x-realtime";
+
+/// Unordered, and resent once at most: webrtc-rs opens a channel that is
+/// never resent as a reliable one.
+pub const REALTIME_OPTIONS: ChannelOptions = ChannelOptions {
+    ordered: false,
+    reliability: Reliability::MaxRetransmits(1),
+};
+
+/// Most bytes a realtime message carries, so that with its stamp and the
+/// SCTP and DTLS headers it still goes in one packet.
+pub const MAX_REALTIME_BYTES: usize = 1024;
+
+/// Starts every message, telling it from other binary data and this
+/// layout from any later one.
+const MAGIC: u8 = 0xa1;
+
+/// The magic byte, sequence number and send time in front of each message.
+const HEADER_BYTES: usize = 13;
+
+/// Furthest a stamp may be from the arrival time, either way, in
+/// microseconds. Past it the clocks are hopelessly apart or the stamp is
+/// garbage, and either would swamp the histogram.
+const MAX_SKEW_US: u64 = 10 * 60 * 1_000_000;
+
+/// Delays kept for the histogram.
+const MAX_DELAYS: usize = 1000;
+
+/// Upper ends of the histogram's buckets, in milliseconds; the last one
+/// takes everything slower.
+pub const DELAY_BUCKETS_MS: [f64; 9] = [1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0];
+
+/// To open a realtime channel with.
+pub fn realtime_init() -> RTCDataChannelInit {
+    RTCDataChannelInit {
+        protocol: Some(REALTIME_PROTOCOL.to_owned()),
+        ..REALTIME_OPTIONS.init()
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RealtimeError {
+    #[error("{0} bytes is more than a realtime message carries ({MAX_REALTIME_BYTES})")]
+    TooLarge(usize),
+    #[error("too short for a realtime message's stamp")]
+    Truncated,
+    #[error("not a stamped realtime message")]
+    NotStamped,
+}
+
+/// One message as it goes over.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RealtimeFrame {
+    pub seq: u32,
+    /// Microseconds since the Unix epoch, by the sender's clock.
+    pub sent_us: u64,
+    pub payload: Vec<u8>,
+}
+
+impl RealtimeFrame {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(HEADER_BYTES + self.payload.len());
+        data.push(MAGIC);
+        data.extend_from_slice(&self.seq.to_be_bytes());
+        data.extend_from_slice(&self.sent_us.to_be_bytes());
+        data.extend_from_slice(&self.payload);
+        data
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, RealtimeError> {
+        if data.len() < HEADER_BYTES {
+            return Err(RealtimeError::Truncated);
+        }
+        if data[0] != MAGIC {
+            return Err(RealtimeError::NotStamped);
+        }
+        let (seq, rest) = data[1..].split_at(4);
+        let (sent_us, payload) = rest.split_at(8);
+        Ok(Self {
+            seq: u32::from_be_bytes(seq.try_into().expect("split at 4")),
+            sent_us: u64::from_be_bytes(sent_us.try_into().expect("split at 8")),
+            payload: payload.to_vec(),
+        })
+    }
+}
+
+/// The time now, as frames carry it.
+pub fn now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_micros() as u64)
+}
+
+/// Stamps what is sent on one channel.
+#[derive(Debug, Default)]
+pub struct RealtimeSender {
+    next_seq: u32,
+}
+
+impl RealtimeSender {
+    /// `payload`, stamped with the next sequence number and `sent_us`.
+    pub fn frame(
+        &mut self,
+        payload: Vec<u8>,
+        sent_us: u64,
+    ) -> Result<RealtimeFrame, RealtimeError> {
+        if payload.len() > MAX_REALTIME_BYTES {
+            return Err(RealtimeError::TooLarge(payload.len()));
+        }
+        let frame = RealtimeFrame {
+            seq: self.next_seq,
+            sent_us,
+            payload,
+        };
+        self.next_seq = self.next_seq.wrapping_add(1);
+        Ok(frame)
+    }
+}
+
+/// How late messages on one channel arrive, and how many go missing.
+#[derive(Debug, Default)]
+pub struct RealtimeReceiver {
+    /// Arrival minus send time, in microseconds; the clocks' offset is in
+    /// there too.
+    delays: VecDeque<i64>,
+    /// The next sequence number expected.
+    next_seq: Option<u32>,
+    pub received: u64,
+    /// Skipped over by a later message, and not arrived since.
+    pub missing: u64,
+    /// Arrived after a later one.
+    pub late: u64,
+    /// Dropped for a stamp too far from when they arrived.
+    pub implausible: u64,
+}
+
+impl RealtimeReceiver {
+    /// `frame` arrived at `arrived_us`.
+    pub fn received(&mut self, frame: &RealtimeFrame, arrived_us: u64) {
+        let delay = i64::try_from(arrived_us)
+            .ok()
+            .zip(i64::try_from(frame.sent_us).ok())
+            .and_then(|(arrived, sent)| arrived.checked_sub(sent))
+            .filter(|delay| delay.unsigned_abs() <= MAX_SKEW_US);
+        let Some(delay) = delay else {
+            self.implausible += 1;
+            return;
+        };
+        self.received += 1;
+        if self.delays.len() == MAX_DELAYS {
+            self.delays.pop_front();
+        }
+        self.delays.push_back(delay);
+        match self.next_seq {
+            Some(next) if frame.seq.wrapping_sub(next) > u32::MAX / 2 => {
+                // Behind the newest: one counted missing after all.
+                self.late += 1;
+                self.missing = self.missing.saturating_sub(1);
+            }
+            Some(next) => {
+                self.missing += frame.seq.wrapping_sub(next) as u64;
+                self.next_seq = Some(frame.seq.wrapping_add(1));
+            }
+            None => self.next_seq = Some(frame.seq.wrapping_add(1)),
+        }
+    }
+
+    /// Each kept delay in milliseconds, past the quickest of them.
+    pub fn delays_ms(&self) -> impl Iterator<Item = f64> + '_ {
+        let quickest = self.delays.iter().copied().min().unwrap_or_default();
+        self.delays
+            .iter()
+            .map(move |delay| delay.saturating_sub(quickest) as f64 / 1000.0)
+    }
+
+    /// How many kept delays fall in each of [`DELAY_BUCKETS_MS`], and
+    /// past the last.
+    pub fn histogram(&self) -> [u64; DELAY_BUCKETS_MS.len() + 1] {
+        let mut counts = [0; DELAY_BUCKETS_MS.len() + 1];
+        for delay in self.delays_ms() {
+            let bucket = DELAY_BUCKETS_MS
+                .iter()
+                .position(|&upper| delay < upper)
+                .unwrap_or(DELAY_BUCKETS_MS.len());
+            counts[bucket] += 1;
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamps_frames_and_bins_how_late_they_are() {
+        let mut sender = RealtimeSender::default();
+        let frames: Vec<_> = (0..5)
+            .map(|n| sender.frame(vec![n as u8], 1_000_000 + n * 10_000).unwrap())
+            .collect();
+        assert_eq!(
+            sender.frame(vec![0; MAX_REALTIME_BYTES + 1], 0),
+            Err(RealtimeError::TooLarge(MAX_REALTIME_BYTES + 1))
+        );
+        let decoded = RealtimeFrame::decode(&frames[3].encode()).unwrap();
+        assert_eq!(decoded, frames[3]);
+        assert_eq!(
+            RealtimeFrame::decode(&[MAGIC; 12]),
+            Err(RealtimeError::Truncated)
+        );
+        assert_eq!(
+            RealtimeFrame::decode(&[0; 13]),
+            Err(RealtimeError::NotStamped)
+        );
+
+        // The far clock is 50 s ahead; frame 2 is lost and 3 overtaken.
+        let mut receiver = RealtimeReceiver::default();
+        let offset = 50_000_000;
+        for (index, extra_us) in [(0, 0), (1, 1_500), (4, 30_000), (3, 250_000)] {
+            let frame = &frames[index];
+            receiver.received(frame, frame.sent_us + offset + extra_us);
+        }
+        // Stamps a day out, or past what an i64 holds, are left out.
+        let mut stray = frames[4].clone();
+        stray.sent_us += 24 * 3600 * 1_000_000;
+        receiver.received(&stray, frames[4].sent_us);
+        stray.sent_us = u64::MAX;
+        receiver.received(&stray, 0);
+        assert_eq!(receiver.received, 4);
+        assert_eq!(receiver.implausible, 2);
+        assert_eq!((receiver.missing, receiver.late), (1, 1));
+        let delays: Vec<_> = receiver.delays_ms().collect();
+        assert_eq!(delays, [0.0, 1.5, 30.0, 250.0]);
+        assert_eq!(receiver.histogram(), [1, 1, 0, 0, 0, 1, 0, 0, 1, 0]);
+    }
+}
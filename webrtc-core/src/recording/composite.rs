@@ -0,0 +1,437 @@
+//! Recording the whole call into one file: the local and remote video
+//! side by side, encoded again at a steady rate, and the microphone mixed
+//! with the remote audio. The mix follows what the peer heard of us, so a
+//! muted microphone records as silence.
+
+#[cfg(feature = "audio")]
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+#[cfg(feature = "audio")]
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use log::info;
+#[cfg(feature = "audio")]
+use webrtc::api::media_engine::MIME_TYPE_OPUS;
+
+use super::{Recorded, RecordedTracks, RecordingError, RecordingTap, VIDEO_CLOCK_RATE};
+use crate::codec::{self, EncoderConfig};
+use crate::snapshot::Snapshot;
+use crate::video::{VideoEncoder, VideoFrame};
+
+/// Each side of the picture, which every frame is scaled to fit.
+pub const TILE_WIDTH: usize = 640;
+pub const TILE_HEIGHT: usize = 360;
+const FRAME_RATE: f32 = 15.0;
+
+/// 20 ms at 48 kHz, the Opus frame the mix is encoded in.
+#[cfg(feature = "audio")]
+const MIX_FRAME: usize = 960;
+#[cfg(feature = "audio")]
+const MIX_FRAME_DURATION: Duration = Duration::from_millis(20);
+/// What each side keeps before it starts into the mix, so audio arriving
+/// in bursts plays through them rather than in pieces.
+#[cfg(feature = "audio")]
+const MIX_PRIMING: usize = 3 * MIX_FRAME;
+/// The most each side holds; past it, the oldest goes.
+#[cfg(feature = "audio")]
+const MIX_QUEUE: usize = 25 * MIX_FRAME;
+
+/// The tracks a recording of the whole call has in this build: video in
+/// the first codec it can encode, and Opus where it has audio.
+pub fn call_tracks() -> RecordedTracks {
+    RecordedTracks {
+        video: codec::encoder_mime_type().map(str::to_owned),
+        #[cfg(feature = "audio")]
+        audio: Some(MIME_TYPE_OPUS.to_owned()),
+        #[cfg(not(feature = "audio"))]
+        audio: None,
+    }
+    .recordable()
+}
+
+/// Draws `frame` into the tile at `left` of `canvas`, as large as it fits
+/// without changing its shape.
+fn fit(frame: &VideoFrame, canvas: &mut VideoFrame, left: usize) {
+    if frame.width == 0 || frame.height == 0 {
+        return;
+    }
+    let scale =
+        (TILE_WIDTH as f32 / frame.width as f32).min(TILE_HEIGHT as f32 / frame.height as f32);
+    let width = ((frame.width as f32 * scale).round() as usize).clamp(1, TILE_WIDTH);
+    let height = ((frame.height as f32 * scale).round() as usize).clamp(1, TILE_HEIGHT);
+    let (x0, y0) = (left + (TILE_WIDTH - width) / 2, (TILE_HEIGHT - height) / 2);
+    for y in 0..height {
+        let source = y * frame.height / height * frame.width;
+        let row = ((y0 + y) * canvas.width + x0) * 4;
+        for x in 0..width {
+            let from = (source + x * frame.width / width) * 4;
+            let to = row + x * 4;
+            canvas.rgba[to..to + 4].copy_from_slice(&frame.rgba[from..from + 4]);
+        }
+    }
+}
+
+/// The local video on the left and the remote on the right, on black
+/// where either is missing or does not fill its side.
+pub fn side_by_side(local: Option<&VideoFrame>, remote: Option<&VideoFrame>) -> VideoFrame {
+    let mut canvas = VideoFrame {
+        width: 2 * TILE_WIDTH,
+        height: TILE_HEIGHT,
+        rgba: [0, 0, 0, 255].repeat(2 * TILE_WIDTH * TILE_HEIGHT),
+    };
+    for (frame, left) in [(local, 0), (remote, TILE_WIDTH)] {
+        if let Some(frame) = frame {
+            fit(frame, &mut canvas, left);
+        }
+    }
+    canvas
+}
+
+/// One side of the mix.
+#[cfg(feature = "audio")]
+#[derive(Default)]
+struct MixInput {
+    queue: VecDeque<f32>,
+    primed: bool,
+}
+
+#[cfg(feature = "audio")]
+impl MixInput {
+    fn push(&mut self, samples: &[f32]) {
+        self.queue.extend(samples);
+        let excess = self.queue.len().saturating_sub(MIX_QUEUE);
+        self.queue.drain(..excess);
+    }
+
+    /// Adds the next `out.len()` samples into `out`, once primed; running
+    /// dry, it primes again.
+    fn add_to(&mut self, out: &mut [f32]) {
+        if !self.primed && self.queue.len() < MIX_PRIMING {
+            return;
+        }
+        let len = out.len().min(self.queue.len());
+        self.primed = len == out.len();
+        for (out, sample) in out.iter_mut().zip(self.queue.drain(..len)) {
+            *out += sample;
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+#[derive(Default)]
+struct Mix {
+    local: MixInput,
+    remote: MixInput,
+}
+
+/// Where the microphone and the remote audio meet a recording of the
+/// whole call, as 48 kHz mono. One is shared by the capture and the
+/// remote playback; while nothing records, what they hand it is dropped.
+#[derive(Clone, Default)]
+pub struct CallMix {
+    #[cfg(feature = "audio")]
+    mix: Arc<Mutex<Option<Mix>>>,
+}
+
+impl CallMix {
+    /// Feeds what the microphone sends, in any length.
+    #[cfg(feature = "audio")]
+    pub(crate) fn local(&self, samples: &[f32]) {
+        if let Some(mix) = &mut *self.mix.lock().unwrap() {
+            mix.local.push(samples);
+        }
+    }
+
+    /// Feeds the remote audio as it is played, in any length.
+    #[cfg(feature = "audio")]
+    pub(crate) fn remote(&self, samples: &[f32]) {
+        if let Some(mix) = &mut *self.mix.lock().unwrap() {
+            mix.remote.push(samples);
+        }
+    }
+
+    #[cfg(feature = "audio")]
+    fn open(&self) {
+        *self.mix.lock().unwrap() = Some(Mix::default());
+    }
+
+    fn close(&self) {
+        #[cfg(feature = "audio")]
+        {
+            *self.mix.lock().unwrap() = None;
+        }
+    }
+
+    /// The next `out.len()` samples of both sides together.
+    #[cfg(feature = "audio")]
+    fn take(&self, out: &mut [f32]) {
+        out.fill(0.0);
+        if let Some(mix) = &mut *self.mix.lock().unwrap() {
+            mix.local.add_to(out);
+            mix.remote.add_to(out);
+        }
+        for sample in out {
+            *sample = sample.clamp(-1.0, 1.0);
+        }
+    }
+}
+
+/// What the recording thread encodes with.
+struct Encoders {
+    video: Option<Box<dyn VideoEncoder>>,
+    #[cfg(feature = "audio")]
+    audio: Option<opus::Encoder>,
+}
+
+impl Encoders {
+    fn open(tracks: &RecordedTracks) -> Result<Self, RecordingError> {
+        let config = EncoderConfig::new(2 * TILE_WIDTH, TILE_HEIGHT, FRAME_RATE);
+        let video = match &tracks.video {
+            Some(codec) => Some(
+                codec::encoder_for(codec, &config)
+                    .ok_or_else(|| RecordingError::Encoder(codec.clone()))?,
+            ),
+            None => None,
+        };
+        #[cfg(feature = "audio")]
+        let audio = match tracks.audio {
+            Some(_) => Some(
+                opus::Encoder::new(
+                    super::OPUS_CLOCK_RATE,
+                    opus::Channels::Mono,
+                    opus::Application::Audio,
+                )
+                .map_err(|err| RecordingError::Encoder(err.to_string()))?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            video,
+            #[cfg(feature = "audio")]
+            audio,
+        })
+    }
+}
+
+/// A recording of the whole call, made on a thread of its own until it is
+/// stopped or dropped.
+pub struct CallRecording {
+    tap: RecordingTap,
+    mix: CallMix,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CallRecording {
+    /// Starts recording `local` and `remote`, the latest frames of each,
+    /// and what comes through `mix` into a new file at `path`.
+    pub fn start(
+        path: &Path,
+        local: Snapshot<Option<VideoFrame>>,
+        remote: Snapshot<Option<VideoFrame>>,
+        mix: CallMix,
+    ) -> Result<Self, RecordingError> {
+        let tracks = call_tracks();
+        if tracks.is_empty() {
+            return Err(RecordingError::NothingToRecord);
+        }
+        let encoders = Encoders::open(&tracks)?;
+        let tap = RecordingTap::default();
+        tap.start(path, tracks)?;
+        #[cfg(feature = "audio")]
+        mix.open();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let (tap, mix, stop) = (tap.clone(), mix.clone(), Arc::clone(&stop));
+            std::thread::Builder::new()
+                .name("call recording".to_owned())
+                .spawn(move || record(encoders, local, remote, mix, tap, &stop))
+                .map_err(|err| RecordingError::Io("start recording", err))?
+        };
+        info!("Recording the whole call into {}", path.display());
+        Ok(Self {
+            tap,
+            mix,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// How much has been written so far, `None` until the file starts.
+    pub fn recorded(&self) -> Option<Duration> {
+        self.tap.recorded()
+    }
+
+    /// Ends the recording and finishes its file.
+    pub fn stop(mut self) -> Result<Recorded, RecordingError> {
+        self.end()
+    }
+
+    fn end(&mut self) -> Result<Recorded, RecordingError> {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        self.mix.close();
+        self.tap
+            .stop()
+            .unwrap_or(Err(RecordingError::NothingToRecord))
+    }
+}
+
+impl Drop for CallRecording {
+    /// Finishes the file of a recording nobody stopped.
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            let _ = self.end();
+        }
+    }
+}
+
+/// Composites and mixes at a steady rate, each frame at its own time,
+/// until `stop` is set.
+fn record(
+    mut encoders: Encoders,
+    local: Snapshot<Option<VideoFrame>>,
+    remote: Snapshot<Option<VideoFrame>>,
+    mix: CallMix,
+    tap: RecordingTap,
+    stop: &AtomicBool,
+) {
+    let started = Instant::now();
+    let video_ticks = (VIDEO_CLOCK_RATE as f32 / FRAME_RATE) as u32;
+    let mut video_frames = 0u32;
+    #[cfg(feature = "audio")]
+    let (mut audio_frames, mut pcm, mut packet) = (0u32, vec![0.0; MIX_FRAME], vec![0; 4000]);
+    #[cfg(not(feature = "audio"))]
+    let _ = &mix;
+    while !stop.load(Ordering::Relaxed) {
+        let video_at = encoders
+            .video
+            .as_ref()
+            .map(|_| Duration::from_secs_f32(video_frames as f32 / FRAME_RATE));
+        #[cfg(feature = "audio")]
+        let audio_at = encoders
+            .audio
+            .as_ref()
+            .map(|_| MIX_FRAME_DURATION * audio_frames);
+        #[cfg(not(feature = "audio"))]
+        let audio_at: Option<Duration> = None;
+        let video_next = match (video_at, audio_at) {
+            (Some(video), Some(audio)) => video <= audio,
+            (video, _) => video.is_some(),
+        };
+        let Some(at) = (if video_next { video_at } else { audio_at }) else {
+            return;
+        };
+        std::thread::sleep((started + at).saturating_duration_since(Instant::now()));
+        if video_next {
+            let frame = side_by_side(
+                local.get().as_ref().as_ref(),
+                remote.get().as_ref().as_ref(),
+            );
+            if let Some(data) = encoders.video.as_mut().and_then(|e| e.encode(&frame)) {
+                let size = Some((frame.width, frame.height));
+                tap.video(&data, video_frames.wrapping_mul(video_ticks), size);
+            }
+            video_frames += 1;
+            continue;
+        }
+        #[cfg(feature = "audio")]
+        if let Some(encoder) = &mut encoders.audio {
+            mix.take(&mut pcm);
+            match encoder.encode_float(&pcm, &mut packet) {
+                Ok(len) => tap.audio(&packet[..len], audio_frames.wrapping_mul(MIX_FRAME as u32)),
+                Err(err) => info!("Opus encode error: {}", err),
+            }
+            audio_frames += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: usize, height: usize, rgba: [u8; 4]) -> VideoFrame {
+        VideoFrame {
+            width,
+            height,
+            rgba: rgba.repeat(width * height),
+        }
+    }
+
+    #[test]
+    fn puts_both_sides_in_their_tiles() {
+        let local = solid(320, 240, [255, 0, 0, 255]);
+        let remote = solid(1280, 720, [0, 0, 255, 255]);
+        let frame = side_by_side(Some(&local), Some(&remote));
+        let at = |x: usize, y: usize| &frame.rgba[(y * frame.width + x) * 4..][..4];
+        assert_eq!((frame.width, frame.height), (2 * TILE_WIDTH, TILE_HEIGHT));
+        // 4:3 in a 16:9 tile leaves bars either side.
+        assert_eq!(at(20, 180), [0, 0, 0, 255]);
+        assert_eq!(at(320, 180), [255, 0, 0, 255]);
+        assert_eq!(at(640, 0), [0, 0, 255, 255]);
+        assert_eq!(at(1279, 359), [0, 0, 255, 255]);
+
+        let empty = side_by_side(None, Some(&remote));
+        assert_eq!(&empty.rgba[..4], [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn records_a_call_that_reads_back() {
+        let tracks = call_tracks();
+        if tracks.video.is_none() {
+            return;
+        }
+        let name = format!("call-{}.{}", std::process::id(), tracks.extension());
+        let path = std::env::temp_dir().join(name);
+        let remote = Snapshot::default();
+        remote.set(Some(solid(320, 180, [0, 128, 255, 255])));
+        let call =
+            CallRecording::start(&path, Snapshot::default(), remote, CallMix::default()).unwrap();
+        std::thread::sleep(Duration::from_millis(400));
+        let recorded = call.stop().unwrap();
+        assert!(recorded.duration >= Duration::from_millis(200));
+
+        let file = std::fs::File::open(&path).unwrap();
+        let mut file = matroska_demuxer::MatroskaFile::open(file).unwrap();
+        let video = file.tracks()[0].video().unwrap();
+        assert_eq!(video.pixel_width().get(), 2 * TILE_WIDTH as u64);
+        let mut frame = matroska_demuxer::Frame::default();
+        assert!(file.next_frame(&mut frame).unwrap());
+        assert_eq!(frame.is_keyframe, Some(true));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "audio")]
+    #[test]
+    fn mixes_both_sides_once_primed() {
+        let mix = CallMix::default();
+        let mut out = vec![0.0; MIX_FRAME];
+        mix.local(&[0.5; MIX_FRAME]);
+        mix.open();
+        mix.local(&[0.25; 4 * MIX_FRAME]);
+        mix.remote(&[0.5; MIX_FRAME]);
+        mix.take(&mut out);
+        // The remote side is still filling up.
+        assert!(out.iter().all(|s| *s == 0.25));
+        mix.remote(&[0.5; 2 * MIX_FRAME]);
+        mix.take(&mut out);
+        assert!(out.iter().all(|s| *s == 0.75));
+        mix.local(&[0.9; MIX_FRAME]);
+        mix.remote(&[0.5; MIX_FRAME]);
+        mix.take(&mut out);
+        mix.take(&mut out);
+        mix.take(&mut out);
+        assert!(out.iter().all(|s| *s == 1.0));
+        mix.close();
+        mix.local(&[0.5; MIX_FRAME]);
+        mix.take(&mut out);
+        assert!(out.iter().all(|s| *s == 0.0));
+    }
+}
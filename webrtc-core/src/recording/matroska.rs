@@ -0,0 +1,447 @@
+//! Writing Matroska (and its WebM subset) as media arrives: the header up
+//! front, clusters as they fill, and the cues, seek head and duration once
+//! the recording ends. Until then the segment's size is left unknown, so a
+//! recording cut short still plays up to its last whole cluster.
+
+use std::io::{self, Seek, SeekFrom, Write};
+use std::time::Duration;
+
+const EBML: u32 = 0x1A45_DFA3;
+const EBML_VERSION: u32 = 0x4286;
+const EBML_READ_VERSION: u32 = 0x42F7;
+const EBML_MAX_ID_LENGTH: u32 = 0x42F2;
+const EBML_MAX_SIZE_LENGTH: u32 = 0x42F3;
+const DOC_TYPE: u32 = 0x4282;
+const DOC_TYPE_VERSION: u32 = 0x4287;
+const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+const SEGMENT: u32 = 0x1853_8067;
+const SEEK_HEAD: u32 = 0x114D_9B74;
+const SEEK: u32 = 0x4DBB;
+const SEEK_ID: u32 = 0x53AB;
+const SEEK_POSITION: u32 = 0x53AC;
+const VOID: u32 = 0xEC;
+const INFO: u32 = 0x1549_A966;
+const TIMESTAMP_SCALE: u32 = 0x2A_D7B1;
+const DURATION: u32 = 0x4489;
+const MUXING_APP: u32 = 0x4D80;
+const WRITING_APP: u32 = 0x5741;
+const TRACKS: u32 = 0x1654_AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const TRACK_UID: u32 = 0x73C5;
+const TRACK_TYPE: u32 = 0x83;
+const FLAG_LACING: u32 = 0x9C;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const CODEC_DELAY: u32 = 0x56AA;
+const SEEK_PRE_ROLL: u32 = 0x56BB;
+const VIDEO: u32 = 0xE0;
+const PIXEL_WIDTH: u32 = 0xB0;
+const PIXEL_HEIGHT: u32 = 0xBA;
+const AUDIO: u32 = 0xE1;
+const SAMPLING_FREQUENCY: u32 = 0xB5;
+const CHANNELS: u32 = 0x9F;
+const CLUSTER: u32 = 0x1F43_B675;
+const TIMESTAMP: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+const CUES: u32 = 0x1C53_BB6B;
+const CUE_POINT: u32 = 0xBB;
+const CUE_TIME: u32 = 0xB3;
+const CUE_TRACK_POSITIONS: u32 = 0xB7;
+const CUE_TRACK: u32 = 0xF7;
+const CUE_CLUSTER_POSITION: u32 = 0xF1;
+
+/// Block times are in milliseconds.
+const TIMESTAMP_SCALE_NS: u64 = 1_000_000;
+/// The longest a cluster runs without a keyframe to start the next one;
+/// well within the ±32 s a block's time can be from its cluster's.
+const MAX_CLUSTER_LENGTH: Duration = Duration::from_secs(5);
+/// Room kept for the seek head, which is only written at the end.
+const SEEK_HEAD_SPACE: usize = 96;
+/// A segment size of all ones is "unknown".
+const UNKNOWN_SIZE: [u8; 8] = [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+
+const APP: &str = concat!("webrtc-rust-native-gui ", env!("CARGO_PKG_VERSION"));
+
+fn write_id(out: &mut Vec<u8>, id: u32) {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|byte| **byte == 0).count();
+    out.extend_from_slice(&bytes[skip..]);
+}
+
+/// An EBML size in as few bytes as it fits.
+fn write_size(out: &mut Vec<u8>, size: u64) {
+    let len = (1..=8)
+        .find(|len| size < (1 << (7 * len)) - 1)
+        .expect("no element is that large");
+    write_size_in(out, size, len);
+}
+
+fn write_size_in(out: &mut Vec<u8>, size: u64, len: usize) {
+    let marked = size | 1 << (7 * len);
+    out.extend_from_slice(&marked.to_be_bytes()[8 - len..]);
+}
+
+fn element(out: &mut Vec<u8>, id: u32, body: &[u8]) {
+    write_id(out, id);
+    write_size(out, body.len() as u64);
+    out.extend_from_slice(body);
+}
+
+fn uint(out: &mut Vec<u8>, id: u32, value: u64) {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|byte| **byte == 0).count().min(7);
+    element(out, id, &bytes[skip..]);
+}
+
+fn float(out: &mut Vec<u8>, id: u32, value: f64) {
+    element(out, id, &value.to_be_bytes());
+}
+
+fn master(out: &mut Vec<u8>, id: u32, fill: impl FnOnce(&mut Vec<u8>)) {
+    let mut body = Vec::new();
+    fill(&mut body);
+    element(out, id, &body);
+}
+
+/// A void element taking exactly `len` bytes, two at the least.
+fn void(out: &mut Vec<u8>, len: usize) {
+    write_id(out, VOID);
+    let size_len = if len - 1 > 127 { 8 } else { 1 };
+    let body = len - 1 - size_len;
+    write_size_in(out, body as u64, size_len);
+    out.resize(out.len() + body, 0);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum TrackKind {
+    Video { width: usize, height: usize },
+    Audio { sample_rate: f64, channels: u64 },
+}
+
+/// A track as the header describes it.
+#[derive(Debug, Clone)]
+pub(super) struct TrackHeader {
+    pub number: u64,
+    pub codec_id: &'static str,
+    pub codec_private: Option<Vec<u8>>,
+    pub kind: TrackKind,
+    /// What the decoder has to run through before the audio is right.
+    pub seek_pre_roll: Option<Duration>,
+}
+
+struct Cluster {
+    timestamp_ms: u64,
+    blocks: Vec<u8>,
+}
+
+struct CuePoint {
+    time_ms: u64,
+    track: u64,
+    /// Of the cluster, from the start of the segment's body.
+    position: u64,
+}
+
+pub(super) struct MatroskaWriter<W: Write + Seek> {
+    out: W,
+    /// Where the segment's size and its body start.
+    segment_size_at: u64,
+    segment_start: u64,
+    seek_head_at: u64,
+    info_at: u64,
+    tracks_at: u64,
+    duration_at: u64,
+    /// Whether any track is video, so clusters start on its keyframes.
+    video: bool,
+    cluster: Option<Cluster>,
+    cues: Vec<CuePoint>,
+    end_ms: u64,
+}
+
+impl<W: Write + Seek> MatroskaWriter<W> {
+    /// Writes the header for `tracks`, as WebM or else as Matroska.
+    pub fn new(mut out: W, webm: bool, tracks: &[TrackHeader]) -> io::Result<Self> {
+        let mut header = Vec::new();
+        master(&mut header, EBML, |ebml| {
+            uint(ebml, EBML_VERSION, 1);
+            uint(ebml, EBML_READ_VERSION, 1);
+            uint(ebml, EBML_MAX_ID_LENGTH, 4);
+            uint(ebml, EBML_MAX_SIZE_LENGTH, 8);
+            element(ebml, DOC_TYPE, if webm { b"webm" } else { b"matroska" });
+            uint(ebml, DOC_TYPE_VERSION, 4);
+            uint(ebml, DOC_TYPE_READ_VERSION, 2);
+        });
+        write_id(&mut header, SEGMENT);
+        let start = out.stream_position()?;
+        let segment_size_at = start + header.len() as u64;
+        header.extend_from_slice(&UNKNOWN_SIZE);
+        let segment_start = start + header.len() as u64;
+
+        let seek_head_at = header.len();
+        void(&mut header, SEEK_HEAD_SPACE);
+        let info_at = header.len();
+        let mut info = Vec::new();
+        uint(&mut info, TIMESTAMP_SCALE, TIMESTAMP_SCALE_NS);
+        element(&mut info, MUXING_APP, APP.as_bytes());
+        element(&mut info, WRITING_APP, APP.as_bytes());
+        // The float comes after a 2-byte ID and a 1-byte size.
+        let duration_in_info = info.len() + 3;
+        float(&mut info, DURATION, 0.0);
+        element(&mut header, INFO, &info);
+        let duration_at = header.len() - info.len() + duration_in_info;
+        let tracks_at = header.len();
+        master(&mut header, TRACKS, |list| {
+            for track in tracks {
+                master(list, TRACK_ENTRY, |entry| {
+                    uint(entry, TRACK_NUMBER, track.number);
+                    uint(entry, TRACK_UID, track.number);
+                    uint(entry, FLAG_LACING, 0);
+                    element(entry, CODEC_ID, track.codec_id.as_bytes());
+                    if let Some(private) = &track.codec_private {
+                        element(entry, CODEC_PRIVATE, private);
+                    }
+                    if let Some(pre_roll) = track.seek_pre_roll {
+                        uint(entry, CODEC_DELAY, 0);
+                        uint(entry, SEEK_PRE_ROLL, pre_roll.as_nanos() as u64);
+                    }
+                    match track.kind {
+                        TrackKind::Video { width, height } => {
+                            uint(entry, TRACK_TYPE, 1);
+                            master(entry, VIDEO, |video| {
+                                uint(video, PIXEL_WIDTH, width as u64);
+                                uint(video, PIXEL_HEIGHT, height as u64);
+                            });
+                        }
+                        TrackKind::Audio {
+                            sample_rate,
+                            channels,
+                        } => {
+                            uint(entry, TRACK_TYPE, 2);
+                            master(entry, AUDIO, |audio| {
+                                float(audio, SAMPLING_FREQUENCY, sample_rate);
+                                uint(audio, CHANNELS, channels);
+                            });
+                        }
+                    }
+                });
+            }
+        });
+        out.write_all(&header)?;
+        let base = start;
+        Ok(Self {
+            out,
+            segment_size_at,
+            segment_start,
+            seek_head_at: base + seek_head_at as u64,
+            info_at: base + info_at as u64,
+            tracks_at: base + tracks_at as u64,
+            duration_at: base + duration_at as u64,
+            video: tracks
+                .iter()
+                .any(|track| matches!(track.kind, TrackKind::Video { .. })),
+            cluster: None,
+            cues: Vec::new(),
+            end_ms: 0,
+        })
+    }
+
+    /// Adds a frame of `track` at `time` from the start. A keyframe of a
+    /// video track starts a new cluster, and a cue point to seek to.
+    pub fn write_block(
+        &mut self,
+        track: u64,
+        time: Duration,
+        keyframe: bool,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let time_ms = time.as_millis() as u64;
+        let starts_cluster = match &self.cluster {
+            None => true,
+            Some(cluster) => {
+                (self.video && keyframe)
+                    || time_ms.saturating_sub(cluster.timestamp_ms)
+                        >= MAX_CLUSTER_LENGTH.as_millis() as u64
+                    || time_ms + i16::MAX as u64 / 2 < cluster.timestamp_ms
+            }
+        };
+        if starts_cluster {
+            self.flush_cluster()?;
+            if keyframe || !self.video {
+                self.cues.push(CuePoint {
+                    time_ms,
+                    track,
+                    position: self.out.stream_position()? - self.segment_start,
+                });
+            }
+            self.cluster = Some(Cluster {
+                timestamp_ms: time_ms,
+                blocks: Vec::new(),
+            });
+        }
+        let cluster = self.cluster.as_mut().expect("started above");
+        let relative = (time_ms as i64 - cluster.timestamp_ms as i64)
+            .clamp(i64::from(i16::MIN), i64::from(i16::MAX)) as i16;
+        let mut block = Vec::with_capacity(data.len() + 4);
+        write_size(&mut block, track);
+        block.extend_from_slice(&relative.to_be_bytes());
+        block.push(if keyframe { 0x80 } else { 0 });
+        block.extend_from_slice(data);
+        element(&mut cluster.blocks, SIMPLE_BLOCK, &block);
+        self.end_ms = self.end_ms.max(time_ms);
+        Ok(())
+    }
+
+    /// Writes out the cluster being filled.
+    fn flush_cluster(&mut self) -> io::Result<()> {
+        let Some(cluster) = self.cluster.take() else {
+            return Ok(());
+        };
+        let mut body = Vec::with_capacity(cluster.blocks.len() + 8);
+        uint(&mut body, TIMESTAMP, cluster.timestamp_ms);
+        body.extend_from_slice(&cluster.blocks);
+        let mut out = Vec::with_capacity(body.len() + 12);
+        element(&mut out, CLUSTER, &body);
+        self.out.write_all(&out)
+    }
+
+    /// How far the recording has got.
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.end_ms)
+    }
+
+    /// Writes the last cluster and the cues, then goes back to fill in the
+    /// segment's size, the seek head and the duration.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush_cluster()?;
+        let cues_at = self.out.stream_position()?;
+        let mut cues = Vec::new();
+        master(&mut cues, CUES, |list| {
+            for cue in &self.cues {
+                master(list, CUE_POINT, |point| {
+                    uint(point, CUE_TIME, cue.time_ms);
+                    master(point, CUE_TRACK_POSITIONS, |positions| {
+                        uint(positions, CUE_TRACK, cue.track);
+                        uint(positions, CUE_CLUSTER_POSITION, cue.position);
+                    });
+                });
+            }
+        });
+        self.out.write_all(&cues)?;
+        let end = self.out.stream_position()?;
+
+        let mut size = Vec::new();
+        write_size_in(&mut size, end - self.segment_start, 8);
+        self.out.seek(SeekFrom::Start(self.segment_size_at))?;
+        self.out.write_all(&size)?;
+
+        let mut seek_head = Vec::new();
+        master(&mut seek_head, SEEK_HEAD, |head| {
+            for (id, at) in [
+                (INFO, self.info_at),
+                (TRACKS, self.tracks_at),
+                (CUES, cues_at),
+            ] {
+                master(head, SEEK, |seek| {
+                    let mut id_bytes = Vec::new();
+                    write_id(&mut id_bytes, id);
+                    element(seek, SEEK_ID, &id_bytes);
+                    uint(seek, SEEK_POSITION, at - self.segment_start);
+                });
+            }
+        });
+        let filler = SEEK_HEAD_SPACE - seek_head.len();
+        void(&mut seek_head, filler);
+        self.out.seek(SeekFrom::Start(self.seek_head_at))?;
+        self.out.write_all(&seek_head)?;
+
+        self.out.seek(SeekFrom::Start(self.duration_at))?;
+        self.out.write_all(&(self.end_ms as f64).to_be_bytes())?;
+        self.out.seek(SeekFrom::Start(end))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use matroska_demuxer::{Frame, MatroskaFile, TrackType};
+
+    use super::*;
+
+    #[test]
+    fn sizes_and_voids_take_the_room_asked_for() {
+        let mut out = Vec::new();
+        write_size(&mut out, 126);
+        write_size(&mut out, 127);
+        assert_eq!(out, [0xFE, 0x40, 0x7F]);
+        for len in [2, 100, 128, 129, 300] {
+            let mut out = Vec::new();
+            void(&mut out, len);
+            assert_eq!(out.len(), len);
+        }
+    }
+
+    #[test]
+    fn writes_a_file_that_reads_back() {
+        let tracks = [
+            TrackHeader {
+                number: 1,
+                codec_id: "V_VP8",
+                codec_private: None,
+                kind: TrackKind::Video {
+                    width: 320,
+                    height: 240,
+                },
+                seek_pre_roll: None,
+            },
+            TrackHeader {
+                number: 2,
+                codec_id: "A_OPUS",
+                codec_private: Some(b"OpusHead".to_vec()),
+                kind: TrackKind::Audio {
+                    sample_rate: 48000.0,
+                    channels: 2,
+                },
+                seek_pre_roll: Some(Duration::from_millis(80)),
+            },
+        ];
+        let mut writer = MatroskaWriter::new(Cursor::new(Vec::new()), true, &tracks).unwrap();
+        for frame in 0..300u64 {
+            let time = Duration::from_millis(frame * 40);
+            // A keyframe every 3 s, the audio alongside.
+            writer
+                .write_block(1, time, frame % 75 == 0, &[frame as u8; 10])
+                .unwrap();
+            writer.write_block(2, time, true, &[0xAA; 3]).unwrap();
+        }
+        assert_eq!(writer.duration(), Duration::from_millis(11960));
+        let file = writer.finish().unwrap().into_inner();
+
+        let mut file = MatroskaFile::open(Cursor::new(file)).unwrap();
+        assert_eq!(file.info().duration(), Some(11960.0));
+        assert_eq!(file.tracks().len(), 2);
+        assert_eq!(file.tracks()[0].track_type(), TrackType::Video);
+        assert_eq!(file.tracks()[0].codec_id(), "V_VP8");
+        assert_eq!(file.tracks()[1].codec_private(), Some(&b"OpusHead"[..]));
+        let mut frame = Frame::default();
+        let (mut video, mut keyframes) = (0, 0);
+        let mut last = 0;
+        while file.next_frame(&mut frame).unwrap() {
+            if frame.track == 1 {
+                assert_eq!(frame.data[0], video as u8);
+                assert!(frame.timestamp >= last);
+                last = frame.timestamp;
+                video += 1;
+                keyframes += usize::from(frame.is_keyframe == Some(true));
+            }
+        }
+        assert_eq!((video, keyframes), (300, 4));
+
+        // Through the cues, to the cluster of the keyframe at 6 s.
+        file.seek(6100).unwrap();
+        assert!(file.next_frame(&mut frame).unwrap());
+        assert!((6100..6200).contains(&frame.timestamp));
+    }
+}
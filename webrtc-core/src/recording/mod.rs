@@ -0,0 +1,637 @@
+//! Recording what the peer sends into a WebM or Matroska file as it
+//! arrives, without decoding it again: the remote video in the codec it
+//! came in, and the remote Opus audio, each timed by its RTP timestamps.
+//!
+//! With video the file starts at its first keyframe, which the header
+//! needs for the picture size and, in H.264 and AV1, what the stream says
+//! about itself; audio before then is left out. H.264 makes a Matroska
+//! file, everything else WebM. A recording of the whole call, both sides
+//! in one picture and one mix, is encoded again instead (see
+//! `CallRecording`).
+
+mod composite;
+mod matroska;
+
+pub use composite::{call_tracks, side_by_side, CallMix, CallRecording, TILE_HEIGHT, TILE_WIDTH};
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::info;
+use thiserror::Error;
+use webrtc::api::media_engine::{
+    MIME_TYPE_AV1, MIME_TYPE_H264, MIME_TYPE_OPUS, MIME_TYPE_VP8, MIME_TYPE_VP9,
+};
+use webrtc::track::track_remote::TrackRemote;
+
+use crate::red;
+use matroska::{MatroskaWriter, TrackHeader, TrackKind};
+
+/// Video, when there is any, is the first track.
+const VIDEO_TRACK: u64 = 1;
+const VIDEO_CLOCK_RATE: u32 = 90_000;
+const OPUS_CLOCK_RATE: u32 = 48_000;
+/// What an Opus decoder needs to settle after a seek, as WebM asks.
+const OPUS_SEEK_PRE_ROLL: Duration = Duration::from_millis(80);
+
+const H264_IDR: u8 = 5;
+const H264_SPS: u8 = 7;
+const H264_PPS: u8 = 8;
+const OBU_SEQUENCE_HEADER: u8 = 1;
+const OBU_TEMPORAL_DELIMITER: u8 = 2;
+
+#[derive(Debug, Error)]
+pub enum RecordingError {
+    #[error("nothing is being received in a codec that can be recorded")]
+    NothingToRecord,
+    #[error("already recording")]
+    AlreadyRecording,
+    #[error("no video keyframe arrived to start the file")]
+    NoKeyframe,
+    #[error("could not open an encoder: {0}")]
+    Encoder(String),
+    #[error("{0}: {1}")]
+    Io(&'static str, #[source] std::io::Error),
+}
+
+/// Whether a track in `mime_type` can go into a recording.
+pub fn can_record(mime_type: &str) -> bool {
+    [
+        MIME_TYPE_VP8,
+        MIME_TYPE_VP9,
+        MIME_TYPE_AV1,
+        MIME_TYPE_H264,
+        MIME_TYPE_OPUS,
+    ]
+    .iter()
+    .any(|codec| codec.eq_ignore_ascii_case(mime_type))
+}
+
+/// The codecs of the remote tracks a recording takes in, by MIME type.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecordedTracks {
+    pub video: Option<String>,
+    pub audio: Option<String>,
+}
+
+impl RecordedTracks {
+    /// Leaves out the tracks whose codec cannot be recorded.
+    pub fn recordable(self) -> Self {
+        let keep = |codec: Option<String>| codec.filter(|codec| can_record(codec));
+        Self {
+            video: keep(self.video),
+            audio: keep(self.audio).filter(|codec| is(codec, MIME_TYPE_OPUS)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.video.is_none() && self.audio.is_none()
+    }
+
+    /// WebM holds all of them but H.264.
+    pub fn extension(&self) -> &'static str {
+        if self.webm() {
+            "webm"
+        } else {
+            "mkv"
+        }
+    }
+
+    fn webm(&self) -> bool {
+        !self
+            .video
+            .as_deref()
+            .is_some_and(|codec| is(codec, MIME_TYPE_H264))
+    }
+}
+
+fn is(codec: &str, mime_type: &str) -> bool {
+    codec.eq_ignore_ascii_case(mime_type)
+}
+
+/// A finished recording.
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    pub path: PathBuf,
+    pub duration: Duration,
+}
+
+/// The NAL units of an H.264 access unit in Annex B.
+fn nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let mut starts = Vec::new();
+    let mut at = 0;
+    while at + 3 <= data.len() {
+        if data[at..at + 3] == [0, 0, 1] {
+            starts.push(at + 3);
+            at += 3;
+        } else {
+            at += 1;
+        }
+    }
+    let mut units = Vec::with_capacity(starts.len());
+    for (index, &start) in starts.iter().enumerate() {
+        let end = starts.get(index + 1).map_or(data.len(), |next| next - 3);
+        // The zero of a four-byte start code belongs to neither.
+        let mut unit = &data[start..end];
+        while let [rest @ .., 0] = unit {
+            unit = rest;
+        }
+        if !unit.is_empty() {
+            units.push(unit);
+        }
+    }
+    units
+}
+
+fn nal_type(unit: &[u8]) -> u8 {
+    unit[0] & 0x1f
+}
+
+/// An `avcC` record for the parameter sets in `data`, the first keyframe.
+fn avc_config(data: &[u8]) -> Option<Vec<u8>> {
+    let units = nal_units(data);
+    let sps = units.iter().find(|unit| nal_type(unit) == H264_SPS)?;
+    let pps = units.iter().find(|unit| nal_type(unit) == H264_PPS)?;
+    let profile = sps.get(1..4)?;
+    // Version 1, four-byte lengths, one SPS and one PPS.
+    let mut record = vec![1, profile[0], profile[1], profile[2], 0xff, 0xe1];
+    record.extend_from_slice(&(sps.len() as u16).to_be_bytes());
+    record.extend_from_slice(sps);
+    record.push(1);
+    record.extend_from_slice(&(pps.len() as u16).to_be_bytes());
+    record.extend_from_slice(pps);
+    Some(record)
+}
+
+/// Turns start codes into the four-byte lengths Matroska holds H.264 in.
+fn length_prefixed(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + 16);
+    for unit in nal_units(data) {
+        out.extend_from_slice(&(unit.len() as u32).to_be_bytes());
+        out.extend_from_slice(unit);
+    }
+    out
+}
+
+/// The OBUs of an AV1 temporal unit, each with its size field.
+fn obus(data: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut obus = Vec::new();
+    let mut rest = data;
+    while let Some(&header) = rest.first() {
+        let header_len = 1 + usize::from(header & 0x04 != 0);
+        let has_size = header & 0x02 != 0;
+        let (mut size, mut at) = (0usize, header_len);
+        if has_size {
+            let mut shift = 0;
+            loop {
+                let Some(&byte) = rest.get(at) else {
+                    return obus;
+                };
+                size |= usize::from(byte & 0x7f) << shift;
+                at += 1;
+                shift += 7;
+                if byte & 0x80 == 0 || shift > 56 {
+                    break;
+                }
+            }
+        } else {
+            size = rest.len() - at.min(rest.len());
+        }
+        let Some(obu) = rest.get(..at + size) else {
+            return obus;
+        };
+        obus.push(((header >> 3) & 0x0f, obu));
+        rest = &rest[at + size..];
+    }
+    obus
+}
+
+/// An `av1C` record carrying the sequence header in `data`. Its fields are
+/// those of the Main profile in 8-bit 4:2:0, as WebRTC sends; decoders
+/// read the real ones from the sequence header that follows.
+fn av1_config(data: &[u8]) -> Option<Vec<u8>> {
+    let (_, sequence_header) = obus(data)
+        .into_iter()
+        .find(|(obu_type, _)| *obu_type == OBU_SEQUENCE_HEADER)?;
+    let mut record = vec![0x81, 0x00, 0x0c, 0x00];
+    record.extend_from_slice(sequence_header);
+    Some(record)
+}
+
+/// A temporal unit without its temporal delimiter, which Matroska leaves
+/// out.
+fn without_temporal_delimiter(data: &[u8]) -> Vec<u8> {
+    obus(data)
+        .into_iter()
+        .filter(|(obu_type, _)| *obu_type != OBU_TEMPORAL_DELIMITER)
+        .flat_map(|(_, obu)| obu)
+        .copied()
+        .collect()
+}
+
+/// Whether `data`, a frame in `codec`, can be decoded on its own.
+fn is_keyframe(codec: &str, data: &[u8]) -> bool {
+    let Some(&first) = data.first() else {
+        return false;
+    };
+    if is(codec, MIME_TYPE_VP8) {
+        first & 0x01 == 0
+    } else if is(codec, MIME_TYPE_VP9) {
+        // After the frame marker, the profile's two bits and, in profile
+        // 3, a reserved one: show_existing_frame, then frame_type.
+        let profile = (first >> 5 & 1) | (first >> 3 & 2);
+        let shift = if profile == 3 { 2 } else { 3 };
+        first >> shift & 1 == 0 && first >> (shift - 1) & 1 == 0
+    } else if is(codec, MIME_TYPE_AV1) {
+        obus(data)
+            .iter()
+            .any(|(obu_type, _)| *obu_type == OBU_SEQUENCE_HEADER)
+    } else {
+        nal_units(data)
+            .iter()
+            .any(|unit| nal_type(unit) == H264_IDR)
+    }
+}
+
+/// An `OpusHead` for a stereo stream at 48 kHz, as RTP Opus always is.
+fn opus_head() -> Vec<u8> {
+    let mut head = b"OpusHead".to_vec();
+    head.extend_from_slice(&[1, 2, 0, 0]);
+    head.extend_from_slice(&OPUS_CLOCK_RATE.to_le_bytes());
+    head.extend_from_slice(&[0, 0, 0]);
+    head
+}
+
+/// Times a track's frames from its RTP timestamps, starting from when its
+/// first one arrived.
+struct TrackClock {
+    clock_rate: u32,
+    start: Option<(u32, Duration)>,
+}
+
+impl TrackClock {
+    fn new(clock_rate: u32) -> Self {
+        Self {
+            clock_rate,
+            start: None,
+        }
+    }
+
+    fn time(&mut self, rtp_timestamp: u32, arrived: Duration) -> Duration {
+        let (first, at) = *self.start.get_or_insert((rtp_timestamp, arrived));
+        let ticks = rtp_timestamp.wrapping_sub(first) as i32;
+        let offset =
+            Duration::from_secs_f64(f64::from(ticks.unsigned_abs()) / f64::from(self.clock_rate));
+        if ticks >= 0 {
+            at + offset
+        } else {
+            at.saturating_sub(offset)
+        }
+    }
+}
+
+struct Recording {
+    path: PathBuf,
+    tracks: RecordedTracks,
+    started: Instant,
+    video_clock: TrackClock,
+    audio_clock: TrackClock,
+    /// Opened at the start, and written once the header can be.
+    file: Option<File>,
+    writer: Option<MatroskaWriter<BufWriter<File>>>,
+    /// The time of the first frame written, where the file's time starts.
+    origin: Duration,
+    failed: Option<std::io::Error>,
+}
+
+impl Recording {
+    fn audio_number(&self) -> u64 {
+        if self.tracks.video.is_some() {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Writes the header, with the video track described from `keyframe`.
+    fn begin(&mut self, keyframe: Option<(&[u8], usize, usize)>, origin: Duration) {
+        let Some(file) = self.file.take() else {
+            return;
+        };
+        let mut headers = Vec::new();
+        if let (Some(codec), Some((data, width, height))) = (&self.tracks.video, keyframe) {
+            let (codec_id, codec_private) = if is(codec, MIME_TYPE_VP8) {
+                ("V_VP8", None)
+            } else if is(codec, MIME_TYPE_VP9) {
+                ("V_VP9", None)
+            } else if is(codec, MIME_TYPE_AV1) {
+                ("V_AV1", av1_config(data))
+            } else {
+                ("V_MPEG4/ISO/AVC", avc_config(data))
+            };
+            headers.push(TrackHeader {
+                number: VIDEO_TRACK,
+                codec_id,
+                codec_private,
+                kind: TrackKind::Video { width, height },
+                seek_pre_roll: None,
+            });
+        }
+        if self.tracks.audio.is_some() {
+            headers.push(TrackHeader {
+                number: self.audio_number(),
+                codec_id: "A_OPUS",
+                codec_private: Some(opus_head()),
+                kind: TrackKind::Audio {
+                    sample_rate: f64::from(OPUS_CLOCK_RATE),
+                    channels: 2,
+                },
+                seek_pre_roll: Some(OPUS_SEEK_PRE_ROLL),
+            });
+        }
+        match MatroskaWriter::new(BufWriter::new(file), self.tracks.webm(), &headers) {
+            Ok(writer) => {
+                info!("Recording the peer into {}", self.path.display());
+                self.writer = Some(writer);
+                self.origin = origin;
+            }
+            Err(err) => self.fail(err),
+        }
+    }
+
+    fn write(&mut self, track: u64, time: Duration, keyframe: bool, data: &[u8]) {
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+        let time = time.saturating_sub(self.origin);
+        if let Err(err) = writer.write_block(track, time, keyframe, data) {
+            self.fail(err);
+        }
+    }
+
+    fn fail(&mut self, err: std::io::Error) {
+        info!("Recording stopped writing: {}", err);
+        self.writer = None;
+        self.failed = Some(err);
+    }
+
+    fn video(&mut self, data: &[u8], rtp_timestamp: u32, size: Option<(usize, usize)>) {
+        let Some(codec) = self.tracks.video.clone() else {
+            return;
+        };
+        let time = self.video_clock.time(rtp_timestamp, self.started.elapsed());
+        let keyframe = is_keyframe(&codec, data);
+        if self.file.is_some() {
+            match size {
+                Some((width, height)) if keyframe => {
+                    self.begin(Some((data, width, height)), time);
+                }
+                _ => return,
+            }
+        }
+        if is(&codec, MIME_TYPE_H264) {
+            self.write(VIDEO_TRACK, time, keyframe, &length_prefixed(data));
+        } else if is(&codec, MIME_TYPE_AV1) {
+            let data = without_temporal_delimiter(data);
+            self.write(VIDEO_TRACK, time, keyframe, &data);
+        } else {
+            self.write(VIDEO_TRACK, time, keyframe, data);
+        }
+    }
+
+    fn audio(&mut self, payload: &[u8], rtp_timestamp: u32) {
+        if self.tracks.audio.is_none() || payload.is_empty() {
+            return;
+        }
+        let time = self.audio_clock.time(rtp_timestamp, self.started.elapsed());
+        if self.file.is_some() && self.tracks.video.is_none() {
+            self.begin(None, time);
+        }
+        self.write(self.audio_number(), time, true, payload);
+    }
+
+    fn finish(mut self) -> Result<Recorded, RecordingError> {
+        if let Some(err) = self.failed.take() {
+            return Err(RecordingError::Io("write the recording", err));
+        }
+        let Some(writer) = self.writer.take() else {
+            drop(self.file.take());
+            let _ = std::fs::remove_file(&self.path);
+            return Err(if self.tracks.video.is_some() {
+                RecordingError::NoKeyframe
+            } else {
+                RecordingError::NothingToRecord
+            });
+        };
+        let duration = writer.duration();
+        writer
+            .finish()
+            .and_then(|mut out| out.flush())
+            .map_err(|err| RecordingError::Io("finish the recording", err))?;
+        info!(
+            "Recorded {:.1} s of the peer into {}",
+            duration.as_secs_f64(),
+            self.path.display()
+        );
+        Ok(Recorded {
+            path: self.path,
+            duration,
+        })
+    }
+}
+
+/// Where received media meets a recording of it, shared by the tasks that
+/// play the remote tracks; while nothing records, what they hand it is
+/// dropped.
+#[derive(Clone, Default)]
+pub struct RecordingTap {
+    recording: Arc<Mutex<Option<Recording>>>,
+}
+
+impl RecordingTap {
+    /// Starts recording `tracks` into a new file at `path`.
+    pub fn start(&self, path: &Path, tracks: RecordedTracks) -> Result<(), RecordingError> {
+        let mut recording = self.recording.lock().unwrap();
+        if recording.is_some() {
+            return Err(RecordingError::AlreadyRecording);
+        }
+        let tracks = tracks.recordable();
+        if tracks.is_empty() {
+            return Err(RecordingError::NothingToRecord);
+        }
+        let file = File::create(path).map_err(|err| RecordingError::Io("create the file", err))?;
+        *recording = Some(Recording {
+            path: path.to_path_buf(),
+            tracks,
+            started: Instant::now(),
+            video_clock: TrackClock::new(VIDEO_CLOCK_RATE),
+            audio_clock: TrackClock::new(OPUS_CLOCK_RATE),
+            file: Some(file),
+            writer: None,
+            origin: Duration::ZERO,
+            failed: None,
+        });
+        Ok(())
+    }
+
+    /// Ends the recording and finishes its file, if one is going.
+    pub fn stop(&self) -> Option<Result<Recorded, RecordingError>> {
+        let recording = self.recording.lock().unwrap().take()?;
+        Some(recording.finish())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.lock().unwrap().is_some()
+    }
+
+    /// How much has been written so far, `None` until the file starts.
+    pub fn recorded(&self) -> Option<Duration> {
+        let recording = self.recording.lock().unwrap();
+        Some(recording.as_ref()?.writer.as_ref()?.duration())
+    }
+
+    /// Hands over a frame of the remote video as it was received, with
+    /// its size where it has been decoded.
+    pub fn video(&self, data: &[u8], rtp_timestamp: u32, size: Option<(usize, usize)>) {
+        if let Some(recording) = &mut *self.recording.lock().unwrap() {
+            recording.video(data, rtp_timestamp, size);
+        }
+    }
+
+    /// Hands over a packet of the remote Opus audio.
+    pub fn audio(&self, payload: &[u8], rtp_timestamp: u32) {
+        if let Some(recording) = &mut *self.recording.lock().unwrap() {
+            recording.audio(payload, rtp_timestamp);
+        }
+    }
+}
+
+/// Reads a remote audio track that is not played, only to record it;
+/// RED is recorded as the Opus it carries.
+pub async fn record_audio_track(track: Arc<TrackRemote>, recording: RecordingTap) {
+    let codec = track.codec();
+    let red = codec
+        .capability
+        .mime_type
+        .eq_ignore_ascii_case(red::MIME_TYPE_RED)
+        .then_some(codec.payload_type);
+    while let Ok((packet, _)) = track.read_rtp().await {
+        let payload = match red {
+            Some(red) if packet.header.payload_type == red => match red::primary(&packet.payload) {
+                Ok(primary) => primary,
+                Err(_) => continue,
+            },
+            _ => &packet.payload[..],
+        };
+        recording.audio(payload, packet.header.timestamp);
+    }
+}
+
+/// `remote-2026-01-31T12-00-00Z.webm` and the like, for a recording
+/// of `tracks` started now.
+pub fn file_name(prefix: &str, tracks: &RecordedTracks) -> String {
+    let now = humantime::format_rfc3339_seconds(std::time::SystemTime::now());
+    format!(
+        "{}-{}.{}",
+        prefix,
+        now.to_string().replace(':', "-"),
+        tracks.extension()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_keyframes_and_parameter_sets() {
+        assert!(is_keyframe(MIME_TYPE_VP8, &[0x10, 0x02]));
+        assert!(!is_keyframe(MIME_TYPE_VP8, &[0x11, 0x02]));
+        // Profile 0: marker, profile bits, show_existing_frame, key.
+        assert!(is_keyframe(MIME_TYPE_VP9, &[0b1000_0000]));
+        assert!(!is_keyframe(MIME_TYPE_VP9, &[0b1000_0100]));
+        assert!(!is_keyframe(MIME_TYPE_VP9, &[0b1000_1000]));
+
+        let sps = [0x67, 0x42, 0xe0, 0x1f, 0xaa];
+        let pps = [0x68, 0xce, 0x38, 0x80];
+        let idr = [0x65, 0x88, 0x84];
+        let mut access_unit = vec![0, 0, 0, 1];
+        access_unit.extend_from_slice(&sps);
+        access_unit.extend_from_slice(&[0, 0, 0, 1]);
+        access_unit.extend_from_slice(&pps);
+        access_unit.extend_from_slice(&[0, 0, 1]);
+        access_unit.extend_from_slice(&idr);
+        assert!(is_keyframe(MIME_TYPE_H264, &access_unit));
+        assert!(!is_keyframe(MIME_TYPE_H264, &[0, 0, 0, 1, 0x41, 0x9a]));
+        let config = avc_config(&access_unit).unwrap();
+        assert_eq!(&config[..6], &[1, 0x42, 0xe0, 0x1f, 0xff, 0xe1]);
+        assert_eq!(&config[6..8], &[0, 5]);
+        assert_eq!(
+            &length_prefixed(&access_unit)[..9],
+            &[0, 0, 0, 5, 0x67, 0x42, 0xe0, 0x1f, 0xaa]
+        );
+
+        // A sequence header then a frame, both with sizes.
+        let obus = [0x0a, 0x02, 0x00, 0x00, 0x32, 0x01, 0x10];
+        assert!(is_keyframe(MIME_TYPE_AV1, &obus));
+        assert!(!is_keyframe(MIME_TYPE_AV1, &obus[4..]));
+        assert_eq!(
+            without_temporal_delimiter(&[0x12, 0x00, 0x32, 0x01, 0x10]),
+            obus[4..]
+        );
+        assert_eq!(
+            av1_config(&obus).unwrap(),
+            [0x81, 0x00, 0x0c, 0x00, 0x0a, 0x02, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn starts_the_file_at_the_first_keyframe() {
+        let path = std::env::temp_dir().join(format!("recording-{}.webm", std::process::id()));
+        let tap = RecordingTap::default();
+        let tracks = RecordedTracks {
+            video: Some(MIME_TYPE_VP8.to_owned()),
+            audio: Some(MIME_TYPE_OPUS.to_owned()),
+        };
+        tap.start(&path, tracks).unwrap();
+        assert!(tap.start(&path, RecordedTracks::default()).is_err());
+        // Left out: audio and a delta frame before the keyframe.
+        tap.audio(&[1, 2, 3], 0);
+        tap.video(&[0x01, 0], 0, Some((64, 48)));
+        assert_eq!(tap.recorded(), None);
+        for frame in 1..=30u32 {
+            tap.video(&[u8::from(frame > 1), 0], frame * 3000, Some((64, 48)));
+            tap.audio(&[1, 2, 3], frame * 1600);
+        }
+        let recorded = tap.stop().unwrap().unwrap();
+        assert_eq!(recorded.duration, Duration::from_millis(966));
+        assert!(!tap.is_recording());
+
+        let file = File::open(&path).unwrap();
+        let mut file = matroska_demuxer::MatroskaFile::open(file).unwrap();
+        assert_eq!(file.tracks().len(), 2);
+        let mut frame = matroska_demuxer::Frame::default();
+        assert!(file.next_frame(&mut frame).unwrap());
+        assert_eq!((frame.track, frame.timestamp), (1, 0));
+        assert_eq!(frame.is_keyframe, Some(true));
+        std::fs::remove_file(&path).unwrap();
+
+        // With no keyframe, no file is left behind.
+        tap.start(&path, RecordedTracks::default().recordable())
+            .unwrap_err();
+        tap.start(
+            &path,
+            RecordedTracks {
+                video: Some(MIME_TYPE_VP8.to_owned()),
+                audio: None,
+            },
+        )
+        .unwrap();
+        tap.video(&[0x01, 0], 0, None);
+        assert!(matches!(tap.stop(), Some(Err(RecordingError::NoKeyframe))));
+        assert!(!path.exists());
+    }
+}
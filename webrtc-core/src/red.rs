@@ -0,0 +1,121 @@
+//! RTP payloads for redundant audio (RFC 2198): each packet carries the
+//! frame before it again, so losing one on its own costs nothing.
+
+use thiserror::Error;
+
+pub const MIME_TYPE_RED: &str = "audio/red";
+
+/// Redundant blocks can start at most this far back, in clock ticks.
+const MAX_OFFSET: u16 = (1 << 14) - 1;
+/// And be at most this long, in bytes.
+const MAX_LENGTH: usize = (1 << 10) - 1;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RedError {
+    #[error("RED payload cut short")]
+    Truncated,
+}
+
+/// One encoding in a RED payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block<'a> {
+    pub payload_type: u8,
+    /// How much earlier than the packet's timestamp it starts; 0 for the
+    /// primary.
+    pub timestamp_offset: u16,
+    pub data: &'a [u8],
+}
+
+/// A payload of `primary` in `payload_type`, after `redundant` (an earlier
+/// frame in the same, and how far back it starts) when there is one that
+/// fits the header.
+pub fn encode(payload_type: u8, primary: &[u8], redundant: Option<(u16, &[u8])>) -> Vec<u8> {
+    let redundant =
+        redundant.filter(|(offset, data)| *offset <= MAX_OFFSET && data.len() <= MAX_LENGTH);
+    let mut payload = Vec::with_capacity(5 + primary.len() + redundant.map_or(0, |r| r.1.len()));
+    if let Some((offset, data)) = redundant {
+        let header =
+            (u32::from(0x80 | payload_type) << 24) | (u32::from(offset) << 10) | data.len() as u32;
+        payload.extend_from_slice(&header.to_be_bytes());
+    }
+    payload.push(payload_type & 0x7f);
+    if let Some((_, data)) = redundant {
+        payload.extend_from_slice(data);
+    }
+    payload.extend_from_slice(primary);
+    payload
+}
+
+/// The blocks of `payload`, oldest first and the primary last.
+pub fn parse(payload: &[u8]) -> Result<Vec<Block<'_>>, RedError> {
+    let mut headers = Vec::new();
+    let mut rest = payload;
+    loop {
+        let (&first, _) = rest.split_first().ok_or(RedError::Truncated)?;
+        if first & 0x80 == 0 {
+            headers.push((first & 0x7f, 0, None));
+            rest = &rest[1..];
+            break;
+        }
+        let header = rest.get(..4).ok_or(RedError::Truncated)?;
+        let header = u32::from_be_bytes(header.try_into().unwrap());
+        let offset = ((header >> 10) & u32::from(MAX_OFFSET)) as u16;
+        let length = (header & MAX_LENGTH as u32) as usize;
+        headers.push((first & 0x7f, offset, Some(length)));
+        rest = &rest[4..];
+    }
+    let mut blocks = Vec::with_capacity(headers.len());
+    for (payload_type, timestamp_offset, length) in headers {
+        let length = length.unwrap_or(rest.len());
+        if rest.len() < length {
+            return Err(RedError::Truncated);
+        }
+        let (data, after) = rest.split_at(length);
+        blocks.push(Block {
+            payload_type,
+            timestamp_offset,
+            data,
+        });
+        rest = after;
+    }
+    Ok(blocks)
+}
+
+/// The primary encoding of `payload`, the one to play when nothing was
+/// lost.
+pub fn primary(payload: &[u8]) -> Result<&[u8], RedError> {
+    Ok(parse(payload)?.pop().ok_or(RedError::Truncated)?.data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carries_the_previous_frame_before_the_primary() {
+        let payload = encode(111, b"now", Some((960, b"before")));
+        assert_eq!(payload.len(), 4 + 1 + 6 + 3);
+        assert_eq!(
+            parse(&payload).unwrap(),
+            [
+                Block {
+                    payload_type: 111,
+                    timestamp_offset: 960,
+                    data: b"before",
+                },
+                Block {
+                    payload_type: 111,
+                    timestamp_offset: 0,
+                    data: b"now",
+                },
+            ]
+        );
+        assert_eq!(primary(&payload).unwrap(), b"now");
+
+        // Too long for the header, so only the primary goes.
+        let long = vec![0; 2000];
+        assert_eq!(encode(111, b"now", Some((960, &long))), b"\x6fnow");
+        assert_eq!(parse(&payload[..8]), Err(RedError::Truncated));
+        assert_eq!(parse(&[]), Err(RedError::Truncated));
+    }
+}
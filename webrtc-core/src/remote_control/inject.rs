@@ -0,0 +1,191 @@
+//! Replaying input through enigo.
+
+use enigo::{Axis, Button, Coordinate, Direction, Enigo, Key, Keyboard, Mouse, Settings};
+use log::info;
+
+use super::{InputMessage, Modifiers, MouseButton, RemoteControlError, RemoteKey};
+use crate::screen::{WindowInfo, WindowLocator};
+
+impl From<enigo::InputError> for RemoteControlError {
+    fn from(err: enigo::InputError) -> Self {
+        RemoteControlError::Input(err.to_string())
+    }
+}
+
+impl From<enigo::NewConError> for RemoteControlError {
+    fn from(err: enigo::NewConError) -> Self {
+        RemoteControlError::Input(err.to_string())
+    }
+}
+
+fn button(button: MouseButton) -> Button {
+    match button {
+        MouseButton::Left => Button::Left,
+        MouseButton::Middle => Button::Middle,
+        MouseButton::Right => Button::Right,
+    }
+}
+
+fn key(key: RemoteKey) -> Option<Key> {
+    Some(match key {
+        RemoteKey::Char(c) => Key::Unicode(c),
+        RemoteKey::Enter => Key::Return,
+        RemoteKey::Tab => Key::Tab,
+        RemoteKey::Backspace => Key::Backspace,
+        RemoteKey::Escape => Key::Escape,
+        RemoteKey::Space => Key::Space,
+        RemoteKey::Delete => Key::Delete,
+        RemoteKey::Home => Key::Home,
+        RemoteKey::End => Key::End,
+        RemoteKey::PageUp => Key::PageUp,
+        RemoteKey::PageDown => Key::PageDown,
+        RemoteKey::Left => Key::LeftArrow,
+        RemoteKey::Right => Key::RightArrow,
+        RemoteKey::Up => Key::UpArrow,
+        RemoteKey::Down => Key::DownArrow,
+        RemoteKey::F(n) => [
+            Key::F1,
+            Key::F2,
+            Key::F3,
+            Key::F4,
+            Key::F5,
+            Key::F6,
+            Key::F7,
+            Key::F8,
+            Key::F9,
+            Key::F10,
+            Key::F11,
+            Key::F12,
+        ]
+        .get(usize::from(n).checked_sub(1)?)
+        .copied()?,
+    })
+}
+
+pub struct Injector {
+    enigo: Enigo,
+    window: WindowLocator,
+    buttons: Vec<MouseButton>,
+    keys: Vec<Key>,
+    modifiers: Modifiers,
+}
+
+impl Injector {
+    pub fn new(window: &WindowInfo) -> Result<Self, RemoteControlError> {
+        Ok(Self {
+            enigo: Enigo::new(&Settings::default())?,
+            window: WindowLocator::open(window)?,
+            buttons: Vec::new(),
+            keys: Vec::new(),
+            modifiers: Modifiers::default(),
+        })
+    }
+
+    pub fn inject(&mut self, message: &InputMessage) -> Result<(), RemoteControlError> {
+        match message {
+            InputMessage::Move { x, y } => {
+                let (x, y) = self.window.rect()?.point(*x, *y);
+                self.enigo.move_mouse(x, y, Coordinate::Abs)?;
+            }
+            InputMessage::Button {
+                button: pressed,
+                down,
+            } => {
+                if *down {
+                    // Whatever covers the window here would take the click.
+                    self.window.activate()?;
+                    self.buttons.push(*pressed);
+                } else {
+                    self.buttons.retain(|held| held != pressed);
+                }
+                self.enigo.button(button(*pressed), direction(*down))?;
+            }
+            InputMessage::Scroll { x, y } => {
+                if *x != 0 {
+                    self.enigo.scroll(*x, Axis::Horizontal)?;
+                }
+                if *y != 0 {
+                    self.enigo.scroll(*y, Axis::Vertical)?;
+                }
+            }
+            InputMessage::Key { key: pressed, down } => {
+                let Some(pressed) = key(*pressed) else {
+                    return Ok(());
+                };
+                if *down {
+                    self.focused()?;
+                    self.keys.push(pressed);
+                } else if !self.keys.contains(&pressed) {
+                    return Ok(());
+                } else {
+                    self.keys.retain(|held| *held != pressed);
+                }
+                self.enigo.key(pressed, direction(*down))?;
+            }
+            InputMessage::Modifiers(modifiers) => self.hold(*modifiers)?,
+            InputMessage::Text { text } => {
+                self.focused()?;
+                self.enigo.text(text)?;
+            }
+            InputMessage::Request
+            | InputMessage::Allow
+            | InputMessage::Deny { .. }
+            | InputMessage::Revoke
+            | InputMessage::Release => {}
+        }
+        Ok(())
+    }
+
+    fn focused(&self) -> Result<(), RemoteControlError> {
+        if self.window.has_focus()? {
+            Ok(())
+        } else {
+            Err(RemoteControlError::NotFocused)
+        }
+    }
+
+    /// Presses and lets go of modifiers until just `modifiers` are held.
+    fn hold(&mut self, modifiers: Modifiers) -> Result<(), RemoteControlError> {
+        let held = self.modifiers;
+        for (key, want, have) in [
+            (Key::Shift, modifiers.shift, held.shift),
+            (Key::Control, modifiers.ctrl, held.ctrl),
+            (Key::Alt, modifiers.alt, held.alt),
+            (Key::Meta, modifiers.meta, held.meta),
+        ] {
+            if want != have {
+                self.enigo.key(key, direction(want))?;
+            }
+        }
+        self.modifiers = modifiers;
+        Ok(())
+    }
+}
+
+fn direction(down: bool) -> Direction {
+    if down {
+        Direction::Press
+    } else {
+        Direction::Release
+    }
+}
+
+impl Drop for Injector {
+    fn drop(&mut self) {
+        let keys = std::mem::take(&mut self.keys);
+        let buttons = std::mem::take(&mut self.buttons);
+        let released = keys
+            .into_iter()
+            .try_for_each(|key| self.enigo.key(key, Direction::Release))
+            .and_then(|()| {
+                buttons
+                    .into_iter()
+                    .try_for_each(|held| self.enigo.button(button(held), Direction::Release))
+            })
+            .map_err(RemoteControlError::from)
+            .and_then(|()| self.hold(Modifiers::default()));
+        if let Err(err) = released {
+            info!("Failed to let go of the peer's input: {}", err);
+        }
+    }
+}
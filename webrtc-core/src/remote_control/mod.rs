@@ -0,0 +1,222 @@
+//! Letting the peer drive the window being shared. The viewer asks over a
+//! pre-negotiated "input" data channel, and once the sharer has allowed
+//! it, sends its pointer and keys there to be replayed onto the window.
+//! Positions go as fractions of the picture, so they land in the same
+//! place whatever size it was sent at.
+//!
+//! Replaying needs the `remote-control` feature; without it every request
+//! is turned down.
+
+#[cfg(feature = "remote-control")]
+mod inject;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::screen::{ScreenError, WindowInfo};
+
+pub const INPUT_LABEL: &str = "input";
+pub const INPUT_STREAM_ID: u16 = 5;
+
+#[derive(Debug, Error)]
+pub enum RemoteControlError {
+    #[error("this build cannot replay input")]
+    Unsupported,
+    #[error("the shared window does not have the keyboard")]
+    NotFocused,
+    #[error("cannot replay input: {0}")]
+    Input(String),
+    #[error(transparent)]
+    Screen(#[from] ScreenError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+/// The keys that are sent as keys rather than as the text they type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoteKey {
+    /// A letter, digit or sign, lowercase; sent as a key only with a
+    /// modifier held, for shortcuts.
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+    Space,
+    Delete,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Left,
+    Right,
+    Up,
+    Down,
+    /// F1 to F12.
+    F(u8),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    /// The Windows or Command key.
+    pub meta: bool,
+}
+
+/// Everything on the input channel, each as a JSON text message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputMessage {
+    /// The viewer would like control.
+    Request,
+    /// The sharer's answers; `Revoke` also when the share ends.
+    Allow,
+    Deny {
+        reason: String,
+    },
+    Revoke,
+    /// The viewer is done.
+    Release,
+    /// The pointer is `x` and `y` of the way across and down the picture.
+    Move {
+        x: f32,
+        y: f32,
+    },
+    Button {
+        button: MouseButton,
+        down: bool,
+    },
+    /// Wheel steps, right and down.
+    Scroll {
+        x: i32,
+        y: i32,
+    },
+    Key {
+        key: RemoteKey,
+        down: bool,
+    },
+    /// The modifiers held now, sent whenever they change.
+    Modifiers(Modifiers),
+    Text {
+        text: String,
+    },
+}
+
+impl InputMessage {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("input messages always serialize")
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(text)
+    }
+
+    /// Whether this drives the window, rather than asks or answers.
+    pub fn is_input(&self) -> bool {
+        !matches!(
+            self,
+            Self::Request | Self::Allow | Self::Deny { .. } | Self::Revoke | Self::Release
+        )
+    }
+}
+
+/// Whether this build can replay the peer's input.
+pub fn can_replay() -> bool {
+    cfg!(feature = "remote-control")
+}
+
+/// Replays the viewer's input onto one window. Keys are only pressed while
+/// that window has the keyboard, and whatever is held down is let go when
+/// this is dropped.
+pub struct Injector {
+    #[cfg(feature = "remote-control")]
+    inner: inject::Injector,
+}
+
+impl Injector {
+    pub fn new(window: &WindowInfo) -> Result<Self, RemoteControlError> {
+        #[cfg(feature = "remote-control")]
+        {
+            Ok(Self {
+                inner: inject::Injector::new(window)?,
+            })
+        }
+        #[cfg(not(feature = "remote-control"))]
+        {
+            let _ = window;
+            Err(RemoteControlError::Unsupported)
+        }
+    }
+
+    /// Replays `message`, if it is input.
+    pub fn inject(&mut self, message: &InputMessage) -> Result<(), RemoteControlError> {
+        #[cfg(feature = "remote-control")]
+        {
+            self.inner.inject(message)
+        }
+        #[cfg(not(feature = "remote-control"))]
+        {
+            let _ = message;
+            Err(RemoteControlError::Unsupported)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::WindowRect;
+
+    #[test]
+    fn round_trips_messages_and_lands_inside_the_window() {
+        for message in [
+            InputMessage::Request,
+            InputMessage::Deny {
+                reason: "no".to_owned(),
+            },
+            InputMessage::Move { x: 0.25, y: 1.0 },
+            InputMessage::Key {
+                key: RemoteKey::Char('c'),
+                down: true,
+            },
+            InputMessage::Modifiers(Modifiers {
+                ctrl: true,
+                ..Default::default()
+            }),
+        ] {
+            assert_eq!(
+                InputMessage::from_json(&message.to_json()).unwrap(),
+                message
+            );
+        }
+        assert_eq!(
+            InputMessage::Button {
+                button: MouseButton::Left,
+                down: false
+            }
+            .to_json(),
+            r#"{"type":"button","button":"left","down":false}"#
+        );
+        assert!(!InputMessage::Revoke.is_input());
+        assert!(InputMessage::Scroll { x: 0, y: 1 }.is_input());
+
+        let rect = WindowRect {
+            x: 100,
+            y: 50,
+            width: 201,
+            height: 101,
+        };
+        assert_eq!(rect.point(0.0, 0.0), (100, 50));
+        assert_eq!(rect.point(0.5, 0.5), (200, 100));
+        assert_eq!(rect.point(1.5, -1.0), (300, 50));
+    }
+}
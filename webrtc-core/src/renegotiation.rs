@@ -0,0 +1,293 @@
+//! What an offer arriving mid-call changes compared to the session we are
+//! in, and whether to answer it without asking.
+//!
+//! New tracks and direction changes are routine: the peer added a camera
+//! or put us on hold. An offer that opens data channels, or swaps the DTLS
+//! certificate or role the call is secured with, is worth a look first.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenegotiationPolicy {
+    /// Answer routine changes, ask about the rest.
+    #[default]
+    AcceptBenign,
+    AlwaysAsk,
+    AcceptAll,
+}
+
+impl RenegotiationPolicy {
+    pub const ALL: [RenegotiationPolicy; 3] = [
+        RenegotiationPolicy::AcceptBenign,
+        RenegotiationPolicy::AlwaysAsk,
+        RenegotiationPolicy::AcceptAll,
+    ];
+}
+
+impl fmt::Display for RenegotiationPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RenegotiationPolicy::AcceptBenign => "Accept new tracks, ask otherwise",
+            RenegotiationPolicy::AlwaysAsk => "Always ask",
+            RenegotiationPolicy::AcceptAll => "Accept everything",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenegotiationSettings {
+    pub policy: RenegotiationPolicy,
+    /// Policies for single contacts, by contact name, over `policy`.
+    pub overrides: BTreeMap<String, RenegotiationPolicy>,
+}
+
+impl RenegotiationSettings {
+    pub fn policy_for(&self, contact: Option<&str>) -> RenegotiationPolicy {
+        contact
+            .and_then(|name| self.overrides.get(name))
+            .copied()
+            .unwrap_or(self.policy)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OfferChange {
+    /// A new audio or video m-line.
+    AddsMedia { kind: String },
+    /// An m-line was disabled with port 0.
+    RemovesMedia { kind: String },
+    Direction {
+        mid: String,
+        from: String,
+        to: String,
+    },
+    /// The first `m=application` section, bringing data channels along.
+    AddsDataChannels,
+    /// Different `a=fingerprint` or `a=setup` values, or SDES keys.
+    Encryption(String),
+}
+
+impl OfferChange {
+    /// Whether the user is asked under `AcceptBenign`.
+    pub fn needs_consent(&self) -> bool {
+        matches!(
+            self,
+            OfferChange::AddsDataChannels | OfferChange::Encryption(_)
+        )
+    }
+}
+
+impl fmt::Display for OfferChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OfferChange::AddsMedia { kind } => write!(f, "adds {}", kind),
+            OfferChange::RemovesMedia { kind } => write!(f, "removes {}", kind),
+            OfferChange::Direction { mid, from, to } => {
+                write!(f, "changes {} from {} to {}", mid, from, to)
+            }
+            OfferChange::AddsDataChannels => f.write_str("opens data channels"),
+            OfferChange::Encryption(detail) => write!(f, "changes encryption: {}", detail),
+        }
+    }
+}
+
+/// Whether to answer an offer making `changes` without asking.
+pub fn auto_accept(policy: RenegotiationPolicy, changes: &[OfferChange]) -> bool {
+    match policy {
+        RenegotiationPolicy::AcceptAll => true,
+        RenegotiationPolicy::AlwaysAsk => false,
+        RenegotiationPolicy::AcceptBenign => !changes.iter().any(OfferChange::needs_consent),
+    }
+}
+
+#[derive(Debug, Default)]
+struct Section {
+    kind: String,
+    port: String,
+    mid: String,
+    direction: Option<String>,
+    setup: Option<String>,
+}
+
+impl Section {
+    fn added(&self) -> OfferChange {
+        if self.kind == "application" {
+            OfferChange::AddsDataChannels
+        } else {
+            OfferChange::AddsMedia {
+                kind: self.kind.clone(),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Session {
+    sections: Vec<Section>,
+    fingerprints: Vec<String>,
+    /// Session-level direction, the default for sections without one.
+    direction: Option<String>,
+    sdes: bool,
+}
+
+impl Session {
+    fn parse(sdp: &str) -> Self {
+        let mut session = Session::default();
+        for line in sdp.lines().map(str::trim) {
+            if let Some(media) = line.strip_prefix("m=") {
+                let mut fields = media.split(' ');
+                session.sections.push(Section {
+                    kind: fields.next().unwrap_or_default().to_owned(),
+                    port: fields.next().unwrap_or_default().to_owned(),
+                    ..Default::default()
+                });
+                continue;
+            }
+            let Some(attribute) = line.strip_prefix("a=") else {
+                continue;
+            };
+            let (name, value) = attribute.split_once(':').unwrap_or((attribute, ""));
+            let section = session.sections.last_mut();
+            match (name, section) {
+                ("fingerprint", _) => session.fingerprints.push(value.to_ascii_uppercase()),
+                ("crypto", _) => session.sdes = true,
+                ("mid", Some(section)) => section.mid = value.to_owned(),
+                ("setup", Some(section)) => section.setup = Some(value.to_owned()),
+                ("sendrecv" | "sendonly" | "recvonly" | "inactive", Some(section)) => {
+                    section.direction = Some(name.to_owned())
+                }
+                ("sendrecv" | "sendonly" | "recvonly" | "inactive", None) => {
+                    session.direction = Some(name.to_owned())
+                }
+                _ => {}
+            }
+        }
+        session.fingerprints.sort();
+        session.fingerprints.dedup();
+        session
+    }
+
+    fn direction(&self, section: &Section) -> String {
+        section
+            .direction
+            .clone()
+            .or_else(|| self.direction.clone())
+            .unwrap_or_else(|| "sendrecv".to_owned())
+    }
+}
+
+/// What `offer` changes compared to `current`, the remote description the
+/// call runs on. Media sections are matched by mid, or by position for
+/// mids that are missing.
+pub fn offer_changes(current: &str, offer: &str) -> Vec<OfferChange> {
+    let current = Session::parse(current);
+    let offer = Session::parse(offer);
+    let mut changes = vec![];
+    if current.fingerprints != offer.fingerprints {
+        changes.push(OfferChange::Encryption(
+            "a different DTLS certificate".to_owned(),
+        ));
+    }
+    if offer.sdes && !current.sdes {
+        changes.push(OfferChange::Encryption("SDES keys in the SDP".to_owned()));
+    }
+    for (index, section) in offer.sections.iter().enumerate() {
+        let previous = if section.mid.is_empty() {
+            current.sections.get(index)
+        } else {
+            current.sections.iter().find(|s| s.mid == section.mid)
+        };
+        let Some(previous) = previous else {
+            if section.port == "0" {
+                continue;
+            }
+            changes.push(section.added());
+            continue;
+        };
+        if section.port == "0" && previous.port != "0" {
+            changes.push(OfferChange::RemovesMedia {
+                kind: section.kind.clone(),
+            });
+            continue;
+        }
+        if previous.port == "0" && section.port != "0" {
+            changes.push(section.added());
+            continue;
+        }
+        let (from, to) = (current.direction(previous), offer.direction(section));
+        if from != to {
+            changes.push(OfferChange::Direction {
+                mid: section.mid.clone(),
+                from,
+                to,
+            });
+        }
+        // An offerer may always propose actpass; only pinning a role counts.
+        if let (Some(from), Some(to)) = (&previous.setup, &section.setup) {
+            if from != to && to != "actpass" {
+                changes.push(OfferChange::Encryption(format!(
+                    "DTLS role of {} set to {}",
+                    section.mid, to
+                )));
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALL: &str = "v=0\r\n\
+        a=fingerprint:sha-256 AB:CD\r\n\
+        m=audio 9 UDP/TLS/RTP/SAVPF 111\r\n\
+        a=mid:0\r\n\
+        a=setup:actpass\r\n\
+        a=sendrecv\r\n\
+        m=application 9 UDP/DTLS/SCTP webrtc-datachannel\r\n\
+        a=mid:1\r\n";
+
+    #[test]
+    fn tracks_are_benign_and_data_channels_or_certificates_are_not() {
+        assert_eq!(offer_changes(CALL, CALL), vec![]);
+
+        let video = format!(
+            "{}{}",
+            CALL.replace("a=sendrecv", "a=sendonly"),
+            "m=video 9 UDP/TLS/RTP/SAVPF 96\r\na=mid:2\r\n"
+        );
+        let changes = offer_changes(CALL, &video);
+        assert_eq!(
+            changes,
+            vec![
+                OfferChange::Direction {
+                    mid: "0".to_owned(),
+                    from: "sendrecv".to_owned(),
+                    to: "sendonly".to_owned(),
+                },
+                OfferChange::AddsMedia {
+                    kind: "video".to_owned()
+                },
+            ]
+        );
+        assert!(auto_accept(RenegotiationPolicy::AcceptBenign, &changes));
+        assert!(!auto_accept(RenegotiationPolicy::AlwaysAsk, &changes));
+
+        let audio_only = CALL.replace("m=application 9", "m=application 0");
+        let channels = offer_changes(&audio_only, CALL);
+        assert_eq!(channels, vec![OfferChange::AddsDataChannels]);
+        assert!(!auto_accept(RenegotiationPolicy::AcceptBenign, &channels));
+        assert!(auto_accept(RenegotiationPolicy::AcceptAll, &channels));
+
+        let rekeyed = CALL.replace("AB:CD", "EF:01");
+        assert!(offer_changes(CALL, &rekeyed)
+            .iter()
+            .all(OfferChange::needs_consent));
+    }
+}
@@ -0,0 +1,320 @@
+//! JSON-RPC 2.0 over the pre-negotiated "rpc" data channel, so each side
+//! can expose a few methods for the other to call. Requests carry an id
+//! their response echoes; calls not answered in time are given up on.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+pub const RPC_LABEL: &str = "rpc";
+pub const RPC_STREAM_ID: u16 = 6;
+
+/// How long a call waits for its response.
+pub const RPC_TIMEOUT: Duration = Duration::from_secs(10);
+
+const VERSION: &str = "2.0";
+
+/// Lists the methods a side exposes, with what each does.
+pub const METHODS_METHOD: &str = "rpc.methods";
+
+/// An error as JSON-RPC carries it, from the peer or for it.
+#[derive(Debug, Clone, PartialEq, Error, Serialize, Deserialize)]
+#[error("{message} ({code})")]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(Self::INVALID_PARAMS, message)
+    }
+}
+
+/// How a call of ours went wrong.
+#[derive(Debug, Error, PartialEq)]
+pub enum RpcCallError {
+    #[error("the rpc channel is not open")]
+    NotOpen,
+    #[error("the rpc channel closed before the peer answered")]
+    Closed,
+    #[error("no answer within {} s", RPC_TIMEOUT.as_secs())]
+    Timeout,
+    #[error("the peer answered with an error: {0}")]
+    Remote(RpcError),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcRequest {
+    pub jsonrpc: String,
+    /// None for a notification, which gets no response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub params: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RpcOutcome {
+    Result(Value),
+    Error(RpcError),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcResponse {
+    pub jsonrpc: String,
+    /// Null when the request could not be read far enough to find it.
+    pub id: Value,
+    #[serde(flatten)]
+    pub outcome: RpcOutcome,
+}
+
+impl RpcResponse {
+    fn new(id: Value, outcome: RpcOutcome) -> Self {
+        Self {
+            jsonrpc: VERSION.to_owned(),
+            id,
+            outcome,
+        }
+    }
+}
+
+/// Anything on the channel; requests and responses go both ways.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RpcMessage {
+    Request(RpcRequest),
+    Response(RpcResponse),
+}
+
+impl RpcMessage {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("rpc messages always serialize")
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(text)
+    }
+}
+
+type Handler = Box<dyn Fn(Value) -> Result<Value, RpcError> + Send + Sync>;
+
+/// The methods this side answers.
+#[derive(Default)]
+pub struct RpcMethods {
+    methods: BTreeMap<String, (String, Handler)>,
+}
+
+impl RpcMethods {
+    /// Exposes `name`, which `handler` answers given the request's params.
+    pub fn register(
+        &mut self,
+        name: &str,
+        description: &str,
+        handler: impl Fn(Value) -> Result<Value, RpcError> + Send + Sync + 'static,
+    ) {
+        self.methods
+            .insert(name.to_owned(), (description.to_owned(), Box::new(handler)));
+    }
+
+    /// Every method exposed, with what it does, by name.
+    pub fn list(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.methods
+            .iter()
+            .map(|(name, (description, _))| (name.as_str(), description.as_str()))
+    }
+
+    /// Answers `request`, unless it is a notification.
+    pub fn handle(&self, request: RpcRequest) -> Option<RpcResponse> {
+        let outcome = if request.jsonrpc != VERSION {
+            Err(RpcError::new(
+                RpcError::INVALID_REQUEST,
+                format!("unsupported JSON-RPC version \"{}\"", request.jsonrpc),
+            ))
+        } else if request.method == METHODS_METHOD {
+            let methods: serde_json::Map<_, _> = self
+                .list()
+                .map(|(name, description)| (name.to_owned(), Value::from(description)))
+                .collect();
+            Ok(Value::Object(methods))
+        } else {
+            match self.methods.get(&request.method) {
+                Some((_, handler)) => handler(request.params),
+                None => Err(RpcError::new(
+                    RpcError::METHOD_NOT_FOUND,
+                    format!("no method \"{}\"", request.method),
+                )),
+            }
+        };
+        let id = request.id?;
+        Some(RpcResponse::new(
+            id,
+            match outcome {
+                Ok(result) => RpcOutcome::Result(result),
+                Err(err) => RpcOutcome::Error(err),
+            },
+        ))
+    }
+
+    /// The response to a message that is not JSON-RPC at all.
+    pub fn unreadable(err: &serde_json::Error) -> RpcResponse {
+        RpcResponse::new(
+            Value::Null,
+            RpcOutcome::Error(RpcError::new(RpcError::PARSE_ERROR, err.to_string())),
+        )
+    }
+}
+
+type Waiter = oneshot::Sender<Result<Value, RpcError>>;
+
+/// Our calls awaiting their response, by id.
+#[derive(Debug, Default)]
+pub struct RpcCalls {
+    next_id: u64,
+    pending: HashMap<u64, Waiter>,
+}
+
+impl RpcCalls {
+    /// Starts a call of `method`, returning the request to send and where
+    /// its answer will arrive.
+    pub fn start(
+        &mut self,
+        method: &str,
+        params: Value,
+    ) -> (RpcRequest, oneshot::Receiver<Result<Value, RpcError>>) {
+        self.next_id += 1;
+        let (waiter, answer) = oneshot::channel();
+        self.pending.insert(self.next_id, waiter);
+        let request = RpcRequest {
+            jsonrpc: VERSION.to_owned(),
+            id: Some(self.next_id.into()),
+            method: method.to_owned(),
+            params,
+        };
+        (request, answer)
+    }
+
+    /// Hands `response` to the call it answers. Returns false when no
+    /// call is waiting for it, such as one already given up on.
+    pub fn answered(&mut self, response: RpcResponse) -> bool {
+        let Some(waiter) = response.id.as_u64().and_then(|id| self.pending.remove(&id)) else {
+            return false;
+        };
+        let _ = waiter.send(match response.outcome {
+            RpcOutcome::Result(result) => Ok(result),
+            RpcOutcome::Error(err) => Err(err),
+        });
+        true
+    }
+
+    /// Gives up on the call made with `request`.
+    pub fn forget(&mut self, request: &RpcRequest) {
+        if let Some(id) = request.id.as_ref().and_then(Value::as_u64) {
+            self.pending.remove(&id);
+        }
+    }
+
+    /// Fails every call waiting, for a channel that closed.
+    pub fn close(&mut self) {
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn message(text: Value) -> RpcMessage {
+        RpcMessage::from_json(&text.to_string()).unwrap()
+    }
+
+    #[test]
+    fn answers_requests_and_matches_responses() {
+        let mut methods = RpcMethods::default();
+        methods.register("add", "Adds two numbers", |params| {
+            let [a, b]: [i64; 2] = serde_json::from_value(params)
+                .map_err(|err| RpcError::invalid_params(err.to_string()))?;
+            Ok(json!(a + b))
+        });
+
+        let mut calls = RpcCalls::default();
+        let (request, mut answer) = calls.start("add", json!([2, 3]));
+        let RpcMessage::Request(request) = message(json!(RpcMessage::Request(request))) else {
+            panic!("not a request");
+        };
+        let response = methods.handle(request).unwrap();
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            json!({"jsonrpc": "2.0", "id": 1, "result": 5})
+        );
+        let RpcMessage::Response(response) = message(json!(response)) else {
+            panic!("not a response");
+        };
+        assert!(calls.answered(response.clone()));
+        assert_eq!(answer.try_recv().unwrap(), Ok(json!(5)));
+        assert!(!calls.answered(response));
+
+        let request = |method: &str, params| RpcRequest {
+            jsonrpc: VERSION.to_owned(),
+            id: Some(json!("x")),
+            method: method.to_owned(),
+            params,
+        };
+        let error = |request| match methods.handle(request).unwrap().outcome {
+            RpcOutcome::Error(err) => err.code,
+            RpcOutcome::Result(result) => panic!("answered {}", result),
+        };
+        assert_eq!(
+            error(request("add", json!("two"))),
+            RpcError::INVALID_PARAMS
+        );
+        assert_eq!(
+            error(request("nope", Value::Null)),
+            RpcError::METHOD_NOT_FOUND
+        );
+        let listed = methods
+            .handle(request(METHODS_METHOD, Value::Null))
+            .unwrap();
+        assert_eq!(
+            listed.outcome,
+            RpcOutcome::Result(json!({"add": "Adds two numbers"}))
+        );
+
+        let notification = RpcRequest {
+            id: None,
+            ..request("add", json!([1, 1]))
+        };
+        assert_eq!(methods.handle(notification), None);
+
+        let (request, mut answer) = calls.start("add", Value::Null);
+        calls.forget(&request);
+        assert!(answer.try_recv().is_err());
+        let (_, mut answer) = calls.start("add", Value::Null);
+        calls.close();
+        assert!(answer.try_recv().is_err());
+    }
+}
@@ -0,0 +1,187 @@
+//! The WebRTC stack behind one interface. Code holding a
+//! `PeerConnectionHandle` or a track handle, and speaking in the plain
+//! types below, does not change when webrtc-rs does; only the
+//! implementation for it in `webrtc_rs` has to. With the `str0m` feature,
+//! `str0m` implements them too.
+
+#[cfg(feature = "str0m")]
+pub mod str0m;
+pub mod webrtc_rs;
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RtcError {
+    #[error("WebRTC error: {0}")]
+    Backend(String),
+    #[error("the peer connection is closed")]
+    Closed,
+    #[error("{0} is not a track of this peer connection")]
+    UnknownTrack(String),
+}
+
+pub type RtcFuture<'a, T> = BoxFuture<'a, Result<T, RtcError>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SdpKind {
+    Offer,
+    Answer,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionDescription {
+    pub kind: SdpKind,
+    pub sdp: String,
+}
+
+impl SessionDescription {
+    pub fn offer(sdp: impl Into<String>) -> Self {
+        Self {
+            kind: SdpKind::Offer,
+            sdp: sdp.into(),
+        }
+    }
+
+    pub fn answer(sdp: impl Into<String>) -> Self {
+        Self {
+            kind: SdpKind::Answer,
+            sdp: sdp.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IceCandidate {
+    /// The `candidate:` attribute, as it appears in SDP.
+    pub candidate: String,
+    pub sdp_mid: Option<String>,
+    pub sdp_mline_index: Option<u16>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MediaKind {
+    Audio,
+    Video,
+}
+
+impl fmt::Display for MediaKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MediaKind::Audio => "audio",
+            MediaKind::Video => "video",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    SendRecv,
+    SendOnly,
+    RecvOnly,
+    Inactive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    New,
+    Connecting,
+    Connected,
+    Disconnected,
+    Failed,
+    Closed,
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConnectionState::New => "new",
+            ConnectionState::Connecting => "connecting",
+            ConnectionState::Connected => "connected",
+            ConnectionState::Disconnected => "disconnected",
+            ConnectionState::Failed => "failed",
+            ConnectionState::Closed => "closed",
+        })
+    }
+}
+
+/// What a local track carries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackCodec {
+    pub mime_type: String,
+    pub clock_rate: u32,
+    /// Zero for video.
+    pub channels: u16,
+}
+
+/// One received RTP packet.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RtpPacket {
+    pub payload_type: u8,
+    pub sequence_number: u16,
+    pub timestamp: u32,
+    pub ssrc: u32,
+    pub marker: bool,
+    pub payload: Bytes,
+}
+
+/// A track we send, fed with encoded media one sample at a time.
+pub trait TrackHandle: Send + Sync {
+    fn id(&self) -> &str;
+    fn kind(&self) -> MediaKind;
+    fn write_sample(&self, data: Bytes, duration: Duration) -> RtcFuture<'_, ()>;
+}
+
+/// A track the peer sends, read a packet at a time until it ends.
+pub trait RemoteTrackHandle: Send + Sync {
+    fn kind(&self) -> MediaKind;
+    /// The negotiated codec's MIME type, e.g. `video/H264`.
+    fn mime_type(&self) -> String;
+    fn read_packet(&self) -> RtcFuture<'_, RtpPacket>;
+}
+
+pub type OnStateChange = Box<dyn FnMut(ConnectionState) -> BoxFuture<'static, ()> + Send + Sync>;
+pub type OnTrack =
+    Box<dyn FnMut(Arc<dyn RemoteTrackHandle>) -> BoxFuture<'static, ()> + Send + Sync>;
+/// Called with `None` once gathering is complete.
+pub type OnIceCandidate =
+    Box<dyn FnMut(Option<IceCandidate>) -> BoxFuture<'static, ()> + Send + Sync>;
+
+pub trait PeerConnectionHandle: Send + Sync {
+    fn add_transceiver(&self, kind: MediaKind, direction: Direction) -> RtcFuture<'_, ()>;
+    /// Creates a track of `kind` and adds it for sending.
+    fn add_track(
+        &self,
+        kind: MediaKind,
+        codec: TrackCodec,
+        stream_id: &str,
+    ) -> RtcFuture<'_, Arc<dyn TrackHandle>>;
+    fn remove_track<'a>(&'a self, track: &'a dyn TrackHandle) -> RtcFuture<'a, ()>;
+
+    fn create_offer(&self) -> RtcFuture<'_, SessionDescription>;
+    fn create_answer(&self) -> RtcFuture<'_, SessionDescription>;
+    fn set_local_description(&self, description: SessionDescription) -> RtcFuture<'_, ()>;
+    /// Sets the local description and waits for ICE gathering, returning
+    /// the description with every candidate in it, for signaling without
+    /// trickle.
+    fn set_local_description_gathered(
+        &self,
+        description: SessionDescription,
+    ) -> RtcFuture<'_, SessionDescription>;
+    fn set_remote_description(&self, description: SessionDescription) -> RtcFuture<'_, ()>;
+    fn local_description(&self) -> BoxFuture<'_, Option<SessionDescription>>;
+    fn remote_description(&self) -> BoxFuture<'_, Option<SessionDescription>>;
+    fn add_ice_candidate(&self, candidate: IceCandidate) -> RtcFuture<'_, ()>;
+
+    fn connection_state(&self) -> ConnectionState;
+    fn on_connection_state_change(&self, handler: OnStateChange);
+    fn on_track(&self, handler: OnTrack);
+    fn on_ice_candidate(&self, handler: OnIceCandidate);
+
+    fn close(&self) -> RtcFuture<'_, ()>;
+}
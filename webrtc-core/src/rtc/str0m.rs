@@ -0,0 +1,918 @@
+//! The handles on str0m, a sans-IO engine. One task owns the `Rtc` and
+//! drives it from its sockets, its timeouts, and the handles' commands in
+//! turn, so nothing about a connection runs concurrently with anything
+//! else about it.
+//!
+//! str0m leaves gathering to its user; only host candidates are offered,
+//! so peers must be reachable without STUN or TURN.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use log::{info, warn};
+use str0m::change::{SdpAnswer, SdpOffer, SdpPendingOffer};
+use str0m::format::Codec;
+use str0m::media::Mid;
+use str0m::net::{Protocol, Receive};
+use str0m::rtp::RtpWrite;
+use str0m::{Candidate, Event, IceConnectionState, Input, Output, Rtc};
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use webrtc::rtp::codecs::av1::Av1Payloader;
+use webrtc::rtp::codecs::h264::H264Payloader;
+use webrtc::rtp::codecs::opus::OpusPayloader;
+use webrtc::rtp::codecs::vp8::Vp8Payloader;
+use webrtc::rtp::codecs::vp9::Vp9Payloader;
+use webrtc::rtp::packetizer::Payloader;
+
+use super::{
+    ConnectionState, Direction, IceCandidate, MediaKind, OnIceCandidate, OnStateChange, OnTrack,
+    PeerConnectionHandle, RemoteTrackHandle, RtcError, RtcFuture, RtpPacket, SdpKind,
+    SessionDescription, TrackCodec, TrackHandle,
+};
+use crate::settings::Settings;
+
+/// Payloads are cut to fit this, as webrtc-rs does.
+const MTU: usize = 1200;
+
+/// Datagrams read but not yet handed to the `Rtc`.
+const DATAGRAM_QUEUE: usize = 256;
+
+type Command = Box<dyn FnOnce(&mut Driver) + Send>;
+
+/// Runs `command` on the driver task and waits for what it returns.
+async fn call<T: Send + 'static>(
+    commands: &mpsc::UnboundedSender<Command>,
+    command: impl FnOnce(&mut Driver) -> Result<T, RtcError> + Send + 'static,
+) -> Result<T, RtcError> {
+    let (done_tx, done) = oneshot::channel();
+    commands
+        .send(Box::new(move |driver| {
+            let _ = done_tx.send(command(driver));
+        }))
+        .map_err(|_| RtcError::Closed)?;
+    done.await.map_err(|_| RtcError::Closed)?
+}
+
+fn backend(err: impl std::fmt::Display) -> RtcError {
+    RtcError::Backend(err.to_string())
+}
+
+impl From<str0m::RtcError> for RtcError {
+    fn from(err: str0m::RtcError) -> Self {
+        backend(err)
+    }
+}
+
+impl From<MediaKind> for str0m::media::MediaKind {
+    fn from(kind: MediaKind) -> Self {
+        match kind {
+            MediaKind::Audio => str0m::media::MediaKind::Audio,
+            MediaKind::Video => str0m::media::MediaKind::Video,
+        }
+    }
+}
+
+fn media_kind(kind: str0m::media::MediaKind) -> MediaKind {
+    match kind {
+        str0m::media::MediaKind::Audio => MediaKind::Audio,
+        str0m::media::MediaKind::Video => MediaKind::Video,
+    }
+}
+
+impl From<Direction> for str0m::media::Direction {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::SendRecv => str0m::media::Direction::SendRecv,
+            Direction::SendOnly => str0m::media::Direction::SendOnly,
+            Direction::RecvOnly => str0m::media::Direction::RecvOnly,
+            Direction::Inactive => str0m::media::Direction::Inactive,
+        }
+    }
+}
+
+/// The str0m codec for a MIME type like `video/H264`.
+fn codec(mime_type: &str) -> Codec {
+    let subtype = mime_type
+        .split_once('/')
+        .map_or(mime_type, |(_, subtype)| subtype);
+    Codec::from(subtype)
+}
+
+fn payloader(codec: Codec) -> Option<Box<dyn Payloader + Send>> {
+    Some(match codec {
+        Codec::Opus => Box::new(OpusPayloader),
+        Codec::H264 => Box::<H264Payloader>::default(),
+        Codec::Vp8 => Box::<Vp8Payloader>::default(),
+        Codec::Vp9 => Box::<Vp9Payloader>::default(),
+        Codec::Av1 => Box::new(Av1Payloader {}),
+        _ => return None,
+    })
+}
+
+/// What the handles read without a round trip to the driver.
+#[derive(Default)]
+struct Shared {
+    state: Option<ConnectionState>,
+    local: Option<SessionDescription>,
+    remote: Option<SessionDescription>,
+    on_state_change: Option<OnStateChange>,
+    on_track: Option<OnTrack>,
+    on_ice_candidate: Option<OnIceCandidate>,
+}
+
+/// A change made through the handle, put into the next offer.
+enum Change {
+    Add {
+        kind: MediaKind,
+        direction: Direction,
+        stream_id: Option<String>,
+        /// The id of the local track the media sends.
+        track: Option<String>,
+    },
+    Direction(Mid, Direction),
+}
+
+struct SendTrack {
+    kind: MediaKind,
+    codec: Codec,
+    clock_rate: u32,
+    /// Unset until an offer or answer puts the track in a media section.
+    mid: Option<Mid>,
+    payloader: Box<dyn Payloader + Send>,
+    sequence_number: u64,
+    timestamp: u32,
+}
+
+struct Datagram {
+    source: SocketAddr,
+    destination: SocketAddr,
+    contents: Vec<u8>,
+}
+
+struct Driver {
+    rtc: Rtc,
+    sockets: Vec<Arc<UdpSocket>>,
+    readers: Vec<JoinHandle<()>>,
+    changes: Vec<Change>,
+    pending_offer: Option<SdpPendingOffer>,
+    /// The answer to the last remote offer, until it is asked for.
+    answer: Option<String>,
+    /// Local tracks, by id.
+    tracks: HashMap<String, SendTrack>,
+    /// Where packets for each remote track go.
+    remote_tracks: HashMap<Mid, mpsc::UnboundedSender<RtpPacket>>,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Drop for Driver {
+    fn drop(&mut self) {
+        for reader in &self.readers {
+            reader.abort();
+        }
+    }
+}
+
+impl Driver {
+    async fn run(
+        mut self,
+        mut commands: mpsc::UnboundedReceiver<Command>,
+        mut datagrams: mpsc::Receiver<Datagram>,
+    ) {
+        while let Some(timeout) = self.drain() {
+            tokio::select! {
+                command = commands.recv() => match command {
+                    Some(command) => command(&mut self),
+                    None => break,
+                },
+                Some(datagram) = datagrams.recv() => self.receive(datagram),
+                _ = tokio::time::sleep_until(timeout.into()) => {
+                    if let Err(err) = self.rtc.handle_input(Input::Timeout(Instant::now())) {
+                        info!("str0m timeout failed: {}", err);
+                    }
+                }
+            }
+        }
+        self.set_state(ConnectionState::Closed);
+    }
+
+    /// Sends and handles everything the `Rtc` has for us, returning when
+    /// it next wants time to pass; `None` once it is gone.
+    fn drain(&mut self) -> Option<Instant> {
+        loop {
+            if !self.rtc.is_alive() {
+                return None;
+            }
+            match self.rtc.poll_output() {
+                Ok(Output::Timeout(at)) => return Some(at),
+                Ok(Output::Transmit(transmit)) => {
+                    let socket = self
+                        .sockets
+                        .iter()
+                        .find(|socket| socket.local_addr().ok() == Some(transmit.source));
+                    if let Some(socket) = socket {
+                        // UDP may drop anything; a full buffer is no different.
+                        let _ = socket.try_send_to(&transmit.contents, transmit.destination);
+                    }
+                }
+                Ok(Output::Event(event)) => self.event(event),
+                Err(err) => {
+                    warn!("str0m failed: {}", err);
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn receive(&mut self, datagram: Datagram) {
+        let Ok(receive) = Receive::new(
+            Protocol::Udp,
+            datagram.source,
+            datagram.destination,
+            &datagram.contents,
+        ) else {
+            return;
+        };
+        if let Err(err) = self
+            .rtc
+            .handle_input(Input::Receive(Instant::now(), receive))
+        {
+            info!("str0m rejected a datagram: {}", err);
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::IceConnectionStateChange(state) => {
+                let state = match state {
+                    IceConnectionState::New => ConnectionState::New,
+                    IceConnectionState::Checking => ConnectionState::Connecting,
+                    // `Event::Connected` follows once DTLS is up too.
+                    IceConnectionState::Connected | IceConnectionState::Completed => {
+                        if self.rtc.is_connected() {
+                            ConnectionState::Connected
+                        } else {
+                            ConnectionState::Connecting
+                        }
+                    }
+                    IceConnectionState::Disconnected => ConnectionState::Disconnected,
+                };
+                self.set_state(state);
+            }
+            Event::Connected => self.set_state(ConnectionState::Connected),
+            Event::RtpPacket(packet) => {
+                let ssrc = packet.header.ssrc;
+                let Some(mid) = self.rtc.direct_api().stream_rx(&ssrc).map(|rx| rx.mid()) else {
+                    return;
+                };
+                let converted = RtpPacket {
+                    payload_type: *packet.header.payload_type,
+                    sequence_number: packet.header.sequence_number,
+                    timestamp: packet.header.timestamp,
+                    ssrc: *ssrc,
+                    marker: packet.header.marker,
+                    payload: Bytes::copy_from_slice(&packet.payload),
+                };
+                if let Some(packets) = self.remote_tracks.get(&mid) {
+                    let _ = packets.send(converted);
+                    return;
+                }
+                // Like webrtc-rs, a remote track starts with its first packet.
+                let Some(kind) = self.rtc.media(mid).map(|media| media_kind(media.kind())) else {
+                    return;
+                };
+                let mime_type = self
+                    .rtc
+                    .codec_config()
+                    .find(|params| params.pt() == packet.header.payload_type)
+                    .map_or_else(String::new, |params| {
+                        format!("{}/{}", kind, params.spec().codec)
+                    });
+                let (packets_tx, packets) = mpsc::unbounded_channel();
+                let _ = packets_tx.send(converted);
+                self.remote_tracks.insert(mid, packets_tx);
+                let track = Arc::new(RemoteTrack {
+                    kind,
+                    mime_type,
+                    packets: tokio::sync::Mutex::new(packets),
+                });
+                let started = self
+                    .shared
+                    .lock()
+                    .unwrap()
+                    .on_track
+                    .as_mut()
+                    .map(|handler| handler(track));
+                if let Some(started) = started {
+                    tokio::spawn(started);
+                }
+            }
+            Event::Closed => self.set_state(ConnectionState::Closed),
+            _ => {}
+        }
+    }
+
+    fn set_state(&self, state: ConnectionState) {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.state == Some(state) {
+            return;
+        }
+        shared.state = Some(state);
+        if let Some(handler) = shared.on_state_change.as_mut() {
+            tokio::spawn(handler(state));
+        }
+    }
+
+    fn write(&mut self, id: &str, data: Bytes, duration: Duration) -> Result<(), RtcError> {
+        let track = self
+            .tracks
+            .get_mut(id)
+            .ok_or_else(|| RtcError::UnknownTrack(id.to_owned()))?;
+        // Not negotiated yet, or no longer sending; webrtc-rs drops these too.
+        let Some(mid) = track.mid else {
+            return Ok(());
+        };
+        let Some(pt) = self
+            .rtc
+            .codec_config()
+            .find(|params| params.spec().codec == track.codec)
+            .map(|params| params.pt())
+        else {
+            return Ok(());
+        };
+        let payloads = track.payloader.payload(MTU, &data).map_err(backend)?;
+        let first = track.sequence_number;
+        let timestamp = track.timestamp;
+        let nackable = track.kind == MediaKind::Video;
+        track.sequence_number += payloads.len() as u64;
+        let ticks = duration.as_secs_f64() * f64::from(track.clock_rate);
+        track.timestamp = timestamp.wrapping_add(ticks.round() as u32);
+
+        let now = Instant::now();
+        let last = payloads.len().saturating_sub(1);
+        for (index, payload) in payloads.into_iter().enumerate() {
+            let mut api = self.rtc.direct_api();
+            let Some(stream) = api.stream_tx_by_mid(mid, None) else {
+                return Ok(());
+            };
+            let sequence_number = first + index as u64;
+            stream.write_rtp(
+                RtpWrite::new(pt, sequence_number.into(), timestamp, now, payload.to_vec())
+                    .marker(index == last)
+                    .nackable(nackable),
+            );
+            // Every write has to be followed by a full drain.
+            self.drain();
+        }
+        Ok(())
+    }
+
+    fn create_offer(&mut self) -> Result<SessionDescription, RtcError> {
+        let mut change = self.rtc.sdp_api();
+        for pending in self.changes.drain(..) {
+            match pending {
+                Change::Add {
+                    kind,
+                    direction,
+                    stream_id,
+                    track,
+                } => {
+                    let mid = change.add_media(
+                        kind.into(),
+                        direction.into(),
+                        stream_id,
+                        track.clone(),
+                        None,
+                    );
+                    if let Some(track) = track.and_then(|id| self.tracks.get_mut(&id)) {
+                        track.mid = Some(mid);
+                    }
+                }
+                Change::Direction(mid, direction) => change.set_direction(mid, direction.into()),
+            }
+        }
+        let (offer, pending) = change
+            .apply()
+            .ok_or_else(|| backend("nothing has changed to offer"))?;
+        self.pending_offer = Some(pending);
+        Ok(SessionDescription::offer(offer.to_sdp_string()))
+    }
+
+    fn set_remote_description(&mut self, description: &SessionDescription) -> Result<(), RtcError> {
+        match description.kind {
+            SdpKind::Answer => {
+                let pending = self
+                    .pending_offer
+                    .take()
+                    .ok_or_else(|| backend("no offer is waiting for an answer"))?;
+                let answer = SdpAnswer::from_sdp_string(&description.sdp).map_err(backend)?;
+                self.rtc.sdp_api().accept_answer(pending, answer)?;
+            }
+            SdpKind::Offer => {
+                let offer = SdpOffer::from_sdp_string(&description.sdp).map_err(backend)?;
+                let offered = offered_media(&description.sdp);
+                let answer = self.rtc.sdp_api().accept_offer(offer)?;
+                self.answer = Some(answer.to_sdp_string());
+                self.place_tracks(&offered);
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts local tracks added before a remote offer into its media
+    /// sections of their kind that we may send on.
+    fn place_tracks(&mut self, offered: &[(MediaKind, Mid)]) {
+        let mut taken: Vec<Mid> = self.tracks.values().filter_map(|track| track.mid).collect();
+        for track in self.tracks.values_mut().filter(|track| track.mid.is_none()) {
+            let free = offered.iter().find(|(kind, mid)| {
+                *kind == track.kind
+                    && !taken.contains(mid)
+                    && self
+                        .rtc
+                        .media(*mid)
+                        .is_some_and(|media| media.direction().is_sending())
+            });
+            if let Some((_, mid)) = free {
+                track.mid = Some(*mid);
+                taken.push(*mid);
+            }
+        }
+    }
+
+    fn remove_track(&mut self, id: &str) -> Result<(), RtcError> {
+        let track = self
+            .tracks
+            .remove(id)
+            .ok_or_else(|| RtcError::UnknownTrack(id.to_owned()))?;
+        match track.mid {
+            Some(mid) => {
+                let receiving = self
+                    .rtc
+                    .media(mid)
+                    .is_some_and(|media| media.direction().is_receiving());
+                let direction = if receiving {
+                    Direction::RecvOnly
+                } else {
+                    Direction::Inactive
+                };
+                self.changes.push(Change::Direction(mid, direction));
+            }
+            None => self.changes.retain(
+                |change| !matches!(change, Change::Add { track: Some(track), .. } if track == id),
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// The kind and mid of each media section of `sdp`, in order.
+fn offered_media(sdp: &str) -> Vec<(MediaKind, Mid)> {
+    let mut media = Vec::new();
+    let mut kind = None;
+    for line in sdp.lines() {
+        if let Some(section) = line.strip_prefix("m=") {
+            kind = match section.split(' ').next() {
+                Some("audio") => Some(MediaKind::Audio),
+                Some("video") => Some(MediaKind::Video),
+                _ => None,
+            };
+        } else if let (Some(mid), Some(kind)) = (line.strip_prefix("a=mid:"), kind) {
+            media.push((kind, Mid::from(mid.trim())));
+        }
+    }
+    media
+}
+
+pub struct LocalTrack {
+    id: String,
+    kind: MediaKind,
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl TrackHandle for LocalTrack {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn kind(&self) -> MediaKind {
+        self.kind
+    }
+
+    fn write_sample(&self, data: Bytes, duration: Duration) -> RtcFuture<'_, ()> {
+        let id = self.id.clone();
+        call(&self.commands, move |driver| {
+            driver.write(&id, data, duration)
+        })
+        .boxed()
+    }
+}
+
+pub struct RemoteTrack {
+    kind: MediaKind,
+    mime_type: String,
+    packets: tokio::sync::Mutex<mpsc::UnboundedReceiver<RtpPacket>>,
+}
+
+impl RemoteTrackHandle for RemoteTrack {
+    fn kind(&self) -> MediaKind {
+        self.kind
+    }
+
+    fn mime_type(&self) -> String {
+        self.mime_type.clone()
+    }
+
+    fn read_packet(&self) -> RtcFuture<'_, RtpPacket> {
+        async move {
+            self.packets
+                .lock()
+                .await
+                .recv()
+                .await
+                .ok_or(RtcError::Closed)
+        }
+        .boxed()
+    }
+}
+
+pub struct Str0mPeerConnection {
+    commands: mpsc::UnboundedSender<Command>,
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// The addresses to offer host candidates on: every usable IPv4 address of
+/// the machine, or loopback when it has nothing else.
+fn host_addresses() -> Vec<IpAddr> {
+    let mut addresses: Vec<IpAddr> = webrtc::util::ifaces::ifaces()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|interface| interface.addr.map(|addr| addr.ip()))
+        .filter(|ip| ip.is_ipv4() && !ip.is_loopback() && !ip.is_unspecified())
+        .collect();
+    addresses.sort();
+    addresses.dedup();
+    if addresses.is_empty() {
+        addresses.push(IpAddr::from([127, 0, 0, 1]));
+    }
+    addresses
+}
+
+/// Opens a peer connection with str0m's default codecs, offering a host
+/// candidate on each local address. `settings` only matter in what this
+/// backend cannot do: there is no relay to use, so relay-only fails.
+pub async fn open(settings: &Settings) -> Result<Arc<Str0mPeerConnection>, RtcError> {
+    if settings.privacy.relay_only {
+        return Err(backend("the str0m backend cannot use TURN relays"));
+    }
+    if !settings.network.stun_servers.is_empty() || !settings.network.turn_servers.is_empty() {
+        info!("The str0m backend offers host candidates only; STUN and TURN servers are unused");
+    }
+
+    let mut rtc = Rtc::builder().set_rtp_mode(true).build(Instant::now());
+    let (datagrams_tx, datagrams) = mpsc::channel(DATAGRAM_QUEUE);
+    let mut sockets = Vec::new();
+    let mut readers = Vec::new();
+    for ip in host_addresses() {
+        let socket = match UdpSocket::bind(SocketAddr::new(ip, 0)).await {
+            Ok(socket) => Arc::new(socket),
+            Err(err) => {
+                info!("Not offering {}: {}", ip, err);
+                continue;
+            }
+        };
+        let local = socket.local_addr().map_err(backend)?;
+        let candidate = Candidate::host(local, "udp").map_err(backend)?;
+        rtc.add_local_candidate(candidate);
+        let reader = Arc::clone(&socket);
+        let datagrams_tx = datagrams_tx.clone();
+        readers.push(tokio::spawn(async move {
+            let mut buf = vec![0; 2000];
+            while let Ok((len, source)) = reader.recv_from(&mut buf).await {
+                let datagram = Datagram {
+                    source,
+                    destination: local,
+                    contents: buf[..len].to_vec(),
+                };
+                if datagrams_tx.send(datagram).await.is_err() {
+                    return;
+                }
+            }
+        }));
+        sockets.push(socket);
+    }
+    if sockets.is_empty() {
+        return Err(backend("no local address to offer"));
+    }
+
+    let shared = Arc::new(Mutex::new(Shared::default()));
+    let (commands, commands_rx) = mpsc::unbounded_channel();
+    let driver = Driver {
+        rtc,
+        sockets,
+        readers,
+        changes: vec![],
+        pending_offer: None,
+        answer: None,
+        tracks: HashMap::new(),
+        remote_tracks: HashMap::new(),
+        shared: Arc::clone(&shared),
+    };
+    tokio::spawn(driver.run(commands_rx, datagrams));
+    Ok(Arc::new(Str0mPeerConnection { commands, shared }))
+}
+
+impl PeerConnectionHandle for Str0mPeerConnection {
+    fn add_transceiver(&self, kind: MediaKind, direction: Direction) -> RtcFuture<'_, ()> {
+        call(&self.commands, move |driver| {
+            driver.changes.push(Change::Add {
+                kind,
+                direction,
+                stream_id: None,
+                track: None,
+            });
+            Ok(())
+        })
+        .boxed()
+    }
+
+    fn add_track(
+        &self,
+        kind: MediaKind,
+        codec: TrackCodec,
+        stream_id: &str,
+    ) -> RtcFuture<'_, Arc<dyn TrackHandle>> {
+        let stream_id = stream_id.to_owned();
+        let commands = self.commands.clone();
+        async move {
+            let id = call(&self.commands, move |driver| {
+                let id = (1..)
+                    .map(|n| match n {
+                        1 => kind.to_string(),
+                        n => format!("{}-{}", kind, n),
+                    })
+                    .find(|id| !driver.tracks.contains_key(id))
+                    .unwrap();
+                let str0m_codec = self::codec(&codec.mime_type);
+                let payloader = payloader(str0m_codec)
+                    .ok_or_else(|| backend(format!("cannot send {}", codec.mime_type)))?;
+                driver.changes.push(Change::Add {
+                    kind,
+                    direction: Direction::SendRecv,
+                    stream_id: Some(stream_id),
+                    track: Some(id.clone()),
+                });
+                driver.tracks.insert(
+                    id.clone(),
+                    SendTrack {
+                        kind,
+                        codec: str0m_codec,
+                        clock_rate: codec.clock_rate,
+                        mid: None,
+                        payloader,
+                        sequence_number: u64::from(rand::random::<u16>()),
+                        timestamp: rand::random(),
+                    },
+                );
+                Ok(id)
+            })
+            .await?;
+            Ok(Arc::new(LocalTrack { id, kind, commands }) as Arc<dyn TrackHandle>)
+        }
+        .boxed()
+    }
+
+    fn remove_track<'a>(&'a self, track: &'a dyn TrackHandle) -> RtcFuture<'a, ()> {
+        let id = track.id().to_owned();
+        call(&self.commands, move |driver| driver.remove_track(&id)).boxed()
+    }
+
+    fn create_offer(&self) -> RtcFuture<'_, SessionDescription> {
+        call(&self.commands, Driver::create_offer).boxed()
+    }
+
+    fn create_answer(&self) -> RtcFuture<'_, SessionDescription> {
+        call(&self.commands, |driver| {
+            driver
+                .answer
+                .clone()
+                .map(SessionDescription::answer)
+                .ok_or_else(|| backend("there is no remote offer to answer"))
+        })
+        .boxed()
+    }
+
+    /// str0m makes its side of the negotiation current as it writes it, so
+    /// this only records it.
+    fn set_local_description(&self, description: SessionDescription) -> RtcFuture<'_, ()> {
+        async move {
+            let gathered = {
+                let mut shared = self.shared.lock().unwrap();
+                shared.local = Some(description);
+                // The candidates are in the description already.
+                shared
+                    .on_ice_candidate
+                    .as_mut()
+                    .map(|handler| handler(None))
+            };
+            if let Some(gathered) = gathered {
+                gathered.await;
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn set_local_description_gathered(
+        &self,
+        description: SessionDescription,
+    ) -> RtcFuture<'_, SessionDescription> {
+        async move {
+            self.set_local_description(description.clone()).await?;
+            Ok(description)
+        }
+        .boxed()
+    }
+
+    fn set_remote_description(&self, description: SessionDescription) -> RtcFuture<'_, ()> {
+        async move {
+            let applied = description.clone();
+            call(&self.commands, move |driver| {
+                driver.set_remote_description(&applied)
+            })
+            .await?;
+            self.shared.lock().unwrap().remote = Some(description);
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn local_description(&self) -> BoxFuture<'_, Option<SessionDescription>> {
+        let local = self.shared.lock().unwrap().local.clone();
+        async move { local }.boxed()
+    }
+
+    fn remote_description(&self) -> BoxFuture<'_, Option<SessionDescription>> {
+        let remote = self.shared.lock().unwrap().remote.clone();
+        async move { remote }.boxed()
+    }
+
+    fn add_ice_candidate(&self, candidate: IceCandidate) -> RtcFuture<'_, ()> {
+        call(&self.commands, move |driver| {
+            let candidate = Candidate::from_sdp_string(&candidate.candidate).map_err(backend)?;
+            driver.rtc.add_remote_candidate(candidate);
+            Ok(())
+        })
+        .boxed()
+    }
+
+    fn connection_state(&self) -> ConnectionState {
+        if self.commands.is_closed() {
+            return ConnectionState::Closed;
+        }
+        self.shared
+            .lock()
+            .unwrap()
+            .state
+            .unwrap_or(ConnectionState::New)
+    }
+
+    fn on_connection_state_change(&self, handler: OnStateChange) {
+        self.shared.lock().unwrap().on_state_change = Some(handler);
+    }
+
+    fn on_track(&self, handler: OnTrack) {
+        self.shared.lock().unwrap().on_track = Some(handler);
+    }
+
+    fn on_ice_candidate(&self, handler: OnIceCandidate) {
+        self.shared.lock().unwrap().on_ice_candidate = Some(handler);
+    }
+
+    fn close(&self) -> RtcFuture<'_, ()> {
+        async move {
+            // Already closed is as good as closing.
+            let _ = call(&self.commands, |driver| {
+                driver.rtc.disconnect();
+                Ok(())
+            })
+            .await;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::timeout;
+    use webrtc::api::media_engine::MediaEngine;
+
+    use super::*;
+    use crate::rtc::webrtc_rs;
+
+    fn opus() -> TrackCodec {
+        TrackCodec {
+            mime_type: "audio/opus".to_owned(),
+            clock_rate: 48000,
+            channels: 2,
+        }
+    }
+
+    /// Writes `payload` on `track` until something arrives on `received`.
+    async fn first_arrival(
+        track: &dyn TrackHandle,
+        payload: &Bytes,
+        received: &mut mpsc::UnboundedReceiver<(MediaKind, String, Bytes)>,
+    ) -> Option<(MediaKind, String, Bytes)> {
+        timeout(Duration::from_secs(10), async {
+            loop {
+                let _ = track
+                    .write_sample(payload.clone(), Duration::from_millis(20))
+                    .await;
+                tokio::select! {
+                    arrived = received.recv() => break arrived,
+                    _ = tokio::time::sleep(Duration::from_millis(20)) => {}
+                }
+            }
+        })
+        .await
+        .expect("no media arrived")
+    }
+
+    fn forward_tracks(
+        pc: &dyn PeerConnectionHandle,
+    ) -> mpsc::UnboundedReceiver<(MediaKind, String, Bytes)> {
+        let (received_tx, received) = mpsc::unbounded_channel();
+        pc.on_track(Box::new(move |remote| {
+            let received_tx = received_tx.clone();
+            Box::pin(async move {
+                if let Ok(packet) = remote.read_packet().await {
+                    let _ = received_tx.send((
+                        remote.kind(),
+                        remote.mime_type().to_lowercase(),
+                        packet.payload,
+                    ));
+                }
+            })
+        }));
+        received
+    }
+
+    #[tokio::test]
+    async fn talks_to_webrtc_rs_both_ways() {
+        let mut settings = Settings::default();
+        settings.network.stun_servers.clear();
+        let ours: Arc<dyn PeerConnectionHandle> = open(&settings).await.unwrap();
+        let mut media_engine = MediaEngine::default();
+        media_engine.register_default_codecs().unwrap();
+        let theirs: Arc<dyn PeerConnectionHandle> =
+            webrtc_rs::open(&settings, media_engine).await.unwrap();
+
+        let our_track = ours
+            .add_track(MediaKind::Audio, opus(), "ours")
+            .await
+            .unwrap();
+        let their_track = theirs
+            .add_track(MediaKind::Audio, opus(), "theirs")
+            .await
+            .unwrap();
+        let mut we_received = forward_tracks(ours.as_ref());
+        let mut they_received = forward_tracks(theirs.as_ref());
+
+        let offer = ours.create_offer().await.unwrap();
+        let offer = ours.set_local_description_gathered(offer).await.unwrap();
+        theirs.set_remote_description(offer).await.unwrap();
+        let answer = theirs.create_answer().await.unwrap();
+        let answer = theirs.set_local_description_gathered(answer).await.unwrap();
+        ours.set_remote_description(answer).await.unwrap();
+
+        let payload = Bytes::from_static(&[0xf8, 0xff, 0xfe]);
+        let expected = Some((MediaKind::Audio, "audio/opus".to_owned(), payload.clone()));
+        assert_eq!(
+            first_arrival(our_track.as_ref(), &payload, &mut they_received).await,
+            expected
+        );
+        assert_eq!(
+            first_arrival(their_track.as_ref(), &payload, &mut we_received).await,
+            expected
+        );
+        assert_eq!(ours.connection_state(), ConnectionState::Connected);
+
+        ours.close().await.unwrap();
+        let _ = theirs.close().await;
+        timeout(Duration::from_secs(5), async {
+            while ours.connection_state() != ConnectionState::Closed {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("str0m did not close");
+        assert!(matches!(ours.create_offer().await, Err(RtcError::Closed)));
+    }
+}
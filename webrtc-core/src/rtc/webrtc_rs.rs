@@ -0,0 +1,490 @@
+//! The handles on webrtc-rs 0.11.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use webrtc::api::media_engine::MediaEngine;
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+use webrtc::media::Sample;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::sdp_type::RTCSdpType;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp::header::Header;
+use webrtc::rtp::packet::Packet;
+use webrtc::rtp_transceiver::rtp_codec::{RTCRtpCodecCapability, RTPCodecType};
+use webrtc::rtp_transceiver::rtp_sender::RTCRtpSender;
+use webrtc::rtp_transceiver::rtp_transceiver_direction::RTCRtpTransceiverDirection;
+use webrtc::rtp_transceiver::RTCRtpTransceiverInit;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+use webrtc::track::track_local::TrackLocal;
+use webrtc::track::track_remote::TrackRemote;
+
+use super::{
+    ConnectionState, Direction, IceCandidate, MediaKind, OnIceCandidate, OnStateChange, OnTrack,
+    PeerConnectionHandle, RemoteTrackHandle, RtcError, RtcFuture, RtpPacket, SdpKind,
+    SessionDescription, TrackCodec, TrackHandle,
+};
+use crate::settings::Settings;
+
+impl From<webrtc::Error> for RtcError {
+    fn from(err: webrtc::Error) -> Self {
+        match err {
+            webrtc::Error::ErrConnectionClosed => RtcError::Closed,
+            other => RtcError::Backend(other.to_string()),
+        }
+    }
+}
+
+fn to_rtc_description(description: SessionDescription) -> Result<RTCSessionDescription, RtcError> {
+    Ok(match description.kind {
+        SdpKind::Offer => RTCSessionDescription::offer(description.sdp)?,
+        SdpKind::Answer => RTCSessionDescription::answer(description.sdp)?,
+    })
+}
+
+fn from_rtc_description(description: RTCSessionDescription) -> Option<SessionDescription> {
+    let kind = match description.sdp_type {
+        RTCSdpType::Offer => SdpKind::Offer,
+        RTCSdpType::Answer | RTCSdpType::Pranswer => SdpKind::Answer,
+        RTCSdpType::Rollback | RTCSdpType::Unspecified => return None,
+    };
+    Some(SessionDescription {
+        kind,
+        sdp: description.sdp,
+    })
+}
+
+fn from_rtc_state(state: RTCPeerConnectionState) -> ConnectionState {
+    match state {
+        RTCPeerConnectionState::Unspecified | RTCPeerConnectionState::New => ConnectionState::New,
+        RTCPeerConnectionState::Connecting => ConnectionState::Connecting,
+        RTCPeerConnectionState::Connected => ConnectionState::Connected,
+        RTCPeerConnectionState::Disconnected => ConnectionState::Disconnected,
+        RTCPeerConnectionState::Failed => ConnectionState::Failed,
+        RTCPeerConnectionState::Closed => ConnectionState::Closed,
+    }
+}
+
+impl From<MediaKind> for RTPCodecType {
+    fn from(kind: MediaKind) -> Self {
+        match kind {
+            MediaKind::Audio => RTPCodecType::Audio,
+            MediaKind::Video => RTPCodecType::Video,
+        }
+    }
+}
+
+/// `None` for webrtc-rs's `Unspecified`.
+pub fn media_kind(kind: RTPCodecType) -> Option<MediaKind> {
+    match kind {
+        RTPCodecType::Audio => Some(MediaKind::Audio),
+        RTPCodecType::Video => Some(MediaKind::Video),
+        RTPCodecType::Unspecified => None,
+    }
+}
+
+impl From<Direction> for RTCRtpTransceiverDirection {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::SendRecv => RTCRtpTransceiverDirection::Sendrecv,
+            Direction::SendOnly => RTCRtpTransceiverDirection::Sendonly,
+            Direction::RecvOnly => RTCRtpTransceiverDirection::Recvonly,
+            Direction::Inactive => RTCRtpTransceiverDirection::Inactive,
+        }
+    }
+}
+
+impl From<RtpPacket> for Packet {
+    fn from(packet: RtpPacket) -> Self {
+        Packet {
+            header: Header {
+                version: 2,
+                marker: packet.marker,
+                payload_type: packet.payload_type,
+                sequence_number: packet.sequence_number,
+                timestamp: packet.timestamp,
+                ssrc: packet.ssrc,
+                ..Default::default()
+            },
+            payload: packet.payload,
+        }
+    }
+}
+
+impl From<Packet> for RtpPacket {
+    fn from(packet: Packet) -> Self {
+        RtpPacket {
+            payload_type: packet.header.payload_type,
+            sequence_number: packet.header.sequence_number,
+            timestamp: packet.header.timestamp,
+            ssrc: packet.header.ssrc,
+            marker: packet.header.marker,
+            payload: packet.payload,
+        }
+    }
+}
+
+pub struct LocalTrack(Arc<TrackLocalStaticSample>);
+
+impl LocalTrack {
+    pub fn new(track: Arc<TrackLocalStaticSample>) -> Self {
+        Self(track)
+    }
+
+    pub fn inner(&self) -> &Arc<TrackLocalStaticSample> {
+        &self.0
+    }
+}
+
+impl TrackHandle for LocalTrack {
+    fn id(&self) -> &str {
+        self.0.id()
+    }
+
+    fn kind(&self) -> MediaKind {
+        media_kind(self.0.kind()).unwrap_or(MediaKind::Video)
+    }
+
+    fn write_sample(&self, data: Bytes, duration: Duration) -> RtcFuture<'_, ()> {
+        async move {
+            let sample = Sample {
+                data,
+                duration,
+                ..Default::default()
+            };
+            Ok(self.0.write_sample(&sample).await?)
+        }
+        .boxed()
+    }
+}
+
+pub struct RemoteTrack(Arc<TrackRemote>);
+
+impl RemoteTrack {
+    pub fn new(track: Arc<TrackRemote>) -> Self {
+        Self(track)
+    }
+}
+
+impl RemoteTrackHandle for RemoteTrack {
+    fn kind(&self) -> MediaKind {
+        media_kind(self.0.kind()).unwrap_or(MediaKind::Video)
+    }
+
+    fn mime_type(&self) -> String {
+        self.0.codec().capability.mime_type
+    }
+
+    fn read_packet(&self) -> RtcFuture<'_, RtpPacket> {
+        async move {
+            let (packet, _) = self.0.read_rtp().await?;
+            Ok(packet.into())
+        }
+        .boxed()
+    }
+}
+
+pub struct WebRtcRsPeerConnection {
+    pc: Arc<RTCPeerConnection>,
+    /// The senders of tracks added through `add_track`, by track id.
+    senders: Mutex<HashMap<String, Arc<RTCRtpSender>>>,
+}
+
+/// Opens a peer connection configured by `settings`, with the codecs in
+/// `media_engine`.
+pub async fn open(
+    settings: &Settings,
+    media_engine: MediaEngine,
+) -> Result<Arc<WebRtcRsPeerConnection>, RtcError> {
+    let api = settings.api_with(media_engine);
+    let pc = api
+        .new_peer_connection(settings.rtc_configuration(false))
+        .await?;
+    Ok(Arc::new(WebRtcRsPeerConnection {
+        pc: Arc::new(pc),
+        senders: Mutex::default(),
+    }))
+}
+
+impl WebRtcRsPeerConnection {
+    /// The webrtc-rs peer connection, for what the handle does not cover.
+    pub fn inner(&self) -> &Arc<RTCPeerConnection> {
+        &self.pc
+    }
+}
+
+impl PeerConnectionHandle for WebRtcRsPeerConnection {
+    fn add_transceiver(&self, kind: MediaKind, direction: Direction) -> RtcFuture<'_, ()> {
+        async move {
+            let init = RTCRtpTransceiverInit {
+                direction: direction.into(),
+                send_encodings: vec![],
+            };
+            self.pc
+                .add_transceiver_from_kind(kind.into(), Some(init))
+                .await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn add_track(
+        &self,
+        kind: MediaKind,
+        codec: TrackCodec,
+        stream_id: &str,
+    ) -> RtcFuture<'_, Arc<dyn TrackHandle>> {
+        let stream_id = stream_id.to_owned();
+        async move {
+            let id = {
+                let senders = self.senders.lock().unwrap();
+                (1..)
+                    .map(|n| match n {
+                        1 => kind.to_string(),
+                        n => format!("{}-{}", kind, n),
+                    })
+                    .find(|id| !senders.contains_key(id))
+                    .unwrap()
+            };
+            let track = Arc::new(TrackLocalStaticSample::new(
+                RTCRtpCodecCapability {
+                    mime_type: codec.mime_type,
+                    clock_rate: codec.clock_rate,
+                    channels: codec.channels,
+                    ..Default::default()
+                },
+                id.clone(),
+                stream_id,
+            ));
+            let sender = self.pc.add_track(track.clone()).await?;
+            self.senders.lock().unwrap().insert(id, sender);
+            Ok(Arc::new(LocalTrack(track)) as Arc<dyn TrackHandle>)
+        }
+        .boxed()
+    }
+
+    fn remove_track<'a>(&'a self, track: &'a dyn TrackHandle) -> RtcFuture<'a, ()> {
+        async move {
+            let sender = self.senders.lock().unwrap().remove(track.id());
+            let sender = sender.ok_or_else(|| RtcError::UnknownTrack(track.id().to_owned()))?;
+            Ok(self.pc.remove_track(&sender).await?)
+        }
+        .boxed()
+    }
+
+    fn create_offer(&self) -> RtcFuture<'_, SessionDescription> {
+        async move {
+            let offer = self.pc.create_offer(None).await?;
+            Ok(SessionDescription::offer(offer.sdp))
+        }
+        .boxed()
+    }
+
+    fn create_answer(&self) -> RtcFuture<'_, SessionDescription> {
+        async move {
+            let answer = self.pc.create_answer(None).await?;
+            Ok(SessionDescription::answer(answer.sdp))
+        }
+        .boxed()
+    }
+
+    fn set_local_description(&self, description: SessionDescription) -> RtcFuture<'_, ()> {
+        async move {
+            let description = to_rtc_description(description)?;
+            Ok(self.pc.set_local_description(description).await?)
+        }
+        .boxed()
+    }
+
+    fn set_local_description_gathered(
+        &self,
+        description: SessionDescription,
+    ) -> RtcFuture<'_, SessionDescription> {
+        async move {
+            let description = to_rtc_description(description)?;
+            let mut gathered = self.pc.gathering_complete_promise().await;
+            self.pc.set_local_description(description).await?;
+            let _ = gathered.recv().await;
+            self.pc
+                .local_description()
+                .await
+                .and_then(from_rtc_description)
+                .ok_or(RtcError::Closed)
+        }
+        .boxed()
+    }
+
+    fn set_remote_description(&self, description: SessionDescription) -> RtcFuture<'_, ()> {
+        async move {
+            let description = to_rtc_description(description)?;
+            Ok(self.pc.set_remote_description(description).await?)
+        }
+        .boxed()
+    }
+
+    fn local_description(&self) -> BoxFuture<'_, Option<SessionDescription>> {
+        async move {
+            self.pc
+                .local_description()
+                .await
+                .and_then(from_rtc_description)
+        }
+        .boxed()
+    }
+
+    fn remote_description(&self) -> BoxFuture<'_, Option<SessionDescription>> {
+        async move {
+            self.pc
+                .remote_description()
+                .await
+                .and_then(from_rtc_description)
+        }
+        .boxed()
+    }
+
+    fn add_ice_candidate(&self, candidate: IceCandidate) -> RtcFuture<'_, ()> {
+        async move {
+            let init = RTCIceCandidateInit {
+                candidate: candidate.candidate,
+                sdp_mid: candidate.sdp_mid,
+                sdp_mline_index: candidate.sdp_mline_index,
+                username_fragment: None,
+            };
+            Ok(self.pc.add_ice_candidate(init).await?)
+        }
+        .boxed()
+    }
+
+    fn connection_state(&self) -> ConnectionState {
+        from_rtc_state(self.pc.connection_state())
+    }
+
+    fn on_connection_state_change(&self, mut handler: OnStateChange) {
+        self.pc
+            .on_peer_connection_state_change(Box::new(move |state| handler(from_rtc_state(state))));
+    }
+
+    fn on_track(&self, mut handler: OnTrack) {
+        self.pc.on_track(Box::new(move |track, _, _| {
+            handler(Arc::new(RemoteTrack(track)))
+        }));
+    }
+
+    fn on_ice_candidate(&self, mut handler: OnIceCandidate) {
+        self.pc.on_ice_candidate(Box::new(move |candidate| {
+            let candidate = match candidate.map(|c| c.to_json()) {
+                None => None,
+                Some(Ok(init)) => Some(IceCandidate {
+                    candidate: init.candidate,
+                    sdp_mid: init.sdp_mid,
+                    sdp_mline_index: init.sdp_mline_index,
+                }),
+                // Nothing to signal for a candidate that cannot be written.
+                Some(Err(_)) => return Box::pin(async {}),
+            };
+            handler(candidate)
+        }));
+    }
+
+    fn close(&self) -> RtcFuture<'_, ()> {
+        async move { Ok(self.pc.close().await?) }.boxed()
+    }
+}
+
+/// Peer connections for tests to connect to each other over loopback.
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+
+    /// A peer connection with the default codecs and no STUN servers.
+    pub(crate) async fn connection() -> Arc<WebRtcRsPeerConnection> {
+        let mut settings = Settings::default();
+        settings.network.stun_servers.clear();
+        let mut media_engine = MediaEngine::default();
+        media_engine.register_default_codecs().unwrap();
+        open(&settings, media_engine).await.unwrap()
+    }
+
+    /// Has `offerer` and `answerer` agree on what each added so far, with
+    /// the candidates in the descriptions.
+    pub(crate) async fn negotiate(
+        offerer: &dyn PeerConnectionHandle,
+        answerer: &dyn PeerConnectionHandle,
+    ) {
+        let offer = offerer.create_offer().await.unwrap();
+        let offer = offerer.set_local_description_gathered(offer).await.unwrap();
+        answerer.set_remote_description(offer).await.unwrap();
+        let answer = answerer.create_answer().await.unwrap();
+        let answer = answerer
+            .set_local_description_gathered(answer)
+            .await
+            .unwrap();
+        assert_eq!(answer.kind, SdpKind::Answer);
+        offerer.set_remote_description(answer).await.unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+    use tokio::time::timeout;
+
+    use super::testing::{connection, negotiate};
+    use super::*;
+
+    #[tokio::test]
+    async fn handles_negotiate_and_carry_media() {
+        let (offerer, answerer) = (connection().await, connection().await);
+        let track = offerer
+            .add_track(
+                MediaKind::Audio,
+                TrackCodec {
+                    mime_type: "audio/opus".to_owned(),
+                    clock_rate: 48000,
+                    channels: 2,
+                },
+                "test",
+            )
+            .await
+            .unwrap();
+        let (received_tx, mut received) = mpsc::unbounded_channel();
+        answerer.on_track(Box::new(move |remote| {
+            let received_tx = received_tx.clone();
+            Box::pin(async move {
+                if let Ok(packet) = remote.read_packet().await {
+                    let _ = received_tx.send((remote.kind(), packet.payload));
+                }
+            })
+        }));
+
+        negotiate(offerer.as_ref(), answerer.as_ref()).await;
+
+        let payload = Bytes::from_static(&[0xf8, 0xff, 0xfe]);
+        let arrived = timeout(Duration::from_secs(10), async {
+            loop {
+                let _ = track
+                    .write_sample(payload.clone(), Duration::from_millis(20))
+                    .await;
+                tokio::select! {
+                    arrived = received.recv() => break arrived,
+                    _ = tokio::time::sleep(Duration::from_millis(20)) => {}
+                }
+            }
+        })
+        .await
+        .expect("no media arrived");
+        assert_eq!(arrived, Some((MediaKind::Audio, payload)));
+
+        offerer.remove_track(track.as_ref()).await.unwrap();
+        assert!(matches!(
+            offerer.remove_track(track.as_ref()).await,
+            Err(RtcError::UnknownTrack(_))
+        ));
+        let _ = tokio::join!(offerer.close(), answerer.close());
+    }
+}
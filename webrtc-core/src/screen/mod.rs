@@ -0,0 +1,329 @@
+//! Sharing a single application window instead of the whole desktop, so
+//! a demo shows nothing the presenter did not pick.
+//!
+//! Windows are found and read through X11, which covers Xorg sessions and
+//! XWayland windows under Wayland; native Wayland windows are not listed.
+//! Where the server has the Composite extension the window's own pixels
+//! are read, so windows overlapping it never leak into the share.
+
+#[cfg(target_os = "linux")]
+mod x11;
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use log::info;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use webrtc::media::Sample;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+
+use crate::bandwidth::{SendTarget, TargetFollower};
+use crate::codec::ScalabilityMode;
+use crate::power::{EncodeLimits, EncodeThrottle};
+use crate::snapshot::Snapshot;
+use crate::video::{self, VideoFrame};
+
+/// Windows are mostly text, where sharpness beats motion.
+pub const SHARE_FRAME_RATE: f32 = 15.0;
+
+/// The id a shared window's track goes out with, which tells the peer it
+/// is the one to point at when controlling it.
+pub const WINDOW_TRACK_ID: &str = "window";
+
+/// The frame rates offered for a share, slowest first.
+pub const SHARE_FRAME_RATES: [f32; 4] = [5.0, 10.0, 15.0, 30.0];
+
+/// How big and how often a window share goes out; it can change while the
+/// share runs.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShareFormat {
+    /// Taller windows are scaled down by a whole factor to fit; `None`
+    /// sends them at their own size.
+    pub max_height: Option<u32>,
+    pub frame_rate: f32,
+}
+
+impl Default for ShareFormat {
+    fn default() -> Self {
+        Self {
+            max_height: None,
+            frame_rate: SHARE_FRAME_RATE,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ScreenError {
+    #[error("window capture is not supported on this platform")]
+    Unsupported,
+    #[error("this build has no video encoder")]
+    NoEncoder,
+    #[error("the window was closed")]
+    Closed,
+    #[error("the window is minimised or hidden")]
+    Hidden,
+    #[error("cannot read {0}")]
+    Format(String),
+    #[error("X11: {0}")]
+    X11(String),
+    #[error("{0}: {1}")]
+    Io(&'static str, #[source] std::io::Error),
+}
+
+/// An open top-level window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl fmt::Display for WindowInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.title.is_empty() {
+            write!(f, "Untitled window {:#x}", self.id)?;
+        } else {
+            f.write_str(&self.title)?;
+        }
+        write!(f, " ({}x{})", self.width, self.height)
+    }
+}
+
+/// Where a window is on the screen, and how big.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowRect {
+    /// The screen position `x` and `y` of the way across and down the
+    /// window, each from 0 to 1 and kept to the window.
+    pub fn point(&self, x: f32, y: f32) -> (i32, i32) {
+        let along = |fraction: f32, size: u32| {
+            (fraction.clamp(0.0, 1.0) * size.saturating_sub(1) as f32).round() as i32
+        };
+        (
+            self.x + along(x, self.width),
+            self.y + along(y, self.height),
+        )
+    }
+}
+
+/// The windows the window manager lists as open, topmost first where it
+/// keeps a stacking order.
+pub fn list_windows() -> Result<Vec<WindowInfo>, ScreenError> {
+    #[cfg(target_os = "linux")]
+    {
+        x11::list_windows()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        Err(ScreenError::Unsupported)
+    }
+}
+
+/// Finds one window on the screen, wherever it has moved, and whether it
+/// has the keyboard.
+pub struct WindowLocator {
+    #[cfg(target_os = "linux")]
+    inner: x11::Locator,
+}
+
+impl WindowLocator {
+    pub fn open(window: &WindowInfo) -> Result<Self, ScreenError> {
+        #[cfg(target_os = "linux")]
+        {
+            Ok(Self {
+                inner: x11::Locator::open(window.id)?,
+            })
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = window;
+            Err(ScreenError::Unsupported)
+        }
+    }
+
+    pub fn rect(&self) -> Result<WindowRect, ScreenError> {
+        #[cfg(target_os = "linux")]
+        {
+            self.inner.rect()
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            Err(ScreenError::Unsupported)
+        }
+    }
+
+    /// Whether keys pressed now go to the window, or to one inside it.
+    pub fn has_focus(&self) -> Result<bool, ScreenError> {
+        #[cfg(target_os = "linux")]
+        {
+            self.inner.has_focus()
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            Err(ScreenError::Unsupported)
+        }
+    }
+
+    /// Asks the window manager to raise the window and give it the
+    /// keyboard.
+    pub fn activate(&self) -> Result<(), ScreenError> {
+        #[cfg(target_os = "linux")]
+        {
+            self.inner.activate()
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            Err(ScreenError::Unsupported)
+        }
+    }
+}
+
+/// One window being read, following it as it is resized.
+pub struct WindowCapture {
+    window: WindowInfo,
+    #[cfg(target_os = "linux")]
+    inner: x11::Capture,
+}
+
+impl WindowCapture {
+    pub fn open(window: &WindowInfo) -> Result<Self, ScreenError> {
+        #[cfg(target_os = "linux")]
+        {
+            Ok(Self {
+                window: window.clone(),
+                inner: x11::Capture::open(window.id)?,
+            })
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = window;
+            Err(ScreenError::Unsupported)
+        }
+    }
+
+    pub fn window(&self) -> &WindowInfo {
+        &self.window
+    }
+
+    /// Reads the window at its current size, less a pixel where the
+    /// encoder needs even dimensions.
+    pub fn next_frame(&mut self) -> Result<VideoFrame, ScreenError> {
+        #[cfg(target_os = "linux")]
+        {
+            self.inner.next_frame()
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            Err(ScreenError::Unsupported)
+        }
+    }
+}
+
+/// Encodes `capture` into `track` in the size and at the frame rate
+/// `format` has at each frame, showing each frame in `preview`, until the
+/// returned task is aborted or the window closes. The encoder follows
+/// size changes, the window's or the format's, with a new keyframe. While
+/// `limits` are set the share slows down to them, but keeps its size:
+/// scaled-down text is worse than a slower share. For the same reason it
+/// takes only the bitrate and frame rate of `target`.
+pub fn spawn_window_share(
+    mut capture: WindowCapture,
+    track: Arc<TrackLocalStaticSample>,
+    preview: Snapshot<Option<VideoFrame>>,
+    limits: Snapshot<Option<EncodeLimits>>,
+    target: Snapshot<Option<SendTarget>>,
+    format: Snapshot<ShareFormat>,
+) -> Result<JoinHandle<()>, ScreenError> {
+    let window = capture.window();
+    let mut encoder = video::encoder_for_track(
+        &track,
+        window.width as usize,
+        window.height as usize,
+        format.get().frame_rate,
+        ScalabilityMode::L1T1,
+    )
+    .ok_or(ScreenError::NoEncoder)?;
+    let (samples_tx, mut samples) = mpsc::channel::<Sample>(2);
+    std::thread::Builder::new()
+        .name("window share".to_owned())
+        .spawn(move || {
+            let mut last = Instant::now();
+            let mut tick = last;
+            let mut hidden = false;
+            let mut throttle = EncodeThrottle::new(limits);
+            let mut follower = TargetFollower::new(target);
+            while !samples_tx.is_closed() {
+                let ShareFormat {
+                    max_height,
+                    frame_rate,
+                } = *format.get();
+                let interval = Duration::from_secs_f32(1.0 / frame_rate.max(1.0));
+                std::thread::sleep(interval.saturating_sub(tick.elapsed()));
+                tick = Instant::now();
+                if !throttle.admit(tick) || !follower.admit(encoder.as_mut()) {
+                    continue;
+                }
+                let frame = match capture.next_frame() {
+                    Ok(frame) => frame,
+                    // Skipped frames only stretch the last one; the share
+                    // picks up again once the window is back.
+                    Err(err @ (ScreenError::Hidden | ScreenError::Format(_))) => {
+                        if !hidden {
+                            info!("Window share paused: {}", err);
+                            hidden = true;
+                        }
+                        continue;
+                    }
+                    Err(err) => {
+                        info!("Window share stopped: {}", err);
+                        return;
+                    }
+                };
+                hidden = false;
+                let now = Instant::now();
+                let duration = now - last;
+                last = now;
+                let frame = match max_height {
+                    Some(max) if frame.height > max as usize => {
+                        let factor = frame.height.div_ceil(max.max(1) as usize);
+                        video::downscale(&frame, factor)
+                    }
+                    _ => frame,
+                };
+                let encoded = encoder.encode(&frame);
+                preview.set(Some(frame));
+                let Some(data) = encoded else {
+                    continue;
+                };
+                let sample = Sample {
+                    data: Bytes::from(data),
+                    duration,
+                    ..Default::default()
+                };
+                if samples_tx.blocking_send(sample).is_err() {
+                    return;
+                }
+            }
+        })
+        .map_err(|err| ScreenError::Io("spawn capture thread", err))?;
+    Ok(tokio::spawn(async move {
+        while let Some(sample) = samples.recv().await {
+            if let Err(err) = track.write_sample(&sample).await {
+                info!("Failed to write window share sample: {:?}", err);
+            }
+        }
+    }))
+}
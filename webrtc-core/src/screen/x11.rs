@@ -0,0 +1,263 @@
+//! Listing and reading windows over the X11 protocol.
+
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::errors::{ConnectError, ConnectionError, ReplyError, ReplyOrIdError};
+use x11rb::protocol::composite::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ClientMessageEvent, ConnectionExt as _, EventMask, ImageFormat, ImageOrder,
+    InputFocus, Window,
+};
+use x11rb::protocol::ErrorKind;
+use x11rb::rust_connection::RustConnection;
+
+use super::{ScreenError, WindowInfo, WindowRect};
+use crate::video::VideoFrame;
+
+/// Longest title read, in 32-bit units.
+const TITLE_LENGTH: u32 = 256;
+
+impl From<ConnectError> for ScreenError {
+    fn from(err: ConnectError) -> Self {
+        ScreenError::X11(err.to_string())
+    }
+}
+
+impl From<ConnectionError> for ScreenError {
+    fn from(err: ConnectionError) -> Self {
+        ScreenError::X11(err.to_string())
+    }
+}
+
+impl From<ReplyError> for ScreenError {
+    fn from(err: ReplyError) -> Self {
+        match err {
+            ReplyError::X11Error(err) if err.error_kind == ErrorKind::Window => ScreenError::Closed,
+            // Unmapped windows have no contents to name or read.
+            ReplyError::X11Error(err) if err.error_kind == ErrorKind::Match => ScreenError::Hidden,
+            err => ScreenError::X11(err.to_string()),
+        }
+    }
+}
+
+impl From<ReplyOrIdError> for ScreenError {
+    fn from(err: ReplyOrIdError) -> Self {
+        ScreenError::X11(err.to_string())
+    }
+}
+
+fn atom(conn: &RustConnection, name: &[u8]) -> Result<Atom, ScreenError> {
+    Ok(conn.intern_atom(false, name)?.reply()?.atom)
+}
+
+pub fn list_windows() -> Result<Vec<WindowInfo>, ScreenError> {
+    let (conn, screen) = RustConnection::connect(None)?;
+    let root = conn.setup().roots[screen].root;
+    let net_wm_name = atom(&conn, b"_NET_WM_NAME")?;
+    let utf8_string = atom(&conn, b"UTF8_STRING")?;
+    let mut clients = Vec::new();
+    for name in [&b"_NET_CLIENT_LIST_STACKING"[..], b"_NET_CLIENT_LIST"] {
+        let property = atom(&conn, name)?;
+        let reply = conn
+            .get_property(false, root, property, AtomEnum::WINDOW, 0, u32::MAX)?
+            .reply()?;
+        clients = reply.value32().map(Iterator::collect).unwrap_or_default();
+        if !clients.is_empty() {
+            break;
+        }
+    }
+    let mut windows = Vec::new();
+    // Stacking order is bottom first; a picker wants the topmost first.
+    for window in clients.into_iter().rev() {
+        // Windows closing while we look are simply left out.
+        let Ok(geometry) = conn.get_geometry(window)?.reply() else {
+            continue;
+        };
+        let mut title = String::new();
+        for (property, typ) in [
+            (net_wm_name, utf8_string),
+            (AtomEnum::WM_NAME.into(), AtomEnum::ANY.into()),
+        ] {
+            let Ok(reply) = conn
+                .get_property(false, window, property, typ, 0, TITLE_LENGTH)?
+                .reply()
+            else {
+                continue;
+            };
+            if !reply.value.is_empty() {
+                title = String::from_utf8_lossy(&reply.value).into_owned();
+                break;
+            }
+        }
+        windows.push(WindowInfo {
+            id: window,
+            title,
+            width: geometry.width.into(),
+            height: geometry.height.into(),
+        });
+    }
+    Ok(windows)
+}
+
+pub struct Locator {
+    conn: RustConnection,
+    root: Window,
+    window: Window,
+    net_active_window: Atom,
+}
+
+impl Locator {
+    pub fn open(window: Window) -> Result<Self, ScreenError> {
+        let (conn, screen) = RustConnection::connect(None)?;
+        let root = conn.setup().roots[screen].root;
+        conn.get_geometry(window)?.reply()?;
+        let net_active_window = atom(&conn, b"_NET_ACTIVE_WINDOW")?;
+        Ok(Self {
+            conn,
+            root,
+            window,
+            net_active_window,
+        })
+    }
+
+    pub fn rect(&self) -> Result<WindowRect, ScreenError> {
+        let geometry = self.conn.get_geometry(self.window)?.reply()?;
+        let origin = self
+            .conn
+            .translate_coordinates(self.window, self.root, 0, 0)?
+            .reply()?;
+        Ok(WindowRect {
+            x: origin.dst_x.into(),
+            y: origin.dst_y.into(),
+            width: geometry.width.into(),
+            height: geometry.height.into(),
+        })
+    }
+
+    pub fn has_focus(&self) -> Result<bool, ScreenError> {
+        let mut focus = self.conn.get_input_focus()?.reply()?.focus;
+        // Applications mostly focus a window of their own inside the one
+        // the window manager lists.
+        while ![x11rb::NONE, InputFocus::POINTER_ROOT.into(), self.root].contains(&focus) {
+            if focus == self.window {
+                return Ok(true);
+            }
+            focus = self.conn.query_tree(focus)?.reply()?.parent;
+        }
+        Ok(false)
+    }
+
+    pub fn activate(&self) -> Result<(), ScreenError> {
+        // Source 2 is a pager, which window managers obey without
+        // second-guessing focus stealing; time 0 is now.
+        let event =
+            ClientMessageEvent::new(32, self.window, self.net_active_window, [2, 0, 0, 0, 0]);
+        self.conn.send_event(
+            false,
+            self.root,
+            EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+            event,
+        )?;
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+pub struct Capture {
+    conn: RustConnection,
+    window: Window,
+    /// Whether the server keeps the window's pixels off screen for us, so
+    /// covered parts read as the window rather than what covers it.
+    composited: bool,
+    blue_first: bool,
+}
+
+impl Capture {
+    pub fn open(window: Window) -> Result<Self, ScreenError> {
+        let (conn, _) = RustConnection::connect(None)?;
+        conn.get_geometry(window)?.reply()?;
+        let composited = conn
+            .extension_information(composite::X11_EXTENSION_NAME)?
+            .is_some()
+            && conn
+                .composite_query_version(0, 2)?
+                .reply()
+                .is_ok_and(|version| version.major_version > 0 || version.minor_version >= 2);
+        if composited {
+            // Automatic redirection leaves the screen as it was; the server
+            // only starts keeping the window's pixels. It ends with our
+            // connection.
+            conn.composite_redirect_window(window, composite::Redirect::AUTOMATIC)?
+                .check()?;
+        }
+        let formats = &conn.setup().pixmap_formats;
+        if !formats
+            .iter()
+            .any(|format| format.depth == 24 && format.bits_per_pixel == 32)
+        {
+            return Err(ScreenError::Format(
+                "24-bit windows stored as other than 32 bits per pixel".to_owned(),
+            ));
+        }
+        let blue_first = conn.setup().image_byte_order == ImageOrder::LSB_FIRST;
+        Ok(Self {
+            conn,
+            window,
+            composited,
+            blue_first,
+        })
+    }
+
+    pub fn next_frame(&mut self) -> Result<VideoFrame, ScreenError> {
+        let geometry = self.conn.get_geometry(self.window)?.reply()?;
+        let width = geometry.width & !1;
+        let height = geometry.height & !1;
+        if width == 0 || height == 0 {
+            return Err(ScreenError::Hidden);
+        }
+        // A named pixmap only holds the window at its size when named, so
+        // every frame names a fresh one.
+        let pixmap = if self.composited {
+            let pixmap = self.conn.generate_id()?;
+            self.conn
+                .composite_name_window_pixmap(self.window, pixmap)?
+                .check()?;
+            Some(pixmap)
+        } else {
+            None
+        };
+        let image = self
+            .conn
+            .get_image(
+                ImageFormat::Z_PIXMAP,
+                pixmap.unwrap_or(self.window),
+                0,
+                0,
+                width,
+                height,
+                !0,
+            )?
+            .reply();
+        if let Some(pixmap) = pixmap {
+            self.conn.free_pixmap(pixmap)?;
+        }
+        let image = image?;
+        let (width, height) = (usize::from(width), usize::from(height));
+        if !matches!(image.depth, 24 | 32) || image.data.len() != width * height * 4 {
+            return Err(ScreenError::Format(format!("{}-bit windows", image.depth)));
+        }
+        let mut rgba = image.data;
+        for pixel in rgba.chunks_exact_mut(4) {
+            let [b, g, r] = if self.blue_first {
+                [pixel[0], pixel[1], pixel[2]]
+            } else {
+                [pixel[3], pixel[2], pixel[1]]
+            };
+            pixel.copy_from_slice(&[r, g, b, 255]);
+        }
+        Ok(VideoFrame {
+            width,
+            height,
+            rgba,
+        })
+    }
+}
@@ -0,0 +1,154 @@
+//! Sending on a data channel no faster than it drains. A send only queues
+//! the message, so a loop of them over a large file would hold the whole
+//! file in memory; a send queue instead waits, once the channel holds more
+//! than a high-water mark, until it is down to a low-water one.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::sync::Notify;
+use webrtc::data_channel::data_channel_state::RTCDataChannelState;
+use webrtc::data_channel::RTCDataChannel;
+
+/// Queued bytes past which sending waits, by default.
+pub const HIGH_WATER_BYTES: usize = 1024 * 1024;
+
+/// Queued bytes sending resumes at, by default.
+pub const LOW_WATER_BYTES: usize = 256 * 1024;
+
+/// How often a wait looks again, should the channel not say it drained.
+const RECHECK: Duration = Duration::from_millis(100);
+
+/// Sends on one channel, waiting for it to drain when it holds too much.
+///
+/// The queue owns the channel's buffered-amount-low handler and threshold
+/// from when it is made: webrtc-rs keeps one handler a channel, so any set
+/// before is replaced, and cannot hand it back to be restored. Once the
+/// queue is dropped its handler stays, waking no one, until another is
+/// set.
+pub struct SendQueue<'a> {
+    channel: &'a RTCDataChannel,
+    drained: Arc<Notify>,
+    high_water: usize,
+    low_water: usize,
+    pauses: u64,
+}
+
+impl<'a> SendQueue<'a> {
+    /// Waits past [`HIGH_WATER_BYTES`] until down to [`LOW_WATER_BYTES`],
+    /// replacing the channel's buffered-amount-low handler.
+    pub async fn new(channel: &'a RTCDataChannel) -> Self {
+        Self::with_marks(channel, HIGH_WATER_BYTES, LOW_WATER_BYTES).await
+    }
+
+    /// Waits past `high_water` queued bytes until down to `low_water`.
+    pub async fn with_marks(
+        channel: &'a RTCDataChannel,
+        high_water: usize,
+        low_water: usize,
+    ) -> Self {
+        let drained = Arc::new(Notify::new());
+        channel.set_buffered_amount_low_threshold(low_water).await;
+        let notify = Arc::clone(&drained);
+        channel
+            .on_buffered_amount_low(Box::new(move || {
+                notify.notify_one();
+                Box::pin(async {})
+            }))
+            .await;
+        Self {
+            channel,
+            drained,
+            high_water,
+            low_water,
+            pauses: 0,
+        }
+    }
+
+    /// Sends `data` as a binary message, once the channel has room for it.
+    pub async fn send(&mut self, data: &Bytes) -> Result<usize, webrtc::Error> {
+        if self.channel.buffered_amount().await > self.high_water {
+            self.pauses += 1;
+            // Past the low-water mark and only then, so a closed channel
+            // leaves the send to fail rather than waiting forever.
+            while self.channel.ready_state() == RTCDataChannelState::Open
+                && self.channel.buffered_amount().await > self.low_water
+            {
+                let _ = tokio::time::timeout(RECHECK, self.drained.notified()).await;
+            }
+        }
+        self.channel.send(data).await
+    }
+
+    /// How many times sending has waited for the channel to drain.
+    pub fn pauses(&self) -> u64 {
+        self.pauses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+    use tokio::time::timeout;
+    use webrtc::data_channel::data_channel_init::RTCDataChannelInit;
+    use webrtc::data_channel::data_channel_message::DataChannelMessage;
+
+    use super::*;
+    use crate::rtc::webrtc_rs::testing::{connection, negotiate};
+    use crate::rtc::PeerConnectionHandle;
+
+    #[tokio::test]
+    async fn keeps_what_is_queued_bounded() {
+        let (offerer, answerer) = (connection().await, connection().await);
+        let init = RTCDataChannelInit {
+            negotiated: Some(0),
+            ..Default::default()
+        };
+        let sending = offerer
+            .inner()
+            .create_data_channel("queue", Some(init.clone()))
+            .await
+            .unwrap();
+        let receiving = answerer
+            .inner()
+            .create_data_channel("queue", Some(init))
+            .await
+            .unwrap();
+        let (opened_tx, mut opened) = mpsc::channel(1);
+        sending.on_open(Box::new(move || {
+            let _ = opened_tx.try_send(());
+            Box::pin(async {})
+        }));
+        let (received_tx, mut received) = mpsc::unbounded_channel();
+        receiving.on_message(Box::new(move |message: DataChannelMessage| {
+            let _ = received_tx.send(message.data.len());
+            Box::pin(async {})
+        }));
+
+        negotiate(offerer.as_ref(), answerer.as_ref()).await;
+        timeout(Duration::from_secs(10), opened.recv())
+            .await
+            .unwrap();
+
+        let chunk = Bytes::from(vec![7; 16 * 1024]);
+        let count = 256;
+        let high_water = 4 * chunk.len();
+        let mut queue = SendQueue::with_marks(&sending, high_water, chunk.len()).await;
+        for _ in 0..count {
+            queue.send(&chunk).await.unwrap();
+            assert!(sending.buffered_amount().await <= high_water + chunk.len());
+        }
+        assert!(queue.pauses() > 0);
+
+        let mut bytes = 0;
+        while bytes < count * chunk.len() {
+            bytes += timeout(Duration::from_secs(10), received.recv())
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        assert_eq!(bytes, count * chunk.len());
+        let _ = tokio::join!(offerer.close(), answerer.close());
+    }
+}
@@ -0,0 +1,544 @@
+//! User settings, persisted between runs by the GUI, and their translation
+//! into webrtc-rs configuration.
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+use webrtc::api::interceptor_registry::{configure_rtcp_reports, configure_twcc};
+use webrtc::api::media_engine::MediaEngine;
+use webrtc::api::setting_engine::SettingEngine;
+use webrtc::api::{APIBuilder, API};
+use webrtc::ice::mdns::MulticastDnsMode;
+use webrtc::ice::network_type::NetworkType;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::interceptor::registry::Registry;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::policy::ice_transport_policy::RTCIceTransportPolicy;
+
+use crate::access::AccessList;
+use crate::audio::{CaptureOptions, ChannelMap, OpusSettings, ProcessingSettings};
+use crate::auth::AuthSettings;
+use crate::camera::BackgroundSettings;
+use crate::codec::ScalabilityMode;
+use crate::codecs::{self, CodecOverride, CodecPreferences};
+use crate::constraints::{ConstrainRange, VideoConstraints};
+use crate::devices::{DeviceKind, DevicePreference};
+use crate::failover::FailoverThresholds;
+use crate::renegotiation::RenegotiationSettings;
+use crate::screen::ShareFormat;
+use crate::signaling::p2p;
+use crate::turn_rest::{self, TurnCredential};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub network: NetworkSettings,
+    pub media: MediaSettings,
+    pub privacy: PrivacySettings,
+    pub advanced: AdvancedSettings,
+    pub devices: DeviceSettings,
+    pub files: FileSettings,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkSettings {
+    pub stun_servers: Vec<String>,
+    pub turn_servers: Vec<TurnServer>,
+    pub signaling_url: String,
+    /// Login for signaling servers that require a bearer token.
+    pub auth: AuthSettings,
+    pub allow_ipv6: bool,
+    /// WHIP-style endpoint that echoes media back, used by "Test my setup".
+    pub echo_test_url: String,
+    /// Relays used for serverless signaling over Nostr.
+    pub nostr_relays: Vec<String>,
+    /// Hex secret key of our Nostr identity, generated on first use so the
+    /// public key peers dial stays the same across runs.
+    pub nostr_secret_key: String,
+    /// Bootstrap multiaddrs for the libp2p DHT.
+    pub libp2p_bootstrap: Vec<String>,
+    /// Circuit relays to reserve a slot on, so peers that cannot dial us
+    /// directly still can through one of them.
+    pub libp2p_relays: Vec<String>,
+    /// Our libp2p identity, protobuf encoded and base64'd; generated on first
+    /// use so our `PeerId` stays the same.
+    pub libp2p_key: String,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            stun_servers: vec![
+                "stun:stun.l.google.com:19302".to_owned(),
+                "stun:stun1.l.google.com:19302".to_owned(),
+                "stun:stun2.l.google.com:19302".to_owned(),
+            ],
+            turn_servers: vec![],
+            signaling_url: "ws://127.0.0.1:8080".to_owned(),
+            auth: AuthSettings::default(),
+            allow_ipv6: true,
+            echo_test_url: String::new(),
+            nostr_relays: vec![
+                "wss://relay.damus.io".to_owned(),
+                "wss://nos.lol".to_owned(),
+                "wss://relay.nostr.band".to_owned(),
+            ],
+            nostr_secret_key: String::new(),
+            libp2p_bootstrap: p2p::DEFAULT_BOOTSTRAP
+                .iter()
+                .map(|address| (*address).to_owned())
+                .collect(),
+            libp2p_relays: vec![],
+            libp2p_key: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TurnServer {
+    pub url: String,
+    /// The login, or with time-limited credentials the user they are for.
+    pub username: String,
+    pub credential: String,
+    pub auth: TurnAuth,
+    /// Credentials last fetched from the credentials URL.
+    #[serde(skip)]
+    pub issued: Option<TurnCredential>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TurnAuth {
+    /// The username and credential are used as they are.
+    #[default]
+    Static,
+    /// Sign time-limited credentials with the TURN server's shared secret.
+    SharedSecret { secret: String },
+    /// Fetch time-limited credentials from a TURN REST service.
+    CredentialsUrl { url: String },
+}
+
+impl TurnServer {
+    /// The ICE server entry, with time-limited credentials issued now
+    /// valid for `lifetime`.
+    pub fn ice_server(&self, lifetime: Duration) -> RTCIceServer {
+        let url = self.url.trim().to_owned();
+        let (urls, username, credential) = match &self.auth {
+            TurnAuth::Static => (vec![url], self.username.clone(), self.credential.clone()),
+            TurnAuth::SharedSecret { secret } => {
+                let issued = turn_rest::sign(secret, &self.username, lifetime, SystemTime::now());
+                (vec![url], issued.username, issued.credential)
+            }
+            TurnAuth::CredentialsUrl { .. } => match &self.issued {
+                Some(issued) if !issued.urls.is_empty() => (
+                    issued.urls.clone(),
+                    issued.username.clone(),
+                    issued.credential.clone(),
+                ),
+                Some(issued) => (
+                    vec![url],
+                    issued.username.clone(),
+                    issued.credential.clone(),
+                ),
+                None => {
+                    warn!("No credentials fetched yet for TURN server {}", url);
+                    (vec![url], String::new(), String::new())
+                }
+            },
+        };
+        RTCIceServer {
+            urls,
+            username,
+            credential,
+            ..Default::default()
+        }
+    }
+
+    /// The credentials URL, when the fetched credentials are missing or
+    /// about to expire at `now`.
+    pub fn stale_credentials_url(&self, now: SystemTime) -> Option<&str> {
+        let TurnAuth::CredentialsUrl { url } = &self.auth else {
+            return None;
+        };
+        let fresh = self
+            .issued
+            .as_ref()
+            .is_some_and(|issued| now < issued.refresh_at());
+        (!fresh && !url.trim().is_empty()).then_some(url.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MediaSettings {
+    /// Add a receive-only audio m-line to offers made outside SIP calls.
+    pub offer_audio: bool,
+    /// Add a receive-only video m-line to offers made outside SIP calls.
+    pub offer_video: bool,
+    /// Send the selected microphone on every new peer connection.
+    pub send_microphone: bool,
+    /// Which of the microphone's input channels are sent, and their gain.
+    pub microphone_channels: ChannelMap,
+    /// The pitch of the test tone offered among the microphones, in Hz.
+    pub test_tone_hz: f32,
+    /// How the microphone is encoded.
+    pub opus: OpusSettings,
+    /// How the microphone is cleaned up before it is encoded.
+    pub processing: ProcessingSettings,
+    /// Which codecs calls offer, in order.
+    pub codecs: CodecPreferences,
+    /// Extra delay for received audio on top of lip sync, in milliseconds;
+    /// negative delays the video instead.
+    pub av_sync_offset_ms: i32,
+    /// Used to pick the camera capture mode.
+    pub video_constraints: VideoConstraints,
+    /// Seconds between thumbnails of the remote video.
+    pub thumbnail_interval_secs: u64,
+    /// Show the camera preview the way a mirror would.
+    pub mirror_self_view: bool,
+    /// Send smaller, slower video on battery or when running hot.
+    pub power_saving: bool,
+    /// Publish video to SFUs as simulcast layers.
+    pub simulcast: bool,
+    /// The layers the camera goes out in, where its codec has them.
+    pub scalability: ScalabilityMode,
+    /// Follow the link's bandwidth when sending video, rather than a
+    /// fixed bitrate for the size.
+    pub adaptive_bitrate: bool,
+    /// The size and frame rate window shares go out at.
+    pub screen_share: ShareFormat,
+    /// What is done behind the person on camera.
+    pub background: BackgroundSettings,
+}
+
+impl Default for MediaSettings {
+    fn default() -> Self {
+        Self {
+            offer_audio: false,
+            offer_video: false,
+            send_microphone: false,
+            microphone_channels: ChannelMap::default(),
+            test_tone_hz: 440.0,
+            opus: OpusSettings::default(),
+            processing: ProcessingSettings::default(),
+            codecs: CodecPreferences::default(),
+            av_sync_offset_ms: 0,
+            video_constraints: VideoConstraints {
+                width: ConstrainRange::ideal(1280),
+                height: ConstrainRange::ideal(720),
+                frame_rate: ConstrainRange::ideal(30.0),
+                ..Default::default()
+            },
+            thumbnail_interval_secs: 10,
+            mirror_self_view: true,
+            power_saving: true,
+            simulcast: true,
+            scalability: ScalabilityMode::L1T1,
+            adaptive_bitrate: true,
+            screen_share: ShareFormat::default(),
+            background: BackgroundSettings::default(),
+        }
+    }
+}
+
+impl MediaSettings {
+    pub fn capture_options(&self) -> CaptureOptions {
+        CaptureOptions {
+            channels: self.microphone_channels.clone(),
+            test_tone_hz: self.test_tone_hz,
+            opus: self.opus.clone(),
+            processing: self.processing.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrivacySettings {
+    /// Advertise host candidates as `.local` mDNS names instead of LAN IPs.
+    pub mdns_host_candidates: bool,
+    /// Only use TURN relay candidates, hiding our public address from the peer.
+    pub relay_only: bool,
+    /// Who may call us, checked before answering incoming offers.
+    pub access: AccessList,
+    /// Which offers the peer sends mid-call are answered without asking.
+    pub renegotiation: RenegotiationSettings,
+}
+
+/// The last used devices, reselected on startup when still present.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceSettings {
+    pub camera: Option<DevicePreference>,
+    pub microphone: Option<DevicePreference>,
+    pub speaker: Option<DevicePreference>,
+}
+
+impl DeviceSettings {
+    pub fn preference(&self, kind: DeviceKind) -> Option<&DevicePreference> {
+        match kind {
+            DeviceKind::Camera => self.camera.as_ref(),
+            DeviceKind::Microphone => self.microphone.as_ref(),
+            DeviceKind::Speaker => self.speaker.as_ref(),
+        }
+    }
+
+    pub fn set_preference(&mut self, kind: DeviceKind, preference: Option<DevicePreference>) {
+        match kind {
+            DeviceKind::Camera => self.camera = preference,
+            DeviceKind::Microphone => self.microphone = preference,
+            DeviceKind::Speaker => self.speaker = preference,
+        }
+    }
+}
+
+/// The kinds of file the app opens or saves, each with its own folder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Sdp,
+    Contacts,
+    Exports,
+    Clips,
+    Recordings,
+    Snapshots,
+    SignalingFolder,
+    Media,
+    Transfers,
+}
+
+impl FileKind {
+    pub const ALL: [FileKind; 9] = [
+        FileKind::Sdp,
+        FileKind::Contacts,
+        FileKind::Exports,
+        FileKind::Clips,
+        FileKind::Recordings,
+        FileKind::Snapshots,
+        FileKind::SignalingFolder,
+        FileKind::Media,
+        FileKind::Transfers,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FileKind::Sdp => "SDP files",
+            FileKind::Contacts => "Contacts (vCard)",
+            FileKind::Exports => "Exports",
+            FileKind::Clips => "Video clips",
+            FileKind::Recordings => "Call recordings",
+            FileKind::Snapshots => "Video snapshots",
+            FileKind::SignalingFolder => "File signaling folder",
+            FileKind::Media => "Media files to send",
+            FileKind::Transfers => "Files sent to and from the peer",
+        }
+    }
+}
+
+/// Where file dialogs start, remembered from the last file picked of each
+/// kind. `None` starts wherever the platform dialog likes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileSettings {
+    pub sdp: Option<PathBuf>,
+    pub contacts: Option<PathBuf>,
+    pub exports: Option<PathBuf>,
+    pub clips: Option<PathBuf>,
+    pub recordings: Option<PathBuf>,
+    pub snapshots: Option<PathBuf>,
+    pub signaling_folder: Option<PathBuf>,
+    pub media: Option<PathBuf>,
+    pub transfers: Option<PathBuf>,
+}
+
+impl FileSettings {
+    pub fn folder(&self, kind: FileKind) -> Option<&PathBuf> {
+        match kind {
+            FileKind::Sdp => self.sdp.as_ref(),
+            FileKind::Contacts => self.contacts.as_ref(),
+            FileKind::Exports => self.exports.as_ref(),
+            FileKind::Clips => self.clips.as_ref(),
+            FileKind::Recordings => self.recordings.as_ref(),
+            FileKind::Snapshots => self.snapshots.as_ref(),
+            FileKind::SignalingFolder => self.signaling_folder.as_ref(),
+            FileKind::Media => self.media.as_ref(),
+            FileKind::Transfers => self.transfers.as_ref(),
+        }
+    }
+
+    pub fn set_folder(&mut self, kind: FileKind, folder: Option<PathBuf>) {
+        match kind {
+            FileKind::Sdp => self.sdp = folder,
+            FileKind::Contacts => self.contacts = folder,
+            FileKind::Exports => self.exports = folder,
+            FileKind::Clips => self.clips = folder,
+            FileKind::Recordings => self.recordings = folder,
+            FileKind::Snapshots => self.snapshots = folder,
+            FileKind::SignalingFolder => self.signaling_folder = folder,
+            FileKind::Media => self.media = folder,
+            FileKind::Transfers => self.transfers = folder,
+        }
+    }
+
+    /// Remembers the folder `path` is in, or `path` itself for folder
+    /// kinds.
+    pub fn remember(&mut self, kind: FileKind, path: &Path) {
+        let folder = match kind {
+            FileKind::Recordings | FileKind::Snapshots | FileKind::SignalingFolder => Some(path),
+            _ => path.parent(),
+        };
+        self.set_folder(kind, folder.map(Path::to_path_buf));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdvancedSettings {
+    pub stats_interval_secs: u64,
+    pub ice_disconnected_timeout_secs: u64,
+    pub ice_failed_timeout_secs: u64,
+    /// Restart ICE on its own when the connection drops.
+    pub auto_ice_restart: bool,
+    /// Restart ICE away from a TURN relay whose RTT or loss stays too high.
+    pub turn_failover: bool,
+    pub failover_rtt_ms: u64,
+    pub failover_loss_percent: u64,
+    /// How long signed TURN credentials stay valid.
+    pub turn_credential_hours: u64,
+}
+
+impl Default for AdvancedSettings {
+    fn default() -> Self {
+        Self {
+            stats_interval_secs: 1,
+            ice_disconnected_timeout_secs: 5,
+            ice_failed_timeout_secs: 25,
+            auto_ice_restart: true,
+            turn_failover: true,
+            failover_rtt_ms: 500,
+            failover_loss_percent: 10,
+            turn_credential_hours: 24,
+        }
+    }
+}
+
+impl Settings {
+    /// Builds the peer connection configuration. ICE Lite style sessions
+    /// skip the configured STUN servers and only gather host candidates.
+    pub fn rtc_configuration(&self, ice_lite: bool) -> RTCConfiguration {
+        let ice_servers = if ice_lite {
+            vec![]
+        } else {
+            self.network
+                .stun_servers
+                .iter()
+                .filter(|url| !url.trim().is_empty())
+                .map(|url| RTCIceServer {
+                    urls: vec![url.trim().to_owned()],
+                    ..Default::default()
+                })
+                .chain(
+                    self.turn_servers()
+                        .map(|server| server.ice_server(self.turn_credential_lifetime())),
+                )
+                .collect()
+        };
+        RTCConfiguration {
+            ice_servers,
+            ice_transport_policy: if self.privacy.relay_only {
+                RTCIceTransportPolicy::Relay
+            } else {
+                RTCIceTransportPolicy::All
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Builds a webrtc-rs API with the preferred codecs and these settings.
+    pub fn api(&self) -> Result<API, webrtc::Error> {
+        self.api_for(&CodecOverride::default())
+    }
+
+    /// Like `api`, offering only the codecs `codecs` lets through.
+    pub fn api_for(&self, codecs: &CodecOverride) -> Result<API, webrtc::Error> {
+        let mut media_engine = MediaEngine::default();
+        codecs::register(
+            &mut media_engine,
+            codecs,
+            &self.media.codecs,
+            &self.media.opus,
+        )?;
+        Ok(self.api_with(media_engine))
+    }
+
+    /// Builds a webrtc-rs API around an already populated media engine.
+    /// Sender and receiver reports are always on; lip sync needs the
+    /// sender's to line streams up. So is transport-wide congestion
+    /// control feedback, which adaptive bitrate follows.
+    pub fn api_with(&self, mut media_engine: MediaEngine) -> API {
+        let registry = configure_rtcp_reports(Registry::new());
+        let registry = configure_twcc(registry, &mut media_engine).unwrap_or_else(|err| {
+            warn!("Sending without congestion control feedback: {}", err);
+            configure_rtcp_reports(Registry::new())
+        });
+        APIBuilder::new()
+            .with_media_engine(media_engine)
+            .with_interceptor_registry(registry)
+            .with_setting_engine(self.setting_engine())
+            .build()
+    }
+
+    pub fn setting_engine(&self) -> SettingEngine {
+        let mut engine = SettingEngine::default();
+        if self.privacy.mdns_host_candidates {
+            engine.set_ice_multicast_dns_mode(MulticastDnsMode::QueryAndGather);
+        }
+        let mut network_types = vec![NetworkType::Udp4, NetworkType::Tcp4];
+        if self.network.allow_ipv6 {
+            network_types.extend([NetworkType::Udp6, NetworkType::Tcp6]);
+        }
+        engine.set_network_types(network_types);
+        engine.set_ice_timeouts(
+            Some(Duration::from_secs(
+                self.advanced.ice_disconnected_timeout_secs,
+            )),
+            Some(Duration::from_secs(self.advanced.ice_failed_timeout_secs)),
+            None,
+        );
+        engine
+    }
+
+    pub fn stats_interval(&self) -> Duration {
+        Duration::from_secs(self.advanced.stats_interval_secs.max(1))
+    }
+
+    pub fn turn_servers(&self) -> impl Iterator<Item = &TurnServer> {
+        self.network
+            .turn_servers
+            .iter()
+            .filter(|server| !server.url.trim().is_empty())
+    }
+
+    /// Failover needs a working relay to move to, so it stays off with
+    /// fewer than two TURN servers.
+    pub fn failover_thresholds(&self) -> Option<FailoverThresholds> {
+        (self.advanced.turn_failover && self.turn_servers().count() > 1).then(|| {
+            FailoverThresholds {
+                max_rtt_ms: self.advanced.failover_rtt_ms as f64,
+                max_loss: self.advanced.failover_loss_percent as f64 / 100.0,
+            }
+        })
+    }
+
+    pub fn turn_credential_lifetime(&self) -> Duration {
+        Duration::from_secs(self.advanced.turn_credential_hours.max(1) * 60 * 60)
+    }
+
+    pub fn thumbnail_interval(&self) -> Duration {
+        Duration::from_secs(self.media.thumbnail_interval_secs.max(1))
+    }
+}
@@ -0,0 +1,177 @@
+//! Signaling through files, for setups where the peers share nothing but a
+//! folder (a USB stick, Dropbox, Syncthing). Each side writes a
+//! self-describing JSON file and watches for its counterpart's.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+/// Bumped whenever the file layout changes incompatibly.
+const FORMAT_VERSION: u32 = 1;
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, thiserror::Error)]
+pub enum SignalFileError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("not a signaling file: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("unsupported signaling file version {0}")]
+    Version(u32),
+    #[error("expected an {expected} file but found an {found}")]
+    WrongRole { expected: Role, found: Role },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Offer,
+    Answer,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::Offer => "offer",
+            Role::Answer => "answer",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignalFile {
+    pub version: u32,
+    pub role: Role,
+    /// Pairs an answer with its offer.
+    pub session: String,
+    /// RFC 3339, UTC.
+    pub created_at: String,
+    pub sdp: String,
+    /// The SDP's `a=candidate` lines, for readers that take candidates
+    /// separately from the description.
+    pub candidates: Vec<String>,
+}
+
+/// A short random id for a new offer.
+pub fn new_session() -> String {
+    format!("{:08x}", rand::random::<u32>())
+}
+
+impl SignalFile {
+    pub fn new(role: Role, session: &str, sdp: String) -> Self {
+        let candidates = sdp
+            .lines()
+            .filter(|line| line.starts_with("a=candidate:"))
+            .map(|line| line.trim_start_matches("a=").to_owned())
+            .collect();
+        Self {
+            version: FORMAT_VERSION,
+            role,
+            session: session.to_owned(),
+            created_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            sdp,
+            candidates,
+        }
+    }
+
+    /// `<session>.<role>.json`, so both sides know what to look for.
+    pub fn file_name(role: Role, session: &str) -> String {
+        format!("{}.{}.json", session, role)
+    }
+
+    /// Writes the file into `dir` under its conventional name. The contents
+    /// go to a temporary file first so a watcher never sees half of it.
+    pub fn write_to(&self, dir: &Path) -> Result<PathBuf, SignalFileError> {
+        let path = dir.join(Self::file_name(self.role, &self.session));
+        let partial = dir.join(format!(
+            ".{}.partial",
+            Self::file_name(self.role, &self.session)
+        ));
+        std::fs::write(&partial, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+
+    pub fn read(path: &Path, expected: Role) -> Result<Self, SignalFileError> {
+        let file: SignalFile = serde_json::from_slice(&std::fs::read(path)?)?;
+        if file.version != FORMAT_VERSION {
+            return Err(SignalFileError::Version(file.version));
+        }
+        if file.role != expected {
+            return Err(SignalFileError::WrongRole {
+                expected,
+                found: file.role,
+            });
+        }
+        Ok(file)
+    }
+
+    /// The SDP, with the separately listed candidates added to its first
+    /// media section when the description carries none of its own.
+    pub fn sdp_with_candidates(&self) -> String {
+        if self.candidates.is_empty() || self.sdp.contains("a=candidate:") {
+            return self.sdp.clone();
+        }
+        let mut sdp = String::new();
+        let mut media_sections = 0;
+        let mut added = false;
+        for line in self.sdp.split_inclusive('\n') {
+            if line.starts_with("m=") {
+                media_sections += 1;
+                if media_sections == 2 && !added {
+                    sdp.push_str(&self.candidate_lines());
+                    added = true;
+                }
+            }
+            sdp.push_str(line);
+        }
+        if !added {
+            sdp.push_str(&self.candidate_lines());
+        }
+        sdp
+    }
+
+    fn candidate_lines(&self) -> String {
+        self.candidates
+            .iter()
+            .map(|candidate| format!("a={}\r\n", candidate))
+            .collect()
+    }
+}
+
+/// Polls `dir` until a `role` file shows up that was modified at or after
+/// `since`, from `session` or from any session when it is `None`. Files that
+/// do not parse yet (still syncing) are retried on the next pass.
+pub async fn wait_for(
+    dir: &Path,
+    role: Role,
+    session: Option<&str>,
+    since: SystemTime,
+) -> Result<(PathBuf, SignalFile), SignalFileError> {
+    let suffix = format!(".{}.json", role);
+    loop {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some(file_session) = name.strip_suffix(&suffix) else {
+                continue;
+            };
+            if file_session.starts_with('.') || session.is_some_and(|s| s != file_session) {
+                continue;
+            }
+            let fresh = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified >= since);
+            if !fresh {
+                continue;
+            }
+            if let Ok(file) = SignalFile::read(&entry.path(), role) {
+                return Ok((entry.path(), file));
+            }
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
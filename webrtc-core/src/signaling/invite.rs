@@ -0,0 +1,206 @@
+//! Invite links: a URL carrying the signaling server, the room code and a
+//! token, for opening with the app's `webrtc-gui:` URL handler or pasting
+//! into the room panel.
+//!
+//! The server signs tokens with a key only it holds, for the room the
+//! member asking is in. Once it has issued one, a join to that room needs
+//! a token, and one that is forged, for another room or expired is turned
+//! away like a missing one.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use sha2::Sha256;
+
+use super::room::room_channel;
+
+pub const SCHEME: &str = "webrtc-gui";
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum InviteError {
+    #[error("not a valid link: {0}")]
+    Url(String),
+    #[error("not a {SCHEME}: invite link")]
+    Scheme,
+    #[error("the link has no {0}")]
+    Missing(&'static str),
+    #[error("the invite was not issued by this server for this room")]
+    BadToken,
+    #[error("the invite has expired")]
+    Expired,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// When `token` stops working, in seconds since the Unix epoch.
+fn expires_at(token: &str) -> Result<u64, InviteError> {
+    token
+        .split_once('.')
+        .and_then(|(expires, _)| u64::from_str_radix(expires, 16).ok())
+        .ok_or(InviteError::BadToken)
+}
+
+/// The server's key for signing invite tokens.
+pub struct InviteKey {
+    key: Vec<u8>,
+}
+
+impl InviteKey {
+    pub fn new(key: &[u8]) -> Self {
+        Self { key: key.to_vec() }
+    }
+
+    fn mac(&self, channel: &str, expires_at: u64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC takes any key");
+        mac.update(format!("webrtc-rust-native-gui/invite/{}/{}", channel, expires_at).as_bytes());
+        mac
+    }
+
+    /// A token for the server channel `channel`, working for `valid_for`.
+    pub fn sign(&self, channel: &str, valid_for: Duration) -> String {
+        let expires_at = now() + valid_for.as_secs();
+        let signature = self.mac(channel, expires_at).finalize().into_bytes();
+        let signature: String = signature.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("{:x}.{}", expires_at, signature)
+    }
+
+    /// Checks that this key signed `token` for `channel`, and that it has
+    /// not expired.
+    pub fn verify(&self, channel: &str, token: &str) -> Result<(), InviteError> {
+        let expires_at = expires_at(token)?;
+        let signature = token
+            .split_once('.')
+            .map(|(_, signature)| signature)
+            .filter(|signature| signature.len() % 2 == 0 && signature.is_ascii())
+            .and_then(|signature| {
+                (0..signature.len())
+                    .step_by(2)
+                    .map(|at| u8::from_str_radix(&signature[at..at + 2], 16).ok())
+                    .collect::<Option<Vec<u8>>>()
+            })
+            .ok_or(InviteError::BadToken)?;
+        self.mac(channel, expires_at)
+            .verify_slice(&signature)
+            .map_err(|_| InviteError::BadToken)?;
+        if now() >= expires_at {
+            return Err(InviteError::Expired);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invite {
+    pub server: String,
+    pub room: String,
+    /// As the server issued it.
+    pub token: String,
+}
+
+impl Invite {
+    pub fn new(server: &str, room: &str, token: &str) -> Self {
+        Self {
+            server: server.to_owned(),
+            room: room.to_owned(),
+            token: token.to_owned(),
+        }
+    }
+
+    /// The server channel the invite is for.
+    pub fn channel(&self) -> String {
+        room_channel(&self.room)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        expires_at(&self.token).map_or(true, |expires_at| now() >= expires_at)
+    }
+
+    pub fn to_url(&self) -> String {
+        let mut url = Url::parse(&format!("{}://join", SCHEME)).expect("static URL");
+        url.query_pairs_mut()
+            .append_pair("server", &self.server)
+            .append_pair("room", &self.room)
+            .append_pair("token", &self.token);
+        url.into()
+    }
+
+    /// Parses an invite link, rejecting it once expired. Only the server
+    /// can tell whether the token is genuine.
+    pub fn parse(link: &str) -> Result<Self, InviteError> {
+        let url = Url::parse(link.trim()).map_err(|err| InviteError::Url(err.to_string()))?;
+        if url.scheme() != SCHEME {
+            return Err(InviteError::Scheme);
+        }
+        let field = |name: &'static str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+                .filter(|value| !value.is_empty())
+                .ok_or(InviteError::Missing(name))
+        };
+        let invite = Self {
+            server: field("server")?,
+            room: field("room")?,
+            token: field("token")?,
+        };
+        if now() >= expires_at(&invite.token)? {
+            return Err(InviteError::Expired);
+        }
+        Ok(invite)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_key_signs_tokens_for_a_room() {
+        let key = InviteKey::new(b"server secret");
+        let invite = Invite::new(
+            "wss://example.net",
+            "ab-12",
+            &key.sign(&room_channel("AB12"), Duration::from_secs(60)),
+        );
+        let parsed = Invite::parse(&invite.to_url()).unwrap();
+        assert_eq!(parsed, invite);
+        assert_eq!(key.verify(&parsed.channel(), &parsed.token), Ok(()));
+
+        assert_eq!(
+            key.verify(&room_channel("other"), &parsed.token),
+            Err(InviteError::BadToken)
+        );
+        assert_eq!(
+            InviteKey::new(b"guessed").verify(&parsed.channel(), &parsed.token),
+            Err(InviteError::BadToken)
+        );
+        // Pushing the expiry out breaks the signature.
+        let (_, signature) = parsed.token.split_once('.').unwrap();
+        let extended = format!("{:x}.{}", now() + 3600 * 24 * 365, signature);
+        assert_eq!(
+            key.verify(&parsed.channel(), &extended),
+            Err(InviteError::BadToken)
+        );
+
+        let expired = key.sign(&parsed.channel(), Duration::ZERO);
+        assert_eq!(
+            key.verify(&parsed.channel(), &expired),
+            Err(InviteError::Expired)
+        );
+        let link = Invite::new("wss://example.net", "ab-12", &expired).to_url();
+        assert_eq!(Invite::parse(&link), Err(InviteError::Expired));
+        assert_eq!(
+            Invite::parse("https://example.net/join"),
+            Err(InviteError::Scheme)
+        );
+        assert_eq!(
+            Invite::parse("webrtc-gui://join?room=ab-12&token=1.00"),
+            Err(InviteError::Missing("server"))
+        );
+    }
+}
@@ -0,0 +1,397 @@
+//! Client for the WebSocket signaling server.
+
+pub mod file;
+pub mod invite;
+pub mod nostr;
+pub mod p2p;
+mod protocol;
+pub mod room;
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use log::{info, warn};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, watch};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{Connector, MaybeTlsStream, WebSocketStream};
+
+use crate::backoff::Backoff;
+use crate::bounded::{BoundedBuffer, DropPolicy};
+
+/// Messages waiting for a stalled socket before `send` starts refusing them.
+const MAX_OUTGOING: usize = 64;
+/// Signals and chat kept for the server while reconnecting.
+const MAX_QUEUED: usize = 64;
+const MAX_QUEUED_BYTES: usize = 256 * 1024;
+
+const INITIAL_RETRY: Duration = Duration::from_millis(500);
+const MAX_RETRY: Duration = Duration::from_secs(30);
+
+pub use protocol::{ClientMessage, PeerInfo, ServerMessage, SignalPayload};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SignalingError {
+    #[error("WebSocket error: {0}")]
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    #[error("signaling connection is closed")]
+    Closed,
+    #[error("access token is not a valid header value")]
+    BadToken,
+    #[error("too many signaling messages waiting to be sent")]
+    Backlog,
+    #[error("cannot trust the given certificate: {0}")]
+    Certificate(String),
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for SignalingError {
+    fn from(err: tokio_tungstenite::tungstenite::Error) -> Self {
+        SignalingError::WebSocket(Box::new(err))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignalingHealth {
+    Connected,
+    /// The connection dropped; signals wait in a queue until it is back.
+    Reconnecting {
+        attempt: u32,
+        retry_in: Duration,
+        queued: usize,
+    },
+    /// Every handle was dropped.
+    Closed,
+}
+
+impl fmt::Display for SignalingHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignalingHealth::Connected => write!(f, "connected"),
+            SignalingHealth::Reconnecting {
+                attempt, queued, ..
+            } => {
+                write!(f, "reconnecting (attempt {})", attempt)?;
+                if *queued > 0 {
+                    write!(f, ", {} messages queued", queued)?;
+                }
+                Ok(())
+            }
+            SignalingHealth::Closed => write!(f, "closed"),
+        }
+    }
+}
+
+/// Handle to a signaling connection that reconnects on its own when it
+/// drops. The connection is closed once every handle has been dropped.
+#[derive(Clone)]
+pub struct SignalingClient {
+    outgoing: mpsc::Sender<ClientMessage>,
+    health: watch::Receiver<SignalingHealth>,
+    token: Arc<Mutex<Option<String>>>,
+}
+
+/// What has to be replayed to a fresh connection.
+struct Session {
+    /// The room we are in, joined again after a reconnect.
+    join: Option<ClientMessage>,
+    /// Our peer ID from the last welcome, and the secret to resume it with.
+    peer_id: Option<String>,
+    resume_secret: Option<String>,
+    queued: BoundedBuffer<ClientMessage>,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            join: None,
+            peer_id: None,
+            resume_secret: None,
+            queued: BoundedBuffer::new(MAX_QUEUED, MAX_QUEUED_BYTES, DropPolicy::DropNewest),
+        }
+    }
+
+    /// Keeps track of room membership as messages go out.
+    fn track(&mut self, message: &ClientMessage) {
+        match message {
+            ClientMessage::Join { .. } => self.join = Some(message.clone()),
+            ClientMessage::Leave => {
+                self.join = None;
+                self.peer_id = None;
+                self.resume_secret = None;
+                self.queued.clear();
+            }
+            ClientMessage::Signal { .. }
+            | ClientMessage::Chat { .. }
+            | ClientMessage::Invite { .. } => {}
+        }
+    }
+
+    /// Holds a message back for the next connection. Joins and leaves are
+    /// covered by `join`.
+    fn queue(&mut self, message: ClientMessage) {
+        let queueable = matches!(
+            message,
+            ClientMessage::Signal { .. } | ClientMessage::Chat { .. }
+        );
+        if queueable && self.queued.push(message) {
+            warn!("Signaling queue is full, dropping a message");
+        }
+    }
+
+    /// The join to send first on a new connection, then whatever queued up.
+    fn replay(&mut self) -> Vec<ClientMessage> {
+        let join = self.join.clone().map(|join| match join {
+            // The invite got us in already, and may have expired since.
+            ClientMessage::Join { room, name, .. } => ClientMessage::Join {
+                room,
+                name,
+                resume: self.peer_id.clone(),
+                resume_secret: self.resume_secret.clone(),
+                invite: None,
+            },
+            other => other,
+        });
+        join.into_iter().chain(self.queued.drain()).collect()
+    }
+}
+
+enum Ended {
+    /// The socket went away; try again.
+    Dropped,
+    /// Nobody is listening any more.
+    Closed,
+}
+
+fn request(url: &str, token: Option<&str>) -> Result<Request, SignalingError> {
+    let mut request = url.into_client_request()?;
+    if let Some(token) = token {
+        let value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|_| SignalingError::BadToken)?;
+        request.headers_mut().insert("Authorization", value);
+    }
+    Ok(request)
+}
+
+/// The web's usual roots, plus the PEM certificates in `ca_pem`.
+fn trusting(ca_pem: &[u8]) -> Result<Connector, SignalingError> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let mut added = 0;
+    for cert in rustls_pemfile::certs(&mut &ca_pem[..]) {
+        let cert = cert.map_err(|err| SignalingError::Certificate(err.to_string()))?;
+        roots
+            .add(cert)
+            .map_err(|err| SignalingError::Certificate(err.to_string()))?;
+        added += 1;
+    }
+    if added == 0 {
+        return Err(SignalingError::Certificate(
+            "no PEM certificates found".to_owned(),
+        ));
+    }
+    let config = rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Connector::Rustls(Arc::new(config)))
+}
+
+async fn open(
+    url: &str,
+    token: Option<&str>,
+    connector: Option<Connector>,
+) -> Result<Socket, SignalingError> {
+    let (socket, _) = tokio_tungstenite::connect_async_tls_with_config(
+        request(url, token)?,
+        None,
+        false,
+        connector,
+    )
+    .await?;
+    Ok(socket)
+}
+
+async fn send_message(socket: &mut Socket, message: &ClientMessage) -> Result<(), SignalingError> {
+    let text = serde_json::to_string(message).expect("client messages serialize");
+    Ok(socket.send(Message::Text(text)).await?)
+}
+
+/// Serves one connection until it drops.
+async fn run(
+    mut socket: Socket,
+    session: &mut Session,
+    outgoing: &mut mpsc::Receiver<ClientMessage>,
+    incoming: &mpsc::UnboundedSender<ServerMessage>,
+) -> Ended {
+    let mut replay = session.replay().into_iter();
+    while let Some(message) = replay.next() {
+        if let Err(err) = send_message(&mut socket, &message).await {
+            warn!("Failed to resend signaling message: {}", err);
+            session.queue(message);
+            replay.for_each(|message| session.queue(message));
+            return Ended::Dropped;
+        }
+    }
+    loop {
+        tokio::select! {
+            message = outgoing.recv() => {
+                let Some(message) = message else {
+                    let _ = socket.close(None).await;
+                    return Ended::Closed;
+                };
+                session.track(&message);
+                if let Err(err) = send_message(&mut socket, &message).await {
+                    warn!("Failed to send signaling message: {}", err);
+                    session.queue(message);
+                    return Ended::Dropped;
+                }
+            }
+            frame = socket.next() => {
+                let text = match frame {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return Ended::Dropped,
+                    Some(Ok(_)) => continue,
+                };
+                match serde_json::from_str::<ServerMessage>(&text) {
+                    Ok(message) => {
+                        if let ServerMessage::Welcome { peer_id, resume_secret, .. } = &message {
+                            session.peer_id = Some(peer_id.clone());
+                            session.resume_secret = resume_secret.clone();
+                        }
+                        if incoming.send(message).is_err() {
+                            return Ended::Closed;
+                        }
+                    }
+                    Err(err) => warn!("Ignoring malformed signaling message: {}", err),
+                }
+            }
+        }
+    }
+}
+
+impl SignalingClient {
+    /// Connects to `url`, sending `token` as a bearer token when the server
+    /// requires a login. Only this first attempt reports errors; later
+    /// drops are retried with backoff, as `health` shows.
+    pub async fn connect(
+        url: &str,
+        token: Option<&str>,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<ServerMessage>), SignalingError> {
+        Self::connect_with(url, token, None).await
+    }
+
+    /// Like `connect`, also trusting the certificates in `ca_pem` for
+    /// `wss://`, for self-hosted servers with a private CA or a
+    /// self-signed certificate.
+    pub async fn connect_trusting(
+        url: &str,
+        token: Option<&str>,
+        ca_pem: &[u8],
+    ) -> Result<(Self, mpsc::UnboundedReceiver<ServerMessage>), SignalingError> {
+        Self::connect_with(url, token, Some(trusting(ca_pem)?)).await
+    }
+
+    async fn connect_with(
+        url: &str,
+        token: Option<&str>,
+        connector: Option<Connector>,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<ServerMessage>), SignalingError> {
+        let socket = open(url, token, connector.clone()).await?;
+        info!("Connected to signaling server {}", url);
+
+        let (outgoing, mut outgoing_rx) = mpsc::channel::<ClientMessage>(MAX_OUTGOING);
+        let (incoming, incoming_rx) = mpsc::unbounded_channel();
+        let (health_tx, health) = watch::channel(SignalingHealth::Connected);
+        let token = Arc::new(Mutex::new(token.map(str::to_owned)));
+
+        let url = url.to_owned();
+        let current_token = Arc::clone(&token);
+        tokio::spawn(async move {
+            let mut session = Session::new();
+            let mut backoff = Backoff::new(INITIAL_RETRY, MAX_RETRY);
+            let mut socket = Some(socket);
+            loop {
+                if let Some(socket) = socket.take() {
+                    backoff.reset();
+                    health_tx.send_replace(SignalingHealth::Connected);
+                    let ended = run(socket, &mut session, &mut outgoing_rx, &incoming).await;
+                    if let Ended::Closed = ended {
+                        break;
+                    }
+                    info!("Signaling connection to {} dropped", url);
+                }
+
+                let retry_in = backoff.next_delay();
+                health_tx.send_replace(SignalingHealth::Reconnecting {
+                    attempt: backoff.attempt(),
+                    retry_in,
+                    queued: session.queued.len(),
+                });
+                let wait = tokio::time::sleep(retry_in);
+                tokio::pin!(wait);
+                loop {
+                    tokio::select! {
+                        _ = &mut wait => break,
+                        message = outgoing_rx.recv() => {
+                            let Some(message) = message else {
+                                health_tx.send_replace(SignalingHealth::Closed);
+                                return;
+                            };
+                            session.track(&message);
+                            session.queue(message);
+                        }
+                    }
+                }
+
+                let token = current_token.lock().unwrap().clone();
+                match open(&url, token.as_deref(), connector.clone()).await {
+                    Ok(reconnected) => {
+                        info!("Reconnected to signaling server {}", url);
+                        socket = Some(reconnected);
+                    }
+                    Err(err) => warn!("Failed to reconnect to {}: {}", url, err),
+                }
+            }
+            health_tx.send_replace(SignalingHealth::Closed);
+            info!("Signaling connection closed");
+        });
+
+        Ok((
+            Self {
+                outgoing,
+                health,
+                token,
+            },
+            incoming_rx,
+        ))
+    }
+
+    /// Queued while reconnecting, so only fails when the queue is full or
+    /// the connection has been closed.
+    pub fn send(&self, message: ClientMessage) -> Result<(), SignalingError> {
+        self.outgoing.try_send(message).map_err(|err| match err {
+            mpsc::error::TrySendError::Full(_) => SignalingError::Backlog,
+            mpsc::error::TrySendError::Closed(_) => SignalingError::Closed,
+        })
+    }
+
+    pub fn health(&self) -> SignalingHealth {
+        self.health.borrow().clone()
+    }
+
+    /// Changes as the connection drops and comes back.
+    pub fn watch_health(&self) -> watch::Receiver<SignalingHealth> {
+        self.health.clone()
+    }
+
+    /// Replaces the bearer token used for reconnecting, after a refresh.
+    pub fn set_token(&self, token: &str) {
+        *self.token.lock().unwrap() = Some(token.to_owned());
+    }
+}
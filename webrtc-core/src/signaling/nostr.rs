@@ -0,0 +1,510 @@
+//! Signaling over public Nostr relays, for when neither peer can host a
+//! signaling server. Offers and answers travel as NIP-04 encrypted,
+//! ephemeral events addressed to the other peer's public key; every relay
+//! gets every event and duplicates are dropped on arrival.
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use futures_util::{SinkExt, StreamExt};
+use k256::schnorr::{Signature, SigningKey, VerifyingKey};
+use log::{info, warn};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::{broadcast, mpsc};
+use tokio_tungstenite::tungstenite::Message;
+
+use super::SignalPayload;
+
+/// Ephemeral kinds (20000-29999) are forwarded but never stored by relays.
+pub const SIGNAL_KIND: u16 = 25050;
+/// How far back to ask relays for events, to cover clock skew.
+const LOOKBACK_SECS: u64 = 60;
+/// Events further than this from our clock either way are dropped, so
+/// their IDs need not be remembered longer.
+const MAX_EVENT_SKEW_SECS: u64 = 2 * LOOKBACK_SECS;
+/// Event IDs remembered at most, however many arrive at once.
+const MAX_SEEN: usize = 1024;
+
+type Aes256CbcEnc = cbc::Encryptor<aes::Aes256>;
+type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum NostrError {
+    #[error("invalid key: {0}")]
+    Key(String),
+    #[error("none of the relays accepted a connection")]
+    NoRelay,
+    #[error("signaling connection is closed")]
+    Closed,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim();
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// A Nostr identity. Peers exchange their public keys out of band.
+#[derive(Clone)]
+pub struct Keys {
+    secret: SigningKey,
+}
+
+impl Keys {
+    pub fn generate() -> Self {
+        Self {
+            secret: SigningKey::random(&mut OsRng),
+        }
+    }
+
+    pub fn from_secret_hex(secret: &str) -> Result<Self, NostrError> {
+        let bytes = from_hex(secret).ok_or_else(|| NostrError::Key("not hex".to_owned()))?;
+        let secret =
+            SigningKey::from_bytes(&bytes).map_err(|err| NostrError::Key(err.to_string()))?;
+        Ok(Self { secret })
+    }
+
+    pub fn secret_hex(&self) -> String {
+        to_hex(&self.secret.to_bytes())
+    }
+
+    /// The x-only public key, hex encoded as Nostr expects.
+    pub fn public_hex(&self) -> String {
+        to_hex(&self.secret.verifying_key().to_bytes())
+    }
+
+    /// The NIP-04 key shared with `peer`: the x coordinate of the ECDH point.
+    fn shared_secret(&self, peer: &VerifyingKey) -> [u8; 32] {
+        let shared = k256::ecdh::diffie_hellman(self.secret.as_nonzero_scalar(), peer.as_affine());
+        (*shared.raw_secret_bytes()).into()
+    }
+
+    fn encrypt(&self, peer: &VerifyingKey, plaintext: &str) -> String {
+        let mut iv = [0u8; 16];
+        OsRng.fill_bytes(&mut iv);
+        let ciphertext = Aes256CbcEnc::new(&self.shared_secret(peer).into(), &iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(plaintext.as_bytes());
+        format!("{}?iv={}", BASE64.encode(ciphertext), BASE64.encode(iv))
+    }
+
+    fn decrypt(&self, peer: &VerifyingKey, content: &str) -> Option<String> {
+        let (ciphertext, iv) = content.split_once("?iv=")?;
+        let ciphertext = BASE64.decode(ciphertext).ok()?;
+        let iv: [u8; 16] = BASE64.decode(iv).ok()?.try_into().ok()?;
+        let plaintext = Aes256CbcDec::new(&self.shared_secret(peer).into(), &iv.into())
+            .decrypt_padded_vec_mut::<Pkcs7>(&ciphertext)
+            .ok()?;
+        String::from_utf8(plaintext).ok()
+    }
+
+    fn sign(&self, kind: u16, tags: Vec<Vec<String>>, content: String) -> Event {
+        let mut event = Event {
+            id: String::new(),
+            pubkey: self.public_hex(),
+            created_at: now(),
+            kind,
+            tags,
+            content,
+            sig: String::new(),
+        };
+        let id = event.digest();
+        let mut aux = [0u8; 32];
+        OsRng.fill_bytes(&mut aux);
+        let signature = self
+            .secret
+            .sign_raw(&id, &aux)
+            .expect("signing a 32-byte digest");
+        event.id = to_hex(&id);
+        event.sig = to_hex(&signature.to_bytes());
+        event
+    }
+}
+
+fn verifying_key(public_hex: &str) -> Result<VerifyingKey, NostrError> {
+    let bytes = from_hex(public_hex).ok_or_else(|| NostrError::Key("not hex".to_owned()))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|err| NostrError::Key(err.to_string()))
+}
+
+/// A signed Nostr event (NIP-01).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Event {
+    id: String,
+    pubkey: String,
+    created_at: u64,
+    kind: u16,
+    tags: Vec<Vec<String>>,
+    content: String,
+    sig: String,
+}
+
+impl Event {
+    fn digest(&self) -> [u8; 32] {
+        let serialized = json!([
+            0,
+            self.pubkey,
+            self.created_at,
+            self.kind,
+            self.tags,
+            self.content
+        ]);
+        Sha256::digest(serialized.to_string()).into()
+    }
+
+    /// Checks the id and the signature over it.
+    fn verify(&self) -> Option<VerifyingKey> {
+        let digest = self.digest();
+        if from_hex(&self.id)? != digest {
+            return None;
+        }
+        let key = verifying_key(&self.pubkey).ok()?;
+        let signature = Signature::try_from(from_hex(&self.sig)?.as_slice()).ok()?;
+        key.verify_raw(&digest, &signature).ok()?;
+        Some(key)
+    }
+}
+
+/// The events already handled, to drop the copies other relays send.
+#[derive(Default)]
+struct Seen {
+    /// When each was created, by ID.
+    ids: HashMap<String, u64>,
+}
+
+impl Seen {
+    /// Whether `event` is recent and not seen before, remembering it if so.
+    fn first(&mut self, event: &Event, now: u64) -> bool {
+        let oldest = now.saturating_sub(MAX_EVENT_SKEW_SECS);
+        if event.created_at < oldest
+            || event.created_at > now + MAX_EVENT_SKEW_SECS
+            || self.ids.contains_key(&event.id)
+        {
+            return false;
+        }
+        if self.ids.len() >= MAX_SEEN {
+            self.ids.retain(|_, created_at| *created_at >= oldest);
+        }
+        if self.ids.len() >= MAX_SEEN {
+            let oldest = self
+                .ids
+                .iter()
+                .min_by_key(|(_, created_at)| **created_at)
+                .map(|(id, _)| id.clone());
+            if let Some(id) = oldest {
+                self.ids.remove(&id);
+            }
+        }
+        self.ids.insert(event.id.clone(), event.created_at);
+        true
+    }
+}
+
+/// A signal received from another peer, identified by public key.
+#[derive(Debug, Clone)]
+pub struct NostrSignal {
+    pub from: String,
+    pub payload: SignalPayload,
+}
+
+/// Handle to the relay connections. They close once every handle has been
+/// dropped.
+#[derive(Clone)]
+pub struct NostrSignaling {
+    keys: Keys,
+    outgoing: broadcast::Sender<String>,
+}
+
+impl NostrSignaling {
+    /// Connects to every relay that answers and subscribes to signals
+    /// addressed to `keys`.
+    pub async fn connect(
+        relays: &[String],
+        keys: Keys,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<NostrSignal>), NostrError> {
+        let (outgoing, _) = broadcast::channel(32);
+        let (events_tx, mut events_rx) = mpsc::unbounded_channel::<Event>();
+        let subscription = json!([
+            "REQ",
+            format!("webrtc-{:08x}", rand::random::<u32>()),
+            {
+                "kinds": [SIGNAL_KIND],
+                "#p": [keys.public_hex()],
+                "since": now().saturating_sub(LOOKBACK_SECS),
+            }
+        ])
+        .to_string();
+
+        let mut connected = 0;
+        for relay in relays.iter().map(|r| r.trim()).filter(|r| !r.is_empty()) {
+            let stream = match tokio_tungstenite::connect_async(relay).await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    warn!("Failed to connect to relay {}: {:?}", relay, err);
+                    continue;
+                }
+            };
+            info!("Connected to Nostr relay {}", relay);
+            connected += 1;
+            let (mut sink, mut source) = stream.split();
+            let mut outgoing_rx = outgoing.subscribe();
+            let subscription = subscription.clone();
+            tokio::spawn(async move {
+                if sink.send(Message::Text(subscription)).await.is_err() {
+                    return;
+                }
+                while let Ok(text) = outgoing_rx.recv().await {
+                    if let Err(err) = sink.send(Message::Text(text)).await {
+                        warn!("Failed to publish to relay: {:?}", err);
+                        break;
+                    }
+                }
+                let _ = sink.close().await;
+            });
+
+            let events_tx = events_tx.clone();
+            let relay = relay.to_owned();
+            tokio::spawn(async move {
+                while let Some(frame) = source.next().await {
+                    let text = match frame {
+                        Ok(Message::Text(text)) => text,
+                        Ok(Message::Close(_)) | Err(_) => break,
+                        Ok(_) => continue,
+                    };
+                    let Ok(Value::Array(message)) = serde_json::from_str::<Value>(&text) else {
+                        continue;
+                    };
+                    match message.first().and_then(Value::as_str) {
+                        Some("EVENT") => {
+                            let event = message.get(2).cloned().map(serde_json::from_value);
+                            if let Some(Ok(event)) = event {
+                                if events_tx.send(event).is_err() {
+                                    break;
+                                }
+                            }
+                        }
+                        Some("NOTICE") | Some("CLOSED") => {
+                            info!("Relay {} says: {}", relay, text)
+                        }
+                        _ => {}
+                    }
+                }
+                info!("Nostr relay {} closed", relay);
+            });
+        }
+        if connected == 0 {
+            return Err(NostrError::NoRelay);
+        }
+
+        let (signals_tx, signals) = mpsc::unbounded_channel();
+        let receiver = keys.clone();
+        tokio::spawn(async move {
+            let mut seen = Seen::default();
+            while let Some(event) = events_rx.recv().await {
+                if event.kind != SIGNAL_KIND {
+                    continue;
+                }
+                // Checked first, so a forged copy cannot claim the ID of a
+                // real event and have it dropped as seen.
+                let Some(sender) = event.verify() else {
+                    warn!("Dropping Nostr event {} with a bad signature", event.id);
+                    continue;
+                };
+                if !seen.first(&event, now()) {
+                    continue;
+                }
+                let payload = receiver
+                    .decrypt(&sender, &event.content)
+                    .and_then(|plaintext| serde_json::from_str(&plaintext).ok());
+                let Some(payload) = payload else {
+                    warn!("Dropping undecryptable Nostr event {}", event.id);
+                    continue;
+                };
+                let signal = NostrSignal {
+                    from: event.pubkey,
+                    payload,
+                };
+                if signals_tx.send(signal).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok((Self { keys, outgoing }, signals))
+    }
+
+    pub fn public_key(&self) -> String {
+        self.keys.public_hex()
+    }
+
+    /// Encrypts `payload` to `to` and publishes it on every relay.
+    pub fn send(&self, to: &str, payload: &SignalPayload) -> Result<(), NostrError> {
+        let peer = verifying_key(to)?;
+        let plaintext = serde_json::to_string(payload).expect("signal payloads serialize");
+        let event = self.keys.sign(
+            SIGNAL_KIND,
+            vec![vec!["p".to_owned(), to.trim().to_owned()]],
+            self.keys.encrypt(&peer, &plaintext),
+        );
+        self.outgoing
+            .send(json!(["EVENT", event]).to_string())
+            .map(|_| ())
+            .map_err(|_| NostrError::Closed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// From the nostr-tools NIP-04 tests, encrypted by go-nostr.
+    const SENDER_SECRET: &str = "91ba716fa9e7ea2fcbad360cf4f8e0d312f73984da63d90f524ad61a6a1e7dbe";
+    const RECEIVER_SECRET: &str =
+        "96f6fa197aa07477ab88f6981118466ae3a982faab8ad5db9d5426870c73d220";
+    const CIPHERTEXT: &str = "zJxfaJ32rN5Dg1ODjOlEew==?iv=EV5bUjcc4OX2Km/zPp4ndQ==";
+
+    fn keys(secret: &str) -> (Keys, VerifyingKey) {
+        let keys = Keys::from_secret_hex(secret).unwrap();
+        let public = verifying_key(&keys.public_hex()).unwrap();
+        (keys, public)
+    }
+
+    #[test]
+    fn decrypts_a_known_nip04_message() {
+        let (_, sender) = keys(SENDER_SECRET);
+        let (receiver, receiver_public) = keys(RECEIVER_SECRET);
+        assert_eq!(
+            receiver.decrypt(&sender, CIPHERTEXT).as_deref(),
+            Some("nanana")
+        );
+
+        let (_, stranger) = keys(&"11".repeat(32));
+        let (sender_keys, _) = keys(SENDER_SECRET);
+        let sent = sender_keys.encrypt(&receiver_public, "hello");
+        assert_eq!(receiver.decrypt(&sender, &sent).as_deref(), Some("hello"));
+        // A wrong key fails the padding check, or at worst reads garbage.
+        assert_ne!(
+            receiver.decrypt(&stranger, CIPHERTEXT).as_deref(),
+            Some("nanana")
+        );
+    }
+
+    #[test]
+    fn rejects_garbled_content() {
+        let (_, sender) = keys(SENDER_SECRET);
+        let (receiver, _) = keys(RECEIVER_SECRET);
+        let (ciphertext, iv) = CIPHERTEXT.split_once("?iv=").unwrap();
+        for garbled in [
+            ciphertext.to_owned(),
+            format!("{}?iv=", ciphertext),
+            format!("{}?iv=not base64!", ciphertext),
+            // Eight bytes, where AES-CBC takes sixteen.
+            format!("{}?iv=AAAAAAAAAAA=", ciphertext),
+            // Not a whole number of blocks.
+            format!("AAAA?iv={}", iv),
+            format!("!!{}?iv={}", ciphertext, iv),
+        ] {
+            assert_eq!(receiver.decrypt(&sender, &garbled), None, "{}", garbled);
+        }
+        // NIP-04 has no MAC: a changed IV only garbles the first block, so
+        // the payload's own parsing has to catch it.
+        let mut flipped = BASE64.decode(iv).unwrap();
+        flipped[0] ^= 1;
+        let flipped = format!("{}?iv={}", ciphertext, BASE64.encode(flipped));
+        assert_ne!(
+            receiver.decrypt(&sender, &flipped).as_deref(),
+            Some("nanana")
+        );
+    }
+
+    #[test]
+    fn checks_event_ids_and_signatures() {
+        let (sender, _) = keys(SENDER_SECRET);
+        let event = sender.sign(SIGNAL_KIND, vec![], "content".to_owned());
+        assert_eq!(event.id, to_hex(&event.digest()));
+        assert!(event.verify().is_some());
+
+        let changed = Event {
+            content: "changed".to_owned(),
+            ..event.clone()
+        };
+        assert!(changed.verify().is_none());
+        // A fresh ID over the change still lacks the signature for it.
+        let reissued = Event {
+            id: to_hex(&changed.digest()),
+            ..changed
+        };
+        assert!(reissued.verify().is_none());
+        let mut sig = from_hex(&event.sig).unwrap();
+        sig[63] ^= 1;
+        let forged = Event {
+            sig: to_hex(&sig),
+            ..event.clone()
+        };
+        assert!(forged.verify().is_none());
+        let (other, _) = keys(RECEIVER_SECRET);
+        let impostor = Event {
+            pubkey: other.public_hex(),
+            ..event
+        };
+        assert!(impostor.verify().is_none());
+    }
+
+    #[test]
+    fn forgets_events_too_old_to_accept() {
+        let (sender, _) = keys(SENDER_SECRET);
+        let event = sender.sign(SIGNAL_KIND, vec![], String::new());
+        let now = event.created_at;
+        let mut seen = Seen::default();
+        assert!(seen.first(&event, now));
+        assert!(!seen.first(&event, now + 1));
+        let stale = now + MAX_EVENT_SKEW_SECS + 1;
+        assert!(!seen.first(&event, stale));
+
+        let early = Event {
+            created_at: now + MAX_EVENT_SKEW_SECS + 1,
+            ..event.clone()
+        };
+        assert!(!seen.first(&early, now));
+
+        for n in 0..MAX_SEEN {
+            let later = Event {
+                id: format!("later-{}", n),
+                created_at: stale,
+                ..event.clone()
+            };
+            assert!(seen.first(&later, stale));
+        }
+        assert_eq!(seen.ids.len(), MAX_SEEN);
+        assert!(!seen.ids.contains_key(&event.id));
+        // All still recent: the oldest makes way.
+        let newest = Event {
+            id: "newest".to_owned(),
+            created_at: stale + 1,
+            ..event
+        };
+        assert!(seen.first(&newest, stale));
+        assert_eq!(seen.ids.len(), MAX_SEEN);
+        assert!(seen.ids.contains_key("newest"));
+    }
+}
@@ -0,0 +1,457 @@
+//! Serverless signaling over libp2p. Peers find each other by `PeerId`
+//! through the public Kademlia DHT and exchange offers and answers over a
+//! request-response stream.
+//!
+//! Only peers the DHT can reach end up in its routing tables, so a peer
+//! behind NAT may not be findable by id alone; dialing its full multiaddr
+//! (`/ip4/.../tcp/.../p2p/<id>`) works wherever the address is reachable,
+//! e.g. on a LAN.
+//!
+//! Peers that cannot be dialed at all can still be reached through a
+//! circuit relay: with relays configured we reserve a slot on each, and the
+//! `/p2p-circuit` addresses that gives us spread through identify and the
+//! DHT like any other. Once ICE has failed the same connection can carry
+//! text messages, as a media-less fallback for the call. Public relays cap
+//! circuits in time and volume, so this is for keeping in touch rather than
+//! for bulk data.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use futures_util::StreamExt;
+use libp2p::kad::{self, store::MemoryStore, GetClosestPeersOk, Mode, QueryResult};
+use libp2p::multiaddr::Protocol;
+use libp2p::request_response::{self, ProtocolSupport};
+use libp2p::swarm::{NetworkBehaviour, SwarmEvent};
+use libp2p::{identify, noise, relay, tcp, yamux, Multiaddr, PeerId, StreamProtocol, Swarm};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use super::SignalPayload;
+use crate::bounded::{BoundedBuffer, DropPolicy};
+
+pub use libp2p::identity::Keypair;
+
+const SIGNAL_PROTOCOL: &str = "/webrtc-rust-native-gui/signal/1";
+const DATA_PROTOCOL: &str = "/webrtc-rust-native-gui/data/1";
+/// Signals held per peer while the DHT looks it up.
+const MAX_PENDING: usize = 8;
+const MAX_PENDING_BYTES: usize = 256 * 1024;
+
+/// The public IPFS bootstrap nodes, which also serve the DHT we use.
+pub const DEFAULT_BOOTSTRAP: &[&str] = &[
+    "/dnsaddr/bootstrap.libp2p.io/p2p/QmNnooDu7bfjPFoTZYxMNLWUQJyrVwtbZg5gBMjTezGAJN",
+    "/dnsaddr/bootstrap.libp2p.io/p2p/QmQCU2EcMqAqQPR2i9bChDtGNJchTbq5TbXJJ16u19uLTa",
+    "/dnsaddr/bootstrap.libp2p.io/p2p/QmbLHAnMoJPWSCR5Zhtx6BHJX9KiKNN6tpvbUcqanj75Nb",
+    "/dnsaddr/bootstrap.libp2p.io/p2p/QmcZf59bWwK5XFi76CZX8cbJ4BhTzzA3gU1ZjYZcYW3dwt",
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum P2pError {
+    #[error("invalid key: {0}")]
+    Key(String),
+    #[error("invalid peer: {0}")]
+    Peer(String),
+    #[error("libp2p transport error: {0}")]
+    Transport(String),
+    #[error("signaling connection is closed")]
+    Closed,
+}
+
+/// A new ed25519 identity; its `PeerId` is what other peers dial.
+pub fn generate_keypair() -> Keypair {
+    Keypair::generate_ed25519()
+}
+
+/// Restores an identity saved with [`encode_keypair`].
+pub fn decode_keypair(saved: &str) -> Result<Keypair, P2pError> {
+    let bytes = BASE64
+        .decode(saved.trim())
+        .map_err(|err| P2pError::Key(err.to_string()))?;
+    Keypair::from_protobuf_encoding(&bytes).map_err(|err| P2pError::Key(err.to_string()))
+}
+
+pub fn encode_keypair(keypair: &Keypair) -> String {
+    BASE64.encode(keypair.to_protobuf_encoding().expect("ed25519 keys encode"))
+}
+
+/// Who to call: a bare `PeerId` to look up in the DHT, or a multiaddr
+/// ending in `/p2p/<id>` to dial directly.
+pub fn parse_peer(text: &str) -> Result<(PeerId, Option<Multiaddr>), P2pError> {
+    let text = text.trim();
+    if let Ok(peer) = text.parse::<PeerId>() {
+        return Ok((peer, None));
+    }
+    let address: Multiaddr = text
+        .parse()
+        .map_err(|err: libp2p::multiaddr::Error| P2pError::Peer(err.to_string()))?;
+    match address.iter().last() {
+        Some(Protocol::P2p(peer)) => Ok((peer, Some(address))),
+        _ => Err(P2pError::Peer(format!(
+            "{} does not end in /p2p/<id>",
+            text
+        ))),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SignalRequest {
+    payload: SignalPayload,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SignalAck {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DataRequest {
+    text: String,
+}
+
+#[derive(NetworkBehaviour)]
+struct Behaviour {
+    kademlia: kad::Behaviour<MemoryStore>,
+    identify: identify::Behaviour,
+    relay_client: relay::client::Behaviour,
+    signal: request_response::json::Behaviour<SignalRequest, SignalAck>,
+    data: request_response::json::Behaviour<DataRequest, SignalAck>,
+}
+
+/// Whether an address goes through a circuit relay.
+pub fn is_relayed(address: &Multiaddr) -> bool {
+    address
+        .iter()
+        .any(|protocol| protocol == Protocol::P2pCircuit)
+}
+
+/// A signal received from another peer.
+#[derive(Debug, Clone)]
+pub struct P2pSignal {
+    pub from: String,
+    pub payload: SignalPayload,
+}
+
+#[derive(Debug, Clone)]
+pub enum P2pEvent {
+    Listening(Multiaddr),
+    Signal(P2pSignal),
+    /// A connection to `peer` is up, possibly through a relay.
+    Connected {
+        peer: String,
+        relayed: bool,
+    },
+    /// A text message sent with [`P2pSignaling::send_text`].
+    Text {
+        from: String,
+        text: String,
+    },
+    /// A signal could not be delivered.
+    SendFailed {
+        to: String,
+        reason: String,
+    },
+}
+
+enum Command {
+    Send {
+        to: PeerId,
+        address: Option<Multiaddr>,
+        payload: SignalPayload,
+    },
+    SendText {
+        to: PeerId,
+        address: Option<Multiaddr>,
+        text: String,
+    },
+}
+
+/// Handle to the running swarm. It shuts down once every handle has been
+/// dropped.
+#[derive(Clone)]
+pub struct P2pSignaling {
+    peer_id: PeerId,
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl P2pSignaling {
+    /// Starts a swarm with `keypair`, listening on every interface and
+    /// joining the DHT through `bootstrap`. Each of `relays`, a multiaddr
+    /// ending in `/p2p/<id>`, is asked for a reservation so peers can reach
+    /// us through it.
+    pub fn start(
+        keypair: Keypair,
+        bootstrap: &[String],
+        relays: &[String],
+    ) -> Result<(Self, mpsc::UnboundedReceiver<P2pEvent>), P2pError> {
+        let peer_id = keypair.public().to_peer_id();
+        let mut swarm = libp2p::SwarmBuilder::with_existing_identity(keypair)
+            .with_tokio()
+            .with_tcp(
+                tcp::Config::default(),
+                noise::Config::new,
+                yamux::Config::default,
+            )
+            .map_err(|err| P2pError::Transport(err.to_string()))?
+            .with_dns()
+            .map_err(|err| P2pError::Transport(err.to_string()))?
+            .with_relay_client(noise::Config::new, yamux::Config::default)
+            .map_err(|err| P2pError::Transport(err.to_string()))?
+            .with_behaviour(|key, relay_client| {
+                let peer_id = key.public().to_peer_id();
+                Behaviour {
+                    kademlia: kad::Behaviour::new(peer_id, MemoryStore::new(peer_id)),
+                    identify: identify::Behaviour::new(identify::Config::new(
+                        "/ipfs/id/1.0.0".to_owned(),
+                        key.public(),
+                    )),
+                    relay_client,
+                    signal: request_response::json::Behaviour::new(
+                        [(StreamProtocol::new(SIGNAL_PROTOCOL), ProtocolSupport::Full)],
+                        request_response::Config::default(),
+                    ),
+                    data: request_response::json::Behaviour::new(
+                        [(StreamProtocol::new(DATA_PROTOCOL), ProtocolSupport::Full)],
+                        request_response::Config::default(),
+                    ),
+                }
+            })
+            .map_err(|err| P2pError::Transport(err.to_string()))?
+            .with_swarm_config(|config| {
+                config.with_idle_connection_timeout(Duration::from_secs(60))
+            })
+            .build();
+
+        // Answer DHT queries so peers looking for us can find us.
+        swarm.behaviour_mut().kademlia.set_mode(Some(Mode::Server));
+        for address in bootstrap.iter().map(|a| a.trim()).filter(|a| !a.is_empty()) {
+            match parse_peer(address) {
+                Ok((peer, Some(address))) => {
+                    swarm.behaviour_mut().kademlia.add_address(&peer, address);
+                }
+                _ => warn!("Ignoring bootstrap address {}", address),
+            }
+        }
+        if let Err(err) = swarm.behaviour_mut().kademlia.bootstrap() {
+            warn!("Cannot join the DHT: {}", err);
+        }
+        for listen in ["/ip4/0.0.0.0/tcp/0", "/ip6/::/tcp/0"] {
+            if let Err(err) = swarm.listen_on(listen.parse().expect("valid multiaddr")) {
+                info!("Not listening on {}: {}", listen, err);
+            }
+        }
+        for address in relays.iter().map(|a| a.trim()).filter(|a| !a.is_empty()) {
+            let Ok((relay, Some(address))) = parse_peer(address) else {
+                warn!("Ignoring relay address {}", address);
+                continue;
+            };
+            swarm
+                .behaviour_mut()
+                .kademlia
+                .add_address(&relay, address.clone());
+            if let Err(err) = swarm.listen_on(address.with(Protocol::P2pCircuit)) {
+                warn!("Cannot reserve a slot on relay {}: {}", relay, err);
+            }
+        }
+
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let (events, events_rx) = mpsc::unbounded_channel();
+        tokio::spawn(run(swarm, commands_rx, events));
+        Ok((Self { peer_id, commands }, events_rx))
+    }
+
+    pub fn peer_id(&self) -> String {
+        self.peer_id.to_string()
+    }
+
+    /// Sends `payload` to `to`, a `PeerId` or a `/p2p/` multiaddr.
+    pub fn send(&self, to: &str, payload: SignalPayload) -> Result<(), P2pError> {
+        let (to, address) = parse_peer(to)?;
+        self.commands
+            .send(Command::Send {
+                to,
+                address,
+                payload,
+            })
+            .map_err(|_| P2pError::Closed)
+    }
+
+    /// Sends a text message to `to` over libp2p, relayed or not, for when
+    /// there is no data channel to carry it.
+    pub fn send_text(&self, to: &str, text: String) -> Result<(), P2pError> {
+        let (to, address) = parse_peer(to)?;
+        self.commands
+            .send(Command::SendText { to, address, text })
+            .map_err(|_| P2pError::Closed)
+    }
+}
+
+async fn run(
+    mut swarm: Swarm<Behaviour>,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    events: mpsc::UnboundedSender<P2pEvent>,
+) {
+    // Signals waiting for a DHT lookup of their recipient.
+    let mut pending: HashMap<PeerId, BoundedBuffer<SignalPayload>> = HashMap::new();
+    loop {
+        tokio::select! {
+            command = commands.recv() => {
+                let (to, address, payload) = match command {
+                    Some(Command::Send { to, address, payload }) => (to, address, payload),
+                    Some(Command::SendText { to, address, text }) => {
+                        if let Some(address) = address {
+                            swarm.add_peer_address(to, address);
+                        }
+                        swarm.behaviour_mut().data.send_request(&to, DataRequest { text });
+                        continue;
+                    }
+                    None => break,
+                };
+                // A known address is dialed right away; otherwise ask the DHT.
+                let direct = address.is_some() || swarm.is_connected(&to);
+                if let Some(address) = address {
+                    swarm.add_peer_address(to, address);
+                }
+                if direct {
+                    swarm.behaviour_mut().signal.send_request(&to, SignalRequest { payload });
+                } else {
+                    info!("Looking up {} in the DHT", to);
+                    let queue = pending.entry(to).or_insert_with(|| {
+                        BoundedBuffer::new(MAX_PENDING, MAX_PENDING_BYTES, DropPolicy::DropNewest)
+                    });
+                    if queue.push(payload) {
+                        let failed = P2pEvent::SendFailed {
+                            to: to.to_string(),
+                            reason: "too many signals waiting for the peer lookup".to_owned(),
+                        };
+                        if events.send(failed).is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                    swarm.behaviour_mut().kademlia.get_closest_peers(to);
+                }
+            }
+            event = swarm.select_next_some() => {
+                let Some(event) = handle_event(&mut swarm, event, &mut pending) else {
+                    continue;
+                };
+                if events.send(event).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    info!("libp2p signaling stopped");
+}
+
+fn handle_event(
+    swarm: &mut Swarm<Behaviour>,
+    event: SwarmEvent<BehaviourEvent>,
+    pending: &mut HashMap<PeerId, BoundedBuffer<SignalPayload>>,
+) -> Option<P2pEvent> {
+    match event {
+        SwarmEvent::NewListenAddr { address, .. } => {
+            let address = address.with(Protocol::P2p(*swarm.local_peer_id()));
+            info!("libp2p listening on {}", address);
+            Some(P2pEvent::Listening(address))
+        }
+        SwarmEvent::ConnectionEstablished {
+            peer_id, endpoint, ..
+        } => Some(P2pEvent::Connected {
+            peer: peer_id.to_string(),
+            relayed: is_relayed(endpoint.get_remote_address()),
+        }),
+        SwarmEvent::Behaviour(BehaviourEvent::RelayClient(
+            relay::client::Event::ReservationReqAccepted { relay_peer_id, .. },
+        )) => {
+            info!("Reachable through relay {}", relay_peer_id);
+            None
+        }
+        SwarmEvent::Behaviour(BehaviourEvent::Identify(identify::Event::Received {
+            peer_id,
+            info,
+        })) => {
+            // What peers say they listen on is what the DHT hands out.
+            for address in info.listen_addrs {
+                swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .add_address(&peer_id, address);
+            }
+            None
+        }
+        SwarmEvent::Behaviour(BehaviourEvent::Kademlia(kad::Event::OutboundQueryProgressed {
+            result: QueryResult::GetClosestPeers(result),
+            step,
+            ..
+        })) => {
+            let (key, found) = match result {
+                Ok(GetClosestPeersOk { key, peers }) => {
+                    let found = PeerId::from_bytes(&key).is_ok_and(|t| peers.contains(&t));
+                    (key, found)
+                }
+                Err(err) => (err.key().clone(), false),
+            };
+            let target = PeerId::from_bytes(&key).ok()?;
+            if !step.last && !found {
+                return None;
+            }
+            // Either the DHT knows where the peer is now, or sending fails
+            // with a dial error that is reported below.
+            let mut queue = pending.remove(&target)?;
+            for payload in queue.drain() {
+                swarm
+                    .behaviour_mut()
+                    .signal
+                    .send_request(&target, SignalRequest { payload });
+            }
+            None
+        }
+        SwarmEvent::Behaviour(BehaviourEvent::Signal(request_response::Event::Message {
+            peer,
+            message:
+                request_response::Message::Request {
+                    request, channel, ..
+                },
+        })) => {
+            let _ = swarm
+                .behaviour_mut()
+                .signal
+                .send_response(channel, SignalAck {});
+            Some(P2pEvent::Signal(P2pSignal {
+                from: peer.to_string(),
+                payload: request.payload,
+            }))
+        }
+        SwarmEvent::Behaviour(BehaviourEvent::Data(request_response::Event::Message {
+            peer,
+            message:
+                request_response::Message::Request {
+                    request, channel, ..
+                },
+        })) => {
+            let _ = swarm
+                .behaviour_mut()
+                .data
+                .send_response(channel, SignalAck {});
+            Some(P2pEvent::Text {
+                from: peer.to_string(),
+                text: request.text,
+            })
+        }
+        SwarmEvent::Behaviour(BehaviourEvent::Signal(
+            request_response::Event::OutboundFailure { peer, error, .. },
+        ))
+        | SwarmEvent::Behaviour(BehaviourEvent::Data(request_response::Event::OutboundFailure {
+            peer,
+            error,
+            ..
+        })) => Some(P2pEvent::SendFailed {
+            to: peer.to_string(),
+            reason: error.to_string(),
+        }),
+        _ => None,
+    }
+}
@@ -0,0 +1,123 @@
+//! JSON messages exchanged with the signaling server over WebSocket.
+
+use serde::{Deserialize, Serialize};
+
+use crate::bounded::Footprint;
+use crate::chat::ChatMessage;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerInfo {
+    pub peer_id: String,
+    pub name: String,
+}
+
+/// Messages relayed between peers through the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SignalPayload {
+    Offer { sdp: String },
+    Answer { sdp: String },
+}
+
+impl Footprint for SignalPayload {
+    fn footprint(&self) -> usize {
+        match self {
+            SignalPayload::Offer { sdp } | SignalPayload::Answer { sdp } => sdp.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Join {
+        room: String,
+        name: String,
+        /// Our peer ID before a reconnect, for servers that can hand it back
+        /// so the room sees no leave and rejoin.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        resume: Option<String>,
+        /// The secret the welcome gave us with that ID, so nobody else who
+        /// saw it can take our place.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        resume_secret: Option<String>,
+        /// The token of the invite link being followed, which the server
+        /// checks before letting us in.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        invite: Option<String>,
+    },
+    Leave,
+    Signal {
+        to: String,
+        payload: SignalPayload,
+    },
+    /// Chat for the rest of the room, on servers that relay it.
+    Chat {
+        message: ChatMessage,
+    },
+    /// Asks for an invite token for the room we are in, working for
+    /// `valid_for` seconds or the server's limit.
+    Invite {
+        valid_for: u64,
+    },
+}
+
+impl Footprint for ClientMessage {
+    fn footprint(&self) -> usize {
+        match self {
+            ClientMessage::Join {
+                room,
+                name,
+                resume,
+                resume_secret,
+                invite,
+            } => {
+                room.len()
+                    + name.len()
+                    + resume.as_ref().map_or(0, String::len)
+                    + resume_secret.as_ref().map_or(0, String::len)
+                    + invite.as_ref().map_or(0, String::len)
+            }
+            ClientMessage::Leave | ClientMessage::Invite { .. } => 0,
+            ClientMessage::Signal { to, payload } => to.len() + payload.footprint(),
+            ClientMessage::Chat { message } => message.footprint(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// Sent once after joining; `peers` lists the other members in join order.
+    Welcome {
+        peer_id: String,
+        peers: Vec<PeerInfo>,
+        /// To resume as `peer_id` after a reconnect, on servers that can;
+        /// only we are sent it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        resume_secret: Option<String>,
+    },
+    PeerJoined {
+        peer: PeerInfo,
+    },
+    PeerLeft {
+        peer_id: String,
+    },
+    Signal {
+        from: String,
+        payload: SignalPayload,
+    },
+    /// Chat from another member, or from the room's history right after
+    /// the welcome.
+    Chat {
+        from: String,
+        message: ChatMessage,
+    },
+    /// The token asked for with an invite request.
+    Invite {
+        token: String,
+    },
+    Error {
+        message: String,
+    },
+}
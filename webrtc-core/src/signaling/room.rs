@@ -0,0 +1,93 @@
+//! Room-code rendezvous on top of the signaling server.
+//!
+//! Users share a short, human-friendly room code; both sides hash it into the
+//! same server channel, and the first two members of that channel negotiate
+//! with each other: the one who joined second sends the offer.
+
+use sha2::{Digest, Sha256};
+
+use super::{PeerInfo, ServerMessage};
+
+/// Derives the server-side channel name for a room code. Codes are
+/// case-insensitive and ignore surrounding whitespace and dashes, so
+/// "ab-12" and "AB12" meet in the same room.
+pub fn room_channel(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect();
+    let digest = Sha256::digest(format!("webrtc-rust-native-gui/room/{}", normalized));
+    digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Room {
+    pub code: String,
+    pub channel: String,
+    pub local_peer_id: Option<String>,
+    /// Every member including ourselves, in join order.
+    pub members: Vec<PeerInfo>,
+}
+
+impl Room {
+    pub fn new(code: &str) -> Self {
+        Self {
+            code: code.to_owned(),
+            channel: room_channel(code),
+            ..Default::default()
+        }
+    }
+
+    /// Updates membership from a server message.
+    pub fn apply(&mut self, message: &ServerMessage, local_name: &str) {
+        match message {
+            ServerMessage::Welcome { peer_id, peers, .. } => {
+                self.local_peer_id = Some(peer_id.clone());
+                self.members = peers.clone();
+                self.members.push(PeerInfo {
+                    peer_id: peer_id.clone(),
+                    name: local_name.to_owned(),
+                });
+            }
+            ServerMessage::PeerJoined { peer } => {
+                if !self.members.iter().any(|m| m.peer_id == peer.peer_id) {
+                    self.members.push(peer.clone());
+                }
+            }
+            ServerMessage::PeerLeft { peer_id } => {
+                self.members.retain(|m| &m.peer_id != peer_id);
+            }
+            ServerMessage::Signal { .. }
+            | ServerMessage::Chat { .. }
+            | ServerMessage::Invite { .. }
+            | ServerMessage::Error { .. } => {}
+        }
+    }
+
+    pub fn is_local(&self, peer_id: &str) -> bool {
+        self.local_peer_id.as_deref() == Some(peer_id)
+    }
+
+    /// The peer we negotiate with, if we are one of the first two members.
+    pub fn partner(&self) -> Option<&PeerInfo> {
+        let local = self.local_peer_id.as_deref()?;
+        let pair = self.members.get(..2)?;
+        if !pair.iter().any(|m| m.peer_id == local) {
+            return None;
+        }
+        pair.iter().find(|m| m.peer_id != local)
+    }
+
+    /// Peer ID of the side that sends the offer: whoever joined second.
+    /// The first member waits to answer, so both ends agree from join order
+    /// alone and nobody has to pick a role.
+    pub fn offerer(&self) -> Option<&str> {
+        self.partner()?;
+        self.members.get(1).map(|member| member.peer_id.as_str())
+    }
+
+    pub fn we_offer(&self) -> bool {
+        self.offerer().is_some_and(|id| self.is_local(id))
+    }
+}
@@ -0,0 +1,505 @@
+//! Simulcast: one video source encoded at up to three sizes at once on a
+//! single sender, each layer an RTP stream of its own told apart by its
+//! `rid` (RFC 8851, 8852), so an SFU can forward each subscriber the layer
+//! that suits its link. webrtc-rs binds one SSRC per sender and knows no
+//! sending rids, so `SimulcastTrack` packetizes the layers itself and
+//! `set_local_offer` writes the rids into the offer.
+
+use std::any::Any;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use log::info;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use webrtc::api::media_engine::MediaEngine;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp::packetizer::{new_packetizer, Packetizer};
+use webrtc::rtp::sequence::new_random_sequencer;
+use webrtc::rtp_transceiver::rtp_codec::{
+    RTCRtpCodecCapability, RTCRtpCodecParameters, RTCRtpHeaderExtensionCapability, RTPCodecType,
+};
+use webrtc::sdp::extmap::{SDES_MID_URI, SDES_RTP_STREAM_ID_URI};
+use webrtc::track::track_local::{TrackLocal, TrackLocalContext, TrackLocalWriter};
+
+use crate::codec::{self, EncoderConfig, ProfileLevelId};
+use crate::video::{downscale, TestPattern, VideoEncoder, VideoFrame};
+
+/// What webrtc-rs packetizes its own tracks to.
+const MTU: usize = 1200;
+/// Layers smaller than this many rows are left out.
+const MIN_HEIGHT: usize = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimulcastLayer {
+    pub rid: &'static str,
+    /// How many times smaller than the source each side is.
+    pub scale_down: usize,
+    pub max_bitrate_bps: u32,
+}
+
+/// Every layer, largest first, with the rids and sizes browsers use.
+pub const LAYERS: [SimulcastLayer; 3] = [
+    SimulcastLayer {
+        rid: "h",
+        scale_down: 1,
+        max_bitrate_bps: 1_200_000,
+    },
+    SimulcastLayer {
+        rid: "m",
+        scale_down: 2,
+        max_bitrate_bps: 400_000,
+    },
+    SimulcastLayer {
+        rid: "l",
+        scale_down: 4,
+        max_bitrate_bps: 120_000,
+    },
+];
+
+/// The layers worth sending of a `width` by `height` source; fewer than
+/// two means simulcast is no use.
+pub fn layers_for(width: usize, height: usize) -> &'static [SimulcastLayer] {
+    let count = LAYERS
+        .iter()
+        .take_while(|layer| {
+            (width / layer.scale_down) & !1 > 0 && height / layer.scale_down >= MIN_HEIGHT
+        })
+        .count();
+    &LAYERS[..count.max(1)]
+}
+
+/// Lets packets carry their rid and mid, which receivers tell the
+/// layers apart by. Peers that answer without both only get the first
+/// layer.
+pub fn register_extensions(media_engine: &mut MediaEngine) -> Result<(), webrtc::Error> {
+    for uri in [SDES_MID_URI, SDES_RTP_STREAM_ID_URI] {
+        media_engine.register_header_extension(
+            RTCRtpHeaderExtensionCapability {
+                uri: uri.to_owned(),
+            },
+            RTPCodecType::Video,
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+/// Makes `pc`'s offer and sets it as the local description, returning
+/// it with `track`'s layers written in for the remote; webrtc-rs takes no
+/// local offer but its own.
+pub async fn set_local_offer(
+    pc: &RTCPeerConnection,
+    track: &SimulcastTrack,
+) -> Result<RTCSessionDescription, webrtc::Error> {
+    let offer = pc.create_offer(None).await?;
+    pc.set_local_description(offer.clone()).await?;
+    let mut mid = None;
+    for transceiver in pc.get_transceivers().await {
+        let sent = transceiver.sender().await.track().await;
+        let ours = sent.as_ref().is_some_and(|sent| {
+            sent.as_any()
+                .downcast_ref::<SimulcastTrack>()
+                .is_some_and(|sent| std::ptr::eq(sent, track))
+        });
+        if ours {
+            mid = transceiver.mid();
+            break;
+        }
+    }
+    let Some(mid) = mid else {
+        return Ok(offer);
+    };
+    *track.mid.lock().unwrap() = Some(mid.to_string());
+    RTCSessionDescription::offer(offer_sdp(&offer.sdp, &mid, track.layers))
+}
+
+/// `sdp` with `layers` offered as simulcast in the section of `mid`, in
+/// place of its SSRCs.
+pub fn offer_sdp(sdp: &str, mid: &str, layers: &[SimulcastLayer]) -> String {
+    let mut sections: Vec<String> = vec![String::new()];
+    for line in sdp.lines() {
+        if line.starts_with("m=") {
+            sections.push(String::new());
+        }
+        let section = sections.last_mut().unwrap();
+        section.push_str(line.trim_end());
+        section.push_str("\r\n");
+    }
+    let mid_line = format!("a=mid:{}\r\n", mid);
+    if let Some(section) = sections
+        .iter_mut()
+        .skip(1)
+        .find(|section| section.split_inclusive("\r\n").any(|line| line == mid_line))
+    {
+        // The rids name the streams; receivers that see an SSRC too take
+        // it for the only one.
+        *section = section
+            .split_inclusive("\r\n")
+            .filter(|line| !line.starts_with("a=ssrc:") && !line.starts_with("a=ssrc-group:"))
+            .collect();
+        for layer in layers {
+            section.push_str(&format!("a=rid:{} send\r\n", layer.rid));
+        }
+        let rids: Vec<&str> = layers.iter().map(|layer| layer.rid).collect();
+        section.push_str(&format!("a=simulcast:send {}\r\n", rids.join(";")));
+    }
+    sections.concat()
+}
+
+/// What has gone out on one layer so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayerStats {
+    pub layer: SimulcastLayer,
+    pub bytes_sent: u64,
+    pub packets_sent: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    bytes: AtomicU64,
+    packets: AtomicU64,
+}
+
+/// The sender this track was bound to, with a packetizer per layer.
+struct Binding {
+    id: String,
+    write_stream: Arc<dyn TrackLocalWriter + Send + Sync>,
+    clock_rate: u32,
+    /// The ids of the rid and mid header extensions, when the remote took
+    /// both.
+    extensions: Option<(u8, u8)>,
+    packetizers: Vec<Box<dyn Packetizer + Send + Sync>>,
+}
+
+/// A video track sent as several layers. The first layer goes out on
+/// the SSRC webrtc-rs gave the sender, so its reports and
+/// retransmissions cover it; the others on SSRCs of their own.
+pub struct SimulcastTrack {
+    codec: RTCRtpCodecCapability,
+    id: String,
+    stream_id: String,
+    layers: &'static [SimulcastLayer],
+    counters: Vec<Counters>,
+    /// The sender's mid, known once the offer is made.
+    mid: std::sync::Mutex<Option<String>>,
+    binding: Mutex<Option<Binding>>,
+}
+
+impl SimulcastTrack {
+    pub fn new(
+        codec: RTCRtpCodecCapability,
+        id: String,
+        stream_id: String,
+        layers: &'static [SimulcastLayer],
+    ) -> Self {
+        Self {
+            codec,
+            id,
+            stream_id,
+            layers,
+            counters: layers.iter().map(|_| Counters::default()).collect(),
+            mid: std::sync::Mutex::new(None),
+            binding: Mutex::new(None),
+        }
+    }
+
+    pub fn codec(&self) -> &RTCRtpCodecCapability {
+        &self.codec
+    }
+
+    pub fn layers(&self) -> &'static [SimulcastLayer] {
+        self.layers
+    }
+
+    pub fn layer_stats(&self) -> Vec<LayerStats> {
+        self.layers
+            .iter()
+            .zip(&self.counters)
+            .map(|(layer, counters)| LayerStats {
+                layer: *layer,
+                bytes_sent: counters.bytes.load(Ordering::Relaxed),
+                packets_sent: counters.packets.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// Sends one encoded frame of layer `index`, lasting `duration`.
+    /// Nothing goes out before the track is bound.
+    pub async fn write_layer(
+        &self,
+        index: usize,
+        data: &Bytes,
+        duration: Duration,
+    ) -> Result<(), webrtc::Error> {
+        let mut binding = self.binding.lock().await;
+        let Some(binding) = binding.as_mut() else {
+            return Ok(());
+        };
+        if index > 0 && binding.extensions.is_none() {
+            return Ok(());
+        }
+        let mid = self.mid.lock().unwrap().clone().unwrap_or_default();
+        let (Some(layer), Some(packetizer)) =
+            (self.layers.get(index), binding.packetizers.get_mut(index))
+        else {
+            return Ok(());
+        };
+        let samples = (duration.as_secs_f64() * f64::from(binding.clock_rate)) as u32;
+        let packets = packetizer.packetize(data, samples)?;
+        let counters = &self.counters[index];
+        for mut packet in packets {
+            if let Some((rid_id, mid_id)) = binding.extensions {
+                packet
+                    .header
+                    .set_extension(rid_id, Bytes::from_static(layer.rid.as_bytes()))?;
+                packet
+                    .header
+                    .set_extension(mid_id, Bytes::from(mid.clone()))?;
+            }
+            binding.write_stream.write_rtp(&packet).await?;
+            counters
+                .bytes
+                .fetch_add(packet.payload.len() as u64, Ordering::Relaxed);
+            counters.packets.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TrackLocal for SimulcastTrack {
+    async fn bind(&self, t: &TrackLocalContext) -> Result<RTCRtpCodecParameters, webrtc::Error> {
+        let same_codec = |codec: &&RTCRtpCodecParameters| {
+            codec
+                .capability
+                .mime_type
+                .eq_ignore_ascii_case(&self.codec.mime_type)
+        };
+        let codecs = t.codec_parameters();
+        let codec = codecs
+            .iter()
+            .filter(same_codec)
+            .find(|codec| codec.capability.sdp_fmtp_line == self.codec.sdp_fmtp_line)
+            .or_else(|| codecs.iter().find(same_codec))
+            .ok_or(webrtc::Error::ErrUnsupportedCodec)?
+            .clone();
+        let extension = |uri: &str| {
+            t.header_extensions()
+                .iter()
+                .find(|extension| extension.uri == uri)
+                .map(|extension| extension.id as u8)
+        };
+        let extensions = extension(SDES_RTP_STREAM_ID_URI).zip(extension(SDES_MID_URI));
+        if extensions.is_none() {
+            info!("The remote takes no rids; sending only the largest simulcast layer");
+        }
+        let write_stream = t
+            .write_stream()
+            .ok_or_else(|| webrtc::Error::new("the sender has no stream to write to".to_owned()))?;
+        let mut packetizers = vec![];
+        for index in 0..self.layers.len() {
+            let ssrc = if index == 0 { t.ssrc() } else { rand::random() };
+            packetizers.push(Box::new(new_packetizer(
+                MTU,
+                codec.payload_type,
+                ssrc,
+                codec.capability.payloader_for_codec()?,
+                Box::new(new_random_sequencer()),
+                codec.capability.clock_rate,
+            )) as Box<dyn Packetizer + Send + Sync>);
+        }
+        *self.binding.lock().await = Some(Binding {
+            id: t.id(),
+            write_stream,
+            clock_rate: codec.capability.clock_rate,
+            extensions,
+            packetizers,
+        });
+        Ok(codec)
+    }
+
+    async fn unbind(&self, t: &TrackLocalContext) -> Result<(), webrtc::Error> {
+        let mut binding = self.binding.lock().await;
+        match &*binding {
+            Some(bound) if bound.id == t.id() => {
+                *binding = None;
+                Ok(())
+            }
+            _ => Err(webrtc::Error::ErrUnbindFailed),
+        }
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn stream_id(&self) -> &str {
+        &self.stream_id
+    }
+
+    fn kind(&self) -> RTPCodecType {
+        RTPCodecType::Video
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// An encoder per layer of a track, each fed the source scaled down to
+/// its size.
+pub struct SimulcastEncoder {
+    encoders: Vec<(usize, Box<dyn VideoEncoder>)>,
+}
+
+impl SimulcastEncoder {
+    pub fn new(
+        track: &SimulcastTrack,
+        width: usize,
+        height: usize,
+        frame_rate: f32,
+    ) -> Option<Self> {
+        let capability = track.codec();
+        let profile = ProfileLevelId::from_fmtp(&capability.sdp_fmtp_line);
+        let encoders = track
+            .layers()
+            .iter()
+            .map(|layer| {
+                let (width, height) = (
+                    (width / layer.scale_down) & !1,
+                    (height / layer.scale_down) & !1,
+                );
+                let mut config = EncoderConfig::new(width, height, frame_rate);
+                config = config.bitrate(config.bitrate_bps.min(layer.max_bitrate_bps));
+                if let Some(profile) = profile {
+                    config = config.h264_profile(profile);
+                }
+                let encoder = codec::encoder_for(&capability.mime_type, &config)?;
+                Some((layer.scale_down, encoder))
+            })
+            .collect::<Option<_>>()?;
+        Some(Self { encoders })
+    }
+
+    /// Each layer's encoding of `frame`, in the track's order.
+    pub fn encode(&mut self, frame: &VideoFrame) -> Vec<Option<Vec<u8>>> {
+        self.encoders
+            .iter_mut()
+            .map(|(scale_down, encoder)| match scale_down {
+                1 => encoder.encode(frame),
+                _ => encoder.encode(&downscale(frame, *scale_down)),
+            })
+            .collect()
+    }
+}
+
+/// As `video::spawn_test_pattern`, into every layer of `track`.
+pub fn spawn_test_pattern(
+    track: Arc<SimulcastTrack>,
+    width: usize,
+    height: usize,
+    frame_rate: f32,
+) -> Option<JoinHandle<()>> {
+    let mut encoder = SimulcastEncoder::new(&track, width, height, frame_rate)?;
+    let mut pattern = TestPattern::new(width, height);
+    let interval = Duration::from_secs_f32(1.0 / frame_rate);
+    Some(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let frame = pattern.next_frame();
+            for (index, data) in encoder.encode(&frame).into_iter().enumerate() {
+                let Some(data) = data else {
+                    continue;
+                };
+                if let Err(err) = track.write_layer(index, &Bytes::from(data), interval).await {
+                    info!("Failed to write simulcast layer {}: {:?}", index, err);
+                }
+            }
+        }
+    }))
+}
+
+/// Each layer's bitrate, from the change in `LayerStats` between
+/// samples at least a second apart.
+#[derive(Debug, Default)]
+pub struct LayerBitrates {
+    last: Option<(Instant, Vec<u64>)>,
+    kbps: Vec<f64>,
+}
+
+impl LayerBitrates {
+    pub fn update(&mut self, now: Instant, stats: &[LayerStats]) {
+        let bytes: Vec<u64> = stats.iter().map(|stats| stats.bytes_sent).collect();
+        match &self.last {
+            Some((then, _)) if now.duration_since(*then) < Duration::from_secs(1) => {}
+            Some((then, before)) if before.len() == bytes.len() => {
+                let seconds = now.duration_since(*then).as_secs_f64();
+                self.kbps = bytes
+                    .iter()
+                    .zip(before)
+                    .map(|(now, before)| {
+                        now.saturating_sub(*before) as f64 * 8.0 / seconds / 1000.0
+                    })
+                    .collect();
+                self.last = Some((now, bytes));
+            }
+            _ => self.last = Some((now, bytes)),
+        }
+    }
+
+    /// Layer `index`'s bitrate, once two samples are in.
+    pub fn kbps(&self, index: usize) -> Option<f64> {
+        self.kbps.get(index).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offers_rids_in_the_video_section() {
+        let sdp = "v=0\r\nm=audio 9 UDP/TLS/RTP/SAVPF 111\r\na=mid:0\r\n\
+                   m=video 9 UDP/TLS/RTP/SAVPF 96\r\na=mid:1\r\na=ssrc:42 cname:local\r\n\
+                   m=application 9 UDP/DTLS/SCTP webrtc-datachannel\r\na=mid:2\r\n";
+        let offer = offer_sdp(sdp, "1", &LAYERS[..2]);
+        let video = offer.split("m=video").nth(1).unwrap();
+        let video = video.split("m=application").next().unwrap();
+        assert!(video.contains("a=rid:h send\r\na=rid:m send\r\na=simulcast:send h;m\r\n"));
+        assert_eq!(offer.matches("a=rid:").count(), 2);
+        assert!(!offer.contains("a=ssrc:"));
+
+        assert_eq!(layers_for(1280, 720).len(), 3);
+        assert_eq!(layers_for(320, 240).len(), 2);
+        assert_eq!(layers_for(160, 120).len(), 1);
+    }
+
+    #[test]
+    fn layer_bitrates_wait_a_second() {
+        let stats = |bytes: [u64; 2]| {
+            bytes
+                .iter()
+                .zip(&LAYERS)
+                .map(|(bytes, layer)| LayerStats {
+                    layer: *layer,
+                    bytes_sent: *bytes,
+                    packets_sent: 0,
+                })
+                .collect::<Vec<_>>()
+        };
+        let start = Instant::now();
+        let mut bitrates = LayerBitrates::default();
+        bitrates.update(start, &stats([0, 0]));
+        bitrates.update(start + Duration::from_millis(500), &stats([1000, 100]));
+        assert_eq!(bitrates.kbps(0), None);
+        bitrates.update(start + Duration::from_secs(2), &stats([250_000, 25_000]));
+        assert_eq!(bitrates.kbps(0), Some(1000.0));
+        assert_eq!(bitrates.kbps(1), Some(100.0));
+    }
+}
@@ -0,0 +1,110 @@
+//! HTTP digest authentication (RFC 2617) as used by SIP registrars and proxies.
+
+use md5::{Digest, Md5};
+
+#[derive(Debug, Clone)]
+pub struct DigestChallenge {
+    pub realm: String,
+    pub nonce: String,
+    pub opaque: Option<String>,
+    pub algorithm: Option<String>,
+    pub qop_auth: bool,
+}
+
+impl DigestChallenge {
+    /// Parses a `WWW-Authenticate` / `Proxy-Authenticate` header value.
+    pub fn parse(value: &str) -> Option<Self> {
+        let params = value.trim().strip_prefix("Digest")?;
+        let mut realm = None;
+        let mut nonce = None;
+        let mut opaque = None;
+        let mut algorithm = None;
+        let mut qop_auth = false;
+
+        for (key, val) in split_params(params) {
+            match key.to_ascii_lowercase().as_str() {
+                "realm" => realm = Some(val),
+                "nonce" => nonce = Some(val),
+                "opaque" => opaque = Some(val),
+                "algorithm" => algorithm = Some(val),
+                "qop" => qop_auth = val.split(',').any(|q| q.trim() == "auth"),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            realm: realm?,
+            nonce: nonce?,
+            opaque,
+            algorithm,
+            qop_auth,
+        })
+    }
+
+    /// Computes the `Authorization` header value answering this challenge.
+    pub fn authorization(
+        &self,
+        username: &str,
+        password: &str,
+        method: &str,
+        uri: &str,
+        nonce_count: u32,
+        cnonce: &str,
+    ) -> String {
+        let ha1 = md5_hex(&format!("{}:{}:{}", username, self.realm, password));
+        let ha2 = md5_hex(&format!("{}:{}", method, uri));
+
+        let mut header = format!(
+            "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\"",
+            username, self.realm, self.nonce, uri
+        );
+        if self.qop_auth {
+            let nc = format!("{:08x}", nonce_count);
+            let response = md5_hex(&format!(
+                "{}:{}:{}:{}:auth:{}",
+                ha1, self.nonce, nc, cnonce, ha2
+            ));
+            header.push_str(&format!(
+                ", response=\"{}\", qop=auth, nc={}, cnonce=\"{}\"",
+                response, nc, cnonce
+            ));
+        } else {
+            let response = md5_hex(&format!("{}:{}:{}", ha1, self.nonce, ha2));
+            header.push_str(&format!(", response=\"{}\"", response));
+        }
+        if let Some(algorithm) = &self.algorithm {
+            header.push_str(&format!(", algorithm={}", algorithm));
+        }
+        if let Some(opaque) = &self.opaque {
+            header.push_str(&format!(", opaque=\"{}\"", opaque));
+        }
+        header
+    }
+}
+
+/// Splits `a="x", b=y` into key/value pairs, honouring commas inside quotes.
+fn split_params(params: &str) -> Vec<(String, String)> {
+    let mut pairs = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in params.chars().chain(std::iter::once(',')) {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                if let Some((key, val)) = current.split_once('=') {
+                    pairs.push((key.trim().to_owned(), val.trim().to_owned()));
+                }
+                current.clear();
+            }
+            _ => current.push(c),
+        }
+    }
+    pairs
+}
+
+fn md5_hex(input: &str) -> String {
+    Md5::digest(input.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
@@ -0,0 +1,234 @@
+//! Minimal SIP message model: just enough of RFC 3261 to register and to
+//! place and receive calls.
+
+use std::fmt;
+
+use super::SipError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartLine {
+    Request { method: String, uri: String },
+    Response { status: u16, reason: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct SipMessage {
+    pub start: StartLine,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// Expands the single-letter compact header forms into their full names.
+fn canonical_name(name: &str) -> &str {
+    match name {
+        "v" | "V" => "Via",
+        "f" | "F" => "From",
+        "t" | "T" => "To",
+        "i" | "I" => "Call-ID",
+        "m" | "M" => "Contact",
+        "l" | "L" => "Content-Length",
+        "c" | "C" => "Content-Type",
+        _ => name,
+    }
+}
+
+impl SipMessage {
+    pub fn request(method: &str, uri: &str) -> Self {
+        Self {
+            start: StartLine::Request {
+                method: method.to_owned(),
+                uri: uri.to_owned(),
+            },
+            headers: vec![],
+            body: String::new(),
+        }
+    }
+
+    pub fn response(status: u16, reason: &str) -> Self {
+        Self {
+            start: StartLine::Response {
+                status,
+                reason: reason.to_owned(),
+            },
+            headers: vec![],
+            body: String::new(),
+        }
+    }
+
+    /// Builds a response to `request`, copying the headers RFC 3261 §8.2.6
+    /// requires to be mirrored back.
+    pub fn response_to(request: &SipMessage, status: u16, reason: &str) -> Self {
+        let mut response = Self::response(status, reason);
+        for name in ["Via", "Record-Route"] {
+            for value in request.headers_named(name) {
+                response.add_header(name, value);
+            }
+        }
+        for name in ["From", "To", "Call-ID", "CSeq"] {
+            if let Some(value) = request.header(name) {
+                response.add_header(name, value);
+            }
+        }
+        response
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, SipError> {
+        let text = std::str::from_utf8(data).map_err(|_| SipError::Parse("not UTF-8"))?;
+        let (head, body) = text
+            .split_once("\r\n\r\n")
+            .ok_or(SipError::Parse("missing header terminator"))?;
+        let mut lines = head.split("\r\n");
+        let first = lines.next().ok_or(SipError::Parse("empty message"))?;
+
+        let start = if let Some(rest) = first.strip_prefix("SIP/2.0 ") {
+            let (status, reason) = rest.split_once(' ').unwrap_or((rest, ""));
+            StartLine::Response {
+                status: status
+                    .parse()
+                    .map_err(|_| SipError::Parse("invalid status code"))?,
+                reason: reason.to_owned(),
+            }
+        } else {
+            let mut parts = first.split(' ');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(method), Some(uri), Some("SIP/2.0")) => StartLine::Request {
+                    method: method.to_owned(),
+                    uri: uri.to_owned(),
+                },
+                _ => return Err(SipError::Parse("invalid request line")),
+            }
+        };
+
+        let mut headers: Vec<(String, String)> = vec![];
+        for line in lines {
+            if line.starts_with(' ') || line.starts_with('\t') {
+                // Header folding: continuation of the previous value.
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+                continue;
+            }
+            let (name, value) = line
+                .split_once(':')
+                .ok_or(SipError::Parse("invalid header line"))?;
+            headers.push((
+                canonical_name(name.trim()).to_owned(),
+                value.trim().to_owned(),
+            ));
+        }
+
+        let mut message = Self {
+            start,
+            headers,
+            body: String::new(),
+        };
+        let length = message
+            .header("Content-Length")
+            .and_then(|l| l.parse::<usize>().ok())
+            .unwrap_or(body.len());
+        message.body = body.get(..length).unwrap_or(body).to_owned();
+        Ok(message)
+    }
+
+    pub fn method(&self) -> Option<&str> {
+        match &self.start {
+            StartLine::Request { method, .. } => Some(method),
+            StartLine::Response { .. } => None,
+        }
+    }
+
+    pub fn status(&self) -> Option<u16> {
+        match &self.start {
+            StartLine::Response { status, .. } => Some(*status),
+            StartLine::Request { .. } => None,
+        }
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn headers_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn add_header(&mut self, name: &str, value: impl Into<String>) {
+        self.headers.push((name.to_owned(), value.into()));
+    }
+
+    pub fn set_header(&mut self, name: &str, value: impl Into<String>) {
+        self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        self.add_header(name, value);
+    }
+
+    pub fn set_body(&mut self, content_type: &str, body: String) {
+        self.set_header("Content-Type", content_type);
+        self.body = body;
+    }
+
+    pub fn call_id(&self) -> Option<&str> {
+        self.header("Call-ID")
+    }
+
+    /// Returns the CSeq sequence number and method.
+    pub fn cseq(&self) -> Option<(u32, &str)> {
+        let (number, method) = self.header("CSeq")?.split_once(' ')?;
+        Some((number.trim().parse().ok()?, method.trim()))
+    }
+
+    /// Returns the branch parameter of the topmost Via header.
+    pub fn branch(&self) -> Option<&str> {
+        header_param(self.header("Via")?, "branch")
+    }
+}
+
+impl fmt::Display for SipMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.start {
+            StartLine::Request { method, uri } => write!(f, "{} {} SIP/2.0\r\n", method, uri)?,
+            StartLine::Response { status, reason } => {
+                write!(f, "SIP/2.0 {} {}\r\n", status, reason)?
+            }
+        }
+        for (name, value) in &self.headers {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                continue;
+            }
+            write!(f, "{}: {}\r\n", name, value)?;
+        }
+        write!(
+            f,
+            "Content-Length: {}\r\n\r\n{}",
+            self.body.len(),
+            self.body
+        )
+    }
+}
+
+/// Looks up a `;name=value` parameter in a header value such as Via, From or To.
+pub fn header_param<'a>(value: &'a str, name: &str) -> Option<&'a str> {
+    // Parameters after the closing `>` belong to the header, not to the URI.
+    let params = match value.rfind('>') {
+        Some(end) => &value[end + 1..],
+        None => value,
+    };
+    params.split(';').skip(1).find_map(|param| {
+        let (key, val) = param.split_once('=').unwrap_or((param, ""));
+        key.trim().eq_ignore_ascii_case(name).then(|| val.trim())
+    })
+}
+
+/// Extracts the URI from a name-addr (`"Bob" <sip:bob@host>;tag=x`) or a bare addr-spec.
+pub fn header_uri(value: &str) -> &str {
+    match (value.find('<'), value.find('>')) {
+        (Some(start), Some(end)) if start < end => &value[start + 1..end],
+        _ => value.split(';').next().unwrap_or(value).trim(),
+    }
+}
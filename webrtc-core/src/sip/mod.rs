@@ -0,0 +1,939 @@
+//! SIP user agent used by the gateway mode.
+//!
+//! The agent registers with a SIP server over UDP and exchanges the SDP bodies
+//! of INVITE / 200 OK as plain strings; the GUI maps those onto the webrtc-rs
+//! peer connection. The far end must speak WebRTC-flavoured SDP (ICE +
+//! DTLS-SRTP), e.g. Asterisk with `webrtc=yes` or a FreeSWITCH WebRTC profile.
+
+mod auth;
+mod message;
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+
+use log::{info, warn};
+use rand::{distributions::Alphanumeric, Rng};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant};
+
+use auth::DigestChallenge;
+pub use message::{header_param, header_uri, SipMessage, StartLine};
+
+const DEFAULT_PORT: u16 = 5060;
+const USER_AGENT: &str = concat!("webrtc-rust-native-gui/", env!("CARGO_PKG_VERSION"));
+const ALLOW: &str = "INVITE, ACK, CANCEL, BYE, OPTIONS";
+
+/// RFC 3261 timer T1 / T2 and the 64*T1 transaction timeout.
+const T1: Duration = Duration::from_millis(500);
+const T2: Duration = Duration::from_secs(4);
+const TIMEOUT: Duration = Duration::from_secs(32);
+
+#[derive(Debug, thiserror::Error)]
+pub enum SipError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("malformed SIP message: {0}")]
+    Parse(&'static str),
+    #[error("could not resolve SIP server {0}")]
+    Resolve(String),
+    #[error("SIP agent is not running")]
+    AgentStopped,
+}
+
+#[derive(Debug, Clone)]
+pub struct SipConfig {
+    /// Registrar / outbound proxy as `host[:port]`.
+    pub server: String,
+    pub username: String,
+    pub password: String,
+    /// SIP domain of the account; defaults to the server host when empty.
+    pub domain: String,
+    /// Requested registration lifetime in seconds.
+    pub expires: u32,
+}
+
+impl Default for SipConfig {
+    fn default() -> Self {
+        Self {
+            server: String::new(),
+            username: String::new(),
+            password: String::new(),
+            domain: String::new(),
+            expires: 3600,
+        }
+    }
+}
+
+impl SipConfig {
+    fn domain(&self) -> &str {
+        if self.domain.is_empty() {
+            self.server.split(':').next().unwrap_or(&self.server)
+        } else {
+            &self.domain
+        }
+    }
+
+    fn aor(&self) -> String {
+        format!("sip:{}@{}", self.username, self.domain())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SipEvent {
+    Registered {
+        expires: u32,
+    },
+    Unregistered,
+    RegistrationFailed(String),
+    IncomingCall {
+        call_id: String,
+        from: String,
+        sdp: String,
+    },
+    Ringing {
+        call_id: String,
+    },
+    CallAnswered {
+        call_id: String,
+        sdp: String,
+    },
+    CallFailed {
+        call_id: String,
+        reason: String,
+    },
+    CallEnded {
+        call_id: String,
+    },
+}
+
+enum Command {
+    Register,
+    Unregister,
+    Invite {
+        call_id: String,
+        target: String,
+        sdp: String,
+    },
+    Answer {
+        call_id: String,
+        sdp: String,
+    },
+    Reject {
+        call_id: String,
+    },
+    Hangup {
+        call_id: String,
+    },
+}
+
+/// Handle to a running SIP user agent. Dropping every handle stops the agent.
+#[derive(Clone)]
+pub struct SipUserAgent {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl SipUserAgent {
+    pub async fn start(
+        config: SipConfig,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<SipEvent>), SipError> {
+        let server = if config.server.contains(':') {
+            config.server.clone()
+        } else {
+            format!("{}:{}", config.server, DEFAULT_PORT)
+        };
+        let server_addr = tokio::net::lookup_host(&server)
+            .await?
+            .next()
+            .ok_or_else(|| SipError::Resolve(server.clone()))?;
+
+        let bind: SocketAddr = if server_addr.is_ipv4() {
+            "0.0.0.0:0".parse().unwrap()
+        } else {
+            "[::]:0".parse().unwrap()
+        };
+        let socket = UdpSocket::bind(bind).await?;
+        socket.connect(server_addr).await?;
+        let local_addr = socket.local_addr()?;
+        info!(
+            "SIP agent bound to {} for server {}",
+            local_addr, server_addr
+        );
+
+        let (commands, command_rx) = mpsc::unbounded_channel();
+        let (events, event_rx) = mpsc::unbounded_channel();
+        let agent = Agent {
+            socket,
+            local_addr,
+            config,
+            events,
+            transactions: HashMap::new(),
+            pending_acks: HashMap::new(),
+            dialogs: HashMap::new(),
+            registration: Registration {
+                call_id: random_token(16),
+                tag: random_token(8),
+                cseq: 0,
+                unregistering: false,
+                refresh_at: None,
+            },
+            nonce_count: 0,
+        };
+        tokio::spawn(agent.run(command_rx));
+
+        Ok((Self { commands }, event_rx))
+    }
+
+    pub fn register(&self) -> Result<(), SipError> {
+        self.send(Command::Register)
+    }
+
+    pub fn unregister(&self) -> Result<(), SipError> {
+        self.send(Command::Unregister)
+    }
+
+    /// Places a call to `target` (a SIP URI or a bare user on the account's
+    /// domain) offering `sdp`. Returns the Call-ID of the new dialog.
+    pub fn invite(&self, target: &str, sdp: String) -> Result<String, SipError> {
+        let call_id = random_token(16);
+        self.send(Command::Invite {
+            call_id: call_id.clone(),
+            target: target.to_owned(),
+            sdp,
+        })?;
+        Ok(call_id)
+    }
+
+    pub fn answer(&self, call_id: &str, sdp: String) -> Result<(), SipError> {
+        self.send(Command::Answer {
+            call_id: call_id.to_owned(),
+            sdp,
+        })
+    }
+
+    pub fn reject(&self, call_id: &str) -> Result<(), SipError> {
+        self.send(Command::Reject {
+            call_id: call_id.to_owned(),
+        })
+    }
+
+    pub fn hangup(&self, call_id: &str) -> Result<(), SipError> {
+        self.send(Command::Hangup {
+            call_id: call_id.to_owned(),
+        })
+    }
+
+    fn send(&self, command: Command) -> Result<(), SipError> {
+        self.commands
+            .send(command)
+            .map_err(|_| SipError::AgentStopped)
+    }
+}
+
+fn random_token(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+fn new_branch() -> String {
+    format!("z9hG4bK{}", random_token(12))
+}
+
+/// A request being retransmitted until a response arrives, or a 2xx
+/// response being retransmitted until its ACK arrives.
+struct Retransmit {
+    message: SipMessage,
+    next: Instant,
+    interval: Duration,
+    deadline: Instant,
+    authenticated: bool,
+}
+
+impl Retransmit {
+    fn new(message: SipMessage) -> Self {
+        let now = Instant::now();
+        Self {
+            message,
+            next: now + T1,
+            interval: T1,
+            deadline: now + TIMEOUT,
+            authenticated: false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum DialogState {
+    Calling,
+    Early,
+    Incoming,
+    Confirmed,
+}
+
+struct Dialog {
+    state: DialogState,
+    /// Our From (outgoing) or To (incoming) header, including our tag.
+    local: String,
+    /// The peer's header value, including its tag once known.
+    remote: String,
+    remote_target: String,
+    route_set: Vec<String>,
+    local_cseq: u32,
+    /// The INVITE that created the dialog; needed for CANCEL, ACK and responses.
+    invite: SipMessage,
+    local_sdp: Option<String>,
+}
+
+struct Registration {
+    call_id: String,
+    tag: String,
+    cseq: u32,
+    unregistering: bool,
+    refresh_at: Option<Instant>,
+}
+
+struct Agent {
+    socket: UdpSocket,
+    local_addr: SocketAddr,
+    config: SipConfig,
+    events: mpsc::UnboundedSender<SipEvent>,
+    /// Client transactions keyed by Via branch.
+    transactions: HashMap<String, Retransmit>,
+    /// 2xx responses to incoming INVITEs keyed by Call-ID.
+    pending_acks: HashMap<String, Retransmit>,
+    dialogs: HashMap<String, Dialog>,
+    registration: Registration,
+    nonce_count: u32,
+}
+
+impl Agent {
+    async fn run(mut self, mut commands: mpsc::UnboundedReceiver<Command>) {
+        let mut buf = vec![0u8; 65535];
+        loop {
+            let wake = self.next_timer();
+            tokio::select! {
+                command = commands.recv() => match command {
+                    Some(command) => self.handle_command(command).await,
+                    None => break,
+                },
+                received = self.socket.recv(&mut buf) => match received {
+                    Ok(len) => match SipMessage::parse(&buf[..len]) {
+                        Ok(message) => self.handle_message(message).await,
+                        Err(err) => warn!("Dropping SIP datagram: {}", err),
+                    },
+                    Err(err) => warn!("SIP socket error: {:?}", err),
+                },
+                _ = tokio::time::sleep_until(wake) => self.on_timer().await,
+            }
+        }
+        info!("SIP agent stopped");
+    }
+
+    fn emit(&self, event: SipEvent) {
+        let _ = self.events.send(event);
+    }
+
+    async fn send(&self, message: &SipMessage) {
+        if let Err(err) = self.socket.send(message.to_string().as_bytes()).await {
+            warn!("Failed to send SIP message: {:?}", err);
+        }
+    }
+
+    fn contact(&self) -> String {
+        format!(
+            "<sip:{}@{};transport=udp>",
+            self.config.username, self.local_addr
+        )
+    }
+
+    fn via(&self, branch: &str) -> String {
+        format!("SIP/2.0/UDP {};branch={};rport", self.local_addr, branch)
+    }
+
+    fn new_request(&self, method: &str, uri: &str, call_id: &str, cseq: u32) -> SipMessage {
+        let mut request = SipMessage::request(method, uri);
+        request.add_header("Via", self.via(&new_branch()));
+        request.add_header("Max-Forwards", "70");
+        request.add_header("Call-ID", call_id);
+        request.add_header("CSeq", format!("{} {}", cseq, method));
+        request.add_header("User-Agent", USER_AGENT);
+        request
+    }
+
+    /// Sends a request and tracks it for retransmission and response matching.
+    async fn send_request(&mut self, request: SipMessage) {
+        self.send(&request).await;
+        if let Some(branch) = request.branch() {
+            self.transactions
+                .insert(branch.to_owned(), Retransmit::new(request));
+        }
+    }
+
+    fn next_timer(&self) -> Instant {
+        let retransmits = self
+            .transactions
+            .values()
+            .chain(self.pending_acks.values())
+            .map(|r| r.next.min(r.deadline));
+        retransmits
+            .chain(self.registration.refresh_at)
+            .min()
+            .unwrap_or_else(|| Instant::now() + Duration::from_secs(1))
+    }
+
+    async fn on_timer(&mut self) {
+        let now = Instant::now();
+
+        let expired: Vec<String> = self
+            .transactions
+            .iter()
+            .filter(|(_, t)| t.deadline <= now)
+            .map(|(branch, _)| branch.clone())
+            .collect();
+        for branch in expired {
+            if let Some(transaction) = self.transactions.remove(&branch) {
+                self.on_transaction_timeout(transaction.message);
+            }
+        }
+        self.pending_acks.retain(|call_id, r| {
+            if r.deadline <= now {
+                warn!("No ACK received for call {}", call_id);
+            }
+            r.deadline > now
+        });
+
+        let mut resend = vec![];
+        for r in self
+            .transactions
+            .values_mut()
+            .chain(self.pending_acks.values_mut())
+        {
+            if r.next <= now {
+                resend.push(r.message.clone());
+                let is_invite =
+                    r.message.method() == Some("INVITE") || r.message.status().is_some();
+                r.interval = if is_invite {
+                    r.interval * 2
+                } else {
+                    (r.interval * 2).min(T2)
+                };
+                r.next = now + r.interval;
+            }
+        }
+        for message in resend {
+            self.send(&message).await;
+        }
+
+        if self.registration.refresh_at.is_some_and(|at| at <= now) {
+            self.registration.refresh_at = None;
+            self.send_register(self.config.expires).await;
+        }
+    }
+
+    fn on_transaction_timeout(&mut self, request: SipMessage) {
+        match request.method() {
+            Some("REGISTER") => self.emit(SipEvent::RegistrationFailed("timed out".to_owned())),
+            Some("INVITE") => {
+                if let Some(call_id) = request.call_id() {
+                    let call_id = call_id.to_owned();
+                    self.dialogs.remove(&call_id);
+                    self.emit(SipEvent::CallFailed {
+                        call_id,
+                        reason: "timed out".to_owned(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    async fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::Register => {
+                self.registration.unregistering = false;
+                self.send_register(self.config.expires).await;
+            }
+            Command::Unregister => {
+                self.registration.unregistering = true;
+                self.registration.refresh_at = None;
+                self.send_register(0).await;
+            }
+            Command::Invite {
+                call_id,
+                target,
+                sdp,
+            } => self.send_invite(call_id, &target, sdp).await,
+            Command::Answer { call_id, sdp } => self.answer(&call_id, sdp).await,
+            Command::Reject { call_id } => self.reject(&call_id, 603, "Decline").await,
+            Command::Hangup { call_id } => self.hangup(&call_id).await,
+        }
+    }
+
+    async fn send_register(&mut self, expires: u32) {
+        self.registration.cseq += 1;
+        let uri = format!("sip:{}", self.config.domain());
+        let mut request = self.new_request(
+            "REGISTER",
+            &uri,
+            &self.registration.call_id,
+            self.registration.cseq,
+        );
+        let aor = format!("<{}>", self.config.aor());
+        request.add_header("From", format!("{};tag={}", aor, self.registration.tag));
+        request.add_header("To", aor);
+        request.add_header("Contact", self.contact());
+        request.add_header("Expires", expires.to_string());
+        self.send_request(request).await;
+    }
+
+    async fn send_invite(&mut self, call_id: String, target: &str, sdp: String) {
+        let uri = if target.starts_with("sip:") || target.starts_with("sips:") {
+            target.to_owned()
+        } else {
+            format!("sip:{}@{}", target, self.config.domain())
+        };
+        let local = format!("<{}>;tag={}", self.config.aor(), random_token(8));
+        let remote = format!("<{}>", uri);
+
+        let mut request = self.new_request("INVITE", &uri, &call_id, 1);
+        request.add_header("From", local.clone());
+        request.add_header("To", remote.clone());
+        request.add_header("Contact", self.contact());
+        request.add_header("Allow", ALLOW);
+        request.set_body("application/sdp", sdp.clone());
+
+        info!("Sending INVITE to {} (Call-ID {})", uri, call_id);
+        self.dialogs.insert(
+            call_id,
+            Dialog {
+                state: DialogState::Calling,
+                local,
+                remote,
+                remote_target: uri,
+                route_set: vec![],
+                local_cseq: 1,
+                invite: request.clone(),
+                local_sdp: Some(sdp),
+            },
+        );
+        self.send_request(request).await;
+    }
+
+    async fn answer(&mut self, call_id: &str, sdp: String) {
+        let contact = self.contact();
+        let Some(dialog) = self.dialogs.get_mut(call_id) else {
+            warn!("Cannot answer unknown call {}", call_id);
+            return;
+        };
+        if dialog.state != DialogState::Incoming {
+            warn!("Call {} is not awaiting an answer", call_id);
+            return;
+        }
+
+        let mut response = SipMessage::response_to(&dialog.invite, 200, "OK");
+        response.set_header("To", dialog.local.clone());
+        response.add_header("Contact", contact);
+        response.add_header("Allow", ALLOW);
+        response.set_body("application/sdp", sdp.clone());
+        dialog.state = DialogState::Confirmed;
+        dialog.local_sdp = Some(sdp);
+
+        self.send(&response).await;
+        self.pending_acks
+            .insert(call_id.to_owned(), Retransmit::new(response));
+    }
+
+    async fn reject(&mut self, call_id: &str, status: u16, reason: &str) {
+        let Some(dialog) = self.dialogs.remove(call_id) else {
+            return;
+        };
+        let mut response = SipMessage::response_to(&dialog.invite, status, reason);
+        response.set_header("To", dialog.local);
+        self.send(&response).await;
+        self.emit(SipEvent::CallEnded {
+            call_id: call_id.to_owned(),
+        });
+    }
+
+    async fn hangup(&mut self, call_id: &str) {
+        let Some(state) = self.dialogs.get(call_id).map(|d| &d.state) else {
+            return;
+        };
+        match state {
+            DialogState::Incoming => self.reject(call_id, 486, "Busy Here").await,
+            DialogState::Calling | DialogState::Early => {
+                // The dialog lingers until the 487 for the INVITE arrives.
+                let invite = &self.dialogs[call_id].invite;
+                let uri = match &invite.start {
+                    StartLine::Request { uri, .. } => uri.clone(),
+                    StartLine::Response { .. } => unreachable!(),
+                };
+                let mut cancel = SipMessage::request("CANCEL", &uri);
+                for name in ["Via", "From", "To", "Call-ID"] {
+                    if let Some(value) = invite.header(name) {
+                        cancel.add_header(name, value);
+                    }
+                }
+                let cseq = invite.cseq().map(|(n, _)| n).unwrap_or(1);
+                cancel.add_header("CSeq", format!("{} CANCEL", cseq));
+                cancel.add_header("Max-Forwards", "70");
+                self.send(&cancel).await;
+            }
+            DialogState::Confirmed => {
+                let bye = self.in_dialog_request(call_id, "BYE");
+                self.dialogs.remove(call_id);
+                self.pending_acks.remove(call_id);
+                if let Some(bye) = bye {
+                    self.send_request(bye).await;
+                }
+                self.emit(SipEvent::CallEnded {
+                    call_id: call_id.to_owned(),
+                });
+            }
+        }
+    }
+
+    fn in_dialog_request(&mut self, call_id: &str, method: &str) -> Option<SipMessage> {
+        let dialog = self.dialogs.get_mut(call_id)?;
+        let cseq = if method == "ACK" {
+            dialog.invite.cseq().map(|(n, _)| n).unwrap_or(1)
+        } else {
+            dialog.local_cseq += 1;
+            dialog.local_cseq
+        };
+        let (target, local, remote, routes) = (
+            dialog.remote_target.clone(),
+            dialog.local.clone(),
+            dialog.remote.clone(),
+            dialog.route_set.clone(),
+        );
+        let mut request = self.new_request(method, &target, call_id, cseq);
+        request.add_header("From", local);
+        request.add_header("To", remote);
+        for route in routes {
+            request.add_header("Route", route);
+        }
+        Some(request)
+    }
+
+    async fn handle_message(&mut self, message: SipMessage) {
+        if message.status().is_some() {
+            self.handle_response(message).await;
+        } else {
+            self.handle_request(message).await;
+        }
+    }
+
+    async fn handle_response(&mut self, response: SipMessage) {
+        let status = response.status().unwrap_or_default();
+        let Some((_, method)) = response.cseq() else {
+            return;
+        };
+        let method = method.to_owned();
+        let branch = response.branch().unwrap_or_default().to_owned();
+
+        // A retransmitted 2xx to our INVITE only needs the ACK repeated.
+        let Some(transaction) = self.transactions.get_mut(&branch) else {
+            if method == "INVITE" && (200..300).contains(&status) {
+                if let Some(call_id) = response.call_id() {
+                    if let Some(ack) = self.in_dialog_request(call_id, "ACK") {
+                        self.send(&ack).await;
+                    }
+                }
+            }
+            return;
+        };
+
+        if status < 200 {
+            // Provisional responses stop retransmission but keep the client
+            // transaction alive while the callee is ringing.
+            transaction.next = Instant::now() + Duration::from_secs(3600);
+            transaction.deadline = Instant::now() + Duration::from_secs(180);
+            if method == "INVITE" && status > 100 {
+                self.on_invite_provisional(&response);
+            }
+            return;
+        }
+        let transaction = self.transactions.remove(&branch).unwrap();
+
+        if (status == 401 || status == 407) && !transaction.authenticated {
+            if method == "INVITE" {
+                self.send_non_2xx_ack(&transaction.message, &response).await;
+            }
+            if self
+                .retry_with_auth(transaction.message.clone(), &response)
+                .await
+            {
+                return;
+            }
+        }
+
+        match method.as_str() {
+            "REGISTER" => self.on_register_response(status, &response),
+            "INVITE" => self.on_invite_final(transaction.message, response).await,
+            _ => {}
+        }
+    }
+
+    async fn retry_with_auth(&mut self, mut request: SipMessage, response: &SipMessage) -> bool {
+        let (challenge_header, auth_header) = if response.status() == Some(407) {
+            ("Proxy-Authenticate", "Proxy-Authorization")
+        } else {
+            ("WWW-Authenticate", "Authorization")
+        };
+        let Some(challenge) = response
+            .header(challenge_header)
+            .and_then(DigestChallenge::parse)
+        else {
+            return false;
+        };
+        let (method, uri) = match &request.start {
+            StartLine::Request { method, uri } => (method.clone(), uri.clone()),
+            StartLine::Response { .. } => return false,
+        };
+        let Some((cseq, _)) = request.cseq() else {
+            return false;
+        };
+
+        self.nonce_count += 1;
+        let authorization = challenge.authorization(
+            &self.config.username,
+            &self.config.password,
+            &method,
+            &uri,
+            self.nonce_count,
+            &random_token(8),
+        );
+        request.set_header("Via", self.via(&new_branch()));
+        request.set_header("CSeq", format!("{} {}", cseq + 1, method));
+        request.set_header(auth_header, authorization);
+
+        if method == "REGISTER" {
+            self.registration.cseq = cseq + 1;
+        } else if let Some(dialog) = request.call_id().and_then(|id| self.dialogs.get_mut(id)) {
+            dialog.local_cseq = cseq + 1;
+            dialog.invite = request.clone();
+        }
+
+        self.send(&request).await;
+        if let Some(branch) = request.branch() {
+            let mut transaction = Retransmit::new(request.clone());
+            transaction.authenticated = true;
+            self.transactions.insert(branch.to_owned(), transaction);
+        }
+        true
+    }
+
+    fn on_register_response(&mut self, status: u16, response: &SipMessage) {
+        if !(200..300).contains(&status) {
+            self.emit(SipEvent::RegistrationFailed(format!("{} response", status)));
+            return;
+        }
+        if self.registration.unregistering {
+            self.emit(SipEvent::Unregistered);
+            return;
+        }
+
+        let expires = response
+            .headers_named("Contact")
+            .find_map(|c| header_param(c, "expires"))
+            .or_else(|| response.header("Expires"))
+            .and_then(|e| e.parse::<u32>().ok())
+            .unwrap_or(self.config.expires);
+        // Refresh well before the binding lapses.
+        self.registration.refresh_at =
+            Some(Instant::now() + Duration::from_secs(u64::from(expires) * 4 / 5));
+        info!("Registered with {} for {}s", self.config.server, expires);
+        self.emit(SipEvent::Registered { expires });
+    }
+
+    fn on_invite_provisional(&mut self, response: &SipMessage) {
+        let Some(call_id) = response.call_id() else {
+            return;
+        };
+        if let Some(dialog) = self.dialogs.get_mut(call_id) {
+            if dialog.state == DialogState::Calling {
+                dialog.state = DialogState::Early;
+                self.emit(SipEvent::Ringing {
+                    call_id: call_id.to_owned(),
+                });
+            }
+        }
+    }
+
+    async fn on_invite_final(&mut self, invite: SipMessage, response: SipMessage) {
+        let status = response.status().unwrap_or_default();
+        let Some(call_id) = response.call_id().map(str::to_owned) else {
+            return;
+        };
+
+        if !(200..300).contains(&status) {
+            self.send_non_2xx_ack(&invite, &response).await;
+            if self.dialogs.remove(&call_id).is_some() {
+                if status == 487 {
+                    self.emit(SipEvent::CallEnded { call_id });
+                } else {
+                    let reason = match &response.start {
+                        StartLine::Response { reason, .. } => format!("{} {}", status, reason),
+                        StartLine::Request { .. } => status.to_string(),
+                    };
+                    self.emit(SipEvent::CallFailed { call_id, reason });
+                }
+            }
+            return;
+        }
+
+        let Some(dialog) = self.dialogs.get_mut(&call_id) else {
+            return;
+        };
+        if let Some(to) = response.header("To") {
+            dialog.remote = to.to_owned();
+        }
+        if let Some(contact) = response.header("Contact") {
+            dialog.remote_target = header_uri(contact).to_owned();
+        }
+        dialog.route_set = response
+            .headers_named("Record-Route")
+            .map(str::to_owned)
+            .collect();
+        dialog.route_set.reverse();
+        dialog.state = DialogState::Confirmed;
+
+        if let Some(ack) = self.in_dialog_request(&call_id, "ACK") {
+            self.send(&ack).await;
+        }
+        info!("Call {} answered", call_id);
+        self.emit(SipEvent::CallAnswered {
+            call_id,
+            sdp: response.body,
+        });
+    }
+
+    /// ACKs a non-2xx final response within the INVITE transaction.
+    async fn send_non_2xx_ack(&self, invite: &SipMessage, response: &SipMessage) {
+        let StartLine::Request { uri, .. } = &invite.start else {
+            return;
+        };
+        let mut ack = SipMessage::request("ACK", uri);
+        for name in ["Via", "From", "Call-ID"] {
+            if let Some(value) = invite.header(name) {
+                ack.add_header(name, value);
+            }
+        }
+        if let Some(to) = response.header("To") {
+            ack.add_header("To", to);
+        }
+        let cseq = invite.cseq().map(|(n, _)| n).unwrap_or(1);
+        ack.add_header("CSeq", format!("{} ACK", cseq));
+        ack.add_header("Max-Forwards", "70");
+        self.send(&ack).await;
+    }
+
+    async fn handle_request(&mut self, request: SipMessage) {
+        let Some(call_id) = request.call_id().map(str::to_owned) else {
+            return;
+        };
+        match request.method().unwrap_or_default() {
+            "INVITE" => self.on_invite(call_id, request).await,
+            "ACK" => {
+                self.pending_acks.remove(&call_id);
+            }
+            "BYE" => {
+                self.send(&SipMessage::response_to(&request, 200, "OK"))
+                    .await;
+                self.pending_acks.remove(&call_id);
+                if self.dialogs.remove(&call_id).is_some() {
+                    info!("Call {} ended by remote", call_id);
+                    self.emit(SipEvent::CallEnded { call_id });
+                }
+            }
+            "CANCEL" => {
+                self.send(&SipMessage::response_to(&request, 200, "OK"))
+                    .await;
+                let unanswered = self
+                    .dialogs
+                    .get(&call_id)
+                    .is_some_and(|d| d.state == DialogState::Incoming);
+                if unanswered {
+                    self.reject(&call_id, 487, "Request Terminated").await;
+                }
+            }
+            "OPTIONS" => {
+                let mut response = SipMessage::response_to(&request, 200, "OK");
+                response.add_header("Allow", ALLOW);
+                self.send(&response).await;
+            }
+            _ => {
+                self.send(&SipMessage::response_to(&request, 501, "Not Implemented"))
+                    .await;
+            }
+        }
+    }
+
+    async fn on_invite(&mut self, call_id: String, request: SipMessage) {
+        let contact = self.contact();
+        if let Some(dialog) = self.dialogs.get(&call_id) {
+            let mut response = match (&dialog.state, &dialog.local_sdp) {
+                // Re-INVITE within an established call: keep the current session.
+                (DialogState::Confirmed, Some(sdp)) => {
+                    let mut response = SipMessage::response_to(&request, 200, "OK");
+                    response.add_header("Contact", contact);
+                    response.set_body("application/sdp", sdp.clone());
+                    response
+                }
+                // Retransmission of an INVITE we are still ringing for.
+                _ => SipMessage::response_to(&request, 180, "Ringing"),
+            };
+            response.set_header("To", dialog.local.clone());
+            self.send(&response).await;
+            return;
+        }
+
+        self.send(&SipMessage::response_to(&request, 100, "Trying"))
+            .await;
+
+        let local = format!(
+            "{};tag={}",
+            request.header("To").unwrap_or_default(),
+            random_token(8)
+        );
+        let remote = request.header("From").unwrap_or_default().to_owned();
+        let remote_target = request
+            .header("Contact")
+            .map(header_uri)
+            .unwrap_or_else(|| header_uri(&remote))
+            .to_owned();
+
+        let mut ringing = SipMessage::response_to(&request, 180, "Ringing");
+        ringing.set_header("To", local.clone());
+        ringing.add_header("Contact", contact);
+        self.send(&ringing).await;
+
+        info!("Incoming call {} from {}", call_id, remote);
+        self.emit(SipEvent::IncomingCall {
+            call_id: call_id.clone(),
+            from: header_uri(&remote).to_owned(),
+            sdp: request.body.clone(),
+        });
+        self.dialogs.insert(
+            call_id,
+            Dialog {
+                state: DialogState::Incoming,
+                local,
+                remote,
+                remote_target,
+                route_set: request
+                    .headers_named("Record-Route")
+                    .map(str::to_owned)
+                    .collect(),
+                local_cseq: 0,
+                invite: request,
+                local_sdp: None,
+            },
+        );
+    }
+}
@@ -0,0 +1,118 @@
+//! Immutable snapshots shared between background tasks and the render loop.
+//!
+//! Writers publish a whole new value and readers get an `Arc` to the latest
+//! one, so no lock is ever held beyond the copy of a pointer. Reading from
+//! the UI thread can therefore never wait on a task that is parked at an
+//! `.await`, which is how a `std::sync::Mutex` shared across both would
+//! deadlock the window.
+
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+pub struct Snapshot<T> {
+    sender: Arc<watch::Sender<Arc<T>>>,
+}
+
+impl<T> Clone for Snapshot<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: Arc::clone(&self.sender),
+        }
+    }
+}
+
+impl<T: Default> Default for Snapshot<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> Snapshot<T> {
+    pub fn new(value: T) -> Self {
+        let (sender, _) = watch::channel(Arc::new(value));
+        Self {
+            sender: Arc::new(sender),
+        }
+    }
+
+    /// The latest published value.
+    pub fn get(&self) -> Arc<T> {
+        Arc::clone(&self.sender.borrow())
+    }
+
+    /// Publishes a new value; readers holding an older snapshot keep it.
+    pub fn set(&self, value: T) {
+        self.sender.send_replace(Arc::new(value));
+    }
+
+    /// A receiver that is notified on every `set`.
+    pub fn subscribe(&self) -> watch::Receiver<Arc<T>> {
+        self.sender.subscribe()
+    }
+
+    /// Whether `other` is a clone of this snapshot rather than another one.
+    pub fn same(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.sender, &other.sender)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use tokio::sync::Notify;
+
+    use super::*;
+
+    /// What this replaced deadlocked when a task held the SDP's std mutex
+    /// across an `.await` and the render thread locked it for a frame. A
+    /// writer parked mid-update must leave a reader on a plain thread free.
+    #[test]
+    fn render_thread_reads_while_a_writer_is_parked_at_await() {
+        let sdp = Snapshot::new("v=0".to_owned());
+        let release = Arc::new(Notify::new());
+        let (parked_tx, parked) = mpsc::channel();
+        let writer = {
+            let sdp = sdp.clone();
+            let release = Arc::clone(&release);
+            std::thread::spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .build()
+                    .unwrap();
+                runtime.block_on(async move {
+                    let held = sdp.get();
+                    sdp.set(format!("{} offer", held));
+                    parked_tx.send(()).unwrap();
+                    release.notified().await;
+                    sdp.set(format!("{} answer", sdp.get()));
+                });
+            })
+        };
+        parked
+            .recv_timeout(Duration::from_secs(5))
+            .expect("the writer never got to its await");
+
+        let (read_tx, read) = mpsc::channel();
+        let render = sdp.clone();
+        std::thread::spawn(move || read_tx.send(render.get().to_string()));
+        let frame = read
+            .recv_timeout(Duration::from_secs(5))
+            .expect("the render thread blocked on the parked writer");
+        assert_eq!(frame, "v=0 offer");
+
+        release.notify_one();
+        writer.join().unwrap();
+        assert_eq!(sdp.get().as_str(), "v=0 offer answer");
+    }
+
+    #[tokio::test]
+    async fn subscribers_see_updates() {
+        let sdp = Snapshot::new(String::new());
+        let mut updates = sdp.subscribe();
+        sdp.set("offer".to_owned());
+        updates.changed().await.unwrap();
+        assert_eq!(updates.borrow().as_str(), "offer");
+    }
+}
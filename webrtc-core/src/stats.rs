@@ -0,0 +1,370 @@
+//! Rolling stats timeline for a peer connection, with a structured log of
+//! session events (state changes, candidates, renegotiations, errors) so
+//! metric dips can be lined up with what caused them.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+use tokio::time::Instant;
+use webrtc::ice::candidate::CandidatePairState;
+use webrtc::stats::{ICECandidateStats, StatsReport, StatsReportType};
+
+/// Ten minutes of history at the default one-second sampling interval.
+const MAX_SAMPLES: usize = 600;
+const MAX_MARKERS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MarkerKind {
+    Renegotiation,
+    CandidatePairSwitch,
+    KeyframeRequest,
+    Mute,
+    TurnFailover,
+    ConnectionState,
+    IceState,
+    SignalingState,
+    GatheringState,
+    LocalCandidate,
+    RemoteCandidate,
+    Error,
+}
+
+impl MarkerKind {
+    pub fn lane(self) -> MarkerLane {
+        match self {
+            MarkerKind::ConnectionState
+            | MarkerKind::IceState
+            | MarkerKind::SignalingState
+            | MarkerKind::GatheringState => MarkerLane::State,
+            MarkerKind::LocalCandidate
+            | MarkerKind::RemoteCandidate
+            | MarkerKind::CandidatePairSwitch
+            | MarkerKind::TurnFailover => MarkerLane::Candidates,
+            MarkerKind::Renegotiation => MarkerLane::Negotiation,
+            MarkerKind::KeyframeRequest | MarkerKind::Mute => MarkerLane::Media,
+            MarkerKind::Error => MarkerLane::Errors,
+        }
+    }
+
+    /// Worth a line across the metrics plot; the rest only show on the
+    /// event timeline.
+    pub fn plotted(self) -> bool {
+        matches!(
+            self,
+            MarkerKind::Renegotiation
+                | MarkerKind::CandidatePairSwitch
+                | MarkerKind::KeyframeRequest
+                | MarkerKind::Mute
+                | MarkerKind::TurnFailover
+        )
+    }
+}
+
+impl fmt::Display for MarkerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MarkerKind::Renegotiation => "Renegotiation",
+            MarkerKind::CandidatePairSwitch => "Candidate pair switch",
+            MarkerKind::KeyframeRequest => "Keyframe request",
+            MarkerKind::Mute => "Mute",
+            MarkerKind::TurnFailover => "TURN failover",
+            MarkerKind::ConnectionState => "Connection state",
+            MarkerKind::IceState => "ICE state",
+            MarkerKind::SignalingState => "Signaling state",
+            MarkerKind::GatheringState => "ICE gathering",
+            MarkerKind::LocalCandidate => "Local candidate",
+            MarkerKind::RemoteCandidate => "Remote candidate",
+            MarkerKind::Error => "Error",
+        })
+    }
+}
+
+/// The rows of the event timeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MarkerLane {
+    State,
+    Candidates,
+    Negotiation,
+    Media,
+    Errors,
+}
+
+impl MarkerLane {
+    pub const ALL: [MarkerLane; 5] = [
+        MarkerLane::State,
+        MarkerLane::Candidates,
+        MarkerLane::Negotiation,
+        MarkerLane::Media,
+        MarkerLane::Errors,
+    ];
+
+    pub fn index(self) -> usize {
+        MarkerLane::ALL
+            .iter()
+            .position(|lane| *lane == self)
+            .expect("every lane is listed")
+    }
+}
+
+impl fmt::Display for MarkerLane {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MarkerLane::State => "State",
+            MarkerLane::Candidates => "Candidates",
+            MarkerLane::Negotiation => "Negotiation",
+            MarkerLane::Media => "Media",
+            MarkerLane::Errors => "Errors",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EventMarker {
+    /// Seconds since the timeline started.
+    pub time: f64,
+    pub kind: MarkerKind,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StatsSample {
+    /// Seconds since the timeline started.
+    pub time: f64,
+    pub rtt_ms: Option<f64>,
+    pub inbound_kbps: f64,
+    pub outbound_kbps: f64,
+    pub packets_lost: i64,
+}
+
+/// The codecs one kind of media in a call ended up with, as mime types.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallCodecs {
+    pub sending: Option<String>,
+    pub receiving: Option<String>,
+}
+
+pub struct StatsTimeline {
+    origin: Instant,
+    samples: VecDeque<StatsSample>,
+    markers: VecDeque<EventMarker>,
+    audio_codecs: CallCodecs,
+    video_codecs: CallCodecs,
+}
+
+impl Default for StatsTimeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatsTimeline {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            samples: VecDeque::new(),
+            markers: VecDeque::new(),
+            audio_codecs: CallCodecs::default(),
+            video_codecs: CallCodecs::default(),
+        }
+    }
+
+    pub fn elapsed(&self) -> f64 {
+        self.origin.elapsed().as_secs_f64()
+    }
+
+    pub fn samples(&self) -> impl DoubleEndedIterator<Item = &StatsSample> {
+        self.samples.iter()
+    }
+
+    pub fn markers(&self) -> impl DoubleEndedIterator<Item = &EventMarker> {
+        self.markers.iter()
+    }
+
+    /// The marker in `lane` closest to `time`, if one is within `within`
+    /// seconds of it.
+    pub fn nearest_marker(&self, lane: MarkerLane, time: f64, within: f64) -> Option<&EventMarker> {
+        self.markers
+            .iter()
+            .filter(|marker| marker.kind.lane() == lane && (marker.time - time).abs() <= within)
+            .min_by(|a, b| (a.time - time).abs().total_cmp(&(b.time - time).abs()))
+    }
+
+    pub fn push_sample(&mut self, sample: StatsSample) {
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn audio_codecs(&self) -> &CallCodecs {
+        &self.audio_codecs
+    }
+
+    pub fn set_audio_codecs(&mut self, codecs: CallCodecs) {
+        self.audio_codecs = codecs;
+    }
+
+    pub fn video_codecs(&self) -> &CallCodecs {
+        &self.video_codecs
+    }
+
+    pub fn set_video_codecs(&mut self, codecs: CallCodecs) {
+        self.video_codecs = codecs;
+    }
+
+    /// Records an event at the current point in time.
+    pub fn mark(&mut self, kind: MarkerKind, detail: impl Into<String>) {
+        if self.markers.len() == MAX_MARKERS {
+            self.markers.pop_front();
+        }
+        self.markers.push_back(EventMarker {
+            time: self.elapsed(),
+            kind,
+            detail: detail.into(),
+        });
+    }
+}
+
+/// Turns successive cumulative `StatsReport`s into timeline samples and
+/// derives markers from counter changes between them.
+#[derive(Default)]
+pub struct StatsSampler {
+    last: Option<(Instant, u64, u64)>,
+    selected_pair: Option<String>,
+    keyframe_requests: HashMap<String, u64>,
+    candidates: HashSet<String>,
+}
+
+impl StatsSampler {
+    pub fn collect(&mut self, report: &StatsReport, timeline: &mut StatsTimeline) {
+        let now = Instant::now();
+        let mut sample = StatsSample {
+            time: timeline.elapsed(),
+            ..Default::default()
+        };
+        let (mut bytes_in, mut bytes_out) = (0, 0);
+
+        for stats in report.reports.values() {
+            match stats {
+                StatsReportType::CandidatePair(pair)
+                    if pair.nominated && pair.state == CandidatePairState::Succeeded =>
+                {
+                    bytes_in += pair.bytes_received;
+                    bytes_out += pair.bytes_sent;
+                    if pair.current_round_trip_time > 0.0 {
+                        sample.rtt_ms = Some(pair.current_round_trip_time * 1000.0);
+                    }
+                    if self.selected_pair.as_ref() != Some(&pair.id) {
+                        if let Some(previous) = self.selected_pair.replace(pair.id.clone()) {
+                            timeline.mark(
+                                MarkerKind::CandidatePairSwitch,
+                                format!("{} -> {}", previous, pair.id),
+                            );
+                        }
+                    }
+                }
+                StatsReportType::InboundRTP(rtp) => {
+                    let count = rtp.pli_count.unwrap_or(0) + rtp.fir_count.unwrap_or(0);
+                    self.check_keyframe_requests(&rtp.id, count, "sent", &rtp.kind, timeline);
+                }
+                StatsReportType::OutboundRTP(rtp) => {
+                    let count = rtp.pli_count.unwrap_or(0) + rtp.fir_count.unwrap_or(0);
+                    self.check_keyframe_requests(&rtp.id, count, "received", &rtp.kind, timeline);
+                }
+                StatsReportType::RemoteInboundRTP(rtp) => {
+                    sample.packets_lost += rtp.packets_lost;
+                }
+                StatsReportType::LocalCandidate(candidate) => {
+                    self.check_candidate(candidate, MarkerKind::LocalCandidate, timeline);
+                }
+                StatsReportType::RemoteCandidate(candidate) => {
+                    self.check_candidate(candidate, MarkerKind::RemoteCandidate, timeline);
+                }
+                _ => {}
+            }
+        }
+
+        if let Some((at, last_in, last_out)) = self.last {
+            let seconds = now.duration_since(at).as_secs_f64().max(f64::EPSILON);
+            sample.inbound_kbps = bytes_in.saturating_sub(last_in) as f64 * 8.0 / 1000.0 / seconds;
+            sample.outbound_kbps =
+                bytes_out.saturating_sub(last_out) as f64 * 8.0 / 1000.0 / seconds;
+        }
+        self.last = Some((now, bytes_in, bytes_out));
+        timeline.push_sample(sample);
+    }
+
+    /// Marks candidates the first time they show up in a report.
+    fn check_candidate(
+        &mut self,
+        candidate: &ICECandidateStats,
+        kind: MarkerKind,
+        timeline: &mut StatsTimeline,
+    ) {
+        if !self.candidates.insert(candidate.id.clone()) {
+            return;
+        }
+        let mut detail = format!(
+            "{} {} {}:{}",
+            candidate.candidate_type, candidate.network_type, candidate.ip, candidate.port
+        );
+        if !candidate.url.is_empty() {
+            detail.push_str(&format!(" via {}", candidate.url));
+        }
+        timeline.mark(kind, detail);
+    }
+
+    fn check_keyframe_requests(
+        &mut self,
+        id: &str,
+        count: u64,
+        direction: &str,
+        kind: &str,
+        timeline: &mut StatsTimeline,
+    ) {
+        let previous = self.keyframe_requests.insert(id.to_owned(), count);
+        if let Some(previous) = previous {
+            if count > previous {
+                timeline.mark(
+                    MarkerKind::KeyframeRequest,
+                    format!(
+                        "{} {} keyframe request(s) {}",
+                        count - previous,
+                        kind,
+                        direction
+                    ),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearest_marker_stays_in_its_lane() {
+        let mut timeline = StatsTimeline::new();
+        for (time, kind) in [
+            (1.0, MarkerKind::IceState),
+            (2.0, MarkerKind::Error),
+            (4.0, MarkerKind::ConnectionState),
+        ] {
+            timeline.markers.push_back(EventMarker {
+                time,
+                kind,
+                detail: String::new(),
+            });
+        }
+        let state = |time, within| {
+            timeline
+                .nearest_marker(MarkerLane::State, time, within)
+                .map(|marker| marker.kind)
+        };
+        assert_eq!(state(2.2, 2.0), Some(MarkerKind::IceState));
+        assert_eq!(state(3.5, 2.0), Some(MarkerKind::ConnectionState));
+        assert_eq!(state(2.5, 0.5), None);
+        assert_eq!(MarkerLane::Errors.index(), MarkerLane::ALL.len() - 1);
+    }
+}
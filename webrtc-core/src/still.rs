@@ -0,0 +1,61 @@
+//! Single frames of video saved as PNG, for a closer look at what the
+//! peer's camera showed at one moment.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::video::VideoFrame;
+
+#[derive(Debug, Error)]
+pub enum StillError {
+    #[error("cannot write the picture: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("cannot encode PNG: {0}")]
+    Encoding(#[from] png::EncodingError),
+}
+
+/// Writes `frame` to `path` as an RGBA PNG.
+pub fn save_png(frame: &VideoFrame, path: &Path) -> Result<(), StillError> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, frame.width as u32, frame.height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&frame.rgba)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// `remote-2026-01-31T12-00-00Z.png` and the like, for a frame taken now.
+pub fn file_name(prefix: &str) -> String {
+    let now = humantime::format_rfc3339_seconds(std::time::SystemTime::now());
+    format!("{}-{}.png", prefix, now.to_string().replace(':', "-"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_a_frame_that_reads_back() {
+        let frame = VideoFrame {
+            width: 4,
+            height: 2,
+            rgba: (0..32).collect(),
+        };
+        let path = std::env::temp_dir().join(format!("still-{}.png", std::process::id()));
+        save_png(&frame, &path).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut rgba = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut rgba).unwrap();
+        assert_eq!((info.width, info.height), (4, 2));
+        assert_eq!(rgba, frame.rgba);
+        std::fs::remove_file(&path).unwrap();
+        assert!(file_name("remote").ends_with("Z.png"));
+    }
+}
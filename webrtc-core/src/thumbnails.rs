@@ -0,0 +1,124 @@
+//! A rolling strip of thumbnails taken from a video stream every few
+//! seconds, each kept at two sizes: a small one for the strip and a larger
+//! one for previewing.
+
+use std::collections::VecDeque;
+
+use tokio::time::{Duration, Instant};
+
+use crate::video::VideoFrame;
+
+/// Width of the thumbnails shown in the strip.
+pub const SMALL_WIDTH: usize = 96;
+/// Width of the preview shown when a thumbnail is selected.
+pub const LARGE_WIDTH: usize = 320;
+
+pub struct Thumbnail {
+    /// Time since the strip started.
+    pub at: Duration,
+    pub small: VideoFrame,
+    pub large: VideoFrame,
+}
+
+pub struct ThumbnailStrip {
+    interval: Duration,
+    capacity: usize,
+    started: Instant,
+    last: Option<Instant>,
+    thumbnails: VecDeque<Thumbnail>,
+}
+
+impl ThumbnailStrip {
+    /// Keeps at most `capacity` thumbnails, dropping the oldest.
+    pub fn new(interval: Duration, capacity: usize) -> Self {
+        Self {
+            interval,
+            capacity,
+            started: Instant::now(),
+            last: None,
+            thumbnails: VecDeque::new(),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn thumbnails(&self) -> impl DoubleEndedIterator<Item = &Thumbnail> {
+        self.thumbnails.iter()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Thumbnail> {
+        self.thumbnails.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.thumbnails.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.thumbnails.is_empty()
+    }
+
+    /// Takes a thumbnail of `frame` if the interval has passed since the
+    /// last one. Returns whether it did.
+    pub fn offer(&mut self, frame: &VideoFrame) -> bool {
+        let now = Instant::now();
+        if self
+            .last
+            .is_some_and(|last| now.duration_since(last) < self.interval)
+        {
+            return false;
+        }
+        self.last = Some(now);
+        if self.thumbnails.len() == self.capacity {
+            self.thumbnails.pop_front();
+        }
+        self.thumbnails.push_back(Thumbnail {
+            at: now.duration_since(self.started),
+            small: downscale(frame, SMALL_WIDTH),
+            large: downscale(frame, LARGE_WIDTH),
+        });
+        true
+    }
+}
+
+/// Box-filters `frame` down to `width`, keeping its aspect ratio. Frames
+/// already narrower are copied as they are.
+pub fn downscale(frame: &VideoFrame, width: usize) -> VideoFrame {
+    if frame.width <= width || frame.width == 0 {
+        return frame.clone();
+    }
+    let height = (frame.height * width / frame.width).max(1);
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        let (y0, y1) = span(y, height, frame.height);
+        for x in 0..width {
+            let (x0, x1) = span(x, width, frame.width);
+            let mut sum = [0u32; 4];
+            for sy in y0..y1 {
+                let row = sy * frame.width * 4;
+                for sx in x0..x1 {
+                    let pixel = &frame.rgba[row + sx * 4..row + sx * 4 + 4];
+                    for (total, &channel) in sum.iter_mut().zip(pixel) {
+                        *total += u32::from(channel);
+                    }
+                }
+            }
+            let count = ((y1 - y0) * (x1 - x0)) as u32;
+            rgba.extend(sum.map(|total| (total / count) as u8));
+        }
+    }
+    VideoFrame {
+        width,
+        height,
+        rgba,
+    }
+}
+
+/// The source pixels covered by destination pixel `i` of `len`.
+fn span(i: usize, len: usize, source_len: usize) -> (usize, usize) {
+    let start = i * source_len / len;
+    let end = ((i + 1) * source_len / len).max(start + 1);
+    (start, end)
+}
@@ -0,0 +1,155 @@
+//! Time-limited TURN credentials, as issued by coturn's REST API scheme
+//! (`use-auth-secret`): the username is `<expiry>:<user>` and the password
+//! is an HMAC-SHA1 of it under a secret shared with the TURN server.
+//!
+//! With the secret itself we sign credentials locally. Without it, a
+//! credentials URL serving the usual JSON (`username`, `password`, `ttl`,
+//! optional `uris`) issues them for us.
+//!
+//! webrtc-rs fixes a peer connection's credentials when it is created, so
+//! a refresh only reaches the next connection or call; the lifetime has to
+//! cover the longest call.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha1::Sha1;
+
+/// Renew this long before expiry, or at a tenth of the lifetime if shorter.
+const REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum TurnRestError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("credentials URL returned {0}")]
+    Status(reqwest::StatusCode),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TurnCredential {
+    pub username: String,
+    pub credential: String,
+    /// Replaces the configured server URL when the service names its own.
+    pub urls: Vec<String>,
+    pub issued_at: SystemTime,
+    pub expires_at: SystemTime,
+}
+
+impl TurnCredential {
+    /// When to get a replacement.
+    pub fn refresh_at(&self) -> SystemTime {
+        let lifetime = self
+            .expires_at
+            .duration_since(self.issued_at)
+            .unwrap_or_default();
+        self.expires_at - REFRESH_MARGIN.min(lifetime / 10)
+    }
+}
+
+/// Signs credentials for `user` valid for `ttl` from `now`.
+pub fn sign(secret: &str, user: &str, ttl: Duration, now: SystemTime) -> TurnCredential {
+    let expires_at = now + ttl;
+    let expiry = expires_at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let username = if user.is_empty() {
+        expiry.to_string()
+    } else {
+        format!("{}:{}", expiry, user)
+    };
+    TurnCredential {
+        credential: password(secret, &username),
+        username,
+        urls: vec![],
+        issued_at: now,
+        expires_at,
+    }
+}
+
+/// The base64 HMAC-SHA1 of `username` under `secret`.
+fn password(secret: &str, username: &str) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret.as_bytes()).expect("HMAC takes any key");
+    mac.update(username.as_bytes());
+    BASE64.encode(mac.finalize().into_bytes())
+}
+
+#[derive(Deserialize)]
+struct RestResponse {
+    username: String,
+    password: String,
+    /// Seconds.
+    ttl: u64,
+    #[serde(default)]
+    uris: Vec<String>,
+}
+
+/// Asks a credentials service for credentials for `user`.
+pub async fn fetch(url: &str, user: &str) -> Result<TurnCredential, TurnRestError> {
+    let mut query = vec![("service", "turn")];
+    if !user.is_empty() {
+        query.push(("username", user));
+    }
+    let response = reqwest::Client::new()
+        .get(url.trim())
+        .query(&query)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(TurnRestError::Status(response.status()));
+    }
+    let issued: RestResponse = response.json().await?;
+    let now = SystemTime::now();
+    Ok(TurnCredential {
+        username: issued.username,
+        credential: issued.password,
+        urls: issued.uris,
+        issued_at: now,
+        expires_at: now + Duration::from_secs(issued.ttl),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn password_is_base64_hmac_sha1() {
+        // RFC 2202 test case 2.
+        assert_eq!(
+            password("Jefe", "what do ya want for nothing?"),
+            BASE64.encode([
+                0xef, 0xfc, 0xdf, 0x6a, 0xe5, 0xeb, 0x2f, 0xa2, 0xd2, 0x74, 0x16, 0xd5, 0xf1, 0x84,
+                0xdf, 0x9c, 0x25, 0x9a, 0x7c, 0x79,
+            ])
+        );
+    }
+
+    #[test]
+    fn signs_the_expiry_and_user() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let signed = sign("north", "alice", Duration::from_secs(3600), now);
+        assert_eq!(signed.username, "1700003600:alice");
+        // `printf '1700003600:alice' | openssl dgst -sha1 -hmac north -binary | base64`
+        assert_eq!(signed.credential, "wjwSXO2ch1B6VaLTLMy2Avn5O9o=");
+        assert_eq!(signed.issued_at, now);
+        assert_eq!(signed.expires_at, now + Duration::from_secs(3600));
+
+        let anonymous = sign("north", "", Duration::from_secs(3600), now);
+        assert_eq!(anonymous.username, "1700003600");
+        assert_eq!(anonymous.credential, password("north", "1700003600"));
+    }
+
+    #[test]
+    fn refreshes_ahead_of_expiry() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let day = sign("north", "alice", Duration::from_secs(86_400), now);
+        assert_eq!(day.refresh_at(), day.expires_at - REFRESH_MARGIN);
+        let short = sign("north", "alice", Duration::from_secs(600), now);
+        assert_eq!(short.refresh_at(), now + Duration::from_secs(540));
+    }
+}
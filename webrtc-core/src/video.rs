@@ -0,0 +1,414 @@
+//! Decoding received video for display, and a synthetic source for
+//! sending video without a camera.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use webrtc::api::media_engine::{
+    MediaEngine, MIME_TYPE_AV1, MIME_TYPE_H264, MIME_TYPE_OPUS, MIME_TYPE_VP8, MIME_TYPE_VP9,
+};
+use webrtc::media::io::sample_builder::SampleBuilder;
+use webrtc::media::Sample;
+use webrtc::rtp::codecs::h264::H264Packet;
+use webrtc::rtp::codecs::vp8::Vp8Packet;
+use webrtc::rtp::codecs::vp9::Vp9Packet;
+use webrtc::rtp::packetizer::Depacketizer;
+use webrtc::rtp_transceiver::rtp_codec::{
+    RTCRtpCodecCapability, RTCRtpCodecParameters, RTPCodecType,
+};
+use webrtc::rtp_transceiver::RTCPFeedback;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+use webrtc::track::track_remote::TrackRemote;
+
+use crate::codec::{self, EncoderConfig, LayerCap, ProfileLevelId, ScalabilityMode};
+use crate::lipsync::LipSync;
+use crate::recording::RecordingTap;
+use crate::rtc::webrtc_rs::RemoteTrack;
+use crate::rtc::RemoteTrackHandle;
+use crate::snapshot::Snapshot;
+
+/// A decoded picture, ready to upload as a texture.
+#[derive(Debug, Clone, Default)]
+pub struct VideoFrame {
+    pub width: usize,
+    pub height: usize,
+    /// Tightly packed RGBA, `width * height * 4` bytes.
+    pub rgba: Vec<u8>,
+}
+
+/// Turns complete access units (as assembled by a `SampleBuilder`) into
+/// frames.
+pub trait VideoDecoder: Send {
+    fn decode(&mut self, access_unit: &[u8]) -> Option<VideoFrame>;
+}
+
+/// Turns frames into access units for a `TrackLocalStaticSample`.
+pub trait VideoEncoder: Send {
+    fn encode(&mut self, frame: &VideoFrame) -> Option<Vec<u8>>;
+
+    /// Sends only the layers under `cap` from the next frame on; for
+    /// encoders of a single layer there is nothing to leave out.
+    fn set_layer_cap(&mut self, _cap: LayerCap) {}
+
+    /// Aims for `bitrate_bps` from the next frame on, as the link allows;
+    /// cheap when it has not changed. Encoders that cannot change it as
+    /// they go keep the one they started with.
+    fn set_bitrate(&mut self, _bitrate_bps: u32) {}
+}
+
+/// Shrinks `frame` by a whole `factor`, averaging each block of pixels,
+/// to an even size as the encoder needs.
+pub fn downscale(frame: &VideoFrame, factor: usize) -> VideoFrame {
+    let factor = factor.max(1);
+    let width = (frame.width / factor) & !1;
+    let height = (frame.height / factor) & !1;
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    let area = (factor * factor) as u32;
+    for row in 0..height {
+        for column in 0..width {
+            let mut sum = [0u32; 4];
+            for y in row * factor..(row + 1) * factor {
+                let start = (y * frame.width + column * factor) * 4;
+                for pixel in frame.rgba[start..start + factor * 4].chunks_exact(4) {
+                    for (sum, value) in sum.iter_mut().zip(pixel) {
+                        *sum += u32::from(*value);
+                    }
+                }
+            }
+            rgba.extend(sum.map(|sum| (sum / area) as u8));
+        }
+    }
+    VideoFrame {
+        width,
+        height,
+        rgba,
+    }
+}
+
+pub use crate::codec::{can_decode, decoder_for, encoder_mime_type};
+
+/// An encoder for this build's sending codec, with the default bitrate
+/// and keyframe interval for the size.
+pub fn encoder(width: usize, height: usize, frame_rate: f32) -> Option<Box<dyn VideoEncoder>> {
+    codec::encoder_for(
+        encoder_mime_type()?,
+        &EncoderConfig::new(width, height, frame_rate),
+    )
+}
+
+/// As `encoder`, for whichever codec and H.264 profile `track` was made
+/// for, in `scalability`'s layers where the codec has them.
+pub fn encoder_for_track(
+    track: &TrackLocalStaticSample,
+    width: usize,
+    height: usize,
+    frame_rate: f32,
+    scalability: ScalabilityMode,
+) -> Option<Box<dyn VideoEncoder>> {
+    let capability = track.codec();
+    let mut config = EncoderConfig::new(width, height, frame_rate).scalability(scalability);
+    if let Some(profile) = ProfileLevelId::from_fmtp(&capability.sdp_fmtp_line) {
+        config = config.h264_profile(profile);
+    }
+    codec::encoder_for(&capability.mime_type, &config)
+}
+
+/// Reassembles frames from a remote video track and decodes them into
+/// `frames` until the track ends.
+pub async fn play_track(track: Arc<TrackRemote>, frames: Snapshot<Option<VideoFrame>>) {
+    play_track_in_sync(track, frames, None, RecordingTap::default()).await;
+}
+
+/// As `play_track`, also reporting each frame to `sync` and holding frames
+/// back as long as it says the video is ahead of the audio. Each frame, as
+/// received, goes to `recording` too.
+pub async fn play_track_in_sync(
+    track: Arc<TrackRemote>,
+    frames: Snapshot<Option<VideoFrame>>,
+    sync: Option<Arc<Mutex<LipSync>>>,
+    recording: RecordingTap,
+) {
+    play_remote_track(Arc::new(RemoteTrack::new(track)), frames, sync, recording).await;
+}
+
+/// As `play_track_in_sync`, for a track from any `PeerConnectionHandle`.
+pub async fn play_remote_track(
+    track: Arc<dyn RemoteTrackHandle>,
+    frames: Snapshot<Option<VideoFrame>>,
+    sync: Option<Arc<Mutex<LipSync>>>,
+    recording: RecordingTap,
+) {
+    let codec = track.mime_type();
+    let decoder = decoder_for(&codec);
+    if codec.eq_ignore_ascii_case(MIME_TYPE_VP8) {
+        play(
+            track,
+            Vp8Packet::default(),
+            decoder,
+            frames,
+            sync,
+            recording,
+        )
+        .await;
+    } else if codec.eq_ignore_ascii_case(MIME_TYPE_VP9) {
+        play(
+            track,
+            Vp9Packet::default(),
+            decoder,
+            frames,
+            sync,
+            recording,
+        )
+        .await;
+    } else if codec.eq_ignore_ascii_case(MIME_TYPE_AV1) {
+        play(
+            track,
+            codec::Av1Packet::default(),
+            decoder,
+            frames,
+            sync,
+            recording,
+        )
+        .await;
+    } else {
+        play(
+            track,
+            H264Packet::default(),
+            decoder,
+            frames,
+            sync,
+            recording,
+        )
+        .await;
+    }
+}
+
+async fn play<T: Depacketizer>(
+    track: Arc<dyn RemoteTrackHandle>,
+    depacketizer: T,
+    mut decoder: Option<Box<dyn VideoDecoder>>,
+    frames: Snapshot<Option<VideoFrame>>,
+    sync: Option<Arc<Mutex<LipSync>>>,
+    recording: RecordingTap,
+) {
+    // In sync, frames wait out their delay on a task of their own so
+    // reading goes on, and in order so none overtakes a held one.
+    let held = sync.as_ref().map(|_| {
+        let (held_tx, mut held) = mpsc::unbounded_channel::<(Instant, VideoFrame)>();
+        let frames = frames.clone();
+        tokio::spawn(async move {
+            while let Some((at, frame)) = held.recv().await {
+                tokio::time::sleep_until(at.into()).await;
+                frames.set(Some(frame));
+            }
+        });
+        held_tx
+    });
+
+    let mut builder = SampleBuilder::new(128, depacketizer, 90000);
+    while let Ok(packet) = track.read_packet().await {
+        builder.push(packet.into());
+        while let Some(sample) = builder.pop() {
+            let frame = decoder.as_mut().and_then(|d| d.decode(&sample.data));
+            let size = frame.as_ref().map(|frame| (frame.width, frame.height));
+            recording.video(&sample.data, sample.packet_timestamp, size);
+            let Some(frame) = frame else {
+                continue;
+            };
+            let (Some(sync), Some(held)) = (&sync, &held) else {
+                frames.set(Some(frame));
+                continue;
+            };
+            let now = Instant::now();
+            let delay = {
+                let mut sync = sync.lock().unwrap();
+                sync.presented(RTPCodecType::Video, sample.packet_timestamp, now);
+                sync.corrections().video
+            };
+            let _ = held.send((now + delay, frame));
+        }
+    }
+}
+
+/// Moving colour bars with a frame counter strip, for sending video when
+/// there is no camera.
+pub struct TestPattern {
+    width: usize,
+    height: usize,
+    frame: u64,
+}
+
+const BARS: [[u8; 3]; 7] = [
+    [192, 192, 192],
+    [192, 192, 0],
+    [0, 192, 192],
+    [0, 192, 0],
+    [192, 0, 192],
+    [192, 0, 0],
+    [0, 0, 192],
+];
+
+impl TestPattern {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            frame: 0,
+        }
+    }
+
+    pub fn next_frame(&mut self) -> VideoFrame {
+        let (width, height) = (self.width, self.height);
+        let offset = (self.frame as usize * 4) % width;
+        let ticker = (self.frame as usize * 8) % width;
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let color = if y > height * 7 / 8 {
+                    // A block sweeping across the bottom shows motion.
+                    if x.abs_diff(ticker) < width / 32 {
+                        [255, 255, 255]
+                    } else {
+                        [16, 16, 16]
+                    }
+                } else {
+                    BARS[((x + offset) % width) * BARS.len() / width]
+                };
+                rgba.extend_from_slice(&[color[0], color[1], color[2], 255]);
+            }
+        }
+        self.frame += 1;
+        VideoFrame {
+            width,
+            height,
+            rgba,
+        }
+    }
+}
+
+/// Encodes a test pattern into `track` at `frame_rate` until the returned
+/// task is aborted. Returns `None` when this build has no video encoder.
+pub fn spawn_test_pattern(
+    track: Arc<TrackLocalStaticSample>,
+    width: usize,
+    height: usize,
+    frame_rate: f32,
+) -> Option<JoinHandle<()>> {
+    let mut encoder = encoder_for_track(&track, width, height, frame_rate, ScalabilityMode::L1T1)?;
+    let mut pattern = TestPattern::new(width, height);
+    let interval = Duration::from_secs_f32(1.0 / frame_rate);
+    Some(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let frame = pattern.next_frame();
+            let Some(data) = encoder.encode(&frame) else {
+                continue;
+            };
+            let sample = Sample {
+                data: Bytes::from(data),
+                duration: interval,
+                ..Default::default()
+            };
+            if let Err(err) = track.write_sample(&sample).await {
+                log::info!("Failed to write test pattern sample: {:?}", err);
+            }
+        }
+    }))
+}
+
+/// Registers Opus and the video codecs worth receiving for playback: the
+/// ones we can decode, or just the common ones when we cannot decode any.
+pub fn register_playback_codecs(media_engine: &mut MediaEngine) -> Result<(), webrtc::Error> {
+    media_engine.register_codec(
+        RTCRtpCodecParameters {
+            capability: RTCRtpCodecCapability {
+                mime_type: MIME_TYPE_OPUS.to_owned(),
+                clock_rate: 48000,
+                channels: 2,
+                sdp_fmtp_line: "minptime=10;useinbandfec=1".to_owned(),
+                rtcp_feedback: vec![],
+            },
+            payload_type: 111,
+            ..Default::default()
+        },
+        RTPCodecType::Audio,
+    )?;
+
+    let feedback = vec![
+        RTCPFeedback {
+            typ: "goog-remb".to_owned(),
+            parameter: String::new(),
+        },
+        RTCPFeedback {
+            typ: "nack".to_owned(),
+            parameter: String::new(),
+        },
+        RTCPFeedback {
+            typ: "nack".to_owned(),
+            parameter: "pli".to_owned(),
+        },
+    ];
+    let h264 = RTCRtpCodecParameters {
+        capability: RTCRtpCodecCapability {
+            mime_type: MIME_TYPE_H264.to_owned(),
+            clock_rate: 90000,
+            channels: 0,
+            sdp_fmtp_line: "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f"
+                .to_owned(),
+            rtcp_feedback: feedback.clone(),
+        },
+        payload_type: 102,
+        ..Default::default()
+    };
+    let vp8 = RTCRtpCodecParameters {
+        capability: RTCRtpCodecCapability {
+            mime_type: MIME_TYPE_VP8.to_owned(),
+            clock_rate: 90000,
+            channels: 0,
+            sdp_fmtp_line: String::new(),
+            rtcp_feedback: feedback.clone(),
+        },
+        payload_type: 96,
+        ..Default::default()
+    };
+    let vp9 = RTCRtpCodecParameters {
+        capability: RTCRtpCodecCapability {
+            mime_type: MIME_TYPE_VP9.to_owned(),
+            clock_rate: 90000,
+            channels: 0,
+            sdp_fmtp_line: "profile-id=0".to_owned(),
+            rtcp_feedback: feedback.clone(),
+        },
+        payload_type: 98,
+        ..Default::default()
+    };
+    let av1 = RTCRtpCodecParameters {
+        capability: RTCRtpCodecCapability {
+            mime_type: MIME_TYPE_AV1.to_owned(),
+            clock_rate: 90000,
+            channels: 0,
+            sdp_fmtp_line: "profile-id=0".to_owned(),
+            rtcp_feedback: feedback,
+        },
+        payload_type: 41,
+        ..Default::default()
+    };
+    let decodable: Vec<_> = [&vp8, &vp9, &h264, &av1]
+        .into_iter()
+        .filter(|codec| can_decode(&codec.capability.mime_type))
+        .cloned()
+        .collect();
+    let codecs = if decodable.is_empty() {
+        vec![vp8, h264]
+    } else {
+        decodable
+    };
+    for codec in codecs {
+        media_engine.register_codec(codec, RTPCodecType::Video)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,124 @@
+//! WHEP playback: receive a stream from a WHEP endpoint and decode its video.
+
+use std::sync::Arc;
+
+use log::info;
+use tokio::sync::mpsc;
+#[cfg(not(feature = "str0m"))]
+use webrtc::api::media_engine::MediaEngine;
+
+use crate::recording::RecordingTap;
+use crate::rtc::{
+    self, ConnectionState, Direction, MediaKind, PeerConnectionHandle, RtcError, SessionDescription,
+};
+use crate::settings::Settings;
+use crate::snapshot::Snapshot;
+use crate::video::{self, VideoFrame};
+use crate::whip::{self, WhipError, WhipSession};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WhepError {
+    #[error(transparent)]
+    Http(#[from] WhipError),
+    #[error("WebRTC error: {0}")]
+    WebRtc(#[from] webrtc::Error),
+    #[error(transparent)]
+    Rtc(#[from] RtcError),
+}
+
+#[derive(Debug, Clone)]
+pub enum PlayerEvent {
+    StateChanged(ConnectionState),
+    TrackStarted {
+        kind: MediaKind,
+        codec: String,
+        decodable: bool,
+    },
+}
+
+pub struct WhepPlayer {
+    pc: Arc<dyn PeerConnectionHandle>,
+    client: reqwest::Client,
+    session: WhipSession,
+    bearer_token: Option<String>,
+}
+
+impl WhepPlayer {
+    /// Connects to the endpoint with a receive-only offer. Decoded video
+    /// frames are published to `frames`.
+    pub async fn start(
+        settings: &Settings,
+        endpoint: &str,
+        bearer_token: Option<String>,
+        frames: Snapshot<Option<VideoFrame>>,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<PlayerEvent>), WhepError> {
+        #[cfg(feature = "str0m")]
+        let pc: Arc<dyn PeerConnectionHandle> = rtc::str0m::open(settings).await?;
+        #[cfg(not(feature = "str0m"))]
+        let pc: Arc<dyn PeerConnectionHandle> = {
+            let mut media_engine = MediaEngine::default();
+            video::register_playback_codecs(&mut media_engine)?;
+            rtc::webrtc_rs::open(settings, media_engine).await?
+        };
+        for kind in [MediaKind::Audio, MediaKind::Video] {
+            pc.add_transceiver(kind, Direction::RecvOnly).await?;
+        }
+
+        let (events_tx, events) = mpsc::unbounded_channel();
+        let state_tx = events_tx.clone();
+        pc.on_connection_state_change(Box::new(move |state| {
+            let _ = state_tx.send(PlayerEvent::StateChanged(state));
+            Box::pin(async {})
+        }));
+        pc.on_track(Box::new(move |track| {
+            let codec = track.mime_type();
+            let kind = track.kind();
+            let decodable = kind == MediaKind::Video && video::can_decode(&codec);
+            let _ = events_tx.send(PlayerEvent::TrackStarted {
+                kind,
+                codec: codec.clone(),
+                decodable,
+            });
+            let frames = frames.clone();
+            Box::pin(async move {
+                if kind != MediaKind::Video {
+                    // No audio playback yet; keep reading so the stream
+                    // does not back up.
+                    while track.read_packet().await.is_ok() {}
+                    return;
+                }
+                video::play_remote_track(track, frames, None, RecordingTap::default()).await;
+            })
+        }));
+
+        let offer = pc.create_offer().await?;
+        let offer = pc.set_local_description_gathered(offer).await?;
+
+        let client = reqwest::Client::new();
+        let session =
+            whip::post_offer(&client, endpoint, &offer.sdp, bearer_token.as_deref()).await?;
+        pc.set_remote_description(SessionDescription::answer(session.answer.clone()))
+            .await?;
+
+        Ok((
+            Self {
+                pc,
+                client,
+                session,
+                bearer_token,
+            },
+            events,
+        ))
+    }
+
+    pub async fn stop(self) {
+        if let Err(err) =
+            whip::delete_session(&self.client, &self.session, self.bearer_token.as_deref()).await
+        {
+            info!("Failed to delete WHEP session: {:?}", err);
+        }
+        if let Err(err) = self.pc.close().await {
+            info!("Failed to close WHEP peer connection: {:?}", err);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! HTTP offer/answer exchange in the style of WHIP (RFC 9725) and WHEP: the
+//! offer is POSTed as `application/sdp` and the answer comes back in the
+//! response body, with a `Location` header naming the session resource.
+
+use reqwest::{header, StatusCode};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WhipError {
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("endpoint answered {0}")]
+    Status(StatusCode),
+    #[error("invalid endpoint URL: {0}")]
+    Url(String),
+}
+
+/// A session created on a WHIP/WHEP endpoint.
+#[derive(Debug, Clone)]
+pub struct WhipSession {
+    pub answer: String,
+    /// Absolute URL of the session resource, used to tear it down.
+    pub resource: Option<String>,
+}
+
+pub async fn post_offer(
+    client: &reqwest::Client,
+    endpoint: &str,
+    offer: &str,
+    bearer_token: Option<&str>,
+) -> Result<WhipSession, WhipError> {
+    let endpoint_url =
+        reqwest::Url::parse(endpoint).map_err(|err| WhipError::Url(err.to_string()))?;
+    let mut request = client
+        .post(endpoint_url.clone())
+        .header(header::CONTENT_TYPE, "application/sdp")
+        .body(offer.to_owned());
+    if let Some(token) = bearer_token {
+        request = request.bearer_auth(token);
+    }
+
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return Err(WhipError::Status(response.status()));
+    }
+    let resource = response
+        .headers()
+        .get(header::LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .and_then(|location| endpoint_url.join(location).ok())
+        .map(String::from);
+    let answer = response.text().await?;
+    Ok(WhipSession { answer, resource })
+}
+
+/// Ends a session by deleting its resource.
+pub async fn delete_session(
+    client: &reqwest::Client,
+    session: &WhipSession,
+    bearer_token: Option<&str>,
+) -> Result<(), WhipError> {
+    let Some(resource) = &session.resource else {
+        return Ok(());
+    };
+    let mut request = client.delete(resource);
+    if let Some(token) = bearer_token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return Err(WhipError::Status(response.status()));
+    }
+    Ok(())
+}
@@ -0,0 +1,780 @@
+//! Sending the selected camera on the current peer connection, with a
+//! self-view that can be opened before a call to check the picture.
+//! Picking another camera while sending moves the same track over to it,
+//! as does picking another size or frame rate, and answering an offer
+//! moves the camera to the codec the offer prefers.
+//! Sent in scalable layers, the top ones can be left out from here. With
+//! adaptive bitrate on, the feedback on the sender sets what it sends.
+//! Stopping the video keeps the track but turns its m-line receive-only
+//! until it resumes, and the peer is told to show a placeholder.
+//! The background can be blurred or replaced while the camera is open,
+//! in the preview and what is sent alike.
+
+use std::sync::Arc;
+
+use eframe::egui;
+use log::info;
+use tokio::task::JoinHandle;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::rtp_transceiver::rtp_sender::RTCRtpSender;
+use webrtc::rtp_transceiver::rtp_transceiver_direction::RTCRtpTransceiverDirection;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+use webrtc::track::track_local::TrackLocal;
+use webrtc_core::bandwidth::{self, SendTarget};
+use webrtc_core::camera::{self, BackgroundSettings, BackgroundStage, Camera, CameraFeed};
+use webrtc_core::codec::{self, EncoderConfig, LayerCap, ScalabilityMode};
+use webrtc_core::constraints::{VideoSelection, FRAME_RATES, RESOLUTIONS};
+use webrtc_core::control::ControlMessage;
+use webrtc_core::devices::CaptureMode;
+use webrtc_core::snapshot::Snapshot;
+use webrtc_core::video::{self, VideoFrame};
+
+use crate::settings_window::background_effect_ui;
+use crate::video_view::VideoView;
+use crate::WebRTCApp;
+
+const PREVIEW_WIDTH: f32 = 240.0;
+/// The self view over the remote video, as a share of its width.
+const OVERLAY_SCALE: f32 = 0.25;
+/// Between the self view and the edges of the remote video.
+const OVERLAY_MARGIN: f32 = 8.0;
+
+pub struct CameraState {
+    /// The open camera, previewing and possibly sending.
+    feed: Option<CameraFeed>,
+    /// Which camera `feed` is, and in which mode it was opened.
+    device: String,
+    mode: Option<CaptureMode>,
+    preview: Snapshot<Option<VideoFrame>>,
+    view: VideoView,
+    /// Where the self view was dragged to over the remote video, from the
+    /// top left of the room it has; the bottom right corner until then.
+    overlay_offset: Option<egui::Vec2>,
+    sender: Option<Arc<RTCRtpSender>>,
+    /// What `sender` sends, kept to feed from another camera.
+    track: Option<Arc<TrackLocalStaticSample>>,
+    busy: bool,
+    /// Moving the track to another camera.
+    switching: bool,
+    /// Sending stopped by the user, with the track kept for resuming.
+    paused: bool,
+    status: String,
+    /// The layers `track` goes out in.
+    layers: ScalabilityMode,
+    /// How many of them are sent, read by the camera thread.
+    layer_cap: Snapshot<LayerCap>,
+    /// What the link allows, read by the camera thread too.
+    send_target: Snapshot<Option<SendTarget>>,
+    /// Reads the feedback on `sender` into `send_target`.
+    rate_control: Option<JoinHandle<()>>,
+    /// The settings' background effect, read by the camera thread, and
+    /// why it is not working when it is not.
+    background: Snapshot<BackgroundSettings>,
+    background_problem: Snapshot<Option<String>>,
+}
+
+impl CameraState {
+    pub fn new(ctx: &egui::Context) -> Self {
+        let preview = Snapshot::default();
+        Self {
+            feed: None,
+            device: String::new(),
+            mode: None,
+            view: VideoView::new("camera_preview".to_owned(), preview.clone(), ctx),
+            preview,
+            overlay_offset: None,
+            sender: None,
+            track: None,
+            busy: false,
+            switching: false,
+            paused: false,
+            status: String::new(),
+            layers: ScalabilityMode::L1T1,
+            layer_cap: Snapshot::default(),
+            send_target: Snapshot::default(),
+            rate_control: None,
+            background: Snapshot::default(),
+            background_problem: Snapshot::default(),
+        }
+    }
+
+    fn sending(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// Stops sending and hands back the sender to remove, if any. The
+    /// preview stays open.
+    fn stop_sending(&mut self) -> Option<Arc<RTCRtpSender>> {
+        if let Some(feed) = &self.feed {
+            let _ = feed.send_to(None, ScalabilityMode::L1T1);
+        }
+        self.track = None;
+        self.paused = false;
+        self.layers = ScalabilityMode::L1T1;
+        if let Some(rate_control) = self.rate_control.take() {
+            rate_control.abort();
+        }
+        self.send_target.set(None);
+        self.sender.take()
+    }
+
+    fn close(&mut self) {
+        self.stop_sending();
+        self.close_feed();
+    }
+
+    /// Closes the camera but leaves the track and sender in place.
+    fn close_feed(&mut self) {
+        self.feed = None;
+        self.preview.set(None);
+    }
+
+    /// Stops sending for a peer connection that is being replaced, which
+    /// takes its senders with it. An open preview carries on.
+    pub(crate) fn reset(&mut self) {
+        self.stop_sending();
+        self.status.clear();
+    }
+
+    /// Sends the feed into `track` in the settings' `mode`, as far as its
+    /// codec allows.
+    fn send_to(
+        &mut self,
+        track: Arc<TrackLocalStaticSample>,
+        mode: ScalabilityMode,
+    ) -> Result<(), String> {
+        let Some(feed) = &self.feed else {
+            return Err("the camera was closed".to_owned());
+        };
+        let layers = codec::scalability_for(&track.codec().mime_type, mode);
+        feed.send_to(Some(track), layers)
+            .map_err(|err| err.to_string())?;
+        self.layers = layers;
+        Ok(())
+    }
+
+    /// Follows the link from the feedback on the sender, for the feed's
+    /// size and frame rate, starting from the last target when there is
+    /// one; or sends at the encoder's own bitrate when `adaptive` is off.
+    fn start_rate_control(&mut self, adaptive: bool) {
+        if let Some(rate_control) = self.rate_control.take() {
+            rate_control.abort();
+        }
+        let (Some(sender), Some(feed), true) = (&self.sender, &self.feed, adaptive) else {
+            self.send_target.set(None);
+            return;
+        };
+        let max_bps =
+            EncoderConfig::new(feed.width(), feed.height(), feed.frame_rate() as f32).bitrate_bps;
+        self.rate_control = Some(bandwidth::spawn_rate_control(
+            Arc::clone(sender),
+            max_bps,
+            self.send_target.clone(),
+        ));
+    }
+
+    /// Hands a changed background effect to the camera thread.
+    fn follow_background(&self, settings: &BackgroundSettings) {
+        if *self.background.get() != *settings {
+            self.background.set(settings.clone());
+        }
+    }
+
+    fn layer_cap_ui(&self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(format!("{} layers", self.layers))
+            .id_source("camera_layers")
+            .show(ui, |ui| {
+                let mut cap = *self.layer_cap.get();
+                let spatial = self.layers.spatial_layers();
+                let temporal = self.layers.temporal_layers();
+                cap.spatial_layers = cap.spatial_layers.min(spatial);
+                cap.temporal_layers = cap.temporal_layers.min(temporal);
+                let before = cap;
+                if spatial > 1 {
+                    ui.add(
+                        egui::Slider::new(&mut cap.spatial_layers, 1..=spatial)
+                            .text("spatial layers sent"),
+                    );
+                }
+                if temporal > 1 {
+                    ui.add(
+                        egui::Slider::new(&mut cap.temporal_layers, 1..=temporal)
+                            .text("temporal layers sent"),
+                    )
+                    .on_hover_text("Each one doubles the frame rate of those under it");
+                }
+                if cap != before {
+                    self.layer_cap.set(cap);
+                }
+            });
+    }
+}
+
+impl WebRTCApp {
+    /// What to send video as on `pc`: of the codecs the settings and this
+    /// call's override allow, the one the remote description prefers once
+    /// there is one, the settings' first before, with the remote's H.264
+    /// profile if it comes to that.
+    pub(crate) async fn sending_video_capability(
+        &self,
+        pc: &RTCPeerConnection,
+    ) -> Option<RTCRtpCodecCapability> {
+        let preferences = self.settings.lock().unwrap().media.codecs.clone();
+        let codecs = *self.codecs.lock().unwrap();
+        let remote = pc.remote_description().await.map(|remote| remote.sdp);
+        let mime_type = remote
+            .as_deref()
+            .and_then(|sdp| {
+                codec::remote_preference_among(sdp, |mime_type| {
+                    preferences.allows(mime_type) && codecs.allows_codec(mime_type)
+                })
+            })
+            .or_else(|| preferences.preferred_encoder(&codecs))
+            .or_else(video::encoder_mime_type)?;
+        Some(codec::sending_capability(mime_type, remote.as_deref()))
+    }
+
+    /// Opens the selected camera for the self-view only.
+    async fn open_camera_preview(&self, selection: VideoSelection) {
+        let status = match self.open_camera_feed(&selection).await {
+            Ok(()) => format!("Previewing {}", selection.device.name),
+            Err(err) => {
+                info!("Failed to open camera: {}", err);
+                format!("Camera failed: {}", err)
+            }
+        };
+        let mut state = self.camera.lock().unwrap();
+        state.busy = false;
+        state.status = status;
+    }
+
+    /// Opens `selection` unless the preview already shows it.
+    async fn open_camera_feed(&self, selection: &VideoSelection) -> Result<(), String> {
+        let same_camera = {
+            let mut state = self.camera.lock().unwrap();
+            if state.feed.is_some()
+                && state.device == selection.device.id
+                && state.mode == selection.mode
+            {
+                return Ok(());
+            }
+            // The old camera closes at its next frame, well before a
+            // different one has finished opening; the same one in another
+            // mode has to wait for it.
+            let feed = state.feed.take();
+            state.close_feed();
+            feed.filter(|_| state.device == selection.device.id)
+        };
+        let (device, mode) = (selection.device.clone(), selection.mode.clone());
+        let camera = tokio::task::spawn_blocking(move || {
+            if let Some(feed) = same_camera {
+                feed.close();
+            }
+            Camera::open(&device, mode.as_ref())
+        })
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+        let limits = self.power_saving.lock().unwrap().limits();
+        let mut state = self.camera.lock().unwrap();
+        let feed = camera::spawn_camera(
+            camera,
+            state.preview.clone(),
+            limits,
+            state.layer_cap.clone(),
+            state.send_target.clone(),
+            BackgroundStage::new(state.background.clone(), state.background_problem.clone()),
+        )
+        .map_err(|err| err.to_string())?;
+        state.feed = Some(feed);
+        state.device = selection.device.id.clone();
+        state.mode = selection.mode.clone();
+        Ok(())
+    }
+
+    /// Adds a camera track to the current peer connection; once connected
+    /// that renegotiates over the control channel, and before then the
+    /// track goes out with the first offer or answer.
+    async fn start_camera(&self, selection: VideoSelection) {
+        let status = match self.add_camera_track(selection).await {
+            Ok(status) => status,
+            Err(err) => {
+                info!("Failed to start camera: {}", err);
+                format!("Camera failed: {}", err)
+            }
+        };
+        let mut state = self.camera.lock().unwrap();
+        state.busy = false;
+        state.status = status;
+    }
+
+    async fn add_camera_track(&self, selection: VideoSelection) -> Result<String, String> {
+        let pc = self.peer_connection.lock().await.clone();
+        let pc = pc.ok_or("initialize a peer connection first")?;
+        let capability = self
+            .sending_video_capability(&pc)
+            .await
+            .ok_or_else(|| camera::CameraError::NoEncoder.to_string())?;
+        self.open_camera_feed(&selection).await?;
+
+        let track = Arc::new(TrackLocalStaticSample::new(
+            capability,
+            "video".to_owned(),
+            "local".to_owned(),
+        ));
+        let sender = pc
+            .add_track(track.clone())
+            .await
+            .map_err(|err| err.to_string())?;
+        let (mode, adaptive) = {
+            let settings = self.settings.lock().unwrap();
+            (settings.media.scalability, settings.media.adaptive_bitrate)
+        };
+        let sent = {
+            let mut state = self.camera.lock().unwrap();
+            let sent = state.send_to(track.clone(), mode).map(|()| {
+                let feed = state.feed.as_ref().expect("sent from the feed");
+                format!(
+                    "Sending {} at {}x{}, {:.0} fps",
+                    selection.device.name,
+                    feed.width(),
+                    feed.height(),
+                    feed.frame_rate()
+                )
+            });
+            if sent.is_ok() {
+                state.sender = Some(sender.clone());
+                state.track = Some(track);
+                state.start_rate_control(adaptive);
+            }
+            sent
+        };
+        match sent {
+            Ok(status) => {
+                info!("{}", status);
+                Ok(status)
+            }
+            Err(err) => {
+                let _ = pc.remove_track(&sender).await;
+                Err(err)
+            }
+        }
+    }
+
+    /// Moves sending over to `selection`, feeding the same track so the
+    /// peer sees the new camera without a renegotiation.
+    async fn switch_camera(&self, selection: VideoSelection) {
+        let opened = self.open_camera_feed(&selection).await;
+        let (mode, adaptive) = {
+            let settings = self.settings.lock().unwrap();
+            (settings.media.scalability, settings.media.adaptive_bitrate)
+        };
+        let mut state = self.camera.lock().unwrap();
+        let sent = opened.and_then(|()| match state.track.clone() {
+            // Picks up from the new camera on resuming.
+            Some(_) if state.paused => Ok(()),
+            Some(track) => state.send_to(track, mode),
+            None => Err("the camera was stopped".to_owned()),
+        });
+        // The new camera's size sets what full quality takes.
+        if sent.is_ok() {
+            state.start_rate_control(adaptive);
+        }
+        state.status = match sent {
+            Ok(()) => format!("Sending {}", selection.device.name),
+            Err(err) => {
+                info!("Failed to switch camera: {}", err);
+                format!("Switching to {} failed: {}", selection.device.name, err)
+            }
+        };
+        state.busy = false;
+        state.switching = false;
+    }
+
+    /// Moves the camera over to the codec and H.264 profile the remote
+    /// offer prefers, before it is answered. A track's codec is fixed, so this swaps in a new one
+    /// on the same sender; webrtc-rs keeps the old one if the new codec
+    /// cannot be bound.
+    pub(crate) async fn follow_remote_video_codec(&self, pc: &RTCPeerConnection) {
+        let Some(capability) = self.sending_video_capability(pc).await else {
+            return;
+        };
+        let name = codec::display_name(&capability.mime_type).to_owned();
+        let (sender, track) = {
+            let state = self.camera.lock().unwrap();
+            let (Some(sender), Some(track)) = (&state.sender, &state.track) else {
+                return;
+            };
+            let current = track.codec();
+            if current
+                .mime_type
+                .eq_ignore_ascii_case(&capability.mime_type)
+                && current.sdp_fmtp_line == capability.sdp_fmtp_line
+            {
+                return;
+            }
+            let replacement = Arc::new(TrackLocalStaticSample::new(
+                capability,
+                track.id().to_owned(),
+                track.stream_id().to_owned(),
+            ));
+            (Arc::clone(sender), replacement)
+        };
+        if let Err(err) = sender.replace_track(Some(track.clone())).await {
+            info!("Cannot send the camera in {}: {}", name, err);
+            return;
+        }
+        let mode = self.settings.lock().unwrap().media.scalability;
+        let mut state = self.camera.lock().unwrap();
+        let sent = match state.feed {
+            Some(_) if !state.paused => state.send_to(track.clone(), mode),
+            _ => Ok(()),
+        };
+        match sent {
+            Ok(()) => {
+                info!("Sending the camera in {}", name);
+                state.track = Some(track);
+            }
+            Err(err) => info!("Failed to switch the camera codec: {}", err),
+        }
+    }
+
+    /// Moves an open camera over once another one is picked in the device
+    /// settings, or the constraints pick another mode of it: the track
+    /// being sent goes along, and a preview reopens.
+    /// The background effect follows the settings too, as they change.
+    pub(crate) fn poll_camera_device(&self, ctx: &egui::Context) {
+        let background = self.settings.lock().unwrap().media.background.clone();
+        {
+            let state = self.camera.lock().unwrap();
+            state.follow_background(&background);
+            if state.feed.is_none() || state.busy {
+                return;
+            }
+        }
+        // The constraints can favour another camera than the one picked.
+        let Some(Ok(selection)) = self.camera_selection() else {
+            return;
+        };
+        let mut state = self.camera.lock().unwrap();
+        if state.device == selection.device.id && state.mode == selection.mode {
+            return;
+        }
+        state.busy = true;
+        let app = self.clone();
+        let ctx = ctx.clone();
+        if state.sending() {
+            state.switching = true;
+            state.status = format!("Switching to {}...", selection.device.name);
+            tokio::spawn(async move {
+                app.switch_camera(selection).await;
+                ctx.request_repaint();
+            });
+        } else {
+            state.status = format!("Opening {}...", selection.device.name);
+            tokio::spawn(async move {
+                app.open_camera_preview(selection).await;
+                ctx.request_repaint();
+            });
+        }
+    }
+
+    /// Picks the size and frame rate to capture in, as the ideal video
+    /// constraints; the camera follows through `poll_camera_device`.
+    fn capture_format_ui(&self, ui: &mut egui::Ui) {
+        let mut settings = self.settings.lock().unwrap();
+        let constraints = &mut settings.media.video_constraints;
+        ui.horizontal(|ui| {
+            ui.label("Capture:");
+            let size = (constraints.width.ideal, constraints.height.ideal);
+            let selected = RESOLUTIONS
+                .iter()
+                .find(|(_, width, height)| size == (Some(*width), Some(*height)))
+                .map_or_else(
+                    || match size {
+                        (Some(width), Some(height)) => format!("{}x{}", width, height),
+                        _ => "Any size".to_owned(),
+                    },
+                    |(name, _, _)| (*name).to_owned(),
+                );
+            egui::ComboBox::from_id_source("capture_resolution")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for (name, width, height) in RESOLUTIONS {
+                        let chosen = size == (Some(width), Some(height));
+                        if ui.selectable_label(chosen, name).clicked() {
+                            constraints.width.ideal = Some(width);
+                            constraints.height.ideal = Some(height);
+                        }
+                    }
+                });
+            let frame_rate = &mut constraints.frame_rate.ideal;
+            let selected =
+                frame_rate.map_or("Any rate".to_owned(), |rate| format!("{:.0} fps", rate));
+            egui::ComboBox::from_id_source("capture_frame_rate")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for rate in FRAME_RATES {
+                        ui.selectable_value(frame_rate, Some(rate), format!("{:.0} fps", rate));
+                    }
+                });
+        })
+        .response
+        .on_hover_text("The closest mode the camera has; exact limits are in Settings");
+    }
+
+    /// Blurs or replaces the background of the open camera; the choice is
+    /// kept in the settings, where the model and image are set.
+    fn background_ui(&self, ui: &mut egui::Ui, state: &CameraState) {
+        if !camera::can_segment() {
+            return;
+        }
+        background_effect_ui(
+            ui,
+            "Background",
+            &mut self.settings.lock().unwrap().media.background,
+        );
+        if let Some(problem) = &*state.background_problem.get() {
+            ui.colored_label(egui::Color32::YELLOW, problem);
+        }
+    }
+
+    pub(crate) fn switching_camera(&self) -> bool {
+        self.camera.lock().unwrap().switching
+    }
+
+    /// The camera's frames as previewed, effects and all.
+    pub(crate) fn camera_preview(&self) -> Snapshot<Option<VideoFrame>> {
+        self.camera.lock().unwrap().preview.clone()
+    }
+
+    async fn stop_camera(&self) {
+        let sender = self.camera.lock().unwrap().stop_sending();
+        let pc = self.peer_connection.lock().await.clone();
+        if let Some((pc, sender)) = pc.zip(sender) {
+            if let Err(err) = pc.remove_track(&sender).await {
+                info!("Failed to remove camera track: {:?}", err);
+            }
+        }
+        let mut state = self.camera.lock().unwrap();
+        state.busy = false;
+        state.status = "Camera stopped; still previewing".to_owned();
+    }
+
+    /// Stops sending video, or resumes it, without taking the track down:
+    /// the transceiver turns receive-only meanwhile, which renegotiates.
+    async fn pause_camera(&self, paused: bool) {
+        let status = match self.set_camera_direction(paused).await {
+            Ok(()) => {
+                let mode = self.settings.lock().unwrap().media.scalability;
+                let mut state = self.camera.lock().unwrap();
+                let sent = match (paused, state.track.clone()) {
+                    (true, _) => state.feed.as_ref().map_or(Ok(()), |feed| {
+                        feed.send_to(None, ScalabilityMode::L1T1)
+                            .map_err(|err| err.to_string())
+                    }),
+                    (false, Some(track)) => state.send_to(track, mode),
+                    (false, None) => Err("the camera was stopped".to_owned()),
+                };
+                match sent {
+                    Ok(()) => {
+                        state.paused = paused;
+                        if paused {
+                            "Video stopped; the peer sees a placeholder".to_owned()
+                        } else {
+                            "Video resumed".to_owned()
+                        }
+                    }
+                    Err(err) => format!("Camera failed: {}", err),
+                }
+            }
+            Err(err) => format!("Failed to change the video direction: {}", err),
+        };
+        info!("{}", status);
+        self.announce_camera_paused().await;
+        let mut state = self.camera.lock().unwrap();
+        state.busy = false;
+        state.status = status;
+    }
+
+    /// Turns the camera's transceiver receive-only while `paused`, and back
+    /// to sending otherwise.
+    async fn set_camera_direction(&self, paused: bool) -> Result<(), String> {
+        let sender = self.camera.lock().unwrap().sender.clone();
+        let pc = self.peer_connection.lock().await.clone();
+        let (Some(pc), Some(sender)) = (pc, sender) else {
+            return Err("the camera is not being sent".to_owned());
+        };
+        for transceiver in pc.get_transceivers().await {
+            if Arc::ptr_eq(&transceiver.sender().await, &sender) {
+                let receiving = transceiver.direction().has_recv();
+                transceiver
+                    .set_direction(RTCRtpTransceiverDirection::from_send_recv(
+                        !paused, receiving,
+                    ))
+                    .await;
+                return Ok(());
+            }
+        }
+        Err("its transceiver is gone".to_owned())
+    }
+
+    /// Tells the peer whether the video is stopped, as it changes and once
+    /// the control channel opens.
+    pub(crate) async fn announce_camera_paused(&self) {
+        let paused = {
+            let state = self.camera.lock().unwrap();
+            if !state.sending() {
+                return;
+            }
+            state.paused
+        };
+        self.send_control(ControlMessage::CameraPaused { paused })
+            .await;
+    }
+
+    pub(crate) fn camera_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let overlaid = self.self_view_overlaid();
+        let mut state = self.camera.lock().unwrap();
+        ui.horizontal(|ui| {
+            if state.sending() {
+                let paused = state.paused;
+                let label = if paused { "Resume Video" } else { "Stop Video" };
+                if ui
+                    .add_enabled(!state.busy, egui::Button::new(label))
+                    .on_hover_text("Keeps the call's video line, for resuming without a new track")
+                    .clicked()
+                {
+                    state.busy = true;
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.pause_camera(!paused).await;
+                        ctx.request_repaint();
+                    });
+                }
+                if ui
+                    .add_enabled(!state.busy, egui::Button::new("Stop Camera"))
+                    .clicked()
+                {
+                    state.busy = true;
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.stop_camera().await;
+                        ctx.request_repaint();
+                    });
+                }
+            } else {
+                let selection = self.camera_selection().and_then(Result::ok);
+                let reason = selection.is_none().then_some("No usable camera");
+                let send_reason = reason.or_else(|| {
+                    video::encoder_mime_type()
+                        .is_none()
+                        .then_some("This build has no video encoder")
+                });
+                let button = ui
+                    .add_enabled(
+                        !state.busy && send_reason.is_none(),
+                        egui::Button::new("Start Camera"),
+                    )
+                    .on_disabled_hover_text(send_reason.unwrap_or_default());
+                if let (true, Some(selection)) = (button.clicked(), selection.clone()) {
+                    state.busy = true;
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.start_camera(selection).await;
+                        ctx.request_repaint();
+                    });
+                }
+                if state.feed.is_some() {
+                    if ui
+                        .add_enabled(!state.busy, egui::Button::new("Close Preview"))
+                        .clicked()
+                    {
+                        state.close();
+                        state.status.clear();
+                    }
+                } else {
+                    let button = ui
+                        .add_enabled(
+                            !state.busy && reason.is_none(),
+                            egui::Button::new("Preview"),
+                        )
+                        .on_hover_text("Check the picture before sending it")
+                        .on_disabled_hover_text(reason.unwrap_or_default());
+                    if let (true, Some(selection)) = (button.clicked(), selection) {
+                        state.busy = true;
+                        let app = self.clone();
+                        let ctx = ctx.clone();
+                        tokio::spawn(async move {
+                            app.open_camera_preview(selection).await;
+                            ctx.request_repaint();
+                        });
+                    }
+                }
+            }
+            if state.switching {
+                ui.spinner();
+            }
+            ui.label(&state.status);
+        });
+        if state.feed.is_some() {
+            self.capture_format_ui(ui);
+            self.background_ui(ui, &state);
+        }
+        if let (true, Some(target)) = (state.sending(), *state.send_target.get()) {
+            ui.label(target.to_string());
+        }
+        if state.sending() && state.layers.is_scalable() {
+            state.layer_cap_ui(ui);
+        }
+        if state.feed.is_some() {
+            if overlaid {
+                ui.weak("Previewing over the remote video");
+            } else {
+                self.self_view_ui(ui, &mut state, PREVIEW_WIDTH);
+            }
+        }
+    }
+
+    /// Shows the preview at `width`.
+    fn self_view_ui(
+        &self,
+        ui: &mut egui::Ui,
+        state: &mut CameraState,
+        width: f32,
+    ) -> Option<egui::Response> {
+        // The tile's menu and the setting both flip the preview.
+        let mirror = &mut self.settings.lock().unwrap().media.mirror_self_view;
+        state.view.transform.mirror = *mirror;
+        let response = state.view.show(ui, width);
+        *mirror = state.view.transform.mirror;
+        response
+    }
+
+    /// Puts the preview in a corner of `picture`, the remote video, from
+    /// where it can be dragged anywhere over it.
+    pub(crate) fn self_view_overlay_ui(&self, ui: &mut egui::Ui, picture: egui::Rect) {
+        let mut state = self.camera.lock().unwrap();
+        if state.feed.is_none() {
+            return;
+        }
+        let width = (picture.width() * OVERLAY_SCALE).round();
+        let size = egui::vec2(width, width * state.view.aspect());
+        let room = picture.shrink(OVERLAY_MARGIN);
+        let offset = state.overlay_offset.unwrap_or(room.max - size - room.min);
+        let min = (room.min + offset).clamp(room.min, (room.max - size).max(room.min));
+        let rect = egui::Rect::from_min_size(min, size);
+        let mut overlay = ui.child_ui(rect, egui::Layout::top_down(egui::Align::Min));
+        if let Some(tile) = self.self_view_ui(&mut overlay, &mut state, width) {
+            if tile.dragged() {
+                state.overlay_offset = Some(min + tile.drag_delta() - room.min);
+            }
+            let stroke = ui.visuals().widgets.noninteractive.fg_stroke;
+            overlay.painter().rect_stroke(tile.rect, 2.0, stroke);
+        }
+    }
+}
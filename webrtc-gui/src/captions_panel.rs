@@ -0,0 +1,242 @@
+//! Live captions over the "captions" data channel: typed here and shown on
+//! both sides while captions are on, then saved as a transcript when the
+//! call ends, beside the call's recording when there is one.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use eframe::egui;
+use log::info;
+use webrtc_core::captions::{
+    self, Caption, CaptionFormat, Cue, Transcript, CAPTIONS_LABEL, CAPTIONS_STREAM_ID,
+};
+
+use crate::negotiated_channel::{self, ChannelPanel, NegotiatedChannel};
+use crate::WebRTCApp;
+
+/// Cues the panel shows, newest last.
+const SHOWN_CUES: usize = 5;
+
+/// A call's transcript once the call is over.
+struct Ended {
+    transcript: Transcript,
+    started: Instant,
+    ended: Instant,
+}
+
+pub struct CaptionsState {
+    channel: NegotiatedChannel,
+    enabled: bool,
+    format: CaptionFormat,
+    /// When the call's channel opened; cue times count from here.
+    started: Option<Instant>,
+    transcript: Transcript,
+    /// The last call's transcript, until it is saved.
+    ended: Option<Ended>,
+    draft: String,
+    status: String,
+    /// Captions arrive on background tasks; redraw when they do.
+    ctx: egui::Context,
+}
+
+impl CaptionsState {
+    pub fn new(ctx: &egui::Context) -> Self {
+        Self {
+            channel: NegotiatedChannel::default(),
+            enabled: false,
+            format: CaptionFormat::Vtt,
+            started: None,
+            transcript: Transcript::default(),
+            ended: None,
+            draft: String::new(),
+            status: String::new(),
+            ctx: ctx.clone(),
+        }
+    }
+
+    /// Adds `caption` to the transcript, timed from the start of the call.
+    fn push(&mut self, caption: Caption) {
+        let Some(started) = self.started else {
+            return;
+        };
+        self.transcript
+            .push(Cue::arrived(started.elapsed(), caption));
+        self.ctx.request_repaint();
+    }
+
+    fn receive(&mut self, caption: Caption) {
+        if self.enabled {
+            self.push(caption);
+        } else {
+            self.status = "The peer is captioning; turn captions on to see them".to_owned();
+            self.ctx.request_repaint();
+        }
+    }
+
+    /// Puts the call's transcript aside for saving.
+    fn end_call(&mut self) {
+        let transcript = std::mem::take(&mut self.transcript);
+        if let Some(started) = self.started.take() {
+            if self.enabled && !transcript.is_empty() {
+                self.ended = Some(Ended {
+                    transcript,
+                    started,
+                    ended: Instant::now(),
+                });
+            }
+        }
+    }
+
+    /// Drops the channel of a peer connection that is being replaced,
+    /// keeping that call's transcript to save.
+    pub(crate) fn reset(&mut self) {
+        self.end_call();
+        self.channel = NegotiatedChannel::default();
+    }
+}
+
+impl ChannelPanel for CaptionsState {
+    fn negotiated(&mut self) -> &mut NegotiatedChannel {
+        &mut self.channel
+    }
+
+    fn replaced(&mut self) {
+        self.reset();
+    }
+
+    fn opened(&mut self) {
+        self.started = Some(Instant::now());
+        self.ctx.request_repaint();
+    }
+
+    fn closed(&mut self) {
+        self.end_call();
+        self.ctx.request_repaint();
+    }
+}
+
+impl WebRTCApp {
+    /// Creates the captions channel on the current peer connection.
+    pub(crate) async fn open_captions_channel(&self) {
+        let captions = Arc::clone(&self.captions);
+        self.open_negotiated_channel(
+            &self.captions,
+            CAPTIONS_LABEL,
+            CAPTIONS_STREAM_ID,
+            move |message| {
+                match Caption::from_json(&message.into_text()?) {
+                    Ok(caption) => captions.lock().unwrap().receive(caption),
+                    Err(err) => info!("Ignoring malformed caption: {}", err),
+                }
+                None
+            },
+        )
+        .await;
+    }
+
+    async fn send_caption(&self, text: String) {
+        let caption = Caption {
+            speaker: self.room_name(),
+            text,
+        };
+        let channel = self.captions.lock().unwrap().channel.sender();
+        let sent = negotiated_channel::send(channel, caption.to_json()).await;
+        let mut state = self.captions.lock().unwrap();
+        match sent {
+            Ok(()) => {
+                state.status.clear();
+                state.push(caption);
+            }
+            Err(err) => state.status = format!("Caption not sent: {}", err),
+        }
+    }
+
+    /// Saves the last call's transcript: beside the recording made during
+    /// it, timed from when that started, or else in the recordings folder.
+    pub(crate) fn poll_captions(&self) {
+        let (ended, format) = {
+            let mut state = self.captions.lock().unwrap();
+            let Some(ended) = state.ended.take() else {
+                return;
+            };
+            (ended, state.format)
+        };
+        let recording = self
+            .remote_video
+            .lock()
+            .unwrap()
+            .last_recording()
+            .filter(|(_, at)| (ended.started..=ended.ended).contains(at));
+        let saved = match recording {
+            // Recordings start at their first keyframe, a moment after this.
+            Some((path, at)) => ended
+                .transcript
+                .since(at - ended.started)
+                .save_beside(&path, format),
+            None => {
+                let path = self.recordings_folder().join(captions::file_name(format));
+                std::fs::write(&path, ended.transcript.export(format)).map(|()| path)
+            }
+        };
+        let status = match saved {
+            Ok(path) => format!("Saved the captions to {}", path.display()),
+            Err(err) => format!("Could not save the captions: {}", err),
+        };
+        info!("{}", status);
+        self.captions.lock().unwrap().status = status;
+    }
+
+    pub(crate) fn captions_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.captions.lock().unwrap();
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut state.enabled, "Live captions")
+                .on_hover_text(
+                    "Shows the captions either side types, and saves them when the call ends",
+                );
+            egui::ComboBox::from_id_source("caption_format")
+                .selected_text(state.format.to_string())
+                .show_ui(ui, |ui| {
+                    for format in CaptionFormat::ALL {
+                        ui.selectable_value(&mut state.format, format, format.to_string());
+                    }
+                });
+        });
+        if !state.channel.is_open() {
+            ui.weak("No captions channel yet.");
+        }
+        if state.enabled {
+            let shown = state.transcript.cues();
+            for cue in &shown[shown.len().saturating_sub(SHOWN_CUES)..] {
+                match &cue.speaker {
+                    Some(speaker) => ui.label(format!("{}: {}", speaker, cue.text)),
+                    None => ui.label(&cue.text),
+                };
+            }
+            ui.horizontal(|ui| {
+                let edit = ui.text_edit_singleline(&mut state.draft);
+                let entered = edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                let open = state.channel.is_open();
+                let send = ui
+                    .add_enabled(
+                        open && !state.draft.trim().is_empty(),
+                        egui::Button::new("Caption"),
+                    )
+                    .on_hover_text("Sends the line to the peer as a caption")
+                    .clicked();
+                if (send || entered) && open && !state.draft.trim().is_empty() {
+                    let text = std::mem::take(&mut state.draft);
+                    edit.request_focus();
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.send_caption(text.trim().to_owned()).await;
+                        ctx.request_repaint();
+                    });
+                }
+            });
+        }
+        if !state.status.is_empty() {
+            ui.label(&state.status);
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! Text chat with the peer: over the "chat" data channel while it is open,
+//! and through the room's signaling server while it is not.
+
+use std::sync::Arc;
+
+use eframe::egui;
+use webrtc_core::chat::{ChatEntry, ChatLog, ChatMessage, CHAT_LABEL, CHAT_STREAM_ID};
+
+use crate::negotiated_channel::{self, ChannelPanel, NegotiatedChannel};
+use crate::WebRTCApp;
+
+pub struct ChatState {
+    log: ChatLog,
+    /// New messages either way, until the history keeps them.
+    unsaved: Vec<ChatEntry>,
+    draft: String,
+    status: String,
+    channel: NegotiatedChannel,
+    /// Chat arrives on background tasks; redraw when it does.
+    ctx: egui::Context,
+}
+
+impl ChatState {
+    pub fn new(ctx: &egui::Context) -> Self {
+        Self {
+            log: ChatLog::new(),
+            unsaved: Vec::new(),
+            draft: String::new(),
+            status: String::new(),
+            channel: NegotiatedChannel::default(),
+            ctx: ctx.clone(),
+        }
+    }
+
+    fn receive(&mut self, message: ChatMessage) {
+        if self.push(message, false) {
+            self.ctx.request_repaint();
+        }
+    }
+
+    fn push(&mut self, message: ChatMessage, local: bool) -> bool {
+        let entry = ChatEntry {
+            message: message.clone(),
+            local,
+        };
+        let new = self.log.push(message, local);
+        if new {
+            self.unsaved.push(entry);
+        }
+        new
+    }
+
+    pub(crate) fn take_unsaved(&mut self) -> Vec<ChatEntry> {
+        std::mem::take(&mut self.unsaved)
+    }
+
+    /// Shows `entries` from the history, in place of a conversation with
+    /// someone else when `fresh`.
+    pub(crate) fn recall(&mut self, entries: Vec<ChatEntry>, fresh: bool) {
+        if fresh {
+            self.log = ChatLog::new();
+        }
+        for entry in entries {
+            self.log.push(entry.message, entry.local);
+        }
+    }
+
+    /// Drops the channel of a peer connection that is being replaced; the
+    /// conversation so far stays.
+    pub(crate) fn reset(&mut self) {
+        self.channel = NegotiatedChannel::default();
+    }
+}
+
+impl ChannelPanel for ChatState {
+    fn negotiated(&mut self) -> &mut NegotiatedChannel {
+        &mut self.channel
+    }
+
+    fn replaced(&mut self) {
+        self.reset();
+    }
+
+    fn opened(&mut self) {
+        self.ctx.request_repaint();
+    }
+
+    fn closed(&mut self) {
+        self.ctx.request_repaint();
+    }
+}
+
+impl WebRTCApp {
+    /// Creates the chat channel on the current peer connection.
+    pub(crate) async fn open_chat_channel(&self) {
+        // Holding the app here would keep the peer connection alive.
+        let chat = Arc::clone(&self.chat);
+        self.open_negotiated_channel(&self.chat, CHAT_LABEL, CHAT_STREAM_ID, move |message| {
+            if let Some(text) = message.into_text() {
+                chat.lock().unwrap().receive(ChatMessage::from_text(&text));
+            }
+            None
+        })
+        .await;
+    }
+
+    /// Sends `message` on the chat channel, if it is open.
+    async fn send_chat_message(&self, message: &ChatMessage) -> bool {
+        let channel = self.chat.lock().unwrap().channel.sender();
+        negotiated_channel::send(channel, message.to_json())
+            .await
+            .is_ok()
+    }
+
+    async fn send_chat(&self, text: String) {
+        let message = ChatMessage::new(&self.room_name(), &text);
+        let sent = if self.send_chat_message(&message).await {
+            Some("")
+        } else if self.send_room_chat(message.clone()) {
+            Some("The chat channel is down, so this went through the signaling server")
+        } else {
+            None
+        };
+        let mut state = self.chat.lock().unwrap();
+        match sent {
+            Some(status) => {
+                state.push(message, true);
+                state.status = status.to_owned();
+            }
+            None => {
+                // Keep what was typed so it can be sent once connected.
+                if state.draft.is_empty() {
+                    state.draft = text;
+                }
+                state.status = "Not sent: no chat channel and no room to relay it".to_owned();
+            }
+        }
+    }
+
+    /// Logs chat from the peer. The same message can come over the data
+    /// channel and from the server's history; it is shown once.
+    pub(crate) fn receive_chat(&self, message: ChatMessage) {
+        self.chat.lock().unwrap().receive(message);
+    }
+
+    pub(crate) fn chat_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.chat.lock().unwrap();
+        if !state.channel.is_open() {
+            ui.weak("No chat channel yet; messages go through the room, when there is one.");
+        }
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for entry in state.log.entries() {
+                    let at = std::time::UNIX_EPOCH
+                        + std::time::Duration::from_millis(entry.message.sent_at);
+                    let name = if entry.local {
+                        "you"
+                    } else {
+                        entry.message.name.as_str()
+                    };
+                    ui.label(format!(
+                        "{} {}: {}",
+                        humantime::format_rfc3339_seconds(at),
+                        name,
+                        entry.message.text
+                    ));
+                }
+            });
+        ui.horizontal(|ui| {
+            let edit = ui.text_edit_singleline(&mut state.draft);
+            let entered = edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            let send = ui
+                .add_enabled(!state.draft.trim().is_empty(), egui::Button::new("Send"))
+                .clicked();
+            if (send || entered) && !state.draft.trim().is_empty() {
+                let text = std::mem::take(&mut state.draft);
+                edit.request_focus();
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.send_chat(text.trim().to_owned()).await;
+                    ctx.request_repaint();
+                });
+            }
+        });
+        if !state.status.is_empty() {
+            ui.label(&state.status);
+        }
+    }
+}
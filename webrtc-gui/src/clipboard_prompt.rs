@@ -0,0 +1,120 @@
+//! Offers to paste a session description or ICE candidate found on the
+//! clipboard when the window regains focus.
+
+use std::io::Cursor;
+
+use eframe::egui;
+use log::info;
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::sdp::SessionDescription;
+
+use crate::WebRTCApp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PasteKind {
+    SessionDescription,
+    Candidate,
+}
+
+#[derive(Default)]
+pub struct ClipboardPrompt {
+    was_focused: bool,
+    detected: Option<(PasteKind, String)>,
+    /// Clipboard text the user already pasted or dismissed, so the prompt
+    /// does not come back every time focus returns.
+    handled: Option<String>,
+}
+
+/// Classifies clipboard text as an SDP blob (raw or wrapped in
+/// `{"type": .., "sdp": ..}` JSON) or a trickled candidate JSON object.
+fn detect(text: &str) -> Option<(PasteKind, String)> {
+    let text = text.trim();
+    if let Ok(description) = serde_json::from_str::<RTCSessionDescription>(text) {
+        if is_sdp(&description.sdp) {
+            return Some((PasteKind::SessionDescription, description.sdp));
+        }
+    }
+    if let Ok(candidate) = serde_json::from_str::<RTCIceCandidateInit>(text) {
+        if candidate.candidate.starts_with("candidate:") {
+            return Some((PasteKind::Candidate, text.to_owned()));
+        }
+    }
+    if is_sdp(text) {
+        return Some((PasteKind::SessionDescription, format!("{}\r\n", text)));
+    }
+    None
+}
+
+fn is_sdp(text: &str) -> bool {
+    text.starts_with("v=0")
+        && SessionDescription::unmarshal(&mut Cursor::new(text.trim_end().to_owned() + "\r\n"))
+            .is_ok()
+}
+
+impl WebRTCApp {
+    /// Checks the clipboard on the frame where the window gains focus.
+    pub(crate) fn poll_clipboard(&self, ctx: &egui::Context) {
+        let focused = ctx.input(|i| i.viewport().focused.unwrap_or(false));
+        let mut prompt = self.clipboard_prompt.lock().unwrap();
+        let gained_focus = focused && !prompt.was_focused;
+        prompt.was_focused = focused;
+        if !gained_focus {
+            return;
+        }
+
+        let text = match arboard::Clipboard::new().and_then(|mut c| c.get_text()) {
+            Ok(text) => text,
+            Err(err) => {
+                info!("Could not read clipboard: {:?}", err);
+                return;
+            }
+        };
+        if prompt.handled.as_deref() == Some(text.as_str()) {
+            return;
+        }
+        prompt.detected = detect(&text).filter(|(_, value)| {
+            value.trim() != self.remote_sdp.get().trim()
+                && value.trim() != self.local_sdp.get().trim()
+        });
+        if prompt.detected.is_some() {
+            prompt.handled = Some(text);
+        }
+    }
+
+    pub(crate) fn clipboard_prompt_ui(&self, ui: &mut egui::Ui) {
+        let mut prompt = self.clipboard_prompt.lock().unwrap();
+        let Some((kind, value)) = &prompt.detected else {
+            return;
+        };
+        let mut close = false;
+        ui.horizontal(|ui| {
+            ui.label(match kind {
+                PasteKind::SessionDescription => "Paste detected session description?",
+                PasteKind::Candidate => "Paste detected ICE candidate?",
+            });
+            if ui.small_button("Paste").clicked() {
+                match kind {
+                    PasteKind::SessionDescription => self.remote_sdp.set(value.clone()),
+                    PasteKind::Candidate => {
+                        if let Ok(candidate) = serde_json::from_str(value) {
+                            let app = self.clone();
+                            let ctx = ui.ctx().clone();
+                            tokio::spawn(async move {
+                                app.add_remote_candidate(candidate).await;
+                                ctx.request_repaint();
+                            });
+                        }
+                    }
+                }
+                close = true;
+            }
+            if ui.small_button("Dismiss").clicked() {
+                close = true;
+            }
+        });
+        if close {
+            prompt.detected = None;
+        }
+    }
+}
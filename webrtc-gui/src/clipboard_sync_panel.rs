@@ -0,0 +1,250 @@
+//! Keeping the clipboard the same as the peer's, over the "clipboard"
+//! data channel. It is off until turned on, every time the app starts,
+//! and received text is only set while it is on here too.
+
+use std::sync::mpsc as std_mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use eframe::egui;
+use log::info;
+use tokio::sync::mpsc;
+use webrtc_core::clipboard::{ClipboardSync, Copied, CLIPBOARD_LABEL, CLIPBOARD_STREAM_ID};
+use webrtc_core::file_transfer::size_label;
+
+use crate::negotiated_channel::{self, ChannelPanel, NegotiatedChannel};
+use crate::WebRTCApp;
+
+/// How often the clipboard is checked for something newly copied.
+const POLL: Duration = Duration::from_millis(500);
+
+/// Characters of each copy the log shows.
+const PREVIEW_CHARS: usize = 80;
+
+pub struct ClipboardSyncState {
+    sync: ClipboardSync,
+    /// Text from the peer for the clipboard thread to set. The thread runs
+    /// while this is here, so dropping it turns syncing off.
+    to_set: Option<std_mpsc::Sender<String>>,
+    channel: NegotiatedChannel,
+    status: String,
+    /// Copies arrive on background threads; redraw when they do.
+    ctx: egui::Context,
+}
+
+impl ClipboardSyncState {
+    pub fn new(ctx: &egui::Context) -> Self {
+        Self {
+            sync: ClipboardSync::default(),
+            to_set: None,
+            channel: NegotiatedChannel::default(),
+            status: String::new(),
+            ctx: ctx.clone(),
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.to_set.is_some()
+    }
+
+    fn receive(&mut self, text: String) {
+        if !self.enabled() {
+            self.status = "The peer copied something; syncing is off here".to_owned();
+        } else if self.sync.received(text.clone()) {
+            if let Some(to_set) = &self.to_set {
+                let _ = to_set.send(text);
+            }
+        }
+        self.ctx.request_repaint();
+    }
+
+    /// Drops the channel of a peer connection that is being replaced; the
+    /// log, and whether syncing is on, stay.
+    pub(crate) fn reset(&mut self) {
+        self.channel = NegotiatedChannel::default();
+    }
+}
+
+impl ChannelPanel for ClipboardSyncState {
+    fn negotiated(&mut self) -> &mut NegotiatedChannel {
+        &mut self.channel
+    }
+
+    fn replaced(&mut self) {
+        self.reset();
+    }
+
+    fn opened(&mut self) {
+        self.ctx.request_repaint();
+    }
+
+    fn closed(&mut self) {
+        self.ctx.request_repaint();
+    }
+}
+
+/// Owns the clipboard while syncing is on, since arboard only keeps
+/// serving what it set while it lives. Sets what the peer copied and
+/// queues what is copied here into `copies`, until `to_set` closes.
+fn watch_clipboard(
+    state: Arc<Mutex<ClipboardSyncState>>,
+    to_set: std_mpsc::Receiver<String>,
+    copies: mpsc::UnboundedSender<String>,
+) {
+    let mut clipboard = match arboard::Clipboard::new() {
+        Ok(clipboard) => clipboard,
+        Err(err) => {
+            let mut state = state.lock().unwrap();
+            state.to_set = None;
+            state.status = format!("Could not open the clipboard: {}", err);
+            return;
+        }
+    };
+    state.lock().unwrap().sync.start(clipboard.get_text().ok());
+    loop {
+        match to_set.recv_timeout(POLL) {
+            Ok(text) => {
+                if let Err(err) = clipboard.set_text(text) {
+                    info!("Could not set clipboard: {:?}", err);
+                }
+                continue;
+            }
+            Err(std_mpsc::RecvTimeoutError::Timeout) => {}
+            Err(std_mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+        // Not text, or nothing copied at all.
+        let Ok(text) = clipboard.get_text() else {
+            continue;
+        };
+        let mut state = state.lock().unwrap();
+        match state.sync.copied(text) {
+            Copied::Unchanged => {}
+            Copied::Send(text) => {
+                let _ = copies.send(text);
+            }
+            Copied::TooLarge(size) => {
+                state.status = format!(
+                    "Not sent: {} is more than the peer takes",
+                    size_label(size as u64)
+                );
+                state.ctx.request_repaint();
+            }
+        }
+    }
+}
+
+/// Sends each copy on the clipboard channel, until the clipboard thread
+/// ends.
+async fn send_copies(
+    state: Arc<Mutex<ClipboardSyncState>>,
+    mut copies: mpsc::UnboundedReceiver<String>,
+) {
+    while let Some(text) = copies.recv().await {
+        let channel = state.lock().unwrap().channel.sender();
+        let sent = negotiated_channel::send(channel, text.clone()).await;
+        let mut state = state.lock().unwrap();
+        match sent {
+            Ok(_) => {
+                state.sync.sent(text);
+                state.status.clear();
+            }
+            Err(err) => state.status = format!("Copy not sent: {}", err),
+        }
+        state.ctx.request_repaint();
+    }
+}
+
+/// The first line of `text`, cut short.
+fn preview(text: &str) -> String {
+    let line = text.lines().next().unwrap_or_default();
+    let mut preview: String = line.chars().take(PREVIEW_CHARS).collect();
+    if preview.len() < text.len() {
+        preview.push('…');
+    }
+    preview
+}
+
+impl WebRTCApp {
+    /// Creates the clipboard channel on the current peer connection.
+    pub(crate) async fn open_clipboard_channel(&self) {
+        let clipboard_sync = Arc::clone(&self.clipboard_sync);
+        self.open_negotiated_channel(
+            &self.clipboard_sync,
+            CLIPBOARD_LABEL,
+            CLIPBOARD_STREAM_ID,
+            move |message| {
+                if let Some(text) = message.into_text() {
+                    clipboard_sync.lock().unwrap().receive(text);
+                }
+                None
+            },
+        )
+        .await;
+    }
+
+    fn start_clipboard_sync(&self) {
+        let (to_set_tx, to_set) = std_mpsc::channel();
+        let (copies_tx, copies) = mpsc::unbounded_channel();
+        {
+            // Before the thread starts, so it can take this away again
+            // when it cannot open the clipboard.
+            let mut state = self.clipboard_sync.lock().unwrap();
+            state.to_set = Some(to_set_tx);
+            state.status.clear();
+        }
+        let state = Arc::clone(&self.clipboard_sync);
+        let spawned = std::thread::Builder::new()
+            .name("clipboard".to_owned())
+            .spawn(move || watch_clipboard(state, to_set, copies_tx));
+        match spawned {
+            Ok(_) => {
+                tokio::spawn(send_copies(Arc::clone(&self.clipboard_sync), copies));
+            }
+            Err(err) => {
+                let mut state = self.clipboard_sync.lock().unwrap();
+                state.to_set = None;
+                state.status = format!("Could not watch the clipboard: {}", err);
+            }
+        }
+    }
+
+    pub(crate) fn clipboard_sync_ui(&self, ui: &mut egui::Ui) {
+        let enabled = self.clipboard_sync.lock().unwrap().enabled();
+        ui.horizontal(|ui| {
+            if !enabled && ui.button("Sync Clipboard").clicked() {
+                self.start_clipboard_sync();
+            }
+            if enabled && ui.button("Stop Syncing").clicked() {
+                // The clipboard thread ends once it sees this go.
+                self.clipboard_sync.lock().unwrap().to_set = None;
+            }
+        });
+        let state = self.clipboard_sync.lock().unwrap();
+        if state.enabled() {
+            ui.label(
+                "Text copied here goes to the peer, and text the peer copies \
+                 replaces what is copied here, while both have syncing on.",
+            );
+        }
+        if !state.channel.is_open() {
+            ui.weak("No clipboard channel yet.");
+        }
+        if !state.status.is_empty() {
+            ui.label(&state.status);
+        }
+        egui::ScrollArea::vertical()
+            .id_source("clipboard_log")
+            .max_height(150.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for entry in state.sync.log() {
+                    ui.label(format!(
+                        "{} {} {}",
+                        humantime::format_rfc3339_seconds(entry.at),
+                        if entry.outgoing { "→" } else { "←" },
+                        preview(&entry.text)
+                    ));
+                }
+            });
+    }
+}
@@ -0,0 +1,204 @@
+//! The address book, with vCard import/export. Picking an identity fills it
+//! into the panel of the backend that can call it.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use eframe::egui;
+use webrtc_core::contacts::{self, Contacts, Identity, IdentityKind};
+use webrtc_core::renegotiation::RenegotiationPolicy;
+use webrtc_core::settings::FileKind;
+
+use crate::WebRTCApp;
+
+pub struct ContactsState {
+    pub(crate) book: Contacts,
+    status: String,
+}
+
+impl ContactsState {
+    pub(crate) fn new(book: Contacts) -> Self {
+        Self {
+            book,
+            status: String::new(),
+        }
+    }
+
+    fn import(&mut self, path: &Path) {
+        let parsed = std::fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| contacts::parse_vcards(&text).map_err(|err| err.to_string()));
+        self.status = match parsed {
+            Ok(imported) => {
+                let read = imported.len();
+                let changed = self.book.merge(imported);
+                format!("Read {} cards, {} new or updated contacts", read, changed)
+            }
+            Err(err) => format!("Failed to import {}: {}", path.display(), err),
+        };
+    }
+
+    fn export(&mut self, path: &Path) {
+        self.status = match std::fs::write(path, contacts::to_vcards(&self.book.contacts)) {
+            Ok(()) => format!(
+                "Exported {} contacts to {}",
+                self.book.contacts.len(),
+                path.display()
+            ),
+            Err(err) => format!("Failed to export {}: {}", path.display(), err),
+        };
+    }
+}
+
+impl WebRTCApp {
+    /// Puts `identity` into the panel that can call it; returns which one.
+    fn use_identity(&self, identity: &Identity) -> &'static str {
+        match identity.kind {
+            IdentityKind::Nostr => {
+                self.nostr.lock().unwrap().peer = identity.value.clone();
+                "Nostr"
+            }
+            IdentityKind::Libp2p => {
+                self.p2p.lock().unwrap().peer = identity.value.clone();
+                "libp2p"
+            }
+            IdentityKind::Sip | IdentityKind::Email => {
+                self.sip.lock().unwrap().dial_target = identity.sip_uri().unwrap_or_default();
+                "SIP Gateway"
+            }
+        }
+    }
+
+    async fn import_contacts(&self) {
+        if let Some(path) = self
+            .open_file_dialog(FileKind::Contacts, "Import contacts")
+            .await
+        {
+            self.contacts.lock().unwrap().import(&path);
+        }
+    }
+
+    async fn export_contacts(&self) {
+        if let Some(path) = self
+            .save_file_dialog(FileKind::Contacts, "Export contacts", "contacts.vcf")
+            .await
+        {
+            self.contacts.lock().unwrap().export(&path);
+        }
+    }
+
+    pub(crate) fn contacts_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.contacts.lock().unwrap();
+        ui.horizontal(|ui| {
+            if ui.button("Import vCard...").clicked() {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.import_contacts().await;
+                    ctx.request_repaint();
+                });
+            }
+            if ui
+                .add_enabled(
+                    !state.book.contacts.is_empty(),
+                    egui::Button::new("Export vCard..."),
+                )
+                .clicked()
+            {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.export_contacts().await;
+                    ctx.request_repaint();
+                });
+            }
+            ui.label(&state.status);
+        });
+
+        if state.book.contacts.is_empty() {
+            ui.label("No contacts yet.");
+            return;
+        }
+        let mut picked = None;
+        let mut removed = None;
+        let mut overrides = self
+            .settings
+            .lock()
+            .unwrap()
+            .privacy
+            .renegotiation
+            .overrides
+            .clone();
+        let overridden = overrides.clone();
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .show(ui, |ui| {
+                egui::Grid::new("contacts").num_columns(4).show(ui, |ui| {
+                    for (index, contact) in state.book.contacts.iter().enumerate() {
+                        ui.label(&contact.name);
+                        ui.horizontal_wrapped(|ui| {
+                            for identity in &contact.identities {
+                                if ui
+                                    .small_button(format!("{}: {}", identity.kind, identity.value))
+                                    .on_hover_text("Fill in to call")
+                                    .clicked()
+                                {
+                                    picked = Some(identity.clone());
+                                }
+                            }
+                        });
+                        renegotiation_override(ui, &contact.name, &mut overrides);
+                        if ui.small_button("Remove").clicked() {
+                            removed = Some(index);
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        if overrides != overridden {
+            self.settings
+                .lock()
+                .unwrap()
+                .privacy
+                .renegotiation
+                .overrides = overrides;
+        }
+        if let Some(index) = removed {
+            let contact = state.book.contacts.remove(index);
+            state.status = format!("Removed {}", contact.name);
+        }
+        if let Some(identity) = picked {
+            // The other panels have their own locks.
+            drop(state);
+            let panel = self.use_identity(&identity);
+            self.contacts.lock().unwrap().status = format!("Filled in the {} panel", panel);
+        }
+    }
+}
+
+/// Picks how mid-call offers from `contact` are handled, or the default.
+fn renegotiation_override(
+    ui: &mut egui::Ui,
+    contact: &str,
+    overrides: &mut BTreeMap<String, RenegotiationPolicy>,
+) {
+    let mut policy = overrides.get(contact).copied();
+    let text = |policy: Option<RenegotiationPolicy>| match policy {
+        None => "Default".to_owned(),
+        Some(policy) => policy.to_string(),
+    };
+    egui::ComboBox::from_id_source(("renegotiation_override", contact))
+        .selected_text(text(policy))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut policy, None, text(None));
+            for option in RenegotiationPolicy::ALL {
+                ui.selectable_value(&mut policy, Some(option), text(Some(option)));
+            }
+        })
+        .response
+        .on_hover_text("Mid-call changes from this contact");
+    match policy {
+        Some(policy) => overrides.insert(contact.to_owned(), policy),
+        None => overrides.remove(contact),
+    };
+}
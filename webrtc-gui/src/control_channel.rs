@@ -0,0 +1,475 @@
+//! In-band renegotiation, heartbeats and pings over the reserved control
+//! data channel.
+
+use std::sync::{Arc, Weak};
+use std::time::Instant;
+
+use eframe::egui;
+use log::info;
+use tokio::sync::mpsc;
+use webrtc::data_channel::data_channel_init::RTCDataChannelInit;
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::data_channel::RTCDataChannel;
+use webrtc::peer_connection::sdp::sdp_type::RTCSdpType;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::signaling_state::RTCSignalingState;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+use webrtc::rtp_transceiver::rtp_transceiver_direction::RTCRtpTransceiverDirection;
+use webrtc::rtp_transceiver::RTCRtpTransceiverInit;
+use webrtc_core::control::{
+    ControlMessage, CONTROL_LABEL, CONTROL_STREAM_ID, HEARTBEAT_INTERVAL, MAX_MISSED_HEARTBEATS,
+};
+use webrtc_core::framing::{self, Reassembler};
+use webrtc_core::latency::LatencyProbe;
+
+use crate::WebRTCApp;
+
+#[derive(Default)]
+pub struct ControlState {
+    pub(crate) channel: Option<Arc<RTCDataChannel>>,
+    pub(crate) open: bool,
+    /// Our own renegotiation lost a glare race and has to be retried once
+    /// the remote offer has been answered.
+    retry_offer: bool,
+    /// When the peer's last heartbeat arrived. Peers that never send any
+    /// are not watched.
+    last_heartbeat: Option<Instant>,
+    /// The peer stopped sending heartbeats and the call was ended.
+    peer_dead: bool,
+    /// The peer said it muted its microphone.
+    peer_muted: bool,
+    /// The peer said it stopped its video.
+    peer_camera_paused: bool,
+    /// Our pings and how long the peer took to answer them.
+    pub(crate) latency: LatencyProbe,
+    status: String,
+}
+
+enum ControlEvent {
+    Opened,
+    Message(ControlMessage),
+    NegotiationNeeded,
+}
+
+impl WebRTCApp {
+    /// Creates the control channel, and the panels' own channels with it,
+    /// on the current peer connection. Must be called on both sides before
+    /// the first offer/answer exchange.
+    pub(crate) async fn open_control_channel(&self) {
+        let pc = self.peer_connection.lock().await.clone();
+        let Some(pc) = pc else {
+            return;
+        };
+        let init = RTCDataChannelInit {
+            ordered: Some(true),
+            negotiated: Some(CONTROL_STREAM_ID),
+            ..Default::default()
+        };
+        let channel = match pc.create_data_channel(CONTROL_LABEL, Some(init)).await {
+            Ok(channel) => channel,
+            Err(err) => {
+                info!("Failed to create control channel: {:?}", err);
+                return;
+            }
+        };
+
+        // Callbacks only forward events; the task below owns the app handle
+        // and ends once the peer connection (and with it the channel) drops.
+        let (events_tx, mut events) = mpsc::unbounded_channel();
+        let control = Arc::clone(&self.control);
+        let opened_tx = events_tx.clone();
+        channel.on_open(Box::new(move || {
+            let mut state = control.lock().unwrap();
+            state.open = true;
+            state.status = "Control channel open".to_owned();
+            let _ = opened_tx.send(ControlEvent::Opened);
+            Box::pin(async {})
+        }));
+        let control = Arc::clone(&self.control);
+        channel.on_close(Box::new(move || {
+            let mut state = control.lock().unwrap();
+            state.open = false;
+            if !state.peer_dead {
+                state.status = "Control channel closed".to_owned();
+            }
+            Box::pin(async {})
+        }));
+        let message_tx = events_tx.clone();
+        let control = Arc::clone(&self.control);
+        let mut reassembler = Reassembler::default();
+        channel.on_message(Box::new(move |message: DataChannelMessage| {
+            let text = match reassembler.receive(&message.data) {
+                Ok(Some(text)) => text,
+                Ok(None) => return Box::pin(async {}),
+                Err(err) => {
+                    info!("Ignoring malformed control message: {}", err);
+                    return Box::pin(async {});
+                }
+            };
+            match ControlMessage::from_json(&text) {
+                Ok(ControlMessage::Heartbeat { .. }) => {
+                    control.lock().unwrap().last_heartbeat = Some(Instant::now());
+                }
+                // Timed here rather than after the event loop below, which
+                // may be busy renegotiating.
+                Ok(ControlMessage::Pong { seq }) => {
+                    control.lock().unwrap().latency.pong(seq, Instant::now());
+                }
+                Ok(ControlMessage::Ping { seq }) => {
+                    let channel = control.lock().unwrap().channel.clone();
+                    return Box::pin(async move {
+                        let Some(channel) = channel else {
+                            return;
+                        };
+                        let pong = ControlMessage::Pong { seq }.to_json();
+                        if let Err(err) = framing::send_text(&channel, pong).await {
+                            info!("Failed to answer ping: {:?}", err);
+                        }
+                    });
+                }
+                Ok(message) => {
+                    let _ = message_tx.send(ControlEvent::Message(message));
+                }
+                Err(err) => info!("Ignoring malformed control message: {}", err),
+            }
+            Box::pin(async {})
+        }));
+        pc.on_negotiation_needed(Box::new(move || {
+            let _ = events_tx.send(ControlEvent::NegotiationNeeded);
+            Box::pin(async {})
+        }));
+
+        *self.control.lock().unwrap() = ControlState {
+            channel: Some(Arc::clone(&channel)),
+            ..Default::default()
+        };
+        self.spawn_heartbeat(Arc::downgrade(&pc), Arc::clone(&channel));
+        self.spawn_pings(Arc::downgrade(&pc), channel);
+
+        let app = self.clone();
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                match event {
+                    ControlEvent::Opened => {
+                        app.announce_microphone_mute().await;
+                        app.announce_camera_paused().await;
+                    }
+                    ControlEvent::Message(message) => app.handle_control_message(message).await,
+                    ControlEvent::NegotiationNeeded => app.renegotiate_in_band().await,
+                }
+            }
+        });
+
+        self.open_log_channel().await;
+        self.open_chat_channel().await;
+        self.open_transfer_channel().await;
+        self.open_clipboard_channel().await;
+        self.open_input_channel().await;
+        self.open_rpc_channel().await;
+        self.open_captions_channel().await;
+    }
+
+    /// Sends heartbeats while `channel` is open, and ends the call once the
+    /// peer's have stopped. Runs until the peer connection or channel is
+    /// replaced.
+    fn spawn_heartbeat(&self, pc: Weak<RTCPeerConnection>, channel: Arc<RTCDataChannel>) {
+        let app = self.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(HEARTBEAT_INTERVAL);
+            let mut seq = 0;
+            loop {
+                ticks.tick().await;
+                if pc.upgrade().is_none() {
+                    return;
+                }
+                let last_heartbeat = {
+                    let state = app.control.lock().unwrap();
+                    let current = state
+                        .channel
+                        .as_ref()
+                        .is_some_and(|current| Arc::ptr_eq(current, &channel));
+                    if !current {
+                        return;
+                    }
+                    if !state.open {
+                        continue;
+                    }
+                    state.last_heartbeat
+                };
+                seq += 1;
+                if !app.send_control(ControlMessage::Heartbeat { seq }).await {
+                    continue;
+                }
+                let silence = last_heartbeat.map(|at| at.elapsed());
+                if silence
+                    .is_some_and(|silence| silence >= HEARTBEAT_INTERVAL * MAX_MISSED_HEARTBEATS)
+                {
+                    app.peer_dead(silence.unwrap_or_default()).await;
+                    return;
+                }
+            }
+        });
+    }
+
+    /// The data channel is still up but the remote app has gone quiet.
+    async fn peer_dead(&self, silence: std::time::Duration) {
+        info!(
+            "No heartbeat from the peer for {:.0?}, ending the call",
+            silence
+        );
+        {
+            let mut state = self.control.lock().unwrap();
+            state.peer_dead = true;
+            state.status = format!(
+                "The peer stopped responding (no heartbeat for {} s); the call was ended",
+                silence.as_secs()
+            );
+        }
+        self.close_peer_connection().await;
+    }
+
+    pub(crate) async fn send_control(&self, message: ControlMessage) -> bool {
+        let channel = {
+            let state = self.control.lock().unwrap();
+            state.channel.clone().filter(|_| state.open)
+        };
+        let Some(channel) = channel else {
+            return false;
+        };
+        match framing::send_text(&channel, message.to_json()).await {
+            Ok(()) => true,
+            Err(err) => {
+                info!("Failed to send control message: {:?}", err);
+                false
+            }
+        }
+    }
+
+    fn set_control_status(&self, status: impl Into<String>) {
+        self.control.lock().unwrap().status = status.into();
+    }
+
+    /// Shows a failure and puts it on the event timeline.
+    fn control_failed(&self, status: String) {
+        self.mark_error(status.clone());
+        self.set_control_status(status);
+    }
+
+    /// Sends a new offer over the control channel. Before the channel is
+    /// open the initial out-of-band negotiation is still in charge.
+    async fn renegotiate_in_band(&self) {
+        if !self.control.lock().unwrap().open {
+            return;
+        }
+        let pc = self.peer_connection.lock().await.clone();
+        let Some(pc) = pc else {
+            return;
+        };
+        // An offer waiting for the user has to be settled first.
+        if pc.signaling_state() != RTCSignalingState::Stable
+            || self.renegotiation.lock().unwrap().is_pending()
+        {
+            self.control.lock().unwrap().retry_offer = true;
+            return;
+        }
+        let result = async {
+            let offer = pc.create_offer(None).await?;
+            pc.set_local_description(offer).await?;
+            pc.local_description()
+                .await
+                .ok_or(webrtc::Error::ErrConnectionClosed)
+        }
+        .await;
+        match result {
+            Ok(offer) => {
+                self.local_sdp.set(offer.sdp.clone());
+                if self
+                    .send_control(ControlMessage::Offer { sdp: offer.sdp })
+                    .await
+                {
+                    self.set_control_status("Renegotiating over control channel...");
+                }
+            }
+            Err(err) => self.control_failed(format!("Renegotiation failed: {}", err)),
+        }
+    }
+
+    async fn handle_control_message(&self, message: ControlMessage) {
+        let pc = self.peer_connection.lock().await.clone();
+        let Some(pc) = pc else {
+            return;
+        };
+        match message {
+            ControlMessage::Offer { sdp } => {
+                if pc.signaling_state() == RTCSignalingState::HaveLocalOffer {
+                    // Glare: the peer that answered the initial offer yields,
+                    // rolls back its own offer and retries after answering.
+                    let polite = pc
+                        .current_local_description()
+                        .await
+                        .is_some_and(|description| description.sdp_type == RTCSdpType::Answer);
+                    if !polite {
+                        info!("Ignoring colliding in-band offer");
+                        return;
+                    }
+                    if !self.roll_back_local_offer(&pc).await {
+                        return;
+                    }
+                    self.control.lock().unwrap().retry_offer = true;
+                }
+                let current = pc
+                    .current_remote_description()
+                    .await
+                    .map(|description| description.sdp)
+                    .unwrap_or_default();
+                if self.hold_for_consent(&pc, &current, &sdp) {
+                    self.set_control_status("The peer wants to change the call");
+                    return;
+                }
+                self.answer_in_band(&pc, sdp).await;
+            }
+            ControlMessage::Answer { sdp } => {
+                self.remote_sdp.set(sdp.clone());
+                let result = match RTCSessionDescription::answer(sdp) {
+                    Ok(answer) => pc.set_remote_description(answer).await,
+                    Err(err) => Err(err),
+                };
+                match result {
+                    Ok(()) => self.set_control_status("Renegotiated over control channel"),
+                    Err(err) => {
+                        self.control_failed(format!("Failed to apply in-band answer: {}", err))
+                    }
+                }
+            }
+            ControlMessage::Declined => {
+                if pc.signaling_state() == RTCSignalingState::HaveLocalOffer
+                    && self.roll_back_local_offer(&pc).await
+                {
+                    self.set_control_status("The peer declined the changes");
+                }
+            }
+            ControlMessage::MicrophoneMuted { muted } => {
+                self.control.lock().unwrap().peer_muted = muted;
+            }
+            ControlMessage::CameraPaused { paused } => {
+                self.control.lock().unwrap().peer_camera_paused = paused;
+            }
+            // Taken care of as they arrive.
+            ControlMessage::Heartbeat { .. }
+            | ControlMessage::Ping { .. }
+            | ControlMessage::Pong { .. } => {}
+        }
+        self.retry_in_band_offer().await;
+    }
+
+    pub(crate) async fn answer_in_band(&self, pc: &RTCPeerConnection, sdp: String) {
+        self.remote_sdp.set(sdp.clone());
+        let result = async {
+            pc.set_remote_description(RTCSessionDescription::offer(sdp)?)
+                .await?;
+            self.follow_remote_video_codec(pc).await;
+            let answer = pc.create_answer(None).await?;
+            pc.set_local_description(answer).await?;
+            pc.local_description()
+                .await
+                .ok_or(webrtc::Error::ErrConnectionClosed)
+        }
+        .await;
+        match result {
+            Ok(answer) => {
+                self.local_sdp.set(answer.sdp.clone());
+                self.send_control(ControlMessage::Answer { sdp: answer.sdp })
+                    .await;
+                self.set_control_status("Renegotiated over control channel");
+            }
+            Err(err) => self.control_failed(format!("Failed to answer in-band offer: {}", err)),
+        }
+    }
+
+    pub(crate) async fn decline_in_band(&self) {
+        if self.send_control(ControlMessage::Declined).await {
+            self.set_control_status("Declined the peer's changes");
+        }
+    }
+
+    /// Makes the offer that had to wait for the peer's to be settled.
+    pub(crate) async fn retry_in_band_offer(&self) {
+        if std::mem::take(&mut self.control.lock().unwrap().retry_offer) {
+            self.renegotiate_in_band().await;
+        }
+    }
+
+    async fn roll_back_local_offer(&self, pc: &RTCPeerConnection) -> bool {
+        let mut rollback = pc.local_description().await.unwrap_or_default();
+        rollback.sdp_type = RTCSdpType::Rollback;
+        match pc.set_local_description(rollback).await {
+            Ok(()) => true,
+            Err(err) => {
+                self.control_failed(format!("Rollback failed: {}", err));
+                false
+            }
+        }
+    }
+
+    async fn add_receive_transceiver(&self, kind: RTPCodecType) {
+        let pc = self.peer_connection.lock().await.clone();
+        let Some(pc) = pc else {
+            return;
+        };
+        let init = RTCRtpTransceiverInit {
+            direction: RTCRtpTransceiverDirection::Recvonly,
+            send_encodings: vec![],
+        };
+        // Negotiation needed fires from here and drives the in-band offer.
+        if let Err(err) = pc.add_transceiver_from_kind(kind, Some(init)).await {
+            self.control_failed(format!("Failed to add {} transceiver: {}", kind, err));
+        }
+    }
+
+    /// Whether the peer said it muted its microphone on this connection.
+    pub(crate) fn peer_muted_microphone(&self) -> bool {
+        self.control.lock().unwrap().peer_muted
+    }
+
+    /// Whether the peer said it stopped its video on this connection.
+    pub(crate) fn peer_camera_paused(&self) -> bool {
+        self.control.lock().unwrap().peer_camera_paused
+    }
+
+    pub(crate) fn control_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let state = self.control.lock().unwrap();
+        if state.channel.is_none() {
+            return;
+        }
+        // The channel opens, and heartbeats lapse, in the background.
+        ctx.request_repaint_after(std::time::Duration::from_secs(1));
+        if state.peer_dead {
+            ui.colored_label(egui::Color32::RED, &state.status);
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.label(if state.status.is_empty() {
+                "Control channel waiting for connection"
+            } else {
+                &state.status
+            });
+            for (label, kind) in [
+                ("Add audio", RTPCodecType::Audio),
+                ("Add video", RTPCodecType::Video),
+            ] {
+                if ui
+                    .add_enabled(state.open, egui::Button::new(label))
+                    .clicked()
+                {
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.add_receive_transceiver(kind).await;
+                        ctx.request_repaint();
+                    });
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,779 @@
+//! Data channels opened by hand, here or by the peer, listed with their
+//! state and traffic. The one picked is shown in full, binary messages as
+//! hexdumps and typed messages laid out, and sent on as text, a typed
+//! message, hex or a snippet of a file. Pinging a channel times a round
+//! trip to the app at the other end; realtime channels instead show how
+//! late their messages arrive.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime};
+
+use eframe::egui;
+use egui_plot::{Bar, BarChart, Plot};
+use log::info;
+use webrtc::data_channel::data_channel_init::RTCDataChannelInit;
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::data_channel::data_channel_state::RTCDataChannelState;
+use webrtc::data_channel::RTCDataChannel;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc_core::data_channels::{
+    self, check_label, hexdump, parse_hex, read_snippet, ChannelLog, ChannelMessage,
+    ChannelOptions, Ping, Pong, Reliability, MAX_SNIPPET_BYTES, RESERVED_LABELS,
+};
+use webrtc_core::file_transfer::size_label;
+use webrtc_core::messaging::{self, Envelope, MessageError, TypedMessage};
+use webrtc_core::realtime::{
+    self, realtime_init, RealtimeFrame, RealtimeReceiver, RealtimeSender, DELAY_BUCKETS_MS,
+    MAX_REALTIME_BYTES, REALTIME_PROTOCOL,
+};
+use webrtc_core::settings::FileKind;
+
+use crate::WebRTCApp;
+
+/// How often each channel's buffered amount is read.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Bytes of each binary message the log dumps; the rest is only counted.
+const DUMP_BYTES: usize = 256;
+
+/// Label of a realtime channel opened without one.
+const REALTIME_LABEL: &str = "realtime";
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+enum Composer {
+    #[default]
+    Text,
+    /// A typed message: its kind, and a JSON body.
+    Message,
+    Hex,
+    Snippet,
+}
+
+/// A piece of a file to send as one binary message.
+struct Snippet {
+    path: Option<PathBuf>,
+    offset: u64,
+    length: usize,
+    /// Read from `path` when it was picked or last reloaded.
+    data: Vec<u8>,
+}
+
+impl Default for Snippet {
+    fn default() -> Self {
+        Self {
+            path: None,
+            offset: 0,
+            length: 256,
+            data: Vec::new(),
+        }
+    }
+}
+
+struct Channel {
+    channel: Arc<RTCDataChannel>,
+    /// Opened from here rather than by the peer.
+    local: bool,
+    /// Fed by the channel's callback.
+    log: Arc<Mutex<ChannelLog>>,
+    /// Bytes queued to send, sampled in the background since webrtc-rs
+    /// only gives it out asynchronously.
+    buffered: Arc<AtomicUsize>,
+    /// How long the last ping took to be answered.
+    round_trip: Arc<Mutex<Option<Duration>>>,
+    realtime: Option<Realtime>,
+}
+
+/// Stamping what a realtime channel sends, and timing what it receives.
+struct Realtime {
+    sender: RealtimeSender,
+    receiver: Arc<Mutex<RealtimeReceiver>>,
+}
+
+#[derive(Default)]
+pub struct DataChannelsState {
+    channels: Vec<Channel>,
+    /// Index into `channels` of the one shown and sent on.
+    selected: Option<usize>,
+    /// The label and options to open the next channel with.
+    label: String,
+    options: ChannelOptions,
+    composer: Composer,
+    draft: String,
+    /// Kept after sending, to send again while poking at a protocol.
+    hex: String,
+    /// The typed message's kind, kept likewise.
+    kind: String,
+    /// The typed message's body, as JSON.
+    body: String,
+    snippet: Snippet,
+    status: String,
+}
+
+impl DataChannelsState {
+    /// Forgets the channels of a peer connection that is being replaced.
+    pub(crate) fn reset(&mut self) {
+        self.channels.clear();
+        self.selected = None;
+        self.draft.clear();
+        self.status.clear();
+    }
+
+    /// Lists `channel`, logging what arrives on it.
+    fn add(&mut self, channel: Arc<RTCDataChannel>, local: bool) {
+        let log = Arc::new(Mutex::new(ChannelLog::default()));
+        let realtime = (channel.protocol() == REALTIME_PROTOCOL).then(|| Realtime {
+            sender: RealtimeSender::default(),
+            receiver: Arc::default(),
+        });
+        let received = Arc::clone(&log);
+        let timing = realtime
+            .as_ref()
+            .map(|realtime| Arc::clone(&realtime.receiver));
+        let round_trip = Arc::new(Mutex::new(None));
+        let timed = Arc::clone(&round_trip);
+        let answering = Arc::downgrade(&channel);
+        channel.on_message(Box::new(move |message: DataChannelMessage| {
+            let arrived_us = realtime::now_us();
+            let mut message = ChannelMessage {
+                at: SystemTime::now(),
+                outgoing: false,
+                is_string: message.is_string,
+                data: message.data.to_vec(),
+            };
+            if let Some(timing) = &timing {
+                match RealtimeFrame::decode(&message.data) {
+                    Ok(frame) => {
+                        timing.lock().unwrap().received(&frame, arrived_us);
+                        // Stamped messages go as binary; text shows as text.
+                        message.is_string = std::str::from_utf8(&frame.payload).is_ok();
+                        message.data = frame.payload;
+                    }
+                    Err(err) => info!("Unstamped message on a realtime channel: {}", err),
+                }
+            }
+            let mut pong = None;
+            if timing.is_none() && message.is_string {
+                let text = message.text();
+                if let Ok(ping) = messaging::decode::<Ping>(&text) {
+                    pong = Some(Pong {
+                        sent_us: ping.sent_us,
+                    });
+                } else if let Ok(pong) = messaging::decode::<Pong>(&text) {
+                    let took = arrived_us.saturating_sub(pong.sent_us);
+                    *timed.lock().unwrap() = Some(Duration::from_micros(took));
+                }
+            }
+            received.lock().unwrap().push(message);
+            let (answering, log) = (answering.clone(), Arc::clone(&received));
+            Box::pin(async move {
+                let (Some(pong), Some(channel)) = (pong, answering.upgrade()) else {
+                    return;
+                };
+                if let Err(err) = send_typed(&channel, &log, &pong).await {
+                    info!("Failed to answer a ping: {}", err);
+                }
+            })
+        }));
+        let buffered = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(sample_buffered(
+            Arc::downgrade(&channel),
+            Arc::clone(&buffered),
+        ));
+        self.channels.push(Channel {
+            channel,
+            local,
+            log,
+            buffered,
+            round_trip,
+            realtime,
+        });
+        if self.selected.is_none() {
+            self.selected = Some(self.channels.len() - 1);
+        }
+    }
+}
+
+/// Sends `message` on `channel` as a typed message, logging it once sent.
+async fn send_typed<M: TypedMessage>(
+    channel: &RTCDataChannel,
+    log: &Mutex<ChannelLog>,
+    message: &M,
+) -> Result<(), MessageError> {
+    messaging::send(channel, message).await?;
+    log.lock().unwrap().push(ChannelMessage {
+        at: SystemTime::now(),
+        outgoing: true,
+        is_string: true,
+        data: messaging::encode(message).into_bytes(),
+    });
+    Ok(())
+}
+
+/// Keeps `buffered` up to date until the channel closes or goes away.
+async fn sample_buffered(channel: Weak<RTCDataChannel>, buffered: Arc<AtomicUsize>) {
+    while let Some(channel) = channel.upgrade() {
+        if channel.ready_state() == RTCDataChannelState::Closed {
+            break;
+        }
+        buffered.store(channel.buffered_amount().await, Ordering::Relaxed);
+        drop(channel);
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+    }
+}
+
+impl WebRTCApp {
+    /// Lists the channels the peer opens on `pc`.
+    pub(crate) fn watch_data_channels(&self, pc: &Arc<RTCPeerConnection>) {
+        let data_channels = Arc::clone(&self.data_channels);
+        pc.on_data_channel(Box::new(move |channel: Arc<RTCDataChannel>| {
+            if !RESERVED_LABELS.contains(&channel.label()) {
+                info!("The peer opened data channel {:?}", channel.label());
+                data_channels.lock().unwrap().add(channel, false);
+            }
+            Box::pin(async {})
+        }));
+    }
+
+    async fn open_data_channel(&self, label: String, init: RTCDataChannelInit) {
+        let pc = self.peer_connection.lock().await.clone();
+        let Some(pc) = pc else {
+            self.data_channels.lock().unwrap().status = "No peer connection yet".to_owned();
+            return;
+        };
+        let status = match pc.create_data_channel(&label, Some(init)).await {
+            Ok(channel) => {
+                let status = format!(
+                    "Opened data channel {:?}, {}",
+                    label,
+                    ChannelOptions::of(&channel)
+                );
+                let mut state = self.data_channels.lock().unwrap();
+                state.add(channel, true);
+                state.selected = Some(state.channels.len() - 1);
+                status
+            }
+            Err(err) => format!("Failed to open data channel {:?}: {}", label, err),
+        };
+        info!("{}", status);
+        self.data_channels.lock().unwrap().status = status;
+    }
+
+    /// Sends `message` on the picked channel, logging it there. On a
+    /// realtime channel it goes stamped, as binary.
+    async fn send_on_data_channel(&self, mut message: ChannelMessage) {
+        let (channel, log, stamped) = {
+            let mut state = self.data_channels.lock().unwrap();
+            let state = &mut *state;
+            let Some(picked) = state
+                .selected
+                .and_then(|index| state.channels.get_mut(index))
+            else {
+                return;
+            };
+            let stamped = match &mut picked.realtime {
+                Some(realtime) => {
+                    match realtime
+                        .sender
+                        .frame(message.data.clone(), realtime::now_us())
+                    {
+                        Ok(frame) => Some(ChannelMessage {
+                            is_string: false,
+                            data: frame.encode(),
+                            ..message.clone()
+                        }),
+                        Err(err) => {
+                            state.status = format!("Not sent: {}", err);
+                            return;
+                        }
+                    }
+                }
+                None => None,
+            };
+            (
+                Arc::clone(&picked.channel),
+                Arc::clone(&picked.log),
+                stamped,
+            )
+        };
+        let sent = data_channels::send(&channel, stamped.as_ref().unwrap_or(&message)).await;
+        let status = match sent {
+            Ok(()) => {
+                message.at = SystemTime::now();
+                log.lock().unwrap().push(message);
+                String::new()
+            }
+            Err(err) => format!("Not sent on {:?}: {}", channel.label(), err),
+        };
+        self.data_channels.lock().unwrap().status = status;
+    }
+
+    /// Pings the picked channel, logging the ping there.
+    async fn ping_data_channel(&self) {
+        let picked = {
+            let state = self.data_channels.lock().unwrap();
+            state
+                .selected
+                .and_then(|index| state.channels.get(index))
+                .map(|picked| (Arc::clone(&picked.channel), Arc::clone(&picked.log)))
+        };
+        let Some((channel, log)) = picked else {
+            return;
+        };
+        let ping = Ping {
+            sent_us: realtime::now_us(),
+        };
+        let status = match send_typed(&channel, &log, &ping).await {
+            Ok(()) => String::new(),
+            Err(err) => format!("Not sent on {:?}: {}", channel.label(), err),
+        };
+        self.data_channels.lock().unwrap().status = status;
+    }
+
+    /// Reads the snippet again from its file, or from a new one first
+    /// when `pick`.
+    async fn load_snippet(&self, pick: bool) {
+        let path = if pick {
+            self.open_file_dialog(FileKind::Transfers, "Send a piece of a file")
+                .await
+        } else {
+            self.data_channels.lock().unwrap().snippet.path.clone()
+        };
+        let Some(path) = path else {
+            return;
+        };
+        let (offset, length) = {
+            let state = self.data_channels.lock().unwrap();
+            (state.snippet.offset, state.snippet.length)
+        };
+        let read = read_snippet(&path, offset, length);
+        let mut state = self.data_channels.lock().unwrap();
+        match read {
+            Ok(data) => {
+                state.snippet.path = Some(path);
+                state.snippet.data = data;
+                state.status.clear();
+            }
+            Err(err) => state.status = format!("Failed to read {}: {}", path.display(), err),
+        }
+    }
+
+    /// The message to send, once the user asks for it sent.
+    fn composer_ui(
+        &self,
+        state: &mut DataChannelsState,
+        ui: &mut egui::Ui,
+        ctx: &egui::Context,
+        open: bool,
+    ) -> Option<ChannelMessage> {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut state.composer, Composer::Text, "Text");
+            ui.selectable_value(&mut state.composer, Composer::Message, "Typed message");
+            ui.selectable_value(&mut state.composer, Composer::Hex, "Hex");
+            ui.selectable_value(&mut state.composer, Composer::Snippet, "File snippet");
+        });
+        let binary = |data: Vec<u8>| ChannelMessage {
+            at: SystemTime::now(),
+            outgoing: true,
+            is_string: false,
+            data,
+        };
+        match state.composer {
+            Composer::Text => {
+                ui.horizontal(|ui| {
+                    let edit = ui.add_enabled(open, egui::TextEdit::singleline(&mut state.draft));
+                    let entered =
+                        edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    let send = ui
+                        .add_enabled(open && !state.draft.is_empty(), egui::Button::new("Send"))
+                        .clicked();
+                    if !((send || entered) && open && !state.draft.is_empty()) {
+                        return None;
+                    }
+                    edit.request_focus();
+                    Some(ChannelMessage {
+                        at: SystemTime::now(),
+                        outgoing: true,
+                        is_string: true,
+                        data: std::mem::take(&mut state.draft).into_bytes(),
+                    })
+                })
+                .inner
+            }
+            Composer::Message => {
+                ui.horizontal(|ui| {
+                    ui.label("Kind");
+                    ui.add_enabled(
+                        open,
+                        egui::TextEdit::singleline(&mut state.kind).hint_text("telemetry.sample"),
+                    );
+                });
+                ui.add_enabled(
+                    open,
+                    egui::TextEdit::multiline(&mut state.body)
+                        .code_editor()
+                        .desired_rows(3)
+                        .hint_text("{\"sensor\": \"hall\", \"celsius\": 21.5}"),
+                );
+                let body = serde_json::from_str(&state.body);
+                ui.horizontal(|ui| {
+                    let kind = state.kind.trim();
+                    let ready = !kind.is_empty() && body.is_ok();
+                    let send = ui
+                        .add_enabled(open && ready, egui::Button::new("Send"))
+                        .clicked();
+                    if let Err(err) = &body {
+                        if !state.body.trim().is_empty() {
+                            ui.colored_label(ui.visuals().error_fg_color, err.to_string());
+                        }
+                    }
+                    let envelope = Envelope {
+                        kind: kind.to_owned(),
+                        body: body.ok()?,
+                    };
+                    (send && ready).then(|| ChannelMessage {
+                        at: SystemTime::now(),
+                        outgoing: true,
+                        is_string: true,
+                        data: envelope.to_json().into_bytes(),
+                    })
+                })
+                .inner
+            }
+            Composer::Hex => {
+                ui.add_enabled(
+                    open,
+                    egui::TextEdit::multiline(&mut state.hex)
+                        .code_editor()
+                        .desired_rows(3)
+                        .hint_text("de ad be ef"),
+                );
+                let parsed = parse_hex(&state.hex);
+                ui.horizontal(|ui| {
+                    let ready = matches!(&parsed, Ok(data) if !data.is_empty());
+                    let send = ui
+                        .add_enabled(open && ready, egui::Button::new("Send"))
+                        .clicked();
+                    match &parsed {
+                        Ok(data) => ui.label(size_label(data.len() as u64)),
+                        Err(err) => ui.colored_label(ui.visuals().error_fg_color, err.to_string()),
+                    };
+                    parsed.ok().filter(|_| send).map(binary)
+                })
+                .inner
+            }
+            Composer::Snippet => {
+                let snippet = &mut state.snippet;
+                let mut reload = false;
+                ui.horizontal(|ui| {
+                    if ui.button("Pick File…").clicked() {
+                        let app = self.clone();
+                        let ctx = ctx.clone();
+                        tokio::spawn(async move {
+                            app.load_snippet(true).await;
+                            ctx.request_repaint();
+                        });
+                    }
+                    match &snippet.path {
+                        Some(path) => ui.label(path.display().to_string()),
+                        None => ui.weak("No file"),
+                    };
+                });
+                ui.horizontal(|ui| {
+                    ui.label("From byte");
+                    reload |= ui.add(egui::DragValue::new(&mut snippet.offset)).changed();
+                    ui.label("take");
+                    reload |= ui
+                        .add(
+                            egui::DragValue::new(&mut snippet.length)
+                                .clamp_range(1..=MAX_SNIPPET_BYTES)
+                                .suffix(" bytes"),
+                        )
+                        .changed();
+                });
+                if reload && snippet.path.is_some() {
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.load_snippet(false).await;
+                        ctx.request_repaint();
+                    });
+                }
+                if !snippet.data.is_empty() {
+                    let shown = &snippet.data[..snippet.data.len().min(DUMP_BYTES)];
+                    ui.monospace(hexdump(shown));
+                }
+                let ready = open && !snippet.data.is_empty();
+                let send = ui.add_enabled(ready, egui::Button::new("Send")).clicked();
+                (send && ready).then(|| binary(snippet.data.clone()))
+            }
+        }
+    }
+
+    pub(crate) fn data_channels_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.data_channels.lock().unwrap();
+        ui.horizontal(|ui| {
+            ui.label("Label:");
+            ui.text_edit_singleline(&mut state.label);
+            let checked = check_label(&state.label);
+            let open = ui.add_enabled(checked.is_ok(), egui::Button::new("Open Channel"));
+            let open = match &checked {
+                Err(err) if !state.label.is_empty() => open.on_disabled_hover_text(err.to_string()),
+                _ => open,
+            };
+            let realtime = ui
+                .add_enabled(
+                    checked.is_ok() || state.label.is_empty(),
+                    egui::Button::new("Realtime Channel"),
+                )
+                .on_hover_text(format!(
+                    "Unordered and resent once at most, for input that is stale once late. \
+                     Messages of up to {} go stamped with their send time.",
+                    size_label(MAX_REALTIME_BYTES as u64)
+                ));
+            let init = if open.clicked() {
+                Some(state.options.init())
+            } else if realtime.clicked() {
+                if state.label.is_empty() {
+                    state.label = REALTIME_LABEL.to_owned();
+                }
+                Some(realtime_init())
+            } else {
+                None
+            };
+            if let Some(init) = init {
+                let label = std::mem::take(&mut state.label);
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.open_data_channel(label, init).await;
+                    ctx.request_repaint();
+                });
+            }
+        });
+        ui.horizontal(|ui| {
+            let options = &mut state.options;
+            ui.checkbox(&mut options.ordered, "Ordered");
+            let reliability = &mut options.reliability;
+            egui::ComboBox::from_id_source("data_channel_reliability")
+                .selected_text(reliability.name())
+                .show_ui(ui, |ui| {
+                    for choice in [
+                        Reliability::Reliable,
+                        Reliability::MaxRetransmits(3),
+                        Reliability::MaxPacketLifetime(100),
+                    ] {
+                        let current = choice.name() == reliability.name();
+                        if ui.selectable_label(current, choice.name()).clicked() && !current {
+                            *reliability = choice;
+                        }
+                    }
+                });
+            match reliability {
+                Reliability::Reliable => {}
+                Reliability::MaxRetransmits(retransmits) => {
+                    ui.add(
+                        egui::DragValue::new(retransmits)
+                            .clamp_range(1..=u16::MAX)
+                            .suffix(" retransmits"),
+                    );
+                }
+                Reliability::MaxPacketLifetime(lifetime) => {
+                    ui.add(
+                        egui::DragValue::new(lifetime)
+                            .clamp_range(1..=u16::MAX)
+                            .suffix(" ms"),
+                    );
+                }
+            }
+        });
+        if !state.status.is_empty() {
+            ui.label(&state.status);
+        }
+        if state.channels.is_empty() {
+            ui.weak("No channels besides the app's own.");
+            return;
+        }
+        // State and buffered amounts change in the background.
+        ctx.request_repaint_after(SAMPLE_INTERVAL);
+
+        let mut selected = state.selected;
+        let mut close = None;
+        egui::Grid::new("data_channels")
+            .striped(true)
+            .show(ui, |ui| {
+                for heading in [
+                    "Label",
+                    "Opened by",
+                    "Delivery",
+                    "State",
+                    "Sent",
+                    "Received",
+                    "Buffered",
+                ] {
+                    ui.strong(heading);
+                }
+                ui.end_row();
+                for (index, entry) in state.channels.iter().enumerate() {
+                    let channel = &entry.channel;
+                    ui.radio_value(&mut selected, Some(index), channel.label());
+                    ui.label(if entry.local { "us" } else { "the peer" });
+                    let delivery = ChannelOptions::of(channel).to_string();
+                    ui.label(match entry.realtime {
+                        Some(_) => format!("{}, realtime", delivery),
+                        None => delivery,
+                    });
+                    ui.label(channel.ready_state().to_string());
+                    let log = entry.log.lock().unwrap();
+                    ui.label(format!(
+                        "{} ({})",
+                        log.messages_sent,
+                        size_label(log.bytes_sent)
+                    ));
+                    ui.label(format!(
+                        "{} ({})",
+                        log.messages_received,
+                        size_label(log.bytes_received)
+                    ));
+                    ui.label(size_label(entry.buffered.load(Ordering::Relaxed) as u64));
+                    let closable = channel.ready_state() == RTCDataChannelState::Open;
+                    if ui
+                        .add_enabled(closable, egui::Button::new("Close"))
+                        .clicked()
+                    {
+                        close = Some(Arc::clone(channel));
+                    }
+                    ui.end_row();
+                }
+            });
+        state.selected = selected;
+        if let Some(channel) = close {
+            tokio::spawn(async move {
+                if let Err(err) = channel.close().await {
+                    info!("Failed to close data channel: {}", err);
+                }
+            });
+        }
+
+        let Some(picked) = selected.and_then(|index| state.channels.get(index)) else {
+            return;
+        };
+        let open = picked.channel.ready_state() == RTCDataChannelState::Open;
+        let log = Arc::clone(&picked.log);
+        if let Some(realtime) = &picked.realtime {
+            delay_histogram_ui(ui, &realtime.receiver.lock().unwrap());
+        } else {
+            ui.horizontal(|ui| {
+                let ping = ui
+                    .add_enabled(open, egui::Button::new("Ping"))
+                    .on_hover_text(format!(
+                        "Times a round trip to the app at the other end, which answers a {} \
+                         with a {}",
+                        Ping::KIND,
+                        Pong::KIND
+                    ));
+                if ping.clicked() {
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.ping_data_channel().await;
+                        ctx.request_repaint();
+                    });
+                }
+                if let Some(took) = *picked.round_trip.lock().unwrap() {
+                    ui.label(format!("Round trip: {} ms", took.as_millis()));
+                }
+            });
+        }
+        egui::ScrollArea::vertical()
+            .id_source("data_channel_messages")
+            .max_height(200.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for message in log.lock().unwrap().messages() {
+                    let at = humantime::format_rfc3339_millis(message.at);
+                    let arrow = if message.outgoing { "→" } else { "←" };
+                    if message.is_string {
+                        match Envelope::from_json(&message.text()) {
+                            Ok(envelope) => {
+                                ui.label(format!("{} {} {}", at, arrow, envelope.kind));
+                                ui.monospace(envelope.pretty_body());
+                            }
+                            Err(_) => {
+                                ui.label(format!("{} {} {}", at, arrow, message.text()));
+                            }
+                        }
+                        continue;
+                    }
+                    let size = message.data.len();
+                    ui.label(format!(
+                        "{} {} {} binary",
+                        at,
+                        arrow,
+                        size_label(size as u64)
+                    ));
+                    ui.monospace(hexdump(&message.data[..size.min(DUMP_BYTES)]));
+                    if size > DUMP_BYTES {
+                        ui.weak(format!("… and {} more bytes", size - DUMP_BYTES));
+                    }
+                }
+            });
+        if let Some(message) = self.composer_ui(&mut state, ui, ctx, open) {
+            let app = self.clone();
+            let ctx = ctx.clone();
+            tokio::spawn(async move {
+                app.send_on_data_channel(message).await;
+                ctx.request_repaint();
+            });
+        }
+    }
+}
+
+/// How late the picked realtime channel's messages arrive, past the
+/// quickest.
+fn delay_histogram_ui(ui: &mut egui::Ui, receiver: &RealtimeReceiver) {
+    ui.label(format!(
+        "{} received, {} missing, {} overtaken by later ones",
+        receiver.received, receiver.missing, receiver.late
+    ));
+    if receiver.implausible > 0 {
+        ui.weak(format!(
+            "{} left out for a send time too far from when they arrived",
+            receiver.implausible
+        ));
+    }
+    if receiver.received == 0 {
+        return;
+    }
+    let bucket_label = |index: usize| match (index.checked_sub(1), DELAY_BUCKETS_MS.get(index)) {
+        (None, Some(upper)) => format!("< {} ms", upper),
+        (Some(lower), Some(upper)) => format!("{}-{} ms", DELAY_BUCKETS_MS[lower], upper),
+        _ => format!("≥ {} ms", DELAY_BUCKETS_MS[DELAY_BUCKETS_MS.len() - 1]),
+    };
+    let bars = receiver
+        .histogram()
+        .iter()
+        .enumerate()
+        .map(|(index, &count)| Bar::new(index as f64, count as f64).name(bucket_label(index)))
+        .collect();
+    Plot::new("realtime_delays")
+        .height(120.0)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .include_y(0.0)
+        .x_axis_formatter(move |mark, _, _| {
+            let index = mark.value.round();
+            if index < 0.0 || index != mark.value {
+                return String::new();
+            }
+            match index as usize {
+                index if index <= DELAY_BUCKETS_MS.len() => bucket_label(index),
+                _ => String::new(),
+            }
+        })
+        .show(ui, |plot_ui| {
+            plot_ui.bar_chart(BarChart::new(bars).name("Delay past the quickest"));
+        });
+}
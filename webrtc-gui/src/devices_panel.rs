@@ -0,0 +1,180 @@
+//! Device selection, remembered across runs.
+
+use std::collections::HashMap;
+
+use eframe::egui;
+use webrtc_core::camera;
+use webrtc_core::constraints::{
+    ConstrainValue, OverconstrainedError, VideoConstraints, VideoSelection,
+};
+use webrtc_core::devices::{self, DeviceInfo, DeviceKind, DevicePreference};
+use webrtc_core::settings::DeviceSettings;
+
+use crate::WebRTCApp;
+
+#[derive(Default)]
+pub struct DeviceState {
+    available: HashMap<DeviceKind, Vec<DeviceInfo>>,
+    selected: HashMap<DeviceKind, DeviceInfo>,
+    /// Fallbacks taken because a remembered device was missing.
+    notices: Vec<String>,
+}
+
+impl DeviceState {
+    /// Enumerates devices and reselects the remembered ones.
+    pub fn load(preferences: &DeviceSettings) -> Self {
+        let mut state = Self::default();
+        state.refresh(preferences);
+        state
+    }
+
+    pub(crate) fn refresh(&mut self, preferences: &DeviceSettings) {
+        self.notices.clear();
+        for kind in DeviceKind::ALL {
+            let available = devices::enumerate(kind);
+            let choice = devices::resolve(kind, preferences.preference(kind), &available);
+            match choice.device {
+                Some(device) => self.selected.insert(kind, device),
+                None => self.selected.remove(&kind),
+            };
+            self.notices.extend(choice.notice);
+            self.available.insert(kind, available);
+        }
+    }
+
+    pub(crate) fn selected(&self, kind: DeviceKind) -> Option<&DeviceInfo> {
+        self.selected.get(&kind)
+    }
+
+    /// A dropdown of the available devices of `kind`. Picking one selects
+    /// it for the next capture and remembers it in `preferences`.
+    pub(crate) fn picker_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        kind: DeviceKind,
+        preferences: &mut DeviceSettings,
+    ) {
+        let selected = self.selected.get(&kind);
+        let text = selected.map_or("None found", |d| d.name.as_str());
+        let mut chosen = None;
+        egui::ComboBox::from_id_source(kind)
+            .selected_text(text)
+            .show_ui(ui, |ui| {
+                for device in self.available.get(&kind).into_iter().flatten() {
+                    let current = selected.is_some_and(|s| s.id == device.id);
+                    if ui.selectable_label(current, &device.name).clicked() && !current {
+                        chosen = Some(device.clone());
+                    }
+                }
+            });
+        if let Some(device) = chosen {
+            preferences.set_preference(kind, Some(DevicePreference::from(&device)));
+            self.selected.insert(kind, device);
+        }
+    }
+
+    /// The camera and mode capture would use for `constraints`, among the
+    /// modes it can read; `None` without a selected camera.
+    fn camera_selection(
+        &self,
+        mut constraints: VideoConstraints,
+    ) -> Option<Result<VideoSelection, OverconstrainedError>> {
+        let camera = self.selected.get(&DeviceKind::Camera)?;
+        constraints.device_id = ConstrainValue::ideal(camera.id.clone());
+        let mut cameras = self.available[&DeviceKind::Camera].clone();
+        for camera in &mut cameras {
+            camera.modes.retain(camera::can_capture);
+        }
+        Some(constraints.select(&cameras))
+    }
+}
+
+fn title(kind: DeviceKind) -> &'static str {
+    match kind {
+        DeviceKind::Camera => "Camera",
+        DeviceKind::Microphone => "Microphone",
+        DeviceKind::Speaker => "Speaker",
+    }
+}
+
+impl WebRTCApp {
+    pub(crate) fn selected_device(&self, kind: DeviceKind) -> Option<DeviceInfo> {
+        self.devices.lock().unwrap().selected.get(&kind).cloned()
+    }
+
+    pub(crate) fn available_devices(&self, kind: DeviceKind) -> Vec<DeviceInfo> {
+        let state = self.devices.lock().unwrap();
+        state.available.get(&kind).cloned().unwrap_or_default()
+    }
+
+    pub(crate) fn camera_selection(&self) -> Option<Result<VideoSelection, OverconstrainedError>> {
+        let constraints = self
+            .settings
+            .lock()
+            .unwrap()
+            .media
+            .video_constraints
+            .clone();
+        self.devices.lock().unwrap().camera_selection(constraints)
+    }
+
+    /// Shows fallback notices until dismissed, and a device switch in
+    /// progress.
+    pub(crate) fn device_notices_ui(&self, ui: &mut egui::Ui) {
+        if self.switching_camera() || self.switching_microphone() {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Switching device...");
+            });
+        }
+        let mut state = self.devices.lock().unwrap();
+        if state.notices.is_empty() {
+            return;
+        }
+        let mut dismiss = false;
+        for notice in &state.notices {
+            ui.colored_label(egui::Color32::YELLOW, notice);
+        }
+        if ui.small_button("Dismiss").clicked() {
+            dismiss = true;
+        }
+        if dismiss {
+            state.notices.clear();
+        }
+    }
+
+    pub(crate) fn devices_ui(&self, ui: &mut egui::Ui) {
+        let mut state = self.devices.lock().unwrap();
+        let mut settings = self.settings.lock().unwrap();
+        egui::Grid::new("devices").num_columns(2).show(ui, |ui| {
+            for kind in DeviceKind::ALL {
+                ui.label(format!("{}:", title(kind)));
+                state.picker_ui(ui, kind, &mut settings.devices);
+                ui.end_row();
+            }
+        });
+
+        let constraints = settings.media.video_constraints.clone();
+        if let Some((camera, selection)) = state
+            .selected
+            .get(&DeviceKind::Camera)
+            .zip(state.camera_selection(constraints))
+        {
+            match selection {
+                Ok(VideoSelection { device, mode }) => {
+                    let mode = mode.map_or("device default".to_owned(), |m| m.to_string());
+                    if device.id == camera.id {
+                        ui.label(format!("Capture mode: {}", mode))
+                    } else {
+                        // Another camera fits the constraints better.
+                        ui.label(format!("Capture mode: {} on {}", mode, device.name))
+                    }
+                }
+                Err(err) => ui.colored_label(egui::Color32::YELLOW, err.to_string()),
+            };
+        }
+        if ui.button("Refresh").clicked() {
+            state.refresh(&settings.devices);
+        }
+    }
+}
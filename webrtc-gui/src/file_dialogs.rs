@@ -0,0 +1,91 @@
+//! Native open/save dialogs for every file the app reads or writes. Each
+//! kind of file starts in its own folder, remembered from the last pick.
+
+use std::path::PathBuf;
+
+use rfd::AsyncFileDialog;
+use webrtc_core::settings::FileKind;
+
+use crate::{data_dir, WebRTCApp};
+
+fn filter(kind: FileKind) -> Option<(&'static str, &'static [&'static str])> {
+    match kind {
+        FileKind::Sdp => Some(("Session description", &["sdp", "txt"])),
+        FileKind::Contacts => Some(("vCard", &["vcf", "vcard"])),
+        FileKind::Exports => Some(("JSON", &["json"])),
+        FileKind::Clips => Some(("GIF", &["gif"])),
+        FileKind::Recordings
+        | FileKind::Snapshots
+        | FileKind::SignalingFolder
+        | FileKind::Transfers => None,
+        FileKind::Media => Some((
+            "Media",
+            &[
+                "mp4", "m4v", "mov", "webm", "mkv", "ivf", "ogg", "opus", "y4m",
+            ],
+        )),
+    }
+}
+
+/// A dialog for `kind` starting in `folder`, or in our data directory.
+fn dialog(kind: FileKind, title: &str, folder: Option<PathBuf>) -> AsyncFileDialog {
+    let mut dialog = AsyncFileDialog::new().set_title(title);
+    if let Some(folder) = folder.or_else(data_dir) {
+        dialog = dialog.set_directory(folder);
+    }
+    if let Some((name, extensions)) = filter(kind) {
+        dialog = dialog.add_filter(name, extensions);
+    }
+    dialog
+}
+
+/// Asks for a folder on behalf of the settings window, which has no app
+/// handle to remember it with.
+pub(crate) async fn pick_folder(kind: FileKind, folder: Option<PathBuf>) -> Option<PathBuf> {
+    let picked = dialog(kind, kind.label(), folder).pick_folder().await?;
+    Some(picked.path().to_path_buf())
+}
+
+impl WebRTCApp {
+    fn start_folder(&self, kind: FileKind) -> Option<PathBuf> {
+        self.settings.lock().unwrap().files.folder(kind).cloned()
+    }
+
+    fn remember_folder(&self, kind: FileKind, path: &std::path::Path) {
+        self.settings.lock().unwrap().files.remember(kind, path);
+    }
+
+    /// `None` when the user cancels.
+    pub(crate) async fn open_file_dialog(&self, kind: FileKind, title: &str) -> Option<PathBuf> {
+        let picked = dialog(kind, title, self.start_folder(kind))
+            .pick_file()
+            .await?;
+        let path = picked.path().to_path_buf();
+        self.remember_folder(kind, &path);
+        Some(path)
+    }
+
+    pub(crate) async fn save_file_dialog(
+        &self,
+        kind: FileKind,
+        title: &str,
+        file_name: &str,
+    ) -> Option<PathBuf> {
+        let picked = dialog(kind, title, self.start_folder(kind))
+            .set_file_name(file_name)
+            .save_file()
+            .await?;
+        let path = picked.path().to_path_buf();
+        self.remember_folder(kind, &path);
+        Some(path)
+    }
+
+    pub(crate) async fn folder_dialog(&self, kind: FileKind, title: &str) -> Option<PathBuf> {
+        let picked = dialog(kind, title, self.start_folder(kind))
+            .pick_folder()
+            .await?;
+        let path = picked.path().to_path_buf();
+        self.remember_folder(kind, &path);
+        Some(path)
+    }
+}
@@ -0,0 +1,245 @@
+//! Offer/answer exchange through files in a shared folder.
+
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use eframe::egui;
+use tokio::task::JoinHandle;
+use webrtc_core::settings::FileKind;
+use webrtc_core::signaling::file::{self, Role, SignalFile};
+
+use crate::WebRTCApp;
+
+pub struct FileSignalingState {
+    folder: String,
+    /// Session of the offer we exported or answered.
+    session: Option<String>,
+    /// Watch the folder for the counterpart file instead of importing it by hand.
+    watch: bool,
+    watcher: Option<JoinHandle<()>>,
+    status: String,
+}
+
+impl FileSignalingState {
+    /// Starts in the remembered folder, or the temporary directory.
+    pub(crate) fn new(folder: Option<&PathBuf>) -> Self {
+        let folder = folder.cloned().unwrap_or_else(std::env::temp_dir);
+        Self {
+            folder: folder.display().to_string(),
+            session: None,
+            watch: true,
+            watcher: None,
+            status: String::new(),
+        }
+    }
+
+    fn stop_watching(&mut self) {
+        if let Some(watcher) = self.watcher.take() {
+            watcher.abort();
+        }
+    }
+}
+
+impl WebRTCApp {
+    fn set_file_status(&self, status: impl Into<String>) {
+        self.file_signaling.lock().unwrap().status = status.into();
+    }
+
+    fn signaling_folder(&self) -> PathBuf {
+        PathBuf::from(self.file_signaling.lock().unwrap().folder.trim())
+    }
+
+    async fn export_offer(&self, ctx: egui::Context) {
+        let folder = self.signaling_folder();
+        let since = SystemTime::now();
+        self.set_file_status("Creating offer...");
+        self.create_peer_connection(false).await;
+        self.add_configured_transceivers().await;
+        self.open_control_channel().await;
+        self.create_offer().await;
+
+        let session = file::new_session();
+        let offer = SignalFile::new(Role::Offer, &session, self.local_sdp.get().to_string());
+        let path = match offer.write_to(&folder) {
+            Ok(path) => path,
+            Err(err) => {
+                self.set_file_status(format!("Failed to write offer: {}", err));
+                return;
+            }
+        };
+        let watch = {
+            let mut state = self.file_signaling.lock().unwrap();
+            state.session = Some(session.clone());
+            state.status = format!("Offer written to {}", path.display());
+            state.watch
+        };
+        if watch {
+            self.watch_folder(ctx, Role::Answer, Some(session), since);
+        }
+    }
+
+    async fn import_answer(&self, path: PathBuf) {
+        let answer = match SignalFile::read(&path, Role::Answer) {
+            Ok(answer) => answer,
+            Err(err) => {
+                self.set_file_status(format!("Failed to read {}: {}", path.display(), err));
+                return;
+            }
+        };
+        let expected = self.file_signaling.lock().unwrap().session.clone();
+        if expected.as_ref() != Some(&answer.session) {
+            self.set_file_status(format!(
+                "{} answers session {}, not our offer",
+                path.display(),
+                answer.session
+            ));
+            return;
+        }
+        self.remote_sdp.set(answer.sdp_with_candidates());
+        self.handle_answer().await;
+        self.set_file_status("Answer imported, connecting...");
+    }
+
+    async fn import_offer(&self, path: PathBuf, offer: SignalFile) {
+        let name = path
+            .file_name()
+            .map_or(String::new(), |name| name.to_string_lossy().into_owned());
+        let sdp = offer.sdp_with_candidates();
+        if !self.admit_caller("file", &name, &sdp) {
+            self.set_file_status(format!("Ignored {}", name));
+            return;
+        }
+        self.set_file_status("Answering offer...");
+        self.create_peer_connection(false).await;
+        self.open_control_channel().await;
+        let sdp = match self.answer_remote_offer(sdp).await {
+            Ok(sdp) => sdp,
+            Err(err) => {
+                self.set_file_status(format!("Failed to answer: {}", err));
+                return;
+            }
+        };
+        let answer = SignalFile::new(Role::Answer, &offer.session, sdp);
+        let folder = path
+            .parent()
+            .map_or_else(|| self.signaling_folder(), PathBuf::from);
+        match answer.write_to(&folder) {
+            Ok(path) => {
+                self.file_signaling.lock().unwrap().session = Some(offer.session);
+                self.set_file_status(format!("Answer written to {}", path.display()));
+            }
+            Err(err) => self.set_file_status(format!("Failed to write answer: {}", err)),
+        }
+    }
+
+    /// Picks the counterpart file up as soon as it lands in the folder.
+    fn watch_folder(
+        &self,
+        ctx: egui::Context,
+        role: Role,
+        session: Option<String>,
+        since: SystemTime,
+    ) {
+        let folder = self.signaling_folder();
+        let waiting = format!("Waiting for an offer in {}...", folder.display());
+        let app = self.clone();
+        let watcher = tokio::spawn(async move {
+            let found = file::wait_for(&folder, role, session.as_deref(), since).await;
+            match found {
+                Ok((path, file)) => match role {
+                    Role::Answer => app.import_answer(path).await,
+                    Role::Offer => app.import_offer(path, file).await,
+                },
+                Err(err) => app.set_file_status(format!("Stopped watching: {}", err)),
+            }
+            ctx.request_repaint();
+        });
+        let mut state = self.file_signaling.lock().unwrap();
+        state.stop_watching();
+        state.watcher = Some(watcher);
+        if role == Role::Offer {
+            state.status = waiting;
+        }
+    }
+
+    /// The file for `role` in the folder, for importing by hand.
+    fn counterpart_path(&self, role: Role) -> Option<PathBuf> {
+        let state = self.file_signaling.lock().unwrap();
+        let session = state.session.as_ref()?;
+        Some(PathBuf::from(state.folder.trim()).join(SignalFile::file_name(role, session)))
+    }
+}
+
+impl WebRTCApp {
+    pub(crate) fn file_signaling_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.file_signaling.lock().unwrap();
+        ui.horizontal(|ui| {
+            ui.label("Shared folder:");
+            if ui.button("Browse...").clicked() {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    if let Some(folder) = app
+                        .folder_dialog(FileKind::SignalingFolder, "Shared signaling folder")
+                        .await
+                    {
+                        app.file_signaling.lock().unwrap().folder = folder.display().to_string();
+                    }
+                    ctx.request_repaint();
+                });
+            }
+            ui.add(egui::TextEdit::singleline(&mut state.folder).desired_width(f32::INFINITY));
+        });
+        ui.checkbox(
+            &mut state.watch,
+            "Watch the folder for the other side's file",
+        );
+        if let Some(session) = &state.session {
+            ui.label(format!("Session: {}", session));
+        }
+
+        let watching = state
+            .watcher
+            .as_ref()
+            .is_some_and(|watcher| !watcher.is_finished());
+        let mut wait_for_offer = false;
+        ui.horizontal(|ui| {
+            if ui.button("Export Offer to File").clicked() {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.export_offer(ctx.clone()).await;
+                    ctx.request_repaint();
+                });
+            }
+            if ui
+                .add_enabled(
+                    state.session.is_some(),
+                    egui::Button::new("Import Answer from File"),
+                )
+                .clicked()
+            {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    if let Some(path) = app.counterpart_path(Role::Answer) {
+                        app.import_answer(path).await;
+                    }
+                    ctx.request_repaint();
+                });
+            }
+            if !watching && ui.button("Wait for Offer").clicked() {
+                wait_for_offer = true;
+            }
+            if watching && ui.button("Stop Watching").clicked() {
+                state.stop_watching();
+                state.status = "Stopped watching".to_owned();
+            }
+        });
+        ui.label(&state.status);
+        drop(state);
+        if wait_for_offer {
+            self.watch_folder(ctx.clone(), Role::Offer, None, SystemTime::now());
+        }
+    }
+}
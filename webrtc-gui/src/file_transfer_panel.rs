@@ -0,0 +1,864 @@
+//! Sending files and folders to the peer and saving the ones it sends, one
+//! each way at a time, over the "files" data channel. Files dropped onto
+//! the window are listed for the user to confirm, then offered one after
+//! another.
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use eframe::egui;
+use log::info;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc_core::file_transfer::{
+    self, size_label, Conflict, FileOffer, FolderManifest, IncomingFile, IncomingFolder,
+    TransferError, TransferMessage, TRANSFER_LABEL, TRANSFER_STREAM_ID,
+};
+use webrtc_core::history::TransferRecord;
+use webrtc_core::settings::FileKind;
+
+use crate::negotiated_channel::{self, ChannelPanel, NegotiatedChannel, Received};
+use crate::WebRTCApp;
+
+/// What is offered either way: a file, or a folder of them.
+#[derive(Clone, PartialEq)]
+enum Offer {
+    File(FileOffer),
+    Folder(FolderManifest),
+}
+
+impl Offer {
+    fn name(&self) -> &str {
+        match self {
+            Offer::File(offer) => &offer.name,
+            Offer::Folder(manifest) => &manifest.name,
+        }
+    }
+
+    fn size(&self) -> u64 {
+        match self {
+            Offer::File(offer) => offer.size,
+            Offer::Folder(manifest) => manifest.size(),
+        }
+    }
+
+    /// The name with its size, and for a folder how many files it holds.
+    fn describe(&self) -> String {
+        match self {
+            Offer::File(offer) => format!("{} ({})", offer.name, size_label(offer.size)),
+            Offer::Folder(manifest) => format!(
+                "the folder {} ({} files, {})",
+                manifest.name,
+                manifest.files.len(),
+                size_label(manifest.size())
+            ),
+        }
+    }
+
+    fn into_message(self) -> TransferMessage {
+        match self {
+            Offer::File(offer) => TransferMessage::Offer(offer),
+            Offer::Folder(manifest) => TransferMessage::OfferFolder(manifest),
+        }
+    }
+}
+
+enum Incoming {
+    File(IncomingFile),
+    Folder(IncomingFolder),
+}
+
+impl Incoming {
+    fn name(&self) -> &str {
+        match self {
+            Incoming::File(file) => &file.offer().name,
+            Incoming::Folder(folder) => &folder.manifest().name,
+        }
+    }
+
+    fn size(&self) -> u64 {
+        match self {
+            Incoming::File(file) => file.offer().size,
+            Incoming::Folder(folder) => folder.manifest().size(),
+        }
+    }
+
+    fn received(&self) -> u64 {
+        match self {
+            Incoming::File(file) => file.received(),
+            Incoming::Folder(folder) => folder.received(),
+        }
+    }
+
+    /// Writes the next `chunk`, and returns whether everything arrived.
+    fn write(&mut self, chunk: &[u8]) -> Result<bool, TransferError> {
+        match self {
+            Incoming::File(file) => file.write(chunk),
+            Incoming::Folder(folder) => folder.write(chunk),
+        }
+    }
+
+    fn discard(self) {
+        match self {
+            Incoming::File(file) => file.discard(),
+            Incoming::Folder(folder) => folder.discard(),
+        }
+    }
+}
+
+/// A file or folder we offered, from the offer until the peer has it.
+struct Outgoing {
+    path: PathBuf,
+    offer: Offer,
+    sent: Arc<AtomicU64>,
+    /// Set once the peer accepts.
+    sender: Option<JoinHandle<()>>,
+}
+
+enum TransferEvent {
+    Message(TransferMessage),
+    /// A binary message: the next piece of the incoming file.
+    Chunk(DataChannelMessage),
+}
+
+#[derive(Default)]
+pub struct FileTransferState {
+    channel: NegotiatedChannel,
+    /// Hashing the file to offer.
+    preparing: bool,
+    outgoing: Option<Outgoing>,
+    /// The peer's offer, until it is saved or declined.
+    offered: Option<Offer>,
+    /// What to do when the peer's folder is already where it is saved.
+    conflict: Conflict,
+    incoming: Option<Incoming>,
+    /// Dropped onto the window, until the user sends or clears them.
+    dropped: Vec<PathBuf>,
+    /// Confirmed, and offered in turn once the one before is done.
+    queue: VecDeque<PathBuf>,
+    /// How transfers ended, until the history keeps them.
+    finished: Vec<TransferRecord>,
+    status: String,
+}
+
+impl FileTransferState {
+    fn finished(&mut self, outgoing: bool, name: &str, size: u64, outcome: &str) {
+        self.finished
+            .push(TransferRecord::now(outgoing, name, size, outcome));
+    }
+
+    pub(crate) fn take_finished(&mut self) -> Vec<TransferRecord> {
+        std::mem::take(&mut self.finished)
+    }
+
+    /// Like `stop`, noting `outcome` for whatever was under way.
+    fn stop_with(&mut self, outcome: &str) {
+        if let Some(outgoing) = &self.outgoing {
+            let (name, size) = (outgoing.offer.name().to_owned(), outgoing.offer.size());
+            self.finished(true, &name, size, outcome);
+        }
+        if let Some(incoming) = &self.incoming {
+            let (name, size) = (incoming.name().to_owned(), incoming.size());
+            self.finished(false, &name, size, outcome);
+        }
+        self.stop();
+    }
+
+    /// Drops both transfers and the files queued after them, deleting
+    /// what arrived of the incoming one.
+    fn stop(&mut self) {
+        if let Some(sender) = self.outgoing.take().and_then(|outgoing| outgoing.sender) {
+            sender.abort();
+        }
+        if let Some(incoming) = self.incoming.take() {
+            incoming.discard();
+        }
+        self.offered = None;
+        self.queue.clear();
+    }
+
+    /// Back to no channel, for a new peer connection.
+    pub(crate) fn reset(&mut self) {
+        self.stop();
+        *self = Self::default();
+    }
+
+    fn sending(&self) -> bool {
+        self.preparing || self.outgoing.is_some()
+    }
+
+    fn receiving(&self) -> bool {
+        self.offered.is_some() || self.incoming.is_some()
+    }
+}
+
+impl ChannelPanel for FileTransferState {
+    fn negotiated(&mut self) -> &mut NegotiatedChannel {
+        &mut self.channel
+    }
+
+    fn replaced(&mut self) {
+        self.reset();
+    }
+
+    fn closed(&mut self) {
+        if self.sending() || self.receiving() {
+            self.status = "The files channel closed; the transfer stopped".to_owned();
+        }
+        self.stop_with("stopped when the channel closed");
+    }
+}
+
+impl WebRTCApp {
+    /// Creates the files channel on the current peer connection.
+    pub(crate) async fn open_transfer_channel(&self) {
+        let (events_tx, mut events) = mpsc::unbounded_channel();
+        self.open_negotiated_channel(
+            &self.file_transfer,
+            TRANSFER_LABEL,
+            TRANSFER_STREAM_ID,
+            move |message| {
+                let event = match message {
+                    Received::Text(text) => match TransferMessage::from_json(&text) {
+                        Ok(message) => TransferEvent::Message(message),
+                        Err(err) => {
+                            info!("Ignoring malformed files message: {}", err);
+                            return None;
+                        }
+                    },
+                    Received::Binary(message) => TransferEvent::Chunk(message),
+                };
+                let _ = events_tx.send(event);
+                None
+            },
+        )
+        .await;
+
+        let app = self.clone();
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                match event {
+                    TransferEvent::Message(message) => app.handle_transfer_message(message).await,
+                    TransferEvent::Chunk(message) => app.receive_chunk(&message.data).await,
+                }
+            }
+        });
+    }
+
+    async fn send_transfer_message(&self, message: TransferMessage) -> bool {
+        let channel = self.file_transfer.lock().unwrap().channel.sender();
+        negotiated_channel::send(channel, message.to_json())
+            .await
+            .is_ok()
+    }
+
+    fn set_transfer_status(&self, status: String) {
+        info!("{}", status);
+        self.file_transfer.lock().unwrap().status = status;
+    }
+
+    /// Hashes the file or folder at `path` and offers it to the peer.
+    async fn offer_file(&self, path: PathBuf) {
+        {
+            let mut state = self.file_transfer.lock().unwrap();
+            if state.sending() {
+                return;
+            }
+            state.preparing = true;
+        }
+        self.hash_and_offer(path).await;
+    }
+
+    /// Offers the next queued file, unless one is already on its way.
+    /// Files that cannot be offered are skipped.
+    async fn offer_next_queued(&self) {
+        loop {
+            let path = {
+                let mut state = self.file_transfer.lock().unwrap();
+                if state.sending() {
+                    return;
+                }
+                let Some(path) = state.queue.pop_front() else {
+                    return;
+                };
+                state.preparing = true;
+                path
+            };
+            if self.hash_and_offer(path).await {
+                return;
+            }
+        }
+    }
+
+    /// With `preparing` set, hashes the file or folder at `path` and
+    /// offers it. Returns whether the offer went out.
+    async fn hash_and_offer(&self, path: PathBuf) -> bool {
+        self.file_transfer.lock().unwrap().status = format!("Hashing {}...", path.display());
+        let hashed = path.clone();
+        let offer = tokio::task::spawn_blocking(move || {
+            if hashed.is_dir() {
+                FolderManifest::for_folder(&hashed).map(Offer::Folder)
+            } else {
+                FileOffer::for_file(&hashed).map(Offer::File)
+            }
+        })
+        .await;
+        self.file_transfer.lock().unwrap().preparing = false;
+        let offer = match offer {
+            Ok(Ok(offer)) => offer,
+            Ok(Err(err)) => {
+                self.set_transfer_status(format!("Cannot send {}: {}", path.display(), err));
+                return false;
+            }
+            Err(err) => {
+                self.set_transfer_status(format!("Cannot send {}: {}", path.display(), err));
+                return false;
+            }
+        };
+        let status = format!(
+            "Offered {}; waiting for the peer to accept",
+            offer.describe()
+        );
+        self.file_transfer.lock().unwrap().outgoing = Some(Outgoing {
+            path,
+            offer: offer.clone(),
+            sent: Arc::default(),
+            sender: None,
+        });
+        if self.send_transfer_message(offer.into_message()).await {
+            self.set_transfer_status(status);
+            true
+        } else {
+            self.file_transfer.lock().unwrap().outgoing = None;
+            self.set_transfer_status("Not sent: the files channel is not open".to_owned());
+            false
+        }
+    }
+
+    async fn handle_transfer_message(&self, message: TransferMessage) {
+        match message {
+            TransferMessage::Offer(offer) => self.offered(Offer::File(offer)).await,
+            TransferMessage::OfferFolder(manifest) => self.offered(Offer::Folder(manifest)).await,
+            TransferMessage::Accept => self.start_sending().await,
+            TransferMessage::Decline => {
+                let outgoing = self.file_transfer.lock().unwrap().outgoing.take();
+                if let Some(outgoing) = outgoing {
+                    let offer = &outgoing.offer;
+                    self.file_transfer.lock().unwrap().finished(
+                        true,
+                        offer.name(),
+                        offer.size(),
+                        "declined by the peer",
+                    );
+                    self.set_transfer_status(format!("The peer declined {}", offer.name()));
+                }
+                self.offer_next_queued().await;
+            }
+            TransferMessage::Cancel => {
+                let mut state = self.file_transfer.lock().unwrap();
+                state.stop_with("cancelled by the peer");
+                state.status = "The peer cancelled the transfer".to_owned();
+            }
+            TransferMessage::Received { verified } => {
+                let outgoing = self.file_transfer.lock().unwrap().outgoing.take();
+                if let Some(outgoing) = outgoing {
+                    let offer = &outgoing.offer;
+                    let outcome = if verified {
+                        "received"
+                    } else {
+                        "reached the peer damaged"
+                    };
+                    self.file_transfer.lock().unwrap().finished(
+                        true,
+                        offer.name(),
+                        offer.size(),
+                        outcome,
+                    );
+                    self.set_transfer_status(if verified {
+                        format!("The peer received {}", offer.name())
+                    } else {
+                        format!(
+                            "{} reached the peer damaged, and was not kept",
+                            offer.name()
+                        )
+                    });
+                }
+                self.offer_next_queued().await;
+            }
+            TransferMessage::FolderReceived { damaged } => {
+                let outgoing = self.file_transfer.lock().unwrap().outgoing.take();
+                if let Some(outgoing) = outgoing {
+                    let outcome = if damaged.is_empty() {
+                        "received".to_owned()
+                    } else {
+                        format!("received, but {} arrived damaged", damaged.len())
+                    };
+                    self.file_transfer.lock().unwrap().finished(
+                        true,
+                        outgoing.offer.name(),
+                        outgoing.offer.size(),
+                        &outcome,
+                    );
+                    self.set_transfer_status(if damaged.is_empty() {
+                        format!("The peer received {}", outgoing.offer.name())
+                    } else {
+                        format!(
+                            "The peer received {}, but not {}, which arrived damaged",
+                            outgoing.offer.name(),
+                            damaged.join(", ")
+                        )
+                    });
+                }
+                self.offer_next_queued().await;
+            }
+        }
+    }
+
+    async fn offered(&self, offer: Offer) {
+        let taken = {
+            let mut state = self.file_transfer.lock().unwrap();
+            let taken = state.receiving();
+            if !taken {
+                state.status = format!("The peer offers {}", offer.describe());
+                state.offered = Some(offer);
+            }
+            taken
+        };
+        // One at a time; the peer can offer it again after.
+        if taken {
+            self.send_transfer_message(TransferMessage::Decline).await;
+        }
+    }
+
+    /// Streams the accepted file, or folder, to the peer.
+    async fn start_sending(&self) {
+        let mut state = self.file_transfer.lock().unwrap();
+        let channel = state.channel.sender();
+        let (Some(channel), Some(outgoing)) = (channel, state.outgoing.as_mut()) else {
+            return;
+        };
+        if outgoing.sender.is_some() {
+            return;
+        }
+        let path = outgoing.path.clone();
+        let sent = Arc::clone(&outgoing.sent);
+        let offer = outgoing.offer.clone();
+        let name = offer.name().to_owned();
+        let app = self.clone();
+        outgoing.sender = Some(tokio::spawn(async move {
+            let result = match &offer {
+                Offer::File(_) => file_transfer::send_file(&channel, &path, &sent).await,
+                Offer::Folder(manifest) => {
+                    file_transfer::send_folder(&channel, &path, manifest, &sent).await
+                }
+            };
+            match result {
+                Ok(()) => app.set_transfer_status(format!(
+                    "Sent {}; waiting for the peer to check it",
+                    name
+                )),
+                Err(err) => {
+                    {
+                        let mut state = app.file_transfer.lock().unwrap();
+                        state.outgoing = None;
+                        state.finished(true, &name, offer.size(), "failed");
+                    }
+                    app.set_transfer_status(format!("Sending {} failed: {}", name, err));
+                    app.send_transfer_message(TransferMessage::Cancel).await;
+                    app.offer_next_queued().await;
+                }
+            }
+        }));
+        state.status = format!("Sending {}...", outgoing.offer.name());
+    }
+
+    async fn receive_chunk(&self, chunk: &[u8]) {
+        let written = {
+            let mut state = self.file_transfer.lock().unwrap();
+            let Some(incoming) = state.incoming.as_mut() else {
+                return;
+            };
+            incoming.write(chunk)
+        };
+        match written {
+            Ok(false) => {}
+            Ok(true) => self.finish_receiving().await,
+            Err(err) => self.fail_receiving(err).await,
+        }
+    }
+
+    /// Checks the whole file against its hash, and tells the peer. A
+    /// folder's files were each checked as they arrived.
+    async fn finish_receiving(&self) {
+        let Some(incoming) = self.file_transfer.lock().unwrap().incoming.take() else {
+            return;
+        };
+        let name = incoming.name().to_owned();
+        let size = incoming.size();
+        let message = match incoming {
+            Incoming::File(file) => {
+                let finished = file.finish();
+                let verified = finished.is_ok();
+                self.file_transfer.lock().unwrap().finished(
+                    false,
+                    &name,
+                    size,
+                    if verified { "saved" } else { "arrived damaged" },
+                );
+                self.set_transfer_status(match finished {
+                    Ok(path) => format!("Saved {} to {}", name, path.display()),
+                    Err(err) => format!("{} was not kept: {}", name, err),
+                });
+                TransferMessage::Received { verified }
+            }
+            Incoming::Folder(folder) => {
+                let result = folder.finish();
+                let outcome = if result.damaged.is_empty() {
+                    "saved".to_owned()
+                } else {
+                    format!("saved, but {} arrived damaged", result.damaged.len())
+                };
+                self.file_transfer
+                    .lock()
+                    .unwrap()
+                    .finished(false, &name, size, &outcome);
+                let mut status = format!(
+                    "Saved {} files of {} to {}",
+                    result.saved,
+                    name,
+                    result.root.display()
+                );
+                if result.skipped > 0 {
+                    status += &format!(", keeping {} already there", result.skipped);
+                }
+                if !result.damaged.is_empty() {
+                    status += &format!(
+                        "; {} arrived damaged and were not kept",
+                        result.damaged.join(", ")
+                    );
+                }
+                self.set_transfer_status(status);
+                TransferMessage::FolderReceived {
+                    damaged: result.damaged,
+                }
+            }
+        };
+        self.send_transfer_message(message).await;
+    }
+
+    async fn fail_receiving(&self, err: TransferError) {
+        {
+            let mut state = self.file_transfer.lock().unwrap();
+            if let Some(incoming) = state.incoming.take() {
+                let (name, size) = (incoming.name().to_owned(), incoming.size());
+                state.finished(false, &name, size, "failed");
+                incoming.discard();
+            }
+        }
+        self.set_transfer_status(format!("Receiving failed: {}", err));
+        self.send_transfer_message(TransferMessage::Cancel).await;
+    }
+
+    /// Asks where to save the peer's offer, and takes it.
+    async fn accept_offer(&self) {
+        let (offer, conflict) = {
+            let state = self.file_transfer.lock().unwrap();
+            let Some(offer) = state.offered.clone() else {
+                return;
+            };
+            (offer, state.conflict)
+        };
+        let path = match &offer {
+            Offer::File(file) => {
+                self.save_file_dialog(FileKind::Transfers, "Save received file", &file.file_name())
+                    .await
+            }
+            Offer::Folder(_) => {
+                self.folder_dialog(FileKind::Transfers, "Save received folder in")
+                    .await
+            }
+        };
+        let Some(path) = path else {
+            return;
+        };
+        let created = match &offer {
+            Offer::File(file) => IncomingFile::create(&path, file.clone()).map(Incoming::File),
+            Offer::Folder(manifest) => {
+                IncomingFolder::create(&path, manifest.clone(), conflict).map(Incoming::Folder)
+            }
+        };
+        let incoming = match created {
+            Ok(incoming) => incoming,
+            Err(err) => {
+                return self.set_transfer_status(format!(
+                    "Cannot save to {}: {}",
+                    path.display(),
+                    err
+                ))
+            }
+        };
+        {
+            let mut state = self.file_transfer.lock().unwrap();
+            if state.offered.as_ref() != Some(&offer) {
+                // Cancelled while the dialog was up.
+                incoming.discard();
+                return;
+            }
+            state.offered = None;
+            state.incoming = Some(incoming);
+            state.status = format!("Receiving {}...", offer.name());
+        }
+        self.send_transfer_message(TransferMessage::Accept).await;
+        if offer.size() == 0 {
+            self.finish_receiving().await;
+        }
+    }
+
+    async fn decline_offer(&self) {
+        let offer = self.file_transfer.lock().unwrap().offered.take();
+        if let Some(offer) = offer {
+            self.file_transfer.lock().unwrap().finished(
+                false,
+                offer.name(),
+                offer.size(),
+                "declined",
+            );
+            self.set_transfer_status(format!("Declined {}", offer.name()));
+            self.send_transfer_message(TransferMessage::Decline).await;
+        }
+    }
+
+    async fn cancel_transfer(&self) {
+        {
+            let mut state = self.file_transfer.lock().unwrap();
+            state.stop_with("cancelled");
+            state.status = "Cancelled the transfer".to_owned();
+        }
+        self.send_transfer_message(TransferMessage::Cancel).await;
+    }
+
+    pub(crate) fn file_transfer_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.file_transfer.lock().unwrap();
+        let state = &mut *state;
+        if !state.channel.is_open() {
+            ui.label("Connect to a peer to send files.");
+            return;
+        }
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!state.sending(), egui::Button::new("Send File..."))
+                .clicked()
+            {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    let picked = app
+                        .open_file_dialog(FileKind::Transfers, "Send a file to the peer")
+                        .await;
+                    if let Some(path) = picked {
+                        app.offer_file(path).await;
+                    }
+                    ctx.request_repaint();
+                });
+            }
+            if ui
+                .add_enabled(!state.sending(), egui::Button::new("Send Folder..."))
+                .clicked()
+            {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    let picked = app
+                        .folder_dialog(FileKind::Transfers, "Send a folder to the peer")
+                        .await;
+                    if let Some(path) = picked {
+                        app.offer_file(path).await;
+                    }
+                    ctx.request_repaint();
+                });
+            }
+        });
+        if let Some(outgoing) = &state.outgoing {
+            let sent = outgoing.sent.load(Ordering::Relaxed);
+            progress_ui(ui, outgoing.offer.name(), outgoing.offer.size(), sent);
+        }
+        if !state.queue.is_empty() {
+            ui.label(format!("{} more to send after this", state.queue.len()));
+        }
+        if let Some(offer) = &state.offered {
+            ui.horizontal(|ui| {
+                ui.label(format!("Save {} from the peer?", offer.describe()));
+                let folder = matches!(offer, Offer::Folder(_));
+                if folder {
+                    egui::ComboBox::from_id_source("folder_conflict")
+                        .selected_text(state.conflict.name())
+                        .show_ui(ui, |ui| {
+                            for conflict in Conflict::ALL {
+                                ui.selectable_value(&mut state.conflict, conflict, conflict.name());
+                            }
+                        })
+                        .response
+                        .on_hover_text("If a folder of that name is already there");
+                }
+                let save = if folder { "Save In..." } else { "Save As..." };
+                if ui.button(save).clicked() {
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.accept_offer().await;
+                        ctx.request_repaint();
+                    });
+                }
+                if ui.button("Decline").clicked() {
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.decline_offer().await;
+                        ctx.request_repaint();
+                    });
+                }
+            });
+        }
+        if let Some(incoming) = &state.incoming {
+            progress_ui(ui, incoming.name(), incoming.size(), incoming.received());
+        }
+        let moving =
+            state.outgoing.as_ref().is_some_and(|o| o.sender.is_some()) || state.incoming.is_some();
+        if moving {
+            if ui.button("Cancel Transfer").clicked() {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.cancel_transfer().await;
+                    ctx.request_repaint();
+                });
+            }
+            // Progress moves in the background.
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+        if !state.status.is_empty() {
+            ui.label(&state.status);
+        }
+    }
+
+    /// Takes files dropped onto the window for the confirmation list, and
+    /// while some are dragged over it, says where they will go.
+    pub(crate) fn poll_dropped_files(&self, ctx: &egui::Context) {
+        let (hovered, dropped) = ctx.input(|i| {
+            let dropped: Vec<_> = i
+                .raw
+                .dropped_files
+                .iter()
+                .filter_map(|file| file.path.clone())
+                .collect();
+            (!i.raw.hovered_files.is_empty(), dropped)
+        });
+        let mut state = self.file_transfer.lock().unwrap();
+        if hovered {
+            let painter = ctx.layer_painter(egui::LayerId::new(
+                egui::Order::Foreground,
+                egui::Id::new("file_drop"),
+            ));
+            let screen = ctx.screen_rect();
+            painter.rect_filled(screen, 0.0, egui::Color32::from_black_alpha(160));
+            painter.text(
+                screen.center(),
+                egui::Align2::CENTER_CENTER,
+                if state.channel.is_open() {
+                    "Drop to send to the peer"
+                } else {
+                    "Connect to a peer to send files"
+                },
+                egui::FontId::proportional(24.0),
+                egui::Color32::WHITE,
+            );
+        }
+        if dropped.is_empty() {
+            return;
+        }
+        if !state.channel.is_open() {
+            state.status = "Connect to a peer to send files".to_owned();
+            return;
+        }
+        for path in dropped {
+            if !path.is_file() && !path.is_dir() {
+                state.status = format!("Not a file or folder: {}", path.display());
+            } else if !state.dropped.contains(&path) {
+                state.dropped.push(path);
+            }
+        }
+    }
+
+    /// Lists the dropped files for the user to send or clear.
+    pub(crate) fn dropped_files_window(&self, ctx: &egui::Context) {
+        let mut state = self.file_transfer.lock().unwrap();
+        if state.dropped.is_empty() {
+            return;
+        }
+        let mut send = false;
+        let mut clear = false;
+        let mut removed = None;
+        egui::Window::new("Send Files")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label("Send these files to the peer?");
+                egui::ScrollArea::vertical()
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        for (index, path) in state.dropped.iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                if ui.small_button("✖").on_hover_text("Leave out").clicked() {
+                                    removed = Some(index);
+                                }
+                                let size = match std::fs::metadata(path) {
+                                    Ok(metadata) if metadata.is_dir() => "folder".to_owned(),
+                                    Ok(metadata) => size_label(metadata.len()),
+                                    Err(_) => String::new(),
+                                };
+                                ui.label(format!("{} {}", path.display(), size));
+                            });
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    send = ui.button("Send").clicked();
+                    clear = ui.button("Cancel").clicked();
+                });
+            });
+        if let Some(index) = removed {
+            state.dropped.remove(index);
+        }
+        if clear {
+            state.dropped.clear();
+        }
+        if send {
+            let dropped = std::mem::take(&mut state.dropped);
+            state.queue.extend(dropped);
+            let app = self.clone();
+            let ctx = ctx.clone();
+            tokio::spawn(async move {
+                app.offer_next_queued().await;
+                ctx.request_repaint();
+            });
+        }
+    }
+}
+
+fn progress_ui(ui: &mut egui::Ui, name: &str, size: u64, done: u64) {
+    let fraction = if size == 0 {
+        1.0
+    } else {
+        done as f32 / size as f32
+    };
+    ui.add(egui::ProgressBar::new(fraction).text(format!(
+        "{}: {} of {}",
+        name,
+        size_label(done),
+        size_label(size)
+    )));
+}
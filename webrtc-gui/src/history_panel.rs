@@ -0,0 +1,235 @@
+//! Chat and file transfers kept between calls, under the identity of the
+//! peer they were with, and forgetting them.
+
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+use eframe::egui;
+use log::info;
+use webrtc_core::file_transfer::size_label;
+use webrtc_core::history::{History, PeerHistory, TransferRecord, RECALLED_MESSAGES};
+
+use crate::WebRTCApp;
+
+/// Transfers listed for the current peer.
+const SHOWN_TRANSFERS: usize = 20;
+
+pub struct HistoryState {
+    history: Option<History>,
+    /// Why there is no history, or the last thing that failed.
+    status: String,
+    /// The caller just admitted, until their call's peer connection is
+    /// made.
+    admitted: Option<String>,
+    /// The identity of the peer on this call, once known.
+    peer: Option<String>,
+    /// Whom the chat log was last filled from the history for.
+    recalled: Option<String>,
+    transfers: Vec<TransferRecord>,
+    peers: Vec<PeerHistory>,
+    /// Set when `transfers` and `peers` need reading again.
+    stale: bool,
+}
+
+impl HistoryState {
+    pub(crate) fn open(path: Option<PathBuf>) -> Self {
+        let (history, status) = match path {
+            Some(path) => match History::open(&path) {
+                Ok(history) => (Some(history), String::new()),
+                Err(err) => {
+                    info!("No history: {}", err);
+                    (None, format!("Cannot open {}: {}", path.display(), err))
+                }
+            },
+            None => (None, "No data folder to keep history in.".to_owned()),
+        };
+        Self {
+            history,
+            status,
+            admitted: None,
+            peer: None,
+            recalled: None,
+            transfers: Vec::new(),
+            peers: Vec::new(),
+            stale: true,
+        }
+    }
+
+    /// For a new peer connection, whose peer is the caller admitted for
+    /// it, if any.
+    pub(crate) fn reset(&mut self) {
+        self.peer = self.admitted.take();
+    }
+
+    pub(crate) fn admit(&mut self, identity: &str) {
+        self.admitted = Some(identity.to_owned());
+    }
+}
+
+impl WebRTCApp {
+    /// Keeps this call's history under `identity`, for calls we placed.
+    pub(crate) fn set_history_peer(&self, identity: &str) {
+        self.history.lock().unwrap().peer = Some(identity.to_owned());
+    }
+
+    /// Who this call's history is kept under: the peer's identity, or in a
+    /// room, its name there.
+    fn history_peer(&self) -> Option<String> {
+        let peer = self.history.lock().unwrap().peer.clone();
+        peer.or_else(|| self.room_partner_name())
+    }
+
+    /// The contact name for `identity`, when it is in the address book.
+    fn history_label(&self, identity: &str) -> String {
+        let contacts = self.contacts.lock().unwrap();
+        match contacts.book.find(identity) {
+            Some(contact) => format!("{} ({})", contact.name, identity),
+            None => identity.to_owned(),
+        }
+    }
+
+    /// Keeps new chat messages and finished transfers, and when the peer
+    /// changes, brings back what was said with them before.
+    pub(crate) fn poll_history(&self) {
+        let peer = self.history_peer();
+        let unsaved = self.chat.lock().unwrap().take_unsaved();
+        let finished = self.file_transfer.lock().unwrap().take_finished();
+        let recalled = {
+            let mut state = self.history.lock().unwrap();
+            let state = &mut *state;
+            let Some(history) = &state.history else {
+                return;
+            };
+            let Some(peer) = peer else {
+                state.recalled = None;
+                return;
+            };
+            let saved = unsaved
+                .iter()
+                .try_for_each(|entry| history.add_chat(&peer, entry))
+                .and_then(|()| {
+                    finished
+                        .iter()
+                        .try_for_each(|record| history.add_transfer(&peer, record))
+                });
+            if let Err(err) = saved {
+                info!("Failed to keep history: {}", err);
+                state.status = err.to_string();
+            }
+            let changed = state.recalled.as_ref() != Some(&peer);
+            if changed || !unsaved.is_empty() || !finished.is_empty() {
+                state.stale = true;
+            }
+            if state.stale {
+                state.stale = false;
+                state.transfers = history
+                    .transfers(&peer, SHOWN_TRANSFERS)
+                    .unwrap_or_default();
+                state.peers = history.peers().unwrap_or_default();
+            }
+            if !changed {
+                return;
+            }
+            state.recalled = Some(peer.clone());
+            history.chat(&peer, RECALLED_MESSAGES)
+        };
+        match recalled {
+            Ok(entries) => self.chat.lock().unwrap().recall(entries, true),
+            Err(err) => self.history.lock().unwrap().status = err.to_string(),
+        }
+    }
+
+    fn forget_history(&self, peer: Option<&str>) {
+        let mut state = self.history.lock().unwrap();
+        let Some(history) = &state.history else {
+            return;
+        };
+        let forgotten = history.purge(peer);
+        state.status = match forgotten {
+            Ok(()) => match peer {
+                Some(peer) => format!("Forgot everything kept for {}", peer),
+                None => "Forgot everything kept for every peer".to_owned(),
+            },
+            Err(err) => err.to_string(),
+        };
+        state.stale = true;
+    }
+
+    pub(crate) fn history_ui(&self, ui: &mut egui::Ui) {
+        let (current, transfers, peers, status) = {
+            let state = self.history.lock().unwrap();
+            if state.history.is_none() {
+                ui.label(&state.status);
+                return;
+            }
+            (
+                state.recalled.clone(),
+                state.transfers.clone(),
+                state.peers.clone(),
+                state.status.clone(),
+            )
+        };
+        match &current {
+            Some(peer) => {
+                ui.label(format!(
+                    "This call's chat and transfers are kept for {}.",
+                    self.history_label(peer)
+                ));
+                if !transfers.is_empty() {
+                    egui::Grid::new("history_transfers")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for record in &transfers {
+                                let at = UNIX_EPOCH + Duration::from_millis(record.at);
+                                ui.label(humantime::format_rfc3339_seconds(at).to_string());
+                                ui.label(if record.outgoing { "sent" } else { "from them" });
+                                ui.label(format!("{} ({})", record.name, size_label(record.size)));
+                                ui.label(&record.outcome);
+                                ui.end_row();
+                            }
+                        });
+                }
+            }
+            None => {
+                ui.weak(
+                    "Nothing is kept for this call: its peer has no identity to keep it under.",
+                );
+            }
+        }
+
+        ui.separator();
+        if peers.is_empty() {
+            ui.label("No history kept yet.");
+        }
+        let mut forget = None;
+        egui::Grid::new("history_peers")
+            .striped(true)
+            .show(ui, |ui| {
+                for peer in &peers {
+                    let at = UNIX_EPOCH + Duration::from_millis(peer.last_at);
+                    ui.label(self.history_label(&peer.peer));
+                    ui.label(format!(
+                        "{} messages, {} transfers",
+                        peer.messages, peer.transfers
+                    ));
+                    ui.label(humantime::format_rfc3339_seconds(at).to_string());
+                    if ui.button("Forget").clicked() {
+                        forget = Some(Some(peer.peer.clone()));
+                    }
+                    ui.end_row();
+                }
+            });
+        if ui
+            .add_enabled(!peers.is_empty(), egui::Button::new("Forget Everything"))
+            .clicked()
+        {
+            forget = Some(None);
+        }
+        if let Some(peer) = forget {
+            self.forget_history(peer.as_deref());
+        }
+        if !status.is_empty() {
+            ui.label(status);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Janus VideoRoom panel: join a room and watch the other publishers.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc_core::janus::videoroom::{JanusRoom, RoomEvent};
+use webrtc_core::simulcast::LayerBitrates;
+
+use crate::video_view::VideoView;
+use crate::WebRTCApp;
+
+const TILE_WIDTH: f32 = 240.0;
+
+pub struct JanusState {
+    url: String,
+    room: u64,
+    display: String,
+    joined: Option<JanusRoom>,
+    joining: bool,
+    status: String,
+    tiles: Vec<Tile>,
+    bitrates: LayerBitrates,
+}
+
+impl Default for JanusState {
+    fn default() -> Self {
+        Self {
+            url: "ws://127.0.0.1:8188".to_owned(),
+            // The demo room of the stock Janus configuration.
+            room: 1234,
+            display: "webrtc-rust-native-gui".to_owned(),
+            joined: None,
+            joining: false,
+            status: String::new(),
+            tiles: vec![],
+            bitrates: LayerBitrates::default(),
+        }
+    }
+}
+
+/// One remote publisher.
+struct Tile {
+    id: u64,
+    display: String,
+    video: Option<VideoView>,
+}
+
+impl WebRTCApp {
+    async fn janus_join(&self, ctx: egui::Context) {
+        let settings = self.settings.lock().unwrap().clone();
+        let (url, room, display) = {
+            let mut state = self.janus.lock().unwrap();
+            state.joining = true;
+            state.status = "Joining...".to_owned();
+            state.tiles.clear();
+            state.bitrates = LayerBitrates::default();
+            (
+                state.url.trim().to_owned(),
+                state.room,
+                state.display.trim().to_owned(),
+            )
+        };
+
+        let result = JanusRoom::join(&settings, &url, room, &display).await;
+        let mut state = self.janus.lock().unwrap();
+        state.joining = false;
+        match result {
+            Ok((joined, mut events)) => {
+                state.joined = Some(joined);
+                state.status = format!("In room {}", room);
+                let janus = Arc::clone(&self.janus);
+                tokio::spawn(async move {
+                    while let Some(event) = events.recv().await {
+                        let mut state = janus.lock().unwrap();
+                        match event {
+                            RoomEvent::StateChanged(connection) => {
+                                state.status = match connection {
+                                    RTCPeerConnectionState::Connected => {
+                                        format!("Publishing in room {}", room)
+                                    }
+                                    other => format!("Publisher {}", other),
+                                }
+                            }
+                            RoomEvent::PublisherJoined { id, display } => {
+                                state.tiles.retain(|tile| tile.id != id);
+                                state.tiles.push(Tile {
+                                    id,
+                                    display,
+                                    video: None,
+                                });
+                            }
+                            RoomEvent::PublisherLeft { id } => {
+                                state.tiles.retain(|tile| tile.id != id);
+                            }
+                            RoomEvent::Subscribed { id, frames, .. } => {
+                                if let Some(tile) = state.tiles.iter_mut().find(|t| t.id == id) {
+                                    let name = format!("janus_feed_{}", id);
+                                    tile.video = Some(VideoView::new(name, frames, &ctx));
+                                }
+                            }
+                            RoomEvent::Error(message) => state.status = message,
+                        }
+                        ctx.request_repaint();
+                    }
+                });
+            }
+            Err(err) => state.status = format!("Failed to join: {}", err),
+        }
+    }
+
+    async fn janus_leave(&self) {
+        let joined = {
+            let mut state = self.janus.lock().unwrap();
+            state.status = "Left the room".to_owned();
+            state.tiles.clear();
+            state.joined.take()
+        };
+        if let Some(joined) = joined {
+            joined.leave().await;
+        }
+    }
+
+    pub(crate) fn janus_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.janus.lock().unwrap();
+        let active = state.joined.is_some() || state.joining;
+        ui.add_enabled_ui(!active, |ui| {
+            egui::Grid::new("janus_settings")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Janus WebSocket URL:");
+                    ui.text_edit_singleline(&mut state.url);
+                    ui.end_row();
+                    ui.label("Room:");
+                    ui.add(egui::DragValue::new(&mut state.room));
+                    ui.end_row();
+                    ui.label("Display name:");
+                    ui.text_edit_singleline(&mut state.display);
+                    ui.end_row();
+                });
+        });
+        ui.horizontal(|ui| {
+            if !active
+                && ui
+                    .add_enabled(!state.url.trim().is_empty(), egui::Button::new("Join"))
+                    .clicked()
+            {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.janus_join(ctx.clone()).await;
+                    ctx.request_repaint();
+                });
+            }
+            if state.joined.is_some() && ui.button("Leave").clicked() {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.janus_leave().await;
+                    ctx.request_repaint();
+                });
+            }
+            if state.joining {
+                ui.spinner();
+            }
+            ui.label(&state.status);
+        });
+
+        if state.joined.is_none() {
+            return;
+        }
+        let state = &mut *state;
+        if let Some(track) = state.joined.as_ref().and_then(JanusRoom::simulcast) {
+            let stats = track.layer_stats();
+            state.bitrates.update(Instant::now(), &stats);
+            egui::CollapsingHeader::new("Simulcast layers")
+                .id_source("janus_simulcast")
+                .show(ui, |ui| {
+                    for (index, layer) in stats.iter().enumerate() {
+                        let size = match layer.layer.scale_down {
+                            1 => "full size".to_owned(),
+                            scale => format!("1/{} size", scale),
+                        };
+                        let rate = state
+                            .bitrates
+                            .kbps(index)
+                            .map_or("measuring".to_owned(), |kbps| format!("{:.0} kbps", kbps));
+                        ui.label(format!(
+                            "{}, {}: {}, {} packets",
+                            layer.layer.rid, size, rate, layer.packets_sent
+                        ));
+                    }
+                });
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
+        if state.tiles.is_empty() {
+            ui.label("Nobody else is publishing.");
+            return;
+        }
+        let columns = ((ui.available_width() / TILE_WIDTH) as usize).max(1);
+        egui::Grid::new("janus_tiles").show(ui, |ui| {
+            for (index, tile) in state.tiles.iter_mut().enumerate() {
+                ui.vertical(|ui| {
+                    match &mut tile.video {
+                        Some(video) => {
+                            video.show(ui, TILE_WIDTH);
+                        }
+                        None => {
+                            ui.spinner();
+                        }
+                    }
+                    ui.label(if tile.display.is_empty() {
+                        format!("Feed {}", tile.id)
+                    } else {
+                        tile.display.clone()
+                    });
+                });
+                if (index + 1) % columns == 0 {
+                    ui.end_row();
+                }
+            }
+        });
+    }
+}
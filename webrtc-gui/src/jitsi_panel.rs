@@ -0,0 +1,213 @@
+//! Jitsi Meet panel: drop into a Jitsi room as a guest.
+
+use std::sync::Arc;
+
+use eframe::egui;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc_core::jitsi::{ConferenceEvent, JitsiConference, Meeting};
+
+use crate::video_view::VideoView;
+use crate::WebRTCApp;
+
+const TILE_WIDTH: f32 = 240.0;
+
+pub struct JitsiState {
+    url: String,
+    /// Only needed when the XMPP domain differs from the web host.
+    xmpp_domain: String,
+    display: String,
+    conference: Option<JitsiConference>,
+    joining: bool,
+    status: String,
+    participants: Vec<Participant>,
+}
+
+impl Default for JitsiState {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            xmpp_domain: String::new(),
+            display: "webrtc-rust-native-gui".to_owned(),
+            conference: None,
+            joining: false,
+            status: String::new(),
+            participants: vec![],
+        }
+    }
+}
+
+struct Participant {
+    endpoint: String,
+    name: String,
+    video: Option<VideoView>,
+}
+
+impl JitsiState {
+    fn participant(&mut self, endpoint: &str) -> &mut Participant {
+        let index = match self
+            .participants
+            .iter()
+            .position(|p| p.endpoint == endpoint)
+        {
+            Some(index) => index,
+            None => {
+                self.participants.push(Participant {
+                    endpoint: endpoint.to_owned(),
+                    name: endpoint.to_owned(),
+                    video: None,
+                });
+                self.participants.len() - 1
+            }
+        };
+        &mut self.participants[index]
+    }
+}
+
+impl WebRTCApp {
+    async fn jitsi_join(&self, ctx: egui::Context) {
+        let settings = self.settings.lock().unwrap().clone();
+        let (meeting, display) = {
+            let mut state = self.jitsi.lock().unwrap();
+            state.participants.clear();
+            let meeting = Meeting::parse(&state.url, Some(&state.xmpp_domain));
+            if meeting.is_ok() {
+                state.joining = true;
+                state.status = "Joining...".to_owned();
+            }
+            (meeting, state.display.trim().to_owned())
+        };
+        let result = match meeting {
+            Ok(meeting) => JitsiConference::join(&settings, &meeting, &display).await,
+            Err(err) => Err(err),
+        };
+
+        let mut state = self.jitsi.lock().unwrap();
+        state.joining = false;
+        let (conference, mut events) = match result {
+            Ok(joined) => joined,
+            Err(err) => {
+                state.status = format!("Failed to join: {}", err);
+                return;
+            }
+        };
+        state.conference = Some(conference);
+
+        let jitsi = Arc::clone(&self.jitsi);
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                let mut state = jitsi.lock().unwrap();
+                match event {
+                    ConferenceEvent::Joined { room, .. } => {
+                        state.status = format!("In {}, waiting for the focus...", room)
+                    }
+                    ConferenceEvent::StateChanged(connection) => {
+                        state.status = match connection {
+                            RTCPeerConnectionState::Connected => "Connected".to_owned(),
+                            other => format!("Media {}", other),
+                        }
+                    }
+                    ConferenceEvent::ParticipantJoined { endpoint, name } => {
+                        state.participant(&endpoint).name = name;
+                    }
+                    ConferenceEvent::ParticipantLeft { endpoint } => {
+                        state.participants.retain(|p| p.endpoint != endpoint);
+                    }
+                    ConferenceEvent::VideoStarted { endpoint, frames } => {
+                        let name = format!("jitsi_{}", endpoint);
+                        state.participant(&endpoint).video =
+                            Some(VideoView::new(name, frames, &ctx));
+                    }
+                    ConferenceEvent::Disconnected(reason) => state.status = reason,
+                }
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    async fn jitsi_leave(&self) {
+        let conference = {
+            let mut state = self.jitsi.lock().unwrap();
+            state.status = "Left the meeting".to_owned();
+            state.participants.clear();
+            state.conference.take()
+        };
+        if let Some(conference) = conference {
+            conference.leave().await;
+        }
+    }
+
+    pub(crate) fn jitsi_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.jitsi.lock().unwrap();
+        let active = state.conference.is_some() || state.joining;
+        ui.add_enabled_ui(!active, |ui| {
+            egui::Grid::new("jitsi_settings")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Meeting URL:");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut state.url)
+                            .hint_text("https://meet.example.com/MyRoom"),
+                    );
+                    ui.end_row();
+                    ui.label("XMPP domain:");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut state.xmpp_domain)
+                            .hint_text("same as the web host"),
+                    );
+                    ui.end_row();
+                    ui.label("Display name:");
+                    ui.text_edit_singleline(&mut state.display);
+                    ui.end_row();
+                });
+        });
+        ui.horizontal(|ui| {
+            if !active
+                && ui
+                    .add_enabled(!state.url.trim().is_empty(), egui::Button::new("Join"))
+                    .clicked()
+            {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.jitsi_join(ctx.clone()).await;
+                    ctx.request_repaint();
+                });
+            }
+            if state.conference.is_some() && ui.button("Leave").clicked() {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.jitsi_leave().await;
+                    ctx.request_repaint();
+                });
+            }
+            if state.joining {
+                ui.spinner();
+            }
+            ui.label(&state.status);
+        });
+
+        if state.conference.is_none() {
+            return;
+        }
+        if state.participants.is_empty() {
+            ui.label("Nobody else is in the meeting.");
+            return;
+        }
+        ui.horizontal_wrapped(|ui| {
+            for participant in &mut state.participants {
+                ui.vertical(|ui| {
+                    match &mut participant.video {
+                        Some(video) => {
+                            video.show(ui, TILE_WIDTH);
+                        }
+                        None => {
+                            ui.label("No video");
+                        }
+                    }
+                    ui.label(&participant.name);
+                });
+            }
+        });
+    }
+}
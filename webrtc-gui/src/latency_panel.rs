@@ -0,0 +1,95 @@
+//! Pings over the control channel, and a plot of how long they take to
+//! come back.
+
+use std::sync::{Arc, Weak};
+use std::time::Instant;
+
+use eframe::egui;
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+use webrtc::data_channel::RTCDataChannel;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc_core::control::ControlMessage;
+use webrtc_core::latency::PING_INTERVAL;
+
+use crate::WebRTCApp;
+
+impl WebRTCApp {
+    /// Pings the peer while `channel` is open. Runs until the peer
+    /// connection or channel is replaced.
+    pub(crate) fn spawn_pings(&self, pc: Weak<RTCPeerConnection>, channel: Arc<RTCDataChannel>) {
+        let app = self.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(PING_INTERVAL);
+            loop {
+                ticks.tick().await;
+                if pc.upgrade().is_none() {
+                    return;
+                }
+                let seq = {
+                    let mut state = app.control.lock().unwrap();
+                    let current = state
+                        .channel
+                        .as_ref()
+                        .is_some_and(|current| Arc::ptr_eq(current, &channel));
+                    if !current {
+                        return;
+                    }
+                    if !state.open {
+                        continue;
+                    }
+                    state.latency.ping(Instant::now())
+                };
+                app.send_control(ControlMessage::Ping { seq }).await;
+            }
+        });
+    }
+
+    pub(crate) fn latency_ui(&self, ui: &mut egui::Ui) {
+        let ice_rtt = self
+            .stats
+            .lock()
+            .unwrap()
+            .samples()
+            .next_back()
+            .and_then(|sample| sample.rtt_ms);
+        let state = self.control.lock().unwrap();
+        let Some(summary) = state.latency.summary() else {
+            ui.label(if state.open {
+                "Waiting for the peer to answer a ping..."
+            } else {
+                "Pings go over the control channel once it is open."
+            });
+            return;
+        };
+        ui.label(format!(
+            "Data channel round trip {:.0} ms (min {:.0}, mean {:.0}, max {:.0}), jitter {:.1} ms",
+            summary.last_ms, summary.min_ms, summary.mean_ms, summary.max_ms, summary.jitter_ms
+        ));
+        ui.horizontal(|ui| {
+            ui.label(format!("{} of {} pings lost", summary.lost, summary.sent));
+            if let Some(rtt) = ice_rtt {
+                ui.weak(format!("ICE round trip {:.0} ms", rtt));
+            }
+        });
+
+        let rtt: PlotPoints = state
+            .latency
+            .samples()
+            .map(|s| [s.time, s.rtt_ms])
+            .collect();
+        let jitter: PlotPoints = state
+            .latency
+            .samples()
+            .map(|s| [s.time, s.jitter_ms])
+            .collect();
+        Plot::new("latency")
+            .height(150.0)
+            .legend(Legend::default())
+            .x_axis_label("seconds")
+            .include_y(0.0)
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(rtt).name("Round trip (ms)"));
+                plot_ui.line(Line::new(jitter).name("Jitter (ms)"));
+            });
+    }
+}
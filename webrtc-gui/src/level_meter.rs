@@ -0,0 +1,40 @@
+//! A VU meter for a microphone or a remote audio track.
+
+use eframe::egui;
+use webrtc_core::audio::AudioLevel;
+
+const METER_SIZE: egui::Vec2 = egui::vec2(160.0, 10.0);
+
+/// How often a meter redraws while it is moving.
+const REFRESH: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Draws `level` as a bar for the average, with a tick at the peak.
+pub(crate) fn level_meter_ui(ui: &mut egui::Ui, label: &str, level: &AudioLevel) {
+    ui.horizontal(|ui| {
+        let (rect, response) = ui.allocate_exact_size(METER_SIZE, egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        let visuals = ui.visuals();
+        painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+        let rms = AudioLevel::fraction(level.rms_dbfs);
+        let color = if level.peak_dbfs > -1.0 {
+            egui::Color32::RED
+        } else if level.rms_dbfs > -12.0 {
+            egui::Color32::YELLOW
+        } else {
+            egui::Color32::GREEN
+        };
+        let mut filled = rect;
+        filled.set_width(rect.width() * rms);
+        painter.rect_filled(filled, 2.0, color);
+        if !level.is_silent() {
+            let x = rect.left() + rect.width() * AudioLevel::fraction(level.peak_dbfs);
+            painter.vline(x, rect.y_range(), visuals.widgets.active.fg_stroke);
+        }
+        response.on_hover_text(format!(
+            "{:.0} dBFS average, {:.0} dBFS peak",
+            level.rms_dbfs, level.peak_dbfs
+        ));
+        ui.label(label);
+    });
+    ui.ctx().request_repaint_after(REFRESH);
+}
@@ -0,0 +1,212 @@
+//! LiveKit panel: join a LiveKit room with an access token and watch its
+//! participants.
+
+use std::sync::Arc;
+
+use eframe::egui;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+use webrtc_core::livekit::{LiveKitRoom, RoomEvent};
+use webrtc_core::stats::StatsTimeline;
+
+use crate::video_view::VideoView;
+use crate::WebRTCApp;
+
+const TILE_WIDTH: f32 = 240.0;
+
+#[derive(Default)]
+pub struct LiveKitState {
+    url: String,
+    token: String,
+    room: Option<LiveKitRoom>,
+    joining: bool,
+    status: String,
+    participants: Vec<Participant>,
+}
+
+struct Participant {
+    sid: String,
+    name: String,
+    tracks: Vec<Track>,
+}
+
+struct Track {
+    sid: String,
+    description: String,
+    video: Option<VideoView>,
+}
+
+impl LiveKitState {
+    fn participant(&mut self, sid: &str) -> &mut Participant {
+        let index = match self.participants.iter().position(|p| p.sid == sid) {
+            Some(index) => index,
+            None => {
+                self.participants.push(Participant {
+                    sid: sid.to_owned(),
+                    name: sid.to_owned(),
+                    tracks: vec![],
+                });
+                self.participants.len() - 1
+            }
+        };
+        &mut self.participants[index]
+    }
+}
+
+impl WebRTCApp {
+    async fn livekit_join(&self, ctx: egui::Context) {
+        let settings = self.settings.lock().unwrap().clone();
+        let (url, token) = {
+            let mut state = self.livekit.lock().unwrap();
+            state.joining = true;
+            state.status = "Connecting...".to_owned();
+            state.participants.clear();
+            (state.url.trim().to_owned(), state.token.trim().to_owned())
+        };
+
+        let result = LiveKitRoom::join(&settings, &url, &token).await;
+        let mut state = self.livekit.lock().unwrap();
+        state.joining = false;
+        let (room, mut events) = match result {
+            Ok(joined) => joined,
+            Err(err) => {
+                state.status = format!("Failed to join: {}", err);
+                return;
+            }
+        };
+        *self.stats.lock().unwrap() = StatsTimeline::new();
+        self.spawn_stats_sampler(room.subscriber(), settings.stats_interval(), None);
+        state.room = Some(room);
+
+        let livekit = Arc::clone(&self.livekit);
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                let mut state = livekit.lock().unwrap();
+                match event {
+                    RoomEvent::Joined { room, identity } => {
+                        state.status = format!("In room {} as {}", room, identity)
+                    }
+                    RoomEvent::StateChanged(connection) => {
+                        if connection != RTCPeerConnectionState::Connected {
+                            state.status = format!("Media {}", connection);
+                        }
+                    }
+                    RoomEvent::ParticipantJoined { sid, name } => {
+                        state.participant(&sid).name = name;
+                    }
+                    RoomEvent::ParticipantLeft { sid } => {
+                        state.participants.retain(|p| p.sid != sid);
+                    }
+                    RoomEvent::TrackSubscribed {
+                        participant_sid,
+                        track_sid,
+                        kind,
+                        codec,
+                        frames,
+                    } => {
+                        let description = match (kind, &frames) {
+                            (RTPCodecType::Video, None) => {
+                                format!("{} {} (no decoder in this build)", kind, codec)
+                            }
+                            (RTPCodecType::Audio, _) => {
+                                format!("{} {} (playback not supported yet)", kind, codec)
+                            }
+                            _ => format!("{} {}", kind, codec),
+                        };
+                        let name = format!("livekit_{}", track_sid);
+                        let video = frames.map(|frames| VideoView::new(name, frames, &ctx));
+                        state.participant(&participant_sid).tracks.push(Track {
+                            sid: track_sid,
+                            description,
+                            video,
+                        });
+                    }
+                    RoomEvent::TrackUnpublished { track_sid } => {
+                        for participant in &mut state.participants {
+                            participant.tracks.retain(|t| t.sid != track_sid);
+                        }
+                    }
+                    RoomEvent::Disconnected(reason) => state.status = reason,
+                }
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    async fn livekit_leave(&self) {
+        let room = {
+            let mut state = self.livekit.lock().unwrap();
+            state.status = "Left the room".to_owned();
+            state.participants.clear();
+            state.room.take()
+        };
+        if let Some(room) = room {
+            room.leave().await;
+        }
+    }
+
+    pub(crate) fn livekit_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.livekit.lock().unwrap();
+        let active = state.room.is_some() || state.joining;
+        ui.add_enabled_ui(!active, |ui| {
+            egui::Grid::new("livekit_settings")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("LiveKit server URL:");
+                    ui.text_edit_singleline(&mut state.url);
+                    ui.end_row();
+                    ui.label("Access token:");
+                    ui.add(egui::TextEdit::singleline(&mut state.token).password(true));
+                    ui.end_row();
+                });
+        });
+        ui.horizontal(|ui| {
+            let ready = !state.url.trim().is_empty() && !state.token.trim().is_empty();
+            if !active && ui.add_enabled(ready, egui::Button::new("Join")).clicked() {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.livekit_join(ctx.clone()).await;
+                    ctx.request_repaint();
+                });
+            }
+            if state.room.is_some() && ui.button("Leave").clicked() {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.livekit_leave().await;
+                    ctx.request_repaint();
+                });
+            }
+            if state.joining {
+                ui.spinner();
+            }
+            ui.label(&state.status);
+        });
+
+        if state.room.is_none() {
+            return;
+        }
+        if state.participants.is_empty() {
+            ui.label("Nobody else is in the room.");
+            return;
+        }
+        for participant in &mut state.participants {
+            ui.separator();
+            ui.strong(&participant.name);
+            if participant.tracks.is_empty() {
+                ui.label("No published tracks");
+            }
+            ui.horizontal_wrapped(|ui| {
+                for track in &mut participant.tracks {
+                    ui.vertical(|ui| {
+                        if let Some(video) = &mut track.video {
+                            video.show(ui, TILE_WIDTH);
+                        }
+                        ui.label(&track.description);
+                    });
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! Login to secured signaling servers, and keeping the token fresh while
+//! logged in.
+
+use std::time::{Duration, SystemTime};
+
+use eframe::egui;
+use log::info;
+use tokio::task::JoinHandle;
+use webrtc_core::auth::{self, Token};
+use webrtc_core::backoff::Backoff;
+
+use crate::WebRTCApp;
+
+/// The soonest the refresher runs again, even for tokens that say they are
+/// already due.
+const MIN_REFRESH_WAIT: Duration = Duration::from_secs(1);
+
+/// How a token refresh went.
+enum Refresh {
+    Renewed,
+    /// The token endpoint could not be reached or failed; the token we
+    /// have is still good for now.
+    Failed,
+    LoggedOut,
+}
+
+#[derive(Default)]
+pub struct LoginState {
+    token: Option<Token>,
+    /// Who the token is for.
+    user: String,
+    password: String,
+    open: bool,
+    busy: bool,
+    refresher: Option<JoinHandle<()>>,
+    status: String,
+}
+
+impl LoginState {
+    fn log_out(&mut self, status: impl Into<String>) {
+        if let Some(refresher) = self.refresher.take() {
+            refresher.abort();
+        }
+        self.token = None;
+        self.status = status.into();
+    }
+}
+
+impl WebRTCApp {
+    async fn log_in(&self, ctx: egui::Context) {
+        let (settings, password) = {
+            let mut state = self.login.lock().unwrap();
+            state.busy = true;
+            state.status = "Logging in...".to_owned();
+            (
+                self.settings.lock().unwrap().network.auth.clone(),
+                std::mem::take(&mut state.password),
+            )
+        };
+        let result = auth::login(&settings, settings.username.trim(), &password).await;
+        let mut state = self.login.lock().unwrap();
+        state.busy = false;
+        match result {
+            Ok(token) => {
+                state.log_out("");
+                state.token = Some(token);
+                state.user = settings.username.trim().to_owned();
+                state.open = false;
+                state.refresher = Some(self.spawn_token_refresh(ctx));
+            }
+            Err(err) => state.status = format!("Login failed: {}", err),
+        }
+    }
+
+    /// Renews the token shortly before it expires, for as long as we stay
+    /// logged in, backing off while the token endpoint is failing.
+    fn spawn_token_refresh(&self, ctx: egui::Context) -> JoinHandle<()> {
+        let app = self.clone();
+        tokio::spawn(async move {
+            let mut retry = Backoff::new(Duration::from_secs(2), Duration::from_secs(60));
+            let mut delay = MIN_REFRESH_WAIT;
+            loop {
+                let refresh_at = app
+                    .login
+                    .lock()
+                    .unwrap()
+                    .token
+                    .as_ref()
+                    .and_then(Token::refresh_at);
+                let Some(refresh_at) = refresh_at else {
+                    return;
+                };
+                let wait = refresh_at
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default();
+                tokio::time::sleep(wait.max(delay)).await;
+                match app.refresh_token().await {
+                    Refresh::Renewed => {
+                        retry.reset();
+                        delay = MIN_REFRESH_WAIT;
+                    }
+                    Refresh::Failed => delay = retry.next_delay(),
+                    Refresh::LoggedOut => {
+                        ctx.request_repaint();
+                        return;
+                    }
+                }
+            }
+        })
+    }
+
+    /// Swaps in a renewed token. A failure that may pass keeps the token
+    /// until it expires; any other logs out and asks to log in again.
+    async fn refresh_token(&self) -> Refresh {
+        let settings = self.settings.lock().unwrap().network.auth.clone();
+        let Some(token) = self.login.lock().unwrap().token.clone() else {
+            return Refresh::LoggedOut;
+        };
+        match auth::refresh(&settings, &token).await {
+            Ok(token) => {
+                info!("Refreshed signaling access token");
+                self.set_room_token(&token.access_token);
+                let mut state = self.login.lock().unwrap();
+                state.token = Some(token);
+                state.status.clear();
+                Refresh::Renewed
+            }
+            Err(err)
+                if err.is_transient()
+                    && token.expires_at.is_none_or(|at| SystemTime::now() < at) =>
+            {
+                info!("Failed to refresh signaling access token: {}", err);
+                self.login.lock().unwrap().status =
+                    format!("Could not renew the session, retrying: {}", err);
+                Refresh::Failed
+            }
+            Err(err) => {
+                let mut state = self.login.lock().unwrap();
+                // The refresher ends on its own once the token is gone.
+                state.refresher = None;
+                state.log_out(format!("Session expired: {}", err));
+                state.open = true;
+                Refresh::LoggedOut
+            }
+        }
+    }
+
+    /// The bearer token for signaling requests, refreshed first if it is
+    /// about to expire. `None` when no login is configured or we are
+    /// logged out.
+    pub(crate) async fn access_token(&self) -> Option<String> {
+        if !self.settings.lock().unwrap().network.auth.enabled() {
+            return None;
+        }
+        let needs_refresh = self
+            .login
+            .lock()
+            .unwrap()
+            .token
+            .as_ref()?
+            .needs_refresh(SystemTime::now());
+        // The timer is late after a suspend.
+        if needs_refresh {
+            if let Refresh::LoggedOut = self.refresh_token().await {
+                return None;
+            }
+        }
+        let state = self.login.lock().unwrap();
+        state.token.as_ref().map(|token| token.access_token.clone())
+    }
+
+    /// Opens the login dialog, saying why.
+    pub(crate) fn request_login(&self, reason: impl Into<String>) {
+        let mut state = self.login.lock().unwrap();
+        state.open = true;
+        state.status = reason.into();
+    }
+
+    /// Login state next to the Settings button.
+    pub(crate) fn login_button_ui(&self, ui: &mut egui::Ui) {
+        if !self.settings.lock().unwrap().network.auth.enabled() {
+            return;
+        }
+        let mut state = self.login.lock().unwrap();
+        if state.token.is_some() {
+            ui.label(format!("Logged in as {}", state.user));
+            if ui.button("Log out").clicked() {
+                state.log_out("Logged out");
+            }
+        } else if ui.button("Log in").clicked() {
+            state.open = !state.open;
+        }
+    }
+
+    pub(crate) fn login_window(&self, ctx: &egui::Context) {
+        let mut state = self.login.lock().unwrap();
+        let mut open = state.open;
+        let mut submit = false;
+        egui::Window::new("Log in")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let mut settings = self.settings.lock().unwrap();
+                egui::Grid::new("login").num_columns(2).show(ui, |ui| {
+                    ui.label("Username:");
+                    ui.text_edit_singleline(&mut settings.network.auth.username);
+                    ui.end_row();
+                    ui.label("Password:");
+                    let password =
+                        ui.add(egui::TextEdit::singleline(&mut state.password).password(true));
+                    submit = password.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(!state.busy, egui::Button::new("Log in"))
+                        .clicked()
+                    {
+                        submit = true;
+                    }
+                    ui.label(&state.status);
+                });
+            });
+        state.open &= open;
+        if submit && !state.busy {
+            drop(state);
+            let app = self.clone();
+            let ctx = ctx.clone();
+            tokio::spawn(async move {
+                app.log_in(ctx.clone()).await;
+                ctx.request_repaint();
+            });
+        }
+    }
+}
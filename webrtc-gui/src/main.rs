@@ -0,0 +1,950 @@
+mod camera_panel;
+mod captions_panel;
+mod chat_panel;
+mod clipboard_prompt;
+mod clipboard_sync_panel;
+mod contacts_panel;
+mod control_channel;
+mod data_channels_panel;
+mod devices_panel;
+mod file_dialogs;
+mod file_signaling_panel;
+mod file_transfer_panel;
+mod history_panel;
+mod janus_panel;
+mod jitsi_panel;
+mod latency_panel;
+mod level_meter;
+mod livekit_panel;
+mod login;
+mod media_file_panel;
+mod microphone;
+mod negotiated_channel;
+mod nostr_panel;
+mod p2p_panel;
+mod power_saving;
+mod reconnect;
+mod remote_candidates;
+mod remote_control_panel;
+mod remote_video_panel;
+mod renegotiation_prompt;
+mod room_panel;
+mod rpc_panel;
+mod security_panel;
+mod settings_window;
+mod sip_panel;
+mod stats_panel;
+mod support_logs;
+mod test_panel;
+mod timeline_panel;
+mod turn_credentials;
+mod video_view;
+mod whep_panel;
+mod window_share_panel;
+
+use camera_panel::CameraState;
+use captions_panel::CaptionsState;
+use chat_panel::ChatState;
+use clipboard_prompt::ClipboardPrompt;
+use clipboard_sync_panel::ClipboardSyncState;
+use contacts_panel::ContactsState;
+use control_channel::ControlState;
+use data_channels_panel::DataChannelsState;
+use devices_panel::DeviceState;
+use eframe::egui;
+use file_signaling_panel::FileSignalingState;
+use file_transfer_panel::FileTransferState;
+use history_panel::HistoryState;
+use janus_panel::JanusState;
+use jitsi_panel::JitsiState;
+use livekit_panel::LiveKitState;
+use log::{info, warn};
+use login::LoginState;
+use media_file_panel::MediaFileState;
+use microphone::MicrophoneState;
+use nostr_panel::NostrState;
+use p2p_panel::P2pState;
+use power_saving::PowerSavingState;
+use reconnect::ReconnectState;
+use remote_candidates::RemoteCandidates;
+use remote_control_panel::RemoteControlState;
+use remote_video_panel::RemoteVideoState;
+use renegotiation_prompt::RenegotiationPrompt;
+use room_panel::RoomState;
+use rpc_panel::RpcState;
+use settings_window::SettingsWindow;
+use sip_panel::SipState;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+use support_logs::SupportLogsState;
+use test_panel::EchoTestState;
+use tokio::sync::mpsc;
+use webrtc::{
+    ice_transport::{
+        ice_candidate::RTCIceCandidateInit, ice_gathering_state::RTCIceGatheringState,
+    },
+    peer_connection::{
+        peer_connection_state::RTCPeerConnectionState,
+        sdp::session_description::RTCSessionDescription, signaling_state::RTCSignalingState,
+        RTCPeerConnection,
+    },
+    rtp_transceiver::{
+        rtp_codec::RTPCodecType, rtp_transceiver_direction::RTCRtpTransceiverDirection,
+        RTCRtpTransceiverInit,
+    },
+};
+use webrtc_core::{
+    audit::AuditLog,
+    bounded::{BoundedBuffer, DropPolicy},
+    codecs::{AudioCodecs, CodecOverride, VideoCodecs},
+    log_stream,
+    settings::{FileKind, Settings},
+    snapshot::Snapshot,
+    stats::{MarkerKind, StatsTimeline},
+};
+use whep_panel::WhepState;
+use window_share_panel::WindowShareState;
+
+const APP_NAME: &str = "WebRTC Client";
+const SETTINGS_KEY: &str = "settings";
+const CONTACTS_KEY: &str = "contacts";
+
+/// Where files we write live, next to the persisted settings.
+fn data_dir() -> Option<std::path::PathBuf> {
+    let dir = eframe::storage_dir(APP_NAME)?;
+    std::fs::create_dir_all(&dir).ok()?;
+    Some(dir)
+}
+
+fn audit_log_path() -> Option<std::path::PathBuf> {
+    Some(data_dir()?.join("audit.log"))
+}
+
+fn history_path() -> Option<std::path::PathBuf> {
+    Some(data_dir()?.join("history.sqlite3"))
+}
+/// Local ICE candidates kept for the answer. Host candidates come first,
+/// so once full the later relay and reflexive ones are the ones not kept.
+const MAX_CANDIDATES: usize = 64;
+const MAX_CANDIDATE_BYTES: usize = 32 * 1024;
+
+/// Hover text on the manual negotiation buttons while in a room.
+const AUTOMATIC: &str = "The room negotiates automatically";
+
+#[tokio::main]
+async fn main() {
+    log_stream::init();
+    let options = eframe::NativeOptions::default();
+    eframe::run_native(
+        APP_NAME,
+        options,
+        Box::new(|cc| Box::new(WebRTCApp::new(cc))),
+    )
+    .unwrap();
+}
+
+struct WebRTCApp {
+    peer_connection: Arc<tokio::sync::Mutex<Option<Arc<RTCPeerConnection>>>>,
+    local_sdp: Snapshot<String>,
+    remote_sdp: Snapshot<String>,
+    ice_candidates: Arc<tokio::sync::Mutex<BoundedBuffer<RTCIceCandidateInit>>>,
+    remote_candidates: Arc<Mutex<RemoteCandidates>>,
+    tx: mpsc::Sender<String>,
+    rx: Arc<tokio::sync::Mutex<mpsc::Receiver<String>>>,
+    sip: Arc<Mutex<SipState>>,
+    stats: Arc<Mutex<StatsTimeline>>,
+    room: Arc<Mutex<RoomState>>,
+    settings: Arc<Mutex<Settings>>,
+    settings_window: Arc<Mutex<SettingsWindow>>,
+    echo_test: Arc<Mutex<EchoTestState>>,
+    clipboard_prompt: Arc<Mutex<ClipboardPrompt>>,
+    clipboard_sync: Arc<Mutex<ClipboardSyncState>>,
+    reconnect: Arc<Mutex<ReconnectState>>,
+    control: Arc<Mutex<ControlState>>,
+    renegotiation: Arc<Mutex<RenegotiationPrompt>>,
+    devices: Arc<Mutex<DeviceState>>,
+    whep: Arc<Mutex<WhepState>>,
+    janus: Arc<Mutex<JanusState>>,
+    jitsi: Arc<Mutex<JitsiState>>,
+    livekit: Arc<Mutex<LiveKitState>>,
+    nostr: Arc<Mutex<NostrState>>,
+    p2p: Arc<Mutex<P2pState>>,
+    file_signaling: Arc<Mutex<FileSignalingState>>,
+    remote_video: Arc<Mutex<RemoteVideoState>>,
+    remote_control: Arc<Mutex<RemoteControlState>>,
+    rpc: Arc<Mutex<RpcState>>,
+    captions: Arc<Mutex<CaptionsState>>,
+    audit: Arc<Mutex<AuditLog>>,
+    contacts: Arc<Mutex<ContactsState>>,
+    login: Arc<Mutex<LoginState>>,
+    support_logs: Arc<Mutex<SupportLogsState>>,
+    camera: Arc<Mutex<CameraState>>,
+    microphone: Arc<Mutex<MicrophoneState>>,
+    window_share: Arc<Mutex<WindowShareState>>,
+    media_file: Arc<Mutex<MediaFileState>>,
+    power_saving: Arc<Mutex<PowerSavingState>>,
+    chat: Arc<Mutex<ChatState>>,
+    file_transfer: Arc<Mutex<FileTransferState>>,
+    history: Arc<Mutex<HistoryState>>,
+    data_channels: Arc<Mutex<DataChannelsState>>,
+    /// Codecs for the next peer connection only; never saved.
+    codecs: Arc<Mutex<CodecOverride>>,
+}
+
+impl WebRTCApp {
+    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let settings: Settings = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, SETTINGS_KEY))
+            .unwrap_or_default();
+        let contacts = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, CONTACTS_KEY))
+            .unwrap_or_default();
+        let devices = DeviceState::load(&settings.devices);
+        let file_signaling =
+            FileSignalingState::new(settings.files.folder(FileKind::SignalingFolder));
+        let remote_video = RemoteVideoState::new(&cc.egui_ctx, settings.thumbnail_interval());
+        let (tx, rx) = mpsc::channel(32);
+        let local_sdp = Snapshot::default();
+        let remote_sdp = Snapshot::default();
+        // Background tasks publish SDP without touching the UI; redraw when
+        // they do.
+        for sdp in [&local_sdp, &remote_sdp] {
+            let mut updates = sdp.subscribe();
+            let ctx = cc.egui_ctx.clone();
+            tokio::spawn(async move {
+                while updates.changed().await.is_ok() {
+                    ctx.request_repaint();
+                }
+            });
+        }
+        let app = Self {
+            peer_connection: Arc::new(tokio::sync::Mutex::new(None)),
+            local_sdp,
+            remote_sdp,
+            ice_candidates: Arc::new(tokio::sync::Mutex::new(BoundedBuffer::new(
+                MAX_CANDIDATES,
+                MAX_CANDIDATE_BYTES,
+                DropPolicy::DropNewest,
+            ))),
+            remote_candidates: Arc::new(Mutex::new(RemoteCandidates::default())),
+            tx,
+            rx: Arc::new(tokio::sync::Mutex::new(rx)),
+            sip: Arc::new(Mutex::new(SipState::default())),
+            stats: Arc::new(Mutex::new(StatsTimeline::new())),
+            room: Arc::new(Mutex::new(RoomState::default())),
+            settings: Arc::new(Mutex::new(settings)),
+            settings_window: Arc::new(Mutex::new(SettingsWindow::default())),
+            echo_test: Arc::new(Mutex::new(EchoTestState::default())),
+            clipboard_prompt: Arc::new(Mutex::new(ClipboardPrompt::default())),
+            clipboard_sync: Arc::new(Mutex::new(ClipboardSyncState::new(&cc.egui_ctx))),
+            reconnect: Arc::new(Mutex::new(ReconnectState::default())),
+            control: Arc::new(Mutex::new(ControlState::default())),
+            renegotiation: Arc::new(Mutex::new(RenegotiationPrompt::default())),
+            devices: Arc::new(Mutex::new(devices)),
+            whep: Arc::new(Mutex::new(WhepState::default())),
+            janus: Arc::new(Mutex::new(JanusState::default())),
+            jitsi: Arc::new(Mutex::new(JitsiState::default())),
+            livekit: Arc::new(Mutex::new(LiveKitState::default())),
+            nostr: Arc::new(Mutex::new(NostrState::default())),
+            p2p: Arc::new(Mutex::new(P2pState::default())),
+            file_signaling: Arc::new(Mutex::new(file_signaling)),
+            remote_video: Arc::new(Mutex::new(remote_video)),
+            remote_control: Arc::new(Mutex::new(RemoteControlState::new(&cc.egui_ctx))),
+            rpc: Arc::new(Mutex::new(RpcState::new(&cc.egui_ctx))),
+            captions: Arc::new(Mutex::new(CaptionsState::new(&cc.egui_ctx))),
+            audit: Arc::new(Mutex::new(AuditLog::open(audit_log_path()))),
+            contacts: Arc::new(Mutex::new(ContactsState::new(contacts))),
+            login: Arc::new(Mutex::new(LoginState::default())),
+            support_logs: Arc::new(Mutex::new(SupportLogsState::default())),
+            camera: Arc::new(Mutex::new(CameraState::new(&cc.egui_ctx))),
+            microphone: Arc::new(Mutex::new(MicrophoneState::default())),
+            window_share: Arc::new(Mutex::new(WindowShareState::new(&cc.egui_ctx))),
+            media_file: Arc::new(Mutex::new(MediaFileState::default())),
+            power_saving: Arc::new(Mutex::new(PowerSavingState::default())),
+            chat: Arc::new(Mutex::new(ChatState::new(&cc.egui_ctx))),
+            file_transfer: Arc::new(Mutex::new(FileTransferState::default())),
+            history: Arc::new(Mutex::new(HistoryState::open(history_path()))),
+            data_channels: Arc::new(Mutex::new(DataChannelsState::default())),
+            codecs: Arc::new(Mutex::new(CodecOverride::default())),
+        };
+        app.spawn_turn_refresh();
+        app
+    }
+}
+
+impl Clone for WebRTCApp {
+    fn clone(&self) -> Self {
+        Self {
+            peer_connection: Arc::clone(&self.peer_connection),
+            local_sdp: self.local_sdp.clone(),
+            remote_sdp: self.remote_sdp.clone(),
+            ice_candidates: Arc::clone(&self.ice_candidates),
+            remote_candidates: Arc::clone(&self.remote_candidates),
+            tx: self.tx.clone(),
+            rx: Arc::clone(&self.rx),
+            sip: Arc::clone(&self.sip),
+            stats: Arc::clone(&self.stats),
+            room: Arc::clone(&self.room),
+            settings: Arc::clone(&self.settings),
+            settings_window: Arc::clone(&self.settings_window),
+            echo_test: Arc::clone(&self.echo_test),
+            clipboard_prompt: Arc::clone(&self.clipboard_prompt),
+            clipboard_sync: Arc::clone(&self.clipboard_sync),
+            reconnect: Arc::clone(&self.reconnect),
+            control: Arc::clone(&self.control),
+            renegotiation: Arc::clone(&self.renegotiation),
+            devices: Arc::clone(&self.devices),
+            whep: Arc::clone(&self.whep),
+            janus: Arc::clone(&self.janus),
+            jitsi: Arc::clone(&self.jitsi),
+            livekit: Arc::clone(&self.livekit),
+            nostr: Arc::clone(&self.nostr),
+            p2p: Arc::clone(&self.p2p),
+            file_signaling: Arc::clone(&self.file_signaling),
+            remote_video: Arc::clone(&self.remote_video),
+            remote_control: Arc::clone(&self.remote_control),
+            rpc: Arc::clone(&self.rpc),
+            captions: Arc::clone(&self.captions),
+            audit: Arc::clone(&self.audit),
+            contacts: Arc::clone(&self.contacts),
+            login: Arc::clone(&self.login),
+            support_logs: Arc::clone(&self.support_logs),
+            camera: Arc::clone(&self.camera),
+            microphone: Arc::clone(&self.microphone),
+            window_share: Arc::clone(&self.window_share),
+            media_file: Arc::clone(&self.media_file),
+            power_saving: Arc::clone(&self.power_saving),
+            chat: Arc::clone(&self.chat),
+            file_transfer: Arc::clone(&self.file_transfer),
+            history: Arc::clone(&self.history),
+            data_channels: Arc::clone(&self.data_channels),
+            codecs: Arc::clone(&self.codecs),
+        }
+    }
+}
+
+impl WebRTCApp {
+    async fn gather_ice_candidates(&self) {
+        let pc = self.peer_connection.lock().await.clone();
+        if let Some(pc) = pc {
+            let mut gather_complete = false;
+            while !gather_complete {
+                let state = pc.ice_gathering_state();
+                match state {
+                    RTCIceGatheringState::Complete => {
+                        gather_complete = true;
+                    }
+                    _ => tokio::time::sleep(tokio::time::Duration::from_millis(100)).await,
+                }
+            }
+        }
+    }
+
+    async fn create_answer(&self) -> RTCSessionDescription {
+        let pc = self.peer_connection.lock().await.clone();
+        if let Some(pc) = pc {
+            info!("Creating answer...");
+            self.follow_remote_video_codec(&pc).await;
+            match pc.create_answer(None).await {
+                Ok(answer) => {
+                    pc.set_local_description(answer.clone()).await.unwrap();
+                    self.gather_ice_candidates().await;
+
+                    if let Some(local_desc) = pc.local_description().await {
+                        info!("Answer created with SDP: {:?}", local_desc);
+                        self.local_sdp.set(local_desc.sdp.clone());
+                        return local_desc.clone();
+                    }
+                }
+                Err(err) => {
+                    info!("Failed to create answer: {:?}", err);
+                    self.mark_error(format!("Failed to create answer: {}", err));
+                }
+            }
+        }
+        panic!("Failed to create answer");
+    }
+
+    async fn set_local_sdp(&self, sdp: RTCSessionDescription) {
+        let pc = self.peer_connection.lock().await.clone();
+        if let Some(pc) = pc {
+            pc.set_local_description(sdp).await.unwrap();
+        }
+    }
+
+    async fn create_offer(&self) {
+        let pc = self.peer_connection.lock().await.clone();
+        if let Some(pc) = pc {
+            info!("Creating offer...");
+            let ice_candidates = Arc::clone(&self.ice_candidates);
+            pc.on_ice_candidate(Box::new(move |candidate| {
+                let ice_candidates = Arc::clone(&ice_candidates);
+                Box::pin(async move {
+                    if let Some(candidate) = candidate {
+                        let mut ice_candidates = ice_candidates.lock().await;
+                        if ice_candidates.push(candidate.to_json().unwrap()) {
+                            warn!("ICE candidate buffer is full, dropping {}", candidate);
+                        }
+                    }
+                })
+            }));
+
+            match pc.create_offer(None).await {
+                Ok(offer) => {
+                    pc.set_local_description(offer.clone()).await.unwrap();
+                    self.gather_ice_candidates().await;
+
+                    if let Some(local_desc) = pc.local_description().await {
+                        info!("Offer created with SDP: {:?}", &local_desc);
+                        self.local_sdp.set(local_desc.sdp);
+                    }
+                }
+                Err(err) => {
+                    info!("Failed to create offer: {:?}", err);
+                    self.mark_error(format!("Failed to create offer: {}", err));
+                }
+            }
+        } else {
+            info!("Peer connection is not initialized");
+        }
+    }
+    async fn handle_offer(&self) {
+        let pc = self.peer_connection.lock().await.clone();
+        if let Some(pc) = pc {
+            let remote_sdp = self.remote_sdp.get().to_string();
+            let offer = RTCSessionDescription::offer(remote_sdp).unwrap();
+            match pc.set_remote_description(offer).await {
+                Ok(ok) => {
+                    info!("Remote description set: {:?}", ok);
+                    self.apply_queued_candidates(&pc).await;
+
+                    let answer = self.create_answer().await;
+                    self.set_local_sdp(answer).await;
+                }
+                Err(err) => {
+                    info!("Failed to set remote description: {:?}", err);
+                    self.mark_error(format!("Failed to set remote description: {}", err));
+                }
+            }
+        }
+    }
+
+    async fn handle_answer(&self) {
+        let pc = self.peer_connection.lock().await.clone();
+        if let Some(pc) = pc {
+            let remote_sdp = self.remote_sdp.get().to_string();
+            let answer = RTCSessionDescription::answer(remote_sdp).unwrap();
+            match pc.set_remote_description(answer).await {
+                Ok(ok) => {
+                    info!("Remote description set: {:?}", ok);
+                    self.apply_queued_candidates(&pc).await;
+
+                    // Add stored ICE candidates
+                    let ice_candidates: Vec<_> =
+                        self.ice_candidates.lock().await.iter().cloned().collect();
+                    for candidate in ice_candidates {
+                        pc.add_ice_candidate(candidate).await.unwrap();
+                    }
+                }
+                Err(err) => {
+                    info!("Failed to set remote description: {:?}", err);
+                    self.mark_error(format!("Failed to set remote description: {}", err));
+                }
+            }
+        }
+    }
+    async fn create_peer_connection(&self, ice_lite: bool) {
+        self.refresh_turn_credentials().await;
+        let settings = self.settings.lock().unwrap().clone();
+        let codecs = *self.codecs.lock().unwrap();
+        if !codecs.is_default() {
+            info!("Offering only: {}", codecs);
+        }
+        let api = settings.api_for(&codecs).unwrap();
+
+        let config = settings.rtc_configuration(ice_lite);
+
+        let peer_connection = Arc::new(api.new_peer_connection(config).await.unwrap());
+
+        self.watch_ice_state(&peer_connection, settings.advanced.auto_ice_restart);
+        self.watch_remote_video(
+            &peer_connection,
+            settings.thumbnail_interval(),
+            settings.media.av_sync_offset_ms,
+        );
+        self.watch_data_channels(&peer_connection);
+
+        let stats = Arc::clone(&self.stats);
+        peer_connection.on_peer_connection_state_change(Box::new(move |state| {
+            {
+                let mut stats = stats.lock().unwrap();
+                stats.mark(MarkerKind::ConnectionState, state.to_string());
+                if state == RTCPeerConnectionState::Failed {
+                    stats.mark(MarkerKind::Error, "The peer connection failed");
+                }
+            }
+            Box::pin(async move {
+                info!("Peer Connection State: {:?}", state);
+                if state == RTCPeerConnectionState::Connected {
+                    info!("Peer Connection Established");
+                }
+            })
+        }));
+        let stats = Arc::clone(&self.stats);
+        peer_connection.on_ice_gathering_state_change(Box::new(move |state| {
+            stats
+                .lock()
+                .unwrap()
+                .mark(MarkerKind::GatheringState, state.to_string());
+            Box::pin(async {})
+        }));
+
+        *self.stats.lock().unwrap() = StatsTimeline::new();
+        *self.control.lock().unwrap() = ControlState::default();
+        self.support_logs.lock().unwrap().reset();
+        self.chat.lock().unwrap().reset();
+        self.clipboard_sync.lock().unwrap().reset();
+        self.remote_control.lock().unwrap().reset();
+        self.rpc.lock().unwrap().reset();
+        self.captions.lock().unwrap().reset();
+        self.file_transfer.lock().unwrap().reset();
+        self.history.lock().unwrap().reset();
+        self.data_channels.lock().unwrap().reset();
+        self.camera.lock().unwrap().reset();
+        self.microphone.lock().unwrap().reset();
+        self.window_share.lock().unwrap().reset();
+        self.media_file.lock().unwrap().reset();
+        self.ice_candidates.lock().await.clear();
+        self.remote_candidates.lock().unwrap().reset();
+        let negotiated = Arc::new(AtomicBool::new(false));
+        let stats = Arc::clone(&self.stats);
+        peer_connection.on_signaling_state_change(Box::new(move |state| {
+            stats
+                .lock()
+                .unwrap()
+                .mark(MarkerKind::SignalingState, state.to_string());
+            // Leaving Stable after a completed negotiation is a renegotiation.
+            match state {
+                RTCSignalingState::Stable => negotiated.store(true, Ordering::SeqCst),
+                RTCSignalingState::HaveLocalOffer | RTCSignalingState::HaveRemoteOffer
+                    if negotiated.load(Ordering::SeqCst) =>
+                {
+                    stats
+                        .lock()
+                        .unwrap()
+                        .mark(MarkerKind::Renegotiation, state.to_string());
+                }
+                _ => {}
+            }
+            Box::pin(async {})
+        }));
+        self.spawn_stats_sampler(
+            Arc::downgrade(&peer_connection),
+            settings.stats_interval(),
+            settings.failover_thresholds(),
+        );
+        self.add_microphone_track(&peer_connection).await;
+
+        let mut pc = self.peer_connection.lock().await;
+        *pc = Some(peer_connection);
+    }
+
+    /// Adds the receive-only m-lines requested in the media settings, but
+    /// no second audio one next to the microphone's.
+    async fn add_configured_transceivers(&self) {
+        let media = self.settings.lock().unwrap().media.clone();
+        let pc = self.peer_connection.lock().await.clone();
+        let Some(pc) = pc else {
+            return;
+        };
+        let kinds = [
+            (
+                media.offer_audio && !self.sending_microphone(),
+                RTPCodecType::Audio,
+            ),
+            (media.offer_video, RTPCodecType::Video),
+        ];
+        for (_, kind) in kinds.into_iter().filter(|(enabled, _)| *enabled) {
+            let init = RTCRtpTransceiverInit {
+                direction: RTCRtpTransceiverDirection::Recvonly,
+                send_encodings: vec![],
+            };
+            if let Err(err) = pc.add_transceiver_from_kind(kind, Some(init)).await {
+                info!("Failed to add {} transceiver: {:?}", kind, err);
+            }
+        }
+    }
+
+    async fn close_peer_connection(&self) {
+        let pc = self.peer_connection.lock().await.take();
+        if let Some(pc) = pc {
+            if let Err(err) = pc.close().await {
+                info!("Failed to close peer connection: {:?}", err);
+            }
+        }
+    }
+
+    async fn save_local_sdp(&self) {
+        let Some(path) = self
+            .save_file_dialog(FileKind::Sdp, "Save local SDP", "local.sdp")
+            .await
+        else {
+            return;
+        };
+        match std::fs::write(&path, self.local_sdp.get().as_bytes()) {
+            Ok(()) => info!("Local SDP saved to {}", path.display()),
+            Err(err) => warn!("Failed to save SDP to {}: {}", path.display(), err),
+        }
+    }
+
+    /// Loading publishes the SDP, which redraws.
+    async fn load_remote_sdp(&self) {
+        let Some(path) = self
+            .open_file_dialog(FileKind::Sdp, "Load remote SDP")
+            .await
+        else {
+            return;
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(sdp) => self.remote_sdp.set(sdp),
+            Err(err) => warn!("Failed to load SDP from {}: {}", path.display(), err),
+        }
+    }
+
+    /// Codec override for the next call, for interop experiments.
+    fn codec_override_ui(&self, ui: &mut egui::Ui) {
+        let mut codecs = self.codecs.lock().unwrap();
+        ui.horizontal(|ui| {
+            ui.label("Codecs for new calls:");
+            egui::ComboBox::from_id_source("audio_codecs")
+                .selected_text(codecs.audio.to_string())
+                .show_ui(ui, |ui| {
+                    for audio in AudioCodecs::ALL {
+                        ui.selectable_value(&mut codecs.audio, audio, audio.to_string());
+                    }
+                });
+            egui::ComboBox::from_id_source("video_codecs")
+                .selected_text(codecs.video.to_string())
+                .show_ui(ui, |ui| {
+                    for video in VideoCodecs::ALL {
+                        ui.selectable_value(&mut codecs.video, video, video.to_string());
+                    }
+                });
+            if !codecs.is_default() && ui.small_button("Reset").clicked() {
+                *codecs = CodecOverride::default();
+            }
+        });
+        drop(codecs);
+        let timeline = self.stats.lock().unwrap();
+        for (kind, codecs) in [
+            ("Audio", timeline.audio_codecs()),
+            ("Video", timeline.video_codecs()),
+        ] {
+            if let Some(text) = stats_panel::codecs_text(codecs) {
+                ui.label(format!("{} codec in this call: {}", kind, text));
+            }
+        }
+    }
+
+    /// Tells the user when a bounded buffer has had to drop entries.
+    fn buffer_warnings_ui(&self, ui: &mut egui::Ui) {
+        // An async lock; rather skip the warning for a frame than block.
+        if let Ok(candidates) = self.ice_candidates.try_lock() {
+            if let Some(warning) = candidates.warning("ICE candidates") {
+                ui.colored_label(egui::Color32::YELLOW, warning);
+            }
+        }
+    }
+
+    /// Applies a remote offer to the current peer connection and returns the
+    /// local answer SDP once ICE gathering has completed.
+    async fn answer_remote_offer(&self, sdp: String) -> Result<String, webrtc::Error> {
+        self.remote_sdp.set(sdp.clone());
+        let pc = self.peer_connection.lock().await.clone();
+        let pc = pc.ok_or(webrtc::Error::ErrConnectionClosed)?;
+        pc.set_remote_description(RTCSessionDescription::offer(sdp)?)
+            .await?;
+        self.apply_queued_candidates(&pc).await;
+        Ok(self.create_answer().await.sdp)
+    }
+}
+
+impl eframe::App for WebRTCApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, SETTINGS_KEY, &*self.settings.lock().unwrap());
+        eframe::set_value(storage, CONTACTS_KEY, &self.contacts.lock().unwrap().book);
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.poll_clipboard(ctx);
+        self.poll_reconnect(ctx);
+        self.poll_remote_video(ctx);
+        self.poll_remote_control();
+        self.poll_dropped_files(ctx);
+        self.poll_history();
+        self.poll_captions();
+        self.poll_camera_device(ctx);
+        self.poll_microphone_device(ctx);
+        self.poll_speaker_device();
+        self.poll_power_saving(ctx);
+
+        {
+            let mut settings = self.settings.lock().unwrap();
+            let mut devices = self.devices.lock().unwrap();
+            self.settings_window
+                .lock()
+                .unwrap()
+                .show(ctx, &mut settings, &mut devices);
+        }
+        self.login_window(ctx);
+        self.remote_control_dialog(ctx);
+        self.dropped_files_window(ctx);
+
+        if self.power_saving_active() {
+            egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+                self.power_saving_ui(ui);
+            });
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.heading("WebRTC Client");
+                if ui.button("Settings").clicked() {
+                    let mut window = self.settings_window.lock().unwrap();
+                    window.open = !window.open;
+                }
+                self.login_button_ui(ui);
+                self.signaling_health_ui(ui);
+                self.log_sharing_indicator_ui(ui);
+                self.remote_control_indicator_ui(ui);
+                self.microphone_mute_ui(ui);
+            });
+
+            self.device_notices_ui(ui);
+            self.buffer_warnings_ui(ui);
+            self.reconnect_ui(ui, ctx);
+            self.control_ui(ui, ctx);
+            self.renegotiation_prompt_ui(ui, ctx);
+            self.codec_override_ui(ui);
+
+            if ui.button("Initialize (Standard)").clicked() {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.create_peer_connection(false).await;
+                    app.add_configured_transceivers().await;
+                    app.open_control_channel().await;
+                    ctx.request_repaint();
+                });
+            }
+
+            if ui.button("Initialize (ICE Lite)").clicked() {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.create_peer_connection(true).await;
+                    app.add_configured_transceivers().await;
+                    app.open_control_channel().await;
+                    ctx.request_repaint();
+                });
+            }
+
+            // Rooms assign roles and negotiate on their own.
+            let manual = !self.in_room();
+
+            if ui
+                .add_enabled(manual, egui::Button::new("Create Offer"))
+                .on_disabled_hover_text(AUTOMATIC)
+                .clicked()
+            {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.create_offer().await;
+                    ctx.request_repaint();
+                });
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("Local SDP:");
+                let mut local_sdp = self.local_sdp.get().to_string();
+                if ui.text_edit_multiline(&mut local_sdp).changed() {
+                    self.local_sdp.set(local_sdp);
+                }
+                if ui.button("Save...").clicked() {
+                    let app = self.clone();
+                    tokio::spawn(async move {
+                        app.save_local_sdp().await;
+                    });
+                }
+            });
+
+            self.clipboard_prompt_ui(ui);
+            self.remote_candidates_ui(ui);
+
+            ui.horizontal(|ui| {
+                ui.label("Remote SDP:");
+                let mut remote_sdp = self.remote_sdp.get().to_string();
+                if ui.text_edit_multiline(&mut remote_sdp).changed() {
+                    self.remote_sdp.set(remote_sdp);
+                }
+                if ui.button("Load...").clicked() {
+                    let app = self.clone();
+                    tokio::spawn(async move {
+                        app.load_remote_sdp().await;
+                    });
+                }
+                if ui
+                    .add_enabled(manual, egui::Button::new("Handle Offer"))
+                    .on_disabled_hover_text(AUTOMATIC)
+                    .clicked()
+                {
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.handle_offer().await;
+                        ctx.request_repaint();
+                    });
+                }
+
+                if ui
+                    .add_enabled(manual, egui::Button::new("Handle Answer"))
+                    .on_disabled_hover_text(AUTOMATIC)
+                    .clicked()
+                {
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.handle_answer().await;
+                        ctx.request_repaint();
+                    });
+                }
+            });
+
+            if ui
+                .add_enabled(manual, egui::Button::new("Create Answer"))
+                .on_disabled_hover_text(AUTOMATIC)
+                .clicked()
+            {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    let answer = app.create_answer().await;
+                    app.set_local_sdp(answer).await;
+                    ctx.request_repaint();
+                });
+            }
+
+            egui::CollapsingHeader::new("Devices").show(ui, |ui| {
+                self.devices_ui(ui);
+                self.camera_ui(ui, ctx);
+                self.microphone_ui(ui);
+            });
+
+            egui::CollapsingHeader::new("Window Share").show(ui, |ui| {
+                self.window_share_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("Media File").show(ui, |ui| {
+                self.media_file_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("Remote Video").show(ui, |ui| {
+                self.remote_video_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("Contacts").show(ui, |ui| {
+                self.contacts_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("Room").show(ui, |ui| {
+                self.room_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("Chat").show(ui, |ui| {
+                self.chat_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("Captions").show(ui, |ui| {
+                self.captions_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("Files").show(ui, |ui| {
+                self.file_transfer_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("History").show(ui, |ui| {
+                self.history_ui(ui);
+            });
+
+            egui::CollapsingHeader::new("Clipboard Sync").show(ui, |ui| {
+                self.clipboard_sync_ui(ui);
+            });
+
+            egui::CollapsingHeader::new("Data Channels").show(ui, |ui| {
+                self.data_channels_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("RPC Console").show(ui, |ui| {
+                self.rpc_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("Nostr").show(ui, |ui| {
+                self.nostr_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("libp2p").show(ui, |ui| {
+                self.p2p_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("File Signaling").show(ui, |ui| {
+                self.file_signaling_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("SIP Gateway").show(ui, |ui| {
+                self.sip_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("WHEP Player").show(ui, |ui| {
+                self.whep_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("Janus VideoRoom").show(ui, |ui| {
+                self.janus_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("LiveKit").show(ui, |ui| {
+                self.livekit_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("Jitsi Meet").show(ui, |ui| {
+                self.jitsi_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("Test My Setup").show(ui, |ui| {
+                self.echo_test_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("Support Logs").show(ui, |ui| {
+                self.support_logs_ui(ui, ctx);
+            });
+
+            egui::CollapsingHeader::new("Security Log").show(ui, |ui| {
+                self.audit_log_ui(ui);
+            });
+
+            egui::CollapsingHeader::new("Diagnostics").show(ui, |ui| {
+                self.event_timeline_ui(ui);
+                ui.separator();
+                self.stats_ui(ui);
+                ui.separator();
+                self.latency_ui(ui);
+                ctx.request_repaint_after(std::time::Duration::from_secs(1));
+            });
+        });
+    }
+}
@@ -0,0 +1,221 @@
+//! Sending a video file's tracks on the current peer connection.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use eframe::egui;
+use log::info;
+use tokio::task::JoinHandle;
+use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::rtp_transceiver::rtp_sender::RTCRtpSender;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+use webrtc_core::media_file::{self, MediaFile};
+use webrtc_core::settings::FileKind;
+
+use crate::WebRTCApp;
+
+#[derive(Default)]
+pub struct MediaFileState {
+    path: Option<PathBuf>,
+    /// Start over at the end, for soak tests.
+    looping: bool,
+    playback: Option<JoinHandle<()>>,
+    senders: Vec<Arc<RTCRtpSender>>,
+    busy: bool,
+    status: String,
+}
+
+impl MediaFileState {
+    fn playing(&self) -> bool {
+        self.playback
+            .as_ref()
+            .is_some_and(|playback| !playback.is_finished())
+    }
+
+    /// Stops playback and hands back the senders to remove.
+    fn stop(&mut self) -> Vec<Arc<RTCRtpSender>> {
+        if let Some(playback) = self.playback.take() {
+            playback.abort();
+        }
+        std::mem::take(&mut self.senders)
+    }
+
+    /// Stops playback for a peer connection that is being replaced, which
+    /// takes its senders with it.
+    pub(crate) fn reset(&mut self) {
+        self.stop();
+        self.status.clear();
+    }
+}
+
+impl WebRTCApp {
+    async fn pick_media_file(&self) {
+        let path = self
+            .open_file_dialog(FileKind::Media, "Send a media file")
+            .await;
+        let mut state = self.media_file.lock().unwrap();
+        state.busy = false;
+        if path.is_some() {
+            state.path = path;
+            state.status.clear();
+        }
+    }
+
+    async fn start_media_file(&self, path: PathBuf, looping: bool) {
+        let status = match self.add_media_file_tracks(path, looping).await {
+            Ok(status) => status,
+            Err(err) => {
+                info!("Failed to send media file: {}", err);
+                format!("Cannot send the file: {}", err)
+            }
+        };
+        let mut state = self.media_file.lock().unwrap();
+        state.busy = false;
+        state.status = status;
+    }
+
+    /// Adds a track per sendable track of the file, which renegotiates like
+    /// the camera does.
+    async fn add_media_file_tracks(&self, path: PathBuf, looping: bool) -> Result<String, String> {
+        let pc = self.peer_connection.lock().await.clone();
+        let pc = pc.ok_or("initialize a peer connection first")?;
+        let file = tokio::task::spawn_blocking(move || MediaFile::open(&path))
+            .await
+            .map_err(|err| err.to_string())?
+            .map_err(|err| err.to_string())?;
+        let name = file.path().file_name().unwrap_or_default();
+        let mut status = format!("Sending {}", name.to_string_lossy());
+        if !file.skipped().is_empty() {
+            status += &format!(" without its {}", file.skipped().join(", "));
+        }
+
+        let mut tracks = vec![];
+        let mut senders = vec![];
+        for (index, track) in file.tracks().iter().enumerate() {
+            let local = Arc::new(TrackLocalStaticSample::new(
+                RTCRtpCodecCapability {
+                    mime_type: track.codec.mime_type.clone(),
+                    clock_rate: track.codec.clock_rate,
+                    channels: track.codec.channels,
+                    ..Default::default()
+                },
+                format!("file-{}-{}", track.kind, index),
+                "file".to_owned(),
+            ));
+            match pc.add_track(local.clone()).await {
+                Ok(sender) => senders.push(sender),
+                Err(err) => {
+                    for sender in &senders {
+                        let _ = pc.remove_track(sender).await;
+                    }
+                    return Err(err.to_string());
+                }
+            }
+            tracks.push(local);
+        }
+        let playback = match media_file::spawn_playback(file, tracks, looping) {
+            Ok(playback) => playback,
+            Err(err) => {
+                for sender in &senders {
+                    let _ = pc.remove_track(sender).await;
+                }
+                return Err(err.to_string());
+            }
+        };
+        info!("{}", status);
+        let mut state = self.media_file.lock().unwrap();
+        state.playback = Some(playback);
+        state.senders = senders;
+        Ok(status)
+    }
+
+    async fn stop_media_file(&self) {
+        let senders = self.media_file.lock().unwrap().stop();
+        let pc = self.peer_connection.lock().await.clone();
+        if let Some(pc) = pc {
+            for sender in senders {
+                if let Err(err) = pc.remove_track(&sender).await {
+                    info!("Failed to remove media file track: {:?}", err);
+                }
+            }
+        }
+        let mut state = self.media_file.lock().unwrap();
+        state.busy = false;
+        state.status = "Stopped sending the file".to_owned();
+    }
+
+    pub(crate) fn media_file_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.media_file.lock().unwrap();
+        let playing = state.playing();
+        if playing {
+            // Notice the end of the file.
+            ctx.request_repaint_after(std::time::Duration::from_secs(1));
+        }
+        ui.horizontal(|ui| {
+            let name = state
+                .path
+                .as_ref()
+                .map_or("No file picked".to_owned(), |path| {
+                    path.display().to_string()
+                });
+            ui.label(name);
+            if ui
+                .add_enabled(!state.busy && !playing, egui::Button::new("Pick File..."))
+                .clicked()
+            {
+                state.busy = true;
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.pick_media_file().await;
+                    ctx.request_repaint();
+                });
+            }
+            ui.add_enabled(!playing, egui::Checkbox::new(&mut state.looping, "Loop"));
+        });
+        ui.horizontal(|ui| {
+            if !state.senders.is_empty() {
+                let label = if playing {
+                    "Stop Sending"
+                } else {
+                    "Remove Tracks"
+                };
+                if ui
+                    .add_enabled(!state.busy, egui::Button::new(label))
+                    .clicked()
+                {
+                    state.busy = true;
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.stop_media_file().await;
+                        ctx.request_repaint();
+                    });
+                }
+            } else {
+                let path = state.path.clone();
+                let button = ui
+                    .add_enabled(
+                        !state.busy && path.is_some(),
+                        egui::Button::new("Send File"),
+                    )
+                    .on_disabled_hover_text("Pick a file first");
+                if let (true, Some(path)) = (button.clicked(), path) {
+                    state.busy = true;
+                    let looping = state.looping;
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.start_media_file(path, looping).await;
+                        ctx.request_repaint();
+                    });
+                }
+            }
+            if !state.senders.is_empty() && !playing {
+                ui.label("Finished");
+            } else {
+                ui.label(&state.status);
+            }
+        });
+    }
+}
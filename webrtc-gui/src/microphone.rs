@@ -0,0 +1,247 @@
+//! Sending the selected microphone, added to each new peer connection
+//! before it negotiates so calls carry voice from the first offer. Picking
+//! another microphone mid-call moves the same track over to it, and muting
+//! sends silence on it rather than taking it down.
+
+use std::sync::Arc;
+
+use eframe::egui;
+use log::info;
+use tokio::task::JoinHandle;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc_core::audio::{self, AudioLevel, EchoReference, MicrophoneTrack};
+use webrtc_core::control::ControlMessage;
+use webrtc_core::devices::{DeviceInfo, DeviceKind};
+use webrtc_core::recording::CallMix;
+use webrtc_core::snapshot::Snapshot;
+
+use crate::level_meter::level_meter_ui;
+use crate::WebRTCApp;
+
+#[derive(Default)]
+pub struct MicrophoneState {
+    capture: Option<JoinHandle<()>>,
+    /// Follows the loss the peer reports, for the capture's FEC and RED.
+    loss: Option<JoinHandle<()>>,
+    track: Option<Arc<MicrophoneTrack>>,
+    /// The id of the microphone being captured; `None` for the default.
+    device: Option<String>,
+    /// Moving the track to another microphone.
+    switching: bool,
+    status: String,
+    /// How loud the microphone is, from whichever capture is running.
+    level: Snapshot<AudioLevel>,
+    /// Read by the capture; kept from one call to the next.
+    muted: Snapshot<bool>,
+    /// Fed by the remote audio playback, for the capture to cancel.
+    echo: EchoReference,
+    /// Fed by the capture and the remote audio playback, for a recording
+    /// of the whole call.
+    mix: CallMix,
+}
+
+impl MicrophoneState {
+    /// Stops sending for a peer connection that is being replaced.
+    pub(crate) fn reset(&mut self) {
+        if let Some(capture) = self.capture.take() {
+            capture.abort();
+        }
+        if let Some(loss) = self.loss.take() {
+            loss.abort();
+        }
+        self.track = None;
+        self.device = None;
+        self.status.clear();
+        self.level.set(AudioLevel::default());
+    }
+}
+
+impl WebRTCApp {
+    /// Adds the microphone track to `pc` when the media settings ask for it.
+    pub(crate) async fn add_microphone_track(&self, pc: &Arc<RTCPeerConnection>) {
+        let options = {
+            let settings = self.settings.lock().unwrap();
+            if !settings.media.send_microphone || !audio::can_capture() {
+                return;
+            }
+            settings.media.capture_options()
+        };
+        if !self.codecs.lock().unwrap().audio.allows_opus() {
+            self.microphone.lock().unwrap().status =
+                "Not sending the microphone: this call's codecs leave out Opus".to_owned();
+            return;
+        }
+        let device = self.selected_device(DeviceKind::Microphone);
+        let (level, muted, echo, mix) = {
+            let state = self.microphone.lock().unwrap();
+            let echo = state.echo.clone();
+            (
+                state.level.clone(),
+                state.muted.clone(),
+                echo,
+                state.mix.clone(),
+            )
+        };
+        let track = Arc::new(MicrophoneTrack::new("audio".to_owned(), "local".to_owned()));
+        let status = match pc.add_track(track.clone()).await {
+            Ok(sender) => match audio::spawn_microphone(
+                device.as_ref().map(|d| d.id.as_str()),
+                options,
+                track.clone(),
+                level,
+                muted,
+                &echo,
+                &mix,
+            ) {
+                Ok(capture) => {
+                    let mut state = self.microphone.lock().unwrap();
+                    state.capture = Some(capture);
+                    state.loss = Some(audio::watch_loss(sender, track.loss()));
+                    state.track = Some(track);
+                    state.device = device.as_ref().map(|d| d.id.clone());
+                    let name = device.map_or("default microphone".to_owned(), |d| d.name);
+                    format!("Sending {}", name)
+                }
+                Err(err) => format!("Microphone failed: {}", err),
+            },
+            Err(err) => format!("Failed to add microphone track: {}", err),
+        };
+        info!("{}", status);
+        self.microphone.lock().unwrap().status = status;
+    }
+
+    /// Moves capture over to `device`, feeding the same track so the peer
+    /// hears the new microphone without a renegotiation.
+    async fn switch_microphone(&self, device: DeviceInfo, track: Arc<MicrophoneTrack>) {
+        let options = self.settings.lock().unwrap().media.capture_options();
+        let (level, muted, echo, mix) = {
+            let state = self.microphone.lock().unwrap();
+            let echo = state.echo.clone();
+            (
+                state.level.clone(),
+                state.muted.clone(),
+                echo,
+                state.mix.clone(),
+            )
+        };
+        let id = device.id.clone();
+        let capture = tokio::task::spawn_blocking(move || {
+            audio::spawn_microphone(Some(&id), options, track, level, muted, &echo, &mix)
+        })
+        .await;
+        let mut state = self.microphone.lock().unwrap();
+        state.switching = false;
+        state.status = match capture {
+            Ok(Ok(capture)) => {
+                state.capture = Some(capture);
+                format!("Sending {}", device.name)
+            }
+            Ok(Err(err)) => format!("Switching to {} failed: {}", device.name, err),
+            Err(err) => format!("Switching to {} failed: {}", device.name, err),
+        };
+        info!("{}", state.status);
+    }
+
+    /// Switches the microphone being sent once another one is picked in
+    /// the device settings.
+    pub(crate) fn poll_microphone_device(&self, ctx: &egui::Context) {
+        let Some(selected) = self.selected_device(DeviceKind::Microphone) else {
+            return;
+        };
+        let mut state = self.microphone.lock().unwrap();
+        let Some(track) = state.track.clone() else {
+            return;
+        };
+        if state.switching || state.device.as_deref() == Some(selected.id.as_str()) {
+            return;
+        }
+        // Stopped first, so the two captures never write into the track
+        // at once.
+        if let Some(capture) = state.capture.take() {
+            capture.abort();
+        }
+        state.device = Some(selected.id.clone());
+        state.switching = true;
+        state.status = format!("Switching to {}...", selected.name);
+        let app = self.clone();
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            app.switch_microphone(selected, track).await;
+            ctx.request_repaint();
+        });
+    }
+
+    pub(crate) fn switching_microphone(&self) -> bool {
+        self.microphone.lock().unwrap().switching
+    }
+
+    /// Whether the current peer connection already has an audio m-line
+    /// from the microphone.
+    pub(crate) fn sending_microphone(&self) -> bool {
+        self.microphone.lock().unwrap().track.is_some()
+    }
+
+    /// Where remote audio being played goes for the microphone's echo
+    /// to be cancelled.
+    pub(crate) fn echo_reference(&self) -> EchoReference {
+        self.microphone.lock().unwrap().echo.clone()
+    }
+
+    /// Where the microphone and the remote audio meet a recording of the
+    /// whole call.
+    pub(crate) fn call_mix(&self) -> CallMix {
+        self.microphone.lock().unwrap().mix.clone()
+    }
+
+    pub(crate) fn microphone_muted(&self) -> bool {
+        *self.microphone.lock().unwrap().muted.get()
+    }
+
+    /// Mutes or unmutes the microphone and tells the peer.
+    pub(crate) fn set_microphone_muted(&self, muted: bool) {
+        self.microphone.lock().unwrap().muted.set(muted);
+        info!("Microphone {}", if muted { "muted" } else { "unmuted" });
+        let app = self.clone();
+        tokio::spawn(async move { app.announce_microphone_mute().await });
+    }
+
+    /// Tells the peer whether the microphone is muted, as it changes and
+    /// once the control channel opens.
+    pub(crate) async fn announce_microphone_mute(&self) {
+        if !self.sending_microphone() {
+            return;
+        }
+        let muted = self.microphone_muted();
+        self.send_control(ControlMessage::MicrophoneMuted { muted })
+            .await;
+    }
+
+    /// The mute button, next to the heading so it is always at hand.
+    pub(crate) fn microphone_mute_ui(&self, ui: &mut egui::Ui) {
+        if !self.sending_microphone() {
+            return;
+        }
+        let muted = self.microphone_muted();
+        if muted {
+            ui.colored_label(egui::Color32::RED, "Microphone muted");
+        }
+        if ui.button(if muted { "Unmute" } else { "Mute" }).clicked() {
+            self.set_microphone_muted(!muted);
+        }
+    }
+
+    pub(crate) fn microphone_ui(&self, ui: &mut egui::Ui) {
+        let state = self.microphone.lock().unwrap();
+        if !state.status.is_empty() {
+            ui.horizontal(|ui| {
+                if state.switching {
+                    ui.spinner();
+                }
+                ui.label(&state.status);
+            });
+        }
+        if state.capture.is_some() {
+            level_meter_ui(ui, "Microphone", &state.level.get());
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! The panels' own data channels: one each for logs, chat, files, the
+//! clipboard, input, rpc and captions. Every peer connection gets all of them next to
+//! the control channel, pre-negotiated the same way on a label and stream ID
+//! both ends reserve, so neither end waits on the other to announce them.
+//! They are ordered, and text too long for one message goes in pieces.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use log::info;
+use webrtc::data_channel::data_channel_init::RTCDataChannelInit;
+use webrtc::data_channel::data_channel_message::DataChannelMessage;
+use webrtc::data_channel::RTCDataChannel;
+use webrtc_core::framing::{self, Reassembler};
+
+use crate::WebRTCApp;
+
+/// A panel's channel on the current peer connection, once created, and
+/// whether it is open.
+#[derive(Default)]
+pub(crate) struct NegotiatedChannel {
+    channel: Option<Arc<RTCDataChannel>>,
+    open: bool,
+}
+
+impl NegotiatedChannel {
+    pub(crate) fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// The channel, while it is open to send on.
+    pub(crate) fn sender(&self) -> Option<Arc<RTCDataChannel>> {
+        self.channel.clone().filter(|_| self.open)
+    }
+}
+
+/// The state of a panel with a channel of its own.
+pub(crate) trait ChannelPanel: Send + 'static {
+    fn negotiated(&mut self) -> &mut NegotiatedChannel;
+
+    /// Forgets the last peer connection's channel, before a new one.
+    fn replaced(&mut self);
+
+    /// The channel opened.
+    fn opened(&mut self) {}
+
+    /// The channel closed, so whatever was using it has stopped.
+    fn closed(&mut self) {}
+}
+
+/// A whole message from the peer.
+pub(crate) enum Received {
+    /// Text, put back together if it came in pieces.
+    Text(String),
+    /// Binary data, as it came.
+    Binary(DataChannelMessage),
+}
+
+impl Received {
+    /// The text, for a channel that only carries text.
+    pub(crate) fn into_text(self) -> Option<String> {
+        match self {
+            Received::Text(text) => Some(text),
+            Received::Binary(_) => {
+                info!("Ignoring a binary message on a text channel");
+                None
+            }
+        }
+    }
+}
+
+/// Why [`send`] sent nothing.
+#[derive(Debug)]
+pub(crate) enum SendError {
+    NotOpen,
+    Failed(webrtc::Error),
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::NotOpen => f.write_str("the channel is not open"),
+            SendError::Failed(err) => err.fmt(f),
+        }
+    }
+}
+
+/// Sends `text` on `channel`, as [`NegotiatedChannel::sender`] gave it.
+pub(crate) async fn send(
+    channel: Option<Arc<RTCDataChannel>>,
+    text: String,
+) -> Result<(), SendError> {
+    let channel = channel.ok_or(SendError::NotOpen)?;
+    framing::send_text(&channel, text).await.map_err(|err| {
+        info!("Failed to send {} message: {:?}", channel.label(), err);
+        SendError::Failed(err)
+    })
+}
+
+impl WebRTCApp {
+    /// Creates the channel `label` on the current peer connection for the
+    /// panel whose state is `panel`. Each whole message goes to `on_message`,
+    /// and whatever it returns is sent back as the reply.
+    pub(crate) async fn open_negotiated_channel<S: ChannelPanel>(
+        &self,
+        panel: &Arc<Mutex<S>>,
+        label: &str,
+        stream_id: u16,
+        mut on_message: impl FnMut(Received) -> Option<String> + Send + Sync + 'static,
+    ) -> Option<Arc<RTCDataChannel>> {
+        let pc = self.peer_connection.lock().await.clone()?;
+        let init = RTCDataChannelInit {
+            ordered: Some(true),
+            negotiated: Some(stream_id),
+            ..Default::default()
+        };
+        let channel = match pc.create_data_channel(label, Some(init)).await {
+            Ok(channel) => channel,
+            Err(err) => {
+                info!("Failed to create {} channel: {:?}", label, err);
+                return None;
+            }
+        };
+
+        let state = Arc::clone(panel);
+        channel.on_open(Box::new(move || {
+            let mut state = state.lock().unwrap();
+            state.negotiated().open = true;
+            state.opened();
+            Box::pin(async {})
+        }));
+        let state = Arc::clone(panel);
+        channel.on_close(Box::new(move || {
+            let mut state = state.lock().unwrap();
+            state.negotiated().open = false;
+            state.closed();
+            Box::pin(async {})
+        }));
+        // The channel holds this handler, so it may only hold the channel
+        // weakly back.
+        let weak = Arc::downgrade(&channel);
+        let label = label.to_owned();
+        let mut reassembler = Reassembler::default();
+        channel.on_message(Box::new(move |message: DataChannelMessage| {
+            let received = if message.is_string {
+                match reassembler.receive(&message.data) {
+                    Ok(Some(text)) => Received::Text(text),
+                    // More of a long message to come.
+                    Ok(None) => return Box::pin(async {}),
+                    Err(err) => {
+                        info!("Ignoring malformed {} message: {}", label, err);
+                        return Box::pin(async {});
+                    }
+                }
+            } else {
+                Received::Binary(message)
+            };
+            let reply = on_message(received).zip(weak.upgrade());
+            Box::pin(async move {
+                if let Some((reply, channel)) = reply {
+                    let _ = send(Some(channel), reply).await;
+                }
+            })
+        }));
+
+        let mut state = panel.lock().unwrap();
+        state.replaced();
+        state.negotiated().channel = Some(Arc::clone(&channel));
+        Some(channel)
+    }
+}
@@ -0,0 +1,193 @@
+//! Serverless signaling through public Nostr relays: peers dial each other
+//! by public key instead of meeting on a signaling server.
+
+use eframe::egui;
+use log::info;
+use webrtc_core::signaling::nostr::{Keys, NostrSignal, NostrSignaling};
+use webrtc_core::signaling::SignalPayload;
+
+use crate::WebRTCApp;
+
+#[derive(Default)]
+pub struct NostrState {
+    /// The peer to call, or the one who called us.
+    pub(crate) peer: String,
+    signaling: Option<NostrSignaling>,
+    status: String,
+}
+
+impl WebRTCApp {
+    fn set_nostr_status(&self, status: impl Into<String>) {
+        self.nostr.lock().unwrap().status = status.into();
+    }
+
+    /// Our identity, created and remembered the first time it is needed.
+    fn nostr_keys(&self) -> Keys {
+        let mut settings = self.settings.lock().unwrap();
+        if let Ok(keys) = Keys::from_secret_hex(&settings.network.nostr_secret_key) {
+            return keys;
+        }
+        let keys = Keys::generate();
+        settings.network.nostr_secret_key = keys.secret_hex();
+        keys
+    }
+
+    async fn nostr_connect(&self, ctx: egui::Context) {
+        let relays = self.settings.lock().unwrap().network.nostr_relays.clone();
+        let keys = self.nostr_keys();
+        self.set_nostr_status("Connecting to relays...");
+        let (signaling, mut signals) = match NostrSignaling::connect(&relays, keys).await {
+            Ok(connection) => connection,
+            Err(err) => {
+                self.set_nostr_status(format!("Failed to connect: {}", err));
+                return;
+            }
+        };
+        {
+            let mut state = self.nostr.lock().unwrap();
+            state.signaling = Some(signaling);
+            state.status = "Listening for calls".to_owned();
+        }
+
+        let app = self.clone();
+        tokio::spawn(async move {
+            while let Some(signal) = signals.recv().await {
+                app.handle_nostr_signal(signal).await;
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    async fn nostr_disconnect(&self) {
+        {
+            let mut state = self.nostr.lock().unwrap();
+            state.signaling = None;
+            state.status = "Disconnected".to_owned();
+        }
+        self.close_peer_connection().await;
+    }
+
+    async fn nostr_call(&self) {
+        let Some((signaling, peer)) = self.nostr_signaling() else {
+            return;
+        };
+        self.set_nostr_status("Sending offer...");
+        self.create_peer_connection(false).await;
+        self.set_history_peer(&peer);
+        self.add_configured_transceivers().await;
+        self.open_control_channel().await;
+        self.create_offer().await;
+        let sdp = self.local_sdp.get().to_string();
+        match signaling.send(&peer, &SignalPayload::Offer { sdp }) {
+            Ok(()) => self.set_nostr_status("Offer sent, waiting for answer..."),
+            Err(err) => self.set_nostr_status(format!("Failed to send offer: {}", err)),
+        }
+    }
+
+    fn nostr_signaling(&self) -> Option<(NostrSignaling, String)> {
+        let state = self.nostr.lock().unwrap();
+        let signaling = state.signaling.clone()?;
+        Some((signaling, state.peer.trim().to_owned()))
+    }
+
+    async fn handle_nostr_signal(&self, signal: NostrSignal) {
+        let Some((signaling, peer)) = self.nostr_signaling() else {
+            return;
+        };
+        let NostrSignal { from, payload } = signal;
+        match payload {
+            SignalPayload::Offer { sdp } => {
+                if !peer.is_empty() && peer != from {
+                    info!(
+                        "Ignoring Nostr offer from {}, we are talking to {}",
+                        from, peer
+                    );
+                    return;
+                }
+                if !self.admit_caller("nostr", &from, &sdp) {
+                    return;
+                }
+                self.nostr.lock().unwrap().peer = from.clone();
+                self.set_nostr_status("Answering offer...");
+                self.create_peer_connection(false).await;
+                self.open_control_channel().await;
+                match self.answer_remote_offer(sdp).await {
+                    Ok(sdp) => match signaling.send(&from, &SignalPayload::Answer { sdp }) {
+                        Ok(()) => self.set_nostr_status("Answer sent, connecting..."),
+                        Err(err) => {
+                            self.set_nostr_status(format!("Failed to send answer: {}", err))
+                        }
+                    },
+                    Err(err) => self.set_nostr_status(format!("Failed to answer: {}", err)),
+                }
+            }
+            SignalPayload::Answer { sdp } => {
+                if peer != from {
+                    info!("Ignoring Nostr answer from {}", from);
+                    return;
+                }
+                self.remote_sdp.set(sdp);
+                self.handle_answer().await;
+                self.set_nostr_status("Answer received, connecting...");
+            }
+        }
+    }
+}
+
+impl WebRTCApp {
+    pub(crate) fn nostr_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.nostr.lock().unwrap();
+        let connected = state.signaling.is_some();
+
+        if let Some(signaling) = &state.signaling {
+            let public_key = signaling.public_key();
+            ui.horizontal(|ui| {
+                ui.label(format!("Your public key: {}...", &public_key[..16]));
+                if ui.small_button("Copy").clicked() {
+                    ui.output_mut(|output| output.copied_text = public_key);
+                }
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.label("Peer public key:");
+            ui.add(
+                egui::TextEdit::singleline(&mut state.peer)
+                    .hint_text("hex, leave empty to accept any caller")
+                    .desired_width(f32::INFINITY),
+            );
+        });
+
+        ui.horizontal(|ui| {
+            if !connected && ui.button("Connect to Relays").clicked() {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.nostr_connect(ctx.clone()).await;
+                    ctx.request_repaint();
+                });
+            }
+            if connected {
+                if ui
+                    .add_enabled(!state.peer.trim().is_empty(), egui::Button::new("Call"))
+                    .clicked()
+                {
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.nostr_call().await;
+                        ctx.request_repaint();
+                    });
+                }
+                if ui.button("Disconnect").clicked() {
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.nostr_disconnect().await;
+                        ctx.request_repaint();
+                    });
+                }
+            }
+            ui.label(&state.status);
+        });
+    }
+}
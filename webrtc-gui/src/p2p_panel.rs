@@ -0,0 +1,350 @@
+//! Decentralized calls over libp2p: peers dial each other by `PeerId`,
+//! found through the DHT, with no signaling server of our own. When ICE
+//! fails the call can carry on as text over the libp2p connection, which
+//! may itself go through a circuit relay.
+
+use std::collections::HashMap;
+
+use eframe::egui;
+use log::info;
+use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
+use webrtc_core::bounded::{BoundedBuffer, DropPolicy};
+use webrtc_core::signaling::p2p::{self, Keypair, P2pEvent, P2pSignal, P2pSignaling};
+use webrtc_core::signaling::SignalPayload;
+
+use crate::WebRTCApp;
+
+const MAX_MESSAGES: usize = 200;
+const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+
+pub struct P2pState {
+    /// A `PeerId` or `/p2p/` multiaddr to call, or the peer who called us.
+    pub(crate) peer: String,
+    signaling: Option<P2pSignaling>,
+    listen_addresses: Vec<String>,
+    /// Connected peers, and whether the connection goes through a relay.
+    connections: HashMap<String, bool>,
+    /// `PeerId` of the peer in the current call.
+    call_peer: Option<String>,
+    /// ICE failed and the call continues as text over libp2p.
+    fallback: bool,
+    message: String,
+    messages: BoundedBuffer<String>,
+    status: String,
+}
+
+impl Default for P2pState {
+    fn default() -> Self {
+        Self {
+            peer: String::new(),
+            signaling: None,
+            listen_addresses: vec![],
+            connections: HashMap::new(),
+            call_peer: None,
+            fallback: false,
+            message: String::new(),
+            messages: BoundedBuffer::new(MAX_MESSAGES, MAX_MESSAGE_BYTES, DropPolicy::DropOldest),
+            status: String::new(),
+        }
+    }
+}
+
+impl WebRTCApp {
+    fn set_p2p_status(&self, status: impl Into<String>) {
+        self.p2p.lock().unwrap().status = status.into();
+    }
+
+    /// Our identity, created and remembered the first time it is needed.
+    fn p2p_keypair(&self) -> Keypair {
+        let mut settings = self.settings.lock().unwrap();
+        if let Ok(keypair) = p2p::decode_keypair(&settings.network.libp2p_key) {
+            return keypair;
+        }
+        let keypair = p2p::generate_keypair();
+        settings.network.libp2p_key = p2p::encode_keypair(&keypair);
+        keypair
+    }
+
+    fn p2p_start(&self, ctx: egui::Context) {
+        let network = self.settings.lock().unwrap().network.clone();
+        let started = P2pSignaling::start(
+            self.p2p_keypair(),
+            &network.libp2p_bootstrap,
+            &network.libp2p_relays,
+        );
+        let (signaling, mut events) = match started {
+            Ok(started) => started,
+            Err(err) => {
+                self.set_p2p_status(format!("Failed to start libp2p: {}", err));
+                return;
+            }
+        };
+        {
+            let mut state = self.p2p.lock().unwrap();
+            state.signaling = Some(signaling);
+            state.listen_addresses.clear();
+            state.status = "Joining the DHT, listening for calls".to_owned();
+        }
+
+        let app = self.clone();
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                match event {
+                    P2pEvent::Listening(address) => {
+                        app.p2p
+                            .lock()
+                            .unwrap()
+                            .listen_addresses
+                            .push(address.to_string());
+                    }
+                    P2pEvent::Signal(signal) => app.handle_p2p_signal(signal).await,
+                    P2pEvent::Connected { peer, relayed } => {
+                        app.p2p.lock().unwrap().connections.insert(peer, relayed);
+                    }
+                    P2pEvent::Text { from, text } => {
+                        let mut state = app.p2p.lock().unwrap();
+                        if state.call_peer.as_ref() == Some(&from) {
+                            state.messages.push(format!("Peer: {}", text));
+                        } else {
+                            info!("Ignoring libp2p message from {}", from);
+                        }
+                    }
+                    P2pEvent::SendFailed { to, reason } => {
+                        app.set_p2p_status(format!("Could not reach {}: {}", to, reason));
+                    }
+                }
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    async fn p2p_stop(&self) {
+        {
+            let mut state = self.p2p.lock().unwrap();
+            *state = P2pState {
+                peer: std::mem::take(&mut state.peer),
+                status: "Stopped".to_owned(),
+                ..P2pState::default()
+            };
+        }
+        self.close_peer_connection().await;
+    }
+
+    fn p2p_signaling(&self) -> Option<(P2pSignaling, String)> {
+        let state = self.p2p.lock().unwrap();
+        let signaling = state.signaling.clone()?;
+        Some((signaling, state.peer.trim().to_owned()))
+    }
+
+    async fn p2p_call(&self) {
+        let Some((signaling, peer)) = self.p2p_signaling() else {
+            return;
+        };
+        self.set_p2p_status("Sending offer...");
+        let id = p2p::parse_peer(&peer).ok().map(|(id, _)| id.to_string());
+        self.create_peer_connection(false).await;
+        if let Some(id) = &id {
+            self.set_history_peer(id);
+        }
+        self.add_configured_transceivers().await;
+        self.open_control_channel().await;
+        self.create_offer().await;
+        let sdp = self.local_sdp.get().to_string();
+        self.start_p2p_call(id);
+        match signaling.send(&peer, SignalPayload::Offer { sdp }) {
+            Ok(()) => self.set_p2p_status("Finding peer and sending offer..."),
+            Err(err) => self.set_p2p_status(format!("Failed to send offer: {}", err)),
+        }
+    }
+
+    fn start_p2p_call(&self, peer: Option<String>) {
+        let mut state = self.p2p.lock().unwrap();
+        state.call_peer = peer;
+        state.fallback = false;
+        state.messages.clear();
+    }
+
+    /// Whether `from` (a bare `PeerId`) is the peer in the call field, which
+    /// may hold a full multiaddr.
+    fn is_p2p_peer(peer: &str, from: &str) -> bool {
+        p2p::parse_peer(peer).is_ok_and(|(id, _)| id.to_string() == from)
+    }
+
+    async fn handle_p2p_signal(&self, signal: P2pSignal) {
+        let Some((signaling, peer)) = self.p2p_signaling() else {
+            return;
+        };
+        let P2pSignal { from, payload } = signal;
+        match payload {
+            SignalPayload::Offer { sdp } => {
+                if !peer.is_empty() && !Self::is_p2p_peer(&peer, &from) {
+                    info!(
+                        "Ignoring libp2p offer from {}, we are talking to {}",
+                        from, peer
+                    );
+                    return;
+                }
+                if !self.admit_caller("libp2p", &from, &sdp) {
+                    return;
+                }
+                if peer.is_empty() {
+                    self.p2p.lock().unwrap().peer = from.clone();
+                }
+                self.start_p2p_call(Some(from.clone()));
+                self.set_p2p_status("Answering offer...");
+                self.create_peer_connection(false).await;
+                self.open_control_channel().await;
+                match self.answer_remote_offer(sdp).await {
+                    Ok(sdp) => match signaling.send(&from, SignalPayload::Answer { sdp }) {
+                        Ok(()) => self.set_p2p_status("Answer sent, connecting..."),
+                        Err(err) => self.set_p2p_status(format!("Failed to send answer: {}", err)),
+                    },
+                    Err(err) => self.set_p2p_status(format!("Failed to answer: {}", err)),
+                }
+            }
+            SignalPayload::Answer { sdp } => {
+                if !Self::is_p2p_peer(&peer, &from) {
+                    info!("Ignoring libp2p answer from {}", from);
+                    return;
+                }
+                self.remote_sdp.set(sdp);
+                self.handle_answer().await;
+                self.set_p2p_status("Answer received, connecting...");
+            }
+        }
+    }
+}
+
+impl WebRTCApp {
+    /// Offers the text fallback once ICE has failed on a libp2p call, and
+    /// shows it while it is in use.
+    fn p2p_fallback_ui(&self, ui: &mut egui::Ui, state: &mut P2pState) {
+        let Some(peer) = state.call_peer.clone() else {
+            return;
+        };
+        let ice_failed =
+            self.reconnect.lock().unwrap().ice_state() == RTCIceConnectionState::Failed;
+        if !ice_failed {
+            // Media is flowing again.
+            state.fallback = false;
+            return;
+        }
+        if !state.fallback {
+            ui.horizontal(|ui| {
+                ui.colored_label(egui::Color32::YELLOW, "The direct connection failed.");
+                if ui.button("Continue over libp2p").clicked() {
+                    state.fallback = true;
+                }
+            });
+            return;
+        }
+        let route = match state.connections.get(&peer) {
+            Some(true) => "through a circuit relay",
+            Some(false) => "directly",
+            None => "once the peer is reachable",
+        };
+        ui.colored_label(
+            egui::Color32::GOLD,
+            format!(
+                "Relayed fallback: no media, messages go over libp2p {}",
+                route
+            ),
+        );
+        if let Some(warning) = state.messages.warning("messages") {
+            ui.label(warning);
+        }
+        egui::ScrollArea::vertical()
+            .max_height(120.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for message in state.messages.iter() {
+                    ui.label(message);
+                }
+            });
+        ui.horizontal(|ui| {
+            let edit = ui.text_edit_singleline(&mut state.message);
+            let entered =
+                edit.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter));
+            if (ui.button("Send").clicked() || entered) && !state.message.trim().is_empty() {
+                let text = std::mem::take(&mut state.message);
+                let sent = match &state.signaling {
+                    Some(signaling) => signaling.send_text(&peer, text.clone()),
+                    None => Err(p2p::P2pError::Closed),
+                };
+                match sent {
+                    Ok(()) => {
+                        state.messages.push(format!("You: {}", text));
+                    }
+                    Err(err) => state.status = format!("Failed to send message: {}", err),
+                }
+            }
+        });
+    }
+
+    pub(crate) fn p2p_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.p2p.lock().unwrap();
+        let started = state.signaling.is_some();
+
+        if let Some(signaling) = &state.signaling {
+            let peer_id = signaling.peer_id();
+            ui.horizontal(|ui| {
+                ui.label(format!("Your PeerId: {}", peer_id));
+                if ui.small_button("Copy").clicked() {
+                    ui.output_mut(|output| output.copied_text = peer_id);
+                }
+            });
+            egui::CollapsingHeader::new("Listen addresses").show(ui, |ui| {
+                for address in &state.listen_addresses {
+                    ui.horizontal(|ui| {
+                        ui.label(address);
+                        if ui.small_button("Copy").clicked() {
+                            ui.output_mut(|output| output.copied_text = address.clone());
+                        }
+                    });
+                }
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.label("Peer:");
+            ui.add(
+                egui::TextEdit::singleline(&mut state.peer)
+                    .hint_text("PeerId or /ip4/.../p2p/<id>, empty to accept any caller")
+                    .desired_width(f32::INFINITY),
+            );
+        });
+
+        let mut start = false;
+        ui.horizontal(|ui| {
+            if !started && ui.button("Start libp2p").clicked() {
+                start = true;
+            }
+            if started {
+                if ui
+                    .add_enabled(!state.peer.trim().is_empty(), egui::Button::new("Call"))
+                    .clicked()
+                {
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.p2p_call().await;
+                        ctx.request_repaint();
+                    });
+                }
+                if ui.button("Stop").clicked() {
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.p2p_stop().await;
+                        ctx.request_repaint();
+                    });
+                }
+            }
+            ui.label(&state.status);
+        });
+        self.p2p_fallback_ui(ui, &mut state);
+        drop(state);
+        if start {
+            self.p2p_start(ctx.clone());
+        }
+    }
+}
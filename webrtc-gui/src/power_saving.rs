@@ -0,0 +1,82 @@
+//! Scaling sent video down on battery or when the machine runs hot, and
+//! the status bar line that says so.
+
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+use log::info;
+use webrtc_core::power::{EncodeLimits, PowerState};
+use webrtc_core::snapshot::Snapshot;
+
+use crate::WebRTCApp;
+
+/// Power changes are slow; sysfs is cheap but not worth reading per frame.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+pub struct PowerSavingState {
+    /// What the camera and window share are held to; `None` for as much
+    /// as the source gives.
+    limits: Snapshot<Option<EncodeLimits>>,
+    /// What the power state calls for, whether or not it is applied.
+    wanted: Option<EncodeLimits>,
+    /// Full quality for this session, whatever the battery says.
+    full_quality: bool,
+    checked: Option<Instant>,
+}
+
+impl PowerSavingState {
+    pub(crate) fn limits(&self) -> Snapshot<Option<EncodeLimits>> {
+        self.limits.clone()
+    }
+}
+
+impl WebRTCApp {
+    /// Rereads the battery and thermal state now and then, and publishes
+    /// the limits that follow from it.
+    pub(crate) fn poll_power_saving(&self, ctx: &egui::Context) {
+        let enabled = self.settings.lock().unwrap().media.power_saving;
+        let mut state = self.power_saving.lock().unwrap();
+        if state
+            .checked
+            .is_none_or(|checked| checked.elapsed() >= POLL_INTERVAL)
+        {
+            state.checked = Some(Instant::now());
+            state.wanted = PowerState::read().encode_limits();
+            ctx.request_repaint_after(POLL_INTERVAL);
+        }
+        let applied = state.wanted.filter(|_| enabled && !state.full_quality);
+        if *state.limits.get() != applied {
+            match &applied {
+                Some(limits) => info!("{}", limits),
+                None => info!("Sending video at full quality"),
+            }
+            state.limits.set(applied);
+        }
+    }
+
+    /// Whether there is anything for the status bar to say.
+    pub(crate) fn power_saving_active(&self) -> bool {
+        self.settings.lock().unwrap().media.power_saving
+            && self.power_saving.lock().unwrap().wanted.is_some()
+    }
+
+    pub(crate) fn power_saving_ui(&self, ui: &mut egui::Ui) {
+        let mut state = self.power_saving.lock().unwrap();
+        let Some(wanted) = state.wanted else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            if state.full_quality {
+                ui.label(format!(
+                    "{}: keeping full quality for this session",
+                    wanted.reason()
+                ));
+            } else {
+                ui.label(wanted.to_string());
+            }
+            ui.checkbox(&mut state.full_quality, "Full quality anyway")
+                .on_hover_text("Turn this off for good under Settings, Media");
+        });
+    }
+}
@@ -0,0 +1,286 @@
+//! ICE restart when the connection drops, with progress shown in the UI.
+
+use std::sync::{Arc, Mutex};
+
+use eframe::egui;
+use log::info;
+use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
+use webrtc::peer_connection::offer_answer_options::RTCOfferOptions;
+use webrtc::peer_connection::sdp::sdp_type::RTCSdpType;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc_core::failover::strip_relay_candidates;
+use webrtc_core::signaling::SignalPayload;
+use webrtc_core::stats::MarkerKind;
+
+use crate::WebRTCApp;
+
+/// Automatic restarts attempted before leaving it to the user.
+const MAX_AUTO_RESTARTS: u32 = 3;
+
+pub struct ReconnectState {
+    ice_state: RTCIceConnectionState,
+    /// An ICE restart offer is out and we are waiting for its answer.
+    restarting: bool,
+    /// Set by the ICE state callback; picked up on the next frame.
+    auto_restart_pending: bool,
+    attempts: u32,
+    /// Relay address to leave out of the next restart offer.
+    avoid_relay: Option<String>,
+    status: String,
+    ctx: Option<egui::Context>,
+}
+
+impl Default for ReconnectState {
+    fn default() -> Self {
+        Self {
+            ice_state: RTCIceConnectionState::New,
+            restarting: false,
+            auto_restart_pending: false,
+            attempts: 0,
+            avoid_relay: None,
+            status: String::new(),
+            ctx: None,
+        }
+    }
+}
+
+impl ReconnectState {
+    fn reset(&mut self) {
+        *self = Self {
+            ctx: self.ctx.take(),
+            ..Self::default()
+        };
+    }
+
+    fn on_ice_state(&mut self, state: RTCIceConnectionState, auto_restart: bool) {
+        self.ice_state = state;
+        match state {
+            RTCIceConnectionState::Connected | RTCIceConnectionState::Completed => {
+                if self.attempts > 0 || !self.status.is_empty() {
+                    self.status = "Reconnected".to_owned();
+                }
+                self.restarting = false;
+                self.attempts = 0;
+            }
+            RTCIceConnectionState::Disconnected | RTCIceConnectionState::Failed => {
+                self.status = if state == RTCIceConnectionState::Failed {
+                    "Connection failed".to_owned()
+                } else {
+                    "Connection interrupted".to_owned()
+                };
+                // A restart that ends in failure frees us to try again.
+                if state == RTCIceConnectionState::Failed {
+                    self.restarting = false;
+                }
+                if auto_restart && !self.restarting && self.attempts < MAX_AUTO_RESTARTS {
+                    self.auto_restart_pending = true;
+                }
+            }
+            RTCIceConnectionState::Closed => self.reset(),
+            _ => {}
+        }
+        if let Some(ctx) = &self.ctx {
+            ctx.request_repaint();
+        }
+    }
+
+    pub(crate) fn ice_state(&self) -> RTCIceConnectionState {
+        self.ice_state
+    }
+
+    /// Schedules an ICE restart that steers away from a degraded relay.
+    pub(crate) fn request_failover(&mut self, relay: String) {
+        if self.restarting || self.attempts >= MAX_AUTO_RESTARTS {
+            return;
+        }
+        self.status = "TURN relay degraded, failing over...".to_owned();
+        self.avoid_relay = Some(relay);
+        self.auto_restart_pending = true;
+        if let Some(ctx) = &self.ctx {
+            ctx.request_repaint();
+        }
+    }
+}
+
+impl WebRTCApp {
+    /// Tracks ICE connection state on a new peer connection so drops can be
+    /// recovered with an ICE restart.
+    pub(crate) fn watch_ice_state(&self, pc: &RTCPeerConnection, auto_restart: bool) {
+        let reconnect = Arc::clone(&self.reconnect);
+        let stats = Arc::clone(&self.stats);
+        reconnect.lock().unwrap().reset();
+        pc.on_ice_connection_state_change(Box::new(move |state| {
+            info!("ICE Connection State: {:?}", state);
+            stats
+                .lock()
+                .unwrap()
+                .mark(MarkerKind::IceState, state.to_string());
+            if state == RTCIceConnectionState::Connected {
+                info!("ICE Connection Established");
+            }
+            reconnect.lock().unwrap().on_ice_state(state, auto_restart);
+            Box::pin(async {})
+        }));
+    }
+
+    fn set_reconnect_status(reconnect: &Mutex<ReconnectState>, status: impl Into<String>) {
+        let mut state = reconnect.lock().unwrap();
+        state.status = status.into();
+        if let Some(ctx) = &state.ctx {
+            ctx.request_repaint();
+        }
+    }
+
+    /// Sends a fresh offer with new ICE credentials over the room, or leaves
+    /// it in the local SDP box for manual signaling.
+    async fn ice_restart(&self, automatic: bool) {
+        let pc = self.peer_connection.lock().await.clone();
+        let Some(pc) = pc else {
+            return;
+        };
+        // Only the side that made the original offer restarts on its own, so
+        // both peers do not send restart offers at each other.
+        let offerer = pc
+            .current_local_description()
+            .await
+            .is_some_and(|description| description.sdp_type == RTCSdpType::Offer);
+        let avoid_relay = self.reconnect.lock().unwrap().avoid_relay.take();
+        if automatic && !offerer {
+            let status = if avoid_relay.is_some() {
+                "TURN relay degraded; only the offering side can fail over"
+            } else {
+                "Connection interrupted, waiting for peer to restart ICE..."
+            };
+            Self::set_reconnect_status(&self.reconnect, status);
+            return;
+        }
+
+        let attempt = {
+            let mut state = self.reconnect.lock().unwrap();
+            state.restarting = true;
+            state.attempts += 1;
+            state.attempts
+        };
+        Self::set_reconnect_status(
+            &self.reconnect,
+            format!("Restarting ICE (attempt {})...", attempt),
+        );
+
+        let options = RTCOfferOptions {
+            ice_restart: true,
+            ..Default::default()
+        };
+        let result = async {
+            let offer = pc.create_offer(Some(options)).await?;
+            let mut gathered = pc.gathering_complete_promise().await;
+            pc.set_local_description(offer).await?;
+            let _ = gathered.recv().await;
+            pc.local_description()
+                .await
+                .ok_or(webrtc::Error::ErrConnectionClosed)
+        }
+        .await;
+        let offer = match result {
+            Ok(offer) => offer,
+            Err(err) => {
+                self.reconnect.lock().unwrap().restarting = false;
+                Self::set_reconnect_status(&self.reconnect, format!("ICE restart failed: {}", err));
+                return;
+            }
+        };
+        let sdp = match &avoid_relay {
+            Some(relay) => strip_relay_candidates(&offer.sdp, relay),
+            None => offer.sdp,
+        };
+        self.local_sdp.set(sdp.clone());
+
+        match self.room_partner() {
+            Some(partner) => {
+                self.send_room_signal(&partner, SignalPayload::Offer { sdp });
+                Self::set_reconnect_status(
+                    &self.reconnect,
+                    format!(
+                        "Restart offer sent (attempt {}), waiting for answer...",
+                        attempt
+                    ),
+                );
+            }
+            None => Self::set_reconnect_status(
+                &self.reconnect,
+                "Restart offer ready: send the local SDP to your peer and handle their answer",
+            ),
+        }
+    }
+
+    /// Applies the answer to an outstanding ICE restart. Returns false when no
+    /// restart is in progress so the caller can treat it as a normal answer.
+    pub(crate) async fn finish_ice_restart(&self, sdp: &str) -> bool {
+        if !self.reconnect.lock().unwrap().restarting {
+            return false;
+        }
+        let pc = self.peer_connection.lock().await.clone();
+        let Some(pc) = pc else {
+            return false;
+        };
+        let result = match RTCSessionDescription::answer(sdp.to_owned()) {
+            Ok(answer) => pc.set_remote_description(answer).await,
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(()) => Self::set_reconnect_status(
+                &self.reconnect,
+                "Restart answer received, reconnecting...",
+            ),
+            Err(err) => {
+                self.reconnect.lock().unwrap().restarting = false;
+                Self::set_reconnect_status(&self.reconnect, format!("ICE restart failed: {}", err));
+            }
+        }
+        true
+    }
+
+    /// Starts a pending automatic restart; called once per frame.
+    pub(crate) fn poll_reconnect(&self, ctx: &egui::Context) {
+        let mut state = self.reconnect.lock().unwrap();
+        if state.ctx.is_none() {
+            state.ctx = Some(ctx.clone());
+        }
+        if std::mem::take(&mut state.auto_restart_pending) {
+            let app = self.clone();
+            tokio::spawn(async move {
+                app.ice_restart(true).await;
+            });
+        }
+    }
+
+    pub(crate) fn reconnect_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let state = self.reconnect.lock().unwrap();
+        let lost = matches!(
+            state.ice_state,
+            RTCIceConnectionState::Disconnected | RTCIceConnectionState::Failed
+        );
+        if !lost && state.status.is_empty() {
+            return;
+        }
+        ui.horizontal(|ui| {
+            let color = if lost {
+                egui::Color32::YELLOW
+            } else {
+                egui::Color32::GREEN
+            };
+            if state.restarting {
+                ui.spinner();
+            }
+            ui.colored_label(color, &state.status);
+            if lost && !state.restarting && ui.button("Restart ICE").clicked() {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.ice_restart(false).await;
+                    ctx.request_repaint();
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,169 @@
+//! Trickled remote ICE candidates. Candidates that arrive before the remote
+//! description has been set are held back instead of failing, and applied
+//! as soon as the description is in.
+
+use std::sync::Arc;
+
+use eframe::egui;
+use log::{info, warn};
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc_core::bounded::{BoundedBuffer, DropPolicy};
+
+use crate::WebRTCApp;
+
+/// A peer trickles a few dozen candidates at most; the earliest ones are
+/// the host candidates, so those are the ones worth keeping.
+const MAX_QUEUED: usize = 64;
+const MAX_QUEUED_BYTES: usize = 32 * 1024;
+
+pub struct RemoteCandidates {
+    queued: BoundedBuffer<RTCIceCandidateInit>,
+    /// Applied to the current peer connection so far.
+    applied: usize,
+    /// A candidate typed or pasted in by hand.
+    input: String,
+    status: String,
+}
+
+impl Default for RemoteCandidates {
+    fn default() -> Self {
+        Self {
+            queued: BoundedBuffer::new(MAX_QUEUED, MAX_QUEUED_BYTES, DropPolicy::DropNewest),
+            applied: 0,
+            input: String::new(),
+            status: String::new(),
+        }
+    }
+}
+
+impl RemoteCandidates {
+    /// Forgets the candidates of a peer connection that is being replaced.
+    pub(crate) fn reset(&mut self) {
+        self.queued.clear();
+        self.applied = 0;
+        self.status.clear();
+    }
+
+    fn queue(&mut self, candidate: RTCIceCandidateInit) {
+        if self.queued.push(candidate) {
+            warn!("Remote candidate queue is full, dropping a candidate");
+        }
+    }
+}
+
+/// Reads a candidate as trickled in JSON (`{"candidate": .., "sdpMid": ..}`)
+/// or as a bare `candidate:` line.
+fn parse_candidate(text: &str) -> Option<RTCIceCandidateInit> {
+    let text = text.trim();
+    let candidate =
+        serde_json::from_str::<RTCIceCandidateInit>(text).unwrap_or_else(|_| RTCIceCandidateInit {
+            candidate: text.strip_prefix("a=").unwrap_or(text).to_owned(),
+            ..Default::default()
+        });
+    candidate
+        .candidate
+        .starts_with("candidate:")
+        .then_some(candidate)
+}
+
+impl WebRTCApp {
+    /// Adds a remote candidate to the current peer connection, or queues
+    /// it until the remote description is set.
+    pub(crate) async fn add_remote_candidate(&self, candidate: RTCIceCandidateInit) {
+        let pc = self.peer_connection.lock().await.clone();
+        let pc = match pc {
+            Some(pc) if pc.remote_description().await.is_some() => pc,
+            _ => {
+                info!("Queueing remote candidate until the remote description is set");
+                self.remote_candidates.lock().unwrap().queue(candidate);
+                return;
+            }
+        };
+        let result = pc.add_ice_candidate(candidate.clone()).await;
+        let mut state = self.remote_candidates.lock().unwrap();
+        match result {
+            Ok(()) => {
+                state.applied += 1;
+                state.status.clear();
+            }
+            // The description was rolled back in the meantime.
+            Err(webrtc::Error::ErrNoRemoteDescription) => state.queue(candidate),
+            Err(err) => {
+                info!("Failed to add remote candidate: {:?}", err);
+                state.status = format!("Failed to add the candidate: {}", err);
+            }
+        }
+    }
+
+    /// Applies the queued candidates to `pc`, once its remote description
+    /// has been set.
+    pub(crate) async fn apply_queued_candidates(&self, pc: &Arc<RTCPeerConnection>) {
+        let queued: Vec<_> = self
+            .remote_candidates
+            .lock()
+            .unwrap()
+            .queued
+            .drain()
+            .collect();
+        if queued.is_empty() {
+            return;
+        }
+        info!("Applying {} queued remote candidates", queued.len());
+        let (mut applied, mut failed) = (0, 0);
+        for candidate in queued {
+            match pc.add_ice_candidate(candidate).await {
+                Ok(()) => applied += 1,
+                Err(err) => {
+                    info!("Failed to add queued remote candidate: {:?}", err);
+                    failed += 1;
+                }
+            }
+        }
+        let mut state = self.remote_candidates.lock().unwrap();
+        state.applied += applied;
+        state.status = if failed > 0 {
+            format!("{} queued candidates could not be added", failed)
+        } else {
+            String::new()
+        };
+    }
+
+    pub(crate) fn remote_candidates_ui(&self, ui: &mut egui::Ui) {
+        let mut state = self.remote_candidates.lock().unwrap();
+        ui.horizontal(|ui| {
+            ui.label("Remote candidate:");
+            ui.text_edit_singleline(&mut state.input);
+            let candidate = parse_candidate(&state.input);
+            let button = ui
+                .add_enabled(candidate.is_some(), egui::Button::new("Add Candidate"))
+                .on_disabled_hover_text("Enter a candidate: line or its JSON");
+            if let (true, Some(candidate)) = (button.clicked(), candidate) {
+                state.input.clear();
+                let app = self.clone();
+                let ctx = ui.ctx().clone();
+                tokio::spawn(async move {
+                    app.add_remote_candidate(candidate).await;
+                    ctx.request_repaint();
+                });
+            }
+        });
+        ui.horizontal(|ui| {
+            if state.queued.is_empty() {
+                ui.label(format!("{} remote candidates applied", state.applied));
+            } else {
+                ui.label(format!(
+                    "{} remote candidates queued until the remote description is set",
+                    state.queued.len()
+                ));
+                if ui.small_button("Discard").clicked() {
+                    state.queued.clear();
+                }
+            }
+            ui.label(&state.status);
+        });
+        if let Some(warning) = state.queued.warning("remote candidates") {
+            ui.colored_label(egui::Color32::YELLOW, warning);
+        }
+    }
+}
@@ -0,0 +1,531 @@
+//! Remote control over the "input" data channel, both ways: driving the
+//! window the peer shares from its tile, and letting the peer drive the
+//! window shared from here once the user allows it in a dialog.
+
+use std::sync::{Arc, Mutex};
+
+use eframe::egui;
+use log::info;
+use tokio::sync::mpsc;
+use webrtc::data_channel::RTCDataChannel;
+use webrtc_core::remote_control::{
+    self, Injector, InputMessage, Modifiers, MouseButton, RemoteControlError, RemoteKey,
+    INPUT_LABEL, INPUT_STREAM_ID,
+};
+use webrtc_core::screen::WindowInfo;
+
+use crate::negotiated_channel::{self, ChannelPanel, NegotiatedChannel};
+use crate::video_view::VideoView;
+use crate::window_share_panel::WindowShareState;
+use crate::WebRTCApp;
+
+/// Points of smooth scrolling that make one wheel step.
+const POINTS_PER_STEP: f32 = 40.0;
+
+/// Controlling the peer's window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Viewer {
+    Off,
+    Asked,
+    Controlling,
+}
+
+pub struct RemoteControlState {
+    channel: NegotiatedChannel,
+    /// Sends what is queued, in order, on the channel of the current peer
+    /// connection.
+    outgoing: Option<mpsc::UnboundedSender<InputMessage>>,
+    /// The peer asks to control this window, shared from here, and the
+    /// user has not answered.
+    asked: Option<WindowInfo>,
+    /// The window the peer controls, and what replays their input on it.
+    controlled: Option<(WindowInfo, Injector)>,
+    viewer: Viewer,
+    /// What was last sent while controlling, to send only changes.
+    pointer: Option<egui::Pos2>,
+    modifiers: Modifiers,
+    /// Buttons pressed on the peer's picture and not yet let go.
+    buttons: Vec<MouseButton>,
+    /// Scrolling short of a whole wheel step, kept for the next event.
+    scroll: egui::Vec2,
+    status: String,
+    /// Requests arrive on background tasks; redraw when they do.
+    ctx: egui::Context,
+}
+
+impl RemoteControlState {
+    pub fn new(ctx: &egui::Context) -> Self {
+        Self {
+            channel: NegotiatedChannel::default(),
+            outgoing: None,
+            asked: None,
+            controlled: None,
+            viewer: Viewer::Off,
+            pointer: None,
+            modifiers: Modifiers::default(),
+            buttons: Vec::new(),
+            scroll: egui::Vec2::ZERO,
+            status: String::new(),
+            ctx: ctx.clone(),
+        }
+    }
+
+    fn send(&self, message: InputMessage) {
+        if let Some(outgoing) = &self.outgoing {
+            let _ = outgoing.send(message);
+        }
+    }
+
+    /// Stops replaying the peer's input, letting go of anything it holds,
+    /// and tells the peer.
+    fn revoke(&mut self, status: &str) {
+        if self.controlled.take().is_some() {
+            self.send(InputMessage::Revoke);
+            self.status = status.to_owned();
+        }
+    }
+
+    fn stop_viewing(&mut self) {
+        self.viewer = Viewer::Off;
+        self.pointer = None;
+        self.modifiers = Modifiers::default();
+        self.buttons.clear();
+        self.scroll = egui::Vec2::ZERO;
+    }
+
+    /// Answers `message` from the peer; `shared` is the window shared from
+    /// here, if any.
+    fn receive(&mut self, message: InputMessage, shared: Option<WindowInfo>) {
+        match message {
+            InputMessage::Request => {
+                let refusal = if shared.is_none() {
+                    Some("no window is shared".to_owned())
+                } else if !remote_control::can_replay() {
+                    Some(RemoteControlError::Unsupported.to_string())
+                } else {
+                    None
+                };
+                match refusal {
+                    Some(reason) => self.send(InputMessage::Deny { reason }),
+                    None => self.asked = shared,
+                }
+            }
+            InputMessage::Release => {
+                self.asked = None;
+                if self.controlled.take().is_some() {
+                    self.status = "The peer gave back control".to_owned();
+                }
+            }
+            InputMessage::Allow if self.viewer == Viewer::Asked => {
+                self.viewer = Viewer::Controlling;
+                self.status =
+                    "You control the peer's window; Escape gives back the keyboard".to_owned();
+            }
+            InputMessage::Deny { reason } if self.viewer == Viewer::Asked => {
+                self.stop_viewing();
+                self.status = format!("The peer did not allow control: {}", reason);
+            }
+            InputMessage::Revoke if self.viewer != Viewer::Off => {
+                self.stop_viewing();
+                self.status = "The peer took back control".to_owned();
+            }
+            message if message.is_input() => {
+                let Some((_, injector)) = &mut self.controlled else {
+                    return;
+                };
+                match injector.inject(&message) {
+                    Ok(()) => {}
+                    Err(RemoteControlError::NotFocused) => {
+                        self.status = "Ignored the peer's typing: the shared window does not \
+                                       have the keyboard"
+                            .to_owned();
+                    }
+                    Err(err) => {
+                        info!("Failed to replay the peer's input: {}", err);
+                        self.status = err.to_string();
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.ctx.request_repaint();
+    }
+
+    /// Drops the channel of a peer connection that is being replaced, and
+    /// any control with it.
+    pub(crate) fn reset(&mut self) {
+        self.asked = None;
+        self.controlled = None;
+        self.stop_viewing();
+        self.channel = NegotiatedChannel::default();
+        self.outgoing = None;
+        self.status.clear();
+    }
+}
+
+impl ChannelPanel for RemoteControlState {
+    fn negotiated(&mut self) -> &mut NegotiatedChannel {
+        &mut self.channel
+    }
+
+    fn replaced(&mut self) {
+        self.reset();
+    }
+
+    fn closed(&mut self) {
+        self.asked = None;
+        self.controlled = None;
+        self.stop_viewing();
+        self.ctx.request_repaint();
+    }
+}
+
+/// Sends what `outgoing` queues on `channel`, until the queue is dropped.
+async fn send_queued(
+    channel: Arc<RTCDataChannel>,
+    mut outgoing: mpsc::UnboundedReceiver<InputMessage>,
+) {
+    while let Some(message) = outgoing.recv().await {
+        let _ = negotiated_channel::send(Some(Arc::clone(&channel)), message.to_json()).await;
+    }
+}
+
+/// The key `key` is sent as: a special key always, and a letter or digit
+/// only for shortcuts, since typing it arrives as text.
+fn remote_key(key: egui::Key, modifiers: egui::Modifiers) -> Option<RemoteKey> {
+    use egui::Key;
+    Some(match key {
+        Key::Enter => RemoteKey::Enter,
+        Key::Tab => RemoteKey::Tab,
+        Key::Backspace => RemoteKey::Backspace,
+        Key::Space => RemoteKey::Space,
+        Key::Delete => RemoteKey::Delete,
+        Key::Home => RemoteKey::Home,
+        Key::End => RemoteKey::End,
+        Key::PageUp => RemoteKey::PageUp,
+        Key::PageDown => RemoteKey::PageDown,
+        Key::ArrowLeft => RemoteKey::Left,
+        Key::ArrowRight => RemoteKey::Right,
+        Key::ArrowUp => RemoteKey::Up,
+        Key::ArrowDown => RemoteKey::Down,
+        Key::F1 => RemoteKey::F(1),
+        Key::F2 => RemoteKey::F(2),
+        Key::F3 => RemoteKey::F(3),
+        Key::F4 => RemoteKey::F(4),
+        Key::F5 => RemoteKey::F(5),
+        Key::F6 => RemoteKey::F(6),
+        Key::F7 => RemoteKey::F(7),
+        Key::F8 => RemoteKey::F(8),
+        Key::F9 => RemoteKey::F(9),
+        Key::F10 => RemoteKey::F(10),
+        Key::F11 => RemoteKey::F(11),
+        Key::F12 => RemoteKey::F(12),
+        key if modifiers.ctrl || modifiers.alt || modifiers.mac_cmd => {
+            let mut name = key.name().chars();
+            match (name.next(), name.next()) {
+                (Some(c), None) if c.is_ascii_alphanumeric() => {
+                    RemoteKey::Char(c.to_ascii_lowercase())
+                }
+                _ => return None,
+            }
+        }
+        _ => return None,
+    })
+}
+
+fn remote_modifiers(modifiers: egui::Modifiers) -> Modifiers {
+    Modifiers {
+        shift: modifiers.shift,
+        ctrl: modifiers.ctrl,
+        alt: modifiers.alt,
+        meta: modifiers.mac_cmd,
+    }
+}
+
+fn remote_button(button: egui::PointerButton) -> Option<MouseButton> {
+    match button {
+        egui::PointerButton::Primary => Some(MouseButton::Left),
+        egui::PointerButton::Middle => Some(MouseButton::Middle),
+        egui::PointerButton::Secondary => Some(MouseButton::Right),
+        _ => None,
+    }
+}
+
+impl WebRTCApp {
+    /// Creates the input channel on the current peer connection.
+    pub(crate) async fn open_input_channel(&self) {
+        let remote_control = Arc::clone(&self.remote_control);
+        let window_share = Arc::clone(&self.window_share);
+        let channel = self
+            .open_negotiated_channel(
+                &self.remote_control,
+                INPUT_LABEL,
+                INPUT_STREAM_ID,
+                move |message| {
+                    match InputMessage::from_json(&message.into_text()?) {
+                        Ok(message) => {
+                            let shared = shared_window(&window_share);
+                            remote_control.lock().unwrap().receive(message, shared);
+                        }
+                        Err(err) => info!("Ignoring input message: {}", err),
+                    }
+                    None
+                },
+            )
+            .await;
+        let Some(channel) = channel else {
+            return;
+        };
+
+        let (outgoing, queued) = mpsc::unbounded_channel();
+        tokio::spawn(send_queued(channel, queued));
+        self.remote_control.lock().unwrap().outgoing = Some(outgoing);
+    }
+
+    /// Whether this end is driving the peer's window.
+    pub(crate) fn controlling_peer(&self) -> bool {
+        self.remote_control.lock().unwrap().viewer == Viewer::Controlling
+    }
+
+    /// Ends the peer's control once the window they control is no longer
+    /// shared.
+    pub(crate) fn poll_remote_control(&self) {
+        let shared = shared_window(&self.window_share);
+        let mut state = self.remote_control.lock().unwrap();
+        let still_shared = |window: &WindowInfo| shared.as_ref().is_some_and(|s| s.id == window.id);
+        if state.asked.as_ref().is_some_and(|w| !still_shared(w)) {
+            state.asked = None;
+            state.send(InputMessage::Deny {
+                reason: "the share ended".to_owned(),
+            });
+        }
+        if state
+            .controlled
+            .as_ref()
+            .is_some_and(|(w, _)| !still_shared(w))
+        {
+            state.revoke("Control ended with the share");
+        }
+    }
+
+    /// Asks whether the peer may control the shared window.
+    pub(crate) fn remote_control_dialog(&self, ctx: &egui::Context) {
+        let mut state = self.remote_control.lock().unwrap();
+        let Some(window) = state.asked.clone() else {
+            return;
+        };
+        let mut answer = None;
+        egui::Window::new("Remote control")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(format!("The peer asks to control {}.", window));
+                ui.label(
+                    "They will move the pointer, click and type in it as if they sat here. \
+                     Stop them at any time from the top of this window, or by ending the \
+                     share.",
+                );
+                ui.horizontal(|ui| {
+                    if ui.button("Allow Control").clicked() {
+                        answer = Some(true);
+                    }
+                    if ui.button("Deny").clicked() {
+                        answer = Some(false);
+                    }
+                });
+            });
+        match answer {
+            Some(true) => {
+                state.asked = None;
+                match Injector::new(&window) {
+                    Ok(injector) => {
+                        info!("Letting the peer control {}", window);
+                        state.controlled = Some((window, injector));
+                        state.status.clear();
+                        state.send(InputMessage::Allow);
+                    }
+                    Err(err) => {
+                        state.status = format!("Cannot hand over control: {}", err);
+                        state.send(InputMessage::Deny {
+                            reason: err.to_string(),
+                        });
+                    }
+                }
+            }
+            Some(false) => {
+                state.asked = None;
+                state.send(InputMessage::Deny {
+                    reason: "the user said no".to_owned(),
+                });
+            }
+            None => {}
+        }
+    }
+
+    /// While the peer controls a window here, says so, with a way to stop.
+    pub(crate) fn remote_control_indicator_ui(&self, ui: &mut egui::Ui) {
+        let mut state = self.remote_control.lock().unwrap();
+        let Some((window, _)) = &state.controlled else {
+            if !state.status.is_empty() && state.viewer == Viewer::Off {
+                ui.label(&state.status);
+            }
+            return;
+        };
+        ui.colored_label(
+            egui::Color32::RED,
+            format!("The peer controls {}", window.title),
+        );
+        if ui.button("Stop Control").clicked() {
+            state.revoke("You took back control");
+        }
+    }
+
+    /// Below the peer's shared window: asking for control, and while it is
+    /// had, sending what is done over `picture`, the response of `view`.
+    pub(crate) fn remote_control_ui(
+        &self,
+        ui: &mut egui::Ui,
+        view: &VideoView,
+        picture: &egui::Response,
+    ) {
+        let mut state = self.remote_control.lock().unwrap();
+        ui.horizontal(|ui| {
+            match state.viewer {
+                Viewer::Off => {
+                    let open = state.outgoing.is_some();
+                    if ui
+                        .add_enabled(open, egui::Button::new("Request Control"))
+                        .clicked()
+                    {
+                        state.viewer = Viewer::Asked;
+                        state.status = "Asked the peer for control".to_owned();
+                        state.send(InputMessage::Request);
+                    }
+                }
+                Viewer::Asked | Viewer::Controlling => {
+                    if ui.button("Release Control").clicked() {
+                        state.send(InputMessage::Modifiers(Modifiers::default()));
+                        for button in std::mem::take(&mut state.buttons) {
+                            state.send(InputMessage::Button {
+                                button,
+                                down: false,
+                            });
+                        }
+                        state.send(InputMessage::Release);
+                        state.stop_viewing();
+                        state.status.clear();
+                    }
+                }
+            }
+            if state.viewer != Viewer::Off {
+                ui.label(&state.status);
+            }
+        });
+        if state.viewer != Viewer::Controlling {
+            return;
+        }
+
+        let rect = picture.rect;
+        if picture.clicked() || picture.secondary_clicked() {
+            picture.request_focus();
+        }
+        let focused = picture.has_focus();
+        if focused {
+            ui.memory_mut(|memory| {
+                memory.set_focus_lock_filter(
+                    picture.id,
+                    egui::EventFilter {
+                        tab: true,
+                        horizontal_arrows: true,
+                        vertical_arrows: true,
+                        escape: false,
+                    },
+                )
+            });
+        }
+        let (events, modifiers) = ui.input(|i| (i.events.clone(), i.modifiers));
+        let modifiers = if focused {
+            remote_modifiers(modifiers)
+        } else {
+            Modifiers::default()
+        };
+        if modifiers != state.modifiers {
+            state.modifiers = modifiers;
+            state.send(InputMessage::Modifiers(modifiers));
+        }
+        for event in events {
+            match event {
+                egui::Event::PointerMoved(pos) => {
+                    let Some(point) = view.picture_point(rect, pos) else {
+                        continue;
+                    };
+                    if state.pointer != Some(point) {
+                        state.pointer = Some(point);
+                        state.send(InputMessage::Move {
+                            x: point.x,
+                            y: point.y,
+                        });
+                    }
+                }
+                egui::Event::PointerButton {
+                    pos,
+                    button,
+                    pressed,
+                    ..
+                } => {
+                    let Some(button) = remote_button(button) else {
+                        continue;
+                    };
+                    if pressed && view.picture_point(rect, pos).is_some() {
+                        state.buttons.push(button);
+                    } else if pressed || !state.buttons.contains(&button) {
+                        continue;
+                    } else {
+                        state.buttons.retain(|held| *held != button);
+                    }
+                    state.send(InputMessage::Button {
+                        button,
+                        down: pressed,
+                    });
+                }
+                egui::Event::MouseWheel { unit, delta, .. } if picture.hovered() => {
+                    let steps = match unit {
+                        egui::MouseWheelUnit::Point => delta / POINTS_PER_STEP,
+                        egui::MouseWheelUnit::Line => delta,
+                        egui::MouseWheelUnit::Page => delta * 10.0,
+                    };
+                    // egui counts towards the top left; the wheel, away.
+                    let scroll = state.scroll - steps;
+                    let whole = egui::vec2(scroll.x.trunc(), scroll.y.trunc());
+                    state.scroll = scroll - whole;
+                    if whole != egui::Vec2::ZERO {
+                        state.send(InputMessage::Scroll {
+                            x: whole.x as i32,
+                            y: whole.y as i32,
+                        });
+                    }
+                }
+                egui::Event::Key {
+                    key,
+                    pressed,
+                    modifiers,
+                    ..
+                } if focused => {
+                    if let Some(key) = remote_key(key, modifiers) {
+                        state.send(InputMessage::Key { key, down: pressed });
+                    }
+                }
+                // Space goes as a key, for games and shortcuts.
+                egui::Event::Text(text) if focused && !text.is_empty() && text != " " => {
+                    state.send(InputMessage::Text { text });
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn shared_window(window_share: &Mutex<WindowShareState>) -> Option<WindowInfo> {
+    window_share.lock().unwrap().shared_window().cloned()
+}
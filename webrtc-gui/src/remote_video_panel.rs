@@ -0,0 +1,850 @@
+//! Remote media of the current call: the video, with a rolling strip of
+//! thumbnails and the last half minute kept for exporting as a clip, any
+//! further video tracks in a grid beside it, and the audio on the selected
+//! speaker, or one picked for the call, kept in sync with it, with a level
+//! meter for each remote audio track. Both can be recorded into a file as
+//! they arrive, or together with our side into one of the whole call.
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use eframe::egui;
+use tokio::time::Duration;
+use webrtc::api::media_engine::MIME_TYPE_OPUS;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+use webrtc_core::audio::{self, AudioLevel, OutputRoute};
+use webrtc_core::clip::{self, ClipBuffer, CLIP_LENGTH};
+use webrtc_core::devices::{DeviceInfo, DeviceKind};
+use webrtc_core::lipsync::{self, LipSync, MAX_CORRECTION};
+use webrtc_core::recording::{
+    self, CallRecording, Recorded, RecordedTracks, RecordingError, RecordingTap,
+};
+use webrtc_core::red::MIME_TYPE_RED;
+use webrtc_core::screen::WINDOW_TRACK_ID;
+use webrtc_core::settings::FileKind;
+use webrtc_core::snapshot::Snapshot;
+use webrtc_core::still;
+use webrtc_core::thumbnails::ThumbnailStrip;
+use webrtc_core::video::{self, VideoFrame};
+
+use crate::level_meter::level_meter_ui;
+use crate::video_view::VideoView;
+use crate::{data_dir, WebRTCApp};
+
+/// Thumbnails kept before the oldest is dropped.
+const STRIP_CAPACITY: usize = 60;
+const VIDEO_WIDTH: f32 = 480.0;
+/// The narrowest tiles get when there are several video tracks.
+const MIN_TILE_WIDTH: f32 = 160.0;
+
+/// A video track after the first, shown in a tile of its own.
+struct VideoTile {
+    /// The track's stream and track ids.
+    label: String,
+    codec: String,
+    frames: Snapshot<Option<VideoFrame>>,
+    /// Made on first show, where there is a context to repaint.
+    view: Option<VideoView>,
+    /// A window the peer shares, which can be asked to control.
+    window: bool,
+}
+
+pub struct RemoteVideoState {
+    frames: Snapshot<Option<VideoFrame>>,
+    view: VideoView,
+    codec: Option<String>,
+    /// The stream and track ids of the first video track.
+    label: String,
+    /// Whether the first video track is a window the peer shares.
+    window: bool,
+    /// The other video tracks, in the order they came.
+    tiles: Vec<VideoTile>,
+    strip: ThumbnailStrip,
+    /// Textures of the small thumbnails, in strip order.
+    textures: VecDeque<egui::TextureHandle>,
+    /// The last frame offered to the strip.
+    polled: Option<Arc<Option<VideoFrame>>>,
+    /// The selected thumbnail's time and large texture.
+    preview: Option<(Duration, egui::TextureHandle)>,
+    clip: ClipBuffer,
+    exporting: bool,
+    clip_status: String,
+    /// Where the last snapshot went, or why it did not.
+    snapshot_status: String,
+    sync: Arc<Mutex<LipSync>>,
+    /// Whether a remote audio track is being played, and how that went.
+    audio: Option<String>,
+    /// The speaker it plays on, followed as it changes.
+    route: OutputRoute,
+    /// A speaker picked for this call only, in place of the selected one.
+    call_speaker: Option<DeviceInfo>,
+    /// A meter for every remote audio track, the played one first.
+    audio_levels: Vec<(String, Snapshot<AudioLevel>)>,
+    /// The codec of the played audio track.
+    audio_codec: Option<String>,
+    recording: RecordingTap,
+    /// Both sides of the call in one file, in place of `recording`.
+    call_recording: Option<CallRecording>,
+    /// Where the recording goes, or how the last one ended.
+    recording_status: String,
+    /// The file of the last recording started, and when, for captions to
+    /// go beside.
+    last_recording: Option<(PathBuf, Instant)>,
+}
+
+impl RemoteVideoState {
+    pub fn new(ctx: &egui::Context, interval: Duration) -> Self {
+        let frames = Snapshot::default();
+        Self {
+            view: VideoView::new("remote_video".to_owned(), frames.clone(), ctx),
+            frames,
+            codec: None,
+            label: String::new(),
+            window: false,
+            tiles: Vec::new(),
+            strip: ThumbnailStrip::new(interval, STRIP_CAPACITY),
+            textures: VecDeque::new(),
+            polled: None,
+            preview: None,
+            clip: ClipBuffer::new(),
+            exporting: false,
+            clip_status: String::new(),
+            snapshot_status: String::new(),
+            sync: Arc::new(Mutex::new(LipSync::new(0))),
+            audio: None,
+            route: OutputRoute::default(),
+            call_speaker: None,
+            audio_levels: Vec::new(),
+            audio_codec: None,
+            recording: RecordingTap::default(),
+            call_recording: None,
+            recording_status: String::new(),
+            last_recording: None,
+        }
+    }
+
+    /// The remote tracks a recording would take in now.
+    fn recordable(&self) -> RecordedTracks {
+        RecordedTracks {
+            // The header needs the picture size, which takes decoding.
+            video: self.codec.clone().filter(|codec| video::can_decode(codec)),
+            audio: self.audio_codec.clone(),
+        }
+        .recordable()
+    }
+
+    pub(crate) fn last_recording(&self) -> Option<(PathBuf, Instant)> {
+        self.last_recording.clone()
+    }
+
+    fn is_recording(&self) -> bool {
+        self.recording.is_recording() || self.call_recording.is_some()
+    }
+
+    fn stop_recording(&mut self) {
+        let stopped = match self.call_recording.take() {
+            Some(call) => Some(call.stop()),
+            None => self.recording.stop(),
+        };
+        if let Some(result) = stopped {
+            self.recording_status = describe(result);
+        }
+    }
+
+    fn reset(&mut self, interval: Duration, sync_offset_ms: i32) {
+        self.frames.set(None);
+        self.codec = None;
+        self.label.clear();
+        self.window = false;
+        self.tiles.clear();
+        self.strip = ThumbnailStrip::new(interval, STRIP_CAPACITY);
+        self.textures.clear();
+        self.polled = None;
+        self.preview = None;
+        self.clip = ClipBuffer::new();
+        self.clip_status.clear();
+        self.snapshot_status.clear();
+        self.sync = Arc::new(Mutex::new(LipSync::new(sync_offset_ms)));
+        self.audio = None;
+        // The last call's playback may still be winding down on the old one.
+        self.route = OutputRoute::default();
+        self.call_speaker = None;
+        self.audio_levels.clear();
+        self.audio_codec = None;
+        // The old call's tracks may still be winding down with the tap.
+        self.stop_recording();
+        self.recording = RecordingTap::default();
+    }
+}
+
+/// `stream / track`, or the track id alone when it has no stream.
+fn track_label(stream_id: &str, track_id: &str) -> String {
+    if stream_id.is_empty() {
+        track_id.to_owned()
+    } else {
+        format!("{} / {}", stream_id, track_id)
+    }
+}
+
+/// Drops the grid tile showing `frames` once its track has ended. A reset
+/// since has already cleared it, and the one in its place is left alone.
+fn remove_tile(state: &Mutex<RemoteVideoState>, frames: Option<Snapshot<Option<VideoFrame>>>) {
+    if let Some(frames) = frames {
+        state
+            .lock()
+            .unwrap()
+            .tiles
+            .retain(|tile| !tile.frames.same(&frames));
+    }
+}
+
+fn image(frame: &VideoFrame) -> egui::ColorImage {
+    egui::ColorImage::from_rgba_unmultiplied([frame.width, frame.height], &frame.rgba)
+}
+
+fn describe(result: Result<Recorded, RecordingError>) -> String {
+    match result {
+        Ok(recorded) => format!(
+            "Recorded {} into {}",
+            timestamp(recorded.duration),
+            recorded.path.display()
+        ),
+        Err(err) => format!("Recording failed: {}", err),
+    }
+}
+
+fn timestamp(at: Duration) -> String {
+    let secs = at.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+impl WebRTCApp {
+    /// Decodes the remote video tracks of `pc` for display, starting a
+    /// fresh thumbnail strip for the first, and plays the first audio track
+    /// on the selected speaker.
+    pub(crate) fn watch_remote_video(
+        &self,
+        pc: &RTCPeerConnection,
+        interval: Duration,
+        sync_offset_ms: i32,
+    ) {
+        self.remote_video
+            .lock()
+            .unwrap()
+            .reset(interval, sync_offset_ms);
+        let route = {
+            let state = self.remote_video.lock().unwrap();
+            let speaker = self.selected_device(DeviceKind::Speaker);
+            state
+                .route
+                .route_to(speaker.as_ref().map(|d| d.id.as_str()));
+            state.route.clone()
+        };
+        let echo = self.echo_reference();
+        let mix = self.call_mix();
+        let state = Arc::clone(&self.remote_video);
+        pc.on_track(Box::new(move |track, receiver, _| {
+            let state = Arc::clone(&state);
+            let route = route.clone();
+            let echo = echo.clone();
+            let mix = mix.clone();
+            Box::pin(async move {
+                let kind = track.kind();
+                let codec = track.codec().capability.mime_type;
+                let label = track_label(&track.stream_id(), &track.id());
+                let window = track.id() == WINDOW_TRACK_ID;
+                let (taken, level, tile) = {
+                    let mut state = state.lock().unwrap();
+                    let mut tile = None;
+                    let taken = match kind {
+                        RTPCodecType::Video if state.codec.is_none() => {
+                            state.codec = Some(codec.clone());
+                            state.label = label;
+                            state.window = window;
+                            true
+                        }
+                        RTPCodecType::Video => {
+                            let frames = Snapshot::default();
+                            state.tiles.push(VideoTile {
+                                label,
+                                codec: codec.clone(),
+                                frames: frames.clone(),
+                                view: None,
+                                window,
+                            });
+                            tile = Some(frames);
+                            false
+                        }
+                        RTPCodecType::Audio if state.audio.is_none() => {
+                            state.audio = Some("Playing remote audio".to_owned());
+                            // RED is played and recorded as the Opus it
+                            // carries.
+                            state.audio_codec =
+                                Some(if codec.eq_ignore_ascii_case(MIME_TYPE_RED) {
+                                    MIME_TYPE_OPUS.to_owned()
+                                } else {
+                                    codec.clone()
+                                });
+                            true
+                        }
+                        _ => false,
+                    };
+                    // Builds that cannot capture audio cannot decode it
+                    // either, so they would show meters stuck at silence.
+                    let metered = kind == RTPCodecType::Audio && audio::can_capture();
+                    let level = metered.then(|| {
+                        let level = Snapshot::default();
+                        let label = if taken {
+                            "Remote audio".to_owned()
+                        } else {
+                            format!("Remote audio {}", track.id())
+                        };
+                        state.audio_levels.push((label, level.clone()));
+                        level
+                    });
+                    (taken, level, tile)
+                };
+                let (sync, recording) = {
+                    let state = state.lock().unwrap();
+                    (Arc::clone(&state.sync), state.recording.clone())
+                };
+                if taken {
+                    tokio::spawn(lipsync::read_sender_reports(
+                        receiver,
+                        kind,
+                        Arc::clone(&sync),
+                    ));
+                }
+                match kind {
+                    RTPCodecType::Video if taken && video::can_decode(&codec) => {
+                        let frames = state.lock().unwrap().frames.clone();
+                        video::play_track_in_sync(track, frames, Some(sync), recording).await;
+                        return;
+                    }
+                    // Lip sync and recordings follow the first track only.
+                    RTPCodecType::Video if video::can_decode(&codec) => {
+                        if let Some(frames) = tile.clone() {
+                            let untaken = RecordingTap::default();
+                            video::play_track_in_sync(track, frames, None, untaken).await;
+                            remove_tile(&state, tile);
+                            return;
+                        }
+                    }
+                    RTPCodecType::Audio if taken => {
+                        let level = level.unwrap_or_default();
+                        let played = audio::play_remote_track(
+                            Arc::clone(&track),
+                            route,
+                            Some(sync),
+                            level.clone(),
+                            echo,
+                            recording.clone(),
+                            mix,
+                        )
+                        .await;
+                        match played {
+                            Ok(()) => return,
+                            Err(err) => {
+                                state.lock().unwrap().audio =
+                                    Some(format!("Remote audio not played: {}", err));
+                                // Metering alone may still work, as on a
+                                // machine without a speaker.
+                                if audio::meter_remote_track(
+                                    Arc::clone(&track),
+                                    level,
+                                    recording.clone(),
+                                )
+                                .await
+                                .is_ok()
+                                {
+                                    return;
+                                }
+                                recording::record_audio_track(track, recording).await;
+                                return;
+                            }
+                        }
+                    }
+                    RTPCodecType::Audio => {
+                        let level = level.unwrap_or_default();
+                        let untaken = RecordingTap::default();
+                        if audio::meter_remote_track(Arc::clone(&track), level, untaken)
+                            .await
+                            .is_ok()
+                        {
+                            return;
+                        }
+                    }
+                    _ => {}
+                }
+                // Keep reading so the stream does not back up.
+                while track.read_rtp().await.is_ok() {}
+                remove_tile(&state, tile);
+            })
+        }));
+    }
+
+    /// Offers newly decoded frames to the thumbnail strip and the clip
+    /// buffer, whether or not the panel is open.
+    pub(crate) fn poll_remote_video(&self, ctx: &egui::Context) {
+        let mut state = self.remote_video.lock().unwrap();
+        let latest = state.frames.get();
+        if state
+            .polled
+            .as_ref()
+            .is_some_and(|polled| Arc::ptr_eq(polled, &latest))
+        {
+            return;
+        }
+        if let Some(frame) = latest.as_ref() {
+            state.clip.offer(frame);
+            if state.strip.offer(frame) {
+                let thumbnail = state.strip.thumbnails().next_back().unwrap();
+                let name = format!("thumbnail_{}", thumbnail.at.as_millis());
+                let texture =
+                    ctx.load_texture(name, image(&thumbnail.small), egui::TextureOptions::LINEAR);
+                state.textures.push_back(texture);
+                while state.textures.len() > state.strip.len() {
+                    state.textures.pop_front();
+                }
+            }
+        }
+        state.polled = Some(latest);
+    }
+
+    fn lip_sync_ui(&self, ui: &mut egui::Ui, sync: &Mutex<LipSync>) {
+        let mut sync = sync.lock().unwrap();
+        let ms = |seconds: f64| (seconds * 1000.0).round();
+        match sync.skew() {
+            None => ui.label("A/V sync: waiting for the sender's reports"),
+            Some(skew) if skew >= 0.0 => {
+                ui.label(format!("A/V sync: audio {} ms behind video", ms(skew)))
+            }
+            Some(skew) => ui.label(format!("A/V sync: audio {} ms ahead of video", ms(-skew))),
+        };
+        let corrections = sync.corrections();
+        if !corrections.audio.is_zero() {
+            ui.label(format!(
+                "Holding audio back {} ms",
+                corrections.audio.as_millis()
+            ));
+        } else if !corrections.video.is_zero() {
+            ui.label(format!(
+                "Holding video back {} ms",
+                corrections.video.as_millis()
+            ));
+        }
+        let max = MAX_CORRECTION.as_millis() as i32;
+        let slider = egui::Slider::new(&mut sync.manual_offset_ms, -max..=max)
+            .text("Audio offset")
+            .suffix(" ms");
+        if ui
+            .add(slider)
+            .on_hover_text("Delays audio (or, below zero, video) on top of what is measured")
+            .changed()
+        {
+            self.settings.lock().unwrap().media.av_sync_offset_ms = sync.manual_offset_ms;
+        }
+    }
+
+    /// A camera button over the top right of `picture` that saves the
+    /// frame on show as a PNG in the snapshots folder.
+    fn snapshot_button_ui(
+        &self,
+        ui: &mut egui::Ui,
+        ctx: &egui::Context,
+        picture: egui::Rect,
+        frames: &Snapshot<Option<VideoFrame>>,
+    ) {
+        let size = egui::vec2(28.0, 24.0);
+        let corner = picture.right_top() + egui::vec2(-size.x - 6.0, 6.0);
+        let button = ui
+            .put(
+                egui::Rect::from_min_size(corner, size),
+                egui::Button::new("📷"),
+            )
+            .on_hover_text("Save this frame as a PNG");
+        if !button.clicked() {
+            return;
+        }
+        let Some(frame) = frames.get().as_ref().clone() else {
+            return;
+        };
+        let folder = self
+            .settings
+            .lock()
+            .unwrap()
+            .files
+            .folder(FileKind::Snapshots)
+            .cloned()
+            .or_else(data_dir)
+            .unwrap_or_default();
+        let path = folder.join(still::file_name("remote"));
+        let state = Arc::clone(&self.remote_video);
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            let saved = path.clone();
+            let status =
+                match tokio::task::spawn_blocking(move || still::save_png(&frame, &path)).await {
+                    Ok(Ok(())) => format!("Saved {}", saved.display()),
+                    Ok(Err(err)) => err.to_string(),
+                    Err(err) => format!("Snapshot failed: {}", err),
+                };
+            state.lock().unwrap().snapshot_status = status;
+            ctx.request_repaint();
+        });
+    }
+
+    /// Asks where to save the buffered video and writes it there as a GIF.
+    async fn export_clip(&self) {
+        let frames = {
+            let mut state = self.remote_video.lock().unwrap();
+            state.exporting = true;
+            state.clip.frames()
+        };
+        let status = match self
+            .save_file_dialog(FileKind::Clips, "Save video clip", "clip.gif")
+            .await
+        {
+            None => String::new(),
+            Some(path) => {
+                let saved = path.clone();
+                match tokio::task::spawn_blocking(move || clip::export_gif(frames, &path)).await {
+                    Ok(Ok(())) => format!("Saved {}", saved.display()),
+                    Ok(Err(err)) => err.to_string(),
+                    Err(err) => format!("Export failed: {}", err),
+                }
+            }
+        };
+        let mut state = self.remote_video.lock().unwrap();
+        state.exporting = false;
+        state.clip_status = status;
+    }
+
+    fn recording_ui(&self, ui: &mut egui::Ui, state: &mut RemoteVideoState) {
+        ui.horizontal(|ui| {
+            if state.is_recording() {
+                if ui.button("Stop Recording").clicked() {
+                    state.stop_recording();
+                    return;
+                }
+                let recorded = match &state.call_recording {
+                    Some(call) => call.recorded(),
+                    None => state.recording.recorded(),
+                };
+                match recorded {
+                    Some(recorded) => ui.label(format!("Recording {}", timestamp(recorded))),
+                    None => ui.label("Waiting for a keyframe to start"),
+                };
+                ui.ctx().request_repaint_after(Duration::from_secs(1));
+                return;
+            }
+            let tracks = state.recordable();
+            if ui
+                .add_enabled(!tracks.is_empty(), egui::Button::new("Record Remote"))
+                .on_hover_text("Writes the remote audio and video to a WebM or Matroska file")
+                .on_disabled_hover_text("Nothing is being received that can be recorded")
+                .clicked()
+            {
+                let path = self.recording_path("remote", &tracks);
+                state.recording_status = match state.recording.start(&path, tracks) {
+                    Ok(()) => {
+                        let status = format!("Recording into {}", path.display());
+                        state.last_recording = Some((path, Instant::now()));
+                        status
+                    }
+                    Err(err) => format!("Recording failed: {}", err),
+                };
+            }
+            let tracks = recording::call_tracks();
+            let in_call = state.codec.is_some() || state.audio.is_some();
+            if ui
+                .add_enabled(
+                    !tracks.is_empty() && in_call,
+                    egui::Button::new("Record Call"),
+                )
+                .on_hover_text(
+                    "Writes both videos side by side and both sides' audio mixed into one file",
+                )
+                .on_disabled_hover_text("Needs a call under way")
+                .clicked()
+            {
+                let path = self.recording_path("call", &tracks);
+                let started = CallRecording::start(
+                    &path,
+                    self.camera_preview(),
+                    state.frames.clone(),
+                    self.call_mix(),
+                );
+                state.recording_status = match started {
+                    Ok(call) => {
+                        state.call_recording = Some(call);
+                        let status = format!("Recording into {}", path.display());
+                        state.last_recording = Some((path, Instant::now()));
+                        status
+                    }
+                    Err(err) => format!("Recording failed: {}", err),
+                };
+            }
+        });
+        if !state.recording_status.is_empty() {
+            ui.label(&state.recording_status);
+        }
+    }
+
+    /// A new file for a recording of `tracks`, in the recordings folder.
+    fn recording_path(&self, prefix: &str, tracks: &RecordedTracks) -> PathBuf {
+        self.recordings_folder()
+            .join(recording::file_name(prefix, tracks))
+    }
+
+    /// The recordings folder picked in the settings, or our data directory.
+    pub(crate) fn recordings_folder(&self) -> PathBuf {
+        self.settings
+            .lock()
+            .unwrap()
+            .files
+            .folder(FileKind::Recordings)
+            .cloned()
+            .or_else(data_dir)
+            .unwrap_or_default()
+    }
+
+    /// Moves the call's audio to the speaker picked for it, or else the
+    /// one selected in the settings, as either changes.
+    pub(crate) fn poll_speaker_device(&self) {
+        let state = self.remote_video.lock().unwrap();
+        let speaker = match &state.call_speaker {
+            Some(speaker) => Some(speaker.clone()),
+            None => self.selected_device(DeviceKind::Speaker),
+        };
+        state
+            .route
+            .route_to(speaker.as_ref().map(|d| d.id.as_str()));
+    }
+
+    /// What the remote audio plays on, with a picker for this call.
+    fn speaker_ui(&self, ui: &mut egui::Ui, state: &mut RemoteVideoState) {
+        let Some(audio) = &state.audio else {
+            return;
+        };
+        let Some(status) = state.route.status() else {
+            ui.label(audio);
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.label("Playing remote audio on");
+            let selected = state
+                .call_speaker
+                .as_ref()
+                .map_or("the selected speaker", |d| d.name.as_str());
+            egui::ComboBox::from_id_source("call_speaker")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut state.call_speaker, None, "the selected speaker");
+                    for speaker in self.available_devices(DeviceKind::Speaker) {
+                        let name = speaker.name.clone();
+                        ui.selectable_value(&mut state.call_speaker, Some(speaker), name);
+                    }
+                })
+                .response
+                .on_hover_text("For this call only; the next ones use the speaker in the settings");
+        });
+        match status.missing {
+            Some(missing) => ui.colored_label(
+                egui::Color32::YELLOW,
+                format!(
+                    "{} is unavailable, so {} plays it until it is back",
+                    missing, status.device
+                ),
+            ),
+            None => ui.small(&status.device),
+        };
+    }
+
+    /// Whether the camera preview goes over the remote video rather than
+    /// in the camera panel: while there is a remote picture to put it on.
+    pub(crate) fn self_view_overlaid(&self) -> bool {
+        let state = self.remote_video.lock().unwrap();
+        state.codec.as_deref().is_some_and(video::can_decode)
+            && state.frames.get().is_some()
+            && !self.peer_camera_paused()
+    }
+
+    /// The first video track at `width`, with the self view over it.
+    fn main_video_ui(
+        &self,
+        ui: &mut egui::Ui,
+        ctx: &egui::Context,
+        state: &mut RemoteVideoState,
+        width: f32,
+    ) {
+        if self.peer_camera_paused() {
+            state
+                .view
+                .show_placeholder(ui, width, "The peer stopped their video");
+        } else {
+            state.view.context_menu = !(state.window && self.controlling_peer());
+            if let Some(picture) = state.view.show(ui, width) {
+                self.self_view_overlay_ui(ui, picture.rect);
+                self.snapshot_button_ui(ui, ctx, picture.rect, &state.frames);
+                if state.window {
+                    self.remote_control_ui(ui, &state.view, &picture);
+                }
+            }
+        }
+    }
+
+    /// A video track after the first, in a tile at `width`.
+    fn tile_ui(&self, ui: &mut egui::Ui, tile: &mut VideoTile, width: f32) {
+        if video::can_decode(&tile.codec) {
+            let name = format!("remote_video_{}", tile.label);
+            let frames = tile.frames.clone();
+            let view = tile
+                .view
+                .get_or_insert_with(|| VideoView::new(name, frames, ui.ctx()));
+            view.context_menu = !(tile.window && self.controlling_peer());
+            if let Some(picture) = view.show(ui, width) {
+                if tile.window {
+                    self.remote_control_ui(ui, view, &picture);
+                }
+            }
+        } else {
+            ui.label(format!("{} (no decoder in this build)", tile.codec));
+        }
+        ui.small(&tile.label);
+    }
+
+    /// Every video track in a labelled tile, as many to a row as make the
+    /// grid about square, narrowing with the panel.
+    fn video_grid_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context, state: &mut RemoteVideoState) {
+        let count = state.tiles.len() + 1;
+        let columns = (count as f32).sqrt().ceil() as usize;
+        let spacing = ui.spacing().item_spacing.x * (columns - 1) as f32;
+        let width =
+            ((ui.available_width() - spacing) / columns as f32).clamp(MIN_TILE_WIDTH, VIDEO_WIDTH);
+        egui::Grid::new("remote_tiles").show(ui, |ui| {
+            ui.vertical(|ui| {
+                self.main_video_ui(ui, ctx, state, width);
+                ui.small(&state.label);
+            });
+            for (index, tile) in state.tiles.iter_mut().enumerate() {
+                ui.vertical(|ui| self.tile_ui(ui, tile, width));
+                if (index + 2) % columns == 0 {
+                    ui.end_row();
+                }
+            }
+        });
+    }
+
+    pub(crate) fn remote_video_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.remote_video.lock().unwrap();
+        let state = &mut *state;
+        self.speaker_ui(ui, state);
+        if self.peer_muted_microphone() {
+            ui.colored_label(egui::Color32::YELLOW, "The peer muted their microphone");
+        }
+        for (label, level) in &state.audio_levels {
+            level_meter_ui(ui, label, &level.get());
+        }
+        self.recording_ui(ui, state);
+        match &state.codec {
+            None => {
+                ui.label("No remote video in this call.");
+                return;
+            }
+            Some(codec) if !video::can_decode(codec) => {
+                ui.label(format!("Receiving {} (no decoder in this build)", codec));
+                return;
+            }
+            Some(codec) => ui.label(format!("Receiving {}", codec)),
+        };
+        if state.tiles.is_empty() {
+            self.main_video_ui(ui, ctx, state, VIDEO_WIDTH);
+        } else {
+            self.video_grid_ui(ui, ctx, state);
+        }
+        if !state.snapshot_status.is_empty() {
+            ui.label(&state.snapshot_status);
+        }
+        if state.audio.is_some() {
+            self.lip_sync_ui(ui, &state.sync);
+        }
+
+        ui.horizontal(|ui| {
+            let label = format!("Clip last {} s...", CLIP_LENGTH.as_secs());
+            if ui
+                .add_enabled(
+                    !state.exporting && !state.clip.is_empty(),
+                    egui::Button::new(label),
+                )
+                .on_hover_text(format!(
+                    "Holding {} s of video",
+                    state.clip.covered().as_secs()
+                ))
+                .clicked()
+            {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.export_clip().await;
+                    ctx.request_repaint();
+                });
+            }
+            ui.label(&state.clip_status);
+        });
+
+        let interval = state.strip.interval().as_secs();
+        ui.label(format!("Thumbnails, one every {} s:", interval));
+        if state.strip.is_empty() {
+            ui.label("None yet");
+            return;
+        }
+        let mut clicked = None;
+        egui::ScrollArea::horizontal()
+            .stick_to_right(true)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    for (index, (thumbnail, texture)) in
+                        state.strip.thumbnails().zip(&state.textures).enumerate()
+                    {
+                        ui.vertical(|ui| {
+                            let selected = state
+                                .preview
+                                .as_ref()
+                                .is_some_and(|(at, _)| *at == thumbnail.at);
+                            let button =
+                                egui::ImageButton::new((texture.id(), texture.size_vec2()))
+                                    .selected(selected);
+                            if ui.add(button).clicked() {
+                                clicked = Some(index);
+                            }
+                            ui.small(timestamp(thumbnail.at));
+                        });
+                    }
+                });
+            });
+        if let Some(thumbnail) = clicked.and_then(|index| state.strip.get(index)) {
+            let deselect = state
+                .preview
+                .as_ref()
+                .is_some_and(|(at, _)| *at == thumbnail.at);
+            state.preview = if deselect {
+                None
+            } else {
+                let texture = ctx.load_texture(
+                    "thumbnail_preview",
+                    image(&thumbnail.large),
+                    egui::TextureOptions::LINEAR,
+                );
+                Some((thumbnail.at, texture))
+            };
+        }
+        if let Some((at, texture)) = &state.preview {
+            ui.label(format!("At {}:", timestamp(*at)));
+            ui.image((texture.id(), texture.size_vec2()));
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Asks before answering a mid-call offer the renegotiation policy does not
+//! accept on its own, such as one opening data channels.
+
+use std::sync::{Arc, Weak};
+
+use eframe::egui;
+use log::info;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc_core::audit::AuditEvent;
+use webrtc_core::renegotiation::{self, OfferChange, RenegotiationPolicy};
+
+use crate::WebRTCApp;
+
+#[derive(Default)]
+pub struct RenegotiationPrompt {
+    /// The identity the current caller was admitted as; their contact's
+    /// override applies. Calls we place use the default policy.
+    peer: Option<String>,
+    pending: Option<PendingOffer>,
+}
+
+struct PendingOffer {
+    /// The call the offer was made in; it is dropped with it.
+    pc: Weak<RTCPeerConnection>,
+    sdp: String,
+    changes: Vec<OfferChange>,
+    contact: Option<String>,
+}
+
+impl RenegotiationPrompt {
+    pub(crate) fn is_pending(&self) -> bool {
+        self.pending
+            .as_ref()
+            .is_some_and(|pending| pending.pc.strong_count() > 0)
+    }
+}
+
+impl WebRTCApp {
+    pub(crate) fn set_call_peer(&self, identity: &str) {
+        let mut prompt = self.renegotiation.lock().unwrap();
+        prompt.peer = Some(identity.to_owned());
+        prompt.pending = None;
+        self.history.lock().unwrap().admit(identity);
+    }
+
+    /// Holds `sdp` for the user when the policy for the peer does not
+    /// accept it as it is. `current` is the remote description in use.
+    pub(crate) fn hold_for_consent(
+        &self,
+        pc: &Arc<RTCPeerConnection>,
+        current: &str,
+        sdp: &str,
+    ) -> bool {
+        let policies = self.settings.lock().unwrap().privacy.renegotiation.clone();
+        let peer = self.renegotiation.lock().unwrap().peer.clone();
+        let contact = peer.and_then(|peer| {
+            let contacts = self.contacts.lock().unwrap();
+            contacts
+                .book
+                .find(&peer)
+                .map(|contact| contact.name.clone())
+        });
+        let changes = renegotiation::offer_changes(current, sdp);
+        if renegotiation::auto_accept(policies.policy_for(contact.as_deref()), &changes) {
+            return false;
+        }
+        info!("Asking before answering an offer that {:?}", changes);
+        self.renegotiation.lock().unwrap().pending = Some(PendingOffer {
+            pc: Arc::downgrade(pc),
+            sdp: sdp.to_owned(),
+            changes,
+            contact,
+        });
+        true
+    }
+
+    async fn settle_pending_offer(&self, accept: bool) {
+        let pending = self.renegotiation.lock().unwrap().pending.take();
+        let Some(pending) = pending else {
+            return;
+        };
+        let pc = self.peer_connection.lock().await.clone();
+        let current = |pc: &Arc<RTCPeerConnection>| {
+            pending
+                .pc
+                .upgrade()
+                .is_some_and(|pending| Arc::ptr_eq(&pending, pc))
+        };
+        let Some(pc) = pc.filter(current) else {
+            return;
+        };
+        let summary = describe(&pending.changes);
+        let peer = self.renegotiation.lock().unwrap().peer.clone();
+        self.audit.lock().unwrap().record(
+            if accept {
+                AuditEvent::RenegotiationAccepted
+            } else {
+                AuditEvent::RenegotiationDeclined
+            },
+            "control",
+            pending.contact.or(peer).as_deref().unwrap_or_default(),
+            summary,
+        );
+        if accept {
+            self.answer_in_band(&pc, pending.sdp).await;
+        } else {
+            self.decline_in_band().await;
+        }
+        self.retry_in_band_offer().await;
+    }
+
+    pub(crate) fn renegotiation_prompt_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let prompt = self.renegotiation.lock().unwrap();
+        if !prompt.is_pending() {
+            return;
+        }
+        let Some(pending) = &prompt.pending else {
+            return;
+        };
+        let mut settle = None;
+        let mut trust = None;
+        ui.horizontal_wrapped(|ui| {
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                format!(
+                    "{} wants to change the call: it {}.",
+                    pending.contact.as_deref().unwrap_or("The peer"),
+                    describe(&pending.changes)
+                ),
+            );
+            if ui.small_button("Accept").clicked() {
+                settle = Some(true);
+            }
+            if ui.small_button("Decline").clicked() {
+                settle = Some(false);
+            }
+            if let Some(contact) = &pending.contact {
+                if ui
+                    .small_button(format!("Always accept from {}", contact))
+                    .clicked()
+                {
+                    trust = Some(contact.clone());
+                    settle = Some(true);
+                }
+            }
+        });
+        drop(prompt);
+        if let Some(contact) = trust {
+            self.settings
+                .lock()
+                .unwrap()
+                .privacy
+                .renegotiation
+                .overrides
+                .insert(contact, RenegotiationPolicy::AcceptAll);
+        }
+        if let Some(accept) = settle {
+            let app = self.clone();
+            let ctx = ctx.clone();
+            tokio::spawn(async move {
+                app.settle_pending_offer(accept).await;
+                ctx.request_repaint();
+            });
+        }
+    }
+}
+
+fn describe(changes: &[OfferChange]) -> String {
+    if changes.is_empty() {
+        return "renegotiates without changing anything".to_owned();
+    }
+    changes
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,493 @@
+//! Room-code rendezvous panel.
+
+use eframe::egui;
+use log::info;
+use tokio::time::Duration;
+use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
+use webrtc_core::chat::ChatMessage;
+use webrtc_core::signaling::{
+    invite::{Invite, SCHEME},
+    room::Room,
+    ClientMessage, ServerMessage, SignalPayload, SignalingClient, SignalingHealth,
+};
+
+use crate::WebRTCApp;
+
+/// How long a copied invite link stays valid.
+const INVITE_VALIDITY: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub struct RoomState {
+    name: String,
+    code: String,
+    room: Option<Room>,
+    client: Option<SignalingClient>,
+    /// The signaling server we joined through.
+    server: String,
+    negotiating_with: Option<String>,
+    /// Our offer while it is unanswered, sent again if the signaling
+    /// connection drops and comes back before the answer arrives.
+    pending_offer: Option<String>,
+    /// An invite link pasted in, or the one the app was opened with.
+    invite_link: String,
+    /// A link made from the token the server just issued, to copy.
+    issued_link: Option<String>,
+    status: String,
+}
+
+impl Default for RoomState {
+    fn default() -> Self {
+        // The URL handler passes the link the app was opened from.
+        let opened_with = std::env::args()
+            .skip(1)
+            .find(|arg| arg.starts_with(&format!("{}:", SCHEME)));
+        let status = if opened_with.is_some() {
+            "Opened from an invite link; check your name and join"
+        } else {
+            ""
+        };
+        Self {
+            name: std::env::var("USER").unwrap_or_else(|_| "guest".to_owned()),
+            code: String::new(),
+            room: None,
+            client: None,
+            server: String::new(),
+            negotiating_with: None,
+            pending_offer: None,
+            invite_link: opened_with.unwrap_or_default(),
+            issued_link: None,
+            status: status.to_owned(),
+        }
+    }
+}
+
+impl WebRTCApp {
+    fn set_room_status(&self, status: impl Into<String>) {
+        self.room.lock().unwrap().status = status.into();
+    }
+
+    /// Joins the room whose code is entered, or the one `invite` is for
+    /// on the server it names.
+    async fn room_join(&self, ctx: egui::Context, invite: Option<Invite>) {
+        let (configured, login) = {
+            let settings = self.settings.lock().unwrap();
+            (
+                settings.network.signaling_url.clone(),
+                settings.network.auth.enabled(),
+            )
+        };
+        // A link can name any server; only ours is sent our login.
+        let url = invite
+            .as_ref()
+            .map_or_else(|| configured.clone(), |invite| invite.server.clone());
+        let ours = url == configured;
+        let token = if ours {
+            self.access_token().await
+        } else {
+            None
+        };
+        if ours && login && token.is_none() {
+            self.request_login("The signaling server requires a login");
+            self.set_room_status("Log in to join rooms on this server");
+            return;
+        }
+        let (code, name) = {
+            let room = self.room.lock().unwrap();
+            (room.code.clone(), room.name.clone())
+        };
+        self.set_room_status("Connecting...");
+        let (client, mut messages) = match SignalingClient::connect(&url, token.as_deref()).await {
+            Ok(connection) => connection,
+            Err(err) => {
+                self.set_room_status(format!("Failed to connect: {}", err));
+                return;
+            }
+        };
+        let room = Room::new(&code);
+        let join = ClientMessage::Join {
+            room: room.channel.clone(),
+            name,
+            resume: None,
+            resume_secret: None,
+            invite: invite.map(|invite| invite.token),
+        };
+        if let Err(err) = client.send(join) {
+            self.set_room_status(format!("Failed to join: {}", err));
+            return;
+        }
+        let mut health = client.watch_health();
+        let repaint = ctx.clone();
+        tokio::spawn(async move {
+            while health.changed().await.is_ok() {
+                repaint.request_repaint();
+            }
+        });
+        {
+            let mut state = self.room.lock().unwrap();
+            state.room = Some(room);
+            state.client = Some(client);
+            state.server = url;
+            state.status = "Joining...".to_owned();
+        }
+
+        let app = self.clone();
+        tokio::spawn(async move {
+            while let Some(message) = messages.recv().await {
+                app.handle_room_message(message).await;
+                ctx.request_repaint();
+            }
+            let mut state = app.room.lock().unwrap();
+            if state.client.is_some() {
+                state.status = "Disconnected from signaling server".to_owned();
+                state.client = None;
+                state.room = None;
+            }
+            ctx.request_repaint();
+        });
+    }
+
+    async fn room_leave(&self) {
+        let client = {
+            let mut state = self.room.lock().unwrap();
+            state.room = None;
+            state.negotiating_with = None;
+            state.pending_offer = None;
+            state.status = "Left room".to_owned();
+            state.client.take()
+        };
+        if let Some(client) = client {
+            let _ = client.send(ClientMessage::Leave);
+        }
+        self.close_peer_connection().await;
+    }
+
+    async fn handle_room_message(&self, message: ServerMessage) {
+        info!("Signaling message: {:?}", message);
+        let (partner_left, start_offer, resend_offer) = {
+            let mut state = self.room.lock().unwrap();
+            let name = state.name.clone();
+            let Some(room) = state.room.as_mut() else {
+                return;
+            };
+            // A second welcome means the client reconnected and rejoined.
+            let rejoined =
+                matches!(message, ServerMessage::Welcome { .. }) && room.local_peer_id.is_some();
+            room.apply(&message, &name);
+            let code = room.code.clone();
+            let partner = room.partner().map(|p| p.peer_id.clone());
+            let we_offer = room.we_offer();
+            let partner_left =
+                state.negotiating_with.is_some() && state.negotiating_with != partner;
+            if partner_left {
+                state.negotiating_with = None;
+                state.pending_offer = None;
+            }
+            let resend_offer = state
+                .pending_offer
+                .clone()
+                .filter(|_| rejoined && state.negotiating_with.is_some())
+                .zip(state.negotiating_with.clone());
+            let start_offer = match partner {
+                Some(partner) if we_offer && state.negotiating_with.is_none() => {
+                    state.negotiating_with = Some(partner.clone());
+                    Some(partner)
+                }
+                _ => None,
+            };
+            if let ServerMessage::Invite { token } = &message {
+                state.issued_link = Some(Invite::new(&state.server, &code, token).to_url());
+            }
+            if let ServerMessage::Error { message } = &message {
+                state.status = format!("Server error: {}", message);
+            } else if state.negotiating_with.is_none() {
+                state.status = "Waiting for a peer...".to_owned();
+            }
+            (partner_left, start_offer, resend_offer)
+        };
+
+        if partner_left {
+            info!("Room partner left, closing peer connection");
+            self.close_peer_connection().await;
+        }
+        if let Some(partner) = start_offer {
+            self.set_room_status("Sending offer...");
+            self.create_peer_connection(false).await;
+            self.add_configured_transceivers().await;
+            self.open_control_channel().await;
+            self.create_offer().await;
+            let sdp = self.local_sdp.get().to_string();
+            self.room.lock().unwrap().pending_offer = Some(sdp.clone());
+            self.send_room_signal(&partner, SignalPayload::Offer { sdp });
+            self.set_room_status("Offer sent, waiting for answer...");
+        }
+        if let Some((sdp, partner)) = resend_offer {
+            let ice_state = self.reconnect.lock().unwrap().ice_state();
+            if !matches!(
+                ice_state,
+                RTCIceConnectionState::Connected | RTCIceConnectionState::Completed
+            ) {
+                info!("Signaling reconnected, sending our offer again");
+                self.send_room_signal(&partner, SignalPayload::Offer { sdp });
+            }
+        }
+        match message {
+            ServerMessage::Signal { from, payload } => self.handle_room_signal(from, payload).await,
+            ServerMessage::Chat { message, .. } => self.receive_chat(message),
+            _ => {}
+        }
+    }
+
+    async fn handle_room_signal(&self, from: String, payload: SignalPayload) {
+        let partner_name = {
+            let state = self.room.lock().unwrap();
+            state
+                .room
+                .as_ref()
+                .and_then(|room| room.partner())
+                .filter(|p| p.peer_id == from)
+                .map(|p| p.name.clone())
+        };
+        let Some(partner_name) = partner_name else {
+            info!("Ignoring signal from {}, who is not our room partner", from);
+            return;
+        };
+        match payload {
+            SignalPayload::Offer { sdp } => {
+                // Members name themselves, so the name alone could be
+                // anyone's; the peer ID is the server's, but new each join,
+                // so the access lists and history go by the name.
+                let shown = format!("{} (unverified, peer {})", partner_name, from);
+                if !self.admit_caller_as("room", &partner_name, &shown, &sdp) {
+                    return;
+                }
+                // A later offer from the same partner (an ICE restart, say)
+                // renegotiates the existing connection instead of replacing it.
+                let renegotiating = self
+                    .room
+                    .lock()
+                    .unwrap()
+                    .negotiating_with
+                    .replace(from.clone())
+                    .is_some_and(|previous| previous == from)
+                    && self.peer_connection.lock().await.is_some();
+                self.set_room_status("Answering offer...");
+                if !renegotiating {
+                    self.create_peer_connection(false).await;
+                    self.open_control_channel().await;
+                }
+                match self.answer_remote_offer(sdp).await {
+                    Ok(sdp) => {
+                        self.send_room_signal(&from, SignalPayload::Answer { sdp });
+                        self.set_room_status("Answer sent, connecting...");
+                    }
+                    Err(err) => self.set_room_status(format!("Failed to answer: {}", err)),
+                }
+            }
+            SignalPayload::Answer { sdp } => {
+                self.room.lock().unwrap().pending_offer = None;
+                self.remote_sdp.set(sdp.clone());
+                if self.finish_ice_restart(&sdp).await {
+                    return;
+                }
+                self.handle_answer().await;
+                self.set_room_status("Answer received, connecting...");
+            }
+        }
+    }
+
+    /// In a room, offers and answers are exchanged automatically.
+    pub(crate) fn in_room(&self) -> bool {
+        self.room.lock().unwrap().client.is_some()
+    }
+
+    /// The peer we are currently negotiating with over the room, if any.
+    pub(crate) fn room_partner(&self) -> Option<String> {
+        self.room.lock().unwrap().negotiating_with.clone()
+    }
+
+    /// The name of the member we are negotiating with. Unlike the peer ID
+    /// it stays the same across joins, and both ends know it.
+    pub(crate) fn room_partner_name(&self) -> Option<String> {
+        let state = self.room.lock().unwrap();
+        let partner = state.negotiating_with.as_ref()?;
+        let room = state.room.as_ref()?;
+        room.members
+            .iter()
+            .find(|member| &member.peer_id == partner)
+            .map(|member| member.name.clone())
+    }
+
+    /// Our display name, as the room shows it.
+    pub(crate) fn room_name(&self) -> String {
+        self.room.lock().unwrap().name.clone()
+    }
+
+    /// Lets the room client reconnect with a refreshed access token.
+    pub(crate) fn set_room_token(&self, token: &str) {
+        if let Some(client) = &self.room.lock().unwrap().client {
+            client.set_token(token);
+        }
+    }
+
+    /// Signaling connection health, for the top bar.
+    pub(crate) fn signaling_health_ui(&self, ui: &mut egui::Ui) {
+        let Some(health) = self
+            .room
+            .lock()
+            .unwrap()
+            .client
+            .as_ref()
+            .map(|c| c.health())
+        else {
+            return;
+        };
+        let color = match health {
+            SignalingHealth::Connected => egui::Color32::GREEN,
+            SignalingHealth::Reconnecting { .. } => egui::Color32::YELLOW,
+            SignalingHealth::Closed => egui::Color32::RED,
+        };
+        ui.colored_label(color, format!("Signaling {}", health));
+    }
+
+    pub(crate) fn send_room_signal(&self, to: &str, payload: SignalPayload) {
+        let client = self.room.lock().unwrap().client.clone();
+        if let Some(client) = client {
+            let signal = ClientMessage::Signal {
+                to: to.to_owned(),
+                payload,
+            };
+            if let Err(err) = client.send(signal) {
+                info!("Failed to send signal: {:?}", err);
+            }
+        }
+    }
+
+    /// Sends chat through the room's server, for when the data channel is
+    /// down. Returns whether there was a room to send it through.
+    pub(crate) fn send_room_chat(&self, message: ChatMessage) -> bool {
+        let client = self.room.lock().unwrap().client.clone();
+        let Some(client) = client else {
+            return false;
+        };
+        match client.send(ClientMessage::Chat { message }) {
+            Ok(()) => true,
+            Err(err) => {
+                info!("Failed to send chat: {:?}", err);
+                false
+            }
+        }
+    }
+}
+
+impl WebRTCApp {
+    pub(crate) fn room_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.room.lock().unwrap();
+        let joined = state.client.is_some();
+
+        ui.add_enabled_ui(!joined, |ui| {
+            egui::Grid::new("room_settings")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Your name:");
+                    ui.text_edit_singleline(&mut state.name);
+                    ui.end_row();
+                    ui.label("Room code:");
+                    ui.text_edit_singleline(&mut state.code);
+                    ui.end_row();
+                    ui.label("Or invite link:");
+                    ui.text_edit_singleline(&mut state.invite_link);
+                    ui.end_row();
+                });
+        });
+        if let Some(link) = state.issued_link.take() {
+            ui.output_mut(|output| output.copied_text = link);
+            state.status = "Invite link copied; it works for 24 hours".to_owned();
+        }
+
+        ui.horizontal(|ui| {
+            if !joined
+                && ui
+                    .add_enabled(
+                        !state.code.trim().is_empty(),
+                        egui::Button::new("Join Room"),
+                    )
+                    .clicked()
+            {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.room_join(ctx.clone(), None).await;
+                    ctx.request_repaint();
+                });
+            }
+            if !joined
+                && ui
+                    .add_enabled(
+                        !state.invite_link.trim().is_empty(),
+                        egui::Button::new("Join from Link"),
+                    )
+                    .clicked()
+            {
+                match Invite::parse(&state.invite_link) {
+                    Ok(invite) => {
+                        state.code = invite.room.clone();
+                        let app = self.clone();
+                        let ctx = ctx.clone();
+                        tokio::spawn(async move {
+                            app.room_join(ctx.clone(), Some(invite)).await;
+                            ctx.request_repaint();
+                        });
+                    }
+                    Err(err) => state.status = format!("Cannot use the link: {}", err),
+                }
+            }
+            if joined && ui.button("Leave Room").clicked() {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.room_leave().await;
+                    ctx.request_repaint();
+                });
+            }
+            ui.label(&state.status);
+        });
+
+        let mut status = None;
+        let Some(room) = &state.room else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.label(format!("Channel: {}", room.channel));
+            let copy = ui.button("Copy invite link").on_hover_text(
+                "From then on, joining the room takes an invite link rather than the code alone",
+            );
+            if copy.clicked() {
+                // Only the server can sign the token.
+                let ask = ClientMessage::Invite {
+                    valid_for: INVITE_VALIDITY.as_secs(),
+                };
+                status = Some(match state.client.as_ref().map(|client| client.send(ask)) {
+                    Some(Ok(())) => "Asking the server for an invite link...".to_owned(),
+                    Some(Err(err)) => format!("Failed to ask for an invite link: {}", err),
+                    None => "Not connected".to_owned(),
+                });
+            }
+        });
+        let offerer = room.offerer();
+        for (index, member) in room.members.iter().enumerate() {
+            let mut line = format!("{} [{}]", member.name, member.peer_id);
+            if room.is_local(&member.peer_id) {
+                line.push_str(" (you)");
+            }
+            if offerer == Some(member.peer_id.as_str()) {
+                line.push_str(" - sends the offer");
+            } else if index == 0 && offerer.is_some() {
+                line.push_str(" - answers");
+            }
+            ui.label(line)
+                .on_hover_text("Names are chosen by each member; the ID is the server's");
+        }
+        if let Some(status) = status {
+            state.status = status;
+        }
+    }
+}
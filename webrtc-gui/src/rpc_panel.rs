@@ -0,0 +1,294 @@
+//! Calling the peer's methods, and answering its calls of ours, over the
+//! "rpc" data channel, with a console to make calls by hand.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use eframe::egui;
+use log::info;
+use serde_json::{json, Value};
+use webrtc_core::rpc::{
+    RpcCallError, RpcCalls, RpcMessage, RpcMethods, METHODS_METHOD, RPC_LABEL, RPC_STREAM_ID,
+    RPC_TIMEOUT,
+};
+
+use crate::negotiated_channel::{self, ChannelPanel, NegotiatedChannel};
+use crate::{WebRTCApp, APP_NAME};
+
+/// Calls the console keeps before dropping the oldest.
+const MAX_CALLS: usize = 50;
+
+/// Characters of each result the console shows.
+const PREVIEW_CHARS: usize = 200;
+
+struct ConsoleCall {
+    method: String,
+    elapsed: Duration,
+    outcome: Result<Value, RpcCallError>,
+}
+
+pub struct RpcState {
+    methods: RpcMethods,
+    calls: RpcCalls,
+    channel: NegotiatedChannel,
+    /// What the peer said it exposes, by name, with what each does.
+    remote_methods: Vec<(String, String)>,
+    method: String,
+    params: String,
+    /// Console calls still waiting for their answer.
+    waiting: usize,
+    /// Console calls made, newest last.
+    log: VecDeque<ConsoleCall>,
+    status: String,
+    /// Answers arrive in the background; redraw when they do.
+    ctx: egui::Context,
+}
+
+impl RpcState {
+    pub fn new(ctx: &egui::Context) -> Self {
+        let mut methods = RpcMethods::default();
+        methods.register("echo", "Returns its params", Ok);
+        methods.register("app.version", "The app's name and version", |_| {
+            Ok(json!({ "name": APP_NAME, "version": env!("CARGO_PKG_VERSION") }))
+        });
+        methods.register(
+            "app.time",
+            "The time here, in milliseconds since the Unix epoch",
+            |_| {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |since| since.as_millis() as u64);
+                Ok(json!(now))
+            },
+        );
+        Self {
+            methods,
+            calls: RpcCalls::default(),
+            channel: NegotiatedChannel::default(),
+            remote_methods: Vec::new(),
+            method: METHODS_METHOD.to_owned(),
+            params: String::new(),
+            waiting: 0,
+            log: VecDeque::new(),
+            status: String::new(),
+            ctx: ctx.clone(),
+        }
+    }
+
+    /// Drops the channel of a peer connection that is being replaced,
+    /// failing the calls still waiting on it; the console stays.
+    pub(crate) fn reset(&mut self) {
+        self.calls.close();
+        self.channel = NegotiatedChannel::default();
+        self.remote_methods.clear();
+    }
+
+    /// Answers `text` from the peer, when it is a request.
+    fn receive(&mut self, text: &str) -> Option<String> {
+        let response = match RpcMessage::from_json(text) {
+            Ok(RpcMessage::Request(request)) => self.methods.handle(request),
+            Ok(RpcMessage::Response(response)) => {
+                if !self.calls.answered(response) {
+                    info!("Ignoring an rpc response no call is waiting for");
+                }
+                None
+            }
+            Err(err) => {
+                info!("Answering unreadable rpc message: {}", err);
+                Some(RpcMethods::unreadable(&err))
+            }
+        };
+        response.map(|response| RpcMessage::Response(response).to_json())
+    }
+}
+
+impl ChannelPanel for RpcState {
+    fn negotiated(&mut self) -> &mut NegotiatedChannel {
+        &mut self.channel
+    }
+
+    fn replaced(&mut self) {
+        self.reset();
+    }
+
+    fn opened(&mut self) {
+        self.ctx.request_repaint();
+    }
+
+    fn closed(&mut self) {
+        self.calls.close();
+        self.ctx.request_repaint();
+    }
+}
+
+impl WebRTCApp {
+    /// Creates the rpc channel on the current peer connection.
+    pub(crate) async fn open_rpc_channel(&self) {
+        let rpc = Arc::clone(&self.rpc);
+        self.open_negotiated_channel(&self.rpc, RPC_LABEL, RPC_STREAM_ID, move |message| {
+            let text = message.into_text()?;
+            rpc.lock().unwrap().receive(&text)
+        })
+        .await;
+    }
+
+    /// Calls the peer's `method` and waits for its answer, for at most
+    /// [`RPC_TIMEOUT`].
+    pub(crate) async fn rpc_call(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<Value, RpcCallError> {
+        let (channel, request, answer) = {
+            let mut state = self.rpc.lock().unwrap();
+            let Some(channel) = state.channel.sender() else {
+                return Err(RpcCallError::NotOpen);
+            };
+            let (request, answer) = state.calls.start(method, params);
+            (channel, request, answer)
+        };
+        let text = RpcMessage::Request(request.clone()).to_json();
+        if negotiated_channel::send(Some(channel), text).await.is_err() {
+            self.rpc.lock().unwrap().calls.forget(&request);
+            return Err(RpcCallError::Closed);
+        }
+        match tokio::time::timeout(RPC_TIMEOUT, answer).await {
+            Ok(Ok(Ok(result))) => Ok(result),
+            Ok(Ok(Err(err))) => Err(RpcCallError::Remote(err)),
+            Ok(Err(_)) => Err(RpcCallError::Closed),
+            Err(_) => {
+                self.rpc.lock().unwrap().calls.forget(&request);
+                Err(RpcCallError::Timeout)
+            }
+        }
+    }
+
+    /// Makes a call from the console and logs how it went.
+    async fn console_call(&self, method: String, params: Value) {
+        let started = Instant::now();
+        let outcome = self.rpc_call(&method, params).await;
+        let mut state = self.rpc.lock().unwrap();
+        state.waiting -= 1;
+        if method == METHODS_METHOD {
+            if let Ok(Value::Object(methods)) = &outcome {
+                state.remote_methods = methods
+                    .iter()
+                    .map(|(name, description)| {
+                        let description = description.as_str().unwrap_or_default();
+                        (name.clone(), description.to_owned())
+                    })
+                    .collect();
+            }
+        }
+        if state.log.len() == MAX_CALLS {
+            state.log.pop_front();
+        }
+        state.log.push_back(ConsoleCall {
+            method,
+            elapsed: started.elapsed(),
+            outcome,
+        });
+    }
+
+    pub(crate) fn rpc_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.rpc.lock().unwrap();
+        let state = &mut *state;
+        ui.horizontal_wrapped(|ui| {
+            ui.label("The peer can call:");
+            ui.weak(METHODS_METHOD)
+                .on_hover_text("Lists the methods, with what each does");
+            for (name, description) in state.methods.list() {
+                ui.weak(name).on_hover_text(description);
+            }
+        });
+        if !state.channel.is_open() {
+            ui.label("Connect to a peer to call its methods.");
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Method");
+            ui.text_edit_singleline(&mut state.method);
+            egui::ComboBox::from_id_source("rpc_remote_methods")
+                .selected_text("Theirs")
+                .show_ui(ui, |ui| {
+                    if state.remote_methods.is_empty() {
+                        ui.weak(format!("Call {} to list them", METHODS_METHOD));
+                    }
+                    for (name, description) in &state.remote_methods {
+                        ui.selectable_value(&mut state.method, name.clone(), name)
+                            .on_hover_text(description);
+                    }
+                });
+        });
+        ui.add(
+            egui::TextEdit::multiline(&mut state.params)
+                .code_editor()
+                .desired_rows(2)
+                .hint_text("Params as JSON, or nothing for none"),
+        );
+        ui.horizontal(|ui| {
+            let method = state.method.trim().to_owned();
+            if ui
+                .add_enabled(!method.is_empty(), egui::Button::new("Call"))
+                .clicked()
+            {
+                let params = if state.params.trim().is_empty() {
+                    Ok(Value::Null)
+                } else {
+                    serde_json::from_str(&state.params)
+                };
+                match params {
+                    Ok(params) => {
+                        state.status.clear();
+                        state.waiting += 1;
+                        let app = self.clone();
+                        let ctx = ctx.clone();
+                        tokio::spawn(async move {
+                            app.console_call(method, params).await;
+                            ctx.request_repaint();
+                        });
+                    }
+                    Err(err) => state.status = format!("The params are not JSON: {}", err),
+                }
+            }
+            if state.waiting > 0 {
+                ui.spinner();
+                ui.label(format!("{} waiting", state.waiting));
+            }
+            if !state.log.is_empty() && ui.button("Clear").clicked() {
+                state.log.clear();
+            }
+        });
+        if !state.status.is_empty() {
+            ui.label(&state.status);
+        }
+
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .show(ui, |ui| {
+                egui::Grid::new("rpc_calls").striped(true).show(ui, |ui| {
+                    for call in state.log.iter().rev() {
+                        ui.label(&call.method);
+                        ui.label(format!("{:.0} ms", call.elapsed.as_secs_f64() * 1000.0));
+                        match &call.outcome {
+                            Ok(result) => ui.monospace(preview(result)),
+                            Err(err) => ui.colored_label(egui::Color32::RED, err.to_string()),
+                        };
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+}
+
+/// `result` as compact JSON, cut short.
+fn preview(result: &Value) -> String {
+    let text = result.to_string();
+    let mut preview: String = text.chars().take(PREVIEW_CHARS).collect();
+    if preview.len() < text.len() {
+        preview.push('…');
+    }
+    preview
+}
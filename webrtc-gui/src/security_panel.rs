@@ -0,0 +1,79 @@
+//! Access checks on incoming calls and the security audit log view.
+
+use eframe::egui;
+use log::info;
+use webrtc_core::audit::AuditEvent;
+
+use crate::WebRTCApp;
+
+impl WebRTCApp {
+    /// Whether to answer an incoming offer. Turned away callers get no
+    /// answer or notice; the decision only goes to the audit log.
+    pub(crate) fn admit_caller(&self, source: &str, identity: &str, offer_sdp: &str) -> bool {
+        self.admit_caller_as(source, identity, identity, offer_sdp)
+    }
+
+    /// As [`Self::admit_caller`], with the caller logged as `shown` rather
+    /// than the identity checked and kept history under.
+    pub(crate) fn admit_caller_as(
+        &self,
+        source: &str,
+        identity: &str,
+        shown: &str,
+        offer_sdp: &str,
+    ) -> bool {
+        let access = self.settings.lock().unwrap().privacy.access.clone();
+        match access.check(identity, offer_sdp) {
+            Ok(()) => {
+                self.set_call_peer(identity);
+                true
+            }
+            Err(denied) => {
+                info!("Rejecting {} call from {}: {}", source, shown, denied);
+                self.audit.lock().unwrap().record(
+                    AuditEvent::CallRejected,
+                    source,
+                    shown,
+                    denied.to_string(),
+                );
+                false
+            }
+        }
+    }
+
+    pub(crate) fn audit_log_ui(&self, ui: &mut egui::Ui) {
+        let audit = self.audit.lock().unwrap();
+        if let Some(path) = audit.path() {
+            ui.label(format!("Written to {}", path.display()));
+        }
+        if audit.entries().next().is_none() {
+            ui.label("No security events yet.");
+            return;
+        }
+        match (audit.hidden(), audit.path()) {
+            (0, _) => {}
+            (hidden, Some(_)) => {
+                ui.label(format!("{} older entries are only in the file", hidden));
+            }
+            (hidden, None) => {
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    format!("{} older entries were discarded", hidden),
+                );
+            }
+        }
+        egui::ScrollArea::vertical()
+            .max_height(160.0)
+            .show(ui, |ui| {
+                egui::Grid::new("audit_log").num_columns(4).show(ui, |ui| {
+                    for entry in audit.entries().rev() {
+                        ui.label(&entry.at);
+                        ui.label(&entry.source);
+                        ui.label(&entry.peer);
+                        ui.label(&entry.detail);
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+}
@@ -0,0 +1,1293 @@
+//! Settings window. Every option is listed in `ENTRIES` so the search box can
+//! filter across all pages at once.
+
+use std::path::PathBuf;
+
+use eframe::egui;
+use tokio::sync::oneshot;
+use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+use webrtc_core::audio::{self, OpusApplication, OpusSettings, ProcessingSettings};
+use webrtc_core::camera::{self, BackgroundEffect, BackgroundSettings};
+use webrtc_core::codec::{self, ScalabilityMode};
+use webrtc_core::codecs::CodecPreference;
+use webrtc_core::devices::{DeviceKind, FacingMode};
+use webrtc_core::renegotiation::RenegotiationPolicy;
+use webrtc_core::settings::{FileKind, Settings, TurnAuth, TurnServer};
+
+use crate::devices_panel::DeviceState;
+use crate::file_dialogs;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SettingsPage {
+    #[default]
+    Network,
+    Media,
+    Audio,
+    Privacy,
+    Advanced,
+    Files,
+}
+
+impl SettingsPage {
+    const ALL: [SettingsPage; 6] = [
+        SettingsPage::Network,
+        SettingsPage::Media,
+        SettingsPage::Audio,
+        SettingsPage::Privacy,
+        SettingsPage::Advanced,
+        SettingsPage::Files,
+    ];
+
+    fn title(self) -> &'static str {
+        match self {
+            SettingsPage::Network => "Network",
+            SettingsPage::Media => "Media",
+            SettingsPage::Audio => "Audio",
+            SettingsPage::Privacy => "Privacy",
+            SettingsPage::Advanced => "Advanced",
+            SettingsPage::Files => "Files",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SettingId {
+    StunServers,
+    TurnServers,
+    SignalingUrl,
+    SignalingLogin,
+    AllowIpv6,
+    EchoTestUrl,
+    NostrRelays,
+    Libp2pBootstrap,
+    Libp2pRelays,
+    Camera,
+    Microphone,
+    Speaker,
+    MicrophoneChannels,
+    TestToneFrequency,
+    RescanDevices,
+    OfferAudio,
+    OfferVideo,
+    SendMicrophone,
+    PreferredResolution,
+    PreferredFrameRate,
+    FacingMode,
+    ThumbnailInterval,
+    MirrorSelfView,
+    PowerSaving,
+    Simulcast,
+    Scalability,
+    AdaptiveBitrate,
+    BackgroundEffect,
+    SegmentationModel,
+    BackgroundImage,
+    VideoCodecs,
+    AudioCodecs,
+    OpusApplication,
+    OpusBitrate,
+    OpusFec,
+    OpusRed,
+    OpusLossThreshold,
+    OpusStereo,
+    OpusDtx,
+    EchoCancellation,
+    NoiseSuppression,
+    MicrophoneGain,
+    MdnsHostCandidates,
+    RelayOnly,
+    AllowlistOnly,
+    AllowedPeers,
+    BlockedPeers,
+    Renegotiation,
+    StatsInterval,
+    IceDisconnectedTimeout,
+    IceFailedTimeout,
+    AutoIceRestart,
+    TurnFailover,
+    FailoverRtt,
+    FailoverLoss,
+    TurnCredentialLifetime,
+    DefaultFolders,
+}
+
+/// The Opus settings are read when the microphone starts sending.
+const OPUS_RESTART_HINT: &str = "Used the next time the microphone starts sending";
+
+struct SettingEntry {
+    id: SettingId,
+    page: SettingsPage,
+    label: &'static str,
+    /// Extra search terms that do not appear in the label.
+    keywords: &'static str,
+}
+
+const ENTRIES: &[SettingEntry] = &[
+    SettingEntry {
+        id: SettingId::StunServers,
+        page: SettingsPage::Network,
+        label: "STUN servers",
+        keywords: "ice server url nat",
+    },
+    SettingEntry {
+        id: SettingId::TurnServers,
+        page: SettingsPage::Network,
+        label: "TURN servers",
+        keywords: "ice relay server url username credential password nat",
+    },
+    SettingEntry {
+        id: SettingId::SignalingUrl,
+        page: SettingsPage::Network,
+        label: "Signaling server",
+        keywords: "websocket room url",
+    },
+    SettingEntry {
+        id: SettingId::SignalingLogin,
+        page: SettingsPage::Network,
+        label: "Signaling login",
+        keywords: "oauth jwt token bearer authentication client id password",
+    },
+    SettingEntry {
+        id: SettingId::AllowIpv6,
+        page: SettingsPage::Network,
+        label: "Gather IPv6 candidates",
+        keywords: "ipv6 ip network ice",
+    },
+    SettingEntry {
+        id: SettingId::EchoTestUrl,
+        page: SettingsPage::Network,
+        label: "Echo test endpoint",
+        keywords: "test my setup whip url diagnostics",
+    },
+    SettingEntry {
+        id: SettingId::NostrRelays,
+        page: SettingsPage::Network,
+        label: "Nostr relays",
+        keywords: "signaling serverless websocket pubkey url",
+    },
+    SettingEntry {
+        id: SettingId::Libp2pBootstrap,
+        page: SettingsPage::Network,
+        label: "libp2p bootstrap peers",
+        keywords: "dht kademlia multiaddr peerid serverless signaling ipfs",
+    },
+    SettingEntry {
+        id: SettingId::Libp2pRelays,
+        page: SettingsPage::Network,
+        label: "libp2p circuit relays",
+        keywords: "relay circuit fallback nat multiaddr reservation",
+    },
+    SettingEntry {
+        id: SettingId::Camera,
+        page: SettingsPage::Media,
+        label: "Camera",
+        keywords: "device webcam video input capture v4l2",
+    },
+    SettingEntry {
+        id: SettingId::Microphone,
+        page: SettingsPage::Media,
+        label: "Microphone",
+        keywords: "device audio input capture mic",
+    },
+    SettingEntry {
+        id: SettingId::Speaker,
+        page: SettingsPage::Media,
+        label: "Speaker",
+        keywords: "device audio output playback headphones",
+    },
+    SettingEntry {
+        id: SettingId::MicrophoneChannels,
+        page: SettingsPage::Media,
+        label: "Microphone channels",
+        keywords: "input mapping matrix gain mixer multichannel usb interface db",
+    },
+    SettingEntry {
+        id: SettingId::TestToneFrequency,
+        page: SettingsPage::Media,
+        label: "Test tone frequency",
+        keywords: "synthetic sine pitch hz microphone signal generator",
+    },
+    SettingEntry {
+        id: SettingId::RescanDevices,
+        page: SettingsPage::Media,
+        label: "Rescan devices",
+        keywords: "refresh plugged usb camera microphone speaker",
+    },
+    SettingEntry {
+        id: SettingId::OfferAudio,
+        page: SettingsPage::Media,
+        label: "Offer to receive audio",
+        keywords: "microphone sound m-line transceiver sdp",
+    },
+    SettingEntry {
+        id: SettingId::OfferVideo,
+        page: SettingsPage::Media,
+        label: "Offer to receive video",
+        keywords: "camera m-line transceiver sdp",
+    },
+    SettingEntry {
+        id: SettingId::SendMicrophone,
+        page: SettingsPage::Media,
+        label: "Send microphone",
+        keywords: "audio voice call opus capture mute",
+    },
+    SettingEntry {
+        id: SettingId::PreferredResolution,
+        page: SettingsPage::Media,
+        label: "Preferred camera resolution",
+        keywords: "constraints width height ideal capture size",
+    },
+    SettingEntry {
+        id: SettingId::PreferredFrameRate,
+        page: SettingsPage::Media,
+        label: "Preferred camera frame rate",
+        keywords: "constraints fps framerate ideal capture",
+    },
+    SettingEntry {
+        id: SettingId::FacingMode,
+        page: SettingsPage::Media,
+        label: "Preferred camera facing",
+        keywords: "constraints facingmode front back user environment",
+    },
+    SettingEntry {
+        id: SettingId::ThumbnailInterval,
+        page: SettingsPage::Media,
+        label: "Thumbnail interval",
+        keywords: "remote video strip timeline snapshot seconds",
+    },
+    SettingEntry {
+        id: SettingId::MirrorSelfView,
+        page: SettingsPage::Media,
+        label: "Mirror the camera preview",
+        keywords: "self view flip horizontal selfie",
+    },
+    SettingEntry {
+        id: SettingId::PowerSaving,
+        page: SettingsPage::Media,
+        label: "Lower video quality on battery or when hot",
+        keywords: "power saving laptop thermal throttling resolution fps energy",
+    },
+    SettingEntry {
+        id: SettingId::Simulcast,
+        page: SettingsPage::Media,
+        label: "Publish simulcast to SFUs",
+        keywords: "layers rid janus quality adaptation bandwidth",
+    },
+    SettingEntry {
+        id: SettingId::Scalability,
+        page: SettingsPage::Media,
+        label: "VP9 scalability mode",
+        keywords: "svc layers spatial temporal sfu degradation bandwidth",
+    },
+    SettingEntry {
+        id: SettingId::AdaptiveBitrate,
+        page: SettingsPage::Media,
+        label: "Adapt video to the connection",
+        keywords: "adaptive bitrate abr congestion bandwidth twcc remb quality resolution",
+    },
+    SettingEntry {
+        id: SettingId::BackgroundEffect,
+        page: SettingsPage::Media,
+        label: "Camera background",
+        keywords: "virtual background blur replace segmentation person camera privacy",
+    },
+    SettingEntry {
+        id: SettingId::SegmentationModel,
+        page: SettingsPage::Media,
+        label: "Segmentation model",
+        keywords: "onnx onnxruntime mediapipe selfie virtual background blur person",
+    },
+    SettingEntry {
+        id: SettingId::BackgroundImage,
+        page: SettingsPage::Media,
+        label: "Background image",
+        keywords: "virtual background replace picture png jpeg camera",
+    },
+    SettingEntry {
+        id: SettingId::VideoCodecs,
+        page: SettingsPage::Media,
+        label: "Video codecs",
+        keywords: "codec order prefer preferred enable disable vp8 vp9 h264 av1 interop",
+    },
+    SettingEntry {
+        id: SettingId::AudioCodecs,
+        page: SettingsPage::Audio,
+        label: "Audio codecs",
+        keywords: "codec order prefer preferred enable disable opus g722 pcmu pcma interop",
+    },
+    SettingEntry {
+        id: SettingId::OpusApplication,
+        page: SettingsPage::Audio,
+        label: "Opus tuned for",
+        keywords: "application mode voip voice speech music codec microphone",
+    },
+    SettingEntry {
+        id: SettingId::OpusBitrate,
+        page: SettingsPage::Audio,
+        label: "Opus bitrate",
+        keywords: "kbps quality codec microphone",
+    },
+    SettingEntry {
+        id: SettingId::OpusFec,
+        page: SettingsPage::Audio,
+        label: "Forward error correction",
+        keywords: "fec opus packet loss redundancy",
+    },
+    SettingEntry {
+        id: SettingId::OpusRed,
+        page: SettingsPage::Audio,
+        label: "Send redundant audio (RED)",
+        keywords: "red rfc 2198 opus packet loss redundancy duplicate",
+    },
+    SettingEntry {
+        id: SettingId::OpusLossThreshold,
+        page: SettingsPage::Audio,
+        label: "Protect only past a loss of",
+        keywords: "fec red threshold packet loss adaptive receiver report",
+    },
+    SettingEntry {
+        id: SettingId::OpusStereo,
+        page: SettingsPage::Audio,
+        label: "Send in stereo",
+        keywords: "opus channels music left right sprop-stereo broadcast",
+    },
+    SettingEntry {
+        id: SettingId::OpusDtx,
+        page: SettingsPage::Audio,
+        label: "Discontinuous transmission",
+        keywords: "dtx opus silence bandwidth",
+    },
+    SettingEntry {
+        id: SettingId::EchoCancellation,
+        page: SettingsPage::Audio,
+        label: "Echo cancellation",
+        keywords: "aec aec3 speaker speakerphone feedback microphone processing",
+    },
+    SettingEntry {
+        id: SettingId::NoiseSuppression,
+        page: SettingsPage::Audio,
+        label: "Noise suppression",
+        keywords: "rnnoise nnnoiseless denoise background fan keyboard microphone processing",
+    },
+    SettingEntry {
+        id: SettingId::MicrophoneGain,
+        page: SettingsPage::Audio,
+        label: "Microphone gain",
+        keywords: "agc automatic gain control volume loudness quiet level db processing",
+    },
+    SettingEntry {
+        id: SettingId::MdnsHostCandidates,
+        page: SettingsPage::Privacy,
+        label: "Hide local IPs behind mDNS names",
+        keywords: "mdns host candidate address leak",
+    },
+    SettingEntry {
+        id: SettingId::RelayOnly,
+        page: SettingsPage::Privacy,
+        label: "Relay-only connections",
+        keywords: "turn ice transport policy ip address",
+    },
+    SettingEntry {
+        id: SettingId::AllowlistOnly,
+        page: SettingsPage::Privacy,
+        label: "Only accept calls from the allowlist",
+        keywords: "access control incoming reject security",
+    },
+    SettingEntry {
+        id: SettingId::AllowedPeers,
+        page: SettingsPage::Privacy,
+        label: "Allowed callers",
+        keywords: "allowlist access identity fingerprint pin pubkey security",
+    },
+    SettingEntry {
+        id: SettingId::BlockedPeers,
+        page: SettingsPage::Privacy,
+        label: "Blocked callers",
+        keywords: "blocklist access identity fingerprint reject pubkey security",
+    },
+    SettingEntry {
+        id: SettingId::Renegotiation,
+        page: SettingsPage::Privacy,
+        label: "Mid-call changes from the peer",
+        keywords: "renegotiation offer data channel encryption dtls prompt accept",
+    },
+    SettingEntry {
+        id: SettingId::StatsInterval,
+        page: SettingsPage::Advanced,
+        label: "Stats sampling interval",
+        keywords: "getstats graph timeline seconds",
+    },
+    SettingEntry {
+        id: SettingId::IceDisconnectedTimeout,
+        page: SettingsPage::Advanced,
+        label: "ICE disconnected timeout",
+        keywords: "connection seconds keepalive",
+    },
+    SettingEntry {
+        id: SettingId::IceFailedTimeout,
+        page: SettingsPage::Advanced,
+        label: "ICE failed timeout",
+        keywords: "connection seconds",
+    },
+    SettingEntry {
+        id: SettingId::AutoIceRestart,
+        page: SettingsPage::Advanced,
+        label: "Restart ICE automatically when the connection drops",
+        keywords: "reconnect ice restart disconnected failed",
+    },
+    SettingEntry {
+        id: SettingId::TurnFailover,
+        page: SettingsPage::Advanced,
+        label: "Fail over to another TURN server when the relay degrades",
+        keywords: "relay ice restart rtt loss automatic",
+    },
+    SettingEntry {
+        id: SettingId::FailoverRtt,
+        page: SettingsPage::Advanced,
+        label: "Failover RTT threshold",
+        keywords: "turn relay round trip latency milliseconds",
+    },
+    SettingEntry {
+        id: SettingId::FailoverLoss,
+        page: SettingsPage::Advanced,
+        label: "Failover packet loss threshold",
+        keywords: "turn relay percent",
+    },
+    SettingEntry {
+        id: SettingId::TurnCredentialLifetime,
+        page: SettingsPage::Advanced,
+        label: "TURN credential lifetime",
+        keywords: "rest api shared secret coturn expiry ttl hours",
+    },
+    SettingEntry {
+        id: SettingId::DefaultFolders,
+        page: SettingsPage::Files,
+        label: "Default folders",
+        keywords: "directory dialog open save sdp vcard contacts export capabilities signaling",
+    },
+];
+
+impl SettingEntry {
+    /// Every whitespace-separated term of the query must appear in the
+    /// label, keywords or page title.
+    fn matches(&self, query: &str) -> bool {
+        let haystack =
+            format!("{} {} {}", self.label, self.keywords, self.page.title()).to_lowercase();
+        query
+            .to_lowercase()
+            .split_whitespace()
+            .all(|term| haystack.contains(term))
+    }
+
+    /// `browse` is set when the entry wants a folder picked for a kind.
+    fn show(
+        &self,
+        ui: &mut egui::Ui,
+        settings: &mut Settings,
+        devices: &mut DeviceState,
+        browse: &mut Option<FileKind>,
+    ) {
+        match self.id {
+            SettingId::StunServers => {
+                ui.label(format!("{} (one per line):", self.label));
+                let mut text = settings.network.stun_servers.join("\n");
+                if ui.text_edit_multiline(&mut text).changed() {
+                    settings.network.stun_servers = text.lines().map(str::to_owned).collect();
+                }
+            }
+            SettingId::TurnServers => {
+                ui.label(format!("{}:", self.label));
+                let servers = &mut settings.network.turn_servers;
+                let mut remove = None;
+                egui::Grid::new("turn_servers")
+                    .num_columns(5)
+                    .show(ui, |ui| {
+                        for (index, server) in servers.iter_mut().enumerate() {
+                            ui.add(
+                                egui::TextEdit::singleline(&mut server.url)
+                                    .hint_text("turn:host:3478"),
+                            );
+                            ui.add(
+                                egui::TextEdit::singleline(&mut server.username)
+                                    .hint_text("username")
+                                    .desired_width(80.0),
+                            );
+                            let kind = match server.auth {
+                                TurnAuth::Static => "Static",
+                                TurnAuth::SharedSecret { .. } => "Shared secret",
+                                TurnAuth::CredentialsUrl { .. } => "Credentials URL",
+                            };
+                            egui::ComboBox::from_id_source(("turn_auth", index))
+                                .selected_text(kind)
+                                .show_ui(ui, |ui| {
+                                    for (label, auth) in [
+                                        ("Static", TurnAuth::Static),
+                                        (
+                                            "Shared secret",
+                                            TurnAuth::SharedSecret {
+                                                secret: String::new(),
+                                            },
+                                        ),
+                                        (
+                                            "Credentials URL",
+                                            TurnAuth::CredentialsUrl { url: String::new() },
+                                        ),
+                                    ] {
+                                        if ui.selectable_label(kind == label, label).clicked()
+                                            && kind != label
+                                        {
+                                            server.auth = auth;
+                                            server.issued = None;
+                                        }
+                                    }
+                                });
+                            let (secret, hint, password) = match &mut server.auth {
+                                TurnAuth::Static => (&mut server.credential, "credential", true),
+                                TurnAuth::SharedSecret { secret } => {
+                                    (secret, "shared secret", true)
+                                }
+                                TurnAuth::CredentialsUrl { url } => {
+                                    (url, "https://.../credentials", false)
+                                }
+                            };
+                            ui.add(
+                                egui::TextEdit::singleline(secret)
+                                    .hint_text(hint)
+                                    .password(password)
+                                    .desired_width(120.0),
+                            );
+                            if ui.small_button("Remove").clicked() {
+                                remove = Some(index);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                if let Some(index) = remove {
+                    servers.remove(index);
+                }
+                if ui.small_button("Add TURN server").clicked() {
+                    servers.push(TurnServer::default());
+                }
+            }
+            SettingId::SignalingUrl => {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}:", self.label));
+                    ui.text_edit_singleline(&mut settings.network.signaling_url);
+                });
+            }
+            SettingId::SignalingLogin => {
+                ui.label(format!("{} (empty token endpoint for none):", self.label));
+                egui::Grid::new("signaling_login")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Token endpoint:");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut settings.network.auth.token_url)
+                                .hint_text("https://auth.example.com/oauth/token"),
+                        );
+                        ui.end_row();
+                        ui.label("Client ID:");
+                        ui.text_edit_singleline(&mut settings.network.auth.client_id);
+                        ui.end_row();
+                    });
+            }
+            SettingId::AllowIpv6 => {
+                ui.checkbox(&mut settings.network.allow_ipv6, self.label);
+            }
+            SettingId::EchoTestUrl => {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}:", self.label));
+                    ui.text_edit_singleline(&mut settings.network.echo_test_url);
+                });
+            }
+            SettingId::NostrRelays => {
+                ui.label(format!("{} (one per line):", self.label));
+                edit_lines(ui, &mut settings.network.nostr_relays);
+            }
+            SettingId::Libp2pBootstrap => {
+                ui.label(format!("{} (multiaddrs, one per line):", self.label));
+                edit_lines(ui, &mut settings.network.libp2p_bootstrap);
+            }
+            SettingId::Libp2pRelays => {
+                ui.label(format!("{} (multiaddrs, one per line):", self.label));
+                edit_lines(ui, &mut settings.network.libp2p_relays);
+            }
+            SettingId::Camera => self.device_ui(ui, DeviceKind::Camera, settings, devices),
+            SettingId::Microphone => self.device_ui(ui, DeviceKind::Microphone, settings, devices),
+            SettingId::Speaker => self.device_ui(ui, DeviceKind::Speaker, settings, devices),
+            SettingId::MicrophoneChannels => {
+                let inputs = devices
+                    .selected(DeviceKind::Microphone)
+                    .and_then(|device| device.channels);
+                let Some(inputs) = inputs.map(usize::from) else {
+                    ui.label(format!("{}: no microphone selected", self.label));
+                    return;
+                };
+                let outputs = settings.media.opus.channels();
+                let map = &mut settings.media.microphone_channels;
+                ui.label(format!("{}:", self.label));
+                egui::Grid::new("microphone_channels").show(ui, |ui| {
+                    ui.label("");
+                    for input in 0..inputs {
+                        ui.label(format!("In {}", input + 1));
+                    }
+                    ui.end_row();
+                    for output in 0..outputs {
+                        ui.label(match (outputs, output) {
+                            (1, _) => "Sent",
+                            (_, 0) => "Left",
+                            _ => "Right",
+                        });
+                        for input in 0..inputs {
+                            let mut feeds = map.feeds(output, input, inputs, outputs);
+                            if ui.checkbox(&mut feeds, "").changed() {
+                                map.set_feeds(output, input, (inputs, outputs), feeds);
+                            }
+                        }
+                        ui.end_row();
+                    }
+                    ui.label("Gain");
+                    for input in 0..inputs {
+                        let mut gain = map.gain_db(input);
+                        let drag = egui::DragValue::new(&mut gain)
+                            .clamp_range(-40.0..=20.0)
+                            .speed(0.5)
+                            .suffix(" dB");
+                        if ui.add(drag).changed() {
+                            map.set_gain_db(input, gain);
+                        }
+                    }
+                    ui.end_row();
+                });
+                if !map.is_default() && ui.small_button("Mix all channels").clicked() {
+                    *map = Default::default();
+                }
+            }
+            SettingId::RescanDevices => {
+                if ui.button(self.label).clicked() {
+                    devices.refresh(&settings.devices);
+                }
+            }
+            SettingId::OfferAudio => {
+                ui.checkbox(&mut settings.media.offer_audio, self.label);
+            }
+            SettingId::OfferVideo => {
+                ui.checkbox(&mut settings.media.offer_video, self.label);
+            }
+            SettingId::SendMicrophone => {
+                ui.add_enabled(
+                    audio::can_capture(),
+                    egui::Checkbox::new(&mut settings.media.send_microphone, self.label),
+                )
+                .on_disabled_hover_text("This build has no audio support");
+            }
+            SettingId::PreferredResolution => {
+                let constraints = &mut settings.media.video_constraints;
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}:", self.label));
+                    let mut width = constraints.width.ideal.unwrap_or(1280);
+                    let mut height = constraints.height.ideal.unwrap_or(720);
+                    ui.add(egui::DragValue::new(&mut width).clamp_range(160..=7680));
+                    ui.label("x");
+                    ui.add(egui::DragValue::new(&mut height).clamp_range(120..=4320));
+                    constraints.width.ideal = Some(width);
+                    constraints.height.ideal = Some(height);
+                });
+            }
+            SettingId::PreferredFrameRate => {
+                let frame_rate = &mut settings.media.video_constraints.frame_rate;
+                let mut ideal = frame_rate.ideal.unwrap_or(30.0);
+                ui.add(
+                    egui::Slider::new(&mut ideal, 5.0..=60.0)
+                        .text(self.label)
+                        .suffix(" fps"),
+                );
+                frame_rate.ideal = Some(ideal);
+            }
+            SettingId::FacingMode => {
+                let facing = &mut settings.media.video_constraints.facing_mode.ideal;
+                let text = |mode: Option<FacingMode>| match mode {
+                    None => "Any",
+                    Some(FacingMode::User) => "Front (user)",
+                    Some(FacingMode::Environment) => "Back (environment)",
+                    Some(FacingMode::Left) => "Left",
+                    Some(FacingMode::Right) => "Right",
+                };
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}:", self.label));
+                    egui::ComboBox::from_id_source("facing_mode")
+                        .selected_text(text(*facing))
+                        .show_ui(ui, |ui| {
+                            for mode in
+                                [None, Some(FacingMode::User), Some(FacingMode::Environment)]
+                            {
+                                ui.selectable_value(facing, mode, text(mode));
+                            }
+                        });
+                });
+            }
+            SettingId::TestToneFrequency => {
+                ui.add(
+                    egui::Slider::new(&mut settings.media.test_tone_hz, 50.0..=4000.0)
+                        .logarithmic(true)
+                        .text(self.label)
+                        .suffix(" Hz"),
+                )
+                .on_hover_text("Used the next time the Test tone microphone starts sending");
+            }
+            SettingId::MirrorSelfView => {
+                ui.checkbox(&mut settings.media.mirror_self_view, self.label)
+                    .on_hover_text("Only on this screen; the peer sees the camera unflipped");
+            }
+            SettingId::PowerSaving => {
+                ui.checkbox(&mut settings.media.power_saving, self.label)
+                    .on_hover_text(
+                        "Up to 720p and 15 fps on battery, less when the battery is low \
+                         or the machine is too hot; the status bar says when",
+                    );
+            }
+            SettingId::Simulcast => {
+                ui.checkbox(&mut settings.media.simulcast, self.label)
+                    .on_hover_text(
+                        "Full, half and quarter size at once, so the SFU can forward each \
+                         subscriber the one that suits it; used from the next room joined",
+                    );
+            }
+            SettingId::AdaptiveBitrate => {
+                ui.checkbox(&mut settings.media.adaptive_bitrate, self.label)
+                    .on_hover_text(
+                        "Lower the bitrate when the remote reports loss or delay, then the \
+                         size and frame rate; used the next time video starts sending",
+                    );
+            }
+            SettingId::Scalability => {
+                let scalability = &mut settings.media.scalability;
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}:", self.label));
+                    egui::ComboBox::from_id_source("scalability_mode")
+                        .selected_text(scalability.to_string())
+                        .show_ui(ui, |ui| {
+                            for mode in ScalabilityMode::ALL {
+                                ui.selectable_value(scalability, mode, mode.to_string());
+                            }
+                        });
+                })
+                .response
+                .on_hover_text(
+                    "L spatial, T temporal layers, which an SVC-aware SFU can leave out \
+                     on a slow link; other codecs send one. Used from the next camera start",
+                );
+            }
+            SettingId::BackgroundEffect => {
+                ui.add_enabled_ui(camera::can_segment(), |ui| {
+                    background_effect_ui(ui, self.label, &mut settings.media.background);
+                })
+                .response
+                .on_hover_text("Changes apply at once, to the preview and what is sent")
+                .on_disabled_hover_text("This build has no background effects");
+            }
+            SettingId::SegmentationModel => {
+                path_ui(
+                    ui,
+                    self.label,
+                    "An .onnx file, such as MediaPipe's selfie segmentation",
+                    &mut settings.media.background.model,
+                );
+            }
+            SettingId::BackgroundImage => {
+                path_ui(
+                    ui,
+                    self.label,
+                    "A PNG or JPEG, cropped to the camera's size",
+                    &mut settings.media.background.image,
+                );
+            }
+            SettingId::ThumbnailInterval => {
+                ui.add(
+                    egui::Slider::new(&mut settings.media.thumbnail_interval_secs, 2..=60)
+                        .text(self.label)
+                        .suffix(" s"),
+                );
+            }
+            SettingId::VideoCodecs => {
+                ui.label(format!("{}, best first:", self.label));
+                edit_codecs(ui, settings.media.codecs.list_mut(RTPCodecType::Video));
+            }
+            SettingId::AudioCodecs => {
+                ui.label(format!("{}, best first:", self.label));
+                edit_codecs(ui, settings.media.codecs.list_mut(RTPCodecType::Audio));
+            }
+            SettingId::OpusApplication => {
+                let application = &mut settings.media.opus.application;
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}:", self.label));
+                    egui::ComboBox::from_id_source("opus_application")
+                        .selected_text(application.to_string())
+                        .show_ui(ui, |ui| {
+                            for option in OpusApplication::ALL {
+                                ui.selectable_value(application, option, option.to_string());
+                            }
+                        });
+                })
+                .response
+                .on_hover_text(OPUS_RESTART_HINT);
+            }
+            SettingId::OpusBitrate => {
+                let bitrate = &mut settings.media.opus.bitrate_kbps;
+                ui.horizontal(|ui| {
+                    let mut automatic = bitrate.is_none();
+                    if ui
+                        .checkbox(&mut automatic, format!("{}: automatic", self.label))
+                        .changed()
+                    {
+                        *bitrate = (!automatic).then_some(32);
+                    }
+                    if let Some(kbps) = bitrate {
+                        ui.add(
+                            egui::Slider::new(kbps, OpusSettings::BITRATE_KBPS)
+                                .logarithmic(true)
+                                .suffix(" kbps"),
+                        );
+                    }
+                })
+                .response
+                .on_hover_text(OPUS_RESTART_HINT);
+            }
+            SettingId::OpusFec => {
+                let opus = &mut settings.media.opus;
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut opus.fec, self.label);
+                    ui.add_enabled(
+                        opus.fec,
+                        egui::Slider::new(&mut opus.expected_loss_percent, 1..=50)
+                            .text("expected loss")
+                            .suffix(" %"),
+                    );
+                })
+                .response
+                .on_hover_text(
+                    "Each packet also carries a rougher copy of the one before, \
+                     at the cost of some bitrate. Tuned for the expected loss until \
+                     the peer reports how much it sees",
+                );
+            }
+            SettingId::OpusRed => {
+                ui.checkbox(&mut settings.media.opus.red, self.label)
+                    .on_hover_text(
+                        "Each packet also carries the whole one before, when the peer takes \
+                         RED, at the cost of twice the bitrate. Used for calls started after \
+                         the change",
+                    );
+            }
+            SettingId::OpusLossThreshold => {
+                let threshold = &mut settings.media.opus.loss_threshold_percent;
+                ui.horizontal(|ui| {
+                    let mut adaptive = threshold.is_some();
+                    if ui.checkbox(&mut adaptive, self.label).changed() {
+                        *threshold = adaptive.then_some(2);
+                    }
+                    if let Some(percent) = threshold {
+                        ui.add(egui::Slider::new(percent, 1..=20).suffix(" %"));
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "FEC and RED go on once the peer reports losing more than this, and off \
+                     again at half of it. Unchecked, they are always on",
+                );
+            }
+            SettingId::OpusStereo => {
+                ui.checkbox(&mut settings.media.opus.stereo, self.label)
+                    .on_hover_text(
+                        "For music: the microphone channels go out as left and right, without \
+                         echo cancellation, noise suppression or automatic gain. Used for calls \
+                         started after the change",
+                    );
+            }
+            SettingId::OpusDtx => {
+                ui.checkbox(&mut settings.media.opus.dtx, self.label)
+                    .on_hover_text("Sends almost nothing while the microphone hears silence");
+            }
+            SettingId::EchoCancellation => {
+                ui.add_enabled(
+                    audio::can_cancel_echo(),
+                    egui::Checkbox::new(
+                        &mut settings.media.processing.echo_cancellation,
+                        self.label,
+                    ),
+                )
+                .on_hover_text(
+                    "Takes the remote audio off the speaker back out of the microphone,                      for calls without headphones. Used the next time the microphone                      starts sending",
+                )
+                .on_disabled_hover_text("This build has no echo cancellation");
+            }
+            SettingId::NoiseSuppression => {
+                ui.add_enabled(
+                    audio::can_capture(),
+                    egui::Checkbox::new(
+                        &mut settings.media.processing.noise_suppression,
+                        self.label,
+                    ),
+                )
+                .on_hover_text(
+                    "Takes out what is not a voice, such as fans, traffic and typing. \
+                     Used the next time the microphone starts sending",
+                )
+                .on_disabled_hover_text("This build has no audio support");
+            }
+            SettingId::MicrophoneGain => {
+                let processing = &mut settings.media.processing;
+                ui.horizontal(|ui| {
+                    ui.checkbox(
+                        &mut processing.automatic_gain,
+                        format!("{}: automatic", self.label),
+                    );
+                    if !processing.automatic_gain {
+                        ui.add(
+                            egui::Slider::new(
+                                &mut processing.manual_gain_db,
+                                ProcessingSettings::MANUAL_GAIN_DB,
+                            )
+                            .suffix(" dB"),
+                        );
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "Automatic brings a voice to a steady loudness, however quiet the \
+                     microphone; otherwise a fixed gain. Used the next time the \
+                     microphone starts sending",
+                );
+            }
+            SettingId::MdnsHostCandidates => {
+                ui.checkbox(&mut settings.privacy.mdns_host_candidates, self.label);
+            }
+            SettingId::RelayOnly => {
+                ui.checkbox(&mut settings.privacy.relay_only, self.label);
+            }
+            SettingId::AllowlistOnly => {
+                ui.checkbox(&mut settings.privacy.access.allowlist_only, self.label);
+            }
+            SettingId::AllowedPeers => {
+                ui.label(format!(
+                    "{} (names, keys or fingerprints, one per line):",
+                    self.label
+                ));
+                edit_lines(ui, &mut settings.privacy.access.allowed);
+            }
+            SettingId::BlockedPeers => {
+                ui.label(format!(
+                    "{} (names, keys or fingerprints, one per line):",
+                    self.label
+                ));
+                edit_lines(ui, &mut settings.privacy.access.blocked);
+            }
+            SettingId::Renegotiation => {
+                let policy = &mut settings.privacy.renegotiation.policy;
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}:", self.label));
+                    egui::ComboBox::from_id_source("renegotiation_policy")
+                        .selected_text(policy.to_string())
+                        .show_ui(ui, |ui| {
+                            for option in RenegotiationPolicy::ALL {
+                                ui.selectable_value(policy, option, option.to_string());
+                            }
+                        });
+                });
+                ui.label("Contacts can override this in the contacts panel.");
+            }
+            SettingId::StatsInterval => {
+                ui.add(
+                    egui::Slider::new(&mut settings.advanced.stats_interval_secs, 1..=10)
+                        .text(self.label)
+                        .suffix(" s"),
+                );
+            }
+            SettingId::IceDisconnectedTimeout => {
+                ui.add(
+                    egui::Slider::new(&mut settings.advanced.ice_disconnected_timeout_secs, 1..=60)
+                        .text(self.label)
+                        .suffix(" s"),
+                );
+            }
+            SettingId::IceFailedTimeout => {
+                ui.add(
+                    egui::Slider::new(&mut settings.advanced.ice_failed_timeout_secs, 5..=120)
+                        .text(self.label)
+                        .suffix(" s"),
+                );
+            }
+            SettingId::AutoIceRestart => {
+                ui.checkbox(&mut settings.advanced.auto_ice_restart, self.label);
+            }
+            SettingId::TurnFailover => {
+                ui.checkbox(&mut settings.advanced.turn_failover, self.label);
+            }
+            SettingId::FailoverRtt => {
+                ui.add(
+                    egui::Slider::new(&mut settings.advanced.failover_rtt_ms, 100..=2000)
+                        .text(self.label)
+                        .suffix(" ms"),
+                );
+            }
+            SettingId::TurnCredentialLifetime => {
+                ui.add(
+                    egui::Slider::new(&mut settings.advanced.turn_credential_hours, 1..=168)
+                        .text(self.label)
+                        .suffix(" h"),
+                );
+            }
+            SettingId::FailoverLoss => {
+                ui.add(
+                    egui::Slider::new(&mut settings.advanced.failover_loss_percent, 1..=50)
+                        .text(self.label)
+                        .suffix(" %"),
+                );
+            }
+            SettingId::DefaultFolders => {
+                ui.label(format!(
+                    "{} (dialogs remember the last one used):",
+                    self.label
+                ));
+                egui::Grid::new("default_folders")
+                    .num_columns(3)
+                    .show(ui, |ui| {
+                        for kind in FileKind::ALL {
+                            ui.label(kind.label());
+                            let mut text = settings
+                                .files
+                                .folder(kind)
+                                .map(|folder| folder.display().to_string())
+                                .unwrap_or_default();
+                            if ui
+                                .add(
+                                    egui::TextEdit::singleline(&mut text)
+                                        .hint_text("Not set")
+                                        .desired_width(200.0),
+                                )
+                                .changed()
+                            {
+                                let folder = (!text.is_empty()).then(|| PathBuf::from(text));
+                                settings.files.set_folder(kind, folder);
+                            }
+                            if ui.small_button("Browse...").clicked() {
+                                *browse = Some(kind);
+                            }
+                            ui.end_row();
+                        }
+                    });
+            }
+        }
+    }
+
+    fn device_ui(
+        &self,
+        ui: &mut egui::Ui,
+        kind: DeviceKind,
+        settings: &mut Settings,
+        devices: &mut DeviceState,
+    ) {
+        ui.horizontal(|ui| {
+            ui.label(format!("{}:", self.label));
+            devices.picker_ui(ui, kind, &mut settings.devices);
+        });
+    }
+}
+
+/// Picks the camera's background effect, with how strong a blur is.
+pub(crate) fn background_effect_ui(
+    ui: &mut egui::Ui,
+    label: &str,
+    background: &mut BackgroundSettings,
+) {
+    ui.horizontal(|ui| {
+        ui.label(format!("{}:", label));
+        egui::ComboBox::from_id_source(format!("background_effect_{}", label))
+            .selected_text(background.effect.to_string())
+            .show_ui(ui, |ui| {
+                for effect in BackgroundEffect::ALL {
+                    ui.selectable_value(&mut background.effect, effect, effect.to_string());
+                }
+            });
+        if background.effect == BackgroundEffect::Blur {
+            ui.add(
+                egui::Slider::new(&mut background.blur_radius, BackgroundSettings::BLUR_RADIUS)
+                    .suffix(" px"),
+            );
+        }
+    });
+}
+
+/// A path typed in, or `None` when left empty.
+fn path_ui(ui: &mut egui::Ui, label: &str, hint: &str, path: &mut Option<PathBuf>) {
+    ui.horizontal(|ui| {
+        ui.label(format!("{}:", label));
+        let mut text = path
+            .as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        if ui
+            .add(
+                egui::TextEdit::singleline(&mut text)
+                    .hint_text(hint)
+                    .desired_width(240.0),
+            )
+            .changed()
+        {
+            *path = (!text.is_empty()).then(|| PathBuf::from(text));
+        }
+    });
+}
+
+/// A multiline editor for a list. Blank lines are kept while editing so a
+/// new entry can be started; readers skip them.
+fn edit_lines(ui: &mut egui::Ui, lines: &mut Vec<String>) {
+    let mut text = lines.join("\n");
+    if ui.text_edit_multiline(&mut text).changed() {
+        *lines = text.split('\n').map(str::to_owned).collect();
+    }
+}
+
+/// Checkboxes to enable codecs and buttons to move them up and down. The
+/// last enabled one cannot be turned off; calls need something to offer.
+fn edit_codecs(ui: &mut egui::Ui, codecs: &mut [CodecPreference]) {
+    let enabled = codecs.iter().filter(|entry| entry.enabled).count();
+    let mut swap = None;
+    for index in 0..codecs.len() {
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(index > 0, egui::Button::new("Up").small())
+                .clicked()
+            {
+                swap = Some(index - 1);
+            }
+            if ui
+                .add_enabled(index + 1 < codecs.len(), egui::Button::new("Down").small())
+                .clicked()
+            {
+                swap = Some(index);
+            }
+            let entry = &mut codecs[index];
+            let name = codec::display_name(&entry.mime_type).to_owned();
+            ui.add_enabled(
+                !entry.enabled || enabled > 1,
+                egui::Checkbox::new(&mut entry.enabled, name),
+            );
+        });
+    }
+    if let Some(index) = swap {
+        codecs.swap(index, index + 1);
+    }
+    ui.label("Used for calls started after a change.");
+}
+
+#[derive(Default)]
+pub struct SettingsWindow {
+    pub open: bool,
+    page: SettingsPage,
+    query: String,
+    /// A folder dialog opened from the Files page, and what it is for.
+    browsing: Option<(FileKind, oneshot::Receiver<Option<PathBuf>>)>,
+}
+
+impl SettingsWindow {
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        settings: &mut Settings,
+        devices: &mut DeviceState,
+    ) {
+        if let Some((kind, picked)) = &mut self.browsing {
+            match picked.try_recv() {
+                Ok(folder) => {
+                    if folder.is_some() {
+                        settings.files.set_folder(*kind, folder);
+                    }
+                    self.browsing = None;
+                }
+                Err(oneshot::error::TryRecvError::Empty) => {}
+                Err(oneshot::error::TryRecvError::Closed) => self.browsing = None,
+            }
+        }
+        let mut browse = None;
+        let mut open = self.open;
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.query)
+                        .hint_text("Search settings")
+                        .desired_width(f32::INFINITY),
+                );
+                ui.separator();
+
+                if self.query.trim().is_empty() {
+                    self.show_page(ui, settings, devices, &mut browse);
+                } else {
+                    self.show_search_results(ui, settings, devices, &mut browse);
+                }
+
+                ui.separator();
+                ui.label("Changes apply to the next peer connection.");
+            });
+        self.open = open;
+
+        if let Some(kind) = browse.filter(|_| self.browsing.is_none()) {
+            let (sender, picked) = oneshot::channel();
+            let folder = settings.files.folder(kind).cloned();
+            let ctx = ctx.clone();
+            tokio::spawn(async move {
+                let _ = sender.send(file_dialogs::pick_folder(kind, folder).await);
+                ctx.request_repaint();
+            });
+            self.browsing = Some((kind, picked));
+        }
+    }
+
+    fn show_page(
+        &mut self,
+        ui: &mut egui::Ui,
+        settings: &mut Settings,
+        devices: &mut DeviceState,
+        browse: &mut Option<FileKind>,
+    ) {
+        ui.horizontal(|ui| {
+            for page in SettingsPage::ALL {
+                ui.selectable_value(&mut self.page, page, page.title());
+            }
+        });
+        ui.separator();
+        for entry in ENTRIES.iter().filter(|e| e.page == self.page) {
+            entry.show(ui, settings, devices, browse);
+        }
+    }
+
+    fn show_search_results(
+        &mut self,
+        ui: &mut egui::Ui,
+        settings: &mut Settings,
+        devices: &mut DeviceState,
+        browse: &mut Option<FileKind>,
+    ) {
+        let mut any = false;
+        for page in SettingsPage::ALL {
+            let mut matches = ENTRIES
+                .iter()
+                .filter(|e| e.page == page && e.matches(&self.query))
+                .peekable();
+            if matches.peek().is_none() {
+                continue;
+            }
+            any = true;
+            ui.strong(page.title());
+            for entry in matches {
+                entry.show(ui, settings, devices, browse);
+            }
+            ui.add_space(4.0);
+        }
+        if !any {
+            ui.label(format!("No settings match \"{}\".", self.query.trim()));
+        }
+    }
+}
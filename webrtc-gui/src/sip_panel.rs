@@ -0,0 +1,273 @@
+//! SIP gateway panel: registration, dialing and call handling.
+
+use eframe::egui;
+use log::info;
+use webrtc::rtp_transceiver::{
+    rtp_codec::RTPCodecType, rtp_transceiver_direction::RTCRtpTransceiverDirection,
+    RTCRtpTransceiverInit,
+};
+use webrtc_core::sip::{SipConfig, SipEvent, SipUserAgent};
+
+use crate::WebRTCApp;
+
+struct IncomingSipCall {
+    call_id: String,
+    from: String,
+    sdp: String,
+}
+
+#[derive(Default)]
+pub struct SipState {
+    config: SipConfig,
+    agent: Option<SipUserAgent>,
+    status: String,
+    pub(crate) dial_target: String,
+    incoming: Option<IncomingSipCall>,
+    active_call: Option<String>,
+}
+
+impl WebRTCApp {
+    /// Creates a standard peer connection with a single audio m-line, which
+    /// is what SIP endpoints expect to negotiate; the microphone's when it
+    /// is being sent.
+    async fn create_sip_peer_connection(&self) {
+        self.create_peer_connection(false).await;
+        if self.sending_microphone() {
+            return;
+        }
+        let pc = self.peer_connection.lock().await.clone();
+        if let Some(pc) = pc {
+            let init = RTCRtpTransceiverInit {
+                direction: RTCRtpTransceiverDirection::Recvonly,
+                send_encodings: vec![],
+            };
+            if let Err(err) = pc
+                .add_transceiver_from_kind(RTPCodecType::Audio, Some(init))
+                .await
+            {
+                info!("Failed to add audio transceiver: {:?}", err);
+            }
+        }
+    }
+
+    fn set_sip_status(&self, status: impl Into<String>) {
+        self.sip.lock().unwrap().status = status.into();
+    }
+
+    async fn sip_register(&self, ctx: egui::Context) {
+        let config = self.sip.lock().unwrap().config.clone();
+        self.set_sip_status("Registering...");
+        match SipUserAgent::start(config).await {
+            Ok((agent, mut events)) => {
+                if let Err(err) = agent.register() {
+                    self.set_sip_status(format!("Registration failed: {}", err));
+                    return;
+                }
+                self.sip.lock().unwrap().agent = Some(agent);
+                let app = self.clone();
+                tokio::spawn(async move {
+                    while let Some(event) = events.recv().await {
+                        app.handle_sip_event(event).await;
+                        ctx.request_repaint();
+                    }
+                });
+            }
+            Err(err) => self.set_sip_status(format!("Registration failed: {}", err)),
+        }
+    }
+
+    async fn handle_sip_event(&self, event: SipEvent) {
+        info!("SIP event: {:?}", event);
+        match event {
+            SipEvent::Registered { expires } => {
+                self.set_sip_status(format!("Registered ({}s)", expires));
+            }
+            SipEvent::Unregistered => {
+                let mut sip = self.sip.lock().unwrap();
+                sip.agent = None;
+                sip.status = "Unregistered".to_owned();
+            }
+            SipEvent::RegistrationFailed(reason) => {
+                let mut sip = self.sip.lock().unwrap();
+                sip.agent = None;
+                sip.status = format!("Registration failed: {}", reason);
+            }
+            SipEvent::IncomingCall { call_id, from, sdp } => {
+                let admitted = self.admit_caller("sip", &from, &sdp);
+                let mut sip = self.sip.lock().unwrap();
+                if !admitted || sip.active_call.is_some() || sip.incoming.is_some() {
+                    if let Some(agent) = &sip.agent {
+                        let _ = agent.reject(&call_id);
+                    }
+                    return;
+                }
+                sip.status = format!("Incoming call from {}", from);
+                sip.incoming = Some(IncomingSipCall { call_id, from, sdp });
+            }
+            SipEvent::Ringing { .. } => self.set_sip_status("Ringing..."),
+            SipEvent::CallAnswered { sdp, .. } => {
+                self.remote_sdp.set(sdp.clone());
+                self.handle_answer().await;
+                self.set_sip_status("In call");
+            }
+            SipEvent::CallFailed { call_id, reason } => {
+                self.end_sip_call(&call_id, format!("Call failed: {}", reason))
+                    .await;
+            }
+            SipEvent::CallEnded { call_id } => {
+                self.end_sip_call(&call_id, "Call ended".to_owned()).await;
+            }
+        }
+    }
+
+    async fn end_sip_call(&self, call_id: &str, status: String) {
+        let was_current = {
+            let mut sip = self.sip.lock().unwrap();
+            let is_active = sip.active_call.as_deref() == Some(call_id);
+            let is_incoming = sip.incoming.as_ref().map(|c| c.call_id.as_str()) == Some(call_id);
+            if is_active {
+                sip.active_call = None;
+            }
+            if is_incoming {
+                sip.incoming = None;
+            }
+            if is_active || is_incoming {
+                sip.status = status;
+            }
+            is_active
+        };
+        if was_current {
+            self.close_peer_connection().await;
+        }
+    }
+
+    async fn sip_call(&self) {
+        let (agent, target) = {
+            let sip = self.sip.lock().unwrap();
+            (sip.agent.clone(), sip.dial_target.clone())
+        };
+        let Some(agent) = agent else {
+            return;
+        };
+        self.set_sip_status(format!("Calling {}...", target));
+        self.create_sip_peer_connection().await;
+        self.create_offer().await;
+        let sdp = self.local_sdp.get().to_string();
+        match agent.invite(&target, sdp) {
+            Ok(call_id) => self.sip.lock().unwrap().active_call = Some(call_id),
+            Err(err) => self.set_sip_status(format!("Call failed: {}", err)),
+        }
+    }
+
+    async fn sip_accept(&self) {
+        let (agent, incoming) = {
+            let mut sip = self.sip.lock().unwrap();
+            (sip.agent.clone(), sip.incoming.take())
+        };
+        let (Some(agent), Some(incoming)) = (agent, incoming) else {
+            return;
+        };
+        self.create_sip_peer_connection().await;
+        let sdp = match self.answer_remote_offer(incoming.sdp).await {
+            Ok(sdp) => sdp,
+            Err(err) => {
+                info!("Failed to answer INVITE: {:?}", err);
+                let _ = agent.reject(&incoming.call_id);
+                return;
+            }
+        };
+        match agent.answer(&incoming.call_id, sdp) {
+            Ok(()) => {
+                let mut sip = self.sip.lock().unwrap();
+                sip.active_call = Some(incoming.call_id);
+                sip.status = format!("In call with {}", incoming.from);
+            }
+            Err(err) => self.set_sip_status(format!("Failed to answer: {}", err)),
+        }
+    }
+}
+
+impl WebRTCApp {
+    pub(crate) fn sip_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut sip = self.sip.lock().unwrap();
+        let registered = sip.agent.is_some();
+
+        ui.add_enabled_ui(!registered, |ui| {
+            egui::Grid::new("sip_account")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Server:");
+                    ui.text_edit_singleline(&mut sip.config.server);
+                    ui.end_row();
+                    ui.label("Username:");
+                    ui.text_edit_singleline(&mut sip.config.username);
+                    ui.end_row();
+                    ui.label("Password:");
+                    ui.add(egui::TextEdit::singleline(&mut sip.config.password).password(true));
+                    ui.end_row();
+                    ui.label("Domain:");
+                    ui.text_edit_singleline(&mut sip.config.domain);
+                    ui.end_row();
+                });
+        });
+
+        ui.horizontal(|ui| {
+            if !registered && ui.button("Register").clicked() {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.sip_register(ctx.clone()).await;
+                    ctx.request_repaint();
+                });
+            }
+            if registered && ui.button("Unregister").clicked() {
+                if let Some(agent) = &sip.agent {
+                    let _ = agent.unregister();
+                }
+            }
+            ui.label(&sip.status);
+        });
+
+        if !registered {
+            return;
+        }
+
+        if let Some(call_id) = sip.active_call.clone() {
+            if ui.button("Hang Up").clicked() {
+                if let Some(agent) = &sip.agent {
+                    let _ = agent.hangup(&call_id);
+                }
+            }
+        } else if let Some(incoming) = &sip.incoming {
+            ui.horizontal(|ui| {
+                ui.label(format!("Incoming call from {}", incoming.from));
+                if ui.button("Accept").clicked() {
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.sip_accept().await;
+                        ctx.request_repaint();
+                    });
+                }
+                if ui.button("Reject").clicked() {
+                    if let Some(agent) = &sip.agent {
+                        let _ = agent.reject(&incoming.call_id);
+                    }
+                }
+            });
+        } else {
+            ui.horizontal(|ui| {
+                ui.label("Call:");
+                ui.text_edit_singleline(&mut sip.dial_target);
+                if ui.button("Dial").clicked() && !sip.dial_target.is_empty() {
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.sip_call().await;
+                        ctx.request_repaint();
+                    });
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Stats timeline sampling and plot.
+
+use std::sync::{Arc, Weak};
+
+use eframe::egui;
+use egui_plot::{Legend, Line, LineStyle, Plot, PlotPoints, VLine};
+use webrtc::peer_connection::{peer_connection_state::RTCPeerConnectionState, RTCPeerConnection};
+use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+use webrtc_core::audio::MicrophoneTrack;
+use webrtc_core::codec;
+use webrtc_core::failover::{turn_server_for, FailoverThresholds, RelayEvent, RelayMonitor};
+use webrtc_core::stats::{CallCodecs, MarkerKind, StatsSampler};
+
+use crate::WebRTCApp;
+
+impl WebRTCApp {
+    /// Polls `get_stats` once a second into the stats timeline until the peer
+    /// connection is closed or replaced. With `failover` thresholds, a TURN
+    /// relay that stays over them triggers an ICE restart away from it.
+    pub(crate) fn spawn_stats_sampler(
+        &self,
+        peer_connection: Weak<RTCPeerConnection>,
+        period: tokio::time::Duration,
+        failover: Option<FailoverThresholds>,
+    ) {
+        let stats = Arc::clone(&self.stats);
+        let reconnect = Arc::clone(&self.reconnect);
+        let turn_servers = self.settings.lock().unwrap().network.turn_servers.clone();
+        tokio::spawn(async move {
+            let mut sampler = StatsSampler::default();
+            let mut monitor = failover.map(RelayMonitor::new);
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                let Some(pc) = peer_connection.upgrade() else {
+                    break;
+                };
+                if pc.connection_state() == RTCPeerConnectionState::Closed {
+                    break;
+                }
+                let report = pc.get_stats().await;
+                let audio_codecs = call_codecs(&pc, RTPCodecType::Audio).await;
+                let video_codecs = call_codecs(&pc, RTPCodecType::Video).await;
+                {
+                    let mut timeline = stats.lock().unwrap();
+                    sampler.collect(&report, &mut timeline);
+                    timeline.set_audio_codecs(audio_codecs);
+                    timeline.set_video_codecs(video_codecs);
+                }
+                match monitor.as_mut().and_then(|monitor| monitor.check(&report)) {
+                    Some(RelayEvent::Degraded {
+                        address,
+                        rtt_ms,
+                        loss,
+                    }) => {
+                        let server = turn_server_for(&turn_servers, &address)
+                            .await
+                            .unwrap_or_else(|| address.clone());
+                        let rtt = rtt_ms.map_or("n/a".to_owned(), |rtt| format!("{:.0} ms", rtt));
+                        stats.lock().unwrap().mark(
+                            MarkerKind::TurnFailover,
+                            format!(
+                                "Leaving {} (RTT {}, loss {:.0}%)",
+                                server,
+                                rtt,
+                                loss * 100.0
+                            ),
+                        );
+                        reconnect.lock().unwrap().request_failover(address);
+                    }
+                    Some(RelayEvent::Replaced { from, to }) => {
+                        stats
+                            .lock()
+                            .unwrap()
+                            .mark(MarkerKind::TurnFailover, format!("{} -> {}", from, to));
+                    }
+                    None => {}
+                }
+            }
+        });
+    }
+}
+
+/// The video codecs `pc` sends and receives in: the codec of the first
+/// local track going out, and of the first remote track that media has
+/// arrived on.
+pub(crate) async fn call_codecs(pc: &RTCPeerConnection, kind: RTPCodecType) -> CallCodecs {
+    let mut codecs = CallCodecs::default();
+    for transceiver in pc.get_transceivers().await {
+        if transceiver.kind() != kind {
+            continue;
+        }
+        let direction = transceiver.current_direction();
+        if codecs.sending.is_none() && direction.has_send() {
+            let track = transceiver.sender().await.track().await;
+            codecs.sending = track.and_then(|track| {
+                let track = track.as_any();
+                match track.downcast_ref::<TrackLocalStaticSample>() {
+                    Some(track) => Some(track.codec().mime_type),
+                    None => track.downcast_ref::<MicrophoneTrack>()?.mime_type(),
+                }
+            });
+        }
+        if codecs.receiving.is_none() && direction.has_recv() {
+            codecs.receiving = transceiver
+                .receiver()
+                .await
+                .tracks()
+                .await
+                .iter()
+                .map(|track| track.codec().capability.mime_type)
+                .find(|mime_type| !mime_type.is_empty());
+        }
+    }
+    codecs
+}
+
+/// "sending X, receiving Y", once either is known.
+pub(crate) fn codecs_text(codecs: &CallCodecs) -> Option<String> {
+    let CallCodecs { sending, receiving } = codecs;
+    if sending.is_none() && receiving.is_none() {
+        return None;
+    }
+    let name = |mime_type: &Option<String>| {
+        mime_type
+            .as_deref()
+            .map_or("nothing", codec::display_name)
+            .to_owned()
+    };
+    Some(format!(
+        "sending {}, receiving {}",
+        name(sending),
+        name(receiving)
+    ))
+}
+
+pub(crate) fn marker_color(kind: MarkerKind) -> egui::Color32 {
+    match kind {
+        MarkerKind::Renegotiation => egui::Color32::LIGHT_BLUE,
+        MarkerKind::CandidatePairSwitch => egui::Color32::GOLD,
+        MarkerKind::KeyframeRequest => egui::Color32::LIGHT_RED,
+        MarkerKind::Mute => egui::Color32::GRAY,
+        MarkerKind::TurnFailover => egui::Color32::RED,
+        MarkerKind::ConnectionState => egui::Color32::LIGHT_GREEN,
+        MarkerKind::IceState => egui::Color32::from_rgb(96, 200, 160),
+        MarkerKind::SignalingState => egui::Color32::from_rgb(120, 160, 255),
+        MarkerKind::GatheringState => egui::Color32::LIGHT_GRAY,
+        MarkerKind::LocalCandidate => egui::Color32::from_rgb(230, 200, 90),
+        MarkerKind::RemoteCandidate => egui::Color32::from_rgb(230, 150, 60),
+        MarkerKind::Error => egui::Color32::from_rgb(255, 60, 60),
+    }
+}
+
+impl WebRTCApp {
+    /// Puts a failure on the event timeline.
+    pub(crate) fn mark_error(&self, detail: impl Into<String>) {
+        self.stats.lock().unwrap().mark(MarkerKind::Error, detail);
+    }
+
+    pub(crate) fn stats_ui(&self, ui: &mut egui::Ui) {
+        let timeline = self.stats.lock().unwrap();
+        for (kind, codecs) in [
+            ("Audio", timeline.audio_codecs()),
+            ("Video", timeline.video_codecs()),
+        ] {
+            if let Some(text) = codecs_text(codecs) {
+                ui.label(format!("{} codec: {}", kind, text));
+            }
+        }
+        let rtt: PlotPoints = timeline
+            .samples()
+            .filter_map(|s| s.rtt_ms.map(|rtt| [s.time, rtt]))
+            .collect();
+        let inbound: PlotPoints = timeline
+            .samples()
+            .map(|s| [s.time, s.inbound_kbps])
+            .collect();
+        let outbound: PlotPoints = timeline
+            .samples()
+            .map(|s| [s.time, s.outbound_kbps])
+            .collect();
+
+        Plot::new("stats_timeline")
+            .height(200.0)
+            .legend(Legend::default())
+            .x_axis_label("seconds")
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(rtt).name("RTT (ms)"));
+                plot_ui.line(Line::new(inbound).name("Inbound (kbps)"));
+                plot_ui.line(Line::new(outbound).name("Outbound (kbps)"));
+                for marker in timeline.markers().filter(|m| m.kind.plotted()) {
+                    plot_ui.vline(
+                        VLine::new(marker.time)
+                            .name(marker.kind)
+                            .color(marker_color(marker.kind))
+                            .style(LineStyle::dashed_loose()),
+                    );
+                }
+            });
+
+        for marker in timeline
+            .markers()
+            .rev()
+            .filter(|m| m.kind.plotted())
+            .take(10)
+        {
+            ui.colored_label(
+                marker_color(marker.kind),
+                format!("{:>7.1}s  {}: {}", marker.time, marker.kind, marker.detail),
+            );
+        }
+    }
+}
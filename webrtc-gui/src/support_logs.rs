@@ -0,0 +1,325 @@
+//! Support sessions: with the user's consent, our log streams to the peer
+//! over the "logs" data channel, and a technician's side shows the log the
+//! peer shares.
+
+use std::sync::Arc;
+
+use eframe::egui;
+use log::{info, Level};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use webrtc_core::audit::AuditEvent;
+use webrtc_core::bounded::{BoundedBuffer, DropPolicy};
+use webrtc_core::framing;
+use webrtc_core::log_stream::{self, LogMessage, LogRecord, LOG_LABEL, LOG_STREAM_ID};
+
+use crate::negotiated_channel::{self, ChannelPanel, NegotiatedChannel};
+use crate::WebRTCApp;
+
+const MAX_RECEIVED: usize = 2000;
+const MAX_RECEIVED_BYTES: usize = 512 * 1024;
+
+pub struct SupportLogsState {
+    channel: NegotiatedChannel,
+    /// The peer asked for our log and we have not answered yet.
+    asked: bool,
+    /// Forwards our log to the peer while we share it.
+    sender: Option<JoinHandle<()>>,
+    /// We asked for the peer's log and are waiting for an answer.
+    requested: bool,
+    /// The peer is sharing its log with us.
+    receiving: bool,
+    received: BoundedBuffer<LogRecord>,
+    status: String,
+}
+
+impl Default for SupportLogsState {
+    fn default() -> Self {
+        Self {
+            channel: NegotiatedChannel::default(),
+            asked: false,
+            sender: None,
+            requested: false,
+            receiving: false,
+            received: BoundedBuffer::new(MAX_RECEIVED, MAX_RECEIVED_BYTES, DropPolicy::DropOldest),
+            status: String::new(),
+        }
+    }
+}
+
+impl SupportLogsState {
+    fn sharing(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// Stops sharing without telling the peer, for when the channel is
+    /// already gone.
+    fn stop_sharing(&mut self) {
+        if let Some(sender) = self.sender.take() {
+            sender.abort();
+            if let Some(tap) = log_stream::tap() {
+                tap.stop();
+            }
+            info!("Stopped sharing logs with the peer");
+        }
+    }
+
+    /// Back to no channel, for a new peer connection.
+    pub(crate) fn reset(&mut self) {
+        self.stop_sharing();
+        *self = Self::default();
+    }
+}
+
+impl ChannelPanel for SupportLogsState {
+    fn negotiated(&mut self) -> &mut NegotiatedChannel {
+        &mut self.channel
+    }
+
+    fn replaced(&mut self) {
+        self.reset();
+    }
+
+    fn closed(&mut self) {
+        self.stop_sharing();
+        self.asked = false;
+        self.requested = false;
+        self.receiving = false;
+    }
+}
+
+impl WebRTCApp {
+    /// Creates the logs channel on the current peer connection.
+    pub(crate) async fn open_log_channel(&self) {
+        let (messages_tx, mut messages) = mpsc::unbounded_channel();
+        self.open_negotiated_channel(
+            &self.support_logs,
+            LOG_LABEL,
+            LOG_STREAM_ID,
+            move |message| {
+                match LogMessage::from_json(&message.into_text()?) {
+                    Ok(message) => {
+                        let _ = messages_tx.send(message);
+                    }
+                    Err(err) => info!("Ignoring malformed logs message: {}", err),
+                }
+                None
+            },
+        )
+        .await;
+
+        let support_logs = Arc::clone(&self.support_logs);
+        tokio::spawn(async move {
+            while let Some(message) = messages.recv().await {
+                let mut state = support_logs.lock().unwrap();
+                match message {
+                    LogMessage::Request => {
+                        if !state.sharing() {
+                            state.asked = true;
+                        }
+                    }
+                    LogMessage::Started => {
+                        state.requested = false;
+                        state.receiving = true;
+                        state.received.clear();
+                        state.status = "The peer is sharing its log".to_owned();
+                    }
+                    LogMessage::Stopped => {
+                        state.status = if state.requested {
+                            "The peer declined to share its log"
+                        } else {
+                            "The peer stopped sharing its log"
+                        }
+                        .to_owned();
+                        state.requested = false;
+                        state.receiving = false;
+                    }
+                    LogMessage::Record(record) => {
+                        if state.receiving {
+                            state.received.push(record);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    async fn send_log_message(&self, message: LogMessage) -> bool {
+        let channel = self.support_logs.lock().unwrap().channel.sender();
+        negotiated_channel::send(channel, message.to_json())
+            .await
+            .is_ok()
+    }
+
+    /// Starts streaming our log; only ever called on the user's say-so.
+    async fn share_logs(&self) {
+        let Some(tap) = log_stream::tap() else {
+            return;
+        };
+        let channel = {
+            let mut state = self.support_logs.lock().unwrap();
+            state.asked = false;
+            match state.channel.sender().filter(|_| !state.sharing()) {
+                Some(channel) => channel,
+                None => return,
+            }
+        };
+        if !self.send_log_message(LogMessage::Started).await {
+            return;
+        }
+        self.audit.lock().unwrap().record(
+            AuditEvent::LogsShared,
+            "support",
+            "",
+            format!("{} and above", log_stream::STREAM_LEVEL),
+        );
+        info!("Sharing logs with the peer");
+        let mut records = tap.start();
+        let sender = tokio::spawn(async move {
+            while let Some(record) = records.recv().await {
+                // Whatever broke the channel also closes it, and closing
+                // stops the stream.
+                if framing::send_text(&channel, LogMessage::Record(record).to_json())
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+        let mut state = self.support_logs.lock().unwrap();
+        state.sender = Some(sender);
+        state.status = "Sharing this app's log with the peer".to_owned();
+    }
+
+    async fn stop_sharing_logs(&self, status: &str) {
+        {
+            let mut state = self.support_logs.lock().unwrap();
+            state.stop_sharing();
+            state.asked = false;
+            state.status = status.to_owned();
+        }
+        self.send_log_message(LogMessage::Stopped).await;
+    }
+
+    /// Always in view while our log is going to the peer.
+    pub(crate) fn log_sharing_indicator_ui(&self, ui: &mut egui::Ui) {
+        if self.support_logs.lock().unwrap().sharing() {
+            ui.colored_label(egui::Color32::GOLD, "Sharing logs with the peer");
+        }
+    }
+
+    pub(crate) fn support_logs_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let state = self.support_logs.lock().unwrap();
+        if !state.channel.is_open() {
+            ui.label("Connect to a peer to share logs with them.");
+            return;
+        }
+        if state.receiving {
+            // Records arrive in the background.
+            ctx.request_repaint_after(std::time::Duration::from_millis(500));
+        }
+
+        if state.asked {
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                format!(
+                    "The peer asks to see this app's log ({} and above). It can include \
+                     addresses and server names.",
+                    log_stream::STREAM_LEVEL
+                ),
+            );
+            ui.horizontal(|ui| {
+                if ui.button("Allow").clicked() {
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.share_logs().await;
+                        ctx.request_repaint();
+                    });
+                }
+                if ui.button("Deny").clicked() {
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.stop_sharing_logs("Declined to share").await;
+                        ctx.request_repaint();
+                    });
+                }
+            });
+        }
+        ui.horizontal(|ui| {
+            if state.sharing() {
+                if ui.button("Stop sharing my log").clicked() {
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.stop_sharing_logs("Stopped sharing").await;
+                        ctx.request_repaint();
+                    });
+                }
+                if let Some(dropped) = log_stream::tap().map(|tap| tap.dropped()) {
+                    if dropped > 0 {
+                        ui.label(format!("{} records were too many to send", dropped));
+                    }
+                }
+            } else if ui.button("Share my log").clicked() {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.share_logs().await;
+                    ctx.request_repaint();
+                });
+            }
+            if ui
+                .add_enabled(
+                    !state.receiving && !state.requested,
+                    egui::Button::new("Ask for the peer's log"),
+                )
+                .clicked()
+            {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    if app.send_log_message(LogMessage::Request).await {
+                        let mut state = app.support_logs.lock().unwrap();
+                        state.requested = true;
+                        state.status = "Waiting for the peer to agree...".to_owned();
+                    }
+                    ctx.request_repaint();
+                });
+            }
+            ui.label(&state.status);
+        });
+
+        if state.received.is_empty() {
+            return;
+        }
+        if let Some(warning) = state.received.warning("log records") {
+            ui.colored_label(egui::Color32::YELLOW, warning);
+        }
+        egui::ScrollArea::vertical()
+            .max_height(240.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for record in state.received.iter() {
+                    let color = match record.level() {
+                        Some(Level::Error) => egui::Color32::RED,
+                        Some(Level::Warn) => egui::Color32::YELLOW,
+                        _ => ui.visuals().text_color(),
+                    };
+                    let at = std::time::UNIX_EPOCH + std::time::Duration::from_millis(record.at);
+                    ui.colored_label(
+                        color,
+                        format!(
+                            "{} {:5} {}: {}",
+                            humantime::format_rfc3339_millis(at),
+                            record.level,
+                            record.target,
+                            record.message
+                        ),
+                    );
+                }
+            });
+    }
+}
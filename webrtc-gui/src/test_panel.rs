@@ -0,0 +1,188 @@
+//! "Test my setup" panel.
+
+use eframe::egui;
+use webrtc_core::audio;
+use webrtc_core::capabilities;
+use webrtc_core::devices::DeviceKind;
+use webrtc_core::echo_test::{self, CheckStatus, EchoTestReport};
+use webrtc_core::settings::FileKind;
+
+use crate::WebRTCApp;
+
+#[derive(Default)]
+enum SpeakerCheck {
+    #[default]
+    NotRun,
+    Playing,
+    /// The tone played; waiting for the user to say whether they heard it.
+    Confirm,
+    Heard,
+    NotHeard,
+    Failed(String),
+}
+
+#[derive(Default)]
+pub struct EchoTestState {
+    running: bool,
+    report: Option<EchoTestReport>,
+    speaker: SpeakerCheck,
+    /// Where the last capabilities export went, or why it failed.
+    export_status: String,
+}
+
+impl WebRTCApp {
+    async fn run_echo_test(&self) {
+        let settings = self.settings.lock().unwrap().clone();
+        let report = echo_test::run(&settings, &settings.network.echo_test_url).await;
+        let mut state = self.echo_test.lock().unwrap();
+        state.running = false;
+        state.report = Some(report);
+    }
+
+    async fn export_capabilities(&self) {
+        let Some(path) = self
+            .save_file_dialog(
+                FileKind::Exports,
+                "Export capabilities",
+                "capabilities.json",
+            )
+            .await
+        else {
+            return;
+        };
+        let settings = self.settings.lock().unwrap().clone();
+        let status = match capabilities::report(&settings).await {
+            Ok(report) => {
+                let json = serde_json::to_vec_pretty(&report).expect("reports serialize");
+                match std::fs::write(&path, json) {
+                    Ok(()) => format!("Capabilities written to {}", path.display()),
+                    Err(err) => format!("Failed to write {}: {}", path.display(), err),
+                }
+            }
+            Err(err) => format!("Failed to collect capabilities: {}", err),
+        };
+        self.echo_test.lock().unwrap().export_status = status;
+    }
+
+    async fn test_speaker(&self) {
+        let device_id = self.selected_device(DeviceKind::Speaker).map(|d| d.id);
+        let result =
+            tokio::task::spawn_blocking(move || audio::play_speaker_test(device_id.as_deref()))
+                .await;
+        self.echo_test.lock().unwrap().speaker = match result {
+            Ok(Ok(())) => SpeakerCheck::Confirm,
+            Ok(Err(err)) => SpeakerCheck::Failed(err.to_string()),
+            Err(err) => SpeakerCheck::Failed(err.to_string()),
+        };
+    }
+
+    fn speaker_check_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context, check: &mut SpeakerCheck) {
+        ui.horizontal(|ui| {
+            let playing = matches!(check, SpeakerCheck::Playing);
+            if ui
+                .add_enabled(!playing, egui::Button::new("Test speaker"))
+                .clicked()
+            {
+                *check = SpeakerCheck::Playing;
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.test_speaker().await;
+                    ctx.request_repaint();
+                });
+            }
+            match check {
+                SpeakerCheck::NotRun => {}
+                SpeakerCheck::Playing => {
+                    ui.spinner();
+                    ui.label("Playing test tone...");
+                }
+                SpeakerCheck::Confirm => {
+                    ui.label("Did you hear three beeps?");
+                    if ui.button("Yes").clicked() {
+                        *check = SpeakerCheck::Heard;
+                    }
+                    if ui.button("No").clicked() {
+                        *check = SpeakerCheck::NotHeard;
+                    }
+                }
+                SpeakerCheck::Heard => {
+                    ui.colored_label(egui::Color32::GREEN, "Speaker: PASS");
+                }
+                SpeakerCheck::NotHeard => {
+                    ui.colored_label(
+                        egui::Color32::RED,
+                        "Speaker: FAIL - check the volume or pick another speaker under Devices",
+                    );
+                }
+                SpeakerCheck::Failed(err) => {
+                    ui.colored_label(egui::Color32::RED, format!("Speaker: FAIL - {}", err));
+                }
+            }
+        });
+    }
+
+    pub(crate) fn echo_test_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.echo_test.lock().unwrap();
+        self.speaker_check_ui(ui, ctx, &mut state.speaker);
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!state.running, egui::Button::new("Test my setup"))
+                .clicked()
+            {
+                state.running = true;
+                state.report = None;
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.run_echo_test().await;
+                    ctx.request_repaint();
+                });
+            }
+            if state.running {
+                ui.spinner();
+                ui.label("Running echo test...");
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui
+                .button("Export capabilities...")
+                .on_hover_text("Codecs, header extensions and devices as JSON, for bug reports")
+                .clicked()
+            {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.export_capabilities().await;
+                    ctx.request_repaint();
+                });
+            }
+            ui.label(&state.export_status);
+        });
+
+        let Some(report) = &state.report else {
+            return;
+        };
+        egui::Grid::new("echo_test_report")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                for check in &report.checks {
+                    let color = match check.status {
+                        CheckStatus::Pass => egui::Color32::GREEN,
+                        CheckStatus::Fail => egui::Color32::RED,
+                        CheckStatus::Skipped => egui::Color32::GRAY,
+                    };
+                    ui.label(check.name);
+                    ui.colored_label(color, check.status.to_string());
+                    ui.label(&check.detail);
+                    ui.end_row();
+                }
+            });
+        if report.passed() {
+            ui.colored_label(egui::Color32::GREEN, "Overall: PASS");
+        } else {
+            ui.colored_label(egui::Color32::RED, "Overall: FAIL");
+        }
+    }
+}
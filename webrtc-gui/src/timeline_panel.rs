@@ -0,0 +1,84 @@
+//! The session event timeline: one row per kind of event, zoomable and
+//! draggable along the time axis, with the details of an event on hover.
+
+use eframe::egui;
+use egui_plot::{GridMark, Plot, Points};
+use webrtc_core::stats::MarkerLane;
+
+use crate::stats_panel::marker_color;
+use crate::WebRTCApp;
+
+/// How close, in screen points, the pointer has to be to an event for its
+/// details to show.
+const HOVER_RADIUS: f64 = 8.0;
+const LANE_HEIGHT: f32 = 24.0;
+
+impl WebRTCApp {
+    pub(crate) fn event_timeline_ui(&self, ui: &mut egui::Ui) {
+        let timeline = self.stats.lock().unwrap();
+        let lanes = MarkerLane::ALL.len();
+        let width = f64::from(ui.available_width().max(1.0));
+
+        let hovered = Plot::new("event_timeline")
+            .height(40.0 + LANE_HEIGHT * lanes as f32)
+            .include_x(0.0)
+            .include_x(timeline.elapsed().max(1.0))
+            .include_y(-0.5)
+            .include_y(lanes as f64 - 0.5)
+            .allow_zoom([true, false])
+            .allow_drag([true, false])
+            .allow_scroll([true, false])
+            .allow_boxed_zoom(false)
+            .show_grid([true, false])
+            .x_axis_label("seconds")
+            .y_grid_spacer(move |_| {
+                (0..lanes)
+                    .map(|lane| GridMark {
+                        value: lane as f64,
+                        step_size: 1.0,
+                    })
+                    .collect()
+            })
+            .y_axis_formatter(|mark, _, _| {
+                MarkerLane::ALL
+                    .get(mark.value.round() as usize)
+                    .map_or_else(String::new, ToString::to_string)
+            })
+            // Hovering is handled below, with the event's details.
+            .label_formatter(|_, _| String::new())
+            .show(ui, |plot_ui| {
+                for marker in timeline.markers() {
+                    let lane = marker.kind.lane().index() as f64;
+                    plot_ui.points(
+                        Points::new(vec![[marker.time, lane]])
+                            .radius(4.0)
+                            .color(marker_color(marker.kind)),
+                    );
+                }
+                let pointer = plot_ui.pointer_coordinate()?;
+                let row = pointer.y.round();
+                let lane = MarkerLane::ALL
+                    .get(row as usize)
+                    .filter(|_| row >= 0.0 && (pointer.y - row).abs() < 0.4)?;
+                let seconds_per_point = plot_ui.plot_bounds().width() / width;
+                timeline
+                    .nearest_marker(*lane, pointer.x, seconds_per_point * HOVER_RADIUS)
+                    .cloned()
+            })
+            .inner;
+        if let Some(marker) = hovered {
+            egui::show_tooltip_at_pointer(ui.ctx(), egui::Id::new("event_timeline_hover"), |ui| {
+                ui.colored_label(
+                    marker_color(marker.kind),
+                    format!("{:.2}s  {}", marker.time, marker.kind),
+                );
+                ui.label(marker.detail);
+            });
+        }
+
+        ui.label(format!(
+            "{} events. Scroll to zoom, drag to pan, double-click to see them all.",
+            timeline.markers().count()
+        ));
+    }
+}
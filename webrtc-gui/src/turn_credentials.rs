@@ -0,0 +1,58 @@
+//! Keeps credentials from TURN REST services fresh, so every new peer
+//! connection is configured with ones that have not expired.
+
+use std::time::{Duration, SystemTime};
+
+use log::{info, warn};
+use webrtc_core::turn_rest;
+
+use crate::WebRTCApp;
+
+/// How often to look for credentials close to expiry.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+impl WebRTCApp {
+    /// Fetches credentials for every TURN server whose credentials are
+    /// missing or about to expire.
+    pub(crate) async fn refresh_turn_credentials(&self) {
+        let stale: Vec<(usize, String, String)> = {
+            let settings = self.settings.lock().unwrap();
+            let now = SystemTime::now();
+            settings
+                .network
+                .turn_servers
+                .iter()
+                .enumerate()
+                .filter_map(|(index, server)| {
+                    let url = server.stale_credentials_url(now)?;
+                    Some((index, url.to_owned(), server.username.clone()))
+                })
+                .collect()
+        };
+        for (index, url, user) in stale {
+            match turn_rest::fetch(&url, &user).await {
+                Ok(issued) => {
+                    info!("Fetched TURN credentials from {}", url);
+                    let mut settings = self.settings.lock().unwrap();
+                    // The list may have been edited while we waited.
+                    if let Some(server) = settings.network.turn_servers.get_mut(index) {
+                        if server.stale_credentials_url(SystemTime::now()) == Some(url.as_str()) {
+                            server.issued = Some(issued);
+                        }
+                    }
+                }
+                Err(err) => warn!("Failed to fetch TURN credentials from {}: {}", url, err),
+            }
+        }
+    }
+
+    pub(crate) fn spawn_turn_refresh(&self) {
+        let app = self.clone();
+        tokio::spawn(async move {
+            loop {
+                app.refresh_turn_credentials().await;
+                tokio::time::sleep(CHECK_INTERVAL).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,244 @@
+//! A texture fed from a decoder's frame snapshot, shared by the SFU panels.
+
+use std::sync::Arc;
+
+use eframe::egui;
+use webrtc_core::snapshot::Snapshot;
+use webrtc_core::video::VideoFrame;
+
+/// Quarter turns clockwise applied to a tile's picture.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Quarter,
+    Half,
+    ThreeQuarter,
+}
+
+impl Rotation {
+    const ALL: [Rotation; 4] = [
+        Rotation::None,
+        Rotation::Quarter,
+        Rotation::Half,
+        Rotation::ThreeQuarter,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Rotation::None => "No rotation",
+            Rotation::Quarter => "Rotate 90°",
+            Rotation::Half => "Rotate 180°",
+            Rotation::ThreeQuarter => "Rotate 270°",
+        }
+    }
+
+    fn turns(self) -> usize {
+        self as usize
+    }
+}
+
+/// How a tile's picture is turned on screen. The texture stays as decoded;
+/// only the corners it is drawn with move.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Transform {
+    /// Left and right swapped, after the rotation.
+    pub mirror: bool,
+    pub rotation: Rotation,
+}
+
+impl Transform {
+    /// The texture coordinates for the corners of the tile, clockwise from
+    /// its top left.
+    fn uvs(self) -> [egui::Pos2; 4] {
+        let corners = [
+            egui::pos2(0.0, 0.0),
+            egui::pos2(1.0, 0.0),
+            egui::pos2(1.0, 1.0),
+            egui::pos2(0.0, 1.0),
+        ];
+        let mut uvs: [egui::Pos2; 4] =
+            std::array::from_fn(|i| corners[(i + 4 - self.rotation.turns()) % 4]);
+        if self.mirror {
+            uvs.swap(0, 1);
+            uvs.swap(2, 3);
+        }
+        uvs
+    }
+
+    /// Where in the texture the point `at` of the tile shows, both as
+    /// fractions of their sides.
+    fn texture_point(self, at: egui::Vec2) -> egui::Pos2 {
+        let [top_left, top_right, _, bottom_left] = self.uvs();
+        top_left + (top_right - top_left) * at.x + (bottom_left - top_left) * at.y
+    }
+
+    /// Height over width of a `size` picture once turned.
+    fn aspect(self, size: egui::Vec2) -> f32 {
+        match self.rotation {
+            Rotation::Quarter | Rotation::ThreeQuarter => size.x / size.y,
+            Rotation::None | Rotation::Half => size.y / size.x,
+        }
+    }
+
+    /// The right-click menu of a tile.
+    fn menu_ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.mirror, "Mirror");
+        ui.separator();
+        for rotation in Rotation::ALL {
+            if ui
+                .radio_value(&mut self.rotation, rotation, rotation.label())
+                .clicked()
+            {
+                ui.close_menu();
+            }
+        }
+    }
+}
+
+pub struct VideoView {
+    name: String,
+    /// Changed from the tile's right-click menu.
+    pub transform: Transform,
+    /// Whether right-clicking opens the transform menu, rather than being
+    /// left to whoever handles the response.
+    pub context_menu: bool,
+    frames: Snapshot<Option<VideoFrame>>,
+    texture: Option<egui::TextureHandle>,
+    /// The frame currently uploaded to `texture`.
+    shown: Option<Arc<Option<VideoFrame>>>,
+}
+
+impl VideoView {
+    /// Repaints `ctx` whenever the decoder publishes a frame.
+    pub fn new(name: String, frames: Snapshot<Option<VideoFrame>>, ctx: &egui::Context) -> Self {
+        let mut updates = frames.subscribe();
+        let repaint = ctx.clone();
+        tokio::spawn(async move {
+            while updates.changed().await.is_ok() {
+                repaint.request_repaint();
+            }
+        });
+        Self {
+            name,
+            transform: Transform::default(),
+            context_menu: true,
+            frames,
+            texture: None,
+            shown: None,
+        }
+    }
+
+    /// Height over width of the picture as shown, or of 16:9 before the
+    /// first frame.
+    pub fn aspect(&self) -> f32 {
+        self.texture.as_ref().map_or(9.0 / 16.0, |texture| {
+            self.transform.aspect(texture.size_vec2())
+        })
+    }
+
+    /// Shows the latest frame at `width`, turned by `transform`, or a
+    /// spinner until the first one. The picture can be clicked and dragged.
+    pub fn show(&mut self, ui: &mut egui::Ui, width: f32) -> Option<egui::Response> {
+        self.upload(ui.ctx());
+        let aspect = self.aspect();
+        match &self.texture {
+            Some(texture) => {
+                let (rect, response) = ui.allocate_exact_size(
+                    egui::vec2(width, width * aspect),
+                    egui::Sense::click_and_drag(),
+                );
+                if ui.is_rect_visible(rect) {
+                    let mut mesh = egui::Mesh::with_texture(texture.id());
+                    let corners = [
+                        rect.left_top(),
+                        rect.right_top(),
+                        rect.right_bottom(),
+                        rect.left_bottom(),
+                    ];
+                    for (pos, uv) in corners.into_iter().zip(self.transform.uvs()) {
+                        mesh.vertices.push(egui::epaint::Vertex {
+                            pos,
+                            uv,
+                            color: egui::Color32::WHITE,
+                        });
+                    }
+                    mesh.indices.extend([0, 1, 2, 0, 2, 3]);
+                    ui.painter().add(mesh);
+                }
+                if self.context_menu {
+                    response.context_menu(|ui| self.transform.menu_ui(ui));
+                }
+                Some(response)
+            }
+            None => {
+                ui.spinner();
+                None
+            }
+        }
+    }
+
+    /// Where `pos` on a picture shown in `rect` is in the frame, as
+    /// fractions across and down, undoing the transform.
+    pub fn picture_point(&self, rect: egui::Rect, pos: egui::Pos2) -> Option<egui::Pos2> {
+        if !rect.contains(pos) {
+            return None;
+        }
+        Some(self.transform.texture_point((pos - rect.min) / rect.size()))
+    }
+
+    /// Stands in for the video at `width` while the sender has stopped it:
+    /// an avatar outline over `label`, at the last frame's shape so the
+    /// layout does not jump.
+    pub fn show_placeholder(&mut self, ui: &mut egui::Ui, width: f32, label: &str) {
+        let (rect, _) = ui.allocate_exact_size(
+            egui::vec2(width, width * self.aspect()),
+            egui::Sense::hover(),
+        );
+        let painter = ui.painter_at(rect);
+        let visuals = ui.visuals();
+        painter.rect_filled(rect, 4.0, visuals.extreme_bg_color);
+        let color = visuals.weak_text_color();
+        let radius = rect.height() * 0.15;
+        let head = rect.center() - egui::vec2(0.0, radius * 1.2);
+        painter.circle_filled(head, radius, color);
+        let shoulders = egui::Rect::from_center_size(
+            head + egui::vec2(0.0, radius * 2.2),
+            egui::vec2(radius * 3.2, radius * 1.6),
+        );
+        painter.rect_filled(shoulders, radius * 0.8, color);
+        painter.text(
+            egui::pos2(rect.center().x, rect.bottom() - radius * 0.6),
+            egui::Align2::CENTER_BOTTOM,
+            label,
+            egui::FontId::proportional(14.0),
+            visuals.text_color(),
+        );
+    }
+
+    /// Uploads the latest decoded frame, if it changed since the last one.
+    fn upload(&mut self, ctx: &egui::Context) {
+        let latest = self.frames.get();
+        if self
+            .shown
+            .as_ref()
+            .is_some_and(|shown| Arc::ptr_eq(shown, &latest))
+        {
+            return;
+        }
+        if let Some(frame) = latest.as_ref() {
+            let image =
+                egui::ColorImage::from_rgba_unmultiplied([frame.width, frame.height], &frame.rgba);
+            match &mut self.texture {
+                Some(texture) if texture.size() == image.size => {
+                    texture.set(image, egui::TextureOptions::LINEAR)
+                }
+                texture => {
+                    *texture =
+                        Some(ctx.load_texture(&self.name, image, egui::TextureOptions::LINEAR))
+                }
+            }
+        }
+        self.shown = Some(latest);
+    }
+}
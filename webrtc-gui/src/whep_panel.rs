@@ -0,0 +1,200 @@
+//! WHEP player panel: view a remote stream from a WHEP endpoint.
+
+use std::sync::Arc;
+
+use eframe::egui;
+use tokio::time::Instant;
+use webrtc_core::rtc::{ConnectionState, MediaKind};
+use webrtc_core::snapshot::Snapshot;
+use webrtc_core::video::VideoFrame;
+use webrtc_core::whep::{PlayerEvent, WhepPlayer};
+
+use crate::WebRTCApp;
+
+#[derive(Default)]
+pub struct WhepState {
+    endpoint: String,
+    token: String,
+    player: Option<WhepPlayer>,
+    starting: bool,
+    status: String,
+    tracks: Vec<String>,
+    frames: Snapshot<Option<VideoFrame>>,
+    texture: Option<egui::TextureHandle>,
+    /// The frame currently uploaded to `texture`.
+    shown: Option<Arc<Option<VideoFrame>>>,
+    /// Start of the current one-second window, frames shown in it, and the
+    /// previous window's count.
+    fps: Option<(Instant, u32, u32)>,
+    size: [usize; 2],
+}
+
+impl WebRTCApp {
+    async fn whep_play(&self, ctx: egui::Context) {
+        let settings = self.settings.lock().unwrap().clone();
+        let (endpoint, token, frames) = {
+            let mut state = self.whep.lock().unwrap();
+            state.starting = true;
+            state.status = "Connecting...".to_owned();
+            state.tracks.clear();
+            state.frames.set(None);
+            let token = Some(state.token.trim().to_owned()).filter(|t| !t.is_empty());
+            (
+                state.endpoint.trim().to_owned(),
+                token,
+                state.frames.clone(),
+            )
+        };
+
+        let mut updates = frames.subscribe();
+        let repaint = ctx.clone();
+        tokio::spawn(async move {
+            while updates.changed().await.is_ok() {
+                repaint.request_repaint();
+            }
+        });
+
+        // The endpoint's own token wins over the signaling login.
+        let token = match token {
+            Some(token) => Some(token),
+            None => self.access_token().await,
+        };
+        let result = WhepPlayer::start(&settings, &endpoint, token, frames).await;
+        let mut state = self.whep.lock().unwrap();
+        state.starting = false;
+        match result {
+            Ok((player, mut events)) => {
+                state.player = Some(player);
+                state.status = "Waiting for media...".to_owned();
+                let whep = Arc::clone(&self.whep);
+                tokio::spawn(async move {
+                    while let Some(event) = events.recv().await {
+                        let mut state = whep.lock().unwrap();
+                        match event {
+                            PlayerEvent::StateChanged(connection) => {
+                                state.status = match connection {
+                                    ConnectionState::Connected => "Playing".to_owned(),
+                                    other => other.to_string(),
+                                }
+                            }
+                            PlayerEvent::TrackStarted {
+                                kind,
+                                codec,
+                                decodable,
+                            } => {
+                                let note = match kind {
+                                    MediaKind::Video if !decodable => " (no decoder in this build)",
+                                    MediaKind::Video => "",
+                                    MediaKind::Audio => " (playback not supported yet)",
+                                };
+                                state.tracks.push(format!("{} {}{}", kind, codec, note));
+                            }
+                        }
+                        ctx.request_repaint();
+                    }
+                });
+            }
+            Err(err) => state.status = format!("Failed to start playback: {}", err),
+        }
+    }
+
+    async fn whep_stop(&self) {
+        let player = {
+            let mut state = self.whep.lock().unwrap();
+            state.status = "Stopped".to_owned();
+            state.texture = None;
+            state.shown = None;
+            state.fps = None;
+            state.player.take()
+        };
+        if let Some(player) = player {
+            player.stop().await;
+        }
+    }
+
+    pub(crate) fn whep_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.whep.lock().unwrap();
+        let active = state.player.is_some() || state.starting;
+        ui.add_enabled_ui(!active, |ui| {
+            egui::Grid::new("whep_settings")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("WHEP endpoint:");
+                    ui.text_edit_singleline(&mut state.endpoint);
+                    ui.end_row();
+                    ui.label("Bearer token:");
+                    ui.add(egui::TextEdit::singleline(&mut state.token).password(true));
+                    ui.end_row();
+                });
+        });
+        ui.horizontal(|ui| {
+            if !active
+                && ui
+                    .add_enabled(!state.endpoint.trim().is_empty(), egui::Button::new("Play"))
+                    .clicked()
+            {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.whep_play(ctx.clone()).await;
+                    ctx.request_repaint();
+                });
+            }
+            if state.player.is_some() && ui.button("Stop").clicked() {
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.whep_stop().await;
+                    ctx.request_repaint();
+                });
+            }
+            if state.starting {
+                ui.spinner();
+            }
+            ui.label(&state.status);
+        });
+        for track in &state.tracks {
+            ui.label(track);
+        }
+
+        if state.player.is_none() {
+            return;
+        }
+        // Only upload when the decoder has published a new frame.
+        let latest = state.frames.get();
+        let fresh = !state
+            .shown
+            .as_ref()
+            .is_some_and(|shown| Arc::ptr_eq(shown, &latest));
+        if let (true, Some(frame)) = (fresh, latest.as_ref()) {
+            let image =
+                egui::ColorImage::from_rgba_unmultiplied([frame.width, frame.height], &frame.rgba);
+            match &mut state.texture {
+                Some(texture) if texture.size() == image.size => {
+                    texture.set(image, egui::TextureOptions::LINEAR)
+                }
+                texture => {
+                    *texture =
+                        Some(ctx.load_texture("whep_video", image, egui::TextureOptions::LINEAR))
+                }
+            }
+            state.size = [frame.width, frame.height];
+            state.fps = match state.fps {
+                Some((started, count, _)) if started.elapsed().as_secs() >= 1 => {
+                    Some((Instant::now(), 1, count))
+                }
+                Some((started, count, last)) => Some((started, count + 1, last)),
+                None => Some((Instant::now(), 1, 0)),
+            };
+            state.shown = Some(latest);
+        }
+        if let Some((_, _, fps)) = state.fps {
+            ui.label(format!("{}x{}, {} fps", state.size[0], state.size[1], fps));
+        }
+        if let Some(texture) = &state.texture {
+            let size = texture.size_vec2();
+            let width = ui.available_width().min(size.x);
+            ui.image((texture.id(), egui::vec2(width, width * size.y / size.x)));
+        }
+    }
+}
@@ -0,0 +1,344 @@
+//! Picking one open window and sharing it on the current peer connection,
+//! at the bitrate the link allows with adaptive bitrate on. The size and
+//! frame rate can change while it is shared.
+
+use std::sync::Arc;
+
+use eframe::egui;
+use log::info;
+use tokio::task::JoinHandle;
+use webrtc::rtp_transceiver::rtp_sender::RTCRtpSender;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+use webrtc_core::bandwidth::{self, SendTarget};
+use webrtc_core::codec::EncoderConfig;
+use webrtc_core::constraints::RESOLUTIONS;
+use webrtc_core::screen::{
+    self, ScreenError, ShareFormat, WindowCapture, WindowInfo, SHARE_FRAME_RATES, WINDOW_TRACK_ID,
+};
+use webrtc_core::snapshot::Snapshot;
+use webrtc_core::video::{self, VideoFrame};
+
+use crate::video_view::VideoView;
+use crate::WebRTCApp;
+
+const PREVIEW_WIDTH: f32 = 240.0;
+
+pub struct WindowShareState {
+    windows: Vec<WindowInfo>,
+    selected: Option<u32>,
+    capture: Option<JoinHandle<()>>,
+    sender: Option<Arc<RTCRtpSender>>,
+    /// What is being sent, for the self-view.
+    preview: Snapshot<Option<VideoFrame>>,
+    view: VideoView,
+    /// What the link allows, read by the capture thread.
+    send_target: Snapshot<Option<SendTarget>>,
+    /// The settings' size and frame rate, read by the capture thread.
+    format: Snapshot<ShareFormat>,
+    /// Reads the feedback on `sender` into `send_target`.
+    rate_control: Option<JoinHandle<()>>,
+    busy: bool,
+    status: String,
+}
+
+impl WindowShareState {
+    pub fn new(ctx: &egui::Context) -> Self {
+        let preview = Snapshot::default();
+        Self {
+            windows: Vec::new(),
+            selected: None,
+            capture: None,
+            sender: None,
+            view: VideoView::new("window_preview".to_owned(), preview.clone(), ctx),
+            preview,
+            send_target: Snapshot::default(),
+            format: Snapshot::default(),
+            rate_control: None,
+            busy: false,
+            status: String::new(),
+        }
+    }
+
+    fn sharing(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    /// Stops capture and hands back the sender to remove, if any.
+    fn stop(&mut self) -> Option<Arc<RTCRtpSender>> {
+        if let Some(capture) = self.capture.take() {
+            capture.abort();
+        }
+        if let Some(rate_control) = self.rate_control.take() {
+            rate_control.abort();
+        }
+        self.send_target.set(None);
+        self.preview.set(None);
+        self.sender.take()
+    }
+
+    /// Stops sharing for a peer connection that is being replaced, which
+    /// takes its senders with it.
+    pub(crate) fn reset(&mut self) {
+        self.stop();
+        self.status.clear();
+    }
+
+    /// The window being shared, if one is.
+    pub(crate) fn shared_window(&self) -> Option<&WindowInfo> {
+        self.selected_window().filter(|_| self.sharing())
+    }
+
+    fn selected_window(&self) -> Option<&WindowInfo> {
+        let id = self.selected?;
+        self.windows.iter().find(|window| window.id == id)
+    }
+}
+
+impl WebRTCApp {
+    async fn refresh_windows(&self) {
+        let listed = tokio::task::spawn_blocking(screen::list_windows)
+            .await
+            .unwrap_or_else(|err| Err(ScreenError::X11(err.to_string())));
+        let mut state = self.window_share.lock().unwrap();
+        state.busy = false;
+        match listed {
+            Ok(windows) => {
+                state.status = format!("{} windows open", windows.len());
+                state.windows = windows;
+                if state.selected_window().is_none() {
+                    state.selected = None;
+                }
+            }
+            Err(err) => {
+                info!("Failed to list windows: {}", err);
+                state.status = format!("Cannot list windows: {}", err);
+            }
+        }
+    }
+
+    /// Adds a track for `window` to the current peer connection, which
+    /// renegotiates like the camera does.
+    async fn start_window_share(&self, window: WindowInfo) {
+        let status = match self.add_window_track(window).await {
+            Ok(status) => status,
+            Err(err) => {
+                info!("Failed to share window: {}", err);
+                format!("Window share failed: {}", err)
+            }
+        };
+        let mut state = self.window_share.lock().unwrap();
+        state.busy = false;
+        state.status = status;
+    }
+
+    async fn add_window_track(&self, window: WindowInfo) -> Result<String, String> {
+        let pc = self.peer_connection.lock().await.clone();
+        let pc = pc.ok_or("initialize a peer connection first")?;
+        let capability = self
+            .sending_video_capability(&pc)
+            .await
+            .ok_or_else(|| ScreenError::NoEncoder.to_string())?;
+        let capture = tokio::task::spawn_blocking(move || WindowCapture::open(&window))
+            .await
+            .map_err(|err| err.to_string())?
+            .map_err(|err| err.to_string())?;
+        let status = format!("Sharing {}", capture.window());
+        let share_format = self.settings.lock().unwrap().media.screen_share;
+        let max_bps = EncoderConfig::new(
+            capture.window().width as usize,
+            capture.window().height as usize,
+            share_format.frame_rate,
+        )
+        .bitrate_bps;
+
+        let track = Arc::new(TrackLocalStaticSample::new(
+            capability,
+            WINDOW_TRACK_ID.to_owned(),
+            "local".to_owned(),
+        ));
+        let sender = pc
+            .add_track(track.clone())
+            .await
+            .map_err(|err| err.to_string())?;
+        let (preview, send_target, format) = {
+            let state = self.window_share.lock().unwrap();
+            state.format.set(share_format);
+            (
+                state.preview.clone(),
+                state.send_target.clone(),
+                state.format.clone(),
+            )
+        };
+        let limits = self.power_saving.lock().unwrap().limits();
+        let capture = match screen::spawn_window_share(
+            capture,
+            track,
+            preview,
+            limits,
+            send_target.clone(),
+            format,
+        ) {
+            Ok(capture) => capture,
+            Err(err) => {
+                let _ = pc.remove_track(&sender).await;
+                return Err(err.to_string());
+            }
+        };
+        info!("{}", status);
+        let adaptive = self.settings.lock().unwrap().media.adaptive_bitrate;
+        let mut state = self.window_share.lock().unwrap();
+        state.capture = Some(capture);
+        if adaptive {
+            state.rate_control = Some(bandwidth::spawn_rate_control(
+                Arc::clone(&sender),
+                max_bps,
+                send_target,
+            ));
+        }
+        state.sender = Some(sender);
+        Ok(status)
+    }
+
+    async fn stop_window_share(&self) {
+        let sender = self.window_share.lock().unwrap().stop();
+        let pc = self.peer_connection.lock().await.clone();
+        if let Some((pc, sender)) = pc.zip(sender) {
+            if let Err(err) = pc.remove_track(&sender).await {
+                info!("Failed to remove window track: {:?}", err);
+            }
+        }
+        let mut state = self.window_share.lock().unwrap();
+        state.busy = false;
+        state.status = "Window share stopped".to_owned();
+    }
+
+    /// Picks the size and frame rate shares go out at, which a running
+    /// share takes up at its next frame.
+    fn share_format_ui(&self, ui: &mut egui::Ui, state: &WindowShareState) {
+        let mut settings = self.settings.lock().unwrap();
+        let format = &mut settings.media.screen_share;
+        let before = *format;
+        ui.horizontal(|ui| {
+            ui.label("Send:");
+            let size = |max_height: Option<u32>| match max_height {
+                None => "Window size".to_owned(),
+                Some(height) => RESOLUTIONS
+                    .iter()
+                    .find(|(_, _, preset)| *preset == height)
+                    .map_or(format!("Up to {} rows", height), |(name, _, _)| {
+                        format!("Up to {}", name)
+                    }),
+            };
+            egui::ComboBox::from_id_source("share_resolution")
+                .selected_text(size(format.max_height))
+                .show_ui(ui, |ui| {
+                    let heights = std::iter::once(None)
+                        .chain(RESOLUTIONS.iter().rev().map(|(_, _, height)| Some(*height)));
+                    for max_height in heights {
+                        ui.selectable_value(&mut format.max_height, max_height, size(max_height));
+                    }
+                })
+                .response
+                .on_hover_text("Taller windows are scaled down, which blurs small text");
+            egui::ComboBox::from_id_source("share_frame_rate")
+                .selected_text(format!("{:.0} fps", format.frame_rate))
+                .show_ui(ui, |ui| {
+                    for rate in SHARE_FRAME_RATES {
+                        ui.selectable_value(
+                            &mut format.frame_rate,
+                            rate,
+                            format!("{:.0} fps", rate),
+                        );
+                    }
+                });
+        });
+        if *format != before {
+            state.format.set(*format);
+        }
+    }
+
+    pub(crate) fn window_share_ui(&self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        let mut state = self.window_share.lock().unwrap();
+        ui.horizontal(|ui| {
+            let selected = state
+                .selected_window()
+                .map_or("Pick a window".to_owned(), ToString::to_string);
+            ui.add_enabled_ui(!state.sharing(), |ui| {
+                egui::ComboBox::from_id_source("window_share")
+                    .selected_text(selected)
+                    .width(240.0)
+                    .show_ui(ui, |ui| {
+                        let WindowShareState {
+                            windows, selected, ..
+                        } = &mut *state;
+                        for window in windows.iter() {
+                            ui.selectable_value(selected, Some(window.id), window.to_string());
+                        }
+                    });
+            });
+            if ui
+                .add_enabled(
+                    !state.busy && !state.sharing(),
+                    egui::Button::new("Refresh"),
+                )
+                .clicked()
+            {
+                state.busy = true;
+                let app = self.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    app.refresh_windows().await;
+                    ctx.request_repaint();
+                });
+            }
+        });
+        ui.horizontal(|ui| {
+            if state.sharing() {
+                if ui
+                    .add_enabled(!state.busy, egui::Button::new("Stop Sharing"))
+                    .clicked()
+                {
+                    state.busy = true;
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.stop_window_share().await;
+                        ctx.request_repaint();
+                    });
+                }
+            } else {
+                let window = state.selected_window().cloned();
+                let reason = if video::encoder_mime_type().is_none() {
+                    Some("This build has no video encoder")
+                } else if window.is_none() {
+                    Some("Pick a window first")
+                } else {
+                    None
+                };
+                let button = ui
+                    .add_enabled(
+                        !state.busy && reason.is_none(),
+                        egui::Button::new("Share Window"),
+                    )
+                    .on_disabled_hover_text(reason.unwrap_or_default());
+                if let (true, Some(window)) = (button.clicked(), window) {
+                    state.busy = true;
+                    let app = self.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        app.start_window_share(window).await;
+                        ctx.request_repaint();
+                    });
+                }
+            }
+            ui.label(&state.status);
+        });
+        self.share_format_ui(ui, &state);
+        if state.sharing() {
+            if let Some(target) = *state.send_target.get() {
+                ui.label(target.to_string());
+            }
+            state.view.show(ui, PREVIEW_WIDTH);
+        }
+    }
+}